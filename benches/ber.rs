@@ -0,0 +1,122 @@
+// BER encoding and decoding of representative LDAP messages.
+//
+// Run with `cargo bench --bench ber`.
+
+mod common;
+
+use common::{bench, Throughput};
+
+use bytes::BytesMut;
+use lber::parse::parse_tag_ref;
+use ldap3::asn1::{
+    parse_tag, write, ASNTag, Integer, OctetString, Sequence, Set, StructureTag, Tag, TagClass,
+};
+
+fn octet_string(value: &[u8]) -> Tag {
+    Tag::OctetString(OctetString {
+        inner: value.to_vec(),
+        ..Default::default()
+    })
+}
+
+fn message(id: i64, op: Tag) -> StructureTag {
+    Tag::Sequence(Sequence {
+        inner: vec![
+            Tag::Integer(Integer {
+                inner: id,
+                ..Default::default()
+            }),
+            op,
+        ],
+        ..Default::default()
+    })
+    .into_structure()
+}
+
+// SearchResultEntry with twenty attributes of a few values each.
+fn search_entry() -> StructureTag {
+    let attrs = (0..20)
+        .map(|a| {
+            Tag::Sequence(Sequence {
+                inner: vec![
+                    octet_string(format!("attribute{}", a).as_bytes()),
+                    Tag::Set(Set {
+                        inner: (0..3)
+                            .map(|v| {
+                                octet_string(format!("value {} of attribute {}", v, a).as_bytes())
+                            })
+                            .collect(),
+                        ..Default::default()
+                    }),
+                ],
+                ..Default::default()
+            })
+        })
+        .collect();
+    message(
+        2,
+        Tag::Sequence(Sequence {
+            id: 4,
+            class: TagClass::Application,
+            inner: vec![
+                octet_string(b"uid=someone,ou=People,dc=example,dc=org"),
+                Tag::Sequence(Sequence {
+                    inner: attrs,
+                    ..Default::default()
+                }),
+            ],
+        }),
+    )
+}
+
+// Simple BindRequest.
+fn bind_request() -> StructureTag {
+    message(
+        1,
+        Tag::Sequence(Sequence {
+            id: 0,
+            class: TagClass::Application,
+            inner: vec![
+                Tag::Integer(Integer {
+                    inner: 3,
+                    ..Default::default()
+                }),
+                octet_string(b"cn=Manager,dc=example,dc=org"),
+                Tag::OctetString(OctetString {
+                    id: 0,
+                    class: TagClass::Context,
+                    inner: b"secret".to_vec(),
+                }),
+            ],
+        }),
+    )
+}
+
+fn encoded(tag: &StructureTag) -> Vec<u8> {
+    let mut buf = BytesMut::new();
+    write::encode_into(&mut buf, tag.clone()).expect("encoded");
+    buf.to_vec()
+}
+
+fn main() {
+    for (name, tag) in &[
+        ("search_entry", search_entry()),
+        ("bind_request", bind_request()),
+    ] {
+        let bytes = encoded(tag);
+        let len = bytes.len();
+        let mut buf = BytesMut::with_capacity(len);
+        bench(&format!("encode/{}", name), Throughput::Bytes(len), || {
+            buf.clear();
+            write::encode_into(&mut buf, tag.clone()).expect("encoded");
+        });
+        bench(&format!("decode/{}", name), Throughput::Bytes(len), || {
+            parse_tag(&bytes)
+        });
+        bench(
+            &format!("decode_ref/{}", name),
+            Throughput::Bytes(len),
+            || parse_tag_ref(&bytes),
+        );
+    }
+}
@@ -0,0 +1,58 @@
+// Minimal timing harness for the benchmarks, which run with `harness = false`.
+//
+// Each benchmark is run in batches of doubling size until a batch takes at least
+// half a second, and the mean time per iteration of that batch is reported. Any
+// command line arguments which don't start with "--" are substring filters on the
+// benchmark names, like with the standard harness.
+
+#![allow(dead_code)]
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const MIN_BATCH_TIME: Duration = Duration::from_millis(500);
+
+/// Amount of work done in one iteration, for reporting the throughput.
+pub enum Throughput {
+    Bytes(usize),
+    Elements(usize),
+}
+
+fn selected(name: &str) -> bool {
+    let mut filters = std::env::args().skip(1).filter(|a| !a.starts_with("--"));
+    match filters.next() {
+        None => true,
+        Some(first) => name.contains(&first) || filters.any(|f| name.contains(&f)),
+    }
+}
+
+/// Time `f`, printing the mean time per iteration and the throughput.
+pub fn bench<T, F: FnMut() -> T>(name: &str, throughput: Throughput, mut f: F) {
+    if !selected(name) {
+        return;
+    }
+    let mut iters = 1u32;
+    let elapsed = loop {
+        let start = Instant::now();
+        for _ in 0..iters {
+            black_box(f());
+        }
+        let elapsed = start.elapsed();
+        if elapsed >= MIN_BATCH_TIME {
+            break elapsed;
+        }
+        iters *= 2;
+    };
+    let per_iter = elapsed / iters;
+    let secs = per_iter.as_secs_f64();
+    let rate = match throughput {
+        Throughput::Bytes(n) => format!("{:.1} MiB/s", n as f64 / secs / (1024.0 * 1024.0)),
+        Throughput::Elements(n) => format!("{:.0} elem/s", n as f64 / secs),
+    };
+    println!(
+        "{:<40} {:>12} ns/iter {:>16}",
+        name,
+        per_iter.as_nanos(),
+        rate
+    );
+}
@@ -0,0 +1,64 @@
+// Search result processing, against the in-memory test server.
+//
+// Run with `cargo bench --features synthetic --bench search`.
+
+mod common;
+
+use common::{bench, Throughput};
+
+use ldap3::testkit::MockServer;
+use ldap3::{AttrInterner, InternedEntry, Ldap, ResultEntry, Scope, SearchEntry, ValueEntry};
+use tokio::runtime::Runtime;
+
+const BASE: &str = "ou=People,dc=example,dc=org";
+const ENTRIES: usize = 1000;
+
+async fn search(ldap: &mut Ldap) -> Vec<ResultEntry> {
+    let (entries, _) = ldap
+        .search(BASE, Scope::OneLevel, "(objectClass=*)", vec!["*"])
+        .await
+        .expect("search")
+        .success()
+        .expect("result");
+    entries
+}
+
+fn main() {
+    let rt = Runtime::new().expect("runtime");
+    let server = MockServer::new();
+    server.add_entry(BASE, vec![("objectClass", vec!["organizationalUnit"])]);
+    server.add_synthetic_entries(BASE, ENTRIES, 20, 32);
+    let mut ldap = rt.block_on(async {
+        let (conn, ldap) = server.connect();
+        ldap3::drive!(conn);
+        ldap
+    });
+    let entries = rt.block_on(search(&mut ldap));
+    assert_eq!(entries.len(), ENTRIES);
+
+    bench(
+        "construct/SearchEntry",
+        Throughput::Elements(ENTRIES),
+        || entries.iter().cloned().map(SearchEntry::construct).count(),
+    );
+    bench(
+        "construct/ValueEntry",
+        Throughput::Elements(ENTRIES),
+        || entries.iter().cloned().map(ValueEntry::construct).count(),
+    );
+    let mut names = AttrInterner::new();
+    bench(
+        "construct/InternedEntry",
+        Throughput::Elements(ENTRIES),
+        || {
+            entries
+                .iter()
+                .cloned()
+                .map(|e| InternedEntry::construct(e, &mut names))
+                .count()
+        },
+    );
+    bench("search/mock_server", Throughput::Elements(ENTRIES), || {
+        rt.block_on(search(&mut ldap)).len()
+    });
+}
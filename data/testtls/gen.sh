@@ -0,0 +1,11 @@
+#!/bin/sh
+# Regenerate the CA and the server certificate used by the TLS tests.
+set -e
+openssl req -x509 -newkey rsa:2048 -nodes -keyout ca.key -out ca.pem -days 36500 \
+    -subj "/CN=ldap3 test CA" -addext "basicConstraints=critical,CA:TRUE" \
+    -addext "keyUsage=critical,keyCertSign,cRLSign"
+openssl req -newkey rsa:2048 -nodes -keyout server.key -out server.csr -subj "/CN=localhost"
+printf 'subjectAltName=DNS:localhost,DNS:ldap.example.test\nextendedKeyUsage=serverAuth\n' > server.ext
+openssl x509 -req -in server.csr -CA ca.pem -CAkey ca.key -CAcreateserial -out server.pem \
+    -days 36500 -extfile server.ext
+rm -f ca.key ca.srl server.csr server.ext
@@ -20,5 +20,5 @@ fn main() -> Result<()> {
         )?
         .success()?;
     println!("{:?}", res);
-    Ok(ldap.unbind()?)
+    ldap.unbind()
 }
@@ -31,5 +31,5 @@ async fn main() -> Result<()> {
         println!("{:?}", entry);
     }
     let _res = search.finish().await.success()?;
-    Ok(ldap.unbind().await?)
+    ldap.unbind().await
 }
@@ -0,0 +1,356 @@
+//! Derive macros for the `BerEncode` and `BerDecode` traits of `lber`.
+//!
+//! The macros are re-exported by `lber::codec` with the `derive` feature, where the
+//! attributes they accept are documented.
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::spanned::Spanned;
+use syn::{
+    parse_macro_input, Attribute, Data, DeriveInput, Error, Fields, GenericArgument, Ident, LitInt,
+    LitStr, PathArguments, Type,
+};
+
+#[derive(Default)]
+struct TagAttrs {
+    class: Option<TokenStream2>,
+    tag: Option<u64>,
+    explicit: bool,
+    optional: bool,
+    default: bool,
+    seq_of: bool,
+    set_of: bool,
+}
+
+fn class_path(lit: &LitStr) -> syn::Result<TokenStream2> {
+    let variant = match lit.value().as_str() {
+        "universal" => quote!(Universal),
+        "application" => quote!(Application),
+        "context" => quote!(Context),
+        "private" => quote!(Private),
+        _ => return Err(Error::new(lit.span(), "unknown tag class")),
+    };
+    Ok(quote!(::lber::common::TagClass::#variant))
+}
+
+fn parse_attrs(attrs: &[Attribute]) -> syn::Result<TagAttrs> {
+    let mut parsed = TagAttrs::default();
+    for attr in attrs.iter().filter(|a| a.path().is_ident("ber")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("class") {
+                parsed.class = Some(class_path(&meta.value()?.parse::<LitStr>()?)?);
+            } else if meta.path.is_ident("tag") {
+                parsed.tag = Some(meta.value()?.parse::<LitInt>()?.base10_parse()?);
+            } else if meta.path.is_ident("explicit") {
+                parsed.explicit = true;
+            } else if meta.path.is_ident("optional") {
+                parsed.optional = true;
+            } else if meta.path.is_ident("default") {
+                parsed.default = true;
+            } else if meta.path.is_ident("seq_of") {
+                parsed.seq_of = true;
+            } else if meta.path.is_ident("set_of") {
+                parsed.set_of = true;
+            } else {
+                return Err(meta.error("unknown ber attribute"));
+            }
+            Ok(())
+        })?;
+    }
+    Ok(parsed)
+}
+
+// The single type argument of a generic type named `outer`, as in Option<T>.
+fn type_arg<'a>(ty: &'a Type, outer: &str) -> Option<&'a Type> {
+    let seg = match ty {
+        Type::Path(tp) => tp.path.segments.last()?,
+        _ => return None,
+    };
+    if seg.ident != outer {
+        return None;
+    }
+    match seg.arguments {
+        PathArguments::AngleBracketed(ref args) if args.args.len() == 1 => match args.args[0] {
+            GenericArgument::Type(ref ty) => Some(ty),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+struct Field<'a> {
+    ident: &'a Ident,
+    attrs: TagAttrs,
+    // Type of the value, without the Option of an optional field.
+    ty: &'a Type,
+    // Element type of a SEQUENCE OF or SET OF.
+    elem: Option<&'a Type>,
+}
+
+impl<'a> Field<'a> {
+    fn new(field: &'a syn::Field) -> syn::Result<Field<'a>> {
+        let attrs = parse_attrs(&field.attrs)?;
+        let ident = field.ident.as_ref().expect("named field");
+        let ty = if attrs.optional {
+            type_arg(&field.ty, "Option")
+                .ok_or_else(|| Error::new(field.ty.span(), "optional field must be an Option"))?
+        } else {
+            &field.ty
+        };
+        if attrs.optional && attrs.default {
+            return Err(Error::new(
+                field.span(),
+                "field can't be both optional and default",
+            ));
+        }
+        if attrs.explicit && attrs.tag.is_none() {
+            return Err(Error::new(
+                field.span(),
+                "explicit tagging needs a tag number",
+            ));
+        }
+        if attrs.class.is_some() && attrs.tag.is_none() {
+            return Err(Error::new(field.span(), "tag class without a tag number"));
+        }
+        let elem = if attrs.seq_of || attrs.set_of {
+            if attrs.explicit {
+                return Err(Error::new(
+                    field.span(),
+                    "explicit SEQUENCE OF is unsupported",
+                ));
+            }
+            Some(type_arg(ty, "Vec").ok_or_else(|| {
+                Error::new(ty.span(), "SEQUENCE OF or SET OF field must be a Vec")
+            })?)
+        } else {
+            None
+        };
+        Ok(Field {
+            ident,
+            attrs,
+            ty,
+            elem,
+        })
+    }
+
+    // Expression for the (class, id) of the field's tag on the wire.
+    fn tag(&self, trait_name: &TokenStream2) -> TokenStream2 {
+        let ty = self.ty;
+        match self.attrs.tag {
+            Some(id) => {
+                let class = self
+                    .attrs
+                    .class
+                    .clone()
+                    .unwrap_or_else(|| quote!(::lber::common::TagClass::Context));
+                quote!((#class, #id))
+            }
+            None if self.attrs.seq_of => {
+                quote!((
+                    ::lber::common::TagClass::Universal,
+                    ::lber::universal::Types::Sequence as u64
+                ))
+            }
+            None if self.attrs.set_of => {
+                quote!((
+                    ::lber::common::TagClass::Universal,
+                    ::lber::universal::Types::Set as u64
+                ))
+            }
+            None => quote!(<#ty as #trait_name>::TAG),
+        }
+    }
+
+    fn encode(&self) -> TokenStream2 {
+        let ident = self.ident;
+        let tag = self.tag(&quote!(::lber::codec::BerEncode));
+        let value = if self.attrs.explicit {
+            quote!(::lber::codec::encode_explicit(v, class, id))
+        } else if self.elem.is_some() {
+            quote!(::lber::codec::encode_seq_of(&v[..], class, id))
+        } else {
+            quote!(::lber::codec::BerEncode::ber_encode(v, class, id))
+        };
+        let push = quote! {
+            let (class, id) = #tag;
+            comps.push(#value);
+        };
+        if self.attrs.optional {
+            quote! {
+                if let ::std::option::Option::Some(ref v) = self.#ident {
+                    #push
+                }
+            }
+        } else if self.attrs.default {
+            let ty = self.ty;
+            quote! {
+                if self.#ident != <#ty as ::std::default::Default>::default() {
+                    let v = &self.#ident;
+                    #push
+                }
+            }
+        } else {
+            quote! {
+                {
+                    let v = &self.#ident;
+                    #push
+                }
+            }
+        }
+    }
+
+    fn decode(&self, name: &Ident) -> TokenStream2 {
+        let ident = self.ident;
+        let ty = self.ty;
+        let tag = self.tag(&quote!(::lber::codec::BerDecode));
+        let value = if self.attrs.explicit {
+            quote!(::lber::codec::decode_explicit::<#ty>(c)?)
+        } else if let Some(elem) = self.elem {
+            quote!(::lber::codec::decode_seq_of::<#elem>(c)?)
+        } else {
+            quote!(<#ty as ::lber::codec::BerDecode>::ber_decode(c)?)
+        };
+        let missing = format!("{}: missing field {}", name, ident);
+        let absent = if self.attrs.optional {
+            quote!(::std::option::Option::None)
+        } else if self.attrs.default {
+            quote!(::std::default::Default::default())
+        } else {
+            quote!(return ::std::result::Result::Err(::lber::structures::ValueError(#missing)))
+        };
+        let present = if self.attrs.optional {
+            quote!(::std::option::Option::Some(#value))
+        } else {
+            value
+        };
+        quote! {
+            let #ident = match comps.next_if(|c| (c.class, c.id) == #tag) {
+                ::std::option::Option::Some(c) => #present,
+                ::std::option::Option::None => #absent,
+            };
+        }
+    }
+}
+
+struct Input<'a> {
+    name: &'a Ident,
+    generics: &'a syn::Generics,
+    tag: TokenStream2,
+    fields: Vec<Field<'a>>,
+}
+
+fn parse_input(input: &DeriveInput) -> syn::Result<Input<'_>> {
+    let fields = match input.data {
+        Data::Struct(ref data) => match data.fields {
+            Fields::Named(ref fields) => fields
+                .named
+                .iter()
+                .map(Field::new)
+                .collect::<syn::Result<Vec<_>>>()?,
+            _ => {
+                return Err(Error::new(
+                    input.span(),
+                    "only structs with named fields are supported",
+                ))
+            }
+        },
+        _ => return Err(Error::new(input.span(), "only structs are supported")),
+    };
+    let attrs = parse_attrs(&input.attrs)?;
+    let tag = match attrs.tag {
+        Some(id) => {
+            let class = attrs
+                .class
+                .unwrap_or_else(|| quote!(::lber::common::TagClass::Context));
+            quote!((#class, #id))
+        }
+        None => {
+            quote!((
+                ::lber::common::TagClass::Universal,
+                ::lber::universal::Types::Sequence as u64
+            ))
+        }
+    };
+    Ok(Input {
+        name: &input.ident,
+        generics: &input.generics,
+        tag,
+        fields,
+    })
+}
+
+/// Derive `BerEncode` for a struct representing a SEQUENCE.
+#[proc_macro_derive(BerEncode, attributes(ber))]
+pub fn derive_ber_encode(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let input = match parse_input(&input) {
+        Ok(input) => input,
+        Err(e) => return e.to_compile_error().into(),
+    };
+    let Input {
+        name,
+        generics,
+        tag,
+        fields,
+    } = input;
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let encode = fields.iter().map(Field::encode);
+    let expanded = quote! {
+        impl #impl_generics ::lber::codec::BerEncode for #name #ty_generics #where_clause {
+            const TAG: (::lber::common::TagClass, u64) = #tag;
+
+            fn ber_encode(
+                &self,
+                class: ::lber::common::TagClass,
+                id: u64,
+            ) -> ::lber::structure::StructureTag {
+                let (outer_class, outer_id) = (class, id);
+                #[allow(unused_mut)]
+                let mut comps = ::std::vec::Vec::new();
+                #(#encode)*
+                ::lber::structure::StructureTag {
+                    class: outer_class,
+                    id: outer_id,
+                    payload: ::lber::structure::PL::C(comps),
+                }
+            }
+        }
+    };
+    expanded.into()
+}
+
+/// Derive `BerDecode` for a struct representing a SEQUENCE.
+#[proc_macro_derive(BerDecode, attributes(ber))]
+pub fn derive_ber_decode(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let input = match parse_input(&input) {
+        Ok(input) => input,
+        Err(e) => return e.to_compile_error().into(),
+    };
+    let Input {
+        name,
+        generics,
+        tag,
+        fields,
+    } = input;
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let decode = fields.iter().map(|f| f.decode(name));
+    let idents = fields.iter().map(|f| f.ident);
+    let expanded = quote! {
+        impl #impl_generics ::lber::codec::BerDecode for #name #ty_generics #where_clause {
+            const TAG: (::lber::common::TagClass, u64) = #tag;
+
+            fn ber_decode(
+                tag: ::lber::structure::StructureTag,
+            ) -> ::std::result::Result<Self, ::lber::structures::ValueError> {
+                #[allow(unused_mut, unused_variables)]
+                let mut comps = ::lber::codec::constructed(tag)?.into_iter().peekable();
+                #(#decode)*
+                ::std::result::Result::Ok(#name { #(#idents),* })
+            }
+        }
+    };
+    expanded.into()
+}
@@ -0,0 +1,74 @@
+use lber::codec::{self, BerDecode, BerEncode};
+use lber::structures::ObjectIdentifier;
+
+#[derive(Debug, PartialEq, BerEncode, BerDecode)]
+struct Inner {
+    oid: ObjectIdentifier,
+    #[ber(default)]
+    critical: bool,
+}
+
+#[derive(Debug, PartialEq, BerEncode, BerDecode)]
+#[ber(class = "application", tag = 3)]
+struct Outer {
+    #[ber(class = "universal", tag = 10)]
+    code: u8,
+    #[ber(tag = 0, optional)]
+    name: Option<String>,
+    #[ber(tag = 1, explicit)]
+    count: i64,
+    #[ber(seq_of)]
+    inner: Vec<Inner>,
+    #[ber(tag = 2, set_of)]
+    values: Vec<Vec<u8>>,
+}
+
+#[test]
+fn round_trip() {
+    let outer = Outer {
+        code: 4,
+        name: None,
+        count: -2,
+        inner: vec![
+            Inner {
+                oid: "2.5.4.3".parse().unwrap(),
+                critical: false,
+            },
+            Inner {
+                oid: "2.5.4.4".parse().unwrap(),
+                critical: true,
+            },
+        ],
+        values: vec![b"a".to_vec()],
+    };
+    let bytes = codec::encode(&outer);
+    assert_eq!(
+        bytes,
+        vec![
+            0x63, 0x20, // [APPLICATION 3]
+            0x0a, 0x01, 0x04, // code
+            0xa1, 0x03, 0x02, 0x01, 0xfe, // count
+            0x30, 0x11, // inner
+            0x30, 0x05, 0x06, 0x03, 0x55, 0x04, 0x03, // default omitted
+            0x30, 0x08, 0x06, 0x03, 0x55, 0x04, 0x04, 0x01, 0x01, 0xff, //
+            0xa2, 0x03, 0x04, 0x01, 0x61, // values
+        ]
+    );
+    assert_eq!(codec::decode::<Outer>(&bytes), Ok(outer));
+}
+
+#[test]
+fn missing_and_extra_components() {
+    // Required field absent.
+    let err = codec::decode::<Inner>(&[0x30, 0x03, 0x01, 0x01, 0xff]).unwrap_err();
+    assert_eq!(err.0, "Inner: missing field oid");
+    // Trailing component ignored.
+    let inner = codec::decode::<Inner>(&[0x30, 0x07, 0x06, 0x01, 0x2a, 0x05, 0x00, 0x05, 0x00]);
+    assert_eq!(
+        inner,
+        Ok(Inner {
+            oid: "1.2".parse().unwrap(),
+            critical: false,
+        })
+    );
+}
@@ -0,0 +1,312 @@
+//! Typed encoding and decoding of ASN.1 values.
+//!
+//! [`BerEncode`](trait.BerEncode.html) and [`BerDecode`](trait.BerDecode.html) map Rust
+//! values to tag structures and back. They are implemented for the basic types, and
+//! with the `derive` feature, they can be derived for structs representing a SEQUENCE:
+//!
+//! ```rust,ignore
+//! use lber::codec::{BerDecode, BerEncode};
+//!
+//! // SortResult ::= SEQUENCE {
+//! //     sortResult     ENUMERATED,
+//! //     attributeType  [0] AttributeDescription OPTIONAL }
+//! #[derive(BerEncode, BerDecode)]
+//! struct SortResult {
+//!     #[ber(class = "universal", tag = 10)]
+//!     result: u32,
+//!     #[ber(tag = 0, optional)]
+//!     attribute: Option<String>,
+//! }
+//! ```
+//!
+//! The fields are encoded in declaration order. A field attribute can change the tag
+//! of the field's type: `tag = N` sets the tag number, with the context class unless
+//! `class` is also given, and `explicit` wraps the value in a constructed element with
+//! that tag, instead of replacing its own tag. An `optional` field must be an `Option`,
+//! and is omitted if `None`; a `default` field is omitted if equal to its type's default
+//! value, and set to it if absent. A `Vec` field marked with `seq_of` or `set_of` is a
+//! SEQUENCE OF or SET OF its elements. Placed on the struct, `tag` and `class` change the
+//! tag of the SEQUENCE itself.
+//!
+//! When decoding, components following the last field are ignored, for compatibility
+//! with extended versions of the structure.
+use common::TagClass;
+use parse::parse_tag;
+use structure::{StructureTag, PL};
+use structures::{ASNTag, BigInteger, ObjectIdentifier, ValueError};
+use universal::Types;
+use write;
+
+use bytes::BytesMut;
+
+use std::convert::TryFrom;
+
+#[cfg(feature = "derive")]
+pub use lber_derive::{BerDecode, BerEncode};
+
+/// Conversion of a value into a tag structure.
+pub trait BerEncode {
+    /// Class and number of the type's own tag.
+    const TAG: (TagClass, u64);
+
+    /// Encode the value with the given tag, which replaces the type's own tag
+    /// if different.
+    fn ber_encode(&self, class: TagClass, id: u64) -> StructureTag;
+}
+
+/// Conversion of a tag structure into a value.
+pub trait BerDecode: Sized {
+    /// Class and number of the type's own tag.
+    const TAG: (TagClass, u64);
+
+    /// Decode the value from the structure. The tag has already been checked
+    /// by the caller, and may differ from the type's own one.
+    fn ber_decode(tag: StructureTag) -> Result<Self, ValueError>;
+}
+
+/// Encode a value with its own tag.
+pub fn encode<T: BerEncode>(value: &T) -> Vec<u8> {
+    let mut buf = BytesMut::new();
+    write::encode_into(&mut buf, value.ber_encode(T::TAG.0, T::TAG.1)).expect("encoded");
+    buf.to_vec()
+}
+
+/// Decode a value with its own tag from BER data.
+pub fn decode<T: BerDecode>(bytes: &[u8]) -> Result<T, ValueError> {
+    match parse_tag(bytes) {
+        ::IResult::Done(_, tag) if (tag.class, tag.id) == T::TAG => T::ber_decode(tag),
+        ::IResult::Done(_, _) => Err(ValueError("unexpected tag")),
+        _ => Err(ValueError("failed to parse tag")),
+    }
+}
+
+/// Contents of a primitive element.
+pub fn primitive(tag: StructureTag) -> Result<Vec<u8>, ValueError> {
+    tag.expect_primitive()
+        .ok_or(ValueError("constructed element instead of primitive"))
+}
+
+/// Components of a constructed element.
+pub fn constructed(tag: StructureTag) -> Result<Vec<StructureTag>, ValueError> {
+    tag.expect_constructed()
+        .ok_or(ValueError("primitive element instead of constructed"))
+}
+
+fn prim(class: TagClass, id: u64, value: Vec<u8>) -> StructureTag {
+    StructureTag {
+        class,
+        id,
+        payload: PL::P(value),
+    }
+}
+
+impl BerEncode for bool {
+    const TAG: (TagClass, u64) = (TagClass::Universal, Types::Boolean as u64);
+
+    fn ber_encode(&self, class: TagClass, id: u64) -> StructureTag {
+        prim(class, id, vec![if *self { 0xff } else { 0x00 }])
+    }
+}
+
+impl BerDecode for bool {
+    const TAG: (TagClass, u64) = (TagClass::Universal, Types::Boolean as u64);
+
+    fn ber_decode(tag: StructureTag) -> Result<bool, ValueError> {
+        match primitive(tag)?[..] {
+            [b] => Ok(b != 0),
+            _ => Err(ValueError("invalid BOOLEAN")),
+        }
+    }
+}
+
+impl BerEncode for BigInteger {
+    const TAG: (TagClass, u64) = (TagClass::Universal, Types::Integer as u64);
+
+    fn ber_encode(&self, class: TagClass, id: u64) -> StructureTag {
+        prim(class, id, self.as_bytes().to_vec())
+    }
+}
+
+impl BerDecode for BigInteger {
+    const TAG: (TagClass, u64) = (TagClass::Universal, Types::Integer as u64);
+
+    fn ber_decode(tag: StructureTag) -> Result<BigInteger, ValueError> {
+        let bytes = primitive(tag)?;
+        if bytes.is_empty() {
+            return Err(ValueError("empty INTEGER"));
+        }
+        Ok(BigInteger::from_bytes(&bytes))
+    }
+}
+
+macro_rules! integer_impls {
+    ($($t:ty),*) => {
+        $(
+            impl BerEncode for $t {
+                const TAG: (TagClass, u64) = (TagClass::Universal, Types::Integer as u64);
+
+                fn ber_encode(&self, class: TagClass, id: u64) -> StructureTag {
+                    BigInteger::from(*self as i128).ber_encode(class, id)
+                }
+            }
+
+            impl BerDecode for $t {
+                const TAG: (TagClass, u64) = (TagClass::Universal, Types::Integer as u64);
+
+                fn ber_decode(tag: StructureTag) -> Result<$t, ValueError> {
+                    BigInteger::ber_decode(tag)?
+                        .to_i128()
+                        .and_then(|v| <$t>::try_from(v).ok())
+                        .ok_or(ValueError(concat!("INTEGER out of range for ", stringify!($t))))
+                }
+            }
+        )*
+    };
+}
+
+integer_impls!(i8, i16, i32, i64, u8, u16, u32, u64);
+
+impl BerEncode for Vec<u8> {
+    const TAG: (TagClass, u64) = (TagClass::Universal, Types::OctetString as u64);
+
+    fn ber_encode(&self, class: TagClass, id: u64) -> StructureTag {
+        prim(class, id, self.clone())
+    }
+}
+
+impl BerDecode for Vec<u8> {
+    const TAG: (TagClass, u64) = (TagClass::Universal, Types::OctetString as u64);
+
+    fn ber_decode(tag: StructureTag) -> Result<Vec<u8>, ValueError> {
+        primitive(tag)
+    }
+}
+
+impl BerEncode for String {
+    const TAG: (TagClass, u64) = (TagClass::Universal, Types::OctetString as u64);
+
+    fn ber_encode(&self, class: TagClass, id: u64) -> StructureTag {
+        prim(class, id, self.as_bytes().to_vec())
+    }
+}
+
+impl BerDecode for String {
+    const TAG: (TagClass, u64) = (TagClass::Universal, Types::OctetString as u64);
+
+    fn ber_decode(tag: StructureTag) -> Result<String, ValueError> {
+        String::from_utf8(primitive(tag)?).map_err(|_| ValueError("invalid UTF-8 string"))
+    }
+}
+
+impl BerEncode for () {
+    const TAG: (TagClass, u64) = (TagClass::Universal, Types::Null as u64);
+
+    fn ber_encode(&self, class: TagClass, id: u64) -> StructureTag {
+        prim(class, id, vec![])
+    }
+}
+
+impl BerDecode for () {
+    const TAG: (TagClass, u64) = (TagClass::Universal, Types::Null as u64);
+
+    fn ber_decode(tag: StructureTag) -> Result<(), ValueError> {
+        if primitive(tag)?.is_empty() {
+            Ok(())
+        } else {
+            Err(ValueError("non-empty NULL"))
+        }
+    }
+}
+
+impl BerEncode for ObjectIdentifier {
+    const TAG: (TagClass, u64) = (TagClass::Universal, Types::ObjectIdentifier as u64);
+
+    fn ber_encode(&self, class: TagClass, id: u64) -> StructureTag {
+        StructureTag {
+            class,
+            id,
+            ..self.clone().into_structure()
+        }
+    }
+}
+
+impl BerDecode for ObjectIdentifier {
+    const TAG: (TagClass, u64) = (TagClass::Universal, Types::ObjectIdentifier as u64);
+
+    fn ber_decode(tag: StructureTag) -> Result<ObjectIdentifier, ValueError> {
+        ObjectIdentifier::from_bytes(&primitive(tag)?)
+    }
+}
+
+/// Encode the elements of a SEQUENCE OF or SET OF, with their own tags.
+pub fn encode_seq_of<T: BerEncode>(items: &[T], class: TagClass, id: u64) -> StructureTag {
+    StructureTag {
+        class,
+        id,
+        payload: PL::C(
+            items
+                .iter()
+                .map(|item| item.ber_encode(T::TAG.0, T::TAG.1))
+                .collect(),
+        ),
+    }
+}
+
+/// Decode the elements of a SEQUENCE OF or SET OF, checking their tags.
+pub fn decode_seq_of<T: BerDecode>(tag: StructureTag) -> Result<Vec<T>, ValueError> {
+    constructed(tag)?
+        .into_iter()
+        .map(|item| {
+            if (item.class, item.id) == T::TAG {
+                T::ber_decode(item)
+            } else {
+                Err(ValueError("unexpected tag in SEQUENCE OF"))
+            }
+        })
+        .collect()
+}
+
+/// Wrap a value in an explicit tag.
+pub fn encode_explicit<T: BerEncode>(value: &T, class: TagClass, id: u64) -> StructureTag {
+    StructureTag {
+        class,
+        id,
+        payload: PL::C(vec![value.ber_encode(T::TAG.0, T::TAG.1)]),
+    }
+}
+
+/// Unwrap a value from an explicit tag.
+pub fn decode_explicit<T: BerDecode>(tag: StructureTag) -> Result<T, ValueError> {
+    match constructed(tag)?.pop() {
+        Some(inner) if (inner.class, inner.id) == T::TAG => T::ber_decode(inner),
+        _ => Err(ValueError("invalid explicitly tagged value")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn basic_types() {
+        assert_eq!(encode(&-129i32), vec![0x02, 0x02, 0xff, 0x7f]);
+        assert_eq!(decode::<i32>(&[0x02, 0x02, 0xff, 0x7f]), Ok(-129));
+        assert_eq!(encode(&0x8000_0000u32), vec![0x02, 0x05, 0, 0x80, 0, 0, 0]);
+        assert!(decode::<u32>(&[0x02, 0x01, 0xff]).is_err());
+        assert!(decode::<u8>(&[0x02, 0x02, 0x01, 0x00]).is_err());
+        assert_eq!(encode(&true), vec![0x01, 0x01, 0xff]);
+        assert_eq!(decode::<bool>(&[0x01, 0x01, 0x01]), Ok(true));
+        assert_eq!(
+            decode::<String>(&[0x04, 0x02, 0x61, 0x62]),
+            Ok(String::from("ab"))
+        );
+        assert!(decode::<String>(&[0x02, 0x01, 0x00]).is_err());
+        let oid = "2.5.4.3".parse::<ObjectIdentifier>().unwrap();
+        assert_eq!(encode(&oid), vec![0x06, 0x03, 0x55, 0x04, 0x03]);
+        assert_eq!(decode::<ObjectIdentifier>(&encode(&oid)), Ok(oid));
+        let seq = encode_seq_of(&[1u8, 2], TagClass::Universal, Types::Set as u64);
+        assert_eq!(decode_seq_of::<u8>(seq), Ok(vec![1, 2]));
+        let explicit = encode_explicit(&5u8, TagClass::Context, 1);
+        assert_eq!(decode_explicit::<u8>(explicit), Ok(5));
+    }
+}
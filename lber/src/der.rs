@@ -0,0 +1,360 @@
+//! Strict DER decoding and encoding.
+//!
+//! The Distinguished Encoding Rules are the subset of BER which allows exactly one
+//! encoding for every value. LDAP itself doesn't require them, but formats built around
+//! certificates do. The functions in this module are counterparts of
+//! [`parse::parse_tag()`](../parse/fn.parse_tag.html) and
+//! [`write::encode_into()`](../write/fn.encode_into.html) which enforce the following:
+//!
+//! * lengths are definite, and encoded in the minimal number of octets;
+//! * tag numbers are encoded in the minimal number of octets;
+//! * the components of a SET are sorted by their encodings;
+//! * a BOOLEAN is a single octet, either 0x00 or 0xff;
+//! * string types are primitive.
+//!
+//! A violation is reported as a [`DerError`](struct.DerError.html), which names
+//! the offending element by its path from the outermost one.
+use bytes::BytesMut;
+use common::{TagClass, TagStructure};
+use parse::{parse_header, parse_tag};
+use structure::{StructureTag, PL};
+use universal::Types;
+use write;
+
+use std::error::Error;
+use std::fmt;
+
+/// Kind of DER violation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DerViolation {
+    /// The element can't be parsed at all.
+    Malformed,
+    /// The element extends past the end of the input.
+    Truncated,
+    /// The length is in the indefinite form.
+    IndefiniteLength,
+    /// The length is encoded in more octets than necessary.
+    NonMinimalLength,
+    /// The tag number is encoded in more octets than necessary.
+    NonMinimalTag,
+    /// The components of a SET aren't sorted by their encodings.
+    UnsortedSet,
+    /// A BOOLEAN isn't a single octet with the value 0x00 or 0xff.
+    InvalidBoolean,
+    /// A string type is constructed.
+    ConstructedString,
+}
+
+impl fmt::Display for DerViolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            DerViolation::Malformed => "malformed element",
+            DerViolation::Truncated => "element truncated",
+            DerViolation::IndefiniteLength => "indefinite length",
+            DerViolation::NonMinimalLength => "length not minimally encoded",
+            DerViolation::NonMinimalTag => "tag number not minimally encoded",
+            DerViolation::UnsortedSet => "SET components not sorted",
+            DerViolation::InvalidBoolean => "invalid BOOLEAN value",
+            DerViolation::ConstructedString => "constructed string",
+        })
+    }
+}
+
+/// Error for data which isn't valid DER.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DerError {
+    /// Path to the offending element. Each component is the element's index in its
+    /// container, followed by its tag, as in `1:[UNIVERSAL 17]`; the outermost element
+    /// has no index.
+    pub path: Vec<String>,
+    pub violation: DerViolation,
+}
+
+impl fmt::Display for DerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "DER violation at {}: {}",
+            self.path.join(" / "),
+            self.violation
+        )
+    }
+}
+
+impl Error for DerError {}
+
+fn segment(index: Option<usize>, class: TagClass, id: u64) -> String {
+    let class = match class {
+        TagClass::Universal => "UNIVERSAL",
+        TagClass::Application => "APPLICATION",
+        TagClass::Context => "CONTEXT",
+        TagClass::Private => "PRIVATE",
+    };
+    match index {
+        Some(index) => format!("{}:[{} {}]", index, class, id),
+        None => format!("[{} {}]", class, id),
+    }
+}
+
+fn is_string(class: TagClass, id: u64) -> bool {
+    class == TagClass::Universal
+        && (id == Types::BitString as u64
+            || id == Types::OctetString as u64
+            || id == Types::Utf8String as u64
+            || (id >= Types::NumericString as u64
+                && id <= Types::BmpString as u64
+                && id != Types::UtcTime as u64
+                && id != Types::GeneralizedTime as u64))
+}
+
+fn is_set(class: TagClass, id: u64) -> bool {
+    class == TagClass::Universal && id == Types::Set as u64
+}
+
+fn check_boolean(class: TagClass, id: u64, value: &[u8]) -> bool {
+    class != TagClass::Universal
+        || id != Types::Boolean as u64
+        || value == [0x00]
+        || value == [0xff]
+}
+
+// Check the element at the start of i, returning its total length.
+fn check(i: &[u8], index: Option<usize>, path: &mut Vec<String>) -> Result<usize, DerError> {
+    let fail = |path: &[String], violation| {
+        Err(DerError {
+            path: path.to_vec(),
+            violation,
+        })
+    };
+    let hdr = match parse_header(i) {
+        ::IResult::Done(_, hdr) => hdr,
+        ::IResult::Incomplete(_) => return fail(path, DerViolation::Truncated),
+        ::IResult::Error(_) => return fail(path, DerViolation::Malformed),
+    };
+    path.push(segment(index, hdr.class, hdr.id));
+    let id_len = if i[0] & 0x1f == 0x1f {
+        if hdr.id < 0x1f || i[1] == 0x80 {
+            return fail(path, DerViolation::NonMinimalTag);
+        }
+        2 + i[1..]
+            .iter()
+            .position(|&b| b & 0x80 == 0)
+            .expect("end of tag")
+    } else {
+        1
+    };
+    let len = match hdr.len {
+        Some(len) => len,
+        None => return fail(path, DerViolation::IndefiniteLength),
+    };
+    let min_len_octets = if len < 0x80 {
+        1
+    } else {
+        1 + (64 - len.leading_zeros() as usize).div_ceil(8)
+    };
+    if hdr.header_len - id_len != min_len_octets {
+        return fail(path, DerViolation::NonMinimalLength);
+    }
+    let total = match hdr.total_len() {
+        Some(total) if total <= i.len() => total,
+        _ => return fail(path, DerViolation::Truncated),
+    };
+    let content = &i[hdr.header_len..total];
+    match hdr.structure {
+        TagStructure::Primitive => {
+            if !check_boolean(hdr.class, hdr.id, content) {
+                return fail(path, DerViolation::InvalidBoolean);
+            }
+        }
+        TagStructure::Constructed => {
+            if is_string(hdr.class, hdr.id) {
+                return fail(path, DerViolation::ConstructedString);
+            }
+            let mut pos = 0;
+            let mut prev: Option<&[u8]> = None;
+            for index in 0.. {
+                if pos == content.len() {
+                    break;
+                }
+                let len = check(&content[pos..], Some(index), path)?;
+                let comp = &content[pos..pos + len];
+                if is_set(hdr.class, hdr.id) && prev.is_some_and(|prev| prev > comp) {
+                    return fail(path, DerViolation::UnsortedSet);
+                }
+                prev = Some(comp);
+                pos += len;
+            }
+        }
+    }
+    path.pop();
+    Ok(total)
+}
+
+/// Parse DER data into a serializable structure, rejecting any encoding which
+/// isn't canonical.
+///
+/// Unlike [`parse::parse_tag()`](../parse/fn.parse_tag.html), incomplete input is
+/// an error.
+pub fn parse_der(i: &[u8]) -> Result<(&[u8], StructureTag), DerError> {
+    let total = check(i, None, &mut Vec::new())?;
+    match parse_tag(&i[..total]) {
+        ::IResult::Done(_, tag) => Ok((&i[total..], tag)),
+        _ => Err(DerError {
+            path: vec![],
+            violation: DerViolation::Malformed,
+        }),
+    }
+}
+
+// Sort the components of every SET, and check the values which can't be fixed.
+fn canonicalize(
+    tag: StructureTag,
+    index: Option<usize>,
+    path: &mut Vec<String>,
+) -> Result<StructureTag, DerError> {
+    path.push(segment(index, tag.class, tag.id));
+    let fail = |path: &[String], violation| DerError {
+        path: path.to_vec(),
+        violation,
+    };
+    let payload = match tag.payload {
+        PL::P(value) => {
+            if !check_boolean(tag.class, tag.id, &value) {
+                return Err(fail(path, DerViolation::InvalidBoolean));
+            }
+            PL::P(value)
+        }
+        PL::C(tags) => {
+            if is_string(tag.class, tag.id) {
+                return Err(fail(path, DerViolation::ConstructedString));
+            }
+            let mut comps = Vec::with_capacity(tags.len());
+            for (index, tag) in tags.into_iter().enumerate() {
+                comps.push(canonicalize(tag, Some(index), path)?);
+            }
+            if is_set(tag.class, tag.id) {
+                let mut keyed = comps
+                    .into_iter()
+                    .map(|comp| {
+                        let mut buf = BytesMut::new();
+                        write::encode_into(&mut buf, comp.clone()).expect("encoded");
+                        (buf, comp)
+                    })
+                    .collect::<Vec<_>>();
+                keyed.sort_by(|a, b| a.0.cmp(&b.0));
+                comps = keyed.into_iter().map(|(_, comp)| comp).collect();
+            }
+            PL::C(comps)
+        }
+    };
+    path.pop();
+    Ok(StructureTag {
+        class: tag.class,
+        id: tag.id,
+        payload,
+    })
+}
+
+/// DER-encode a tag structure into the provided buffer.
+///
+/// The components of every SET are sorted as required. Values which can't be put into
+/// canonical form, such as a multi-octet BOOLEAN, are rejected.
+pub fn encode_der_into(buf: &mut BytesMut, tag: StructureTag) -> Result<(), DerError> {
+    let tag = canonicalize(tag, None, &mut Vec::new())?;
+    write::encode_into(buf, tag).expect("encoded");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn canonical() {
+        // SEQUENCE { BOOLEAN TRUE, SET { INTEGER 1, INTEGER 2 } }
+        let bytes = [
+            0x30, 0x0b, 0x01, 0x01, 0xff, 0x31, 0x06, 0x02, 0x01, 0x01, 0x02, 0x01, 0x02,
+        ];
+        let (rest, tag) = parse_der(&bytes[..]).unwrap();
+        assert!(rest.is_empty());
+        let mut buf = BytesMut::new();
+        encode_der_into(&mut buf, tag).unwrap();
+        assert_eq!(&buf[..], &bytes[..]);
+    }
+
+    #[test]
+    fn violations() {
+        let cases: &[(&[u8], DerViolation, &[&str])] = &[
+            (
+                &[0x30, 0x81, 0x03, 0x02, 0x01, 0x01],
+                DerViolation::NonMinimalLength,
+                &["[UNIVERSAL 16]"],
+            ),
+            (
+                &[0x30, 0x80, 0x02, 0x01, 0x01, 0x00, 0x00],
+                DerViolation::IndefiniteLength,
+                &["[UNIVERSAL 16]"],
+            ),
+            (
+                &[0x30, 0x06, 0x31, 0x04, 0x01, 0x01, 0x01, 0x00],
+                DerViolation::InvalidBoolean,
+                &["[UNIVERSAL 16]", "0:[UNIVERSAL 17]", "0:[UNIVERSAL 1]"],
+            ),
+            (
+                &[0x30, 0x08, 0x31, 0x06, 0x02, 0x01, 0x02, 0x02, 0x01, 0x01],
+                DerViolation::UnsortedSet,
+                &["[UNIVERSAL 16]", "0:[UNIVERSAL 17]"],
+            ),
+            (
+                &[0x30, 0x03, 0x9f, 0x05, 0x00],
+                DerViolation::NonMinimalTag,
+                &["[UNIVERSAL 16]", "0:[CONTEXT 5]"],
+            ),
+            (
+                &[0x24, 0x03, 0x04, 0x01, 0x61],
+                DerViolation::ConstructedString,
+                &["[UNIVERSAL 4]"],
+            ),
+            (
+                &[0x30, 0x03, 0x02, 0x01],
+                DerViolation::Truncated,
+                &["[UNIVERSAL 16]"],
+            ),
+        ];
+        for &(bytes, violation, path) in cases {
+            let err = parse_der(bytes).unwrap_err();
+            assert_eq!(
+                (err.violation, err.path.iter().map(|s| s.as_str()).collect()),
+                (violation, path.to_vec()),
+                "{:02x?}",
+                bytes
+            );
+        }
+        let err = parse_der(cases[2].0).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "DER violation at [UNIVERSAL 16] / 0:[UNIVERSAL 17] / 0:[UNIVERSAL 1]: invalid BOOLEAN value"
+        );
+    }
+
+    #[test]
+    fn sorted_set() {
+        let int = |v: u8| StructureTag {
+            class: TagClass::Universal,
+            id: Types::Integer as u64,
+            payload: PL::P(vec![v]),
+        };
+        let set = StructureTag {
+            class: TagClass::Universal,
+            id: Types::Set as u64,
+            payload: PL::C(vec![int(3), int(1), int(2)]),
+        };
+        let mut buf = BytesMut::new();
+        encode_der_into(&mut buf, set).unwrap();
+        assert_eq!(
+            &buf[..],
+            &[0x31, 0x09, 0x02, 0x01, 0x01, 0x02, 0x01, 0x02, 0x02, 0x01, 0x03]
+        );
+        assert!(parse_der(&buf[..]).is_ok());
+    }
+}
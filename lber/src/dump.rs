@@ -0,0 +1,160 @@
+use common::TagClass;
+use structure::{StructureTag, PL};
+use write;
+
+use std::fmt::Write;
+
+fn universal_name(id: u64) -> Option<&'static str> {
+    Some(match id {
+        0 => "END-OF-CONTENTS",
+        1 => "BOOLEAN",
+        2 => "INTEGER",
+        3 => "BIT STRING",
+        4 => "OCTET STRING",
+        5 => "NULL",
+        6 => "OBJECT IDENTIFIER",
+        7 => "ObjectDescriptor",
+        8 => "EXTERNAL",
+        9 => "REAL",
+        10 => "ENUMERATED",
+        11 => "EMBEDDED PDV",
+        12 => "UTF8String",
+        13 => "RELATIVE-OID",
+        16 => "SEQUENCE",
+        17 => "SET",
+        18 => "NumericString",
+        19 => "PrintableString",
+        20 => "T61String",
+        21 => "VideotexString",
+        22 => "IA5String",
+        23 => "UTCTime",
+        24 => "GeneralizedTime",
+        25 => "GraphicString",
+        26 => "VisibleString",
+        27 => "GeneralString",
+        28 => "UniversalString",
+        29 => "CHARACTER STRING",
+        30 => "BMPString",
+        _ => return None,
+    })
+}
+
+// Bytes per line of a hex dump.
+const LINE: usize = 16;
+
+fn hex_line(out: &mut String, bytes: &[u8], pad: bool) {
+    for (n, b) in bytes.iter().enumerate() {
+        if n > 0 {
+            out.push(' ');
+        }
+        let _ = write!(out, "{:02x}", b);
+    }
+    if pad {
+        for _ in bytes.len()..LINE {
+            out.push_str("   ");
+        }
+    }
+    out.push_str("  |");
+    out.extend(bytes.iter().map(|&b| {
+        if b.is_ascii_graphic() || b == b' ' {
+            b as char
+        } else {
+            '.'
+        }
+    }));
+    out.push_str("|\n");
+}
+
+fn dump_level(out: &mut String, tag: &StructureTag, level: usize) {
+    let indent = level * 2;
+    let class = match tag.class {
+        TagClass::Universal => "UNIVERSAL",
+        TagClass::Application => "APPLICATION",
+        TagClass::Context => "CONTEXT",
+        TagClass::Private => "PRIVATE",
+    };
+    let _ = write!(out, "{:indent$}[{} {}]", "", class, tag.id, indent = indent);
+    if tag.class == TagClass::Universal {
+        if let Some(name) = universal_name(tag.id) {
+            let _ = write!(out, " {}", name);
+        }
+    }
+    match tag.payload {
+        PL::P(ref v) => {
+            let _ = write!(out, " ({})", v.len());
+            if v.is_empty() {
+                out.push('\n');
+            } else if v.len() <= LINE {
+                out.push_str(": ");
+                hex_line(out, v, false);
+            } else {
+                out.push_str(":\n");
+                for (n, line) in v.chunks(LINE).enumerate() {
+                    let _ = write!(out, "{:indent$}{:04x}  ", "", n * LINE, indent = indent + 2);
+                    hex_line(out, line, true);
+                }
+            }
+        }
+        PL::C(ref tags) => {
+            let len: usize = tags.iter().map(write::encoded_len).sum();
+            let _ = writeln!(out, " ({}) {{", len);
+            for tag in tags {
+                dump_level(out, tag, level + 1);
+            }
+            let _ = writeln!(out, "{:indent$}}}", "", indent = indent);
+        }
+    }
+}
+
+// Rendering of StructureTag::pretty().
+pub(crate) fn render(tag: &StructureTag) -> String {
+    let mut out = String::new();
+    dump_level(&mut out, tag, 0);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn annotated_dump() {
+        let tag = StructureTag {
+            class: TagClass::Universal,
+            id: 16,
+            payload: PL::C(vec![
+                StructureTag {
+                    class: TagClass::Universal,
+                    id: 2,
+                    payload: PL::P(vec![0x01]),
+                },
+                StructureTag {
+                    class: TagClass::Application,
+                    id: 2,
+                    payload: PL::P(vec![]),
+                },
+                StructureTag {
+                    class: TagClass::Context,
+                    id: 3,
+                    payload: PL::C(vec![StructureTag {
+                        class: TagClass::Universal,
+                        id: 4,
+                        payload: PL::P(b"cn=Directory Manager".to_vec()),
+                    }]),
+                },
+            ]),
+        };
+        assert_eq!(
+            tag.pretty().to_string(),
+            "[UNIVERSAL 16] SEQUENCE (29) {\n\
+             \x20 [UNIVERSAL 2] INTEGER (1): 01  |.|\n\
+             \x20 [APPLICATION 2] (0)\n\
+             \x20 [CONTEXT 3] (22) {\n\
+             \x20   [UNIVERSAL 4] OCTET STRING (20):\n\
+             \x20     0000  63 6e 3d 44 69 72 65 63 74 6f 72 79 20 4d 61 6e  |cn=Directory Man|\n\
+             \x20     0010  61 67 65 72                                      |ager|\n\
+             \x20 }\n\
+             }\n"
+        );
+    }
+}
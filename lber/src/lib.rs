@@ -2,14 +2,26 @@ extern crate byteorder;
 extern crate bytes;
 #[macro_use]
 extern crate nom;
+#[cfg(feature = "derive")]
+extern crate lber_derive;
+#[cfg(test)]
+extern crate proptest;
+#[cfg(feature = "serde")]
+extern crate serde;
 
+pub mod codec;
 pub mod common;
+pub mod der;
+mod dump;
 pub mod parse;
+pub mod raw;
+#[cfg(feature = "serde")]
+mod serialize;
 pub mod structure;
 pub mod structures;
 pub mod universal;
 pub mod write;
 
 pub use nom::IResult::*;
-pub use nom::{Consumer, ConsumerState, IResult, Input, Move};
+pub use nom::{Consumer, ConsumerState, IResult, Input, Move, Needed};
 pub use parse::Parser;
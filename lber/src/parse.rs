@@ -1,6 +1,6 @@
 use common::TagClass;
 use common::TagStructure;
-use structure::{StructureTag, PL};
+use structure::{PLRef, StructureTag, StructureTagRef, PL};
 
 use nom;
 use nom::Consumer;
@@ -11,33 +11,36 @@ use nom::Input;
 use nom::Input::*;
 use nom::InputLength;
 use nom::Move;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
 
-named!(class_bits<(&[u8], usize), TagClass>,
-    map_opt!(
-        take_bits!(u8, 2),
-        TagClass::from_u8
-    )
-);
-
-named!(pc_bit<(&[u8], usize), TagStructure>,
-    map_opt!(
-        take_bits!(u8, 1),
-        TagStructure::from_u8
-    )
-);
-
-named!(tagnr_bits<(&[u8], usize), u64>,
-    take_bits!(u64, 5)
-);
-
-named!(pub parse_type_header<(TagClass, TagStructure, u64)>, bits!(
-    do_parse!(
-        class: class_bits >>
-        pc: pc_bit >>
-        tagnr: tagnr_bits >>
-        ((class, pc, tagnr))
-   )
-));
+/// Parse the identifier octets of a BER element: the class, the structure, and the tag
+/// number, which can be in the high tag number form, spanning several octets, for numbers
+/// above 30.
+pub fn parse_type_header(i: &[u8]) -> nom::IResult<&[u8], (TagClass, TagStructure, u64)> {
+    let first = match i.first() {
+        Some(&first) => first,
+        None => return nom::IResult::Incomplete(nom::Needed::Size(1)),
+    };
+    let class = TagClass::from_u8(first >> 6).expect("two-bit class");
+    let structure = TagStructure::from_u8((first >> 5) & 1).expect("one-bit structure");
+    if first & 0x1f != 0x1f {
+        return nom::IResult::Done(&i[1..], (class, structure, (first & 0x1f) as u64));
+    }
+    let mut id = 0u64;
+    for (n, &b) in i[1..].iter().enumerate() {
+        if id >> 57 != 0 {
+            // The next shift would overflow.
+            return nom::IResult::Error(nom::ErrorKind::Custom(1));
+        }
+        id = (id << 7) | (b & 0x7f) as u64;
+        if b & 0x80 == 0 {
+            return nom::IResult::Done(&i[n + 2..], (class, structure, id));
+        }
+    }
+    nom::IResult::Incomplete(nom::Needed::Size(1))
+}
 
 named!(pub parse_length<u64>,
     alt!(
@@ -74,42 +77,390 @@ pub fn parse_uint(i: &[u8]) -> nom::IResult<&[u8], u64> {
     nom::IResult::Done(i, i.iter().fold(0, |res, &byte| (res << 8) | byte as u64))
 }
 
+/// Maximum nesting of constructed elements in the indefinite length form.
+pub const MAX_INDEFINITE_DEPTH: usize = 32;
+
+/// Maximum nesting of constructed elements accepted by [`parse_tag()`](fn.parse_tag.html)
+/// and [`parse_tag_ref()`](fn.parse_tag_ref.html), regardless of any [`Limits`](struct.Limits.html).
+pub const MAX_DEPTH: usize = 256;
+
+/// Nesting depth limit of the default [`Limits`](struct.Limits.html).
+pub const DEFAULT_MAX_DEPTH: usize = 64;
+
+/// Identifier and length octets of a BER element.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Header {
+    pub class: TagClass,
+    pub structure: TagStructure,
+    pub id: u64,
+    /// Length of the identifier and length octets.
+    pub header_len: usize,
+    /// Length of the contents, or `None` for the indefinite form, where the contents
+    /// are terminated by the end-of-contents octets. A length which doesn't fit in
+    /// 64 bits is `u64::MAX`.
+    pub len: Option<u64>,
+}
+
+impl Header {
+    /// Total length of the element, saturating at `usize::MAX`, or `None` for the
+    /// indefinite form.
+    pub fn total_len(&self) -> Option<usize> {
+        self.len.map(|len| {
+            usize::try_from(len)
+                .ok()
+                .and_then(|len| len.checked_add(self.header_len))
+                .unwrap_or(usize::MAX)
+        })
+    }
+}
+
+/// Parse the identifier and length octets at the start of `i`.
+///
+/// If the header is incomplete, the returned `Incomplete` holds the number of additional
+/// bytes needed to complete it: exact for the length octets, and a lower bound of one for
+/// the identifier octet.
+pub fn parse_header(i: &[u8]) -> nom::IResult<&[u8], Header> {
+    if i.len() < 2 {
+        return nom::IResult::Incomplete(nom::Needed::Size(2 - i.len()));
+    }
+    let (len_octets, (class, structure, id)) = try_parse!(i, parse_type_header);
+    let (rest, len) = match len_octets.first() {
+        None => return nom::IResult::Incomplete(nom::Needed::Size(1)),
+        Some(&0x80) => (&len_octets[1..], None),
+        Some(&b) => {
+            let n = (b & 0x7f) as usize;
+            if b & 0x80 != 0 && len_octets.len() < 1 + n {
+                return nom::IResult::Incomplete(nom::Needed::Size(1 + n - len_octets.len()));
+            }
+            if b & 0x80 != 0 && n > 8 {
+                // Doesn't fit in 64 bits; saturate instead of wrapping around.
+                return nom::IResult::Done(
+                    &len_octets[1 + n..],
+                    Header {
+                        class,
+                        structure,
+                        id,
+                        header_len: i.len() - len_octets.len() + 1 + n,
+                        len: Some(u64::MAX),
+                    },
+                );
+            }
+            let (rest, len) = try_parse!(len_octets, parse_length);
+            (rest, Some(len))
+        }
+    };
+    nom::IResult::Done(
+        rest,
+        Header {
+            class,
+            structure,
+            id,
+            header_len: i.len() - rest.len(),
+            len,
+        },
+    )
+}
+
+// Why the length of an element can't be determined.
+enum Scan {
+    Needed(usize),
+    Invalid,
+}
+
+// Length of the complete element at the start of i, including the end-of-contents
+// octets of the indefinite form, which is scanned through.
+fn element_len(i: &[u8], depth: usize) -> Result<usize, Scan> {
+    let hdr = match parse_header(i) {
+        nom::IResult::Done(_, hdr) => hdr,
+        nom::IResult::Incomplete(nom::Needed::Size(n)) => return Err(Scan::Needed(n)),
+        nom::IResult::Incomplete(nom::Needed::Unknown) => return Err(Scan::Needed(1)),
+        nom::IResult::Error(_) => return Err(Scan::Invalid),
+    };
+    if let Some(total) = hdr.total_len() {
+        return if total > i.len() {
+            Err(Scan::Needed(total - i.len()))
+        } else {
+            Ok(total)
+        };
+    }
+    if hdr.structure != TagStructure::Constructed || depth >= MAX_INDEFINITE_DEPTH {
+        return Err(Scan::Invalid);
+    }
+    let mut pos = hdr.header_len;
+    loop {
+        match &i[pos..] {
+            [0, 0, ..] => return Ok(pos + 2),
+            [0] => return Err(Scan::Needed(1)),
+            rest => pos += element_len(rest, depth + 1)?,
+        }
+    }
+}
+
+/// Return the number of bytes which must be appended to `i` for the first element in it
+/// to be complete, or zero if it already is. If the header of the element is incomplete,
+/// the count covers only the rest of the header, and the call must be repeated once it
+/// arrives. The same goes for elements in the indefinite length form, for which the count
+/// covers the rest of the component being received.
+pub fn bytes_needed(i: &[u8]) -> usize {
+    match element_len(i, 0) {
+        Ok(_) | Err(Scan::Invalid) => 0,
+        Err(Scan::Needed(n)) => n,
+    }
+}
+
+/// Limits on the structure of the parsed data.
+///
+/// The limits are checked by [`check_limits()`](fn.check_limits.html) without recursion,
+/// so data which would exhaust the stack or memory of the recursive parsers can be
+/// rejected beforehand. The default limits only the depth, to
+/// [`DEFAULT_MAX_DEPTH`](constant.DEFAULT_MAX_DEPTH.html) levels.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Limits {
+    /// Deepest nesting of constructed elements.
+    pub max_depth: usize,
+    /// Largest number of elements, at all levels and including the outermost one.
+    pub max_elements: usize,
+    /// Largest encoded length of the element.
+    pub max_size: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_depth: DEFAULT_MAX_DEPTH,
+            max_elements: usize::MAX,
+            max_size: usize::MAX,
+        }
+    }
+}
+
+/// Exceeded parsing limit, with its value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LimitError {
+    Depth(usize),
+    Elements(usize),
+    Size(usize),
+}
+
+impl fmt::Display for LimitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LimitError::Depth(max) => write!(f, "BER nesting deeper than {} levels", max),
+            LimitError::Elements(max) => write!(f, "more than {} BER elements", max),
+            LimitError::Size(max) => write!(f, "BER element larger than {} bytes", max),
+        }
+    }
+}
+
+impl Error for LimitError {}
+
+/// Check the first element of `i` against `limits`.
+///
+/// The element may be incomplete, in which case the limits are checked for the part
+/// which is present, and the size limit against the length in the header, if known.
+/// Malformed encodings are left for the parser to reject.
+pub fn check_limits(i: &[u8], limits: &Limits) -> Result<(), LimitError> {
+    // Ends of the enclosing constructed elements, None for the indefinite form.
+    let mut ends: Vec<Option<usize>> = Vec::new();
+    let mut pos = 0;
+    let mut elements = 0;
+    loop {
+        while let Some(&Some(end)) = ends.last() {
+            if end > pos {
+                break;
+            }
+            ends.pop();
+        }
+        if pos > limits.max_size {
+            return Err(LimitError::Size(limits.max_size));
+        }
+        if pos >= i.len() || (pos > 0 && ends.is_empty()) {
+            return Ok(());
+        }
+        if ends.last() == Some(&None) && i[pos..].starts_with(&[0, 0]) {
+            ends.pop();
+            pos += 2;
+            continue;
+        }
+        let hdr = match parse_header(&i[pos..]) {
+            nom::IResult::Done(_, hdr) => hdr,
+            _ => return Ok(()),
+        };
+        elements += 1;
+        if elements > limits.max_elements {
+            return Err(LimitError::Elements(limits.max_elements));
+        }
+        let end = hdr.total_len().map(|len| pos.saturating_add(len));
+        if pos == 0 && end.is_some_and(|end| end > limits.max_size) {
+            return Err(LimitError::Size(limits.max_size));
+        }
+        match (hdr.structure, end) {
+            (TagStructure::Constructed, end) => {
+                ends.push(end);
+                if ends.len() > limits.max_depth {
+                    return Err(LimitError::Depth(limits.max_depth));
+                }
+                pos += hdr.header_len;
+            }
+            (TagStructure::Primitive, Some(end)) => pos = end,
+            (TagStructure::Primitive, None) => return Ok(()),
+        }
+    }
+}
+
+/// Parse raw BER data like [`parse_tag()`](fn.parse_tag.html), after checking it
+/// against `limits`.
+pub fn parse_tag_limited<'a>(
+    i: &'a [u8],
+    limits: &Limits,
+) -> Result<nom::IResult<&'a [u8], StructureTag>, LimitError> {
+    check_limits(i, limits)?;
+    Ok(parse_tag(i))
+}
+
 /// Parse raw BER data into a serializable structure.
+///
+/// If the data is incomplete, the returned `Incomplete` holds the number of additional
+/// bytes needed to parse the first element, as calculated by
+/// [`bytes_needed()`](fn.bytes_needed.html). Since the elements nested inside a
+/// complete element must themselves be complete, their truncation is an error.
+///
+/// Constructed elements in the indefinite length form are accepted, up to the nesting
+/// depth of [`MAX_INDEFINITE_DEPTH`](constant.MAX_INDEFINITE_DEPTH.html). Nesting deeper
+/// than [`MAX_DEPTH`](constant.MAX_DEPTH.html) in either form is an error.
 pub fn parse_tag(i: &[u8]) -> nom::IResult<&[u8], StructureTag> {
-    let (mut i, ((class, structure, id), len)) = try_parse!(
-        i,
-        do_parse!(hdr: parse_type_header >> len: parse_length >> ((hdr, len)))
-    );
+    match bytes_needed(i) {
+        0 => match parse_tag_inner(i, 0, 0) {
+            nom::IResult::Incomplete(_) => nom::IResult::Error(nom::ErrorKind::Eof),
+            res => res,
+        },
+        n => nom::IResult::Incomplete(nom::Needed::Size(n)),
+    }
+}
 
-    let pl: PL = match structure {
-        TagStructure::Primitive => {
-            let (j, content) = try_parse!(i, length_data!(value!(len)));
-            i = j;
+/// Parse raw BER data into a structure borrowing its primitive values from `i`.
+///
+/// This is the non-copying counterpart of [`parse_tag()`](fn.parse_tag.html), with the
+/// same handling of incomplete data.
+pub fn parse_tag_ref(i: &[u8]) -> nom::IResult<&[u8], StructureTagRef<'_>> {
+    match bytes_needed(i) {
+        0 => match parse_tag_ref_inner(i, 0, 0) {
+            nom::IResult::Incomplete(_) => nom::IResult::Error(nom::ErrorKind::Eof),
+            res => res,
+        },
+        n => nom::IResult::Incomplete(nom::Needed::Size(n)),
+    }
+}
+
+// Contents of the element with the header hdr at the start of i, and the rest of
+// the input. The contents of the indefinite form exclude the end-of-contents octets.
+pub(crate) fn contents<'a>(
+    i: &'a [u8],
+    hdr: &Header,
+    depth: usize,
+) -> nom::IResult<&'a [u8], &'a [u8]> {
+    let body = &i[hdr.header_len..];
+    match hdr.len {
+        Some(len) => match usize::try_from(len) {
+            Ok(len) if len <= body.len() => nom::IResult::Done(&body[len..], &body[..len]),
+            _ => nom::IResult::Incomplete(nom::Needed::Unknown),
+        },
+        None => match element_len(i, depth) {
+            Ok(total) => nom::IResult::Done(&i[total..], &i[hdr.header_len..total - 2]),
+            Err(Scan::Needed(_)) => nom::IResult::Incomplete(nom::Needed::Unknown),
+            Err(Scan::Invalid) => nom::IResult::Error(nom::ErrorKind::Custom(2)),
+        },
+    }
+}
 
-            PL::P(content.to_vec())
+// The depth counts the enclosing elements in the indefinite length form, which are
+// scanned for their end, and the level all enclosing constructed elements.
+fn parse_tag_ref_inner(
+    i: &[u8],
+    depth: usize,
+    level: usize,
+) -> nom::IResult<&[u8], StructureTagRef<'_>> {
+    let (_, hdr) = try_parse!(i, parse_header);
+    let (rest, mut content) = try_parse!(i, call!(contents, &hdr, depth));
+    let depth = depth + hdr.len.is_none() as usize;
+    let payload = match hdr.structure {
+        TagStructure::Primitive => PLRef::P(content),
+        TagStructure::Constructed if level >= MAX_DEPTH => {
+            return nom::IResult::Error(nom::ErrorKind::Custom(3));
         }
         TagStructure::Constructed => {
-            let (j, mut content) = try_parse!(i, length_bytes!(value!(len)));
-            i = j;
+            let mut tv = Vec::new();
+            while !content.is_empty() {
+                let (tail, tag) = try_parse!(content, call!(parse_tag_ref_inner, depth, level + 1));
+                content = tail;
+                tv.push(tag);
+            }
+            PLRef::C(tv)
+        }
+    };
+    nom::IResult::Done(
+        rest,
+        StructureTagRef {
+            class: hdr.class,
+            id: hdr.id,
+            payload,
+        },
+    )
+}
 
+/// Split the first element of `i`, which must be constructed, into the raw encodings
+/// of its immediate components, without parsing them. Each component can then be
+/// parsed separately with [`parse_tag()`](fn.parse_tag.html), so that a large message
+/// doesn't have to be turned into a single structure tree at once.
+pub fn parse_components(i: &[u8]) -> nom::IResult<&[u8], (Header, Vec<&[u8]>)> {
+    let needed = bytes_needed(i);
+    if needed > 0 {
+        return nom::IResult::Incomplete(nom::Needed::Size(needed));
+    }
+    let (_, hdr) = try_parse!(i, parse_header);
+    if hdr.structure != TagStructure::Constructed {
+        return nom::IResult::Error(nom::ErrorKind::Custom(0));
+    }
+    let (rest, mut content) = try_parse!(i, call!(contents, &hdr, 0));
+    let mut comps = Vec::new();
+    while !content.is_empty() {
+        let len = match element_len(content, 1) {
+            Ok(len) => len,
+            Err(_) => return nom::IResult::Error(nom::ErrorKind::Eof),
+        };
+        let (comp, tail) = content.split_at(len);
+        comps.push(comp);
+        content = tail;
+    }
+    nom::IResult::Done(rest, (hdr, comps))
+}
+
+// Depth and level as in parse_tag_ref_inner().
+fn parse_tag_inner(i: &[u8], depth: usize, level: usize) -> nom::IResult<&[u8], StructureTag> {
+    let (_, hdr) = try_parse!(i, parse_header);
+    let (rest, mut content) = try_parse!(i, call!(contents, &hdr, depth));
+    let depth = depth + hdr.len.is_none() as usize;
+    let payload = match hdr.structure {
+        TagStructure::Primitive => PL::P(content.to_vec()),
+        TagStructure::Constructed if level >= MAX_DEPTH => {
+            return nom::IResult::Error(nom::ErrorKind::Custom(3));
+        }
+        TagStructure::Constructed => {
             let mut tv: Vec<StructureTag> = Vec::new();
             while content.input_len() > 0 {
-                let pres = try_parse!(content, call!(parse_tag));
-                content = pres.0;
-                let res: StructureTag = pres.1;
-                tv.push(res);
+                let (tail, tag) = try_parse!(content, call!(parse_tag_inner, depth, level + 1));
+                content = tail;
+                tv.push(tag);
             }
-
             PL::C(tv)
         }
     };
-
     nom::IResult::Done(
-        i,
+        rest,
         StructureTag {
-            class: class,
-            id: id,
-            payload: pl,
+            class: hdr.class,
+            id: hdr.id,
+            payload,
         },
     )
 }
@@ -118,6 +469,12 @@ pub struct Parser {
     state: ConsumerState<StructureTag, (), Move>,
 }
 
+impl Default for Parser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Parser {
     pub fn new() -> Parser {
         Parser {
@@ -126,7 +483,7 @@ impl Parser {
     }
 }
 
-impl<'a> Consumer<&'a [u8], StructureTag, (), Move> for Parser {
+impl Consumer<&[u8], StructureTag, (), Move> for Parser {
     fn handle(&mut self, input: Input<&[u8]>) -> &ConsumerState<StructureTag, (), Move> {
         use nom::Offset;
         match input {
@@ -151,8 +508,10 @@ impl<'a> Consumer<&'a [u8], StructureTag, (), Move> for Parser {
 #[cfg(test)]
 mod test {
     use super::*;
-    use common::{TagClass, TagStructure};
-    use nom::IResult;
+    use common::TagClass;
+    use nom::{IResult, Needed};
+    use proptest::collection;
+    use proptest::prelude::*;
     use structure::{StructureTag, PL};
 
     #[test]
@@ -253,4 +612,257 @@ mod test {
         let tag = parse_tag(&bytes[..]);
         assert_eq!(tag, IResult::Done(&rest_tag[..], result_tag));
     }
+
+    #[test]
+    fn test_incomplete() {
+        let bytes: Vec<u8> = vec![0x30, 0x82, 0x01, 0x00, 0x04, 0x02, 0x61, 0x62];
+        assert_eq!(parse_tag(&bytes[..0]), IResult::Incomplete(Needed::Size(2)));
+        assert_eq!(parse_tag(&bytes[..2]), IResult::Incomplete(Needed::Size(2)));
+        assert_eq!(
+            parse_tag(&bytes[..]),
+            IResult::Incomplete(Needed::Size(252))
+        );
+        assert_eq!(bytes_needed(&bytes[..6]), 254);
+        // Component overruns its container.
+        let bytes: Vec<u8> = vec![0x30, 0x03, 0x04, 0x02, 0x61];
+        assert!(parse_tag(&bytes[..]).is_err());
+    }
+
+    #[test]
+    fn test_header() {
+        let bytes: Vec<u8> = vec![0x30, 0x82, 0x01, 0x00];
+        let hdr = parse_header(&bytes).unwrap().1;
+        assert_eq!(
+            (hdr.header_len, hdr.len, hdr.total_len()),
+            (4, Some(256), Some(260))
+        );
+        assert_eq!(
+            parse_header(&bytes[..3]),
+            IResult::Incomplete(Needed::Size(1))
+        );
+        // Nine length octets don't fit in 64 bits.
+        let bytes: Vec<u8> = vec![0x30, 0x89, 1, 0, 0, 0, 0, 0, 0, 0, 0];
+        let hdr = parse_header(&bytes).unwrap().1;
+        assert_eq!((hdr.header_len, hdr.len), (11, Some(u64::MAX)));
+        assert_eq!(hdr.total_len(), Some(usize::MAX));
+    }
+
+    #[test]
+    fn test_borrowed() {
+        let bytes: Vec<u8> = vec![0x30, 0x07, 0x02, 0x01, 0x05, 0x04, 0x02, 0x61, 0x62];
+        let (rest, tag) = parse_tag_ref(&bytes[..]).unwrap();
+        assert!(rest.is_empty());
+        let owned = tag.to_owned_tag();
+        let elems = tag.expect_constructed().unwrap();
+        assert_eq!(elems[1].clone().expect_primitive(), Some(&bytes[7..9]));
+        assert_eq!(parse_tag(&bytes[..]), IResult::Done(&[][..], owned));
+        assert_eq!(
+            parse_tag_ref(&bytes[..4]),
+            IResult::Incomplete(Needed::Size(5))
+        );
+    }
+
+    #[test]
+    fn test_high_tag_numbers() {
+        use bytes::BytesMut;
+        use write;
+
+        // Tag numbers around the form and octet boundaries; prop_high_tag_numbers covers the rest.
+        for id in vec![0, 30, 31, 127, 128, 16383, 16384, u64::MAX >> 1, u64::MAX] {
+            let tag = StructureTag {
+                class: TagClass::Private,
+                id,
+                payload: PL::C(vec![StructureTag {
+                    class: TagClass::Context,
+                    id,
+                    payload: PL::P(vec![1, 2, 3]),
+                }]),
+            };
+            let mut buf = BytesMut::new();
+            write::encode_into(&mut buf, tag.clone()).unwrap();
+            assert_eq!(
+                parse_tag(&buf[..]),
+                IResult::Done(&[][..], tag),
+                "tag {}",
+                id
+            );
+            assert_eq!(bytes_needed(&buf[..buf.len() - 1]), 1);
+        }
+        assert_eq!(
+            parse_type_header(&[0x5f, 0x81][..]),
+            IResult::Incomplete(Needed::Size(1))
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn prop_high_tag_numbers(
+            class in 0usize..4,
+            shift in 0u32..64,
+            id in any::<u64>(),
+            payload in collection::vec(any::<u8>(), 0..200),
+        ) {
+            use bytes::BytesMut;
+            use write;
+
+            // Shift the number right by a random amount, so that every encoded length is
+            // about equally likely.
+            let id = id >> shift;
+            let class = [
+                TagClass::Universal,
+                TagClass::Application,
+                TagClass::Context,
+                TagClass::Private,
+            ][class];
+            let tag = StructureTag {
+                class,
+                id,
+                payload: PL::C(vec![StructureTag {
+                    class,
+                    id,
+                    payload: PL::P(payload),
+                }]),
+            };
+            let mut buf = BytesMut::new();
+            write::encode_into(&mut buf, tag.clone()).unwrap();
+            prop_assert_eq!(parse_tag(&buf[..]), IResult::Done(&[][..], tag));
+            prop_assert_eq!(bytes_needed(&buf[..buf.len() - 1]), 1);
+        }
+    }
+
+    #[test]
+    fn test_components() {
+        let bytes: Vec<u8> = vec![0x30, 0x07, 0x02, 0x01, 0x05, 0x04, 0x02, 0x61, 0x62, 0xff];
+        let (rest, (hdr, comps)) = parse_components(&bytes[..]).unwrap();
+        assert_eq!(rest, &[0xff]);
+        assert_eq!((hdr.id, hdr.header_len, hdr.len), (16, 2, Some(7)));
+        assert_eq!(comps, vec![&bytes[2..5], &bytes[5..9]]);
+        assert_eq!(
+            parse_tag(comps[1]),
+            IResult::Done(
+                &[][..],
+                StructureTag {
+                    class: TagClass::Universal,
+                    id: 4,
+                    payload: PL::P(vec![0x61, 0x62]),
+                }
+            )
+        );
+    }
+
+    #[test]
+    fn test_indefinite() {
+        // SEQUENCE { SEQUENCE { INTEGER 5 } OCTET STRING "ab" }, both sequences indefinite.
+        let bytes: Vec<u8> = vec![
+            0x30, 0x80, 0x30, 0x80, 0x02, 0x01, 0x05, 0x00, 0x00, 0x04, 0x02, 0x61, 0x62, 0x00,
+            0x00, 0xff,
+        ];
+        let (rest, tag) = parse_tag(&bytes[..]).unwrap();
+        assert_eq!(rest, &[0xff]);
+        let (_, borrowed) = parse_tag_ref(&bytes[..]).unwrap();
+        assert_eq!(borrowed.to_owned_tag(), tag);
+        let elems = tag.expect_constructed().unwrap();
+        assert_eq!(elems.len(), 2);
+        assert_eq!(elems[1].clone().expect_primitive(), Some(vec![0x61, 0x62]));
+        let (rest, (hdr, comps)) = parse_components(&bytes[..]).unwrap();
+        assert_eq!((rest, hdr.len), (&[0xff][..], None));
+        assert_eq!(comps, vec![&bytes[2..9], &bytes[9..13]]);
+        // Missing end-of-contents.
+        assert_eq!(bytes_needed(&bytes[..13]), 2);
+        assert_eq!(bytes_needed(&bytes[..14]), 1);
+        assert_eq!(parse_tag(&bytes[..7]), IResult::Incomplete(Needed::Size(2)));
+        // Primitive elements can't use the indefinite form.
+        assert!(parse_tag(&[0x04, 0x80, 0x61, 0x00, 0x00][..]).is_err());
+        // Nesting is limited.
+        let mut deep = [0x30, 0x80].repeat(MAX_INDEFINITE_DEPTH);
+        deep.extend(vec![0x00; 2 * MAX_INDEFINITE_DEPTH]);
+        assert!(parse_tag(&deep[..]).is_done());
+        let mut deeper = vec![0x30, 0x80];
+        deeper.extend(deep);
+        deeper.extend([0x00, 0x00]);
+        assert!(parse_tag(&deeper[..]).is_err());
+    }
+
+    #[test]
+    fn test_limits() {
+        let limited = |depth, elements, size| Limits {
+            max_depth: depth,
+            max_elements: elements,
+            max_size: size,
+        };
+        // SEQUENCE { SEQUENCE { SEQUENCE {} } }, 6 bytes.
+        let nested = [0x30, 0x04, 0x30, 0x02, 0x30, 0x00];
+        assert_eq!(check_limits(&nested, &limited(3, 3, 6)), Ok(()));
+        assert_eq!(
+            check_limits(&nested, &limited(2, 3, 6)),
+            Err(LimitError::Depth(2))
+        );
+        assert_eq!(
+            check_limits(&nested, &limited(3, 2, 6)),
+            Err(LimitError::Elements(2))
+        );
+        assert_eq!(
+            check_limits(&nested, &limited(3, 3, 5)),
+            Err(LimitError::Size(5))
+        );
+        // The size is known from the header alone.
+        assert_eq!(
+            check_limits(&nested[..2], &limited(3, 3, 5)),
+            Err(LimitError::Size(5))
+        );
+        // The same, with the inner tags in the high tag number form: [PRIVATE 200].
+        let nested = [0x30, 0x08, 0xff, 0x81, 0x48, 0x03, 0xff, 0x81, 0x48, 0x00];
+        assert!(check_limits(&nested, &limited(3, 3, 10)).is_ok());
+        assert!(check_limits(&nested, &limited(2, 3, 10)).is_err());
+        // The same, with the outer tags in the indefinite length form.
+        let nested = [0x30, 0x80, 0x30, 0x80, 0x30, 0x00, 0x00, 0x00, 0x00, 0x00];
+        assert!(check_limits(&nested, &limited(3, 3, 10)).is_ok());
+        assert!(check_limits(&nested, &limited(2, 3, 10)).is_err());
+        assert_eq!(
+            check_limits(&nested, &limited(3, 3, 9)),
+            Err(LimitError::Size(9))
+        );
+        // Elements following the first one aren't counted.
+        let seq = [0x30, 0x03, 0x02, 0x01, 0x05, 0x02, 0x01, 0x06];
+        assert!(check_limits(&seq, &limited(1, 2, 5)).is_ok());
+        assert!(parse_tag_limited(&seq, &limited(1, 1, 5)).is_err());
+        assert!(parse_tag_limited(&seq, &Limits::default())
+            .unwrap()
+            .is_done());
+    }
+
+    // NULL inside constructed elements in the definite length form, nested `levels` deep.
+    fn nested_definite(levels: usize) -> Vec<u8> {
+        let mut headers = Vec::with_capacity(levels);
+        let mut len = 2;
+        for _ in 0..levels {
+            let header = if len < 0x80 {
+                vec![0x30, len as u8]
+            } else {
+                vec![0x30, 0x83, (len >> 16) as u8, (len >> 8) as u8, len as u8]
+            };
+            len += header.len();
+            headers.push(header);
+        }
+        let mut bytes: Vec<u8> = headers.into_iter().rev().flatten().collect();
+        bytes.extend([0x05, 0x00]);
+        bytes
+    }
+
+    #[test]
+    fn test_depth() {
+        let deep = nested_definite(DEFAULT_MAX_DEPTH + 1);
+        assert_eq!(
+            check_limits(&deep, &Limits::default()),
+            Err(LimitError::Depth(DEFAULT_MAX_DEPTH))
+        );
+        assert!(check_limits(&nested_definite(DEFAULT_MAX_DEPTH), &Limits::default()).is_ok());
+        assert!(parse_tag(&nested_definite(MAX_DEPTH)).is_done());
+        assert!(parse_tag_ref(&nested_definite(MAX_DEPTH)).is_done());
+        let deeper = nested_definite(MAX_DEPTH + 1);
+        assert!(parse_tag(&deeper).is_err());
+        assert!(parse_tag_ref(&deeper).is_err());
+        // Far too deep to parse recursively without the guard.
+        assert!(parse_tag(&nested_definite(100_000)).is_err());
+    }
 }
@@ -0,0 +1,246 @@
+//! Decoded elements which keep their original encoding.
+//!
+//! BER permits several encodings of the same value: the length can be in the long form
+//! when the short one would do, or in the indefinite form, and strings can be split into
+//! constructed chunks. Parsing into a [`StructureTag`](../structure/struct.StructureTag.html)
+//! loses these choices, so re-encoding the structure may not reproduce the input. This
+//! matters for values which must be echoed verbatim, such as signed data or opaque cookies
+//! embedded in larger structures.
+//!
+//! A [`RawTag`](struct.RawTag.html) remembers the exact bytes of every element it was parsed
+//! from. The guarantee is that [`encode_into()`](struct.RawTag.html#method.encode_into)
+//! reproduces the original bytes of every element which wasn't modified through
+//! [`payload_mut()`](struct.RawTag.html#method.payload_mut). A modified element is encoded
+//! in the definite length form, while its unmodified components are still written as they
+//! were received.
+use common::{TagClass, TagStructure};
+use parse::{bytes_needed, contents, parse_header};
+use structure::{StructureTag, PL};
+use write;
+
+use bytes::{BufMut, Bytes, BytesMut};
+use nom;
+
+/// Element payload, with components which keep their encoding.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RawPL {
+    /// Primitive value.
+    P(Bytes),
+    /// Constructed value.
+    C(Vec<RawTag>),
+}
+
+/// Decoded element with its original encoding.
+///
+/// Equality compares the decoded contents, not the encodings.
+#[derive(Clone, Debug)]
+pub struct RawTag {
+    class: TagClass,
+    id: u64,
+    payload: RawPL,
+    raw: Option<Bytes>,
+}
+
+impl PartialEq for RawTag {
+    fn eq(&self, other: &RawTag) -> bool {
+        self.class == other.class && self.id == other.id && self.payload == other.payload
+    }
+}
+
+impl Eq for RawTag {}
+
+impl RawTag {
+    /// Parse the first element of `data`. The returned element and its components share
+    /// the buffer of `data` instead of copying from it.
+    ///
+    /// Incomplete and invalid data are handled as in [`parse_tag()`](../parse/fn.parse_tag.html).
+    pub fn parse(data: &Bytes) -> nom::IResult<&[u8], RawTag> {
+        match bytes_needed(data) {
+            0 => match parse_raw(&data[..], data, 0) {
+                nom::IResult::Incomplete(_) => nom::IResult::Error(nom::ErrorKind::Eof),
+                res => res,
+            },
+            n => nom::IResult::Incomplete(nom::Needed::Size(n)),
+        }
+    }
+
+    pub fn class(&self) -> TagClass {
+        self.class
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn payload(&self) -> &RawPL {
+        &self.payload
+    }
+
+    /// Mutable access to the payload. The element forgets its original encoding, and
+    /// will be re-encoded from the payload.
+    pub fn payload_mut(&mut self) -> &mut RawPL {
+        self.raw = None;
+        &mut self.payload
+    }
+
+    /// Original encoding of the element, if it was parsed and hasn't been modified since.
+    pub fn raw(&self) -> Option<&Bytes> {
+        self.raw.as_ref()
+    }
+
+    /// Convert into a structure, losing the original encodings.
+    pub fn into_structure(self) -> StructureTag {
+        StructureTag {
+            class: self.class,
+            id: self.id,
+            payload: match self.payload {
+                RawPL::P(v) => PL::P(v.to_vec()),
+                RawPL::C(tags) => PL::C(tags.into_iter().map(RawTag::into_structure).collect()),
+            },
+        }
+    }
+
+    /// Length of the encoding of the element.
+    pub fn encoded_len(&self) -> usize {
+        match self.raw {
+            Some(ref raw) => raw.len(),
+            None => {
+                let len = self.content_len();
+                write::type_len(self.id) + write::length_len(len) + len
+            }
+        }
+    }
+
+    fn content_len(&self) -> usize {
+        match self.payload {
+            RawPL::P(ref v) => v.len(),
+            RawPL::C(ref tags) => tags.iter().map(RawTag::encoded_len).sum(),
+        }
+    }
+
+    /// Encode the element into the buffer, reproducing the original encodings of
+    /// unmodified elements.
+    pub fn encode_into(&self, buf: &mut BytesMut) {
+        buf.reserve(self.encoded_len());
+        self.write(buf);
+    }
+
+    fn write(&self, buf: &mut BytesMut) {
+        if let Some(ref raw) = self.raw {
+            buf.put_slice(raw);
+            return;
+        }
+        let len = self.content_len();
+        match self.payload {
+            RawPL::P(ref v) => {
+                write::write_type(buf, self.class, TagStructure::Primitive, self.id);
+                write::write_length(buf, len);
+                buf.put_slice(v);
+            }
+            RawPL::C(ref tags) => {
+                write::write_type(buf, self.class, TagStructure::Constructed, self.id);
+                write::write_length(buf, len);
+                for tag in tags {
+                    tag.write(buf);
+                }
+            }
+        }
+    }
+}
+
+impl From<StructureTag> for RawTag {
+    fn from(tag: StructureTag) -> RawTag {
+        RawTag {
+            class: tag.class,
+            id: tag.id,
+            payload: match tag.payload {
+                PL::P(v) => RawPL::P(Bytes::from(v)),
+                PL::C(tags) => RawPL::C(tags.into_iter().map(RawTag::from).collect()),
+            },
+            raw: None,
+        }
+    }
+}
+
+// Parse the element at the start of i, which is a slice of data.
+fn parse_raw<'a>(i: &'a [u8], data: &Bytes, depth: usize) -> nom::IResult<&'a [u8], RawTag> {
+    let (_, hdr) = try_parse!(i, parse_header);
+    let (rest, mut content) = try_parse!(i, call!(contents, &hdr, depth));
+    let depth = depth + hdr.len.is_none() as usize;
+    let payload = match hdr.structure {
+        TagStructure::Primitive => RawPL::P(data.slice_ref(content)),
+        TagStructure::Constructed => {
+            let mut tv = Vec::new();
+            while !content.is_empty() {
+                let (tail, tag) = try_parse!(content, call!(parse_raw, data, depth));
+                content = tail;
+                tv.push(tag);
+            }
+            RawPL::C(tv)
+        }
+    };
+    nom::IResult::Done(
+        rest,
+        RawTag {
+            class: hdr.class,
+            id: hdr.id,
+            payload,
+            raw: Some(data.slice_ref(&i[..i.len() - rest.len()])),
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parse::parse_tag;
+
+    fn round_trip(input: &[u8]) -> RawTag {
+        let data = Bytes::copy_from_slice(input);
+        let (rest, tag) = match RawTag::parse(&data) {
+            nom::IResult::Done(rest, tag) => (rest, tag),
+            _ => panic!("parse failed"),
+        };
+        assert!(rest.is_empty());
+        let mut buf = BytesMut::new();
+        tag.encode_into(&mut buf);
+        assert_eq!(&buf[..], input);
+        assert_eq!(tag.encoded_len(), input.len());
+        match parse_tag(input) {
+            nom::IResult::Done(_, parsed) => assert_eq!(tag.clone().into_structure(), parsed),
+            _ => panic!("parse_tag failed"),
+        }
+        tag
+    }
+
+    #[test]
+    fn lossless() {
+        // Minimal encoding.
+        round_trip(&[0x30, 0x06, 0x02, 0x01, 0x05, 0x04, 0x01, 0x61]);
+        // Non-minimal lengths.
+        round_trip(&[0x30, 0x81, 0x07, 0x02, 0x82, 0x00, 0x01, 0x05, 0x04, 0x00]);
+        // Indefinite length with a constructed string.
+        round_trip(&[
+            0x30, 0x80, 0x24, 0x80, 0x04, 0x01, 0x61, 0x04, 0x01, 0x62, 0x00, 0x00, 0x00, 0x00,
+        ]);
+    }
+
+    #[test]
+    fn modified() {
+        let input = [
+            0x30, 0x80, 0x04, 0x81, 0x01, 0x61, 0x30, 0x03, 0x02, 0x01, 0x05, 0x00, 0x00,
+        ];
+        let mut tag = round_trip(&input);
+        if let RawPL::C(ref mut comps) = *tag.payload_mut() {
+            *comps[1].payload_mut() = RawPL::C(vec![]);
+        }
+        assert!(tag.raw().is_none());
+        let mut buf = BytesMut::new();
+        tag.encode_into(&mut buf);
+        // The untouched string keeps its long-form length.
+        assert_eq!(&buf[..], &[0x30, 0x06, 0x04, 0x81, 0x01, 0x61, 0x30, 0x00]);
+        let fresh = RawTag::from(tag.clone().into_structure());
+        assert_eq!(fresh, tag);
+        assert!(fresh.raw().is_none());
+    }
+}
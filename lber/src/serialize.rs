@@ -0,0 +1,128 @@
+// Serde support for StructureTag and PL. The shape is the one derived
+// implementations would produce, and is documented on StructureTag; it's written
+// out by hand so that the crate doesn't depend on serde_derive.
+
+use std::fmt;
+
+use common::TagClass;
+use structure::{StructureTag, PL};
+
+use serde::de::{self, Deserialize, Deserializer, EnumAccess, MapAccess, VariantAccess, Visitor};
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+
+const CLASSES: &[&str] = &["Universal", "Application", "Context", "Private"];
+const FIELDS: &[&str] = &["class", "id", "payload"];
+const PAYLOADS: &[&str] = &["P", "C"];
+
+impl Serialize for TagClass {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let idx = *self as u32;
+        serializer.serialize_unit_variant("TagClass", idx, CLASSES[idx as usize])
+    }
+}
+
+impl<'de> Deserialize<'de> for TagClass {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ClassVisitor;
+
+        impl<'de> Visitor<'de> for ClassVisitor {
+            type Value = TagClass;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a tag class name")
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<TagClass, E> {
+                match CLASSES.iter().position(|&c| c == v) {
+                    Some(idx) => Ok(TagClass::from_u8(idx as u8).expect("class")),
+                    None => Err(E::unknown_variant(v, CLASSES)),
+                }
+            }
+
+            fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<TagClass, A::Error> {
+                let (class, variant): (String, _) = data.variant()?;
+                variant.unit_variant()?;
+                self.visit_str(&class)
+            }
+        }
+
+        deserializer.deserialize_enum("TagClass", CLASSES, ClassVisitor)
+    }
+}
+
+impl Serialize for StructureTag {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut st = serializer.serialize_struct("StructureTag", 3)?;
+        st.serialize_field("class", &self.class)?;
+        st.serialize_field("id", &self.id)?;
+        st.serialize_field("payload", &self.payload)?;
+        st.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for StructureTag {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct TagVisitor;
+
+        impl<'de> Visitor<'de> for TagVisitor {
+            type Value = StructureTag;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a BER structure tag")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<StructureTag, A::Error> {
+                let (mut class, mut id, mut payload) = (None, None, None);
+                while let Some(key) = map.next_key::<String>()? {
+                    match key.as_str() {
+                        "class" => class = Some(map.next_value()?),
+                        "id" => id = Some(map.next_value()?),
+                        "payload" => payload = Some(map.next_value()?),
+                        other => return Err(de::Error::unknown_field(other, FIELDS)),
+                    }
+                }
+                Ok(StructureTag {
+                    class: class.ok_or_else(|| de::Error::missing_field("class"))?,
+                    id: id.ok_or_else(|| de::Error::missing_field("id"))?,
+                    payload: payload.ok_or_else(|| de::Error::missing_field("payload"))?,
+                })
+            }
+        }
+
+        deserializer.deserialize_struct("StructureTag", FIELDS, TagVisitor)
+    }
+}
+
+impl Serialize for PL {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match *self {
+            PL::P(ref val) => serializer.serialize_newtype_variant("PL", 0, "P", val),
+            PL::C(ref inner) => serializer.serialize_newtype_variant("PL", 1, "C", inner),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for PL {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct PayloadVisitor;
+
+        impl<'de> Visitor<'de> for PayloadVisitor {
+            type Value = PL;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a primitive or constructed payload")
+            }
+
+            fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<PL, A::Error> {
+                let (kind, variant): (String, _) = data.variant()?;
+                match kind.as_str() {
+                    "P" => variant.newtype_variant().map(PL::P),
+                    "C" => variant.newtype_variant().map(PL::C),
+                    other => Err(de::Error::unknown_variant(other, PAYLOADS)),
+                }
+            }
+        }
+
+        deserializer.deserialize_enum("PL", PAYLOADS, PayloadVisitor)
+    }
+}
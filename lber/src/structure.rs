@@ -1,6 +1,21 @@
+use std::fmt;
+
 use common::TagClass;
+use dump;
 
 /// ASN.1 structure prepared for serialization.
+///
+/// With the __serde__ feature, `StructureTag` and [`PL`](enum.PL.html) implement
+/// `Serialize` and `Deserialize`. The shape is stable; in JSON, it's:
+///
+/// ```text
+/// {"class": "Universal", "id": 16, "payload": {"C": [
+///     {"class": "Universal", "id": 4, "payload": {"P": [97, 98]}}
+/// ]}}
+/// ```
+///
+/// The class is one of `"Universal"`, `"Application"`, `"Context"` or `"Private"`,
+/// and a primitive value is an array of bytes.
 #[derive(Clone, PartialEq, Debug, Eq)]
 pub struct StructureTag {
     pub class: TagClass,
@@ -47,12 +62,120 @@ impl StructureTag {
             PL::C(_) => None,
         }
     }
+
+    /// Return a wrapper whose `Display` implementation renders the structure as an
+    /// annotated, indented tree, for diagnostic output.
+    ///
+    /// Every element is shown on its own line with its class and tag number, followed by
+    /// the type name for universal tags, and the length of its contents. Primitive values
+    /// are shown in hex and ASCII, on additional lines with offsets if longer than sixteen
+    /// bytes.
+    ///
+    /// ```text
+    /// [UNIVERSAL 16] SEQUENCE (8) {
+    ///   [UNIVERSAL 2] INTEGER (1): 01  |.|
+    ///   [APPLICATION 2] (0)
+    ///   [CONTEXT 0] (1): 61  |a|
+    /// }
+    /// ```
+    pub fn pretty(&self) -> Pretty<'_> {
+        Pretty(self)
+    }
+}
+
+/// ASN.1 structure borrowing its primitive values from the parsed buffer.
+///
+/// Obtained with [`parse_tag_ref()`](../parse/fn.parse_tag_ref.html), which doesn't copy
+/// any values. Elements which must outlive the buffer can be converted to an owned
+/// [`StructureTag`](struct.StructureTag.html) with [`to_owned_tag()`](#method.to_owned_tag)
+/// or `From`.
+#[derive(Clone, PartialEq, Debug, Eq)]
+pub struct StructureTagRef<'a> {
+    pub class: TagClass,
+    pub id: u64,
+    pub payload: PLRef<'a>,
+}
+
+/// Borrowed tagged value payload.
+#[derive(Clone, PartialEq, Debug, Eq)]
+pub enum PLRef<'a> {
+    /// Primitive value.
+    P(&'a [u8]),
+    /// Constructed value.
+    C(Vec<StructureTagRef<'a>>),
+}
+
+impl<'a> StructureTagRef<'a> {
+    pub fn match_class(self, class: TagClass) -> Option<Self> {
+        if self.class == class {
+            Some(self)
+        } else {
+            None
+        }
+    }
+
+    pub fn match_id(self, id: u64) -> Option<Self> {
+        if self.id == id {
+            Some(self)
+        } else {
+            None
+        }
+    }
+
+    pub fn expect_constructed(self) -> Option<Vec<StructureTagRef<'a>>> {
+        match self.payload {
+            PLRef::P(_) => None,
+            PLRef::C(i) => Some(i),
+        }
+    }
+
+    pub fn expect_primitive(self) -> Option<&'a [u8]> {
+        match self.payload {
+            PLRef::P(i) => Some(i),
+            PLRef::C(_) => None,
+        }
+    }
+
+    /// Copy the structure into an owned `StructureTag`.
+    pub fn to_owned_tag(&self) -> StructureTag {
+        StructureTag {
+            class: self.class,
+            id: self.id,
+            payload: match self.payload {
+                PLRef::P(val) => PL::P(val.to_vec()),
+                PLRef::C(ref inner) => PL::C(inner.iter().map(|t| t.to_owned_tag()).collect()),
+            },
+        }
+    }
+}
+
+impl<'a> From<StructureTagRef<'a>> for StructureTag {
+    fn from(tag: StructureTagRef<'a>) -> StructureTag {
+        StructureTag {
+            class: tag.class,
+            id: tag.id,
+            payload: match tag.payload {
+                PLRef::P(val) => PL::P(val.to_vec()),
+                PLRef::C(inner) => PL::C(inner.into_iter().map(StructureTag::from).collect()),
+            },
+        }
+    }
+}
+
+/// Indented rendering of a `StructureTag`, obtained by calling
+/// [`pretty()`](struct.StructureTag.html#method.pretty).
+pub struct Pretty<'a>(&'a StructureTag);
+
+impl<'a> fmt::Display for Pretty<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&dump::render(self.0))
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use common::{TagClass, TagStructure};
+    use common::TagClass;
 
     #[test]
     fn expect_exact() {
@@ -109,4 +232,55 @@ mod tests {
         assert!(a.is_some());
         assert!(b.is_some());
     }
+
+    #[test]
+    fn borrowed_to_owned() {
+        let bytes = [0x61, 0x62];
+        let tag = StructureTagRef {
+            class: TagClass::Application,
+            id: 3,
+            payload: PLRef::C(vec![StructureTagRef {
+                class: TagClass::Universal,
+                id: 4,
+                payload: PLRef::P(&bytes[..]),
+            }]),
+        };
+        let owned = StructureTag {
+            class: TagClass::Application,
+            id: 3,
+            payload: PL::C(vec![StructureTag {
+                class: TagClass::Universal,
+                id: 4,
+                payload: PL::P(vec![0x61, 0x62]),
+            }]),
+        };
+        assert_eq!(tag.to_owned_tag(), owned);
+        let inner = tag.expect_constructed().unwrap().pop().unwrap();
+        assert_eq!(inner.expect_primitive(), Some(&bytes[..]));
+    }
+
+    #[test]
+    fn pretty_print() {
+        let tag = StructureTag {
+            class: TagClass::Application,
+            id: 3,
+            payload: PL::C(vec![
+                StructureTag {
+                    class: TagClass::Universal,
+                    id: 4,
+                    payload: PL::P(vec![0x61, 0x62]),
+                },
+                StructureTag {
+                    class: TagClass::Context,
+                    id: 0,
+                    payload: PL::C(vec![]),
+                },
+            ]),
+        };
+        assert_eq!(
+            tag.pretty().to_string(),
+            "[APPLICATION 3] (6) {\n  [UNIVERSAL 4] OCTET STRING (2): 61 62  |ab|\n  \
+             [CONTEXT 0] (0) {\n  }\n}\n"
+        );
+    }
 }
@@ -0,0 +1,143 @@
+use std::default;
+use structure;
+use universal;
+
+use super::{ASNTag, ValueError};
+use common::TagClass;
+
+/// String of bits.
+///
+/// Bits are numbered from zero, starting with the most significant bit of the first
+/// octet, which is how named bits in ASN.1 definitions are numbered as well.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BitString {
+    pub id: u64,
+    pub class: TagClass,
+    /// Octets holding the bits.
+    pub inner: Vec<u8>,
+    /// Number of unused bits at the end of the last octet, from 0 to 7.
+    pub unused: u8,
+}
+
+impl BitString {
+    /// Create a bit string from a sequence of bits.
+    pub fn from_bits<I: IntoIterator<Item = bool>>(bits: I) -> BitString {
+        let mut inner = Vec::new();
+        let mut len = 0;
+        for bit in bits {
+            if len % 8 == 0 {
+                inner.push(0);
+            }
+            if bit {
+                *inner.last_mut().expect("octet") |= 0x80 >> (len % 8);
+            }
+            len += 1;
+        }
+        BitString {
+            inner,
+            unused: ((8 - len % 8) % 8) as u8,
+            ..Default::default()
+        }
+    }
+
+    /// Decode the contents of a BIT STRING element: the count of unused bits,
+    /// followed by the octets holding the bits.
+    pub fn from_bytes(bytes: &[u8]) -> Result<BitString, ValueError> {
+        let (&unused, inner) = bytes
+            .split_first()
+            .ok_or(ValueError("bit string: missing unused bit count"))?;
+        if unused > 7 || (inner.is_empty() && unused != 0) {
+            return Err(ValueError("bit string: invalid unused bit count"));
+        }
+        Ok(BitString {
+            inner: inner.to_vec(),
+            unused,
+            ..Default::default()
+        })
+    }
+
+    /// Number of bits.
+    pub fn len(&self) -> usize {
+        (self.inner.len() * 8).saturating_sub(self.unused as usize)
+    }
+
+    /// Whether the string has no bits.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Value of bit `n`. Bits past the end are zero, as with named bit lists.
+    pub fn bit(&self, n: usize) -> bool {
+        n < self.len() && self.inner[n / 8] & (0x80 >> (n % 8)) != 0
+    }
+
+    /// Iterator over the bits.
+    pub fn bits(&self) -> impl Iterator<Item = bool> + '_ {
+        (0..self.len()).map(move |n| self.bit(n))
+    }
+}
+
+impl ASNTag for BitString {
+    fn into_structure(self) -> structure::StructureTag {
+        let mut out = Vec::with_capacity(self.inner.len() + 1);
+        let unused = if self.inner.is_empty() {
+            0
+        } else {
+            self.unused & 7
+        };
+        out.push(unused);
+        out.extend(self.inner);
+        // Unused bits are always sent as zero.
+        if let Some(last) = out.last_mut() {
+            if unused > 0 {
+                *last &= 0xff << unused;
+            }
+        }
+        structure::StructureTag {
+            id: self.id,
+            class: self.class,
+            payload: structure::PL::P(out),
+        }
+    }
+}
+
+impl default::Default for BitString {
+    fn default() -> Self {
+        BitString {
+            id: universal::Types::BitString as u64,
+            class: TagClass::Universal,
+            inner: Vec::new(),
+            unused: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::BitString;
+    use structure::PL;
+    use structures::ASNTag;
+
+    #[test]
+    fn test_bit_string() {
+        // X.690 8.6.4.2: '0A3B5F291CD'H, with four unused bits.
+        let encoded = [0x04, 0x0a, 0x3b, 0x5f, 0x29, 0x1c, 0xd0];
+        let bs = BitString::from_bytes(&encoded).unwrap();
+        assert_eq!(bs.len(), 44);
+        assert_eq!(bs.clone().into_structure().payload, PL::P(encoded.to_vec()));
+        let bits = bs.bits().collect::<Vec<_>>();
+        assert_eq!(BitString::from_bits(bits), bs);
+        // Garbage in the unused bits is cleared.
+        let bs = BitString::from_bits(vec![true, false, true]);
+        assert_eq!((bs.inner.clone(), bs.unused), (vec![0xa0], 5));
+        let dirty = BitString {
+            inner: vec![0xbf],
+            ..bs
+        };
+        assert_eq!(dirty.into_structure().payload, PL::P(vec![0x05, 0xa0]));
+        assert!(BitString::from_bytes(&[]).is_err());
+        assert!(BitString::from_bytes(&[0x01]).is_err());
+        assert!(BitString::from_bytes(&[0x08, 0x00]).is_err());
+        assert!(BitString::from_bytes(&[0x00]).unwrap().is_empty());
+    }
+}
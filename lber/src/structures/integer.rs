@@ -3,6 +3,7 @@ use common::TagClass;
 use structure;
 use universal;
 
+use std::convert::TryFrom;
 use std::default;
 
 use byteorder::{BigEndian, WriteBytesExt};
@@ -23,9 +24,141 @@ pub struct Enumerated {
     pub inner: i64,
 }
 
+/// Integer of arbitrary size.
+///
+/// The value is kept as its big-endian two's complement representation, which is also
+/// its BER encoding. Values which fit into the smaller integer types can be extracted
+/// with [`to_i64()`](#method.to_i64) and the related methods; larger ones, such as
+/// 128-bit serial numbers or longer, remain available as raw bytes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BigInteger {
+    pub id: u64,
+    pub class: TagClass,
+    /// Two's complement representation. Redundant leading octets are tolerated,
+    /// and removed when encoding.
+    pub inner: Vec<u8>,
+}
+
+// Strip the leading octets which only repeat the sign.
+fn minimal(mut bytes: &[u8]) -> &[u8] {
+    while bytes.len() > 1
+        && ((bytes[0] == 0x00 && bytes[1] & 0x80 == 0)
+            || (bytes[0] == 0xff && bytes[1] & 0x80 != 0))
+    {
+        bytes = &bytes[1..];
+    }
+    bytes
+}
+
+impl BigInteger {
+    /// Create an INTEGER from its two's complement representation, for example
+    /// the contents of a parsed element.
+    pub fn from_bytes(bytes: &[u8]) -> BigInteger {
+        BigInteger {
+            inner: minimal(bytes).to_vec(),
+            ..Default::default()
+        }
+    }
+
+    /// Minimal two's complement representation of the value.
+    pub fn as_bytes(&self) -> &[u8] {
+        minimal(&self.inner)
+    }
+
+    /// Whether the value is negative.
+    pub fn is_negative(&self) -> bool {
+        self.inner.first().is_some_and(|b| b & 0x80 != 0)
+    }
+
+    /// The value as `i128`, if it fits.
+    pub fn to_i128(&self) -> Option<i128> {
+        let bytes = self.as_bytes();
+        if bytes.len() > 16 {
+            return None;
+        }
+        let init = if self.is_negative() { -1 } else { 0 };
+        Some(bytes.iter().fold(init, |acc, &b| (acc << 8) | b as i128))
+    }
+
+    /// The value as `i64`, if it fits.
+    pub fn to_i64(&self) -> Option<i64> {
+        self.to_i128().and_then(|v| i64::try_from(v).ok())
+    }
+
+    /// The value as `u128`, if it's non-negative and fits.
+    pub fn to_u128(&self) -> Option<u128> {
+        if self.is_negative() {
+            return None;
+        }
+        let mut bytes = self.as_bytes();
+        if bytes.len() == 17 {
+            bytes = &bytes[1..];
+        }
+        if bytes.len() > 16 {
+            return None;
+        }
+        Some(bytes.iter().fold(0, |acc, &b| (acc << 8) | b as u128))
+    }
+}
+
+impl From<i128> for BigInteger {
+    fn from(v: i128) -> BigInteger {
+        BigInteger::from_bytes(&v.to_be_bytes())
+    }
+}
+
+impl From<i64> for BigInteger {
+    fn from(v: i64) -> BigInteger {
+        BigInteger::from(v as i128)
+    }
+}
+
+impl From<u128> for BigInteger {
+    fn from(v: u128) -> BigInteger {
+        let mut bytes = vec![0];
+        bytes.extend_from_slice(&v.to_be_bytes());
+        BigInteger::from_bytes(&bytes)
+    }
+}
+
+impl From<Integer> for BigInteger {
+    fn from(i: Integer) -> BigInteger {
+        BigInteger {
+            id: i.id,
+            class: i.class,
+            ..BigInteger::from(i.inner)
+        }
+    }
+}
+
+impl ASNTag for BigInteger {
+    fn into_structure(self) -> structure::StructureTag {
+        let inner = if self.inner.is_empty() {
+            vec![0]
+        } else {
+            minimal(&self.inner).to_vec()
+        };
+        structure::StructureTag {
+            id: self.id,
+            class: self.class,
+            payload: structure::PL::P(inner),
+        }
+    }
+}
+
+impl default::Default for BigInteger {
+    fn default() -> BigInteger {
+        BigInteger {
+            id: universal::Types::Integer as u64,
+            class: TagClass::Universal,
+            inner: vec![0],
+        }
+    }
+}
+
 fn i_e_into_structure(id: u64, class: TagClass, inner: i64) -> structure::StructureTag {
     let mut count = 0u8;
-    let mut rem: i64 = if inner >= 0 { inner } else { inner * -1 };
+    let mut rem: i64 = if inner >= 0 { inner } else { -inner };
     while {
         count += 1;
         rem >>= 8;
@@ -44,8 +177,8 @@ fn i_e_into_structure(id: u64, class: TagClass, inner: i64) -> structure::Struct
     out.write_int::<BigEndian>(inner, count as usize).unwrap();
 
     structure::StructureTag {
-        id: id,
-        class: class,
+        id,
+        class,
         payload: structure::PL::P(out),
     }
 }
@@ -84,7 +217,8 @@ impl default::Default for Enumerated {
 
 #[cfg(test)]
 mod test {
-    use super::i_e_into_structure;
+    use super::{i_e_into_structure, BigInteger};
+    use structures::ASNTag;
 
     use common::TagClass;
     use structure;
@@ -105,4 +239,39 @@ mod test {
         let correct = structure::PL::P(vec![0, 128]);
         assert_eq![result.payload, correct];
     }
+
+    #[test]
+    fn test_big_integer() {
+        let serial = 0x8000_0000_0000_0000_0000_0000_0000_0001u128;
+        let big = BigInteger::from(serial);
+        assert_eq!(big.as_bytes().len(), 17);
+        assert_eq!(big.to_u128(), Some(serial));
+        assert_eq!((big.to_i128(), big.to_i64()), (None, None));
+        let structure = big.clone().into_structure();
+        assert_eq!(structure.payload, structure::PL::P(big.inner.clone()));
+        let cases: &[(i64, &[u8])] = &[
+            (0, &[0x00]),
+            (127, &[0x7f]),
+            (128, &[0x00, 0x80]),
+            (-1, &[0xff]),
+            (-128, &[0x80]),
+            (-129, &[0xff, 0x7f]),
+            (i64::MIN, &[0x80, 0, 0, 0, 0, 0, 0, 0]),
+        ];
+        for &(v, bytes) in cases {
+            let big = BigInteger::from(v);
+            assert_eq!(big.to_i64(), Some(v));
+            assert_eq!(
+                big.into_structure().payload,
+                structure::PL::P(bytes.to_vec())
+            );
+        }
+        // Redundant sign octets are ignored.
+        let big = BigInteger::from_bytes(&[0xff, 0xff, 0x80]);
+        assert_eq!((big.as_bytes(), big.to_i64()), (&[0x80][..], Some(-128)));
+        assert_eq!(
+            BigInteger::from_bytes(&[0x00, 0x00, 0xff]).to_i64(),
+            Some(255)
+        );
+    }
 }
@@ -1,19 +1,42 @@
 use structure;
 
+use std::error::Error;
+use std::fmt;
+
+pub mod bitstring;
 pub mod boolean;
 pub mod explicit;
 pub mod integer;
 pub mod null;
 pub mod octetstring;
+pub mod oid;
+pub mod real;
 pub mod sequence;
+pub mod time;
 
 // Reexport everything
+pub use self::bitstring::BitString;
 pub use self::boolean::Boolean;
 pub use self::explicit::ExplicitTag;
-pub use self::integer::{Enumerated, Integer};
+pub use self::integer::{BigInteger, Enumerated, Integer};
 pub use self::null::Null;
 pub use self::octetstring::OctetString;
+pub use self::oid::ObjectIdentifier;
+pub use self::real::Real;
 pub use self::sequence::{Sequence, SequenceOf, Set, SetOf};
+pub use self::time::{GeneralizedTime, Time, UtcTime};
+
+/// Error in decoding or encoding a typed value.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ValueError(pub &'static str);
+
+impl fmt::Display for ValueError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+impl Error for ValueError {}
 
 /// Conversion of a tag into a serializable form.
 pub trait ASNTag {
@@ -33,6 +56,8 @@ pub enum Tag {
     Integer(integer::Integer),
     /// Integer with a different tag.
     Enumerated(integer::Enumerated),
+    /// Integer of arbitrary size.
+    BigInteger(integer::BigInteger),
     /// Sequence of values.
     Sequence(sequence::Sequence),
     /// Set of values; doesn't allow duplicates.
@@ -43,6 +68,16 @@ pub enum Tag {
     Boolean(boolean::Boolean),
     /// Null value.
     Null(null::Null),
+    /// String of bits.
+    BitString(bitstring::BitString),
+    /// Object identifier.
+    ObjectIdentifier(oid::ObjectIdentifier),
+    /// Floating point value.
+    Real(real::Real),
+    /// Time with a two-digit year.
+    UtcTime(time::UtcTime),
+    /// Time with a four-digit year and optional fractional seconds.
+    GeneralizedTime(time::GeneralizedTime),
     /// Explicitly tagged value. LDAP uses implicit tagging, but external structures might not.
     ExplicitTag(explicit::ExplicitTag),
     /// Serializable value.
@@ -54,11 +89,17 @@ impl ASNTag for Tag {
         match self {
             Tag::Integer(i) => i.into_structure(),
             Tag::Enumerated(i) => i.into_structure(),
+            Tag::BigInteger(i) => i.into_structure(),
             Tag::Sequence(i) => i.into_structure(),
             Tag::Set(i) => i.into_structure(),
             Tag::OctetString(i) => i.into_structure(),
             Tag::Boolean(i) => i.into_structure(),
             Tag::Null(i) => i.into_structure(),
+            Tag::BitString(i) => i.into_structure(),
+            Tag::ObjectIdentifier(i) => i.into_structure(),
+            Tag::Real(i) => i.into_structure(),
+            Tag::UtcTime(i) => i.into_structure(),
+            Tag::GeneralizedTime(i) => i.into_structure(),
             Tag::ExplicitTag(i) => i.into_structure(),
             Tag::StructureTag(s) => s,
         }
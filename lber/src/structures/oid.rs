@@ -0,0 +1,184 @@
+use std::default;
+use std::fmt;
+use std::str::FromStr;
+use structure;
+use universal;
+
+use super::{ASNTag, ValueError};
+use common::TagClass;
+
+/// Object identifier.
+///
+/// The arcs can be converted from and to the dotted decimal form with `FromStr`
+/// and `Display`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ObjectIdentifier {
+    pub id: u64,
+    pub class: TagClass,
+    /// Arcs of the identifier. There must be at least two, the first one no larger
+    /// than 2, and the second one below 40 unless the first one is 2.
+    pub inner: Vec<u64>,
+}
+
+fn check_arcs(arcs: &[u64]) -> Result<(), ValueError> {
+    match *arcs {
+        [first, second, ..] if first < 2 && second < 40 => Ok(()),
+        [2, second, ..] if second <= u64::MAX - 80 => Ok(()),
+        [_, _, ..] => Err(ValueError("oid: invalid initial arcs")),
+        _ => Err(ValueError("oid: fewer than two arcs")),
+    }
+}
+
+impl ObjectIdentifier {
+    /// Create an identifier from its arcs.
+    pub fn from_arcs(arcs: &[u64]) -> Result<ObjectIdentifier, ValueError> {
+        check_arcs(arcs)?;
+        Ok(ObjectIdentifier {
+            inner: arcs.to_vec(),
+            ..Default::default()
+        })
+    }
+
+    /// Decode the contents of an OBJECT IDENTIFIER element.
+    pub fn from_bytes(bytes: &[u8]) -> Result<ObjectIdentifier, ValueError> {
+        let mut subids = Vec::new();
+        let mut subid = 0u64;
+        let mut start = true;
+        for &b in bytes {
+            if start && b == 0x80 {
+                return Err(ValueError("oid: non-minimal subidentifier"));
+            }
+            if subid > u64::MAX >> 7 {
+                return Err(ValueError("oid: subidentifier too large"));
+            }
+            subid = subid << 7 | (b & 0x7f) as u64;
+            start = b & 0x80 == 0;
+            if start {
+                subids.push(subid);
+                subid = 0;
+            }
+        }
+        if !start {
+            return Err(ValueError("oid: truncated subidentifier"));
+        }
+        let (&first, rest) = subids.split_first().ok_or(ValueError("oid: empty value"))?;
+        let mut inner = match first {
+            0..=39 => vec![0, first],
+            40..=79 => vec![1, first - 40],
+            _ => vec![2, first - 80],
+        };
+        inner.extend_from_slice(rest);
+        Ok(ObjectIdentifier {
+            inner,
+            ..Default::default()
+        })
+    }
+}
+
+fn write_subid(out: &mut Vec<u8>, subid: u64) {
+    let groups = (64 - subid.leading_zeros() as usize).div_ceil(7).max(1);
+    for n in (0..groups).rev() {
+        let group = (subid >> (7 * n)) as u8 & 0x7f;
+        out.push(if n > 0 { group | 0x80 } else { group });
+    }
+}
+
+impl ASNTag for ObjectIdentifier {
+    fn into_structure(self) -> structure::StructureTag {
+        let mut out = Vec::with_capacity(self.inner.len() + 4);
+        let first = self.inner.first().copied().unwrap_or(0);
+        let second = self.inner.get(1).copied().unwrap_or(0);
+        write_subid(&mut out, (first.min(2) * 40).saturating_add(second));
+        for &arc in self.inner.iter().skip(2) {
+            write_subid(&mut out, arc);
+        }
+        structure::StructureTag {
+            id: self.id,
+            class: self.class,
+            payload: structure::PL::P(out),
+        }
+    }
+}
+
+impl FromStr for ObjectIdentifier {
+    type Err = ValueError;
+
+    fn from_str(s: &str) -> Result<ObjectIdentifier, ValueError> {
+        let arcs = s
+            .split('.')
+            .map(|arc| match arc.as_bytes() {
+                [b'0', _, ..] => Err(ValueError("oid: leading zero in arc")),
+                _ if !arc.is_empty() && arc.bytes().all(|b| b.is_ascii_digit()) => {
+                    arc.parse().map_err(|_| ValueError("oid: arc too large"))
+                }
+                _ => Err(ValueError("oid: invalid arc")),
+            })
+            .collect::<Result<Vec<u64>, _>>()?;
+        ObjectIdentifier::from_arcs(&arcs)
+    }
+}
+
+impl fmt::Display for ObjectIdentifier {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (n, arc) in self.inner.iter().enumerate() {
+            if n > 0 {
+                f.write_str(".")?;
+            }
+            write!(f, "{}", arc)?;
+        }
+        Ok(())
+    }
+}
+
+impl default::Default for ObjectIdentifier {
+    fn default() -> Self {
+        ObjectIdentifier {
+            id: universal::Types::ObjectIdentifier as u64,
+            class: TagClass::Universal,
+            inner: vec![0, 0],
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ObjectIdentifier;
+    use structure::PL;
+    use structures::ASNTag;
+
+    #[test]
+    fn test_oid() {
+        let vectors: &[(&str, &[u8])] = &[
+            // rsaEncryption
+            (
+                "1.2.840.113549.1.1.1",
+                &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x01],
+            ),
+            // id-at-commonName
+            ("2.5.4.3", &[0x55, 0x04, 0x03]),
+            // X.690 8.19.5 example
+            ("2.999.3", &[0x88, 0x37, 0x03]),
+            // LDAP Paged Results control
+            (
+                "1.2.840.113556.1.4.319",
+                &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x14, 0x01, 0x04, 0x82, 0x3f],
+            ),
+            ("0.0", &[0x00]),
+        ];
+        for &(dotted, encoded) in vectors {
+            let oid = dotted.parse::<ObjectIdentifier>().unwrap();
+            assert_eq!(oid.to_string(), dotted);
+            assert_eq!(
+                oid.clone().into_structure().payload,
+                PL::P(encoded.to_vec())
+            );
+            assert_eq!(ObjectIdentifier::from_bytes(encoded).unwrap(), oid);
+        }
+        for bad in &["1", "3.1", "1.40", "1.02", "1..2", "1.2.", "1.x", ""] {
+            assert!(bad.parse::<ObjectIdentifier>().is_err(), "{}", bad);
+        }
+        assert!(ObjectIdentifier::from_bytes(&[]).is_err());
+        assert!(ObjectIdentifier::from_bytes(&[0x2a, 0x86]).is_err());
+        assert!(ObjectIdentifier::from_bytes(&[0x2a, 0x80, 0x01]).is_err());
+    }
+}
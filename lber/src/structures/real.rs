@@ -0,0 +1,210 @@
+use std::default;
+use structure;
+use universal;
+
+use super::{ASNTag, ValueError};
+use common::TagClass;
+
+/// Floating point value.
+///
+/// Finite values are encoded in the binary form with base 2 and a normalized (odd)
+/// mantissa, as required by DER. Decoding additionally accepts bases 8 and 16, a scaling
+/// factor, and the decimal forms.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Real {
+    pub id: u64,
+    pub class: TagClass,
+    pub inner: f64,
+}
+
+// Multiply x by 2^exp, in steps which don't overflow or underflow needlessly.
+fn scale(mut x: f64, mut exp: i64) -> f64 {
+    while exp > 1000 && x.is_finite() {
+        x *= 2f64.powi(1000);
+        exp -= 1000;
+    }
+    while exp < -1000 && x != 0.0 {
+        x *= 2f64.powi(-1000);
+        exp += 1000;
+    }
+    x * 2f64.powi(exp as i32)
+}
+
+impl Real {
+    /// Decode the contents of a REAL element.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Real, ValueError> {
+        let inner = match bytes.split_first() {
+            None => 0.0,
+            Some((&0x40, [])) => f64::INFINITY,
+            Some((&0x41, [])) => f64::NEG_INFINITY,
+            Some((&0x42, [])) => f64::NAN,
+            Some((&0x43, [])) => -0.0,
+            Some((&first, rest)) if first & 0x80 != 0 => {
+                let base_bits = match (first >> 4) & 3 {
+                    0 => 1,
+                    1 => 3,
+                    2 => 4,
+                    _ => return Err(ValueError("real: reserved base")),
+                };
+                let (exp_len, rest) = match first & 3 {
+                    3 => rest
+                        .split_first()
+                        .map(|(&len, rest)| (len as usize, rest))
+                        .ok_or(ValueError("real: missing exponent length"))?,
+                    n => (n as usize + 1, rest),
+                };
+                if exp_len == 0 || exp_len > 4 || rest.len() < exp_len {
+                    return Err(ValueError("real: invalid exponent"));
+                }
+                let (exp, mantissa) = rest.split_at(exp_len);
+                let init = if exp[0] & 0x80 != 0 { -1 } else { 0 };
+                let exp = exp.iter().fold(init, |acc, &b| (acc << 8) | b as i64);
+                let mantissa = match mantissa.iter().position(|&b| b != 0) {
+                    Some(start) if mantissa.len() - start > 8 => {
+                        return Err(ValueError("real: mantissa too large"))
+                    }
+                    Some(start) => mantissa[start..]
+                        .iter()
+                        .fold(0u64, |acc, &b| (acc << 8) | b as u64),
+                    None => 0,
+                };
+                let factor = ((first >> 2) & 3) as i64;
+                let value = scale(mantissa as f64, exp * base_bits + factor);
+                if first & 0x40 != 0 {
+                    -value
+                } else {
+                    value
+                }
+            }
+            Some((&first, rest)) if first & 0xc0 == 0 && (1..=3).contains(&(first & 0x3f)) => {
+                // ISO 6093 NR1, NR2 or NR3 form.
+                let text = std::str::from_utf8(rest)
+                    .map_err(|_| ValueError("real: invalid decimal form"))?
+                    .trim_start_matches(' ')
+                    .replace(',', ".");
+                text.parse::<f64>()
+                    .map_err(|_| ValueError("real: invalid decimal form"))?
+            }
+            Some(_) => return Err(ValueError("real: unrecognized encoding")),
+        };
+        Ok(Real {
+            inner,
+            ..Default::default()
+        })
+    }
+}
+
+fn encode(value: f64) -> Vec<u8> {
+    if value == 0.0 {
+        return if value.is_sign_negative() {
+            vec![0x43]
+        } else {
+            vec![]
+        };
+    }
+    if value.is_nan() {
+        return vec![0x42];
+    }
+    if value.is_infinite() {
+        return vec![if value > 0.0 { 0x40 } else { 0x41 }];
+    }
+    let bits = value.to_bits();
+    let biased = ((bits >> 52) & 0x7ff) as i64;
+    let fraction = bits & ((1 << 52) - 1);
+    let (mut mantissa, mut exp) = if biased == 0 {
+        (fraction, -1074)
+    } else {
+        (fraction | 1 << 52, biased - 1075)
+    };
+    let zeros = mantissa.trailing_zeros();
+    mantissa >>= zeros;
+    exp += zeros as i64;
+    let exp_bytes = if (-128..128).contains(&exp) {
+        vec![exp as u8]
+    } else {
+        (exp as i16).to_be_bytes().to_vec()
+    };
+    let mut out = vec![0x80 | if value < 0.0 { 0x40 } else { 0 } | (exp_bytes.len() as u8 - 1)];
+    out.extend(exp_bytes);
+    let mantissa_bytes = mantissa.to_be_bytes();
+    let start = mantissa_bytes
+        .iter()
+        .position(|&b| b != 0)
+        .expect("non-zero");
+    out.extend_from_slice(&mantissa_bytes[start..]);
+    out
+}
+
+impl ASNTag for Real {
+    fn into_structure(self) -> structure::StructureTag {
+        structure::StructureTag {
+            id: self.id,
+            class: self.class,
+            payload: structure::PL::P(encode(self.inner)),
+        }
+    }
+}
+
+impl default::Default for Real {
+    fn default() -> Self {
+        Real {
+            id: universal::Types::Real as u64,
+            class: TagClass::Universal,
+            inner: 0.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Real;
+    use structure::PL;
+    use structures::ASNTag;
+
+    fn encoded(value: f64) -> Vec<u8> {
+        match (Real {
+            inner: value,
+            ..Default::default()
+        })
+        .into_structure()
+        .payload
+        {
+            PL::P(bytes) => bytes,
+            PL::C(_) => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_real() {
+        let vectors: &[(f64, &[u8])] = &[
+            (0.0, &[]),
+            (-0.0, &[0x43]),
+            (f64::INFINITY, &[0x40]),
+            (f64::NEG_INFINITY, &[0x41]),
+            (1.0, &[0x80, 0x00, 0x01]),
+            (0.5, &[0x80, 0xff, 0x01]),
+            (-3.0, &[0xc0, 0x00, 0x03]),
+            (1024.0, &[0x80, 0x0a, 0x01]),
+            (0.1, &[0x80, 0xc9, 0x0c, 0xcc, 0xcc, 0xcc, 0xcc, 0xcc, 0xcd]),
+            (
+                f64::MAX,
+                &[0x81, 0x03, 0xcb, 0x1f, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff],
+            ),
+            (5e-324, &[0x81, 0xfb, 0xce, 0x01]),
+        ];
+        for &(value, bytes) in vectors {
+            assert_eq!(encoded(value), bytes, "{}", value);
+            let decoded = Real::from_bytes(bytes).unwrap().inner;
+            assert_eq!(decoded.to_bits(), value.to_bits(), "{}", value);
+        }
+        assert!(Real::from_bytes(&[0x42]).unwrap().inner.is_nan());
+        // Base 16, scaling factor 1: 0x03 * 2 * 16^1.
+        assert_eq!(Real::from_bytes(&[0xa4, 0x01, 0x03]).unwrap().inner, 96.0);
+        // Decimal forms.
+        assert_eq!(Real::from_bytes(b"\x01 -12").unwrap().inner, -12.0);
+        assert_eq!(Real::from_bytes(b"\x02 3,25").unwrap().inner, 3.25);
+        assert_eq!(Real::from_bytes(b"\x03 25E-2").unwrap().inner, 0.25);
+        assert!(Real::from_bytes(&[0xb0, 0x00, 0x01]).is_err());
+        assert!(Real::from_bytes(&[0x80]).is_err());
+    }
+}
@@ -0,0 +1,408 @@
+use std::default;
+use std::fmt::Write;
+use structure;
+use universal;
+
+use super::{ASNTag, ValueError};
+use common::TagClass;
+
+/// Calendar date and time of day, as carried by the ASN.1 time types.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Time {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    /// Second, up to 60 for a leap second.
+    pub second: u8,
+    pub nanosecond: u32,
+    /// Offset from UTC in minutes, or `None` for local time, which only
+    /// GeneralizedTime can express.
+    pub offset: Option<i16>,
+}
+
+// Days since 1970-01-01 for a date in the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let doy = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+// Inverse of days_from_civil().
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let doe = days - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+fn days_in_month(year: u16, month: u8) -> u8 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+impl Time {
+    /// Time in UTC corresponding to the number of seconds and nanoseconds since
+    /// the Unix epoch.
+    pub fn from_unix(secs: i64, nanosecond: u32) -> Time {
+        let (year, month, day) = civil_from_days(secs.div_euclid(86_400));
+        let tod = secs.rem_euclid(86_400);
+        Time {
+            year: year as u16,
+            month: month as u8,
+            day: day as u8,
+            hour: (tod / 3600) as u8,
+            minute: (tod / 60 % 60) as u8,
+            second: (tod % 60) as u8,
+            nanosecond,
+            offset: Some(0),
+        }
+    }
+
+    /// Number of seconds since the Unix epoch, or `None` for local time.
+    pub fn unix_timestamp(&self) -> Option<i64> {
+        let days = days_from_civil(self.year as i64, self.month as i64, self.day as i64);
+        let secs =
+            days * 86_400 + self.hour as i64 * 3600 + self.minute as i64 * 60 + self.second as i64;
+        self.offset.map(|offset| secs - offset as i64 * 60)
+    }
+
+    fn validate(self) -> Result<Time, ValueError> {
+        if self.month < 1
+            || self.month > 12
+            || self.day < 1
+            || self.day > days_in_month(self.year, self.month)
+            || self.hour > 23
+            || self.minute > 59
+            || self.second > 60
+        {
+            return Err(ValueError("time: field out of range"));
+        }
+        Ok(self)
+    }
+
+    fn write_offset(&self, out: &mut String) {
+        match self.offset {
+            None => (),
+            Some(0) => out.push('Z'),
+            Some(offset) => {
+                let sign = if offset < 0 { '-' } else { '+' };
+                let offset = offset.unsigned_abs();
+                let _ = write!(out, "{}{:02}{:02}", sign, offset / 60, offset % 60);
+            }
+        }
+    }
+}
+
+// Cursor over the text of a time value.
+struct Text<'a>(&'a [u8]);
+
+impl<'a> Text<'a> {
+    fn digits(&mut self, n: usize) -> Result<u32, ValueError> {
+        match self.0.get(..n) {
+            Some(digits) if digits.iter().all(u8::is_ascii_digit) => {
+                self.0 = &self.0[n..];
+                Ok(digits
+                    .iter()
+                    .fold(0, |acc, &d| acc * 10 + (d - b'0') as u32))
+            }
+            _ => Err(ValueError("time: expected digits")),
+        }
+    }
+
+    fn has_digit(&self) -> bool {
+        self.0.first().is_some_and(u8::is_ascii_digit)
+    }
+
+    // Time zone suffix: "Z", or a signed offset with hours and optional minutes.
+    fn offset(&mut self) -> Result<Option<i16>, ValueError> {
+        let sign = match self.0.split_first() {
+            None => return Ok(None),
+            Some((b'Z', rest)) => {
+                self.0 = rest;
+                return Ok(Some(0));
+            }
+            Some((b'+', rest)) => {
+                self.0 = rest;
+                1
+            }
+            Some((b'-', rest)) => {
+                self.0 = rest;
+                -1
+            }
+            _ => return Err(ValueError("time: invalid time zone")),
+        };
+        let hours = self.digits(2)?;
+        let minutes = if self.0.is_empty() {
+            0
+        } else {
+            self.digits(2)?
+        };
+        if hours > 23 || minutes > 59 {
+            return Err(ValueError("time: invalid time zone"));
+        }
+        Ok(Some(sign * (hours * 60 + minutes) as i16))
+    }
+
+    fn end(&self) -> Result<(), ValueError> {
+        if self.0.is_empty() {
+            Ok(())
+        } else {
+            Err(ValueError("time: trailing characters"))
+        }
+    }
+}
+
+/// Time with a two-digit year, covering 1950 to 2049.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UtcTime {
+    pub id: u64,
+    pub class: TagClass,
+    pub inner: Time,
+}
+
+impl UtcTime {
+    /// Decode the contents of a UTCTime element. Seconds may be omitted, but the
+    /// time zone is mandatory.
+    pub fn from_bytes(bytes: &[u8]) -> Result<UtcTime, ValueError> {
+        let mut text = Text(bytes);
+        let yy = text.digits(2)? as u16;
+        let mut time = Time {
+            year: if yy < 50 { 2000 + yy } else { 1900 + yy },
+            month: text.digits(2)? as u8,
+            day: text.digits(2)? as u8,
+            hour: text.digits(2)? as u8,
+            minute: text.digits(2)? as u8,
+            ..Time::default()
+        };
+        if text.has_digit() {
+            time.second = text.digits(2)? as u8;
+        }
+        time.offset = text.offset()?;
+        if time.offset.is_none() {
+            return Err(ValueError("time: missing time zone"));
+        }
+        text.end()?;
+        Ok(UtcTime {
+            inner: time.validate()?,
+            ..Default::default()
+        })
+    }
+}
+
+impl ASNTag for UtcTime {
+    fn into_structure(self) -> structure::StructureTag {
+        let t = self.inner;
+        let mut out = format!(
+            "{:02}{:02}{:02}{:02}{:02}{:02}",
+            t.year % 100,
+            t.month,
+            t.day,
+            t.hour,
+            t.minute,
+            t.second
+        );
+        t.write_offset(&mut out);
+        structure::StructureTag {
+            id: self.id,
+            class: self.class,
+            payload: structure::PL::P(out.into_bytes()),
+        }
+    }
+}
+
+impl default::Default for UtcTime {
+    fn default() -> Self {
+        UtcTime {
+            id: universal::Types::UtcTime as u64,
+            class: TagClass::Universal,
+            inner: Time::default(),
+        }
+    }
+}
+
+/// Time with a four-digit year and optional fractional seconds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GeneralizedTime {
+    pub id: u64,
+    pub class: TagClass,
+    pub inner: Time,
+}
+
+impl GeneralizedTime {
+    /// Decode the contents of a GeneralizedTime element. Minutes and seconds may be
+    /// omitted, and the time zone as well, which denotes local time. Fractions are
+    /// only supported for seconds, with up to nanosecond precision.
+    pub fn from_bytes(bytes: &[u8]) -> Result<GeneralizedTime, ValueError> {
+        let mut text = Text(bytes);
+        let mut time = Time {
+            year: text.digits(4)? as u16,
+            month: text.digits(2)? as u8,
+            day: text.digits(2)? as u8,
+            hour: text.digits(2)? as u8,
+            ..Time::default()
+        };
+        if text.has_digit() {
+            time.minute = text.digits(2)? as u8;
+            if text.has_digit() {
+                time.second = text.digits(2)? as u8;
+                if let Some((b'.', rest)) | Some((b',', rest)) = text.0.split_first() {
+                    text.0 = rest;
+                    let len = text.0.iter().take_while(|d| d.is_ascii_digit()).count();
+                    if len == 0 {
+                        return Err(ValueError("time: empty fraction"));
+                    }
+                    let nanos = text.digits(len.min(9))?;
+                    time.nanosecond = nanos * 10u32.pow(9 - len.min(9) as u32);
+                    // Precision past nanoseconds is dropped.
+                    text.digits(len - len.min(9))?;
+                }
+            }
+        }
+        if let Some(b'.') | Some(b',') = text.0.first() {
+            return Err(ValueError("time: fractional hours and minutes unsupported"));
+        }
+        time.offset = text.offset()?;
+        text.end()?;
+        Ok(GeneralizedTime {
+            inner: time.validate()?,
+            ..Default::default()
+        })
+    }
+}
+
+impl ASNTag for GeneralizedTime {
+    fn into_structure(self) -> structure::StructureTag {
+        let t = self.inner;
+        let mut out = format!(
+            "{:04}{:02}{:02}{:02}{:02}{:02}",
+            t.year, t.month, t.day, t.hour, t.minute, t.second
+        );
+        if t.nanosecond > 0 {
+            let fraction = format!("{:09}", t.nanosecond);
+            out.push('.');
+            out.push_str(fraction.trim_end_matches('0'));
+        }
+        t.write_offset(&mut out);
+        structure::StructureTag {
+            id: self.id,
+            class: self.class,
+            payload: structure::PL::P(out.into_bytes()),
+        }
+    }
+}
+
+impl default::Default for GeneralizedTime {
+    fn default() -> Self {
+        GeneralizedTime {
+            id: universal::Types::GeneralizedTime as u64,
+            class: TagClass::Universal,
+            inner: Time::default(),
+        }
+    }
+}
+
+impl default::Default for Time {
+    /// The Unix epoch.
+    fn default() -> Self {
+        Time::from_unix(0, 0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{GeneralizedTime, Time, UtcTime};
+    use structure::PL;
+    use structures::ASNTag;
+
+    fn encoded<T: ASNTag>(t: T) -> Vec<u8> {
+        match t.into_structure().payload {
+            PL::P(bytes) => bytes,
+            PL::C(_) => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_utc_time() {
+        let vectors: &[(&str, i64, &str)] = &[
+            // X.680 example
+            ("8201021200Z", 378_820_800, "820102120000Z"),
+            ("240229120000Z", 1_709_208_000, "240229120000Z"),
+            ("491231235959Z", 2_524_607_999, "491231235959Z"),
+            ("8511062106-0500", 500_177_160, "851106210600-0500"),
+        ];
+        for &(text, unix, canonical) in vectors {
+            let t = UtcTime::from_bytes(text.as_bytes()).unwrap();
+            assert_eq!(t.inner.unix_timestamp(), Some(unix), "{}", text);
+            assert_eq!(encoded(t), canonical.as_bytes());
+        }
+        let t = UtcTime {
+            inner: Time::from_unix(2_524_607_999, 0),
+            ..Default::default()
+        };
+        assert_eq!(encoded(t), b"491231235959Z");
+        for bad in &[
+            "8201021200",
+            "8213021200Z",
+            "820230120000Z",
+            "8201021260Z",
+            "8201021200Zx",
+        ] {
+            assert!(UtcTime::from_bytes(bad.as_bytes()).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_generalized_time() {
+        // X.680 examples: local time, UTC, and an offset.
+        let t = GeneralizedTime::from_bytes(b"19851106210627.3")
+            .unwrap()
+            .inner;
+        assert_eq!((t.second, t.nanosecond, t.offset), (27, 300_000_000, None));
+        assert_eq!(t.unix_timestamp(), None);
+        let t = GeneralizedTime::from_bytes(b"19851106210627.3Z").unwrap();
+        assert_eq!(t.inner.unix_timestamp(), Some(500_159_187));
+        assert_eq!(encoded(t), b"19851106210627.3Z");
+        let t = GeneralizedTime::from_bytes(b"19851106210627.3-0500").unwrap();
+        assert_eq!(t.inner.unix_timestamp(), Some(500_177_187));
+        assert_eq!(encoded(t), b"19851106210627.3-0500");
+        // Omitted minutes and seconds, comma as the decimal sign, excess precision.
+        let t = GeneralizedTime::from_bytes(b"2024022912Z").unwrap().inner;
+        assert_eq!(t.unix_timestamp(), Some(1_709_208_000));
+        let t = GeneralizedTime::from_bytes(b"20240229120000,1234567891Z")
+            .unwrap()
+            .inner;
+        assert_eq!(t.nanosecond, 123_456_789);
+        let t = GeneralizedTime {
+            inner: Time::from_unix(-1, 500_000_000),
+            ..Default::default()
+        };
+        assert_eq!(encoded(t), b"19691231235959.5Z");
+        for bad in &[
+            "198511062106.5Z",
+            "19851106210627.Z",
+            "19850229000000Z",
+            "1985110621+2400",
+        ] {
+            assert!(
+                GeneralizedTime::from_bytes(bad.as_bytes()).is_err(),
+                "{}",
+                bad
+            );
+        }
+    }
+}
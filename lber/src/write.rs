@@ -1,45 +1,135 @@
 //! BER encoding support.
-use byteorder::{BigEndian, WriteBytesExt};
-use bytes::BytesMut;
+use bytes::{BufMut, Bytes, BytesMut};
 use common::{TagClass, TagStructure};
 use structure::{StructureTag, PL};
 
-use std::io::{self, Write};
+use std::io;
 
 /// BER-encode a tag structure into the provided buffer.
+///
+/// The lengths of all constructed elements are computed in a first pass over the
+/// structure, after which the encoding is written directly into `buf`, which is grown
+/// only once.
 pub fn encode_into(buf: &mut BytesMut, tag: StructureTag) -> io::Result<()> {
-    let mut tag_vec = Vec::new();
-    encode_inner(&mut tag_vec, tag)?;
-    buf.extend(tag_vec);
+    let mut lens = Vec::new();
+    let total = content_lens(&tag, &mut lens);
+    buf.reserve(total);
+    write_tag(buf, tag, &mut lens.into_iter(), &mut |buf, v| {
+        buf.extend_from_slice(&v)
+    });
     Ok(())
 }
 
-fn encode_inner(buf: &mut Vec<u8>, tag: StructureTag) -> io::Result<()> {
-    let structure = match tag.payload {
-        PL::P(_) => TagStructure::Primitive,
-        PL::C(_) => TagStructure::Constructed,
+/// Length of the BER encoding of a tag structure.
+pub fn encoded_len(tag: &StructureTag) -> usize {
+    content_lens(tag, &mut Vec::new())
+}
+
+/// BER-encode a tag structure into a list of chunks suitable for vectored writes.
+///
+/// Primitive values at least `min_chunk` bytes long become chunks of their own, taken
+/// over from the structure without copying. Everything else is gathered into chunks
+/// between them. Concatenated, the chunks are the same as the output of
+/// [`encode_into()`](fn.encode_into.html).
+pub fn encode_chunks(tag: StructureTag, min_chunk: usize) -> Vec<Bytes> {
+    let mut lens = Vec::new();
+    let total = content_lens(&tag, &mut lens);
+    let mut chunks = Vec::new();
+    let mut buf = BytesMut::with_capacity(total.min(4096));
+    let mut split_off = |buf: &mut BytesMut, value: Vec<u8>| {
+        if !buf.is_empty() {
+            chunks.push(buf.split().freeze());
+        }
+        chunks.push(Bytes::from(value));
+    };
+    write_tag(&mut buf, tag, &mut lens.into_iter(), &mut |buf, value| {
+        if value.len() >= min_chunk {
+            split_off(buf, value);
+        } else {
+            buf.extend_from_slice(&value);
+        }
+    });
+    if !buf.is_empty() {
+        chunks.push(buf.freeze());
+    }
+    chunks
+}
+
+// Encoded lengths of the contents of all elements in tag, in pre-order, appended to
+// lens. Returns the length of the whole encoding.
+fn content_lens(tag: &StructureTag, lens: &mut Vec<usize>) -> usize {
+    let idx = lens.len();
+    lens.push(0);
+    let len = match tag.payload {
+        PL::P(ref v) => v.len(),
+        PL::C(ref tags) => tags.iter().map(|tag| content_lens(tag, lens)).sum(),
     };
+    lens[idx] = len;
+    type_len(tag.id) + length_len(len) + len
+}
+
+pub(crate) fn type_len(id: u64) -> usize {
+    if id > 30 {
+        1 + (64 - id.leading_zeros() as usize).div_ceil(7)
+    } else {
+        1
+    }
+}
+
+pub(crate) fn length_len(len: usize) -> usize {
+    if len < 128 {
+        1
+    } else {
+        1 + (64 - (len as u64).leading_zeros() as usize).div_ceil(8)
+    }
+}
 
-    write_type(buf, tag.class, structure, tag.id);
+// Write tag with the precomputed content lengths. Primitive values are handed to
+// value, which must append them to buf or keep them otherwise.
+fn write_tag<I, F>(buf: &mut BytesMut, tag: StructureTag, lens: &mut I, value: &mut F)
+where
+    I: Iterator<Item = usize>,
+    F: FnMut(&mut BytesMut, Vec<u8>),
+{
+    let len = lens.next().expect("content length");
     match tag.payload {
         PL::P(v) => {
-            write_length(buf, v.len());
-            buf.extend(v);
+            write_type(buf, tag.class, TagStructure::Primitive, tag.id);
+            write_length(buf, len);
+            value(buf, v);
         }
         PL::C(tags) => {
-            let mut tmp = Vec::new();
+            write_type(buf, tag.class, TagStructure::Constructed, tag.id);
+            write_length(buf, len);
             for tag in tags {
-                encode_inner(&mut tmp, tag)?;
+                write_tag(buf, tag, lens, value);
             }
-            write_length(buf, tmp.len());
-            buf.extend(tmp);
         }
-    };
+    }
+}
 
-    Ok(())
+/// BER-encode a tag structure into the provided buffer, using the indefinite length
+/// form for all constructed elements.
+///
+/// Every constructed element is terminated by the end-of-contents octets instead of
+/// being preceded by its length. This is only useful for talking to peers which expect
+/// such encodings; LDAP itself requires the definite form.
+pub fn encode_indefinite_into(buf: &mut BytesMut, tag: StructureTag) -> io::Result<()> {
+    match tag.payload {
+        PL::P(_) => encode_into(buf, tag),
+        PL::C(tags) => {
+            write_type(buf, tag.class, TagStructure::Constructed, tag.id);
+            buf.extend_from_slice(&[0x80]);
+            for tag in tags {
+                encode_indefinite_into(buf, tag)?;
+            }
+            buf.extend_from_slice(&[0, 0]);
+            Ok(())
+        }
+    }
 }
 
-fn write_type(w: &mut dyn Write, class: TagClass, structure: TagStructure, id: u64) {
+pub(crate) fn write_type(w: &mut BytesMut, class: TagClass, structure: TagStructure, id: u64) {
     let extended_tag: Option<Vec<u8>>;
 
     let type_byte = {
@@ -75,7 +165,7 @@ fn write_type(w: &mut dyn Write, class: TagClass, structure: TagStructure, id: u
         }
     }; // let type_byte
 
-    let _ = w.write_u8(type_byte);
+    w.put_u8(type_byte);
 
     if let Some(mut ext_bytes) = extended_tag {
         for _ in 0..ext_bytes.len() - 1 {
@@ -84,19 +174,19 @@ fn write_type(w: &mut dyn Write, class: TagClass, structure: TagStructure, id: u
             // Set the first bit
             byte |= 0x80;
 
-            let _ = w.write_u8(byte);
+            w.put_u8(byte);
         }
 
         let byte = ext_bytes.pop().unwrap();
-        let _ = w.write_u8(byte);
+        w.put_u8(byte);
     }
 }
 
 // Yes I know you could overflow the length in theory. But, do you have 2^64 bytes of memory?
-fn write_length(w: &mut dyn Write, length: usize) {
+pub(crate) fn write_length(w: &mut BytesMut, length: usize) {
     // Short form
     if length < 128 {
-        let _ = w.write_u8(length as u8);
+        w.put_u8(length as u8);
     }
     // Long form
     else {
@@ -108,8 +198,8 @@ fn write_length(w: &mut dyn Write, length: usize) {
             len > 0
         } {}
 
-        let _ = w.write_u8(count | 0x80);
-        let _ = w.write_uint::<BigEndian>(length as u64, count as usize);
+        w.put_u8(count | 0x80);
+        w.put_uint(length as u64, count as usize);
     }
 }
 
@@ -196,4 +286,60 @@ mod tests {
 
         assert_eq!(buf, expected);
     }
+
+    #[test]
+    fn indefinite_tag() {
+        let tag = Tag::Sequence(Sequence {
+            inner: vec![Tag::Sequence(Sequence {
+                inner: vec![Tag::Integer(Integer {
+                    inner: 5,
+                    ..Default::default()
+                })],
+                ..Default::default()
+            })],
+            ..Default::default()
+        })
+        .into_structure();
+
+        let mut buf = BytesMut::new();
+        super::encode_indefinite_into(&mut buf, tag.clone()).unwrap();
+
+        assert_eq!(
+            buf,
+            vec![0x30, 0x80, 0x30, 0x80, 0x02, 0x01, 0x05, 0x00, 0x00, 0x00, 0x00]
+        );
+        assert_eq!(::parse::parse_tag(&buf), ::IResult::Done(&[][..], tag));
+    }
+
+    #[test]
+    fn chunked_encoding() {
+        let tag = Tag::Sequence(Sequence {
+            inner: vec![
+                Tag::Integer(Integer {
+                    inner: 5,
+                    ..Default::default()
+                }),
+                Tag::OctetString(OctetString {
+                    inner: vec![0x61; 300],
+                    ..Default::default()
+                }),
+                Tag::OctetString(OctetString {
+                    inner: vec![0x62; 3],
+                    ..Default::default()
+                }),
+            ],
+            ..Default::default()
+        })
+        .into_structure();
+
+        let mut buf = BytesMut::new();
+        super::encode_into(&mut buf, tag.clone()).unwrap();
+        assert_eq!(super::encoded_len(&tag), buf.len());
+        let chunks = super::encode_chunks(tag, 256);
+        assert_eq!(
+            chunks.iter().map(|c| c.len()).collect::<Vec<_>>(),
+            vec![11, 300, 5]
+        );
+        assert_eq!(chunks.concat(), &buf[..]);
+    }
 }
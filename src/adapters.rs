@@ -15,18 +15,52 @@
 //! methods on the returned handle will execute the chain of `Adapter` methods from each adapter in
 //! turn, ending with the direct call of the regular stream method.
 //!
+//! A chain of several adapters is most easily put together with [`AdapterChain`](struct.AdapterChain.html),
+//! which takes adapter instances in order and boxes them:
+//!
+//! ```rust,no_run
+//! # use ldap3::adapters::{AdapterChain, EntriesOnly, PagedResults};
+//! # use ldap3::{LdapConn, Scope};
+//! # let mut ldap = LdapConn::new("ldapi://ldapi").unwrap();
+//! let chain = AdapterChain::new()
+//!     .with(EntriesOnly::new())
+//!     .with(PagedResults::new(500));
+//! let mut stream = ldap.streaming_search_with(chain, "dc=example,dc=org", Scope::Subtree, "(uid=*)", vec!["uid"]);
+//! # let _ = stream;
+//! ```
+//!
+//! The first adapter added is the first one to be called. User-defined adapters can appear
+//! in a chain alongside the ones provided by this module, and need nothing but an
+//! implementation of [`Adapter`](trait.Adapter.html), whose documentation describes how to write one.
+//!
 //! Adapters must be written with async calls, but work equally well for both async and sync versions of the API
 //! because the sync API is just a blocking façade for the async one.
 
+use std::collections::{HashMap, VecDeque};
 use std::fmt::Debug;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::{Duration, Instant};
 
 use crate::controls::{self, Control, ControlType};
+use crate::controls_impl::{build_tag, parse_controls};
 use crate::ldap::Ldap;
 use crate::result::{LdapError, LdapResult, Result};
 use crate::search::parse_refs;
-use crate::search::{ResultEntry, Scope, SearchStream};
+use crate::search::{ResultEntry, Scope, SearchStream, StreamState};
+use crate::util::normalize_dn;
+
+use lber::common::TagClass;
+use lber::parse::parse_tag;
+use lber::structure::{StructureTag, PL};
+use lber::{write, IResult};
 
 use async_trait::async_trait;
+use bytes::BytesMut;
+use tokio::sync::watch;
 
 /// Adapter interface to a Search.
 ///
@@ -162,7 +196,10 @@ pub trait Adapter<'a, S>: AdapterClone<'a, S> + Debug + Send + Sync + 'a {
 }
 
 /// Helper trait to enforce `Clone` on `Adapter` implementors.
+///
+/// It's implemented automatically for every `Adapter` which is `Clone`.
 pub trait AdapterClone<'a, S> {
+    /// Return a boxed clone of the adapter.
     fn box_clone(&self) -> Box<dyn Adapter<'a, S> + 'a>;
 }
 
@@ -184,7 +221,13 @@ where
 pub trait SoloMarker {}
 
 /// Helper trait for `Adapter` instance/chain conversions.
+///
+/// Anything implementing this trait can be passed as the adapter argument of
+/// [`streaming_search_with()`](../struct.Ldap.html#method.streaming_search_with): a vector
+/// of boxed adapters, an [`AdapterChain`](struct.AdapterChain.html), or a single adapter
+/// which implements [`SoloMarker`](trait.SoloMarker.html).
 pub trait IntoAdapterVec<'a, S> {
+    /// Convert the value into a vector of boxed adapters.
     fn into(self) -> Vec<Box<dyn Adapter<'a, S> + 'a>>;
 }
 
@@ -204,6 +247,62 @@ where
     }
 }
 
+/// Builder of adapter chains.
+///
+/// Adapters are called in the order in which they were added with [`with()`](#method.with).
+/// The chain is passed to [`streaming_search_with()`](../struct.Ldap.html#method.streaming_search_with)
+/// like a vector of boxed adapters, and the two forms are interchangeable: adapters can be added
+/// to an existing vector with [`from_vec()`](#method.from_vec), and the vector retrieved with
+/// [`into_vec()`](#method.into_vec).
+pub struct AdapterChain<'a, S> {
+    adapters: Vec<Box<dyn Adapter<'a, S> + 'a>>,
+}
+
+impl<'a, S> Default for AdapterChain<'a, S> {
+    fn default() -> Self {
+        AdapterChain { adapters: vec![] }
+    }
+}
+
+impl<'a, S> AdapterChain<'a, S> {
+    /// Create an empty chain.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a chain starting with the adapters in `adapters`.
+    pub fn from_vec(adapters: Vec<Box<dyn Adapter<'a, S> + 'a>>) -> Self {
+        AdapterChain { adapters }
+    }
+
+    /// Append `adapter` to the chain.
+    pub fn with<A: Adapter<'a, S>>(mut self, adapter: A) -> Self {
+        self.adapters.push(Box::new(adapter));
+        self
+    }
+
+    /// Return the number of adapters in the chain.
+    pub fn len(&self) -> usize {
+        self.adapters.len()
+    }
+
+    /// Check whether the chain is empty.
+    pub fn is_empty(&self) -> bool {
+        self.adapters.is_empty()
+    }
+
+    /// Return the vector of boxed adapters.
+    pub fn into_vec(self) -> Vec<Box<dyn Adapter<'a, S> + 'a>> {
+        self.adapters
+    }
+}
+
+impl<'a, S> IntoAdapterVec<'a, S> for AdapterChain<'a, S> {
+    fn into(self) -> Vec<Box<dyn Adapter<'a, S> + 'a>> {
+        self.adapters
+    }
+}
+
 /// Adapter which returns just the directory entries.
 ///
 /// This adapter mimics the earlier behavior of the crate, where referrals were collected
@@ -290,6 +389,14 @@ where
 /// control; if it does, an error is reported. If the complete result set is not
 /// retrieved in the first protocol operation, the adapter will automatically issue
 /// further Searches until the whole search is done.
+///
+/// The total number of returned entries and the total duration of the Search can be
+/// limited on the client side with [`with_entry_budget()`](#method.with_entry_budget) and
+/// [`with_time_budget()`](#method.with_time_budget). When a budget is exceeded, the
+/// Search is abandoned, the entries retrieved so far remain with the caller, and the
+/// overall result has the code 4 (`sizeLimitExceeded`) or 3 (`timeLimitExceeded`),
+/// which [`Budget::exceeded_in()`](enum.Budget.html#method.exceeded_in) tells apart
+/// from the limits enforced by the server.
 #[derive(Clone, Debug)]
 pub struct PagedResults<S> {
     page_size: i32,
@@ -298,6 +405,35 @@ pub struct PagedResults<S> {
     scope: Scope,
     filter: String,
     attrs: Vec<S>,
+    max_entries: Option<usize>,
+    max_time: Option<Duration>,
+    deadline: Option<Instant>,
+    returned: usize,
+    exceeded: Option<Budget>,
+}
+
+/// Client-side budget of a paged Search.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Budget {
+    /// Total number of entries.
+    Entries,
+    /// Total wall-clock time.
+    Time,
+}
+
+const ENTRY_BUDGET_TEXT: &str = "client entry budget exceeded";
+const TIME_BUDGET_TEXT: &str = "client time budget exceeded";
+
+impl Budget {
+    /// Return the budget which was exceeded, if `res` is the result of a Search
+    /// abandoned by the [`PagedResults`](struct.PagedResults.html) adapter.
+    pub fn exceeded_in(res: &LdapResult) -> Option<Budget> {
+        match (res.rc, res.text.as_str()) {
+            (4, ENTRY_BUDGET_TEXT) => Some(Budget::Entries),
+            (3, TIME_BUDGET_TEXT) => Some(Budget::Time),
+            _ => None,
+        }
+    }
 }
 
 impl<S> SoloMarker for PagedResults<S> {}
@@ -312,8 +448,38 @@ impl<S> PagedResults<S> {
             scope: Scope::Base,
             filter: String::from(""),
             attrs: vec![],
+            max_entries: None,
+            max_time: None,
+            deadline: None,
+            returned: 0,
+            exceeded: None,
         }
     }
+
+    /// Stop the Search after `max_entries` entries have been returned. Referrals and
+    /// intermediate messages aren't counted.
+    pub fn with_entry_budget(mut self, max_entries: usize) -> Self {
+        self.max_entries = Some(max_entries);
+        self
+    }
+
+    /// Stop the Search if it hasn't finished within `max_time` of starting, counting
+    /// all pages.
+    pub fn with_time_budget(mut self, max_time: Duration) -> Self {
+        self.max_time = Some(max_time);
+        self
+    }
+
+    // Abandon the current page's Search when a budget runs out.
+    async fn exceed<'a>(&mut self, stream: &mut SearchStream<'a, S>, budget: Budget) -> Result<()>
+    where
+        S: AsRef<str> + Send + Sync + 'a,
+    {
+        self.exceeded = Some(budget);
+        let ldap = stream.ldap_handle();
+        let msgid = ldap.last_id();
+        ldap.abandon_cancelled(msgid).await
+    }
 }
 
 #[async_trait]
@@ -329,7 +495,7 @@ where
         filter: &str,
         attrs: Vec<S>,
     ) -> Result<()> {
-        let mut stream = stream;
+        let stream = stream;
         let stream_ldap = stream.ldap_handle();
         let mut ldap = stream_ldap.clone();
         ldap.timeout = stream_ldap.timeout;
@@ -371,12 +537,38 @@ where
         self.scope = scope;
         self.filter = String::from(filter);
         self.attrs = attrs.clone();
+        self.deadline = self.max_time.map(|max_time| Instant::now() + max_time);
+        self.returned = 0;
+        self.exceeded = None;
         stream.start(base, scope, filter, attrs).await
     }
 
     async fn next(&mut self, stream: &mut SearchStream<'a, S>) -> Result<Option<ResultEntry>> {
+        if self.exceeded.is_some() {
+            return Ok(None);
+        }
         'ent: loop {
-            match stream.next().await {
+            let next = match self.deadline {
+                Some(deadline) => {
+                    match tokio::time::timeout_at(deadline.into(), stream.next()).await {
+                        Ok(next) => next,
+                        Err(_) => {
+                            self.exceed(stream, Budget::Time).await?;
+                            return Ok(None);
+                        }
+                    }
+                }
+                None => stream.next().await,
+            };
+            match next {
+                Ok(Some(re)) if !re.is_ref() && !re.is_intermediate() => {
+                    if self.max_entries.map_or(false, |max| self.returned >= max) {
+                        self.exceed(stream, Budget::Entries).await?;
+                        return Ok(None);
+                    }
+                    self.returned += 1;
+                    return Ok(Some(re));
+                }
                 Ok(None) => {
                     let mut pr_index = None;
                     let ctrls = if let Some(res_ref) = stream.res.as_mut() {
@@ -434,6 +626,902 @@ where
     }
 
     async fn finish(&mut self, stream: &mut SearchStream<'a, S>) -> LdapResult {
+        let mut res = stream.finish().await;
+        match self.exceeded {
+            Some(Budget::Entries) => {
+                res.rc = 4;
+                res.text = String::from(ENTRY_BUDGET_TEXT);
+            }
+            Some(Budget::Time) => {
+                res.rc = 3;
+                res.text = String::from(TIME_BUDGET_TEXT);
+            }
+            None => (),
+        }
+        res
+    }
+}
+
+type CacheKey = (String, i32, String, Vec<String>);
+
+#[derive(Debug)]
+struct CachedResult {
+    stored: Instant,
+    entries: Vec<ResultEntry>,
+    result: LdapResult,
+}
+
+#[derive(Debug, Default)]
+struct CacheInner {
+    results: HashMap<CacheKey, CachedResult>,
+    in_flight: HashMap<CacheKey, watch::Receiver<()>>,
+}
+
+/// Shared store for cached Search results.
+///
+/// Searches run through the [`CachedSearch`](struct.CachedSearch.html) adapter obtained
+/// from the store with [`adapter()`](#method.adapter) first look for a stored result of
+/// a Search with the same base, scope, filter and attribute list. If a stored result
+/// younger than the time-to-live is found, its entries and overall result are returned
+/// without contacting the server. Otherwise, the Search is performed, and, if it was
+/// successful and read to the end, its result stored for later use. When the store is
+/// full, the oldest result is evicted.
+///
+/// Controls and search options are not a part of the lookup key, and neither is the
+/// identity of the bound user. Searches for which these matter should use separate
+/// stores, or no caching.
+///
+/// With coalescing enabled, a Search which would be performed while an identical one
+/// is in progress waits for the first one to finish, and takes its result from the
+/// store. The handle is cheaply cloneable, and all clones share the same store.
+#[derive(Clone, Debug)]
+pub struct SearchCache {
+    inner: Arc<StdMutex<CacheInner>>,
+    ttl: Duration,
+    max_entries: usize,
+    coalesce: bool,
+}
+
+impl SearchCache {
+    /// Create a store keeping at most `max_entries` results, each for `ttl`.
+    pub fn new(ttl: Duration, max_entries: usize) -> Self {
+        SearchCache {
+            inner: Arc::new(StdMutex::new(CacheInner::default())),
+            ttl,
+            max_entries,
+            coalesce: false,
+        }
+    }
+
+    /// Enable or disable coalescing of concurrent identical Searches. Disabled by default.
+    pub fn with_coalescing(mut self, coalesce: bool) -> Self {
+        self.coalesce = coalesce;
+        self
+    }
+
+    /// Create an adapter instance using this store. The adapter should be the first
+    /// one in the chain, so that the stored result is the one produced by the rest of
+    /// the chain.
+    pub fn adapter(&self) -> CachedSearch {
+        CachedSearch {
+            cache: self.clone(),
+            key: None,
+            collected: vec![],
+            replay: None,
+            leader: None,
+        }
+    }
+
+    /// Remove all stored results.
+    pub fn clear(&self) {
+        self.lock().results.clear();
+    }
+
+    /// Return the number of stored results, including the expired ones which
+    /// haven't been evicted yet.
+    pub fn len(&self) -> usize {
+        self.lock().results.len()
+    }
+
+    /// Check whether the store is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, CacheInner> {
+        self.inner.lock().expect("cache mutex")
+    }
+
+    fn store(&self, key: CacheKey, entries: Vec<ResultEntry>, result: LdapResult) {
+        let mut inner = self.lock();
+        let ttl = self.ttl;
+        inner.results.retain(|_, r| r.stored.elapsed() < ttl);
+        if inner.results.len() >= self.max_entries && !inner.results.contains_key(&key) {
+            let oldest = inner
+                .results
+                .iter()
+                .min_by_key(|(_, r)| r.stored)
+                .map(|(k, _)| k.clone());
+            if let Some(oldest) = oldest {
+                inner.results.remove(&oldest);
+            }
+        }
+        if self.max_entries > 0 {
+            inner.results.insert(
+                key,
+                CachedResult {
+                    stored: Instant::now(),
+                    entries,
+                    result,
+                },
+            );
+        }
+    }
+}
+
+/// Adapter which returns stored Search results. See [`SearchCache`](struct.SearchCache.html).
+#[derive(Clone, Debug)]
+pub struct CachedSearch {
+    cache: SearchCache,
+    key: Option<CacheKey>,
+    collected: Vec<ResultEntry>,
+    replay: Option<(std::vec::IntoIter<ResultEntry>, LdapResult)>,
+    leader: Option<Arc<watch::Sender<()>>>,
+}
+
+impl SoloMarker for CachedSearch {}
+
+impl CachedSearch {
+    // Look up the stored result, waiting for an identical Search in progress if
+    // coalescing. Registers this Search as in progress if there's no result.
+    async fn lookup(&mut self, key: &CacheKey) -> Option<(Vec<ResultEntry>, LdapResult)> {
+        loop {
+            let mut rx = {
+                let mut inner = self.cache.lock();
+                if let Some(r) = inner.results.get(key) {
+                    if r.stored.elapsed() < self.cache.ttl {
+                        return Some((r.entries.clone(), r.result.clone()));
+                    }
+                }
+                if !self.cache.coalesce {
+                    return None;
+                }
+                match inner.in_flight.get(key) {
+                    Some(rx) if rx.has_changed().is_ok() => rx.clone(),
+                    _ => {
+                        let (tx, rx) = watch::channel(());
+                        inner.in_flight.insert(key.clone(), rx);
+                        self.leader = Some(Arc::new(tx));
+                        return None;
+                    }
+                }
+            };
+            // The leader never sends anything; this returns when it's gone.
+            let _ = rx.changed().await;
+        }
+    }
+}
+
+#[async_trait]
+impl<'a, S> Adapter<'a, S> for CachedSearch
+where
+    S: AsRef<str> + Send + Sync + 'a,
+{
+    async fn start(
+        &mut self,
+        stream: &mut SearchStream<'a, S>,
+        base: &str,
+        scope: Scope,
+        filter: &str,
+        attrs: Vec<S>,
+    ) -> Result<()> {
+        let key = (
+            base.to_owned(),
+            scope as i32,
+            filter.to_owned(),
+            attrs.iter().map(|a| a.as_ref().to_owned()).collect(),
+        );
+        self.collected.clear();
+        self.replay = None;
+        self.leader = None;
+        if let Some((entries, result)) = self.lookup(&key).await {
+            self.replay = Some((entries.into_iter(), result));
+            stream.state = StreamState::Active;
+            return Ok(());
+        }
+        self.key = Some(key);
+        stream.start(base, scope, filter, attrs).await
+    }
+
+    async fn next(&mut self, stream: &mut SearchStream<'a, S>) -> Result<Option<ResultEntry>> {
+        if let Some((ref mut entries, _)) = self.replay {
+            return Ok(entries.next());
+        }
+        let res = stream.next().await;
+        if let Ok(Some(ref re)) = res {
+            self.collected.push(re.clone());
+        }
+        res
+    }
+
+    async fn finish(&mut self, stream: &mut SearchStream<'a, S>) -> LdapResult {
+        if let Some((_, result)) = self.replay.take() {
+            stream.state = StreamState::Closed;
+            return result;
+        }
+        let complete = stream.state() == StreamState::Done;
+        let res = stream.finish().await;
+        if let Some(key) = self.key.take() {
+            let entries = std::mem::take(&mut self.collected);
+            if complete && res.rc == 0 {
+                self.cache.store(key.clone(), entries, res.clone());
+            }
+            if self.leader.take().is_some() {
+                self.cache.lock().in_flight.remove(&key);
+            }
+        }
+        res
+    }
+}
+
+#[derive(Clone, Debug)]
+struct MergedEntry {
+    dn: Vec<u8>,
+    attrs: Vec<(Vec<u8>, Vec<Vec<u8>>)>,
+    ctrls: Vec<Control>,
+}
+
+#[derive(Clone, Debug)]
+enum MergeItem {
+    Entry(MergedEntry),
+    Other(ResultEntry),
+}
+
+impl MergedEntry {
+    fn parse(re: &ResultEntry) -> Option<Self> {
+        let mut tags = re.0.clone().match_id(4)?.expect_constructed()?.into_iter();
+        let dn = tags.next()?.expect_primitive()?;
+        let mut attrs = vec![];
+        for attr in tags.next()?.expect_constructed()? {
+            let mut attr = attr.expect_constructed()?.into_iter();
+            let atype = attr.next()?.expect_primitive()?;
+            let vals = attr
+                .next()?
+                .expect_constructed()?
+                .into_iter()
+                .map(|v| v.expect_primitive())
+                .collect::<Option<Vec<_>>>()?;
+            attrs.push((atype, vals));
+        }
+        Some(MergedEntry {
+            dn,
+            attrs,
+            ctrls: re.1.clone(),
+        })
+    }
+
+    fn merge(&mut self, other: MergedEntry) {
+        for (atype, vals) in other.attrs {
+            match self
+                .attrs
+                .iter_mut()
+                .find(|(t, _)| t.eq_ignore_ascii_case(&atype))
+            {
+                Some((_, existing)) => {
+                    for val in vals {
+                        if !existing.contains(&val) {
+                            existing.push(val);
+                        }
+                    }
+                }
+                None => self.attrs.push((atype, vals)),
+            }
+        }
+    }
+
+    fn into_result_entry(self) -> ResultEntry {
+        fn universal(id: u64, payload: PL) -> StructureTag {
+            StructureTag {
+                class: TagClass::Universal,
+                id,
+                payload,
+            }
+        }
+        let attrs = self
+            .attrs
+            .into_iter()
+            .map(|(atype, vals)| {
+                universal(
+                    16,
+                    PL::C(vec![
+                        universal(4, PL::P(atype)),
+                        universal(
+                            17,
+                            PL::C(vals.into_iter().map(|v| universal(4, PL::P(v))).collect()),
+                        ),
+                    ]),
+                )
+            })
+            .collect();
+        let tag = StructureTag {
+            class: TagClass::Application,
+            id: 4,
+            payload: PL::C(vec![
+                universal(4, PL::P(self.dn)),
+                universal(16, PL::C(attrs)),
+            ]),
+        };
+        ResultEntry(tag, self.ctrls)
+    }
+}
+
+/// Adapter which merges multiple instances of the same entry.
+///
+/// When a Search spans several protocol operations, for example because another adapter
+/// chases referrals or searches several bases, the same entry can be returned more than
+/// once, possibly with different attributes if the naming contexts overlap. This adapter
+/// returns each entry only once: instances with the same DN are merged into the first
+/// one, which receives all attributes and values of the later instances it didn't already
+/// have. DNs are compared in normalized form, ignoring case and whitespace around RDN
+/// components, and attribute types are compared case-insensitively.
+///
+/// Since it can't be known whether another instance of an entry will arrive until the
+/// operation is over, the adapter reads the complete result set before returning the
+/// first entry. Referrals and intermediate messages are returned as they were received,
+/// relative to the entries. The adapter should be placed in the chain before the
+/// adapters which produce the duplicates.
+#[derive(Clone, Debug, Default)]
+pub struct MergeEntries {
+    merged: Option<std::vec::IntoIter<ResultEntry>>,
+}
+
+impl MergeEntries {
+    /// Create a new adapter instance.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl SoloMarker for MergeEntries {}
+
+#[async_trait]
+impl<'a, S> Adapter<'a, S> for MergeEntries
+where
+    S: AsRef<str> + Send + Sync + 'a,
+{
+    async fn start(
+        &mut self,
+        stream: &mut SearchStream<'a, S>,
+        base: &str,
+        scope: Scope,
+        filter: &str,
+        attrs: Vec<S>,
+    ) -> Result<()> {
+        self.merged = None;
+        stream.start(base, scope, filter, attrs).await
+    }
+
+    async fn next(&mut self, stream: &mut SearchStream<'a, S>) -> Result<Option<ResultEntry>> {
+        if self.merged.is_none() {
+            let mut items = vec![];
+            let mut index = HashMap::new();
+            while let Some(re) = stream.next().await? {
+                let entry = if re.is_ref() || re.is_intermediate() {
+                    None
+                } else {
+                    MergedEntry::parse(&re)
+                };
+                let entry = match entry {
+                    Some(entry) => entry,
+                    None => {
+                        items.push(MergeItem::Other(re));
+                        continue;
+                    }
+                };
+                let dn = normalize_dn(&String::from_utf8_lossy(&entry.dn));
+                match index.get(&dn) {
+                    Some(&ix) => {
+                        if let MergeItem::Entry(ref mut first) = items[ix] {
+                            first.merge(entry);
+                        }
+                    }
+                    None => {
+                        index.insert(dn, items.len());
+                        items.push(MergeItem::Entry(entry));
+                    }
+                }
+            }
+            let merged: Vec<_> = items
+                .into_iter()
+                .map(|item| match item {
+                    MergeItem::Entry(entry) => entry.into_result_entry(),
+                    MergeItem::Other(re) => re,
+                })
+                .collect();
+            self.merged = Some(merged.into_iter());
+        }
+        Ok(self.merged.as_mut().and_then(|m| m.next()))
+    }
+
+    async fn finish(&mut self, stream: &mut SearchStream<'a, S>) -> LdapResult {
+        self.merged = None;
+        stream.finish().await
+    }
+}
+
+/// Adapter which reads the complete result set, keeping the part of it which exceeds
+/// a memory threshold in a temporary file.
+///
+/// This is meant for jobs which must materialize result sets too large to be kept in
+/// memory, or which want to release the connection before processing a large result.
+/// Like [`MergeEntries`](struct.MergeEntries.html), the adapter reads everything before
+/// returning the first item. Items are kept in memory until their total encoded size
+/// reaches the threshold, and the rest are written to a file, from which they're read
+/// back once the ones in memory are exhausted. The order of items is preserved. The file
+/// is created in the system temporary directory, unless another one is set with
+/// [`with_dir()`](#method.with_dir), and removed when the Search is finished or the
+/// adapter dropped.
+///
+/// File operations are buffered, but blocking.
+#[derive(Debug)]
+pub struct SpillToDisk {
+    threshold: usize,
+    dir: Option<PathBuf>,
+    spill: Option<Spill>,
+}
+
+impl Clone for SpillToDisk {
+    fn clone(&self) -> Self {
+        Self {
+            threshold: self.threshold,
+            dir: self.dir.clone(),
+            spill: None,
+        }
+    }
+}
+
+impl SpillToDisk {
+    /// Create a new adapter instance, which keeps at most `threshold` bytes of encoded
+    /// items in memory.
+    pub fn new(threshold: usize) -> Self {
+        Self {
+            threshold,
+            dir: None,
+            spill: None,
+        }
+    }
+
+    /// Create the temporary file in `dir` instead of the system temporary directory.
+    pub fn with_dir<P: Into<PathBuf>>(mut self, dir: P) -> Self {
+        self.dir = Some(dir.into());
+        self
+    }
+}
+
+impl SoloMarker for SpillToDisk {}
+
+#[async_trait]
+impl<'a, S> Adapter<'a, S> for SpillToDisk
+where
+    S: AsRef<str> + Send + Sync + 'a,
+{
+    async fn start(
+        &mut self,
+        stream: &mut SearchStream<'a, S>,
+        base: &str,
+        scope: Scope,
+        filter: &str,
+        attrs: Vec<S>,
+    ) -> Result<()> {
+        self.spill = None;
+        stream.start(base, scope, filter, attrs).await
+    }
+
+    async fn next(&mut self, stream: &mut SearchStream<'a, S>) -> Result<Option<ResultEntry>> {
+        if self.spill.is_none() {
+            let dir = self.dir.clone().unwrap_or_else(std::env::temp_dir);
+            let mut spill = Spill::new(self.threshold, dir);
+            while let Some(re) = stream.next().await? {
+                spill.push(re)?;
+            }
+            spill.rewind()?;
+            self.spill = Some(spill);
+        }
+        Ok(self.spill.as_mut().expect("spill").pop()?)
+    }
+
+    async fn finish(&mut self, stream: &mut SearchStream<'a, S>) -> LdapResult {
+        self.spill = None;
         stream.finish().await
     }
 }
+
+// Items of a result set, in memory up to the threshold, and then in a file.
+#[derive(Debug)]
+struct Spill {
+    threshold: usize,
+    in_memory: usize,
+    memory: VecDeque<ResultEntry>,
+    dir: PathBuf,
+    file: Option<SpillFile>,
+}
+
+#[derive(Debug)]
+struct SpillFile {
+    path: PathBuf,
+    writer: Option<BufWriter<File>>,
+    reader: Option<BufReader<File>>,
+}
+
+impl Drop for SpillFile {
+    fn drop(&mut self) {
+        self.writer = None;
+        self.reader = None;
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+static SPILL_FILES: AtomicUsize = AtomicUsize::new(0);
+
+impl Spill {
+    fn new(threshold: usize, dir: PathBuf) -> Self {
+        Spill {
+            threshold,
+            in_memory: 0,
+            memory: VecDeque::new(),
+            dir,
+            file: None,
+        }
+    }
+
+    fn push(&mut self, re: ResultEntry) -> io::Result<()> {
+        if self.file.is_none() {
+            let len = write::encoded_len(&re.0);
+            if self.in_memory.saturating_add(len) <= self.threshold {
+                self.in_memory += len;
+                self.memory.push_back(re);
+                return Ok(());
+            }
+            let name = format!(
+                "ldap3-spill-{}-{}",
+                std::process::id(),
+                SPILL_FILES.fetch_add(1, Ordering::Relaxed)
+            );
+            let path = self.dir.join(name);
+            let file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create_new(true)
+                .open(&path)?;
+            self.file = Some(SpillFile {
+                path,
+                writer: Some(BufWriter::new(file)),
+                reader: None,
+            });
+        }
+        let ResultEntry(tag, ctrls) = re;
+        let record = StructureTag {
+            class: TagClass::Universal,
+            id: 16,
+            payload: PL::C(vec![
+                tag,
+                StructureTag {
+                    class: TagClass::Context,
+                    id: 0,
+                    payload: PL::C(ctrls.into_iter().map(|c| build_tag(c.1)).collect()),
+                },
+            ]),
+        };
+        let mut buf = BytesMut::new();
+        write::encode_into(&mut buf, record)?;
+        let writer = self
+            .file
+            .as_mut()
+            .and_then(|f| f.writer.as_mut())
+            .expect("writer");
+        writer.write_all(&(buf.len() as u64).to_be_bytes())?;
+        writer.write_all(&buf)
+    }
+
+    // Switch the file from writing to reading.
+    fn rewind(&mut self) -> io::Result<()> {
+        if let Some(ref mut file) = self.file {
+            let mut writer = file.writer.take().expect("writer");
+            writer.flush()?;
+            let mut file_handle = writer.into_inner().map_err(|e| e.into_error())?;
+            file_handle.seek(SeekFrom::Start(0))?;
+            file.reader = Some(BufReader::new(file_handle));
+        }
+        Ok(())
+    }
+
+    fn pop(&mut self) -> io::Result<Option<ResultEntry>> {
+        if let Some(re) = self.memory.pop_front() {
+            return Ok(Some(re));
+        }
+        let reader = match self.file.as_mut().and_then(|f| f.reader.as_mut()) {
+            Some(reader) => reader,
+            None => return Ok(None),
+        };
+        let mut len = [0; 8];
+        match reader.read_exact(&mut len) {
+            Ok(()) => (),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                self.file = None;
+                return Ok(None);
+            }
+            Err(e) => return Err(e),
+        }
+        let mut buf = vec![0; u64::from_be_bytes(len) as usize];
+        reader.read_exact(&mut buf)?;
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "invalid spilled entry");
+        let mut comps = match parse_tag(&buf) {
+            IResult::Done(_, tag) => tag.expect_constructed().ok_or_else(invalid)?,
+            _ => return Err(invalid()),
+        };
+        if comps.len() != 2 {
+            return Err(invalid());
+        }
+        let ctrls = parse_controls(comps.pop().expect("controls"));
+        Ok(Some(ResultEntry(comps.pop().expect("entry"), ctrls)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{MergedEntry, Spill};
+    use crate::controls::{Control, RawControl};
+    use crate::search::SearchEntry;
+
+    fn entry(dn: &str, attrs: &[(&str, &[&str])]) -> MergedEntry {
+        MergedEntry {
+            dn: Vec::from(dn),
+            attrs: attrs
+                .iter()
+                .map(|(a, vs)| (Vec::from(*a), vs.iter().map(|v| Vec::from(*v)).collect()))
+                .collect(),
+            ctrls: vec![],
+        }
+    }
+
+    #[test]
+    fn merge_instances() {
+        let mut first = entry("uid=jdoe,dc=example,dc=org", &[("cn", &["John Doe"])]);
+        let second = entry(
+            "UID=jdoe, dc=example,dc=org",
+            &[
+                ("CN", &["John Doe", "Johnny"]),
+                ("mail", &["jdoe@example.org"]),
+            ],
+        );
+        first.merge(MergedEntry::parse(&second.into_result_entry()).unwrap());
+        let se = SearchEntry::construct(first.into_result_entry());
+        assert_eq!(se.dn, "uid=jdoe,dc=example,dc=org");
+        assert_eq!(se.attrs["cn"], vec!["John Doe", "Johnny"]);
+        assert_eq!(se.attrs["mail"], vec!["jdoe@example.org"]);
+    }
+
+    #[test]
+    fn spill_to_file() {
+        let dir = std::env::temp_dir();
+        let mut spill = Spill::new(60, dir);
+        for n in 0..10 {
+            let mut e = entry(
+                &format!("uid=user{},dc=example,dc=org", n),
+                &[("cn", &["User"])],
+            );
+            e.ctrls = vec![Control(
+                None,
+                RawControl {
+                    ctype: "1.2.3.4".to_owned(),
+                    crit: n % 2 == 0,
+                    val: Some(vec![n as u8]),
+                },
+            )];
+            spill.push(e.into_result_entry()).unwrap();
+        }
+        assert_eq!(spill.memory.len(), 1);
+        let path = spill.file.as_ref().unwrap().path.clone();
+        assert!(path.exists());
+        spill.rewind().unwrap();
+        for n in 0..10 {
+            let re = spill.pop().unwrap().unwrap();
+            let se = SearchEntry::construct(re);
+            assert_eq!(se.dn, format!("uid=user{},dc=example,dc=org", n));
+            assert_eq!(se.attrs["cn"], vec!["User"]);
+            assert_eq!(se.ctrls[0].1.crit, n % 2 == 0);
+            assert_eq!(se.ctrls[0].1.val, Some(vec![n as u8]));
+        }
+        assert!(spill.pop().unwrap().is_none());
+        assert!(!path.exists());
+    }
+}
+
+#[cfg(all(test, feature = "testkit"))]
+mod mock_test {
+    use super::{
+        Adapter, AdapterChain, Budget, EntriesOnly, PagedResults, SearchCache, SoloMarker,
+        SpillToDisk,
+    };
+    use crate::result::Result;
+    use crate::testkit::MockServer;
+    use crate::{LdapResult, ResultEntry, Scope, SearchEntry, SearchStream};
+
+    use std::time::Duration;
+
+    fn server() -> MockServer {
+        let server = MockServer::new();
+        server.add_entry("dc=example,dc=org", vec![("dc", vec!["example"])]);
+        server
+    }
+
+    #[tokio::test]
+    async fn cached_search() {
+        let server = server();
+        let (conn, mut ldap) = server.connect();
+        crate::drive!(conn);
+        let cache = SearchCache::new(Duration::from_secs(60), 8);
+        let mut counts = vec![];
+        for _ in 0..3 {
+            let adapters: Vec<Box<dyn Adapter<_>>> =
+                vec![Box::new(cache.adapter()), Box::new(EntriesOnly::new())];
+            let mut stream = ldap
+                .streaming_search_with(
+                    adapters,
+                    "dc=example,dc=org",
+                    Scope::Subtree,
+                    "(objectClass=*)",
+                    vec!["dc"],
+                )
+                .await
+                .unwrap();
+            let mut count = 0;
+            while let Some(_entry) = stream.next().await.unwrap() {
+                count += 1;
+            }
+            stream.finish().await.success().unwrap();
+            counts.push(count);
+            if counts.len() == 1 {
+                server.add_entry("ou=People,dc=example,dc=org", vec![("ou", vec!["People"])]);
+            } else {
+                assert_eq!(cache.len(), 1);
+                cache.clear();
+            }
+        }
+        assert_eq!(counts, vec![1, 1, 2]);
+    }
+
+    // Returns at most the given number of entries.
+    #[derive(Clone, Debug)]
+    struct Limit(usize);
+
+    impl SoloMarker for Limit {}
+
+    #[async_trait::async_trait]
+    impl<'a, S: AsRef<str> + Send + Sync + 'a> Adapter<'a, S> for Limit {
+        async fn start(
+            &mut self,
+            stream: &mut SearchStream<'a, S>,
+            base: &str,
+            scope: Scope,
+            filter: &str,
+            attrs: Vec<S>,
+        ) -> Result<()> {
+            stream.start(base, scope, filter, attrs).await
+        }
+
+        async fn next(&mut self, stream: &mut SearchStream<'a, S>) -> Result<Option<ResultEntry>> {
+            if self.0 == 0 {
+                return Ok(None);
+            }
+            self.0 -= 1;
+            stream.next().await
+        }
+
+        async fn finish(&mut self, stream: &mut SearchStream<'a, S>) -> LdapResult {
+            stream.finish().await
+        }
+    }
+
+    #[tokio::test]
+    async fn user_adapter_chain() {
+        let server = server();
+        server.add_entry("ou=People,dc=example,dc=org", vec![("ou", vec!["People"])]);
+        server.add_entry("ou=Groups,dc=example,dc=org", vec![("ou", vec!["Groups"])]);
+        let (conn, mut ldap) = server.connect();
+        crate::drive!(conn);
+        let chain = AdapterChain::new().with(Limit(2)).with(EntriesOnly::new());
+        assert_eq!(chain.len(), 2);
+        let mut stream = ldap
+            .streaming_search_with(
+                chain,
+                "dc=example,dc=org",
+                Scope::Subtree,
+                "(objectClass=*)",
+                vec!["1.1"],
+            )
+            .await
+            .unwrap();
+        let mut count = 0;
+        while let Some(_entry) = stream.next().await.unwrap() {
+            count += 1;
+        }
+        assert_eq!(count, 2);
+        assert_eq!(stream.finish().await.rc, 88);
+    }
+
+    #[tokio::test]
+    async fn paged_budget() {
+        let server = server();
+        for n in 0..5 {
+            let dn = format!("uid=u{},dc=example,dc=org", n);
+            server.add_entry(&dn, vec![("uid", vec![&format!("u{}", n)[..]])]);
+        }
+        let (conn, mut ldap) = server.connect();
+        crate::drive!(conn);
+        let mut stream = ldap
+            .streaming_search_with(
+                PagedResults::new(10).with_entry_budget(2),
+                "dc=example,dc=org",
+                Scope::Subtree,
+                "(uid=*)",
+                vec!["uid"],
+            )
+            .await
+            .unwrap();
+        let mut count = 0;
+        while stream.next().await.unwrap().is_some() {
+            count += 1;
+        }
+        let res = stream.finish().await;
+        assert_eq!(count, 2);
+        assert_eq!(res.rc, 4);
+        assert_eq!(Budget::exceeded_in(&res), Some(Budget::Entries));
+
+        let mut stream = ldap
+            .streaming_search_with(
+                PagedResults::new(10).with_entry_budget(5),
+                "dc=example,dc=org",
+                Scope::Subtree,
+                "(uid=*)",
+                vec!["uid"],
+            )
+            .await
+            .unwrap();
+        let mut count = 0;
+        while stream.next().await.unwrap().is_some() {
+            count += 1;
+        }
+        let res = stream.finish().await;
+        assert_eq!((count, res.rc), (5, 0));
+        assert_eq!(Budget::exceeded_in(&res), None);
+    }
+
+    #[tokio::test]
+    async fn spill_to_disk() {
+        let server = server();
+        for n in 0..20 {
+            let dn = format!("uid=u{:02},dc=example,dc=org", n);
+            server.add_entry(&dn, vec![("uid", vec![&format!("u{:02}", n)[..]])]);
+        }
+        let (conn, mut ldap) = server.connect();
+        crate::drive!(conn);
+        let mut stream = ldap
+            .streaming_search_with(
+                SpillToDisk::new(200),
+                "dc=example,dc=org",
+                Scope::OneLevel,
+                "(uid=*)",
+                vec!["uid"],
+            )
+            .await
+            .unwrap();
+        let mut uids = vec![];
+        while let Some(re) = stream.next().await.unwrap() {
+            uids.push(SearchEntry::construct(re).attrs["uid"][0].clone());
+        }
+        assert_eq!(stream.finish().await.rc, 0);
+        let expected: Vec<_> = (0..20).map(|n| format!("u{:02}", n)).collect();
+        assert_eq!(uids, expected);
+    }
+}
@@ -0,0 +1,459 @@
+//! Audit records of directory modifications.
+//!
+//! With the `audit` feature, a connection can produce a record for every Add, Modify,
+//! Delete and ModifyDN operation it performs. The record contains the target DN, the
+//! changes requested, the result code, and the identity of the last successful Bind on
+//! the connection; it can be rendered as a single-line JSON object with
+//! [`to_json()`](struct.AuditRecord.html#method.to_json). Records are delivered to an
+//! [`AuditSink`](trait.AuditSink.html), installed with
+//! [`LdapConnSettings::set_audit_sink()`](../struct.LdapConnSettings.html#method.set_audit_sink).
+//! [`JsonLines`](struct.JsonLines.html) is a sink which writes JSON records to any
+//! `Write`r, one per line.
+//!
+//! Attribute values are included in the record by default. Since they may be sensitive,
+//! they can be redacted, keeping only their number, with
+//! [`LdapConnSettings::set_audit_redaction()`](../struct.LdapConnSettings.html#method.set_audit_redaction).
+//!
+//! The record is produced when the result of the operation arrives. If the operation is
+//! abandoned or times out, it's produced without the result code, since the outcome on
+//! the server is unknown.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! use std::fs::OpenOptions;
+//! use std::sync::Arc;
+//!
+//! use ldap3::audit::JsonLines;
+//! use ldap3::{LdapConnAsync, LdapConnSettings};
+//! # use ldap3::result::Result;
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<()> {
+//! let log = OpenOptions::new().create(true).append(true).open("ldap-audit.log")?;
+//! let settings = LdapConnSettings::new()
+//!     .set_audit_sink(Arc::new(JsonLines::new(log)))
+//!     .set_audit_redaction(true);
+//! let (conn, ldap) = LdapConnAsync::with_settings(settings, "ldap://localhost:2389").await?;
+//! ldap3::drive!(conn);
+//! # drop(ldap);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use crate::request::OpKind;
+use crate::RequestId;
+
+use lber::common::TagClass;
+use lber::structures::Tag;
+
+/// Single change of an attribute in an audit record.
+#[derive(Clone, Debug)]
+pub struct AuditChange {
+    /// Kind of change: `"add"`, `"delete"`, `"replace"`, or `"increment"`. The attributes
+    /// of a new entry are recorded as additions.
+    pub action: &'static str,
+    /// Attribute name.
+    pub attr: String,
+    /// Attribute values, converted lossily to UTF-8. If values are redacted, each is
+    /// replaced with `"***"`.
+    pub values: Vec<String>,
+}
+
+/// Audit record of a single modifying operation.
+#[derive(Clone, Debug)]
+pub struct AuditRecord {
+    /// Time when the request was sent.
+    pub timestamp: SystemTime,
+    /// Message ID of the operation.
+    pub msgid: RequestId,
+    /// Operation type: `"add"`, `"modify"`, `"delete"`, or `"modifydn"`.
+    pub op: &'static str,
+    /// DN of the target entry.
+    pub dn: String,
+    /// Requested changes. Empty for Delete and ModifyDN.
+    pub changes: Vec<AuditChange>,
+    /// New RDN, for ModifyDN.
+    pub new_rdn: Option<String>,
+    /// New superior entry, for ModifyDN which moves the entry.
+    pub new_superior: Option<String>,
+    /// Bind DN of the last successful Bind on the connection. Empty for anonymous
+    /// connections, and if the last Bind was a SASL Bind without a name.
+    pub bind_dn: String,
+    /// Result code, or `None` if the result wasn't received.
+    pub rc: Option<u32>,
+}
+
+fn push_json_str(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+impl AuditRecord {
+    /// Render the record as a single-line JSON object. The timestamp is given in
+    /// seconds since the Unix epoch, with millisecond precision. Absent optional
+    /// values are rendered as `null`.
+    pub fn to_json(&self) -> String {
+        let mut out = String::with_capacity(128);
+        let ts = self
+            .timestamp
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        let _ = write!(
+            out,
+            "{{\"timestamp\":{}.{:03},\"msgid\":{},\"op\":",
+            ts.as_secs(),
+            ts.subsec_millis(),
+            self.msgid
+        );
+        push_json_str(&mut out, self.op);
+        out.push_str(",\"dn\":");
+        push_json_str(&mut out, &self.dn);
+        out.push_str(",\"bind_dn\":");
+        push_json_str(&mut out, &self.bind_dn);
+        out.push_str(",\"changes\":[");
+        for (n, change) in self.changes.iter().enumerate() {
+            if n > 0 {
+                out.push(',');
+            }
+            out.push_str("{\"action\":");
+            push_json_str(&mut out, change.action);
+            out.push_str(",\"attr\":");
+            push_json_str(&mut out, &change.attr);
+            out.push_str(",\"values\":[");
+            for (n, val) in change.values.iter().enumerate() {
+                if n > 0 {
+                    out.push(',');
+                }
+                push_json_str(&mut out, val);
+            }
+            out.push_str("]}");
+        }
+        out.push(']');
+        for (name, val) in &[
+            ("new_rdn", &self.new_rdn),
+            ("new_superior", &self.new_superior),
+        ] {
+            let _ = write!(out, ",\"{}\":", name);
+            match val {
+                Some(val) => push_json_str(&mut out, val),
+                None => out.push_str("null"),
+            }
+        }
+        match self.rc {
+            Some(rc) => {
+                let _ = write!(out, ",\"rc\":{}}}", rc);
+            }
+            None => out.push_str(",\"rc\":null}"),
+        }
+        out
+    }
+}
+
+/// Receiver of audit records.
+///
+/// Like the [metrics sink](../metrics/trait.MetricsSink.html), the audit sink is called
+/// from the connection loop, and shouldn't block for long.
+pub trait AuditSink: Send + Sync {
+    /// Called with a finished record.
+    fn record(&self, record: &AuditRecord);
+}
+
+/// Sink which writes JSON records to a `Write`r, one per line.
+///
+/// Write errors are logged, and otherwise ignored.
+pub struct JsonLines<W>(Mutex<W>);
+
+impl<W: Write + Send> JsonLines<W> {
+    /// Create a sink writing to `out`.
+    pub fn new(out: W) -> Self {
+        JsonLines(Mutex::new(out))
+    }
+}
+
+impl<W: Write + Send> AuditSink for JsonLines<W> {
+    fn record(&self, record: &AuditRecord) {
+        let mut line = record.to_json();
+        line.push('\n');
+        let mut out = self.0.lock().expect("audit writer mutex");
+        if let Err(e) = out.write_all(line.as_bytes()).and_then(|_| out.flush()) {
+            warn!("audit record write error: {}", e);
+        }
+    }
+}
+
+fn string(tag: &Tag) -> Option<String> {
+    match *tag {
+        Tag::OctetString(ref os) => Some(String::from_utf8_lossy(&os.inner).into_owned()),
+        _ => None,
+    }
+}
+
+fn seq(tag: &Tag) -> Option<&[Tag]> {
+    match *tag {
+        Tag::Sequence(ref s) => Some(&s.inner),
+        Tag::Set(ref s) => Some(&s.inner),
+        _ => None,
+    }
+}
+
+// Attribute description and values from a PartialAttribute.
+fn attribute(tag: &Tag, redact: bool) -> Option<(String, Vec<String>)> {
+    let parts = seq(tag)?;
+    let attr = string(parts.first()?)?;
+    let values = seq(parts.get(1)?)?
+        .iter()
+        .filter_map(|v| {
+            if redact {
+                Some(String::from("***"))
+            } else {
+                string(v)
+            }
+        })
+        .collect();
+    Some((attr, values))
+}
+
+// Build the record for a request, if it's a modifying one.
+fn record_for(tag: &Tag, redact: bool) -> Option<AuditRecord> {
+    let mut record = AuditRecord {
+        timestamp: SystemTime::now(),
+        msgid: 0,
+        op: "",
+        dn: String::new(),
+        changes: vec![],
+        new_rdn: None,
+        new_superior: None,
+        bind_dn: String::new(),
+        rc: None,
+    };
+    match *tag {
+        Tag::OctetString(ref os) if os.class == TagClass::Application && os.id == 10 => {
+            record.op = "delete";
+            record.dn = String::from_utf8_lossy(&os.inner).into_owned();
+            return Some(record);
+        }
+        Tag::Sequence(ref s) if s.class == TagClass::Application => {
+            let inner = &s.inner;
+            record.dn = string(inner.first()?)?;
+            match s.id {
+                6 => {
+                    record.op = "modify";
+                    for change in seq(inner.get(1)?)? {
+                        let change = seq(change)?;
+                        let action = match change.first()? {
+                            Tag::Enumerated(ref e) => match e.inner {
+                                0 => "add",
+                                1 => "delete",
+                                2 => "replace",
+                                3 => "increment",
+                                _ => "unknown",
+                            },
+                            _ => return None,
+                        };
+                        let (attr, values) = attribute(change.get(1)?, redact)?;
+                        record.changes.push(AuditChange {
+                            action,
+                            attr,
+                            values,
+                        });
+                    }
+                }
+                8 => {
+                    record.op = "add";
+                    for attr in seq(inner.get(1)?)? {
+                        let (attr, values) = attribute(attr, redact)?;
+                        record.changes.push(AuditChange {
+                            action: "add",
+                            attr,
+                            values,
+                        });
+                    }
+                }
+                12 => {
+                    record.op = "modifydn";
+                    record.new_rdn = string(inner.get(1)?);
+                    record.new_superior = inner.get(3).and_then(string);
+                }
+                _ => return None,
+            }
+        }
+        _ => return None,
+    }
+    Some(record)
+}
+
+/// Audit bookkeeping for a connection.
+pub(crate) struct AuditTracker {
+    sink: Option<Arc<dyn AuditSink>>,
+    redact: bool,
+    bind_dn: String,
+    binds: HashMap<RequestId, String>,
+    ops: HashMap<RequestId, AuditRecord>,
+}
+
+impl AuditTracker {
+    pub(crate) fn new(sink: Option<Arc<dyn AuditSink>>, redact: bool) -> Self {
+        AuditTracker {
+            sink,
+            redact,
+            bind_dn: String::new(),
+            binds: HashMap::new(),
+            ops: HashMap::new(),
+        }
+    }
+
+    pub(crate) fn start(&mut self, id: RequestId, tag: &Tag) {
+        if self.sink.is_none() {
+            return;
+        }
+        if OpKind::from_tag(tag) == Some(OpKind::Bind) {
+            if let Some(dn) = seq(tag).and_then(|s| s.get(1)).and_then(string) {
+                self.binds.insert(id, dn);
+            }
+        } else if let Some(mut record) = record_for(tag, self.redact) {
+            record.msgid = id;
+            self.ops.insert(id, record);
+        }
+    }
+
+    pub(crate) fn finish(&mut self, id: RequestId, rc: Option<u32>) {
+        if let Some(dn) = self.binds.remove(&id) {
+            match rc {
+                Some(0) => self.bind_dn = dn,
+                // A failed Bind leaves the connection anonymous.
+                Some(14) | None => (),
+                Some(_) => self.bind_dn.clear(),
+            }
+        } else if let Some(mut record) = self.ops.remove(&id) {
+            record.rc = rc;
+            record.bind_dn = self.bind_dn.clone();
+            if let Some(ref sink) = self.sink {
+                sink.record(&record);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{AuditChange, AuditRecord};
+
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn json_rendering() {
+        let record = AuditRecord {
+            timestamp: SystemTime::UNIX_EPOCH + Duration::from_millis(1_500),
+            msgid: 3,
+            op: "modify",
+            dn: String::from("cn=\"Q\",dc=example"),
+            changes: vec![AuditChange {
+                action: "replace",
+                attr: String::from("description"),
+                values: vec![String::from("a\nb")],
+            }],
+            new_rdn: None,
+            new_superior: None,
+            bind_dn: String::from("cn=admin"),
+            rc: Some(0),
+        };
+        assert_eq!(
+            record.to_json(),
+            concat!(
+                r#"{"timestamp":1.500,"msgid":3,"op":"modify","dn":"cn=\"Q\",dc=example","#,
+                r#""bind_dn":"cn=admin","changes":[{"action":"replace","attr":"description","#,
+                r#""values":["a\nb"]}],"new_rdn":null,"new_superior":null,"rc":0}"#
+            )
+        );
+    }
+}
+
+#[cfg(all(test, feature = "testkit"))]
+mod mock_test {
+    use super::{AuditRecord, AuditSink};
+    use crate::testkit::MockServer;
+    use crate::{LdapConnSettings, Mod};
+
+    use std::sync::{Arc, Mutex};
+
+    use maplit::hashset;
+
+    #[derive(Default)]
+    struct AuditLines(Mutex<Vec<String>>);
+
+    impl AuditSink for AuditLines {
+        fn record(&self, record: &AuditRecord) {
+            let mut json = record.to_json();
+            let start = json.find("\"msgid\"").unwrap();
+            json.replace_range(1..start, "");
+            self.0.lock().unwrap().push(json);
+        }
+    }
+
+    #[tokio::test]
+    async fn audit_records() {
+        let server = MockServer::new();
+        server.add_entry("dc=example,dc=org", vec![("dc", vec!["example"])]);
+        server.add_entry(
+            "cn=admin,dc=example,dc=org",
+            vec![("cn", vec!["admin"]), ("userPassword", vec!["secret"])],
+        );
+        let sink = Arc::new(AuditLines::default());
+        let settings = LdapConnSettings::new()
+            .set_audit_sink(sink.clone())
+            .set_audit_redaction(true);
+        let (conn, mut ldap) = server.connect_with_settings(settings);
+        crate::drive!(conn);
+        ldap.simple_bind("cn=admin,dc=example,dc=org", "secret")
+            .await
+            .unwrap()
+            .success()
+            .unwrap();
+        ldap.modify(
+            "dc=example,dc=org",
+            vec![Mod::Replace("description", hashset! {"top"})],
+        )
+        .await
+        .unwrap();
+        ldap.delete("ou=None,dc=example,dc=org").await.unwrap();
+        ldap.compare("dc=example,dc=org", "dc", "example")
+            .await
+            .unwrap();
+        assert_eq!(
+            *sink.0.lock().unwrap(),
+            vec![
+                concat!(
+                    r#"{"msgid":2,"op":"modify","dn":"dc=example,dc=org","#,
+                    r#""bind_dn":"cn=admin,dc=example,dc=org","changes":[{"action":"replace","#,
+                    r#""attr":"description","values":["***"]}],"new_rdn":null,"#,
+                    r#""new_superior":null,"rc":0}"#
+                ),
+                concat!(
+                    r#"{"msgid":3,"op":"delete","dn":"ou=None,dc=example,dc=org","#,
+                    r#""bind_dn":"cn=admin,dc=example,dc=org","changes":[],"new_rdn":null,"#,
+                    r#""new_superior":null,"rc":32}"#
+                ),
+            ]
+        );
+    }
+}
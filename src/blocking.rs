@@ -0,0 +1,574 @@
+//! Synchronous client which doesn't use an async runtime.
+//!
+//! [`LdapConn`](../struct.LdapConn.html) wraps the asynchronous connection and runs it on
+//! a private Tokio runtime, which means at least one additional thread and all the
+//! machinery of the runtime, even for a single Bind. That can be a problem in processes
+//! which fork after connecting, in libraries loaded through FFI into hosts with their own
+//! ideas about threads, and on small systems. [`BlockingLdap`](struct.BlockingLdap.html)
+//! instead performs every operation on the calling thread, with ordinary blocking I/O on
+//! a standard library socket.
+//!
+//! The price is that only one operation can be in progress at a time, since nothing reads
+//! from the connection between calls. Responses are decoded with the same codec as on the
+//! asynchronous connection, and the operations return the same result types.
+//!
+//! ```rust,no_run
+//! use ldap3::blocking::BlockingLdap;
+//! use ldap3::{Scope, SearchEntry};
+//!
+//! # fn main() -> ldap3::result::Result<()> {
+//! let mut ldap = BlockingLdap::new("ldap://localhost:2389")?;
+//! ldap.simple_bind("cn=Manager,dc=example,dc=org", "secret")?.success()?;
+//! let (rs, _res) = ldap
+//!     .search("dc=example,dc=org", Scope::Subtree, "(uid=*)", vec!["cn"])?
+//!     .success()?;
+//! for entry in rs {
+//!     println!("{:?}", SearchEntry::construct(entry));
+//! }
+//! ldap.unbind()
+//! # }
+//! ```
+use std::collections::HashSet;
+use std::fmt;
+use std::hash::Hash;
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use crate::controls::Control;
+use crate::controls_impl::IntoRawControlVec;
+use crate::exop::Exop;
+use crate::ldap::{sasl_external_tag, simple_bind_tag, Mod};
+use crate::protocol::{LdapCodec, MaybeControls};
+use crate::request::{
+    AddRequest, CompareRequest, DeleteRequest, ExtendedRequest, ModifyDnRequest, ModifyRequest,
+    Request, SearchRequest,
+};
+use crate::result::{
+    CompareResult, ExopResult, LdapError, LdapResult, LdapResultExt, Result, SearchResult,
+};
+use crate::search::{parse_refs, ResultEntry, Scope, SearchOptions};
+use crate::RequestId;
+
+use bytes::BytesMut;
+use lber::common::TagClass;
+use lber::structures::{Integer, Null, Tag};
+#[cfg(all(unix, feature = "ldapi"))]
+use percent_encoding::percent_decode;
+use tokio_util::codec::{Decoder, Encoder};
+use url::Url;
+
+// OID of the Notice of Disconnection unsolicited notification.
+const NOTICE_OF_DISCONNECTION: &str = "1.3.6.1.4.1.1466.20036";
+
+// Size of a single read from the stream.
+const READ_CHUNK: usize = 16 * 1024;
+
+/// Transport for a blocking connection.
+pub trait BlockingStream: Read + Write + Send {}
+
+impl<T: Read + Write + Send> BlockingStream for T {}
+
+/// Blocking connection to an LDAP server, without an async runtime.
+///
+/// See the [module-level documentation](index.html) for the rationale. The methods have
+/// the same names, parameters and results as those of [`Ldap`](../struct.Ldap.html), but
+/// they perform the whole operation before returning. Timeouts are set for the socket
+/// with [`with_timeout()`](#method.with_timeout), and apply to each read and write; an
+/// expired timeout is returned as an I/O error, after which the connection should be
+/// dropped, since a late response would be taken as belonging to the next operation.
+pub struct BlockingLdap {
+    stream: Box<dyn BlockingStream>,
+    codec: LdapCodec,
+    buf: BytesMut,
+    last_id: RequestId,
+    controls: MaybeControls,
+    search_opts: Option<SearchOptions>,
+}
+
+impl fmt::Debug for BlockingLdap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BlockingLdap")
+            .field("last_id", &self.last_id)
+            .field("buffered", &self.buf.len())
+            .field("controls", &self.controls)
+            .field("search_opts", &self.search_opts)
+            .finish()
+    }
+}
+
+impl BlockingLdap {
+    /// Open a connection to the server specified by `url`. The recognized schemes are
+    /// __ldap__ and, on Unix-like systems with the __ldapi__ feature, __ldapi__; with the
+    /// __tls-native__ feature, __ldaps__ is also supported.
+    pub fn new(url: &str) -> Result<Self> {
+        Self::connect(&Url::parse(url)?, None)
+    }
+
+    /// Open a connection like [`new()`](#method.new), with `timeout` used both for
+    /// establishing the connection and for every subsequent read and write.
+    pub fn with_timeout(url: &str, timeout: Duration) -> Result<Self> {
+        Self::connect(&Url::parse(url)?, Some(timeout))
+    }
+
+    /// Use an already established `stream` for the connection.
+    pub fn from_stream<S: BlockingStream + 'static>(stream: S) -> Self {
+        BlockingLdap {
+            stream: Box::new(stream),
+            codec: LdapCodec::default(),
+            buf: BytesMut::new(),
+            last_id: 0,
+            controls: None,
+            search_opts: None,
+        }
+    }
+
+    #[allow(unused_mut)]
+    fn connect(url: &Url, timeout: Option<Duration>) -> Result<Self> {
+        let mut port = 389;
+        match url.scheme() {
+            "ldap" => (),
+            #[cfg(feature = "tls-native")]
+            "ldaps" => port = 636,
+            #[cfg(all(unix, feature = "ldapi"))]
+            "ldapi" => return Self::connect_local(url, timeout),
+            s => return Err(LdapError::UnknownScheme(String::from(s))),
+        }
+        let port = url.port().unwrap_or(port);
+        let hostname = match url.host_str() {
+            Some(h) if !h.is_empty() => h,
+            _ => "localhost",
+        };
+        let stream = match timeout {
+            Some(timeout) => {
+                let mut last_err = None;
+                let mut stream = None;
+                for addr in (hostname, port).to_socket_addrs()? {
+                    match TcpStream::connect_timeout(&addr, timeout) {
+                        Ok(s) => {
+                            stream = Some(s);
+                            break;
+                        }
+                        Err(e) => last_err = Some(e),
+                    }
+                }
+                match stream {
+                    Some(stream) => stream,
+                    None => {
+                        return Err(last_err
+                            .unwrap_or_else(|| {
+                                io::Error::new(io::ErrorKind::NotFound, "no address for host")
+                            })
+                            .into())
+                    }
+                }
+            }
+            None => TcpStream::connect((hostname, port))?,
+        };
+        stream.set_nodelay(true)?;
+        stream.set_read_timeout(timeout)?;
+        stream.set_write_timeout(timeout)?;
+        #[cfg(feature = "tls-native")]
+        {
+            if url.scheme() == "ldaps" {
+                let connector = native_tls::TlsConnector::new()?;
+                let stream = connector.connect(hostname, stream).map_err(|e| match e {
+                    native_tls::HandshakeError::Failure(e) => LdapError::from(e),
+                    native_tls::HandshakeError::WouldBlock(_) => LdapError::from(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        "TLS handshake timed out",
+                    )),
+                })?;
+                return Ok(Self::from_stream(stream));
+            }
+        }
+        Ok(Self::from_stream(stream))
+    }
+
+    #[cfg(all(unix, feature = "ldapi"))]
+    fn connect_local(url: &Url, timeout: Option<Duration>) -> Result<Self> {
+        use std::os::unix::net::UnixStream;
+
+        let path = url.host_str().unwrap_or("");
+        if path.is_empty() {
+            return Err(LdapError::EmptyUnixPath);
+        }
+        if path.contains(':') {
+            return Err(LdapError::PortInUnixPath);
+        }
+        let dec_path = percent_decode(path.as_bytes()).decode_utf8_lossy();
+        let stream = UnixStream::connect(dec_path.as_ref())?;
+        stream.set_read_timeout(timeout)?;
+        stream.set_write_timeout(timeout)?;
+        Ok(Self::from_stream(stream))
+    }
+
+    /// Use the provided `SearchOptions` with the next Search operation.
+    pub fn with_search_options(&mut self, opts: SearchOptions) -> &mut Self {
+        self.search_opts = Some(opts);
+        self
+    }
+
+    /// Pass the provided request control(s) to the next LDAP operation.
+    pub fn with_controls<V: IntoRawControlVec>(&mut self, ctrls: V) -> &mut Self {
+        self.controls = Some(ctrls.into());
+        self
+    }
+
+    /// Return the message ID of the last request sent on the connection.
+    pub fn last_id(&self) -> RequestId {
+        self.last_id
+    }
+
+    fn send(&mut self, req: Tag) -> Result<RequestId> {
+        self.last_id = match self.last_id {
+            RequestId::MAX => 1,
+            id => id + 1,
+        };
+        let controls = self.controls.take();
+        let mut out = BytesMut::new();
+        self.codec.encode((self.last_id, req, controls), &mut out)?;
+        self.stream.write_all(&out)?;
+        self.stream.flush()?;
+        Ok(self.last_id)
+    }
+
+    // Read the next message for `msgid`. Unsolicited notifications are logged, and
+    // responses to other message IDs, which can only belong to abandoned operations,
+    // are discarded.
+    fn recv(&mut self, msgid: RequestId) -> Result<(Tag, Vec<Control>)> {
+        loop {
+            while let Some((id, (tag, controls))) = self.codec.decode(&mut self.buf)? {
+                if id == msgid {
+                    return Ok((tag, controls));
+                }
+                if id == 0 {
+                    let LdapResultExt(mut result, exop) = LdapResultExt::from(tag);
+                    result.ctrls = controls;
+                    warn!(
+                        "unsolicited notification, oid: {:?}, result: {}",
+                        exop.name, result
+                    );
+                    if exop.name.as_deref() == Some(NOTICE_OF_DISCONNECTION) {
+                        return Err(LdapError::EndOfStream);
+                    }
+                } else {
+                    debug!("discarding a response for msgid {}", id);
+                }
+            }
+            let len = self.buf.len();
+            self.buf.resize(len + READ_CHUNK, 0);
+            let n = match self.stream.read(&mut self.buf[len..]) {
+                Ok(n) => n,
+                Err(e) => {
+                    self.buf.truncate(len);
+                    return Err(e.into());
+                }
+            };
+            self.buf.truncate(len + n);
+            if n == 0 {
+                return Err(LdapError::EndOfStream);
+            }
+        }
+    }
+
+    // Send a request with a single response, skipping any Intermediate responses.
+    fn single(&mut self, req: Tag) -> Result<(LdapResult, Exop)> {
+        let msgid = self.send(req)?;
+        loop {
+            let (tag, controls) = self.recv(msgid)?;
+            if let Tag::StructureTag(ref st) = tag {
+                if st.class == TagClass::Application && st.id == 25 {
+                    continue;
+                }
+            }
+            let LdapResultExt(mut result, exop) = LdapResultExt::from(tag);
+            result.ctrls = controls;
+            return Ok((result, exop));
+        }
+    }
+
+    /// Do a simple Bind with the provided DN (`bind_dn`) and password (`bind_pw`).
+    pub fn simple_bind(&mut self, bind_dn: &str, bind_pw: &str) -> Result<LdapResult> {
+        Ok(self.single(simple_bind_tag(bind_dn, bind_pw))?.0)
+    }
+
+    /// Do a SASL EXTERNAL bind on the connection. The identity of the client must have already
+    /// been established by connection-specific methods, as is the case for Unix domain sockets
+    /// or TLS client certificates.
+    pub fn sasl_external_bind(&mut self) -> Result<LdapResult> {
+        Ok(self.single(sasl_external_tag())?.0)
+    }
+
+    /// Perform a Search with the given base location, scope, filter, and list of attributes
+    /// to return. Referrals are collected in the `refs` vector of the operation result, and
+    /// Intermediate messages are discarded, as with [`Ldap::search()`](../struct.Ldap.html#method.search).
+    pub fn search<S: AsRef<str>>(
+        &mut self,
+        base: &str,
+        scope: Scope,
+        filter: &str,
+        attrs: Vec<S>,
+    ) -> Result<SearchResult> {
+        let mut search = self.streaming_search(base, scope, filter, attrs)?;
+        let mut entries = vec![];
+        let mut refs = vec![];
+        while let Some(entry) = search.next()? {
+            if entry.is_intermediate() {
+                continue;
+            } else if entry.is_ref() {
+                refs.extend(parse_refs(entry.0));
+            } else {
+                entries.push(entry);
+            }
+        }
+        let mut res = search.finish();
+        res.refs.extend(refs);
+        Ok(SearchResult(entries, res))
+    }
+
+    /// Perform a Search, returning a handle for retrieving the entries one by one. Unlike
+    /// with [`search()`](#method.search), referrals and Intermediate messages are returned
+    /// as they arrive. The connection can't be used for anything else while the handle
+    /// is alive.
+    pub fn streaming_search<S: AsRef<str>>(
+        &mut self,
+        base: &str,
+        scope: Scope,
+        filter: &str,
+        attrs: Vec<S>,
+    ) -> Result<BlockingSearch<'_>> {
+        let opts = self.search_opts.take().unwrap_or_default();
+        let req = SearchRequest::new(base, scope, filter, attrs)
+            .opts(opts)
+            .to_tag()?;
+        let msgid = self.send(req)?;
+        Ok(BlockingSearch {
+            ldap: self,
+            msgid,
+            res: None,
+            done: false,
+        })
+    }
+
+    /// Add an entry named by `dn`, with the list of attributes and their values
+    /// given in `attrs`. None of the `HashSet`s of values for an attribute may
+    /// be empty.
+    pub fn add<S: AsRef<[u8]> + Eq + Hash>(
+        &mut self,
+        dn: &str,
+        attrs: Vec<(S, HashSet<S>)>,
+    ) -> Result<LdapResult> {
+        let req = AddRequest::new(dn, attrs).to_tag()?;
+        Ok(self.single(req)?.0)
+    }
+
+    /// Compare the value(s) of the attribute `attr` within an entry named by `dn` with the
+    /// value `val`.
+    pub fn compare<B: AsRef<[u8]>>(
+        &mut self,
+        dn: &str,
+        attr: &str,
+        val: B,
+    ) -> Result<CompareResult> {
+        let req = CompareRequest::new(dn, attr, val).to_tag()?;
+        Ok(CompareResult(self.single(req)?.0))
+    }
+
+    /// Delete an entry named by `dn`.
+    pub fn delete(&mut self, dn: &str) -> Result<LdapResult> {
+        let req = DeleteRequest::new(dn).to_tag()?;
+        Ok(self.single(req)?.0)
+    }
+
+    /// Modify an entry named by `dn` by sequentially applying the modifications given by `mods`.
+    pub fn modify<S: AsRef<[u8]> + Eq + Hash>(
+        &mut self,
+        dn: &str,
+        mods: Vec<Mod<S>>,
+    ) -> Result<LdapResult> {
+        let req = ModifyRequest::new(dn, mods).to_tag()?;
+        Ok(self.single(req)?.0)
+    }
+
+    /// Rename and/or move an entry named by `dn`.
+    pub fn modifydn(
+        &mut self,
+        dn: &str,
+        rdn: &str,
+        delete_old: bool,
+        new_sup: Option<&str>,
+    ) -> Result<LdapResult> {
+        let req = ModifyDnRequest::new(dn, rdn, delete_old, new_sup).to_tag()?;
+        Ok(self.single(req)?.0)
+    }
+
+    /// Perform an Extended operation given by `exop`.
+    pub fn extended<E: Into<Exop>>(&mut self, exop: E) -> Result<ExopResult> {
+        let req = ExtendedRequest::new(exop).to_tag()?;
+        self.single(req).map(|(res, exop)| ExopResult(exop, res))
+    }
+
+    /// Ask the server to abandon the operation identified by `msgid`. There is no response,
+    /// and any further messages for the abandoned operation are discarded.
+    pub fn abandon(&mut self, msgid: RequestId) -> Result<()> {
+        self.controls = None;
+        self.send(Tag::Integer(Integer {
+            id: 16,
+            class: TagClass::Application,
+            inner: msgid as i64,
+        }))
+        .map(|_| ())
+    }
+
+    /// Terminate the connection to the server.
+    pub fn unbind(&mut self) -> Result<()> {
+        self.send(Tag::Null(Null {
+            id: 2,
+            class: TagClass::Application,
+            inner: (),
+        }))?;
+        Ok(())
+    }
+}
+
+/// Handle for retrieving the results of a Search on a blocking connection.
+///
+/// Entries are returned by [`next()`](#method.next) until it returns `None`, after which
+/// the overall result is available through [`finish()`](#method.finish). Dropping the
+/// handle before that abandons the Search.
+#[derive(Debug)]
+pub struct BlockingSearch<'a> {
+    ldap: &'a mut BlockingLdap,
+    msgid: RequestId,
+    res: Option<LdapResult>,
+    done: bool,
+}
+
+impl<'a> BlockingSearch<'a> {
+    /// Return the next entry, referral or Intermediate message, or `None` when the
+    /// Search is done.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Result<Option<ResultEntry>> {
+        if self.res.is_some() {
+            return Ok(None);
+        }
+        let (tag, controls) = self.ldap.recv(self.msgid)?;
+        let st = match tag {
+            Tag::StructureTag(st) => st,
+            _ => unreachable!(),
+        };
+        match st.id {
+            4 | 19 | 25 => Ok(Some(ResultEntry(st, controls))),
+            _ => {
+                let mut res = LdapResult::from(Tag::StructureTag(st));
+                res.ctrls = controls;
+                self.res = Some(res);
+                Ok(None)
+            }
+        }
+    }
+
+    /// Return the overall result of the Search. If the Search hasn't ended, it's abandoned
+    /// and the result code is 88 (`userCanceled`).
+    pub fn finish(mut self) -> LdapResult {
+        match self.res.take() {
+            Some(res) => {
+                self.done = true;
+                res
+            }
+            None => LdapResult {
+                rc: 88,
+                text: String::from("search abandoned"),
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Return the message ID of the Search.
+    pub fn msgid(&self) -> RequestId {
+        self.msgid
+    }
+}
+
+impl<'a> Drop for BlockingSearch<'a> {
+    fn drop(&mut self) {
+        if !self.done && self.res.is_none() {
+            let _ = self.ldap.abandon(self.msgid);
+        }
+    }
+}
+
+#[cfg(all(test, feature = "testkit"))]
+mod test {
+    use super::BlockingLdap;
+    use crate::testkit::MockServer;
+    use crate::{Scope, SearchEntry};
+
+    use std::time::Duration;
+
+    use maplit::hashset;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn blocking_client() {
+        let server = MockServer::new();
+        server.add_entry("dc=example,dc=org", vec![("dc", vec!["example"])]);
+        server.add_entry(
+            "uid=test,dc=example,dc=org",
+            vec![("uid", vec!["test"]), ("userPassword", vec!["secret"])],
+        );
+        let url = server.listen().await;
+        tokio::task::spawn_blocking(move || {
+            let mut ldap = BlockingLdap::with_timeout(&url, Duration::from_secs(5)).unwrap();
+            let res = ldap
+                .simple_bind("uid=test,dc=example,dc=org", "wrong")
+                .unwrap();
+            assert_eq!(res.rc, 49);
+            ldap.simple_bind("uid=test,dc=example,dc=org", "secret")
+                .unwrap()
+                .success()
+                .unwrap();
+            ldap.add(
+                "uid=new,dc=example,dc=org",
+                vec![("uid", hashset! {"new"}), ("cn", hashset! {"New"})],
+            )
+            .unwrap()
+            .success()
+            .unwrap();
+            let (rs, _res) = ldap
+                .search("dc=example,dc=org", Scope::OneLevel, "(uid=*)", vec!["uid"])
+                .unwrap()
+                .success()
+                .unwrap();
+            let mut uids: Vec<_> = rs
+                .into_iter()
+                .map(|re| SearchEntry::construct(re).attrs["uid"][0].clone())
+                .collect();
+            uids.sort();
+            assert_eq!(uids, vec!["new", "test"]);
+            let mut search = ldap
+                .streaming_search(
+                    "uid=new,dc=example,dc=org",
+                    Scope::Base,
+                    "(cn=*)",
+                    vec!["cn"],
+                )
+                .unwrap();
+            assert!(search.next().unwrap().is_some());
+            assert!(search.next().unwrap().is_none());
+            assert_eq!(search.finish().rc, 0);
+            assert!(ldap
+                .compare("uid=new,dc=example,dc=org", "cn", "New")
+                .unwrap()
+                .equal()
+                .unwrap());
+            ldap.delete("uid=new,dc=example,dc=org")
+                .unwrap()
+                .success()
+                .unwrap();
+            ldap.unbind().unwrap();
+        })
+        .await
+        .unwrap();
+    }
+}
@@ -0,0 +1,547 @@
+//! Unified stream of directory changes.
+//!
+//! Directory servers offer different ways of following the changes in a subtree.
+//! OpenLDAP implements Content Synchronization ([RFC 4533](https://tools.ietf.org/html/rfc4533)),
+//! 389-ds and others support the Persistent Search control, and Active Directory provides
+//! DirSync, which must be polled. The [`ChangeStream`](trait.ChangeStream.html) trait
+//! hides the differences: each mechanism delivers [`EntryChange`](struct.EntryChange.html)
+//! values with the entry and the kind of change, and a cookie, if the mechanism has one,
+//! for resuming after a restart.
+//!
+//! [`ChangeStreamBuilder`](struct.ChangeStreamBuilder.html) opens a stream with the
+//! mechanism chosen by looking at the `supportedControl` values of the root DSE, preferring
+//! Content Synchronization, then Persistent Search, then DirSync, or with the mechanism
+//! set explicitly.
+//!
+//! The mechanisms don't report the same amount of detail. With Content Synchronization,
+//! a ModifyDN is reported as a modification, and deleted entries may be identified only
+//! by their `entryUUID`, with an empty DN. DirSync doesn't distinguish additions from
+//! modifications, and reports only the changed attributes. Persistent Search has no
+//! cookie, and changes made while the stream is closed are lost.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! use ldap3::change_stream::{ChangeKind, ChangeStreamBuilder};
+//! use ldap3::LdapConnAsync;
+//! # use ldap3::result::Result;
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<()> {
+//! let (conn, mut ldap) = LdapConnAsync::new("ldap://localhost:2389").await?;
+//! ldap3::drive!(conn);
+//! let mut changes = ChangeStreamBuilder::new("dc=example,dc=org")
+//!     .filter("(objectClass=inetOrgPerson)")
+//!     .attrs(vec!["uid", "mail"])
+//!     .open(&mut ldap)
+//!     .await?;
+//! while let Some(change) = changes.next().await? {
+//!     if change.kind != ChangeKind::Present {
+//!         println!("{:?} {}", change.kind, change.dn);
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use crate::controls::{
+    parse_syncinfo, Control, ControlType, DirSync, EntryChangeNotification, EntryChangeType,
+    EntryState, MakeCritical, PersistentSearch, RefreshMode, SyncDone, SyncInfo, SyncRequest,
+    SyncState,
+};
+use crate::ldap::Ldap;
+use crate::result::{LdapError, LdapResult, Result};
+use crate::search::{Scope, SearchEntry, SearchStream};
+
+use async_trait::async_trait;
+use tokio::time;
+
+const SYNC_REQUEST_OID: &str = "1.3.6.1.4.1.4203.1.9.1.1";
+const PERSISTENT_SEARCH_OID: &str = "2.16.840.1.113730.3.4.3";
+const DIRSYNC_OID: &str = "1.2.840.113556.1.4.841";
+
+/// Change tracking mechanism.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mechanism {
+    /// Content Synchronization in the refreshAndPersist mode.
+    ContentSync,
+    /// Persistent Search.
+    PersistentSearch,
+    /// Active Directory DirSync, polled periodically.
+    DirSync,
+}
+
+impl Mechanism {
+    /// Choose the preferred mechanism among those whose controls are listed in
+    /// `supported`, the values of `supportedControl`.
+    pub fn choose<S: AsRef<str>>(supported: &[S]) -> Option<Mechanism> {
+        let has = |oid| supported.iter().any(|s| s.as_ref() == oid);
+        if has(SYNC_REQUEST_OID) {
+            Some(Mechanism::ContentSync)
+        } else if has(PERSISTENT_SEARCH_OID) {
+            Some(Mechanism::PersistentSearch)
+        } else if has(DIRSYNC_OID) {
+            Some(Mechanism::DirSync)
+        } else {
+            None
+        }
+    }
+}
+
+/// Kind of a change.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChangeKind {
+    /// Entry belonging to the initial content, or unchanged since the cookie's state.
+    Present,
+    /// Added entry.
+    Add,
+    /// Modified entry.
+    Modify,
+    /// Deleted entry.
+    Delete,
+    /// Renamed or moved entry.
+    ModifyDn,
+}
+
+/// Change of an entry.
+#[derive(Clone, Debug)]
+pub struct EntryChange {
+    /// Kind of the change.
+    pub kind: ChangeKind,
+    /// DN of the entry; may be empty for a deletion reported only by its identifier.
+    pub dn: String,
+    /// DN before a ModifyDN, if reported.
+    pub previous_dn: Option<String>,
+    /// Unique identifier of the entry, `entryUUID` or `objectGUID`, if reported.
+    pub id: Option<Vec<u8>>,
+    /// The entry, as returned by the server. Empty for a deletion reported only by
+    /// its identifier.
+    pub entry: SearchEntry,
+}
+
+impl EntryChange {
+    fn new(kind: ChangeKind, entry: SearchEntry) -> Self {
+        EntryChange {
+            kind,
+            dn: entry.dn.clone(),
+            previous_dn: None,
+            id: None,
+            entry,
+        }
+    }
+}
+
+/// Source of directory changes.
+///
+/// See the [module-level documentation](index.html) for the overview.
+#[async_trait]
+pub trait ChangeStream: Send {
+    /// Return the next change. Returns `Ok(None)` if the server has ended the stream.
+    async fn next(&mut self) -> Result<Option<EntryChange>>;
+
+    /// Cookie representing the state up to the last returned change, for resuming
+    /// the stream. `None` if the mechanism doesn't have cookies or the server hasn't
+    /// sent one yet.
+    fn cookie(&self) -> Option<Vec<u8>>;
+
+    /// Mechanism used by the stream.
+    fn mechanism(&self) -> Mechanism;
+}
+
+/// Builder for a change stream.
+#[derive(Clone, Debug)]
+pub struct ChangeStreamBuilder {
+    base: String,
+    filter: String,
+    attrs: Vec<String>,
+    cookie: Option<Vec<u8>>,
+    mechanism: Option<Mechanism>,
+    poll_interval: Duration,
+}
+
+impl ChangeStreamBuilder {
+    /// Create a builder for the changes in the subtree under `base`. Without further
+    /// settings, all entries and all user attributes are returned, the mechanism
+    /// is detected, and DirSync is polled every 30 seconds.
+    pub fn new(base: &str) -> Self {
+        ChangeStreamBuilder {
+            base: base.to_owned(),
+            filter: String::from("(objectClass=*)"),
+            attrs: vec![],
+            cookie: None,
+            mechanism: None,
+            poll_interval: Duration::from_secs(30),
+        }
+    }
+
+    /// Return only the entries matching `filter`.
+    pub fn filter(mut self, filter: &str) -> Self {
+        self.filter = filter.to_owned();
+        self
+    }
+
+    /// Return the listed attributes.
+    pub fn attrs<S: AsRef<str>>(mut self, attrs: Vec<S>) -> Self {
+        self.attrs = attrs.iter().map(|a| a.as_ref().to_owned()).collect();
+        self
+    }
+
+    /// Resume from the state represented by `cookie`, obtained from
+    /// [`ChangeStream::cookie()`](trait.ChangeStream.html#tymethod.cookie).
+    pub fn cookie(mut self, cookie: Vec<u8>) -> Self {
+        self.cookie = Some(cookie);
+        self
+    }
+
+    /// Use `mechanism` instead of detecting it.
+    pub fn mechanism(mut self, mechanism: Mechanism) -> Self {
+        self.mechanism = Some(mechanism);
+        self
+    }
+
+    /// Wait for `interval` between DirSync polls which return no more changes.
+    pub fn poll_interval(mut self, interval: Duration) -> Self {
+        self.poll_interval = interval;
+        self
+    }
+
+    /// Determine the mechanism supported by the server.
+    pub async fn detect(ldap: &mut Ldap) -> Result<Option<Mechanism>> {
+        let (rs, _res) = ldap
+            .search("", Scope::Base, "(objectClass=*)", vec!["supportedControl"])
+            .await?
+            .success()?;
+        Ok(rs.into_iter().next().and_then(|re| {
+            let dse = SearchEntry::construct(re);
+            dse.attrs
+                .into_iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case("supportedControl"))
+                .and_then(|(_, oids)| Mechanism::choose(&oids))
+        }))
+    }
+
+    /// Start the stream on a clone of `ldap`.
+    pub async fn open(self, ldap: &mut Ldap) -> Result<Box<dyn ChangeStream>> {
+        let mechanism = match self.mechanism {
+            Some(mechanism) => mechanism,
+            None => match Self::detect(ldap).await? {
+                Some(mechanism) => mechanism,
+                None => {
+                    return Err(LdapError::InvalidValue(String::from(
+                        "no supported change tracking mechanism",
+                    )))
+                }
+            },
+        };
+        let ldap = ldap.clone();
+        Ok(match mechanism {
+            Mechanism::ContentSync => Box::new(SyncStream::start(ldap, self).await?),
+            Mechanism::PersistentSearch => Box::new(PsearchStream::start(ldap, self).await?),
+            Mechanism::DirSync => Box::new(DirSyncStream::new(ldap, self)),
+        })
+    }
+
+    async fn search(&self, ldap: &mut Ldap) -> Result<SearchStream<'static, String>> {
+        ldap.streaming_search(&self.base, Scope::Subtree, &self.filter, self.attrs.clone())
+            .await
+    }
+}
+
+// Finish the stream, returning an error for a non-success result.
+async fn finish(stream: &mut SearchStream<'static, String>) -> Result<LdapResult> {
+    stream.finish().await.success()
+}
+
+fn find_control(ctrls: &[Control], ctype: ControlType) -> Option<&Control> {
+    ctrls.iter().find(|ctrl| ctrl.0 == Some(ctype))
+}
+
+struct SyncStream {
+    stream: SearchStream<'static, String>,
+    cookie: Option<Vec<u8>>,
+    queue: VecDeque<EntryChange>,
+}
+
+impl SyncStream {
+    async fn start(mut ldap: Ldap, builder: ChangeStreamBuilder) -> Result<Self> {
+        let cookie = builder.cookie.clone();
+        ldap.with_controls(
+            SyncRequest {
+                mode: RefreshMode::RefreshAndPersist,
+                cookie: cookie.clone(),
+                reload_hint: false,
+            }
+            .critical(),
+        );
+        Ok(SyncStream {
+            stream: builder.search(&mut ldap).await?,
+            cookie,
+            queue: VecDeque::new(),
+        })
+    }
+
+    fn set_cookie(&mut self, cookie: Option<Vec<u8>>) {
+        if cookie.is_some() {
+            self.cookie = cookie;
+        }
+    }
+}
+
+#[async_trait]
+impl ChangeStream for SyncStream {
+    async fn next(&mut self) -> Result<Option<EntryChange>> {
+        loop {
+            if let Some(change) = self.queue.pop_front() {
+                return Ok(Some(change));
+            }
+            let re = match self.stream.next().await? {
+                Some(re) => re,
+                None => {
+                    let res = finish(&mut self.stream).await?;
+                    if let Some(ctrl) = find_control(&res.ctrls, ControlType::SyncDone) {
+                        self.set_cookie(ctrl.1.parse::<SyncDone>().cookie);
+                    }
+                    return Ok(None);
+                }
+            };
+            if re.is_intermediate() {
+                match parse_syncinfo(re) {
+                    SyncInfo::NewCookie(cookie) => self.set_cookie(Some(cookie)),
+                    SyncInfo::RefreshDelete { cookie, .. }
+                    | SyncInfo::RefreshPresent { cookie, .. } => self.set_cookie(cookie),
+                    SyncInfo::SyncIdSet {
+                        cookie,
+                        refresh_deletes,
+                        sync_uuids,
+                    } => {
+                        self.set_cookie(cookie);
+                        if refresh_deletes {
+                            for uuid in sync_uuids {
+                                let mut change =
+                                    EntryChange::new(ChangeKind::Delete, SearchEntry::default());
+                                change.id = Some(uuid);
+                                self.queue.push_back(change);
+                            }
+                        }
+                    }
+                }
+                continue;
+            }
+            if re.is_ref() {
+                continue;
+            }
+            let entry = SearchEntry::construct(re);
+            let state = match find_control(&entry.ctrls, ControlType::SyncState) {
+                Some(ctrl) => ctrl.1.parse::<SyncState>(),
+                None => continue,
+            };
+            let kind = match state.state {
+                EntryState::Present => ChangeKind::Present,
+                EntryState::Add => ChangeKind::Add,
+                EntryState::Modify => ChangeKind::Modify,
+                EntryState::Delete => ChangeKind::Delete,
+            };
+            self.set_cookie(state.cookie);
+            let mut change = EntryChange::new(kind, entry);
+            change.id = Some(state.entry_uuid);
+            return Ok(Some(change));
+        }
+    }
+
+    fn cookie(&self) -> Option<Vec<u8>> {
+        self.cookie.clone()
+    }
+
+    fn mechanism(&self) -> Mechanism {
+        Mechanism::ContentSync
+    }
+}
+
+struct PsearchStream {
+    stream: SearchStream<'static, String>,
+}
+
+impl PsearchStream {
+    async fn start(mut ldap: Ldap, builder: ChangeStreamBuilder) -> Result<Self> {
+        ldap.with_controls(PersistentSearch::default().critical());
+        Ok(PsearchStream {
+            stream: builder.search(&mut ldap).await?,
+        })
+    }
+}
+
+#[async_trait]
+impl ChangeStream for PsearchStream {
+    async fn next(&mut self) -> Result<Option<EntryChange>> {
+        loop {
+            let re = match self.stream.next().await? {
+                Some(re) => re,
+                None => {
+                    finish(&mut self.stream).await?;
+                    return Ok(None);
+                }
+            };
+            if re.is_ref() || re.is_intermediate() {
+                continue;
+            }
+            let entry = SearchEntry::construct(re);
+            let ecn = find_control(&entry.ctrls, ControlType::EntryChangeNotification)
+                .map(|ctrl| ctrl.1.parse::<EntryChangeNotification>());
+            let (kind, previous_dn) = match ecn {
+                Some(ecn) => (
+                    match ecn.change_type {
+                        EntryChangeType::Add => ChangeKind::Add,
+                        EntryChangeType::Delete => ChangeKind::Delete,
+                        EntryChangeType::Modify => ChangeKind::Modify,
+                        EntryChangeType::ModDn => ChangeKind::ModifyDn,
+                    },
+                    ecn.previous_dn,
+                ),
+                None => (ChangeKind::Present, None),
+            };
+            let mut change = EntryChange::new(kind, entry);
+            change.previous_dn = previous_dn;
+            return Ok(Some(change));
+        }
+    }
+
+    fn cookie(&self) -> Option<Vec<u8>> {
+        None
+    }
+
+    fn mechanism(&self) -> Mechanism {
+        Mechanism::PersistentSearch
+    }
+}
+
+struct DirSyncStream {
+    ldap: Ldap,
+    builder: ChangeStreamBuilder,
+    stream: Option<SearchStream<'static, String>>,
+    cookie: Vec<u8>,
+    poll: bool,
+}
+
+impl DirSyncStream {
+    fn new(ldap: Ldap, builder: ChangeStreamBuilder) -> Self {
+        DirSyncStream {
+            ldap,
+            cookie: builder.cookie.clone().unwrap_or_default(),
+            builder,
+            stream: None,
+            poll: false,
+        }
+    }
+}
+
+#[async_trait]
+impl ChangeStream for DirSyncStream {
+    async fn next(&mut self) -> Result<Option<EntryChange>> {
+        loop {
+            let stream = match self.stream {
+                Some(ref mut stream) => stream,
+                None => {
+                    if self.poll {
+                        time::sleep(self.builder.poll_interval).await;
+                    }
+                    self.ldap.with_controls(
+                        DirSync {
+                            flags: DirSync::INCREMENTAL_VALUES,
+                            max_bytes: 0,
+                            cookie: self.cookie.clone(),
+                        }
+                        .critical(),
+                    );
+                    let stream = self.builder.search(&mut self.ldap).await?;
+                    self.stream.get_or_insert(stream)
+                }
+            };
+            let re = match stream.next().await? {
+                Some(re) => re,
+                None => {
+                    let res = finish(stream).await?;
+                    self.stream = None;
+                    let resp = find_control(&res.ctrls, ControlType::DirSync)
+                        .map(|ctrl| ctrl.1.parse::<DirSync>())
+                        .unwrap_or_default();
+                    self.cookie = resp.cookie;
+                    self.poll = resp.flags == 0;
+                    continue;
+                }
+            };
+            if re.is_ref() || re.is_intermediate() {
+                continue;
+            }
+            let entry = SearchEntry::construct(re);
+            let deleted = entry
+                .attrs
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case("isDeleted"))
+                .is_some_and(|(_, vals)| vals.iter().any(|v| v.eq_ignore_ascii_case("TRUE")));
+            let id = entry
+                .bin_attrs
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case("objectGUID"))
+                .and_then(|(_, vals)| vals.first().cloned());
+            let kind = if deleted {
+                ChangeKind::Delete
+            } else {
+                ChangeKind::Modify
+            };
+            let mut change = EntryChange::new(kind, entry);
+            change.id = id;
+            return Ok(Some(change));
+        }
+    }
+
+    fn cookie(&self) -> Option<Vec<u8>> {
+        if self.cookie.is_empty() {
+            None
+        } else {
+            Some(self.cookie.clone())
+        }
+    }
+
+    fn mechanism(&self) -> Mechanism {
+        Mechanism::DirSync
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Mechanism;
+    use crate::controls::{
+        ControlParser, DirSync, EntryChangeNotification, EntryChangeType, RawControl,
+    };
+
+    #[test]
+    fn choose_mechanism() {
+        assert_eq!(
+            Mechanism::choose(&["1.2.840.113556.1.4.841", "2.16.840.1.113730.3.4.3"]),
+            Some(Mechanism::PersistentSearch)
+        );
+        assert_eq!(
+            Mechanism::choose(&["1.2.840.113556.1.4.841"]),
+            Some(Mechanism::DirSync)
+        );
+        assert_eq!(Mechanism::choose::<&str>(&[]), None);
+    }
+
+    #[test]
+    fn controls() {
+        let raw = RawControl::from(DirSync {
+            flags: 1,
+            max_bytes: 0,
+            cookie: b"abc".to_vec(),
+        });
+        let ds: DirSync = raw.parse();
+        assert_eq!((ds.flags, ds.cookie.as_slice()), (1, &b"abc"[..]));
+        // SEQUENCE { ENUMERATED 8, OCTET STRING "cn=a" }
+        let ecn = EntryChangeNotification::parse(b"\x30\x09\x0a\x01\x08\x04\x04cn=a");
+        assert_eq!(ecn.change_type, EntryChangeType::ModDn);
+        assert_eq!(ecn.previous_dn.as_deref(), Some("cn=a"));
+    }
+}
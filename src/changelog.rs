@@ -0,0 +1,479 @@
+//! Retro changelog reader.
+//!
+//! Some servers record every update in a changelog suffix, conventionally `cn=changelog`,
+//! where each change is an entry named like `changeNumber=42,cn=changelog`. This is the
+//! case with the Retro Changelog plugin of 389-ds, with eDirectory, and with OpenLDAP's
+//! `retrocl`-compatible setups. The entry holds the sequence number of the change in
+//! `changeNumber`, the DN of the modified entry in `targetDN`, the operation in `changeType`,
+//! and, for Add and Modify, the content of the operation in LDIF form in `changes`.
+//! A ModifyDN change has its parameters in `newRDN`, `deleteOldRDN` and `newSuperior`.
+//! For servers without Content Synchronization or DirSync, reading the changelog is the
+//! only way to follow the updates.
+//!
+//! [`ChangelogReader`](struct.ChangelogReader.html) retrieves the changes with numbers
+//! above its checkpoint, parses them into [`ChangeEvent`](struct.ChangeEvent.html)s
+//! ordered by number, and advances the checkpoint. Each event carries the change as
+//! a [`request`](../request/index.html) structure, which can be inspected or replayed
+//! against another server with [`Ldap::send()`](../struct.Ldap.html#method.send).
+//!
+//! The checkpoint is the number of the last change returned. To survive restarts, the
+//! application should store it, or the number of the last event it has processed, and
+//! resume with [`start_after()`](struct.ChangelogReader.html#method.start_after). The
+//! changelog is trimmed by the server, and if the first available change is more than
+//! one past the checkpoint, the changes in between are lost; this can be detected by
+//! comparing the checkpoint with the bounds returned by
+//! [`bounds()`](struct.ChangelogReader.html#method.bounds).
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! use std::time::Duration;
+//!
+//! use ldap3::changelog::{Change, ChangelogReader};
+//! use ldap3::LdapConnAsync;
+//! # use ldap3::result::Result;
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<()> {
+//! let (conn, mut ldap) = LdapConnAsync::new("ldap://localhost:2389").await?;
+//! ldap3::drive!(conn);
+//! ldap.simple_bind("cn=Directory Manager", "secret").await?.success()?;
+//! let mut reader = ChangelogReader::new(ldap).batch_size(500);
+//! reader.skip_to_end().await?;
+//! loop {
+//!     for event in reader.tail(Duration::from_secs(5)).await? {
+//!         if let Change::Delete(ref req) = event.change {
+//!             println!("{}: deleted {}", event.number, req.dn);
+//!         }
+//!     }
+//! }
+//! # }
+//! ```
+
+use std::collections::HashSet;
+use std::time::Duration;
+
+use crate::ldap::{Ldap, Mod};
+use crate::request::{AddRequest, DeleteRequest, ModifyDnRequest, ModifyRequest};
+use crate::result::{LdapError, Result};
+use crate::search::{Scope, SearchEntry};
+use crate::util::base64_decode;
+
+use tokio::time;
+
+/// Conventional base DN of the changelog.
+pub const CHANGELOG_DN: &str = "cn=changelog";
+
+const CHANGE_ATTRS: &[&str] = &[
+    "changeNumber",
+    "targetDN",
+    "changeType",
+    "changes",
+    "newRDN",
+    "deleteOldRDN",
+    "newSuperior",
+    "changeTime",
+];
+
+/// Operation recorded in a changelog entry.
+#[derive(Clone, Debug)]
+pub enum Change {
+    /// Add.
+    Add(AddRequest),
+    /// Delete.
+    Delete(DeleteRequest),
+    /// Modify.
+    Modify(ModifyRequest),
+    /// ModifyDN.
+    ModifyDn(ModifyDnRequest),
+}
+
+impl Change {
+    /// DN of the entry targeted by the change.
+    pub fn dn(&self) -> &str {
+        match self {
+            Change::Add(req) => &req.dn,
+            Change::Delete(req) => &req.dn,
+            Change::Modify(req) => &req.dn,
+            Change::ModifyDn(req) => &req.dn,
+        }
+    }
+}
+
+/// Parsed changelog entry.
+#[derive(Clone, Debug)]
+pub struct ChangeEvent {
+    /// Change number.
+    pub number: u64,
+    /// Value of `changeTime`, if present, in the GeneralizedTime form.
+    pub time: Option<String>,
+    /// The change.
+    pub change: Change,
+}
+
+fn changelog_error<T>(msg: String) -> Result<T> {
+    Err(LdapError::Changelog(msg))
+}
+
+fn first_value(entry: &SearchEntry, attr: &str) -> Option<String> {
+    if let Some((_, vals)) = entry
+        .attrs
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(attr))
+    {
+        return vals.first().cloned();
+    }
+    entry
+        .bin_attrs
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(attr))
+        .and_then(|(_, vals)| vals.first())
+        .map(|val| String::from_utf8_lossy(val).into_owned())
+}
+
+fn required_value(entry: &SearchEntry, attr: &str) -> Result<String> {
+    match first_value(entry, attr) {
+        Some(val) => Ok(val),
+        None => changelog_error(format!("{}: missing {}", entry.dn, attr)),
+    }
+}
+
+// Unfold continuation lines and split `name: value` pairs, decoding base64
+// values. Lines consisting of a lone `-` separate the parts of a Modify and are
+// returned as an empty name. Comments and empty lines are skipped.
+fn ldif_lines(text: &str) -> Result<Vec<(String, Vec<u8>)>> {
+    let mut lines: Vec<String> = vec![];
+    for line in text.lines() {
+        let line = line.trim_end_matches('\r');
+        match line.strip_prefix(' ') {
+            Some(cont) if !lines.is_empty() => lines.last_mut().expect("line").push_str(cont),
+            _ => lines.push(line.to_owned()),
+        }
+    }
+    let mut pairs = vec![];
+    for line in lines {
+        let line = line.trim_end_matches('\0');
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if line == "-" {
+            pairs.push((String::new(), vec![]));
+            continue;
+        }
+        let (name, rest) = match line.split_once(':') {
+            Some(pair) => pair,
+            None => return changelog_error(format!("malformed LDIF line: {}", line)),
+        };
+        let val = if let Some(b64) = rest.strip_prefix(':') {
+            base64_decode(b64.trim()).or_else(changelog_error)?
+        } else if rest.starts_with('<') {
+            return changelog_error(format!("URL value for {} not supported", name));
+        } else {
+            Vec::from(rest.trim_start_matches(' '))
+        };
+        pairs.push((name.trim().to_owned(), val));
+    }
+    Ok(pairs)
+}
+
+fn parse_add(dn: &str, changes: &str) -> Result<AddRequest> {
+    let mut attrs: Vec<(Vec<u8>, HashSet<Vec<u8>>)> = vec![];
+    for (name, val) in ldif_lines(changes)? {
+        if name.is_empty() {
+            continue;
+        }
+        match attrs
+            .iter_mut()
+            .find(|(attr, _)| attr.eq_ignore_ascii_case(name.as_bytes()))
+        {
+            Some((_, vals)) => {
+                vals.insert(val);
+            }
+            None => attrs.push((name.into_bytes(), vec![val].into_iter().collect())),
+        }
+    }
+    Ok(AddRequest {
+        dn: dn.to_owned(),
+        attrs,
+    })
+}
+
+fn parse_modify(dn: &str, changes: &str) -> Result<ModifyRequest> {
+    let mut mods = vec![];
+    let mut pairs = ldif_lines(changes)?.into_iter();
+    while let Some((op, attr)) = pairs.next() {
+        if op.is_empty() {
+            continue;
+        }
+        let mut vals = HashSet::new();
+        for (name, val) in pairs.by_ref() {
+            if name.is_empty() {
+                break;
+            }
+            if !name.as_bytes().eq_ignore_ascii_case(&attr) {
+                return changelog_error(format!(
+                    "{}: value of {} in the change of {}",
+                    dn,
+                    name,
+                    String::from_utf8_lossy(&attr)
+                ));
+            }
+            vals.insert(val);
+        }
+        mods.push(match op.to_ascii_lowercase().as_str() {
+            "add" => Mod::Add(attr, vals),
+            "delete" => Mod::Delete(attr, vals),
+            "replace" => Mod::Replace(attr, vals),
+            "increment" => match vals.into_iter().next() {
+                Some(val) => Mod::Increment(attr, val),
+                None => return changelog_error(format!("{}: increment without a value", dn)),
+            },
+            _ => return changelog_error(format!("{}: unknown modification {}", dn, op)),
+        });
+    }
+    Ok(ModifyRequest {
+        dn: dn.to_owned(),
+        mods,
+    })
+}
+
+impl ChangeEvent {
+    /// Parse a changelog entry retrieved with at least the attributes `changeNumber`,
+    /// `targetDN`, `changeType`, and, depending on the type, `changes` or the ModifyDN
+    /// parameters.
+    pub fn from_entry(entry: &SearchEntry) -> Result<ChangeEvent> {
+        let number = required_value(entry, "changeNumber")?;
+        let number = match number.trim().parse() {
+            Ok(number) => number,
+            Err(_) => return changelog_error(format!("{}: invalid change number", entry.dn)),
+        };
+        let dn = required_value(entry, "targetDN")?;
+        let ctype = required_value(entry, "changeType")?;
+        let change = match ctype.trim().to_ascii_lowercase().as_str() {
+            "add" => Change::Add(parse_add(&dn, &required_value(entry, "changes")?)?),
+            "delete" => Change::Delete(DeleteRequest { dn }),
+            "modify" => Change::Modify(parse_modify(&dn, &required_value(entry, "changes")?)?),
+            "modrdn" | "moddn" => {
+                let delete_old = first_value(entry, "deleteOldRDN").unwrap_or_default();
+                Change::ModifyDn(ModifyDnRequest {
+                    rdn: required_value(entry, "newRDN")?,
+                    delete_old: matches!(delete_old.trim(), "1" | "TRUE" | "true"),
+                    new_sup: first_value(entry, "newSuperior"),
+                    dn,
+                })
+            }
+            other => {
+                return changelog_error(format!("{}: unknown change type {}", entry.dn, other))
+            }
+        };
+        Ok(ChangeEvent {
+            number,
+            time: first_value(entry, "changeTime"),
+            change,
+        })
+    }
+}
+
+/// Reader of the changelog.
+///
+/// See the [module-level documentation](index.html) for the overview.
+#[derive(Clone, Debug)]
+pub struct ChangelogReader {
+    ldap: Ldap,
+    base: String,
+    checkpoint: u64,
+    batch: Option<u64>,
+}
+
+impl ChangelogReader {
+    /// Create a reader of the changelog under [`CHANGELOG_DN`](constant.CHANGELOG_DN.html),
+    /// starting from the first change.
+    pub fn new(ldap: Ldap) -> Self {
+        ChangelogReader {
+            ldap,
+            base: String::from(CHANGELOG_DN),
+            checkpoint: 0,
+            batch: None,
+        }
+    }
+
+    /// Read the changelog under `base`.
+    pub fn base(mut self, base: &str) -> Self {
+        self.base = base.to_owned();
+        self
+    }
+
+    /// Resume after the change numbered `checkpoint`.
+    pub fn start_after(mut self, checkpoint: u64) -> Self {
+        self.checkpoint = checkpoint;
+        self
+    }
+
+    /// Retrieve at most `size` changes in a single call. Without a batch size,
+    /// all changes above the checkpoint are retrieved at once.
+    pub fn batch_size(mut self, size: u64) -> Self {
+        self.batch = Some(size.max(1));
+        self
+    }
+
+    /// Number of the last change returned.
+    pub fn checkpoint(&self) -> u64 {
+        self.checkpoint
+    }
+
+    /// Return the first and the last change number available, as published in the
+    /// `firstChangeNumber` and `lastChangeNumber` attributes of the root DSE, or `None`
+    /// if the server doesn't publish them.
+    pub async fn bounds(&mut self) -> Result<Option<(u64, u64)>> {
+        let (rs, _res) = self
+            .ldap
+            .search(
+                "",
+                Scope::Base,
+                "(objectClass=*)",
+                vec!["firstChangeNumber", "lastChangeNumber"],
+            )
+            .await?
+            .success()?;
+        let dse = match rs.into_iter().next() {
+            Some(re) => SearchEntry::construct(re),
+            None => return Ok(None),
+        };
+        let number = |attr| first_value(&dse, attr).and_then(|v| v.trim().parse().ok());
+        Ok(number("firstChangeNumber").zip(number("lastChangeNumber")))
+    }
+
+    /// Move the checkpoint to the last change recorded, so that only the changes made
+    /// from now on are returned.
+    pub async fn skip_to_end(&mut self) -> Result<()> {
+        match self.bounds().await? {
+            Some((_, last)) => {
+                self.checkpoint = last;
+                Ok(())
+            }
+            None => changelog_error(String::from("change number bounds not published")),
+        }
+    }
+
+    /// Retrieve the changes above the checkpoint, up to the batch size, and advance the
+    /// checkpoint to the last one. The result is empty if there are no new changes.
+    pub async fn poll(&mut self) -> Result<Vec<ChangeEvent>> {
+        let next = self.checkpoint + 1;
+        let filter = match self.batch {
+            Some(size) => format!(
+                "(&(changeNumber>={})(changeNumber<={}))",
+                next,
+                next + size - 1
+            ),
+            None => format!("(changeNumber>={})", next),
+        };
+        let (rs, _res) = self
+            .ldap
+            .search(&self.base, Scope::OneLevel, &filter, CHANGE_ATTRS.to_vec())
+            .await?
+            .success()?;
+        let mut events = rs
+            .into_iter()
+            .map(|re| ChangeEvent::from_entry(&SearchEntry::construct(re)))
+            .collect::<Result<Vec<_>>>()?;
+        events.retain(|ev| ev.number >= next);
+        events.sort_by_key(|ev| ev.number);
+        if let Some(last) = events.last() {
+            self.checkpoint = last.number;
+        }
+        Ok(events)
+    }
+
+    /// Poll the changelog every `interval` until there are new changes, and return them.
+    pub async fn tail(&mut self, interval: Duration) -> Result<Vec<ChangeEvent>> {
+        loop {
+            let events = self.poll().await?;
+            if !events.is_empty() {
+                return Ok(events);
+            }
+            time::sleep(interval).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_add, parse_modify};
+    use crate::ldap::Mod;
+    use maplit::hashset;
+
+    #[test]
+    fn changes() {
+        let add = parse_add(
+            "cn=x,dc=example,dc=org",
+            "objectClass: top\nobjectClass: person\ncn: x\nsn:: w6lz\ndescription: fo\n o\n",
+        )
+        .unwrap();
+        assert_eq!(add.attrs.len(), 4);
+        assert_eq!(add.attrs[0].1.len(), 2);
+        assert_eq!(add.attrs[2].1, hashset! { "és".as_bytes().to_vec() });
+        assert_eq!(add.attrs[3].1, hashset! { b"foo".to_vec() });
+        let modify = parse_modify(
+            "cn=x,dc=example,dc=org",
+            "replace: description\ndescription: one\ndescription: two\n-\ndelete: mail\n-\n",
+        )
+        .unwrap();
+        assert_eq!(
+            modify.mods,
+            vec![
+                Mod::Replace(
+                    b"description".to_vec(),
+                    hashset! { b"one".to_vec(), b"two".to_vec() }
+                ),
+                Mod::Delete(b"mail".to_vec(), hashset! {}),
+            ]
+        );
+        assert!(parse_modify("cn=x", "replace: cn\nsn: y\n").is_err());
+    }
+}
+
+#[cfg(all(test, feature = "testkit"))]
+mod mock_test {
+    use super::{Change, ChangelogReader};
+    use crate::testkit::MockServer;
+
+    #[tokio::test]
+    async fn changelog_poll() {
+        let server = MockServer::new();
+        server.add_entry("cn=changelog", vec![("objectClass", vec!["nsContainer"])]);
+        server.add_entry(
+            "changeNumber=1,cn=changelog",
+            vec![
+                ("changeNumber", vec!["1"]),
+                ("targetDN", vec!["uid=a,dc=example,dc=org"]),
+                ("changeType", vec!["add"]),
+                ("changes", vec!["objectClass: account\nuid: a\n"]),
+            ],
+        );
+        server.add_entry(
+            "changeNumber=2,cn=changelog",
+            vec![
+                ("changeNumber", vec!["2"]),
+                ("targetDN", vec!["uid=a,dc=example,dc=org"]),
+                ("changeType", vec!["modrdn"]),
+                ("newRDN", vec!["uid=b"]),
+                ("deleteOldRDN", vec!["TRUE"]),
+            ],
+        );
+        let (conn, ldap) = server.connect();
+        crate::drive!(conn);
+        let mut reader = ChangelogReader::new(ldap.clone()).batch_size(1);
+        let events = reader.poll().await.unwrap();
+        assert_eq!(events.len(), 1);
+        assert!(matches!(events[0].change, Change::Add(ref req) if req.attrs.len() == 2));
+        let events = reader.poll().await.unwrap();
+        match events[0].change {
+            Change::ModifyDn(ref req) => assert!(req.rdn == "uid=b" && req.delete_old),
+            ref other => panic!("unexpected change {:?}", other),
+        }
+        assert_eq!(reader.checkpoint(), 2);
+        assert!(reader.poll().await.unwrap().is_empty());
+        let mut reader = ChangelogReader::new(ldap).start_after(1);
+        assert_eq!(reader.poll().await.unwrap()[0].number, 2);
+    }
+}
@@ -0,0 +1,373 @@
+//! Connectionless LDAP (CLDAP) ping for Active Directory domain controllers.
+//!
+//! Windows clients locate a domain controller by sending a Search request over UDP to
+//! candidate servers. The search is for the root DSE, with a filter containing the DNS
+//! domain name and the requested response version (`NtVer`), and asks for a single
+//! attribute, `Netlogon`. The server answers with a binary structure describing its
+//! capabilities, the forest and domain it belongs to, and the AD sites of both the
+//! server and the client. That information determines whether the server is usable
+//! and how close it is to the client.
+//!
+//! [`ping()`](fn.ping.html) performs this exchange with a single server and decodes
+//! the response into [`NetlogonInfo`](struct.NetlogonInfo.html). The request is sent
+//! once; retransmission, if desired, is left to the caller.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! # use std::time::Duration;
+//! # use ldap3::result::Result;
+//! # #[tokio::main]
+//! # async fn main() -> Result<()> {
+//! let info = ldap3::cldap::ping("dc1.example.com:389", "example.com", Duration::from_secs(2)).await?;
+//! println!("{} in site {}, closest: {}", info.dns_host_name, info.dc_site_name, info.is_closest());
+//! # Ok(())
+//! # }
+//! ```
+
+use std::io;
+use std::time::Duration;
+
+use crate::dns::Reader;
+use crate::filter::parse as parse_filter;
+use crate::protocol::LdapCodec;
+use crate::result::{LdapError, LdapResult, Result};
+use crate::search::{ResultEntry, SearchEntry};
+use crate::util::ldap_escape;
+
+use lber::common::TagClass;
+use lber::structures::{Boolean, Enumerated, Integer, OctetString, Sequence, Tag};
+
+use bytes::BytesMut;
+use tokio::net::{lookup_host, ToSocketAddrs, UdpSocket};
+use tokio::time;
+use tokio_util::codec::{Decoder, Encoder};
+
+/// The server is the PDC of the domain.
+pub const DS_PDC_FLAG: u32 = 0x0000_0001;
+/// The server is a Global Catalog.
+pub const DS_GC_FLAG: u32 = 0x0000_0004;
+/// The server supports LDAP.
+pub const DS_LDAP_FLAG: u32 = 0x0000_0008;
+/// The server is a directory service server.
+pub const DS_DS_FLAG: u32 = 0x0000_0010;
+/// The server is a Kerberos KDC.
+pub const DS_KDC_FLAG: u32 = 0x0000_0020;
+/// The server runs a time service.
+pub const DS_TIMESERV_FLAG: u32 = 0x0000_0040;
+/// The server is in the site closest to the client.
+pub const DS_CLOSEST_FLAG: u32 = 0x0000_0080;
+/// The server has a writable copy of the directory.
+pub const DS_WRITABLE_FLAG: u32 = 0x0000_0100;
+/// The server has a reliable time source.
+pub const DS_GOOD_TIMESERV_FLAG: u32 = 0x0000_0200;
+/// The naming context is an application NC.
+pub const DS_NDNC_FLAG: u32 = 0x0000_0400;
+/// The server is a read-only domain controller.
+pub const DS_SELECT_SECRET_DOMAIN_6_FLAG: u32 = 0x0000_0800;
+/// The server is a writable domain controller, Windows Server 2008 or later.
+pub const DS_FULL_SECRET_DOMAIN_6_FLAG: u32 = 0x0000_1000;
+/// The server runs Active Directory Web Services.
+pub const DS_WS_FLAG: u32 = 0x0000_2000;
+/// The server runs Windows Server 2012 or later.
+pub const DS_DS_8_FLAG: u32 = 0x0000_4000;
+/// The server runs Windows Server 2012 R2 or later.
+pub const DS_DS_9_FLAG: u32 = 0x0000_8000;
+/// The server runs Windows Server 2016 or later.
+pub const DS_DS_10_FLAG: u32 = 0x0001_0000;
+
+// Response opcodes
+const LOGON_SAM_LOGON_RESPONSE_EX: u16 = 23;
+const LOGON_SAM_USER_UNKNOWN_EX: u16 = 25;
+
+// Requested response version: NETLOGON_NT_VERSION_5 | NETLOGON_NT_VERSION_5EX
+const NT_VERSION: &str = "\\06\\00\\00\\00";
+const NETLOGON_NT_VERSION_5EP: u32 = 0x0000_0004;
+const NETLOGON_NT_VERSION_VCS: u32 = 0x0000_0010;
+
+const MAX_DATAGRAM: usize = 65536;
+
+/// Decoded `NETLOGON_SAM_LOGON_RESPONSE_EX` structure.
+///
+/// The string fields are empty if the server didn't provide a value.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NetlogonInfo {
+    /// Server capability flags, a combination of the `DS_*_FLAG` constants.
+    pub flags: u32,
+    /// GUID of the domain, in its binary form.
+    pub domain_guid: [u8; 16],
+    /// DNS name of the forest.
+    pub dns_forest_name: String,
+    /// DNS name of the domain.
+    pub dns_domain_name: String,
+    /// DNS name of the server.
+    pub dns_host_name: String,
+    /// NetBIOS name of the domain.
+    pub netbios_domain_name: String,
+    /// NetBIOS name of the server.
+    pub netbios_computer_name: String,
+    /// User name from the request, if any.
+    pub user_name: String,
+    /// Site of the server.
+    pub dc_site_name: String,
+    /// Site of the client, as determined by the server from the client address.
+    pub client_site_name: String,
+    /// Next closest site, if returned by the server.
+    pub next_closest_site_name: Option<String>,
+    /// Version of the response structure.
+    pub nt_version: u32,
+    /// `true` if the server indicated that the requested user is unknown.
+    pub user_unknown: bool,
+}
+
+impl NetlogonInfo {
+    /// Decode the value of the `Netlogon` attribute.
+    pub fn parse(buf: &[u8]) -> Result<NetlogonInfo> {
+        Self::decode(buf).ok_or(LdapError::NetlogonDecoding)
+    }
+
+    fn decode(buf: &[u8]) -> Option<NetlogonInfo> {
+        let mut rd = Reader::new(buf);
+        let user_unknown = match rd.u16_le()? {
+            LOGON_SAM_LOGON_RESPONSE_EX => false,
+            LOGON_SAM_USER_UNKNOWN_EX => true,
+            _ => return None,
+        };
+        let _sbz = rd.u16_le()?;
+        let flags = rd.u32_le()?;
+        let mut domain_guid = [0; 16];
+        domain_guid.copy_from_slice(rd.bytes(16)?);
+        let dns_forest_name = rd.name()?;
+        let dns_domain_name = rd.name()?;
+        let dns_host_name = rd.name()?;
+        let netbios_domain_name = rd.name()?;
+        let netbios_computer_name = rd.name()?;
+        let user_name = rd.name()?;
+        let dc_site_name = rd.name()?;
+        let client_site_name = rd.name()?;
+        // The version is at a fixed offset from the end, and determines
+        // which of the optional fields are present.
+        if buf.len() < rd.pos() + 8 {
+            return None;
+        }
+        let nt_version = Reader::at(buf, buf.len() - 8).u32_le()?;
+        if nt_version & NETLOGON_NT_VERSION_5EP != 0 {
+            let size = rd.bytes(1)?[0] as usize;
+            rd.bytes(size)?;
+        }
+        let next_closest_site_name = if nt_version & NETLOGON_NT_VERSION_VCS != 0 {
+            Some(rd.name()?)
+        } else {
+            None
+        };
+        Some(NetlogonInfo {
+            flags,
+            domain_guid,
+            dns_forest_name,
+            dns_domain_name,
+            dns_host_name,
+            netbios_domain_name,
+            netbios_computer_name,
+            user_name,
+            dc_site_name,
+            client_site_name,
+            next_closest_site_name,
+            nt_version,
+            user_unknown,
+        })
+    }
+
+    /// Check whether all bits of `flag` are set in the capability flags.
+    pub fn has_flag(&self, flag: u32) -> bool {
+        self.flags & flag == flag
+    }
+
+    /// The server is in the site closest to the client.
+    pub fn is_closest(&self) -> bool {
+        self.has_flag(DS_CLOSEST_FLAG)
+    }
+
+    /// The server has a writable copy of the directory.
+    pub fn is_writable(&self) -> bool {
+        self.has_flag(DS_WRITABLE_FLAG)
+    }
+
+    /// The server is a Global Catalog.
+    pub fn is_gc(&self) -> bool {
+        self.has_flag(DS_GC_FLAG)
+    }
+
+    /// The server is the PDC of the domain.
+    pub fn is_pdc(&self) -> bool {
+        self.has_flag(DS_PDC_FLAG)
+    }
+}
+
+fn netlogon_request(domain: &str) -> Result<Tag> {
+    let filter = if domain.is_empty() {
+        format!("(NtVer={})", NT_VERSION)
+    } else {
+        format!(
+            "(&(DnsDomain={})(NtVer={}))",
+            ldap_escape(domain),
+            NT_VERSION
+        )
+    };
+    Ok(Tag::Sequence(Sequence {
+        id: 3,
+        class: TagClass::Application,
+        inner: vec![
+            Tag::OctetString(OctetString {
+                inner: vec![],
+                ..Default::default()
+            }),
+            Tag::Enumerated(Enumerated {
+                inner: 0,
+                ..Default::default()
+            }),
+            Tag::Enumerated(Enumerated {
+                inner: 0,
+                ..Default::default()
+            }),
+            Tag::Integer(Integer {
+                inner: 0,
+                ..Default::default()
+            }),
+            Tag::Integer(Integer {
+                inner: 0,
+                ..Default::default()
+            }),
+            Tag::Boolean(Boolean {
+                inner: false,
+                ..Default::default()
+            }),
+            parse_filter(&filter).map_err(|_| LdapError::FilterParsing)?,
+            Tag::Sequence(Sequence {
+                inner: vec![Tag::OctetString(OctetString {
+                    inner: Vec::from("Netlogon"),
+                    ..Default::default()
+                })],
+                ..Default::default()
+            }),
+        ],
+    }))
+}
+
+/// Send a CLDAP ping for `domain` to the server at `addr`, and decode the response.
+///
+/// If `addr` resolves to several addresses, only the first one is used. The `domain`
+/// can be empty, in which case the server responds for its own domain. If there is no
+/// response before `timeout` expires, a timeout error is returned.
+pub async fn ping<A: ToSocketAddrs>(
+    addr: A,
+    domain: &str,
+    timeout: Duration,
+) -> Result<NetlogonInfo> {
+    let addr = lookup_host(addr)
+        .await?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::AddrNotAvailable, "no address"))?;
+    let local = if addr.is_ipv4() {
+        "0.0.0.0:0"
+    } else {
+        "[::]:0"
+    };
+    let socket = UdpSocket::bind(local).await?;
+    socket.connect(addr).await?;
+    let mut codec = LdapCodec::default();
+    let mut out = BytesMut::new();
+    let msgid = 1;
+    codec.encode((msgid, netlogon_request(domain)?, None), &mut out)?;
+    socket.send(&out).await?;
+    time::timeout(timeout, receive(&socket, &mut codec, msgid)).await?
+}
+
+async fn receive(socket: &UdpSocket, codec: &mut LdapCodec, msgid: i32) -> Result<NetlogonInfo> {
+    let mut netlogon = None;
+    let mut datagram = vec![0; MAX_DATAGRAM];
+    loop {
+        let len = socket.recv(&mut datagram).await?;
+        let mut buf = BytesMut::from(&datagram[..len]);
+        while let Some((id, (tag, ctrls))) = codec.decode(&mut buf)? {
+            if id != msgid {
+                continue;
+            }
+            match tag {
+                Tag::StructureTag(t) if t.class == TagClass::Application && t.id == 4 => {
+                    let SearchEntry {
+                        attrs, bin_attrs, ..
+                    } = SearchEntry::construct(ResultEntry(t, ctrls));
+                    let is_netlogon = |attr: &String| attr.eq_ignore_ascii_case("netlogon");
+                    netlogon = bin_attrs
+                        .into_iter()
+                        .find(|(attr, _)| is_netlogon(attr))
+                        .and_then(|(_, mut vals)| vals.pop())
+                        .or_else(|| {
+                            attrs
+                                .into_iter()
+                                .find(|(attr, _)| is_netlogon(attr))
+                                .and_then(|(_, mut vals)| vals.pop())
+                                .map(String::into_bytes)
+                        });
+                }
+                tag => {
+                    LdapResult::from(tag).success()?;
+                    return match netlogon {
+                        Some(netlogon) => NetlogonInfo::parse(&netlogon),
+                        None => Err(LdapError::NetlogonDecoding),
+                    };
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_response() {
+        let mut buf = vec![];
+        buf.extend(&LOGON_SAM_LOGON_RESPONSE_EX.to_le_bytes());
+        buf.extend(&[0, 0]);
+        buf.extend(&(DS_GC_FLAG | DS_CLOSEST_FLAG | DS_WRITABLE_FLAG).to_le_bytes());
+        buf.extend(&[0x11; 16]);
+        // forest: example.com
+        buf.extend(b"\x07example\x03com\x00");
+        // domain: pointer to forest at offset 24
+        buf.extend(&[0xc0, 24]);
+        // host: dc1 + pointer to forest
+        buf.extend(b"\x03dc1\xc0\x18");
+        buf.extend(b"\x07EXAMPLE\x00");
+        buf.extend(b"\x03DC1\x00");
+        buf.extend(b"\x00");
+        buf.extend(b"\x07Default\x00");
+        // client site: pointer to server site
+        let site = (buf.len() - 9) as u8;
+        buf.extend(&[0xc0, site]);
+        buf.extend(&0x0000_0006u32.to_le_bytes());
+        buf.extend(&[0xff, 0xff, 0xff, 0xff]);
+        let info = NetlogonInfo::parse(&buf).unwrap();
+        assert_eq!(info.dns_forest_name, "example.com");
+        assert_eq!(info.dns_domain_name, "example.com");
+        assert_eq!(info.dns_host_name, "dc1.example.com");
+        assert_eq!(info.netbios_domain_name, "EXAMPLE");
+        assert_eq!(info.netbios_computer_name, "DC1");
+        assert_eq!(info.user_name, "");
+        assert_eq!(info.dc_site_name, "Default");
+        assert_eq!(info.client_site_name, "Default");
+        assert_eq!(info.next_closest_site_name, None);
+        assert!(info.is_closest() && info.is_gc() && info.is_writable() && !info.is_pdc());
+    }
+
+    #[test]
+    fn reject_pointer_loop() {
+        let mut buf = vec![];
+        buf.extend(&LOGON_SAM_LOGON_RESPONSE_EX.to_le_bytes());
+        buf.extend(&[0; 22]);
+        buf.extend(&[0xc0, 24]);
+        buf.extend(&[0; 8]);
+        assert!(NetlogonInfo::parse(&buf).is_err());
+    }
+}
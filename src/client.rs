@@ -0,0 +1,329 @@
+//! Object-safe traits for the operation surface of `Ldap` and `LdapConn`.
+//!
+//! Application code which performs LDAP operations through a concrete handle can't
+//! be tested without a server. If it's instead written against
+//! [`LdapClient`](trait.LdapClient.html), for the asynchronous interface, or
+//! [`LdapClientSync`](trait.LdapClientSync.html), for the synchronous one, a test
+//! can substitute its own implementation of the trait. Both traits are object-safe,
+//! and can be used as `&mut dyn LdapClient` or `Box<dyn LdapClient>`.
+//!
+//! Since generic methods would make the traits unusable as objects, parameters which are
+//! generic in the inherent methods have concrete types here: attribute values are byte
+//! vectors, and extended operations are passed as a generic [`Exop`](../exop/struct.Exop.html).
+//! Controls, timeouts and search options must be set on the concrete handle.
+//!
+//! The asynchronous trait uses the `async_trait` proc macro; implementing it requires
+//! the `async-trait` crate.
+
+use std::collections::HashSet;
+
+use crate::exop::Exop;
+use crate::ldap::{Ldap, Mod};
+use crate::result::{CompareResult, ExopResult, LdapResult, Result, SearchResult};
+use crate::search::Scope;
+#[cfg(feature = "sync")]
+use crate::sync::LdapConn;
+
+use async_trait::async_trait;
+
+/// Asynchronous LDAP operations.
+///
+/// The methods have the same meaning as the identically named methods of
+/// [`Ldap`](../struct.Ldap.html).
+#[async_trait]
+pub trait LdapClient: Send {
+    /// Do a simple Bind.
+    async fn simple_bind(&mut self, bind_dn: &str, bind_pw: &str) -> Result<LdapResult>;
+
+    /// Do a SASL EXTERNAL Bind.
+    async fn sasl_external_bind(&mut self) -> Result<LdapResult>;
+
+    /// Perform a Search, returning all results at once.
+    async fn search(
+        &mut self,
+        base: &str,
+        scope: Scope,
+        filter: &str,
+        attrs: Vec<String>,
+    ) -> Result<SearchResult>;
+
+    /// Add an entry.
+    async fn add(
+        &mut self,
+        dn: &str,
+        attrs: Vec<(Vec<u8>, HashSet<Vec<u8>>)>,
+    ) -> Result<LdapResult>;
+
+    /// Compare an attribute value.
+    async fn compare(&mut self, dn: &str, attr: &str, val: &[u8]) -> Result<CompareResult>;
+
+    /// Delete an entry.
+    async fn delete(&mut self, dn: &str) -> Result<LdapResult>;
+
+    /// Modify an entry.
+    async fn modify(&mut self, dn: &str, mods: Vec<Mod<Vec<u8>>>) -> Result<LdapResult>;
+
+    /// Rename and/or move an entry.
+    async fn modifydn(
+        &mut self,
+        dn: &str,
+        rdn: &str,
+        delete_old: bool,
+        new_sup: Option<&str>,
+    ) -> Result<LdapResult>;
+
+    /// Perform an Extended operation.
+    async fn extended(&mut self, exop: Exop) -> Result<ExopResult>;
+
+    /// Terminate the connection.
+    async fn unbind(&mut self) -> Result<()>;
+}
+
+#[async_trait]
+impl LdapClient for Ldap {
+    async fn simple_bind(&mut self, bind_dn: &str, bind_pw: &str) -> Result<LdapResult> {
+        Ldap::simple_bind(self, bind_dn, bind_pw).await
+    }
+
+    async fn sasl_external_bind(&mut self) -> Result<LdapResult> {
+        Ldap::sasl_external_bind(self).await
+    }
+
+    async fn search(
+        &mut self,
+        base: &str,
+        scope: Scope,
+        filter: &str,
+        attrs: Vec<String>,
+    ) -> Result<SearchResult> {
+        Ldap::search(self, base, scope, filter, attrs).await
+    }
+
+    async fn add(
+        &mut self,
+        dn: &str,
+        attrs: Vec<(Vec<u8>, HashSet<Vec<u8>>)>,
+    ) -> Result<LdapResult> {
+        Ldap::add(self, dn, attrs).await
+    }
+
+    async fn compare(&mut self, dn: &str, attr: &str, val: &[u8]) -> Result<CompareResult> {
+        Ldap::compare(self, dn, attr, val).await
+    }
+
+    async fn delete(&mut self, dn: &str) -> Result<LdapResult> {
+        Ldap::delete(self, dn).await
+    }
+
+    async fn modify(&mut self, dn: &str, mods: Vec<Mod<Vec<u8>>>) -> Result<LdapResult> {
+        Ldap::modify(self, dn, mods).await
+    }
+
+    async fn modifydn(
+        &mut self,
+        dn: &str,
+        rdn: &str,
+        delete_old: bool,
+        new_sup: Option<&str>,
+    ) -> Result<LdapResult> {
+        Ldap::modifydn(self, dn, rdn, delete_old, new_sup).await
+    }
+
+    async fn extended(&mut self, exop: Exop) -> Result<ExopResult> {
+        Ldap::extended(self, exop).await
+    }
+
+    async fn unbind(&mut self) -> Result<()> {
+        Ldap::unbind(self).await
+    }
+}
+
+/// Synchronous LDAP operations.
+///
+/// The methods have the same meaning as the identically named methods of
+/// [`LdapConn`](../struct.LdapConn.html).
+pub trait LdapClientSync {
+    /// Do a simple Bind.
+    fn simple_bind(&mut self, bind_dn: &str, bind_pw: &str) -> Result<LdapResult>;
+
+    /// Do a SASL EXTERNAL Bind.
+    fn sasl_external_bind(&mut self) -> Result<LdapResult>;
+
+    /// Perform a Search, returning all results at once.
+    fn search(
+        &mut self,
+        base: &str,
+        scope: Scope,
+        filter: &str,
+        attrs: Vec<String>,
+    ) -> Result<SearchResult>;
+
+    /// Add an entry.
+    fn add(&mut self, dn: &str, attrs: Vec<(Vec<u8>, HashSet<Vec<u8>>)>) -> Result<LdapResult>;
+
+    /// Compare an attribute value.
+    fn compare(&mut self, dn: &str, attr: &str, val: &[u8]) -> Result<CompareResult>;
+
+    /// Delete an entry.
+    fn delete(&mut self, dn: &str) -> Result<LdapResult>;
+
+    /// Modify an entry.
+    fn modify(&mut self, dn: &str, mods: Vec<Mod<Vec<u8>>>) -> Result<LdapResult>;
+
+    /// Rename and/or move an entry.
+    fn modifydn(
+        &mut self,
+        dn: &str,
+        rdn: &str,
+        delete_old: bool,
+        new_sup: Option<&str>,
+    ) -> Result<LdapResult>;
+
+    /// Perform an Extended operation.
+    fn extended(&mut self, exop: Exop) -> Result<ExopResult>;
+
+    /// Terminate the connection.
+    fn unbind(&mut self) -> Result<()>;
+}
+
+#[cfg(feature = "sync")]
+impl LdapClientSync for LdapConn {
+    fn simple_bind(&mut self, bind_dn: &str, bind_pw: &str) -> Result<LdapResult> {
+        LdapConn::simple_bind(self, bind_dn, bind_pw)
+    }
+
+    fn sasl_external_bind(&mut self) -> Result<LdapResult> {
+        LdapConn::sasl_external_bind(self)
+    }
+
+    fn search(
+        &mut self,
+        base: &str,
+        scope: Scope,
+        filter: &str,
+        attrs: Vec<String>,
+    ) -> Result<SearchResult> {
+        LdapConn::search(self, base, scope, filter, attrs)
+    }
+
+    fn add(&mut self, dn: &str, attrs: Vec<(Vec<u8>, HashSet<Vec<u8>>)>) -> Result<LdapResult> {
+        LdapConn::add(self, dn, attrs)
+    }
+
+    fn compare(&mut self, dn: &str, attr: &str, val: &[u8]) -> Result<CompareResult> {
+        LdapConn::compare(self, dn, attr, val)
+    }
+
+    fn delete(&mut self, dn: &str) -> Result<LdapResult> {
+        LdapConn::delete(self, dn)
+    }
+
+    fn modify(&mut self, dn: &str, mods: Vec<Mod<Vec<u8>>>) -> Result<LdapResult> {
+        LdapConn::modify(self, dn, mods)
+    }
+
+    fn modifydn(
+        &mut self,
+        dn: &str,
+        rdn: &str,
+        delete_old: bool,
+        new_sup: Option<&str>,
+    ) -> Result<LdapResult> {
+        LdapConn::modifydn(self, dn, rdn, delete_old, new_sup)
+    }
+
+    fn extended(&mut self, exop: Exop) -> Result<ExopResult> {
+        LdapConn::extended(self, exop)
+    }
+
+    fn unbind(&mut self) -> Result<()> {
+        LdapConn::unbind(self)
+    }
+}
+
+#[cfg(all(test, feature = "testkit"))]
+mod test {
+    use super::LdapClient;
+    #[cfg(feature = "sync")]
+    use super::LdapClientSync;
+    use crate::result::Result;
+    use crate::testkit::MockServer;
+    #[cfg(feature = "sync")]
+    use crate::LdapConn;
+    use crate::Scope;
+
+    use maplit::hashset;
+
+    // Application code written against the trait.
+    async fn register(client: &mut dyn LdapClient, uid: &str) -> Result<bool> {
+        let dn = format!("uid={},dc=example,dc=org", uid);
+        let res = client
+            .add(
+                &dn,
+                vec![(b"uid".to_vec(), hashset! { uid.as_bytes().to_vec() })],
+            )
+            .await?;
+        if res.rc == 68 {
+            return Ok(false);
+        }
+        res.success()?;
+        Ok(true)
+    }
+
+    fn server() -> MockServer {
+        let server = MockServer::new();
+        server.add_entry("dc=example,dc=org", vec![("dc", vec!["example"])]);
+        server
+    }
+
+    #[tokio::test]
+    async fn async_client() {
+        let server = server();
+        let (conn, ldap) = server.connect();
+        crate::drive!(conn);
+        let mut client: Box<dyn LdapClient> = Box::new(ldap);
+        assert!(register(client.as_mut(), "test").await.unwrap());
+        assert!(!register(client.as_mut(), "test").await.unwrap());
+        let (entries, _) = client
+            .search("dc=example,dc=org", Scope::OneLevel, "(uid=*)", vec![])
+            .await
+            .unwrap()
+            .success()
+            .unwrap();
+        assert_eq!(entries.len(), 1);
+        let res = client
+            .compare("uid=test,dc=example,dc=org", "uid", b"test")
+            .await
+            .unwrap();
+        assert!(res.equal().unwrap());
+        client.unbind().await.unwrap();
+    }
+
+    #[cfg(feature = "sync")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn sync_client() {
+        let server = server();
+        let url = server.listen().await;
+        tokio::task::spawn_blocking(move || {
+            let mut client: Box<dyn LdapClientSync> = Box::new(LdapConn::new(&url).unwrap());
+            client
+                .add(
+                    "uid=test,dc=example,dc=org",
+                    vec![(b"uid".to_vec(), hashset! { b"test".to_vec() })],
+                )
+                .unwrap()
+                .success()
+                .unwrap();
+            let res = client.delete("uid=missing,dc=example,dc=org").unwrap();
+            assert_eq!(res.rc, 32);
+            client
+                .delete("uid=test,dc=example,dc=org")
+                .unwrap()
+                .success()
+                .unwrap();
+            client.unbind().unwrap();
+        })
+        .await
+        .unwrap();
+    }
+}
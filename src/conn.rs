@@ -1,39 +1,72 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::io::Write;
 #[cfg(feature = "tls-rustls")]
 use std::net::IpAddr;
+use std::net::SocketAddr;
+#[cfg(any(feature = "tls-native", feature = "tls-rustls"))]
+use std::path::{Path, PathBuf};
 use std::pin::Pin;
 #[cfg(feature = "tls-rustls")]
 use std::str::FromStr;
+use std::sync::atomic::AtomicBool;
 use std::sync::{Arc, Mutex};
 use std::task::{Context, Poll};
 use std::time::Duration;
 
+#[cfg(feature = "audit")]
+use crate::audit::{AuditSink, AuditTracker};
+use crate::controls::Control;
 #[cfg(any(feature = "tls-native", feature = "tls-rustls"))]
 use crate::exop_impl::StartTLS;
-use crate::ldap::Ldap;
-use crate::protocol::{ItemSender, LdapCodec, LdapOp, MaybeControls, ResultSender};
-use crate::result::{LdapError, Result};
+use crate::exop_impl::{construct_exop, WhoAmI};
+use crate::intercept::{Interceptor, Interceptors};
+use crate::ldap::{next_msgid, BoundIdentity, Ldap, Priority, Shared};
+#[cfg(feature = "metrics")]
+use crate::metrics::{MetricsSink, OpTracker, SlowOp, SlowOpCallback};
+#[cfg(feature = "observer")]
+use crate::observer::ConnObserver;
+#[cfg(any(feature = "metrics", feature = "audit", feature = "observer"))]
+use crate::protocol::result_code;
+use crate::protocol::{
+    BerDump, BerDumpFormat, ItemSender, LdapCodec, LdapOp, MaybeControls, ResultSender, StreamItem,
+};
+use crate::request::OpKind;
+use crate::resolve::Resolver;
+use crate::result::{ExopResult, LdapError, LdapResultExt, Result};
+#[cfg(any(feature = "tls-native", feature = "tls-rustls"))]
+use crate::revocation;
 use crate::search::SearchItem;
+use crate::tcp;
 use crate::RequestId;
 
-use lber::structures::{Null, Tag};
+use lber::common::TagClass;
+use lber::structures::{Null, Sequence, Tag};
 
-#[cfg(any(feature = "tls-native", feature = "tls-rustls"))]
+#[cfg(any(
+    all(any(unix, windows), feature = "ldapi"),
+    feature = "tls-native",
+    feature = "tls-rustls"
+))]
 use futures_util::future::TryFutureExt;
 use futures_util::sink::SinkExt;
 #[cfg(feature = "tls-native")]
-use native_tls::TlsConnector;
-#[cfg(unix)]
+use native_tls::{Certificate, Protocol, TlsConnector};
+#[cfg(all(any(unix, windows), feature = "ldapi"))]
 use percent_encoding::percent_decode;
 #[cfg(feature = "tls-rustls")]
-use rustls::ClientConfig;
+use rustls::internal::pemfile;
+#[cfg(feature = "tls-rustls")]
+use rustls::{Certificate, ClientConfig, ProtocolVersion, Session, SupportedCipherSuite};
 use tokio::io::{self, AsyncRead, AsyncWrite, AsyncWriteExt, ReadBuf};
+#[cfg(all(windows, feature = "ldapi"))]
+use tokio::net::windows::named_pipe::ClientOptions;
 use tokio::net::TcpStream;
-#[cfg(unix)]
+#[cfg(all(unix, feature = "ldapi"))]
 use tokio::net::UnixStream;
-use tokio::sync::mpsc;
-#[cfg(any(feature = "tls-native", feature = "tls-rustls"))]
+use tokio::sync::mpsc::error::TrySendError;
 use tokio::sync::oneshot;
+use tokio::sync::{mpsc, watch};
 use tokio::time;
 #[cfg(all(feature = "tls-native", not(feature = "tls-rustls")))]
 use tokio_native_tls::{TlsConnector as TokioTlsConnector, TlsStream};
@@ -45,13 +78,197 @@ compile_error!(r#"Only one of "tls-native" and "tls-rustls" may be enabled for T
 use tokio_util::codec::{Decoder, Framed};
 use url::{self, Url};
 
-#[derive(Debug)]
 enum ConnType {
     Tcp(TcpStream),
     #[cfg(any(feature = "tls-native", feature = "tls-rustls"))]
     Tls(TlsStream<TcpStream>),
-    #[cfg(unix)]
+    #[cfg(all(unix, feature = "ldapi"))]
     Unix(UnixStream),
+    Stream(Box<dyn AsyncStream>),
+}
+
+/// Bidirectional byte stream usable as the transport of an LDAP connection.
+///
+/// Automatically implemented for every type satisfying the trait bounds.
+pub trait AsyncStream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<S: AsyncRead + AsyncWrite + Unpin + Send> AsyncStream for S {}
+
+fn is_intermediate(tag: &Tag) -> bool {
+    matches!(tag, Tag::StructureTag(t) if t.class == TagClass::Application && t.id == 25)
+}
+
+/// Parameters of an established TLS session.
+///
+/// The values are those reported by the TLS library, in its own notation. The native TLS
+/// backend doesn't report the protocol version and the cipher suite, so they're always
+/// `None` with it; it also gives access only to the server's own certificate, not the
+/// whole chain.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TlsInfo {
+    /// Negotiated protocol version, such as `"TLSv1_3"`.
+    pub version: Option<String>,
+    /// Negotiated cipher suite, such as `"TLS13_AES_256_GCM_SHA384"`.
+    pub cipher: Option<String>,
+    /// DER-encoded certificates presented by the server, starting with the server's
+    /// own certificate.
+    pub peer_certificates: Vec<Vec<u8>>,
+}
+
+#[cfg(feature = "tls-native")]
+fn tls_info(stream: &TlsStream<TcpStream>) -> TlsInfo {
+    let peer_certificates = match stream.get_ref().peer_certificate() {
+        Ok(Some(cert)) => cert.to_der().into_iter().collect(),
+        _ => vec![],
+    };
+    TlsInfo {
+        peer_certificates,
+        ..Default::default()
+    }
+}
+
+#[cfg(feature = "tls-rustls")]
+fn tls_info(stream: &TlsStream<TcpStream>) -> TlsInfo {
+    let (_, session) = stream.get_ref();
+    TlsInfo {
+        version: session.get_protocol_version().map(|v| format!("{:?}", v)),
+        cipher: session
+            .get_negotiated_ciphersuite()
+            .map(|cs| format!("{:?}", cs.suite)),
+        peer_certificates: session
+            .get_peer_certificates()
+            .map(|certs| certs.into_iter().map(|cert| cert.0).collect())
+            .unwrap_or_default(),
+    }
+}
+
+/// TLS protocol version, for setting the minimum acceptable version with
+/// [`LdapConnSettings::set_min_tls_version()`](struct.LdapConnSettings.html#method.set_min_tls_version).
+///
+/// Older versions are deprecated, and not offered by the crate's TLS setup; a
+/// custom connector must be used to enable them with native TLS.
+#[cfg(any(feature = "tls-native", feature = "tls-rustls"))]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TlsVersion {
+    /// TLS 1.2.
+    #[default]
+    Tls12,
+    /// TLS 1.3.
+    Tls13,
+}
+
+/// Use of StartTLS on __ldap__ connections.
+///
+/// Whatever the policy, a failed TLS handshake always fails the connection, since
+/// the state of the protocol stream is undefined afterwards. The policies differ in
+/// what happens if the server rejects the StartTLS operation itself.
+#[cfg(any(feature = "tls-native", feature = "tls-rustls"))]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum StartTlsPolicy {
+    /// Don't use StartTLS.
+    #[default]
+    Never,
+    /// Try StartTLS, and continue without TLS if the server rejects it. This exposes
+    /// the connection to downgrade attacks, and shouldn't be used where credentials
+    /// are sent over the connection.
+    Allow,
+    /// Require StartTLS to succeed, failing the connection otherwise.
+    Demand,
+}
+
+/// What to do when the revocation status of the server's certificate is unknown.
+///
+/// The status is unknown if none of the current CRLs added with
+/// [`LdapConnSettings::add_crl()`](struct.LdapConnSettings.html#method.add_crl) has
+/// been issued by the certificate's issuer, or if the certificate or a CRL can't be
+/// parsed. A CRL is current if the present time lies between its `thisUpdate` and
+/// `nextUpdate` times. A certificate found in a CRL always fails the connection.
+///
+/// __Limitation__: the signatures of the CRLs are not verified. The CRLs are trusted
+/// exactly as given, so whoever can substitute a CRL, for instance by controlling the
+/// location from which the application downloads it, can make a revoked certificate
+/// pass the check. Only use CRLs obtained over a channel the application trusts, or
+/// verify their signatures before adding them.
+#[cfg(any(feature = "tls-native", feature = "tls-rustls"))]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RevocationPolicy {
+    /// Fail the connection.
+    #[default]
+    HardFail,
+    /// Log a warning and continue.
+    SoftFail,
+}
+
+/// Credentials for binding a connection which the library opens on its own.
+///
+/// See [`LdapConnSettings::set_rebind_fn()`](struct.LdapConnSettings.html#method.set_rebind_fn).
+#[derive(Clone)]
+pub enum RebindCredentials {
+    /// Simple Bind with a DN and a password.
+    Simple {
+        /// Bind DN.
+        dn: String,
+        /// Password.
+        password: String,
+    },
+    /// SASL EXTERNAL Bind, relying on the identity established by the transport.
+    SaslExternal,
+}
+
+impl fmt::Debug for RebindCredentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            RebindCredentials::Simple { ref dn, .. } => f
+                .debug_struct("Simple")
+                .field("dn", dn)
+                .field("password", &"***")
+                .finish(),
+            RebindCredentials::SaslExternal => f.write_str("SaslExternal"),
+        }
+    }
+}
+
+impl RebindCredentials {
+    #[cfg_attr(
+        not(any(
+            feature = "lazy",
+            feature = "failover",
+            feature = "retry",
+            feature = "routing"
+        )),
+        allow(dead_code)
+    )]
+    pub(crate) async fn bind(&self, ldap: &mut Ldap) -> Result<()> {
+        match *self {
+            RebindCredentials::Simple {
+                ref dn,
+                ref password,
+            } => ldap.simple_bind(dn, password).await?.success()?,
+            RebindCredentials::SaslExternal => ldap.sasl_external_bind().await?.success()?,
+        };
+        Ok(())
+    }
+}
+
+type RebindFn = Arc<dyn Fn(&Url) -> Option<RebindCredentials> + Send + Sync>;
+
+#[cfg(any(feature = "tls-native", feature = "tls-rustls"))]
+fn is_pem(data: &[u8]) -> bool {
+    data.windows(11).any(|w| w == b"-----BEGIN ")
+}
+
+// Split PEM text into individual certificates, since native_tls::Certificate::from_pem()
+// only looks at the first one.
+#[cfg(feature = "tls-native")]
+fn pem_certs(data: &[u8]) -> Vec<&[u8]> {
+    const END: &[u8] = b"-----END CERTIFICATE-----";
+    let mut certs = vec![];
+    let mut rest = data;
+    while let Some(pos) = rest.windows(END.len()).position(|w| w == END) {
+        certs.push(&rest[..pos + END.len()]);
+        rest = &rest[pos + END.len()..];
+    }
+    certs
 }
 
 #[cfg(feature = "tls-rustls")]
@@ -80,8 +297,9 @@ impl AsyncRead for ConnType {
             ConnType::Tcp(ts) => Pin::new(ts).poll_read(cx, buf),
             #[cfg(any(feature = "tls-native", feature = "tls-rustls"))]
             ConnType::Tls(tls) => Pin::new(tls).poll_read(cx, buf),
-            #[cfg(unix)]
+            #[cfg(all(unix, feature = "ldapi"))]
             ConnType::Unix(us) => Pin::new(us).poll_read(cx, buf),
+            ConnType::Stream(s) => Pin::new(s).poll_read(cx, buf),
         }
     }
 }
@@ -92,8 +310,9 @@ impl AsyncWrite for ConnType {
             ConnType::Tcp(ts) => Pin::new(ts).poll_write(cx, buf),
             #[cfg(any(feature = "tls-native", feature = "tls-rustls"))]
             ConnType::Tls(tls) => Pin::new(tls).poll_write(cx, buf),
-            #[cfg(unix)]
+            #[cfg(all(unix, feature = "ldapi"))]
             ConnType::Unix(us) => Pin::new(us).poll_write(cx, buf),
+            ConnType::Stream(s) => Pin::new(s).poll_write(cx, buf),
         }
     }
 
@@ -102,8 +321,9 @@ impl AsyncWrite for ConnType {
             ConnType::Tcp(ts) => Pin::new(ts).poll_flush(cx),
             #[cfg(any(feature = "tls-native", feature = "tls-rustls"))]
             ConnType::Tls(tls) => Pin::new(tls).poll_flush(cx),
-            #[cfg(unix)]
+            #[cfg(all(unix, feature = "ldapi"))]
             ConnType::Unix(us) => Pin::new(us).poll_flush(cx),
+            ConnType::Stream(s) => Pin::new(s).poll_flush(cx),
         }
     }
 
@@ -112,8 +332,9 @@ impl AsyncWrite for ConnType {
             ConnType::Tcp(ts) => Pin::new(ts).poll_shutdown(cx),
             #[cfg(any(feature = "tls-native", feature = "tls-rustls"))]
             ConnType::Tls(tls) => Pin::new(tls).poll_shutdown(cx),
-            #[cfg(unix)]
+            #[cfg(all(unix, feature = "ldapi"))]
             ConnType::Unix(us) => Pin::new(us).poll_shutdown(cx),
+            ConnType::Stream(s) => Pin::new(s).poll_shutdown(cx),
         }
     }
 }
@@ -127,14 +348,60 @@ impl AsyncWrite for ConnType {
 #[derive(Clone, Default)]
 pub struct LdapConnSettings {
     conn_timeout: Option<Duration>,
+    #[cfg(feature = "tcp-options")]
+    tcp_keepalive: Option<Duration>,
+    #[cfg(feature = "tcp-options")]
+    tcp_user_timeout: Option<Duration>,
+    proxy_source: Option<SocketAddr>,
+    heartbeat: Option<Duration>,
+    idle_timeout: Option<Duration>,
+    op_timeout: Option<Duration>,
+    stream_buffer: Option<usize>,
+    max_message_size: Option<usize>,
+    max_depth: Option<usize>,
+    max_elements: Option<usize>,
+    max_in_flight: Option<usize>,
+    #[cfg(feature = "observer")]
+    observer: Option<Arc<dyn ConnObserver>>,
+    interceptors: Interceptors,
+    idle_callback: Option<Arc<dyn Fn() + Send + Sync>>,
     #[cfg(feature = "tls-native")]
     connector: Option<TlsConnector>,
     #[cfg(feature = "tls-rustls")]
     config: Option<Arc<ClientConfig>>,
     #[cfg(any(feature = "tls-native", feature = "tls-rustls"))]
-    starttls: bool,
+    starttls: StartTlsPolicy,
     #[cfg(any(feature = "tls-native", feature = "tls-rustls"))]
     no_tls_verify: bool,
+    #[cfg(any(feature = "tls-native", feature = "tls-rustls"))]
+    root_certs: Vec<Vec<u8>>,
+    #[cfg(any(feature = "tls-native", feature = "tls-rustls"))]
+    ca_bundle: Option<PathBuf>,
+    #[cfg(any(feature = "tls-native", feature = "tls-rustls"))]
+    tls_server_name: Option<String>,
+    #[cfg(any(feature = "tls-native", feature = "tls-rustls"))]
+    min_tls_version: TlsVersion,
+    #[cfg(feature = "tls-rustls")]
+    cipher_suites: Option<Vec<&'static SupportedCipherSuite>>,
+    #[cfg(any(feature = "tls-native", feature = "tls-rustls"))]
+    crls: Vec<Vec<u8>>,
+    #[cfg(any(feature = "tls-native", feature = "tls-rustls"))]
+    revocation_policy: RevocationPolicy,
+    ldapi_external: bool,
+    error_context: bool,
+    resolver: Option<Arc<dyn Resolver>>,
+    rebind_fn: Option<RebindFn>,
+    #[cfg(feature = "metrics")]
+    metrics_sink: Option<Arc<dyn MetricsSink>>,
+    #[cfg(feature = "metrics")]
+    slow_op_threshold: Option<Duration>,
+    #[cfg(feature = "metrics")]
+    slow_op_callback: Option<SlowOpCallback>,
+    #[cfg(feature = "audit")]
+    audit_sink: Option<Arc<dyn AuditSink>>,
+    #[cfg(feature = "audit")]
+    audit_redact: bool,
+    ber_dump: Option<BerDump>,
 }
 
 impl LdapConnSettings {
@@ -154,6 +421,140 @@ impl LdapConnSettings {
         self
     }
 
+    /// Set the default timeout for all operations on the connection. An explicit
+    /// [`Ldap::with_timeout()`](struct.Ldap.html#method.with_timeout) takes precedence
+    /// for the operation it's applied to. Defaults to `None`, meaning that operations
+    /// wait for the response indefinitely.
+    pub fn set_op_timeout(mut self, timeout: Duration) -> Self {
+        self.op_timeout = Some(timeout);
+        self
+    }
+
+    /// Limit the number of unread replies buffered for each streaming Search or
+    /// Extended operation to `capacity`, which must be greater than zero. When the
+    /// buffer of a stream is full, the connection stops reading from the socket
+    /// until the consumer catches up, so that memory use stays bounded with huge
+    /// result sets.
+    ///
+    /// Since all operations on the connection share the socket, a full buffer also
+    /// holds back the replies to every other operation. New requests are still sent,
+    /// so an unread stream can be abandoned, but code which waits for the reply to
+    /// another operation on the same connection while leaving a stream unread will
+    /// deadlock.
+    /// Defaults to `None`, meaning unlimited buffering.
+    pub fn set_stream_buffer(mut self, capacity: usize) -> Self {
+        assert!(capacity > 0, "stream buffer capacity must be positive");
+        self.stream_buffer = Some(capacity);
+        self
+    }
+
+    /// Fail the connection if the server sends a message whose encoded length exceeds
+    /// `size` bytes. The check uses the length in the message header, so an oversized
+    /// message is rejected before any of its contents are buffered. Defaults to `None`,
+    /// meaning no limit.
+    ///
+    /// The limits on incoming messages are checked before a message is parsed. A message
+    /// over any of them fails the connection with an `io::Error` of the kind `InvalidData`,
+    /// which wraps the [`LimitError`](asn1/enum.LimitError.html) naming the limit.
+    pub fn set_max_message_size(mut self, size: usize) -> Self {
+        self.max_message_size = Some(size);
+        self
+    }
+
+    /// Fail the connection if the server sends a message with constructed BER elements
+    /// nested more than `depth` levels deep. Responses of well-behaved servers rarely go
+    /// beyond ten levels. Defaults to `None`, meaning lber's default of 64 levels. The
+    /// parser itself never accepts more than 256.
+    pub fn set_max_nesting_depth(mut self, depth: usize) -> Self {
+        self.max_depth = Some(depth);
+        self
+    }
+
+    /// Fail the connection if the server sends a message with more than `count` BER
+    /// elements, counted at all levels. Since every element is turned into a separately
+    /// allocated structure, this bounds the memory used for parsing a message of many
+    /// small elements more tightly than the size limit. Defaults to `None`, meaning
+    /// no limit.
+    pub fn set_max_elements(mut self, count: usize) -> Self {
+        self.max_elements = Some(count);
+        self
+    }
+
+    /// Limit the number of operations awaiting a response on the connection to `max`,
+    /// which must be greater than zero. Operations over the limit are queued in the
+    /// connection, and sent as the earlier ones finish, in the order of their
+    /// [priority](struct.Ldap.html#method.with_priority); a streaming operation
+    /// counts until its final response. A timeout on a queued operation also covers the
+    /// time spent in the queue. The queue depth is reported to the
+    /// [metrics sink](#method.set_metrics_sink). Abandon and Unbind are never queued.
+    /// Defaults to `None`, meaning no limit.
+    pub fn set_max_in_flight(mut self, max: usize) -> Self {
+        assert!(max > 0, "in-flight limit must be positive");
+        self.max_in_flight = Some(max);
+        self
+    }
+
+    #[cfg(feature = "tcp-options")]
+    /// Enable TCP keepalive on the connection, with `idle` as the time of inactivity
+    /// before the first keepalive probe is sent. The interval between the probes and
+    /// their number are the system defaults. A connection through a NAT device or a
+    /// firewall which silently drops idle flows is thus kept alive, or detected as broken.
+    /// Defaults to `None`, meaning that the system setting is used; keepalive usually
+    /// isn't enabled by default.
+    pub fn set_tcp_keepalive(mut self, idle: Duration) -> Self {
+        self.tcp_keepalive = Some(idle);
+        self
+    }
+
+    #[cfg(feature = "tcp-options")]
+    /// Set the maximum time that sent data may remain unacknowledged before the
+    /// connection is forcibly closed (the `TCP_USER_TIMEOUT` socket option). Only
+    /// supported on Linux and Android, and ignored elsewhere. Defaults to `None`,
+    /// meaning the system default.
+    pub fn set_tcp_user_timeout(mut self, timeout: Duration) -> Self {
+        self.tcp_user_timeout = Some(timeout);
+        self
+    }
+
+    /// Send a PROXY protocol (version 2) header immediately after connecting, giving
+    /// `source` as the address of the original client. This is for directory servers
+    /// behind a load balancer which expects the header, such as HAProxy with
+    /// `accept-proxy`; the header would be a protocol error for a server contacted
+    /// directly. Only used for __ldap__ and __ldaps__ connections. Defaults to `None`,
+    /// meaning no header.
+    pub fn set_proxy_protocol(mut self, source: SocketAddr) -> Self {
+        self.proxy_source = Some(source);
+        self
+    }
+
+    /// Send a Who Am I? request to the server every `interval` while the connection is
+    /// driven, and close the connection if the previous request hasn't been answered by
+    /// the time the next one is due. After that, [`Ldap::is_closed()`](struct.Ldap.html#method.is_closed)
+    /// returns `true`, and the operations in progress fail. Defaults to `None`, meaning
+    /// no heartbeat.
+    pub fn set_heartbeat(mut self, interval: Duration) -> Self {
+        self.heartbeat = Some(interval);
+        self
+    }
+
+    /// Unbind and close the connection when no operation has been in progress for
+    /// `timeout`. The connection driver then exits normally, and subsequent operations
+    /// on the handle fail. Heartbeat requests don't count as activity. Setting this
+    /// below the server's idle limit avoids having the server drop the connection at
+    /// an arbitrary moment, possibly while a request is in transit. Defaults to `None`,
+    /// meaning no idle timeout.
+    pub fn set_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Call `callback` from the connection driver when the connection is closed because
+    /// of the idle timeout. The callback must not block.
+    pub fn set_idle_callback<F: Fn() + Send + Sync + 'static>(mut self, callback: F) -> Self {
+        self.idle_callback = Some(Arc::new(callback));
+        self
+    }
+
     #[cfg(feature = "tls-native")]
     /// Set a custom TLS connector, which enables setting various options
     /// when establishing a secure connection. The default of `None` will
@@ -174,9 +575,23 @@ impl LdapConnSettings {
 
     #[cfg(any(feature = "tls-native", feature = "tls-rustls"))]
     /// If `true`, use the StartTLS extended operation to establish a
-    /// secure connection. Defaults to `false`.
+    /// secure connection. Defaults to `false`. This is a shorthand for
+    /// setting the StartTLS policy to `Demand` or `Never`.
     pub fn set_starttls(mut self, starttls: bool) -> Self {
-        self.starttls = starttls;
+        self.starttls = if starttls {
+            StartTlsPolicy::Demand
+        } else {
+            StartTlsPolicy::Never
+        };
+        self
+    }
+
+    #[cfg(any(feature = "tls-native", feature = "tls-rustls"))]
+    /// Set the StartTLS policy for __ldap__ URLs. See
+    /// [`StartTlsPolicy`](enum.StartTlsPolicy.html) for the options. Defaults to
+    /// `StartTlsPolicy::Never`.
+    pub fn set_starttls_policy(mut self, policy: StartTlsPolicy) -> Self {
+        self.starttls = policy;
         self
     }
 
@@ -184,7 +599,7 @@ impl LdapConnSettings {
     /// The `starttls` settings indicates whether the StartTLS extended
     /// operation will be used to establish a secure connection.
     pub fn starttls(&self) -> bool {
-        self.starttls
+        self.starttls != StartTlsPolicy::Never
     }
 
     #[cfg(not(any(feature = "tls-native", feature = "tls-rustls")))]
@@ -200,6 +615,237 @@ impl LdapConnSettings {
         self.no_tls_verify = no_tls_verify;
         self
     }
+
+    #[cfg(any(feature = "tls-native", feature = "tls-rustls"))]
+    /// Trust the CA certificate `cert` when verifying the server's certificate. The
+    /// certificate can be DER-encoded, or PEM-encoded, in which case `cert` may contain
+    /// several certificates. May be called repeatedly to add more certificates.
+    ///
+    /// With native TLS, the certificates are added to the system trust store. Rustls
+    /// doesn't have any trust roots by default, so the added certificates are the only
+    /// ones trusted. Neither this method nor [`set_ca_bundle()`](#method.set_ca_bundle)
+    /// has any effect if the TLS connector or configuration is provided by the caller.
+    /// Invalid certificates are reported when connecting.
+    pub fn add_root_certificate(mut self, cert: &[u8]) -> Self {
+        self.root_certs.push(cert.to_vec());
+        self
+    }
+
+    #[cfg(any(feature = "tls-native", feature = "tls-rustls"))]
+    /// Trust the CA certificates in the file at `path`, in the same way as if the contents
+    /// were passed to [`add_root_certificate()`](#method.add_root_certificate). The file is
+    /// read when connecting.
+    pub fn set_ca_bundle<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.ca_bundle = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    #[cfg(any(feature = "tls-native", feature = "tls-rustls"))]
+    /// Use `name` as the server name in the TLS handshake, both for SNI and for verifying
+    /// the server's certificate, instead of the host from the URL. This allows connecting
+    /// by IP address, or through a load balancer, and still checking the certificate
+    /// against the directory server's proper name. Defaults to `None`.
+    pub fn set_tls_server_name(mut self, name: &str) -> Self {
+        self.tls_server_name = Some(String::from(name));
+        self
+    }
+
+    #[cfg(any(feature = "tls-native", feature = "tls-rustls"))]
+    /// Set the lowest TLS protocol version acceptable for the connection. Defaults
+    /// to TLS 1.2. Has no effect if the TLS connector or configuration is provided
+    /// by the caller.
+    pub fn set_min_tls_version(mut self, version: TlsVersion) -> Self {
+        self.min_tls_version = version;
+        self
+    }
+
+    #[cfg(feature = "tls-rustls")]
+    /// Restrict the cipher suites offered in the TLS handshake to `suites`, in order
+    /// of preference. The default is the rustls default set, which contains only
+    /// suites with forward secrecy and authenticated encryption. Has no effect if the
+    /// TLS configuration is provided by the caller.
+    pub fn set_cipher_suites(mut self, suites: Vec<&'static SupportedCipherSuite>) -> Self {
+        self.cipher_suites = Some(suites);
+        self
+    }
+
+    #[cfg(any(feature = "tls-native", feature = "tls-rustls"))]
+    /// Add a DER-encoded certificate revocation list. If any CRLs are present, the
+    /// certificates presented by the server are checked against them after the TLS
+    /// handshake, and the connection fails if one of them is revoked. May be called
+    /// repeatedly to add more CRLs.
+    ///
+    /// The CRLs are trusted as given: their signatures __aren't verified__, see
+    /// [`RevocationPolicy`](enum.RevocationPolicy.html). A CRL past its `nextUpdate`
+    /// time no longer counts for the certificates it doesn't list, and refreshing the
+    /// CRLs is up to the application. The server's certificate is looked up in the CRLs
+    /// of its issuer; if there is no current one, the outcome depends on the
+    /// [revocation policy](#method.set_revocation_policy).
+    pub fn add_crl(mut self, crl: &[u8]) -> Self {
+        self.crls.push(crl.to_vec());
+        self
+    }
+
+    #[cfg(any(feature = "tls-native", feature = "tls-rustls"))]
+    /// Set the policy for the case when the revocation status of the server's certificate
+    /// can't be determined. Defaults to `RevocationPolicy::HardFail`. Has no effect if
+    /// no CRLs have been added.
+    pub fn set_revocation_policy(mut self, policy: RevocationPolicy) -> Self {
+        self.revocation_policy = policy;
+        self
+    }
+
+    // Raw contents of all added trust roots, reading the CA bundle if necessary.
+    #[cfg(any(feature = "tls-native", feature = "tls-rustls"))]
+    fn root_cert_data(&self) -> Result<Vec<Vec<u8>>> {
+        let mut data = self.root_certs.clone();
+        if let Some(ref path) = self.ca_bundle {
+            data.push(std::fs::read(path)?);
+        }
+        Ok(data)
+    }
+
+    /// If `true`, immediately perform a SASL EXTERNAL Bind after connecting to an
+    /// __ldapi__ URL, authenticating with the credentials of the current process, which
+    /// the server obtains from the Unix domain socket or named pipe. This is what OpenLDAP command-line
+    /// tools do with `-Y EXTERNAL`. If the Bind fails, the connection attempt returns an
+    /// error. Has no effect on other URL schemes. Defaults to `false`.
+    pub fn set_ldapi_external(mut self, ldapi_external: bool) -> Self {
+        self.ldapi_external = ldapi_external;
+        self
+    }
+
+    /// Return the errors of operations wrapped in
+    /// [`LdapError::Context`](result/enum.LdapError.html#variant.Context), which names
+    /// the operation, the DN in the request, and the server. The helper methods of
+    /// `LdapError`, such as [`category()`](result/enum.LdapError.html#method.category),
+    /// look through the wrapper. Error result codes are returned in the operation result
+    /// as usual, without the context. Defaults to `false`.
+    pub fn set_error_context(mut self, error_context: bool) -> Self {
+        self.error_context = error_context;
+        self
+    }
+
+    /// Use `resolver` for looking up the addresses of the server host. See the
+    /// [`resolve`](resolve/index.html) module for the default. IP address literals
+    /// in URLs are never passed to the resolver.
+    pub fn set_resolver(mut self, resolver: Arc<dyn Resolver>) -> Self {
+        self.resolver = Some(resolver);
+        self
+    }
+
+    /// Supply credentials for connections opened by the library itself, rather than
+    /// by an explicit call from the application: reconnections in
+    /// [`RetryingLdap`](retry/struct.RetryingLdap.html) and connections to the servers
+    /// of a [`ServerSet`](failover/struct.ServerSet.html). The function is called with
+    /// the URL of the target server, and the returned credentials are used to bind the
+    /// new connection before it's handed out. If it returns `None`, the connection is
+    /// left as it is. Defaults to `None`, meaning that such connections are only
+    /// rebound if the component has remembered credentials of its own.
+    pub fn set_rebind_fn<F>(mut self, rebind_fn: F) -> Self
+    where
+        F: Fn(&Url) -> Option<RebindCredentials> + Send + Sync + 'static,
+    {
+        self.rebind_fn = Some(Arc::new(rebind_fn));
+        self
+    }
+
+    #[cfg_attr(
+        not(any(
+            feature = "lazy",
+            feature = "failover",
+            feature = "retry",
+            feature = "routing"
+        )),
+        allow(dead_code)
+    )]
+    pub(crate) fn rebind_credentials(&self, url: &str) -> Result<Option<RebindCredentials>> {
+        match self.rebind_fn {
+            Some(ref rebind_fn) => Ok(rebind_fn(&Url::parse(url)?)),
+            None => Ok(None),
+        }
+    }
+
+    #[cfg(feature = "metrics")]
+    /// Set the receiver of operation measurements for the connection. See the
+    /// [`metrics`](metrics/index.html) module for details. Defaults to `None`,
+    /// meaning that no measurements are made.
+    pub fn set_metrics_sink(mut self, sink: Arc<dyn MetricsSink>) -> Self {
+        self.metrics_sink = Some(sink);
+        self
+    }
+
+    #[cfg(feature = "metrics")]
+    /// Report operations which take at least `threshold` to complete. See the
+    /// [`metrics`](metrics/index.html) module for details. Defaults to `None`, meaning
+    /// that slow operations aren't reported.
+    pub fn set_slow_op_threshold(mut self, threshold: Duration) -> Self {
+        self.slow_op_threshold = Some(threshold);
+        self
+    }
+
+    #[cfg(feature = "metrics")]
+    /// Call `callback` from the connection driver with the report of every slow
+    /// operation, instead of logging it. Has no effect unless the threshold is
+    /// also set. The callback must not block.
+    pub fn set_slow_op_callback<F: Fn(&SlowOp) + Send + Sync + 'static>(
+        mut self,
+        callback: F,
+    ) -> Self {
+        self.slow_op_callback = Some(Arc::new(callback));
+        self
+    }
+
+    #[cfg(feature = "audit")]
+    /// Set the receiver of audit records for modifying operations on the connection.
+    /// See the [`audit`](audit/index.html) module for details. Defaults to `None`.
+    pub fn set_audit_sink(mut self, sink: Arc<dyn AuditSink>) -> Self {
+        self.audit_sink = Some(sink);
+        self
+    }
+
+    #[cfg(feature = "audit")]
+    /// If `redact` is `true`, replace attribute values in audit records with a
+    /// placeholder. Defaults to `false`.
+    pub fn set_audit_redaction(mut self, redact: bool) -> Self {
+        self.audit_redact = redact;
+        self
+    }
+
+    #[cfg(feature = "observer")]
+    /// Set the receiver of lifecycle events for the connection. See the
+    /// [`observer`](observer/index.html) module for details. Defaults to `None`.
+    pub fn set_observer(mut self, observer: Arc<dyn ConnObserver>) -> Self {
+        self.observer = Some(observer);
+        self
+    }
+
+    /// Add an interceptor of requests and results to the connection. Several interceptors
+    /// can be added, and each of them will be called. See the [`intercept`](intercept/index.html)
+    /// module for details.
+    pub fn add_interceptor(mut self, interceptor: Arc<dyn Interceptor>) -> Self {
+        self.interceptors.0.push(interceptor);
+        self
+    }
+
+    /// Write every LDAP message sent or received on the connection to `sink`, in the
+    /// given `format`. Each message is preceded by a line with the direction, `>>` for
+    /// sent and `<<` for received messages, and the length of the encoded message.
+    /// Defaults to no dump.
+    ///
+    /// This is a debugging aid for interoperability problems, and will considerably slow
+    /// down the connection. Bind passwords and all other data will be dumped as-is.
+    pub fn set_ber_dump<W: Write + Send + 'static>(
+        mut self,
+        sink: W,
+        format: BerDumpFormat,
+    ) -> Self {
+        self.ber_dump = Some(BerDump {
+            sink: Arc::new(Mutex::new(sink)),
+            format,
+        });
+        self
+    }
 }
 
 enum LoopMode {
@@ -232,7 +878,10 @@ enum LoopMode {
 /// ```
 ///
 /// If you need custom connection lifecycle handling, use the [`drive()`](#method.drive) method
-/// on the connection inside your own `async` block.
+/// on the connection inside your own `async` block. The future returned by `drive()` can also
+/// be selected against other futures, or awaited directly. To find out when and why the
+/// connection ended without holding on to that future, obtain a [`ConnCompletion`](struct.ConnCompletion.html)
+/// handle by calling [`completion()`](#method.completion) before driving the connection.
 ///
 /// The `Ldap` handle can be freely cloned, with each clone capable of launching a separate
 /// LDAP operation multiplexed on the original connection. Dropping the last handle will automatically
@@ -247,11 +896,194 @@ pub struct LdapConnAsync {
     msgmap: Arc<Mutex<(i32, HashSet<i32>)>>,
     resultmap: HashMap<i32, ResultSender>,
     searchmap: HashMap<i32, ItemSender>,
-    rx: mpsc::UnboundedReceiver<(RequestId, LdapOp, Tag, MaybeControls, ResultSender)>,
+    rx: mpsc::UnboundedReceiver<(
+        RequestId,
+        LdapOp,
+        Tag,
+        MaybeControls,
+        ResultSender,
+        Priority,
+    )>,
     id_scrub_rx: mpsc::UnboundedReceiver<RequestId>,
+    notif_tx: Option<mpsc::UnboundedSender<ExopResult>>,
+    #[cfg(feature = "metrics")]
+    tracker: OpTracker,
+    #[cfg(feature = "audit")]
+    audit: AuditTracker,
+    heartbeat: Option<Heartbeat>,
+    idle: Option<Idle>,
+    max_in_flight: Option<usize>,
+    queue: Lanes,
+    unbound: bool,
+    end: Option<DisconnectReason>,
+    done_tx: Option<watch::Sender<Option<DisconnectReason>>>,
+    #[cfg(feature = "observer")]
+    observer: Option<Arc<dyn ConnObserver>>,
+    #[cfg(feature = "observer")]
+    binds: HashSet<RequestId>,
+    parked: Option<Parked>,
     stream: Framed<ConnType, LdapCodec>,
 }
 
+// A stream item which didn't fit in the buffer of its stream. The socket isn't read
+// until the item is delivered, but new requests are still accepted.
+struct Parked {
+    id: RequestId,
+    item: StreamItem,
+    done: bool,
+}
+
+// The timer is created when the connection starts being driven, since that
+// requires a runtime.
+struct Heartbeat {
+    period: Duration,
+    ticker: Option<time::Interval>,
+    pending: Option<oneshot::Receiver<(Tag, Vec<Control>)>>,
+    pending_id: RequestId,
+}
+
+// Scheme, host and port of the URL, which is all that's safe to show, since the
+// URL could contain credentials.
+fn server_url(url: &Url) -> String {
+    let mut server = format!("{}://{}", url.scheme(), url.host_str().unwrap_or(""));
+    if let Some(port) = url.port() {
+        server.push_str(&format!(":{}", port));
+    }
+    server
+}
+
+type QueuedOp = (RequestId, LdapOp, Tag, MaybeControls, ResultSender);
+
+// Operations waiting to be written, in a queue for each priority. A Bind is a barrier:
+// it's written after everything submitted before it, and nothing submitted after it is
+// written before it. Operations behind a Bind which can't be written yet are held, in
+// the order of submission, until the lanes are empty.
+#[derive(Default)]
+struct Lanes {
+    ready: [VecDeque<QueuedOp>; 3],
+    held: VecDeque<(Priority, QueuedOp)>,
+}
+
+impl Lanes {
+    fn lane(priority: Priority) -> usize {
+        match priority {
+            Priority::High => 0,
+            Priority::Normal => 1,
+            Priority::Low => 2,
+        }
+    }
+
+    fn is_bind(op: &QueuedOp) -> bool {
+        OpKind::from_tag(&op.2) == Some(OpKind::Bind)
+    }
+
+    fn push(&mut self, priority: Priority, op: QueuedOp) {
+        let ready_empty = self.ready.iter().all(VecDeque::is_empty);
+        if !self.held.is_empty() || (Self::is_bind(&op) && !ready_empty) {
+            self.held.push_back((priority, op));
+        } else if Self::is_bind(&op) {
+            // The lanes are empty, and everything after the Bind goes behind it.
+            self.ready[0].push_back(op);
+        } else {
+            self.ready[Self::lane(priority)].push_back(op);
+        }
+    }
+
+    fn pop(&mut self) -> Option<QueuedOp> {
+        if let Some(op) = self.ready.iter_mut().find_map(VecDeque::pop_front) {
+            return Some(op);
+        }
+        // Release the held operations up to the next Bind after the first one.
+        while let Some((priority, op)) = self.held.pop_front() {
+            if !Self::is_bind(&op) {
+                self.ready[Self::lane(priority)].push_back(op);
+            } else if self.ready.iter().all(VecDeque::is_empty) {
+                self.ready[0].push_back(op);
+            } else {
+                self.held.push_front((priority, op));
+                break;
+            }
+        }
+        self.ready.iter_mut().find_map(VecDeque::pop_front)
+    }
+
+    #[cfg_attr(not(feature = "metrics"), allow(dead_code))]
+    fn len(&self) -> usize {
+        self.ready.iter().map(VecDeque::len).sum::<usize>() + self.held.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.ready.iter().all(VecDeque::is_empty) && self.held.is_empty()
+    }
+
+    fn remove(&mut self, id: RequestId) {
+        for lane in self.ready.iter_mut() {
+            lane.retain(|op| op.0 != id);
+        }
+        self.held.retain(|(_, op)| op.0 != id);
+    }
+}
+
+struct Idle {
+    timeout: Duration,
+    last_active: time::Instant,
+    callback: Option<Arc<dyn Fn() + Send + Sync>>,
+}
+
+/// The reason for the end of a connection.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DisconnectReason {
+    /// All `Ldap` handles for the connection were dropped.
+    HandlesDropped,
+    /// The connection was closed after an Unbind.
+    Unbind,
+    /// The server closed the connection.
+    ServerClosed,
+    /// The connection was closed because of the idle timeout; see
+    /// [`LdapConnSettings::set_idle_timeout()`](struct.LdapConnSettings.html#method.set_idle_timeout).
+    IdleTimeout,
+    /// The connection failed, with the error message as the value. This includes
+    /// a missed heartbeat.
+    Error(String),
+    /// The connection was dropped without being driven to the end, or its driver
+    /// task was aborted.
+    Aborted,
+}
+
+/// Handle for learning when and why a connection has ended. __*__
+///
+/// Obtained by calling [`LdapConnAsync::completion()`](struct.LdapConnAsync.html#method.completion).
+/// The handle can be cloned, and is independent of the `Ldap` handles, so it can be kept by
+/// the code responsible for reconnecting.
+#[derive(Clone, Debug)]
+pub struct ConnCompletion {
+    rx: watch::Receiver<Option<DisconnectReason>>,
+}
+
+impl ConnCompletion {
+    /// Return the reason for the end of the connection, or `None` if the connection is
+    /// still active.
+    pub fn reason(&self) -> Option<DisconnectReason> {
+        self.rx.borrow().clone()
+    }
+
+    /// Wait until the connection ends, and return the reason.
+    pub async fn wait(&mut self) -> DisconnectReason {
+        loop {
+            if let Some(reason) = self.rx.borrow().clone() {
+                return reason;
+            }
+            if self.rx.changed().await.is_err() {
+                return self
+                    .rx
+                    .borrow()
+                    .clone()
+                    .unwrap_or(DisconnectReason::Aborted);
+            }
+        }
+    }
+}
+
 /// Drive the connection until its completion. __*__
 ///
 /// See the introduction of [LdapConnAsync](struct.LdapConnAsync.html) for the exact code produced by
@@ -280,11 +1112,21 @@ impl LdapConnAsync {
     /// The `url` is an LDAP URL. Depending on the platform and compile-time features, the
     /// library will recognize one or more URL schemes.
     ///
-    /// The __ldap__ scheme, which uses a plain TCP connection, is always available. Unix-like
-    /// platforms also support __ldapi__, using Unix domain sockets. With the __tls__ or
+    /// The __ldap__ scheme, which uses a plain TCP connection, is always available. With the
+    /// __ldapi__ feature, Unix-like platforms also support __ldapi__, using Unix domain
+    /// sockets, with the socket path percent-encoded in the host part of the URL, as in
+    /// `ldapi://%2Fvar%2Frun%2Fldapi`. On Windows, __ldapi__ connects to a named pipe; the
+    /// host part is either the full, percent-encoded pipe path, like
+    /// `ldapi://%5C%5C.%5Cpipe%5Cldap`, or just the pipe name, like `ldapi://ldap`, for
+    /// a pipe on the local machine. With the __tls__ or
     /// __tls-rustls__ feature, the __ldaps__ scheme and StartTLS over __ldap__ are additionally
     /// supported.
     ///
+    /// If the host name resolves to several addresses, all of them are tried, alternating
+    /// between IPv6 and IPv4, with a new attempt started every 250 ms until one succeeds
+    /// (the "Happy Eyeballs" algorithm of RFC 8305). An unreachable address thus doesn't
+    /// hold up the connection until the timeout.
+    ///
     /// The connection element in the returned tuple must be spawned on the current Tokio
     /// executor before using the `Ldap` element. See the introduction to this struct's
     /// documentation.
@@ -298,8 +1140,48 @@ impl LdapConnAsync {
         settings: LdapConnSettings,
         url: &Url,
     ) -> Result<(Self, Ldap)> {
+        #[cfg(feature = "tracing")]
+        let started = std::time::Instant::now();
+        #[cfg(feature = "observer")]
+        let observer = settings.observer.clone();
+        let mut res = Self::connect(settings, url).await;
+        #[cfg(feature = "metrics")]
+        if let Ok((ref mut conn, _)) = res {
+            conn.tracker.set_server(server_url(url));
+        }
+        if let Ok((_, ref mut ldap)) = res {
+            if let Some(shared) = Arc::get_mut(&mut ldap.shared) {
+                shared.server = Some(server_url(url));
+            }
+        }
+        #[cfg(feature = "observer")]
+        if let Some(observer) = observer {
+            match res {
+                Ok(_) => observer.connected(url),
+                Err(ref e) => observer.connect_failed(url, e),
+            }
+        }
+        #[cfg(feature = "tracing")]
+        {
+            // Only the scheme, host and port are recorded, since the URL could
+            // contain credentials.
+            let scheme = url.scheme();
+            let host = url.host_str().unwrap_or("");
+            let port = url.port();
+            let elapsed_us = started.elapsed().as_micros() as u64;
+            match res {
+                Ok(_) => tracing::debug!(scheme, host, port, elapsed_us, "connected"),
+                Err(ref e) => {
+                    tracing::warn!(scheme, host, port, elapsed_us, error = %e, "connection failed")
+                }
+            }
+        }
+        res
+    }
+
+    async fn connect(settings: LdapConnSettings, url: &Url) -> Result<(Self, Ldap)> {
         if url.scheme() == "ldapi" {
-            LdapConnAsync::new_unix(url, settings).await
+            LdapConnAsync::new_local(url, settings).await
         } else {
             // For some reason, "mut settings" is transformed to "__arg0" in the docs,
             // this is a workaround. On GitHub, at the time of writing, there is:
@@ -318,13 +1200,27 @@ impl LdapConnAsync {
         }
     }
 
+    /// Create a connection over an already established `stream`, using `settings` for
+    /// additional parameters.
+    ///
+    /// The stream can be any bidirectional transport: a TLS stream from a library not
+    /// supported internally, a tunnel, or one half of an in-memory duplex in tests.
+    /// Since the connection is already set up, the connection timeout and the TLS-related
+    /// settings are ignored; in particular, StartTLS can't be used on such a stream.
+    pub fn from_stream<S: AsyncStream + 'static>(
+        stream: S,
+        settings: LdapConnSettings,
+    ) -> (Self, Ldap) {
+        Self::conn_pair(ConnType::Stream(Box::new(stream)), &settings)
+    }
+
     /// Open a connection to an LDAP server specified by an already parsed `Url`.
     pub async fn from_url(url: &Url) -> Result<(Self, Ldap)> {
         Self::from_url_with_settings(LdapConnSettings::new(), url).await
     }
 
-    #[cfg(unix)]
-    async fn new_unix(url: &Url, _settings: LdapConnSettings) -> Result<(Self, Ldap)> {
+    #[cfg(all(any(unix, windows), feature = "ldapi"))]
+    async fn new_local(url: &Url, settings: LdapConnSettings) -> Result<(Self, Ldap)> {
         let path = url.host_str().unwrap_or("");
         if path.is_empty() {
             return Err(LdapError::EmptyUnixPath);
@@ -333,13 +1229,41 @@ impl LdapConnAsync {
             return Err(LdapError::PortInUnixPath);
         }
         let dec_path = percent_decode(path.as_bytes()).decode_utf8_lossy();
-        let stream = UnixStream::connect(dec_path.as_ref()).await?;
-        Ok(Self::conn_pair(ConnType::Unix(stream)))
+        let stream = Self::open_local(dec_path.as_ref()).await?;
+        let (mut conn, mut ldap) = Self::conn_pair(stream, &settings);
+        if settings.ldapi_external {
+            let (tx, rx) = oneshot::channel();
+            tokio::spawn(async move {
+                conn.single_op(tx).await;
+            });
+            let (conn_res, res) =
+                tokio::try_join!(rx.map_err(LdapError::from), ldap.sasl_external_bind())?;
+            conn = conn_res?;
+            res.success()?;
+        }
+        Ok((conn, ldap))
+    }
+
+    #[cfg(all(unix, feature = "ldapi"))]
+    async fn open_local(path: &str) -> Result<ConnType> {
+        Ok(ConnType::Unix(UnixStream::connect(path).await?))
     }
 
-    #[cfg(not(unix))]
-    async fn new_unix(_url: &Url, _settings: LdapConnSettings) -> Result<(Self, Ldap)> {
-        unimplemented!("no Unix domain sockets on non-Unix platforms");
+    #[cfg(all(windows, feature = "ldapi"))]
+    async fn open_local(path: &str) -> Result<ConnType> {
+        // A bare name is taken to be a pipe on the local machine.
+        let path = if path.starts_with(r"\\") {
+            path.to_owned()
+        } else {
+            format!(r"\\.\pipe\{}", path)
+        };
+        let pipe = ClientOptions::new().open(path)?;
+        Ok(ConnType::Stream(Box::new(pipe)))
+    }
+
+    #[cfg(not(all(any(unix, windows), feature = "ldapi")))]
+    async fn new_local(url: &Url, _settings: LdapConnSettings) -> Result<(Self, Ldap)> {
+        Err(LdapError::UnknownScheme(String::from(url.scheme())))
     }
 
     #[allow(unused_mut)]
@@ -364,13 +1288,18 @@ impl LdapConnAsync {
         if let Some(url_port) = url.port() {
             port = url_port;
         }
-        let (_hostname, host_port) = match url.host_str() {
-            Some(h) if h != "" => (h, format!("{}:{}", h, port)),
-            Some(h) if h == "" => ("localhost", format!("localhost:{}", port)),
+        let _hostname = match url.host_str() {
+            Some(h) if !h.is_empty() => h,
+            Some(_) => "localhost",
             _ => panic!("unexpected None from url.host_str()"),
         };
-        let stream = TcpStream::connect(host_port.as_str()).await?;
-        let (mut conn, mut ldap) = Self::conn_pair(ConnType::Tcp(stream));
+        let mut stream = tcp::connect(_hostname, port, settings.resolver.as_deref()).await?;
+        #[cfg(feature = "tcp-options")]
+        tcp::set_options(&stream, settings.tcp_keepalive, settings.tcp_user_timeout)?;
+        if let Some(source) = settings.proxy_source {
+            tcp::send_proxy_header(&mut stream, source).await?;
+        }
+        let (mut conn, mut ldap) = Self::conn_pair(ConnType::Tcp(stream), &settings);
         match scheme {
             "ldap" => (),
             #[cfg(any(feature = "tls-native", feature = "tls-rustls"))]
@@ -385,17 +1314,42 @@ impl LdapConnAsync {
                     match res {
                         Ok((conn_res, res)) => {
                             conn = conn_res?;
-                            res.success()?;
+                            if let Err(e) = res.success() {
+                                if settings.starttls != StartTlsPolicy::Allow {
+                                    return Err(e);
+                                }
+                                warn!("StartTLS rejected, continuing without TLS: {}", e);
+                                return Ok((conn, ldap));
+                            }
                         }
                         Err(e) => return Err(e),
                     }
                 }
                 let parts = conn.stream.into_parts();
+                #[cfg(feature = "observer")]
+                let observer = settings.observer.clone();
+                let crls = revocation::load(&settings.crls, settings.revocation_policy)?;
+                let revocation_policy = settings.revocation_policy;
                 let tls_stream = if let ConnType::Tcp(stream) = parts.io {
-                    LdapConnAsync::create_tls_stream(settings, &_hostname, stream).await?
+                    LdapConnAsync::create_tls_stream(settings, _hostname, stream).await?
                 } else {
                     panic!("underlying stream not TCP");
                 };
+                let info = tls_info(&tls_stream);
+                if !crls.is_empty() {
+                    let now = std::time::SystemTime::now()
+                        .duration_since(std::time::UNIX_EPOCH)
+                        .map_or(0, |d| d.as_secs() as i64);
+                    revocation::check(&info.peer_certificates, &crls, revocation_policy, now)?;
+                }
+                #[cfg(feature = "observer")]
+                if let Some(observer) = observer {
+                    observer.tls_established(url, &info);
+                }
+                // The handle was just created, and hasn't been cloned yet.
+                Arc::get_mut(&mut ldap.shared)
+                    .expect("unshared handle")
+                    .tls_info = Some(info);
                 conn.stream = parts.codec.framed(ConnType::Tls(tls_stream));
             }
             _ => unimplemented!(),
@@ -411,8 +1365,9 @@ impl LdapConnAsync {
     ) -> Result<TlsStream<TcpStream>> {
         let connector = match settings.connector {
             Some(connector) => connector,
-            None => LdapConnAsync::create_connector(&settings),
+            None => LdapConnAsync::create_connector(&settings)?,
         };
+        let hostname = settings.tls_server_name.as_deref().unwrap_or(hostname);
         TokioTlsConnector::from(connector)
             .connect(hostname, stream)
             .await
@@ -428,8 +1383,9 @@ impl LdapConnAsync {
         let no_tls_verify = settings.no_tls_verify;
         let config = match settings.config {
             Some(config) => config,
-            None => LdapConnAsync::create_config(&settings),
+            None => LdapConnAsync::create_config(&settings)?,
         };
+        let hostname = settings.tls_server_name.as_deref().unwrap_or(hostname);
         TokioTlsConnector::from(config)
             .connect(
                 tokio_rustls::webpki::DNSNameRef::try_from_ascii_str(hostname).or_else(|e| {
@@ -450,28 +1406,74 @@ impl LdapConnAsync {
     }
 
     #[cfg(feature = "tls-rustls")]
-    fn create_config(settings: &LdapConnSettings) -> Arc<ClientConfig> {
+    fn create_config(settings: &LdapConnSettings) -> Result<Arc<ClientConfig>> {
         let mut config = ClientConfig::new();
+        if settings.min_tls_version == TlsVersion::Tls13 {
+            config.versions = vec![ProtocolVersion::TLSv1_3];
+        }
+        if let Some(ref suites) = settings.cipher_suites {
+            config.ciphersuites = suites.clone();
+        }
         if settings.no_tls_verify {
             let no_cert_verifier = NoCertVerification;
             config
                 .dangerous()
                 .set_certificate_verifier(Arc::new(no_cert_verifier));
         }
-        Arc::new(config)
+        for data in settings.root_cert_data()? {
+            let certs = if is_pem(&data) {
+                pemfile::certs(&mut data.as_slice()).map_err(|_| {
+                    LdapError::InvalidCertificate(String::from("malformed PEM data"))
+                })?
+            } else {
+                vec![Certificate(data)]
+            };
+            for cert in certs {
+                config
+                    .root_store
+                    .add(&cert)
+                    .map_err(|e| LdapError::InvalidCertificate(e.to_string()))?;
+            }
+        }
+        Ok(Arc::new(config))
     }
 
     #[cfg(feature = "tls-native")]
-    fn create_connector(settings: &LdapConnSettings) -> TlsConnector {
+    fn create_connector(settings: &LdapConnSettings) -> Result<TlsConnector> {
         let mut builder = TlsConnector::builder();
+        builder.min_protocol_version(Some(match settings.min_tls_version {
+            TlsVersion::Tls12 => Protocol::Tlsv12,
+            TlsVersion::Tls13 => Protocol::Tlsv13,
+        }));
         if settings.no_tls_verify {
             builder.danger_accept_invalid_certs(true);
         }
-        builder.build().expect("connector")
+        for data in settings.root_cert_data()? {
+            if is_pem(&data) {
+                let certs = pem_certs(&data);
+                if certs.is_empty() {
+                    return Err(LdapError::InvalidCertificate(String::from(
+                        "no certificates in PEM data",
+                    )));
+                }
+                for cert in certs {
+                    builder.add_root_certificate(Certificate::from_pem(cert)?);
+                }
+            } else {
+                builder.add_root_certificate(Certificate::from_der(&data)?);
+            }
+        }
+        Ok(builder.build()?)
     }
 
-    fn conn_pair(ctype: ConnType) -> (Self, Ldap) {
-        let codec = LdapCodec;
+    fn conn_pair(ctype: ConnType, settings: &LdapConnSettings) -> (Self, Ldap) {
+        let codec = LdapCodec {
+            dump: settings.ber_dump.clone(),
+            max_message_size: settings.max_message_size,
+            max_depth: settings.max_depth,
+            max_elements: settings.max_elements,
+            ..Default::default()
+        };
         let (tx, rx) = mpsc::unbounded_channel();
         let (id_scrub_tx, id_scrub_rx) = mpsc::unbounded_channel();
         let conn = LdapConnAsync {
@@ -480,110 +1482,500 @@ impl LdapConnAsync {
             searchmap: HashMap::new(),
             rx,
             id_scrub_rx,
+            notif_tx: None,
+            #[cfg(feature = "metrics")]
+            tracker: OpTracker::new(
+                settings.metrics_sink.clone(),
+                settings.slow_op_threshold,
+                settings.slow_op_callback.clone(),
+            ),
+            #[cfg(feature = "audit")]
+            audit: AuditTracker::new(settings.audit_sink.clone(), settings.audit_redact),
+            heartbeat: settings.heartbeat.map(|period| Heartbeat {
+                period,
+                ticker: None,
+                pending: None,
+                pending_id: 0,
+            }),
+            idle: settings.idle_timeout.map(|timeout| Idle {
+                timeout,
+                last_active: time::Instant::now(),
+                callback: settings.idle_callback.clone(),
+            }),
+            max_in_flight: settings.max_in_flight,
+            queue: Lanes::default(),
+            unbound: false,
+            end: None,
+            done_tx: None,
+            #[cfg(feature = "observer")]
+            observer: settings.observer.clone(),
+            #[cfg(feature = "observer")]
+            binds: HashSet::new(),
+            parked: None,
             stream: codec.framed(ctype),
         };
         let ldap = Ldap {
-            msgmap: conn.msgmap.clone(),
-            tx,
-            id_scrub_tx,
+            shared: Arc::new(Shared {
+                msgmap: conn.msgmap.clone(),
+                tx,
+                id_scrub_tx,
+                default_timeout: settings.op_timeout,
+                stream_buffer: settings.stream_buffer,
+                tls_info: None,
+                server: None,
+                error_context: settings.error_context,
+                interceptors: settings.interceptors.clone(),
+                identity: Mutex::new(BoundIdentity::default()),
+                closing: AtomicBool::new(false),
+            }),
             last_id: 0,
+            authzid_request: false,
+            dry_run: false,
             timeout: None,
+            cancel: None,
+            priority: None,
             controls: None,
             search_opts: None,
         };
         (conn, ldap)
     }
 
+    /// Return a channel receiver for unsolicited notifications sent by the server.
+    ///
+    /// An unsolicited notification is an Extended response with the message ID of zero,
+    /// which the server can send at any time, without a corresponding request. The most
+    /// common one is Notice of Disconnection, with the OID of `1.3.6.1.4.1.1466.20036`,
+    /// which the server sends before terminating the connection. The OID is in the `name`
+    /// field of the `Exop` part of the received [`ExopResult`](result/struct.ExopResult.html),
+    /// while the `LdapResult` part contains the reason reported by the server.
+    ///
+    /// The method must be called before the connection is driven. If it's called more than
+    /// once, only the last returned receiver will get the notifications. Without a receiver,
+    /// the notifications are just logged.
+    pub fn unsolicited_notifications(&mut self) -> mpsc::UnboundedReceiver<ExopResult> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        self.notif_tx = Some(tx);
+        rx
+    }
+
+    fn handle_unsolicited(&mut self, tag: Tag, controls: Vec<Control>) {
+        let LdapResultExt(mut result, exop) = LdapResultExt::from(tag);
+        result.ctrls = controls;
+        warn!(
+            "unsolicited notification, oid: {:?}, result: {}",
+            exop.name, result
+        );
+        if let Some(ref tx) = self.notif_tx {
+            if tx.send(ExopResult(exop, result)).is_err() {
+                self.notif_tx = None;
+            }
+        }
+    }
+
+    /// Return a handle for learning when and why the connection has ended. Must be
+    /// called before [`drive()`](#method.drive), which consumes the connection.
+    pub fn completion(&mut self) -> ConnCompletion {
+        let tx = self.done_tx.get_or_insert_with(|| watch::channel(None).0);
+        ConnCompletion { rx: tx.subscribe() }
+    }
+
     /// Repeatedly poll the connection until it exits.
-    pub async fn drive(self) -> Result<()> {
-        self.turn(LoopMode::Continuous).await.map(|_| ())
+    pub async fn drive(mut self) -> Result<()> {
+        let done_tx = self.done_tx.take();
+        #[cfg(feature = "observer")]
+        let observer = self.observer.clone();
+        let res = self.turn(LoopMode::Continuous).await;
+        let reason = match res {
+            Ok(ref conn) => conn.end.clone().unwrap_or(DisconnectReason::HandlesDropped),
+            Err(ref e) => DisconnectReason::Error(e.to_string()),
+        };
+        #[cfg(feature = "observer")]
+        if let Some(observer) = observer {
+            observer.closed(&reason);
+        }
+        if let Some(done_tx) = done_tx {
+            let _ = done_tx.send(Some(reason));
+        }
+        res.map(|_| ())
     }
 
-    #[cfg(any(feature = "tls-native", feature = "tls-rustls"))]
+    #[cfg_attr(
+        not(any(
+            feature = "ldapi",
+            feature = "tls-native",
+            feature = "tls-rustls",
+            feature = "failover"
+        )),
+        allow(dead_code)
+    )]
     pub(crate) async fn single_op(self, tx: oneshot::Sender<Result<Self>>) {
         if tx.send(self.turn(LoopMode::SingleOp).await).is_err() {
             warn!("single op send error");
         }
     }
 
+    async fn heartbeat_tick(heartbeat: &mut Option<Heartbeat>) {
+        match heartbeat {
+            Some(hb) => {
+                let period = hb.period;
+                hb.ticker
+                    .get_or_insert_with(|| {
+                        let mut ticker = time::interval_at(time::Instant::now() + period, period);
+                        ticker.set_missed_tick_behavior(time::MissedTickBehavior::Delay);
+                        ticker
+                    })
+                    .tick()
+                    .await;
+            }
+            None => futures_util::future::pending().await,
+        }
+    }
+
+    async fn send_heartbeat(&mut self) -> Result<()> {
+        let hb = self.heartbeat.as_mut().expect("heartbeat");
+        if let Some(mut rx) = hb.pending.take() {
+            if rx.try_recv().is_err() {
+                warn!("no response to heartbeat, closing connection");
+                return Err(LdapError::from(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "no response to heartbeat",
+                )));
+            }
+        }
+        let (tx, rx) = oneshot::channel();
+        let id = next_msgid(&self.msgmap);
+        hb.pending = Some(rx);
+        hb.pending_id = id;
+        let req = Tag::Sequence(Sequence {
+            id: 23,
+            class: TagClass::Application,
+            inner: construct_exop(WhoAmI.into()),
+        });
+        self.stream.send((id, req, None)).await?;
+        self.resultmap.insert(id, tx);
+        Ok(())
+    }
+
+    fn heartbeat_id(&self) -> Option<RequestId> {
+        self.heartbeat.as_ref().map(|hb| hb.pending_id)
+    }
+
+    // Idle means that nothing except possibly a heartbeat is outstanding.
+    fn is_idle(&self) -> bool {
+        let hb_id = self.heartbeat_id();
+        self.queue.is_empty()
+            && self.searchmap.is_empty()
+            && self.resultmap.keys().all(|id| Some(*id) == hb_id)
+    }
+
+    fn touch(&mut self) {
+        if let Some(ref mut idle) = self.idle {
+            idle.last_active = time::Instant::now();
+        }
+    }
+
+    async fn idle_close(&mut self) {
+        let id = next_msgid(&self.msgmap);
+        let req = Tag::Null(Null {
+            id: 2,
+            class: TagClass::Application,
+            inner: (),
+        });
+        if let Err(e) = self.stream.send((id, req, None)).await {
+            warn!("socket send error: {}", e);
+        }
+        if let Err(e) = self.stream.get_mut().shutdown().await {
+            warn!("socket shutdown error: {}", e);
+        }
+        if let Err(e) = self.stream.close().await {
+            warn!("socket close error: {}", e);
+        }
+        if let Some(callback) = self.idle.as_ref().and_then(|idle| idle.callback.as_ref()) {
+            callback();
+        }
+    }
+
+    fn in_flight(&self) -> usize {
+        self.resultmap.len() + self.searchmap.len()
+    }
+
+    // Operations which get a response wait in the lanes, from which they're written in
+    // the order of priority. Abandon and Unbind are written at once, after what's waiting
+    // without a limit on the operations in flight, so that the ordering of requests is kept
+    // when nothing holds them back. Returns true if the operation awaits a single response.
+    async fn submit(&mut self, queued: QueuedOp, priority: Priority) -> Result<bool> {
+        match queued.1 {
+            LdapOp::Single | LdapOp::Search(_) | LdapOp::MultiExop(_) => {
+                let single = matches!(queued.1, LdapOp::Single);
+                self.queue.push(priority, queued);
+                Ok(single)
+            }
+            _ => {
+                self.drain_queue().await?;
+                let (id, op, tag, controls, tx) = queued;
+                self.send_op(id, op, tag, controls, tx).await
+            }
+        }
+    }
+
+    async fn drain_queue(&mut self) -> Result<()> {
+        let max = self.max_in_flight.unwrap_or(usize::MAX);
+        while self.in_flight() < max {
+            let (id, op, tag, controls, tx) = match self.queue.pop() {
+                Some(queued) => queued,
+                None => break,
+            };
+            #[cfg(feature = "metrics")]
+            self.tracker.queue_depth(self.queue.len());
+            self.send_op(id, op, tag, controls, tx).await?;
+        }
+        Ok(())
+    }
+
+    // Returns true if the operation awaits a single response.
+    async fn send_op(
+        &mut self,
+        id: RequestId,
+        op: LdapOp,
+        tag: Tag,
+        controls: MaybeControls,
+        tx: ResultSender,
+    ) -> Result<bool> {
+        if let LdapOp::Search(ref search_tx) | LdapOp::MultiExop(ref search_tx) = op {
+            self.searchmap.insert(id, search_tx.clone());
+        }
+        #[cfg(feature = "observer")]
+        if self.observer.is_some() && OpKind::from_tag(&tag) == Some(OpKind::Bind) {
+            self.binds.insert(id);
+        }
+        #[cfg(feature = "audit")]
+        if let LdapOp::Single = op {
+            self.audit.start(id, &tag);
+        }
+        #[cfg(feature = "metrics")]
+        let (op_name, target) = (
+            OpKind::from_tag(&tag).map_or("unknown", OpKind::name),
+            self.tracker.target(&tag),
+        );
+        if let Err(e) = self.stream.send((id, tag, controls)).await {
+            warn!("socket send error: {}", e);
+            #[cfg(feature = "tracing")]
+            tracing::warn!(msgid = id, op = op_name, error = %e, "socket send error");
+            return Err(LdapError::from(e));
+        }
+        #[cfg(feature = "metrics")]
+        if let LdapOp::Single | LdapOp::Search(_) | LdapOp::MultiExop(_) = op {
+            self.tracker
+                .start(id, op_name, self.stream.codec().last_out, target);
+        }
+        match op {
+            LdapOp::Single => {
+                self.resultmap.insert(id, tx);
+                return Ok(true);
+            }
+            LdapOp::Search(_) | LdapOp::MultiExop(_) => (),
+            LdapOp::Abandon(msgid) => {
+                self.resultmap.remove(&msgid);
+                self.searchmap.remove(&msgid);
+                #[cfg(feature = "metrics")]
+                self.tracker.finish(msgid, None);
+                #[cfg(feature = "audit")]
+                self.audit.finish(msgid, None);
+                let mut msgmap = self.msgmap.lock().expect("msgmap mutex (abandon)");
+                msgmap.1.remove(&id);
+            }
+            LdapOp::Unbind => {
+                self.unbound = true;
+                if let Err(e) = self.stream.get_mut().shutdown().await {
+                    warn!("socket shutdown error: {}", e);
+                }
+                if let Err(e) = self.stream.close().await {
+                    warn!("socket close error: {}", e);
+                }
+            }
+        }
+        if let Err(e) = tx.send((
+            Tag::Null(Null {
+                ..Default::default()
+            }),
+            vec![],
+        )) {
+            warn!("ldap null result send error: {:?}", e);
+        }
+        Ok(false)
+    }
+
+    // Passes a stream item to the consumer, parking it if the stream buffer is full.
+    // Items for an operation which has meanwhile been abandoned are dropped.
+    fn deliver(&mut self, id: RequestId, item: StreamItem, done: bool) {
+        let tx = match self.searchmap.get(&id) {
+            Some(tx) => tx,
+            None => return,
+        };
+        let done = match tx.try_send(item) {
+            Ok(()) => done,
+            Err(TrySendError::Full(item)) => {
+                self.parked = Some(Parked { id, item, done });
+                return;
+            }
+            Err(TrySendError::Closed(e)) => {
+                warn!("ldap search item send error, op={}: {:?}", id, e);
+                true
+            }
+        };
+        if done {
+            self.searchmap.remove(&id);
+            let mut msgmap = self.msgmap.lock().expect("msgmap mutex (stream done)");
+            msgmap.1.remove(&id);
+        }
+    }
+
+    // Resolves when the parked item can be delivered, or dropped.
+    async fn parked_room(parked: &Option<Parked>, searchmap: &HashMap<i32, ItemSender>) {
+        if let Some(tx) = parked.as_ref().and_then(|parked| searchmap.get(&parked.id)) {
+            tx.ready().await;
+        }
+    }
+
     async fn turn(mut self, mode: LoopMode) -> Result<Self> {
         loop {
+            if !self.queue.is_empty() {
+                self.drain_queue().await?;
+            }
+            let idle_deadline = match self.idle {
+                Some(ref idle) if matches!(mode, LoopMode::Continuous) && self.is_idle() => {
+                    Some(idle.last_active + idle.timeout)
+                }
+                _ => None,
+            };
             tokio::select! {
                 req_id = self.id_scrub_rx.recv() => {
                     if let Some(req_id) = req_id {
+                        #[cfg(feature = "metrics")]
+                        let queued = self.queue.len();
+                        self.queue.remove(req_id);
+                        #[cfg(feature = "metrics")]
+                        if self.queue.len() != queued {
+                            self.tracker.queue_depth(self.queue.len());
+                        }
                         self.resultmap.remove(&req_id);
                         self.searchmap.remove(&req_id);
+                        #[cfg(feature = "observer")]
+                        self.binds.remove(&req_id);
+                        if self.parked.as_ref().is_some_and(|parked| parked.id == req_id) {
+                            self.parked = None;
+                        }
+                        #[cfg(feature = "metrics")]
+                        self.tracker.finish(req_id, None);
+                        #[cfg(feature = "audit")]
+                        self.audit.finish(req_id, None);
                         let mut msgmap = self.msgmap.lock().expect("msgmap mutex (id_scrub)");
                         msgmap.1.remove(&req_id);
                     }
                 },
                 op_tuple = self.rx.recv() => {
-                    if let Some((id, op, tag, controls, tx)) = op_tuple {
-                        if let LdapOp::Search(ref search_tx) = op {
-                            self.searchmap.insert(id, search_tx.clone());
+                    if let Some((id, op, tag, controls, tx, priority)) = op_tuple {
+                        self.touch();
+                        let mut single = self.submit((id, op, tag, controls, tx), priority).await?;
+                        // Take everything else submitted in the meantime, so that it's
+                        // written in the order of priority.
+                        while let Ok((id, op, tag, controls, tx, priority)) = self.rx.try_recv() {
+                            single = self.submit((id, op, tag, controls, tx), priority).await?;
                         }
-                        if let Err(e) = self.stream.send((id, tag, controls)).await {
-                            warn!("socket send error: {}", e);
-                            return Err(LdapError::from(e));
-                        } else {
-                            match op {
-                                LdapOp::Single => {
-                                    self.resultmap.insert(id, tx);
-                                    continue;
-                                },
-                                LdapOp::Search(_) => (),
-                                LdapOp::Abandon(msgid) => {
-                                    self.resultmap.remove(&msgid);
-                                    self.searchmap.remove(&msgid);
-                                    let mut msgmap = self.msgmap.lock().expect("msgmap mutex (abandon)");
-                                    msgmap.1.remove(&id);
-                                },
-                                LdapOp::Unbind => {
-                                    if let Err(e) = self.stream.get_mut().shutdown().await {
-                                        warn!("socket shutdown error: {}", e);
-                                    }
-                                    if let Err(e) = self.stream.close().await {
-                                        warn!("socket close error: {}", e);
-                                    }
-                                },
-                            }
-                            if let Err(e) = tx.send((Tag::Null(Null { ..Default::default() }), vec![])) {
-                                warn!("ldap null result send error: {:?}", e);
-                            }
+                        self.drain_queue().await?;
+                        #[cfg(feature = "metrics")]
+                        if !self.queue.is_empty() {
+                            self.tracker.queue_depth(self.queue.len());
+                        }
+                        if single {
+                            continue;
                         }
                     } else {
+                        self.end = Some(DisconnectReason::HandlesDropped);
                         break;
                     }
                 },
-                resp = self.stream.next() => {
+                _ = Self::heartbeat_tick(&mut self.heartbeat), if matches!(mode, LoopMode::Continuous) => {
+                    self.send_heartbeat().await?;
+                },
+                _ = time::sleep_until(idle_deadline.unwrap_or_else(time::Instant::now)), if idle_deadline.is_some() => {
+                    debug!("idle timeout, closing connection");
+                    self.idle_close().await;
+                    self.end = Some(DisconnectReason::IdleTimeout);
+                    break;
+                },
+                _ = Self::parked_room(&self.parked, &self.searchmap), if self.parked.is_some() => {
+                    if let Some(Parked { id, item, done }) = self.parked.take() {
+                        self.deliver(id, item, done);
+                    }
+                },
+                resp = self.stream.next(), if self.parked.is_none() => {
                     let (id, (tag, controls)) = match resp {
-                        None => break,
+                        None => {
+                            self.end = Some(if self.unbound {
+                                DisconnectReason::Unbind
+                            } else {
+                                DisconnectReason::ServerClosed
+                            });
+                            break;
+                        },
                         Some(Err(e)) => {
                             warn!("socket receive error: {}", e);
+                            #[cfg(feature = "tracing")]
+                            tracing::warn!(error = %e, "socket receive error");
                             return Err(LdapError::from(e));
                         },
                         Some(Ok(resp)) => resp,
                     };
-                    if let Some(tx) = self.searchmap.get(&id) {
+                    #[cfg(feature = "metrics")]
+                    self.tracker.received(id, self.stream.codec().last_in);
+                    if id != 0 && Some(id) != self.heartbeat_id() {
+                        self.touch();
+                    }
+                    if id == 0 {
+                        self.handle_unsolicited(tag, controls);
+                    } else if self.searchmap.contains_key(&id) {
                         let protoop = if let Tag::StructureTag(protoop) = tag {
                             protoop
                         } else {
                             panic!("unmatched tag structure: {:?}", tag);
                         };
-                        let (item, mut remove) = match protoop.id {
+                        let (item, done) = match protoop.id {
                             4 | 25 => (SearchItem::Entry(protoop), false),
                             5 => (SearchItem::Done(Tag::StructureTag(protoop).into()), true),
                             19 => (SearchItem::Referral(protoop), false),
+                            24 => {
+                                let LdapResultExt(res, exop) = Tag::StructureTag(protoop).into();
+                                (SearchItem::ExopDone(res, exop), true)
+                            }
                             _ => panic!("unrecognized op id: {}", protoop.id),
                         };
-                        if let Err(e) = tx.send((item, controls)) {
-                            warn!("ldap search item send error, op={}: {:?}", id, e);
-                            remove = true;
-                        }
-                        if remove {
-                            self.searchmap.remove(&id);
+                        #[cfg(feature = "metrics")]
+                        match item {
+                            SearchItem::Done(ref res) | SearchItem::ExopDone(ref res, _) => {
+                                self.tracker.finish(id, Some(res.rc))
+                            }
+                            _ => (),
                         }
+                        self.deliver(id, (item, controls), done);
+                    } else if is_intermediate(&tag) && self.resultmap.contains_key(&id) {
+                        // An Intermediate response for an operation expecting a single
+                        // result can't be returned to the caller, and must not be
+                        // mistaken for the final response.
+                        debug!("discarding intermediate response, op={}", id);
                     } else if let Some(tx) = self.resultmap.remove(&id) {
+                        #[cfg(any(feature = "metrics", feature = "audit", feature = "observer"))]
+                        let rc = result_code(&tag);
+                        #[cfg(feature = "metrics")]
+                        self.tracker.finish(id, rc);
+                        #[cfg(feature = "audit")]
+                        self.audit.finish(id, rc);
+                        #[cfg(feature = "observer")]
+                        if self.binds.remove(&id) {
+                            if let (Some(observer), Some(rc)) = (&self.observer, rc) {
+                                observer.bind_finished(rc);
+                            }
+                        }
                         if let Err(e) = tx.send((tag, controls)) {
                             warn!("ldap result send error: {:?}", e);
                         }
@@ -601,3 +1993,531 @@ impl LdapConnAsync {
         Ok(self)
     }
 }
+
+#[cfg(all(test, feature = "testkit"))]
+mod test {
+    use super::{DisconnectReason, LdapConnAsync, LdapConnSettings};
+    use crate::exop_impl::WhoAmI;
+    #[cfg(feature = "metrics")]
+    use crate::metrics::{MetricsSink, OpMetrics};
+    use crate::result::LdapError;
+    use crate::testkit::{response, MockServer};
+    use crate::{Priority, Scope};
+
+    use std::io;
+    #[cfg(feature = "metrics")]
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use lber::common::TagClass;
+    use lber::structures::{OctetString, Tag};
+    use tokio::net::TcpStream;
+    use tokio::time;
+
+    // The base entry and ten users below it.
+    fn users() -> MockServer {
+        let server = MockServer::new();
+        server.add_entry("dc=example,dc=org", vec![("dc", vec!["example"])]);
+        for i in 0..10 {
+            let dn = format!("cn=user{},dc=example,dc=org", i);
+            server.add_entry(&dn, vec![("cn", vec![format!("user{}", i)])]);
+        }
+        server
+    }
+
+    #[tokio::test]
+    async fn heartbeat() {
+        let server = MockServer::new();
+        let settings = LdapConnSettings::new().set_heartbeat(Duration::from_millis(10));
+        let (conn, mut ldap) = server.connect_with_settings(settings);
+        crate::drive!(conn);
+        time::sleep(Duration::from_millis(50)).await;
+        assert!(!ldap.is_closed());
+        let whoami = |server: &MockServer| server.received().iter().filter(|r| r.0 == 23).count();
+        assert!(whoami(&server) >= 2);
+        ldap.check_alive(Duration::from_secs(1)).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn heartbeat_unanswered() {
+        let server = MockServer::new();
+        server.ignore(23);
+        let settings = LdapConnSettings::new().set_heartbeat(Duration::from_millis(10));
+        let (conn, mut ldap) = server.connect_with_settings(settings);
+        let res = time::timeout(Duration::from_secs(5), conn.drive())
+            .await
+            .expect("connection kept open");
+        match res {
+            Err(LdapError::Io { source }) => assert_eq!(source.kind(), io::ErrorKind::TimedOut),
+            res => panic!("unexpected result: {:?}", res),
+        }
+        assert!(ldap.is_closed());
+        assert_eq!(server.received().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn priority_lanes() {
+        let server = MockServer::new();
+        server.add_entry("dc=example,dc=org", vec![("dc", vec!["example"])]);
+        let settings = LdapConnSettings::new().set_max_in_flight(1);
+        let (conn, ldap) = server.connect_with_settings(settings);
+        let searches = [Priority::Low, Priority::Normal, Priority::High]
+            .iter()
+            .map(|&priority| {
+                let mut ldap = ldap.clone();
+                async move {
+                    ldap.with_priority(priority)
+                        .search("dc=example,dc=org", Scope::Base, "(dc=*)", vec!["dc"])
+                        .await
+                        .unwrap()
+                        .success()
+                        .unwrap();
+                }
+            });
+        // The searches are polled first, so that they're all submitted, and numbered
+        // in the order of submission, by the time the connection is driven.
+        tokio::select! {
+            biased;
+            _ = futures::future::join_all(searches) => (),
+            res = conn.drive() => panic!("connection ended: {:?}", res),
+        }
+        let written = server
+            .received()
+            .into_iter()
+            .filter(|r| r.0 == 3)
+            .map(|r| r.1)
+            .collect::<Vec<_>>();
+        assert_eq!(written, vec![3, 2, 1]);
+    }
+
+    #[tokio::test]
+    async fn bind_barrier() {
+        let server = MockServer::new();
+        server.add_entry("dc=example,dc=org", vec![("dc", vec!["example"])]);
+        let settings = LdapConnSettings::new().set_max_in_flight(1);
+        let (conn, ldap) = server.connect_with_settings(settings);
+        let search = |priority| {
+            let mut ldap = ldap.clone();
+            async move {
+                ldap.with_priority(priority)
+                    .search("dc=example,dc=org", Scope::Base, "(dc=*)", vec!["dc"])
+                    .await
+                    .unwrap()
+                    .success()
+                    .unwrap();
+            }
+        };
+        let mut binder = ldap.clone();
+        let bind = async move {
+            binder
+                .with_priority(Priority::High)
+                .simple_bind("", "")
+                .await
+                .unwrap()
+                .success()
+                .unwrap();
+        };
+        tokio::select! {
+            biased;
+            _ = futures::future::join3(search(Priority::Low), bind, search(Priority::High)) => (),
+            res = conn.drive() => panic!("connection ended: {:?}", res),
+        }
+        let written = server.received();
+        assert_eq!(written, vec![(3, 1), (0, 2), (3, 3)]);
+    }
+
+    #[tokio::test]
+    async fn bounded_stream_buffer() {
+        let server = users();
+        let settings = LdapConnSettings::new().set_stream_buffer(1);
+        let (conn, mut ldap) = server.connect_with_settings(settings);
+        crate::drive!(conn);
+        let (entries, _) = ldap
+            .search("dc=example,dc=org", Scope::Subtree, "(cn=*)", vec!["cn"])
+            .await
+            .unwrap()
+            .success()
+            .unwrap();
+        assert_eq!(entries.len(), 10);
+    }
+
+    #[tokio::test]
+    async fn full_stream_buffer() {
+        let server = users();
+        let settings = LdapConnSettings::new().set_stream_buffer(1);
+        let (conn, mut ldap) = server.connect_with_settings(settings);
+        crate::drive!(conn);
+        let mut stream = ldap
+            .clone()
+            .streaming_search("dc=example,dc=org", Scope::Subtree, "(cn=*)", vec!["cn"])
+            .await
+            .unwrap();
+        let msgid = stream.ldap_handle().last_id();
+        // Let the replies arrive and fill the buffer, which is then left unread.
+        time::sleep(Duration::from_millis(50)).await;
+        let second = async {
+            ldap.abandon(msgid).await.unwrap();
+            ldap.compare("cn=user0,dc=example,dc=org", "cn", "user0")
+                .await
+                .unwrap()
+                .equal()
+                .unwrap()
+        };
+        let equal = time::timeout(Duration::from_secs(5), second)
+            .await
+            .expect("operations blocked by the full stream");
+        assert!(equal);
+        drop(stream);
+    }
+
+    #[tokio::test]
+    async fn idle_timeout() {
+        let server = MockServer::new();
+        let closed = Arc::new(AtomicBool::new(false));
+        let flag = closed.clone();
+        let settings = LdapConnSettings::new()
+            .set_idle_timeout(Duration::from_millis(30))
+            .set_idle_callback(move || flag.store(true, Ordering::SeqCst));
+        let (conn, mut ldap) = server.connect_with_settings(settings);
+        let driver = tokio::spawn(conn.drive());
+        ldap.simple_bind("", "").await.unwrap();
+        driver.await.unwrap().unwrap();
+        assert!(closed.load(Ordering::SeqCst));
+        assert!(ldap.is_closed());
+    }
+
+    #[cfg(feature = "metrics")]
+    #[derive(Default)]
+    struct QueueDepth(AtomicUsize);
+
+    #[cfg(feature = "metrics")]
+    impl MetricsSink for QueueDepth {
+        fn op_finished(&self, _metrics: &OpMetrics) {}
+
+        fn queue_depth(&self, depth: usize) {
+            self.0.fetch_max(depth, Ordering::SeqCst);
+        }
+    }
+
+    #[cfg(feature = "metrics")]
+    #[tokio::test]
+    async fn in_flight_limit() {
+        let server = MockServer::new();
+        server.add_entry("dc=example,dc=org", vec![("dc", vec!["example"])]);
+        let depth = Arc::new(QueueDepth::default());
+        let settings = LdapConnSettings::new()
+            .set_max_in_flight(1)
+            .set_metrics_sink(depth.clone());
+        let (conn, ldap) = server.connect_with_settings(settings);
+        crate::drive!(conn);
+        let searches = (0..3).map(|_| {
+            let mut ldap = ldap.clone();
+            async move {
+                ldap.search(
+                    "dc=example,dc=org",
+                    Scope::Base,
+                    "(objectClass=*)",
+                    vec!["dc"],
+                )
+                .await
+                .unwrap()
+                .success()
+                .unwrap()
+                .0
+                .len()
+            }
+        });
+        let counts = futures::future::join_all(searches).await;
+        assert_eq!(counts, vec![1, 1, 1]);
+        assert_eq!(depth.0.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn completion_reason() {
+        let server = MockServer::new();
+        let (mut conn, mut ldap) = server.connect();
+        let mut completion = conn.completion();
+        crate::drive!(conn);
+        assert_eq!(completion.reason(), None);
+        ldap.unbind().await.unwrap();
+        assert_eq!(completion.wait().await, DisconnectReason::Unbind);
+        let (mut conn, ldap) = server.connect();
+        let mut completion = conn.completion();
+        crate::drive!(conn);
+        drop(ldap);
+        assert_eq!(completion.wait().await, DisconnectReason::HandlesDropped);
+    }
+
+    #[tokio::test]
+    async fn op_timeout() {
+        let server = users();
+        server.ignore(3);
+        let settings = LdapConnSettings::new().set_op_timeout(Duration::from_millis(30));
+        let (conn, mut ldap) = server.connect_with_settings(settings);
+        crate::drive!(conn);
+        let res = ldap
+            .search("dc=example,dc=org", Scope::Base, "(dc=*)", vec!["dc"])
+            .await;
+        assert!(matches!(res, Err(LdapError::Timeout { .. })));
+        let mut stream = ldap
+            .streaming_search("dc=example,dc=org", Scope::Base, "(dc=*)", vec!["dc"])
+            .await
+            .unwrap();
+        assert!(matches!(
+            stream.next().await,
+            Err(LdapError::Timeout { .. })
+        ));
+        ldap.compare("dc=example,dc=org", "dc", "example")
+            .await
+            .unwrap();
+        // An explicit timeout takes precedence.
+        let settings = LdapConnSettings::new().set_op_timeout(Duration::from_secs(60));
+        let (conn, mut ldap) = server.connect_with_settings(settings);
+        crate::drive!(conn);
+        let search = ldap.with_timeout(Duration::from_millis(30)).search(
+            "dc=example,dc=org",
+            Scope::Base,
+            "(dc=*)",
+            vec!["dc"],
+        );
+        let res = time::timeout(Duration::from_secs(5), search)
+            .await
+            .expect("explicit timeout");
+        assert!(matches!(res, Err(LdapError::Timeout { .. })));
+    }
+
+    #[tokio::test]
+    async fn tcp_stream() {
+        let server = users();
+        let url = server.listen().await;
+        let stream = TcpStream::connect(url.trim_start_matches("ldap://"))
+            .await
+            .unwrap();
+        let (conn, mut ldap) = LdapConnAsync::from_stream(stream, LdapConnSettings::new());
+        crate::drive!(conn);
+        let (entries, _) = ldap
+            .search("dc=example,dc=org", Scope::OneLevel, "(cn=*)", vec!["cn"])
+            .await
+            .unwrap()
+            .success()
+            .unwrap();
+        assert_eq!(entries.len(), 10);
+        ldap.unbind().await.unwrap();
+    }
+
+    #[cfg(all(unix, feature = "ldapi"))]
+    #[tokio::test]
+    async fn ldapi_external() {
+        let server = users();
+        let path = std::env::temp_dir().join(format!("ldap3-test-{}.sock", std::process::id()));
+        let url = server.listen_unix(&path);
+        let settings = LdapConnSettings::new().set_ldapi_external(true);
+        // The mock server rejects SASL binds unless told otherwise.
+        match LdapConnAsync::with_settings(settings.clone(), &url).await {
+            Err(LdapError::LdapResult { result }) => assert_eq!(result.rc, 7),
+            res => panic!("unexpected result: {:?}", res.map(|_| ())),
+        }
+        server.script(0, |id| vec![(id, response(1, 0, ""), None)]);
+        let (conn, mut ldap) = LdapConnAsync::with_settings(settings, &url).await.unwrap();
+        crate::drive!(conn);
+        let (entries, _) = ldap
+            .search("dc=example,dc=org", Scope::OneLevel, "(cn=*)", vec!["cn"])
+            .await
+            .unwrap()
+            .success()
+            .unwrap();
+        assert_eq!(entries.len(), 10);
+        let ops = server
+            .received()
+            .into_iter()
+            .map(|r| r.0)
+            .collect::<Vec<_>>();
+        assert_eq!(ops, vec![0, 0, 3]);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[cfg(all(windows, feature = "ldapi"))]
+    #[tokio::test]
+    async fn ldapi_named_pipe() {
+        let server = users();
+        let url = server.listen_pipe(&format!("ldap3-test-{}", std::process::id()));
+        for _ in 0..2 {
+            let (conn, mut ldap) = LdapConnAsync::new(&url).await.unwrap();
+            crate::drive!(conn);
+            let (entries, _) = ldap
+                .search("dc=example,dc=org", Scope::OneLevel, "(cn=*)", vec!["cn"])
+                .await
+                .unwrap()
+                .success()
+                .unwrap();
+            assert_eq!(entries.len(), 10);
+            ldap.unbind().await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn unsolicited_notification() {
+        let server = MockServer::new();
+        // Notice of Disconnection precedes the answer to the first Extended request.
+        server.script(23, |id| {
+            let mut notice = response(24, 52, "going down");
+            if let Tag::Sequence(ref mut seq) = notice {
+                seq.inner.push(Tag::OctetString(OctetString {
+                    class: TagClass::Context,
+                    id: 10,
+                    inner: b"1.3.6.1.4.1.1466.20036".to_vec(),
+                }));
+            }
+            vec![(0, notice, None), (id, response(24, 0, ""), None)]
+        });
+        let (mut conn, mut ldap) = server.connect();
+        let mut notifs = conn.unsolicited_notifications();
+        crate::drive!(conn);
+        ldap.extended(WhoAmI).await.unwrap().success().unwrap();
+        let notif = time::timeout(Duration::from_secs(1), notifs.recv())
+            .await
+            .expect("notification")
+            .expect("open channel");
+        assert_eq!(notif.0.name.as_deref(), Some("1.3.6.1.4.1.1466.20036"));
+        assert_eq!(notif.1.rc, 52);
+        assert_eq!(notif.1.text, "going down");
+    }
+}
+
+#[cfg(all(test, feature = "testkit", feature = "tls-native"))]
+mod tls_test {
+    use super::{LdapConnAsync, LdapConnSettings, StartTlsPolicy, TlsVersion};
+    use crate::exop_impl::WhoAmI;
+    use crate::result::{LdapError, Result};
+    use crate::testkit::{response, MockServer};
+
+    use native_tls::{Certificate, Identity, Protocol, TlsAcceptor, TlsAcceptorBuilder};
+
+    const CA: &[u8] = include_bytes!("../data/testtls/ca.pem");
+    const CERT: &[u8] = include_bytes!("../data/testtls/server.pem");
+    const KEY: &[u8] = include_bytes!("../data/testtls/server.key");
+
+    // Acceptor with a certificate for "localhost" and "ldap.example.test", issued by CA.
+    fn acceptor() -> TlsAcceptorBuilder {
+        TlsAcceptor::builder(Identity::from_pkcs8(CERT, KEY).unwrap())
+    }
+
+    // Listen for TLS connections, returning the port.
+    async fn listen(server: &MockServer, acceptor: TlsAcceptorBuilder) -> u16 {
+        let url = server.listen_tls(acceptor.build().unwrap()).await;
+        url.rsplit(':').next().unwrap().parse().unwrap()
+    }
+
+    // Connect and check the connection with a Who Am I operation.
+    async fn connect(settings: LdapConnSettings, url: &str) -> Result<()> {
+        let (conn, mut ldap) = LdapConnAsync::with_settings(settings, url).await?;
+        crate::drive!(conn);
+        ldap.extended(WhoAmI).await?.success()?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn tls_session_info() {
+        let server = MockServer::new();
+        let port = listen(&server, acceptor()).await;
+        let settings = LdapConnSettings::new().add_root_certificate(CA);
+        let url = format!("ldaps://localhost:{}", port);
+        let (conn, mut ldap) = LdapConnAsync::with_settings(settings, &url).await.unwrap();
+        crate::drive!(conn);
+        let info = ldap.tls_info().cloned().unwrap();
+        let cert = Certificate::from_pem(CERT).unwrap().to_der().unwrap();
+        assert_eq!(info.peer_certificates, vec![cert]);
+        // Not reported by the native backend.
+        assert_eq!((info.version, info.cipher), (None, None));
+        ldap.extended(WhoAmI).await.unwrap().success().unwrap();
+        let (conn, ldap) = server.connect();
+        crate::drive!(conn);
+        assert_eq!(ldap.tls_info(), None);
+    }
+
+    #[tokio::test]
+    async fn extra_root_certs() {
+        let server = MockServer::new();
+        let port = listen(&server, acceptor()).await;
+        let url = format!("ldaps://localhost:{}", port);
+        assert!(connect(LdapConnSettings::new(), &url).await.is_err());
+        let settings = LdapConnSettings::new().add_root_certificate(CA);
+        connect(settings, &url).await.unwrap();
+        let der = Certificate::from_pem(CA).unwrap().to_der().unwrap();
+        let settings = LdapConnSettings::new().add_root_certificate(&der);
+        connect(settings, &url).await.unwrap();
+        let bundle = concat!(env!("CARGO_MANIFEST_DIR"), "/data/testtls/ca.pem");
+        let settings = LdapConnSettings::new().set_ca_bundle(bundle);
+        connect(settings, &url).await.unwrap();
+        let settings = LdapConnSettings::new().set_ca_bundle("/nonexistent/ca.pem");
+        let res = connect(settings, &url).await;
+        assert!(matches!(res, Err(LdapError::Io { .. })));
+    }
+
+    #[tokio::test]
+    async fn tls_server_name() {
+        let server = MockServer::new();
+        let port = listen(&server, acceptor()).await;
+        // The certificate doesn't contain the address.
+        let url = format!("ldaps://127.0.0.1:{}", port);
+        let settings = LdapConnSettings::new().add_root_certificate(CA);
+        assert!(connect(settings.clone(), &url).await.is_err());
+        let res = connect(
+            settings.clone().set_tls_server_name("other.example.test"),
+            &url,
+        )
+        .await;
+        assert!(res.is_err());
+        connect(settings.set_tls_server_name("ldap.example.test"), &url)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn min_tls_version() {
+        let server = MockServer::new();
+        let mut tls12 = acceptor();
+        tls12.max_protocol_version(Some(Protocol::Tlsv12));
+        let port = listen(&server, tls12).await;
+        let url = format!("ldaps://localhost:{}", port);
+        let settings = LdapConnSettings::new().add_root_certificate(CA);
+        connect(settings.clone(), &url).await.unwrap();
+        let settings = settings.set_min_tls_version(TlsVersion::Tls13);
+        let res = connect(settings, &url).await;
+        assert!(matches!(res, Err(LdapError::NativeTLS { .. })));
+    }
+
+    #[tokio::test]
+    async fn starttls_rejected() {
+        // The mock server doesn't support StartTLS.
+        let server = MockServer::new();
+        let url = server.listen().await;
+        let settings = LdapConnSettings::new().set_starttls_policy(StartTlsPolicy::Demand);
+        match connect(settings, &url).await {
+            Err(LdapError::LdapResult { result }) => assert_eq!(result.rc, 2),
+            res => panic!("unexpected result: {:?}", res),
+        }
+        let settings = LdapConnSettings::new().set_starttls_policy(StartTlsPolicy::Allow);
+        connect(settings, &url).await.unwrap();
+        connect(LdapConnSettings::new(), &url).await.unwrap();
+        // StartTLS, then Who Am I for Demand and Allow; just Who Am I for Never.
+        let exops = server.received().iter().filter(|r| r.0 == 23).count();
+        assert_eq!(exops, 4);
+    }
+
+    #[tokio::test]
+    async fn starttls_handshake_failure() {
+        // StartTLS is accepted, but the server doesn't switch to TLS.
+        let server = MockServer::new();
+        server.script(23, |id| vec![(id, response(24, 0, ""), None)]);
+        let url = server.listen().await;
+        for policy in [StartTlsPolicy::Demand, StartTlsPolicy::Allow] {
+            let settings = LdapConnSettings::new()
+                .add_root_certificate(CA)
+                .set_starttls_policy(policy);
+            assert!(connect(settings, &url).await.is_err());
+        }
+    }
+}
@@ -1,18 +1,18 @@
+use std::any::Any;
 use std::collections::HashMap;
+use std::sync::{OnceLock, RwLock};
 
 use lber::structure::{StructureTag, PL};
 use lber::structures::{ASNTag, Boolean, OctetString, Sequence, Tag};
 use lber::universal::Types;
 
-use lazy_static::lazy_static;
-
 /// Recognized control types.
 ///
 /// The variants can't be exhaustively matched, since the list of
 /// recognized and internally implemented controls can change from one
 /// release to the next.
 #[non_exhaustive]
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum ControlType {
     PagedResults,
     PostReadResp,
@@ -21,18 +21,54 @@ pub enum ControlType {
     SyncState,
     ManageDsaIt,
     MatchedValues,
+    AuthzIdResp,
+    EntryChangeNotification,
+    DirSync,
+    PasswordPolicyResp,
+    AccountUsabilityResp,
+    DereferenceResp,
+    VirtualListViewResp,
+    /// Control whose parser was registered with
+    /// [`register_control_parser()`](fn.register_control_parser.html).
+    Registered,
 }
 
+mod account_usability;
+pub use self::account_usability::{AccountUnusable, AccountUsability, AccountUsabilityResp};
+
 mod assertion;
 pub use self::assertion::Assertion;
 
+mod authz_id;
+pub use self::authz_id::{AuthzIdRequest, AuthzIdResp};
+
 mod content_sync;
 pub use self::content_sync::parse_syncinfo;
 pub use self::content_sync::{EntryState, RefreshMode, SyncDone, SyncInfo, SyncRequest, SyncState};
 
+mod deref;
+pub use self::deref::{DerefRes, Dereference, DereferenceResp};
+
+mod dirsync;
+pub use self::dirsync::DirSync;
+
+mod dont_use_copy;
+pub use self::dont_use_copy::DontUseCopy;
+
+mod no_op;
+pub use self::no_op::{NoOp, NO_OP_RC};
+
 mod paged_results;
 pub use self::paged_results::PagedResults;
 
+mod persistent_search;
+pub use self::persistent_search::{EntryChangeNotification, EntryChangeType, PersistentSearch};
+
+mod ppolicy;
+pub use self::ppolicy::{
+    PasswordPolicy, PasswordPolicyError, PasswordPolicyResp, PasswordPolicyWarning,
+};
+
 mod proxy_auth;
 pub use self::proxy_auth::ProxyAuth;
 
@@ -42,25 +78,105 @@ pub use self::read_entry::{PostRead, PostReadResp, PreRead, PreReadResp, ReadEnt
 mod relax_rules;
 pub use self::relax_rules::RelaxRules;
 
+mod subentries;
+pub use self::subentries::Subentries;
+
+mod vlv;
+pub use self::vlv::{VirtualListView, VirtualListViewResp, VlvTarget};
+
 mod manage_dsa_it;
 pub use self::manage_dsa_it::ManageDsaIt;
 
 mod matched_values;
 pub use self::matched_values::MatchedValues;
 
+type DynParser = fn(&[u8]) -> Box<dyn Any + Send>;
+
+fn parse_boxed<T: ControlParser + Send + 'static>(val: &[u8]) -> Box<dyn Any + Send> {
+    Box::new(T::parse(val))
+}
+
+// Controls implemented by the library: the OID, the type, and the parser for
+// response controls. Both lookup maps below are built from this table.
 #[rustfmt::skip]
-lazy_static! {
-    static ref CONTROLS: HashMap<&'static str, ControlType> = {
-        let mut map = HashMap::new();
-        map.insert(self::paged_results::PAGED_RESULTS_OID, ControlType::PagedResults);
-        map.insert(self::read_entry::POST_READ_OID, ControlType::PostReadResp);
-        map.insert(self::read_entry::PRE_READ_OID, ControlType::PreReadResp);
-        map.insert(self::content_sync::SYNC_DONE_OID, ControlType::SyncDone);
-        map.insert(self::content_sync::SYNC_STATE_OID, ControlType::SyncState);
-        map.insert(self::manage_dsa_it::MANAGE_DSA_IT_OID, ControlType::ManageDsaIt);
-        map.insert(self::matched_values::MATCHED_VALUES_OID, ControlType::MatchedValues);
-        map
-    };
+static BUILTIN: &[(&str, ControlType, Option<DynParser>)] = &[
+    (self::paged_results::PAGED_RESULTS_OID, ControlType::PagedResults, Some(parse_boxed::<PagedResults>)),
+    (self::read_entry::POST_READ_OID, ControlType::PostReadResp, Some(parse_boxed::<ReadEntryResp>)),
+    (self::read_entry::PRE_READ_OID, ControlType::PreReadResp, Some(parse_boxed::<ReadEntryResp>)),
+    (self::content_sync::SYNC_DONE_OID, ControlType::SyncDone, Some(parse_boxed::<SyncDone>)),
+    (self::content_sync::SYNC_STATE_OID, ControlType::SyncState, Some(parse_boxed::<SyncState>)),
+    (self::manage_dsa_it::MANAGE_DSA_IT_OID, ControlType::ManageDsaIt, None),
+    (self::matched_values::MATCHED_VALUES_OID, ControlType::MatchedValues, None),
+    (self::authz_id::AUTHZ_ID_RESPONSE_OID, ControlType::AuthzIdResp, Some(parse_boxed::<AuthzIdResp>)),
+    (self::persistent_search::ENTRY_CHANGE_NOTIFICATION_OID, ControlType::EntryChangeNotification, Some(parse_boxed::<EntryChangeNotification>)),
+    (self::dirsync::DIRSYNC_OID, ControlType::DirSync, Some(parse_boxed::<DirSync>)),
+    (self::ppolicy::PASSWORD_POLICY_OID, ControlType::PasswordPolicyResp, Some(parse_boxed::<PasswordPolicyResp>)),
+    (self::account_usability::ACCOUNT_USABILITY_OID, ControlType::AccountUsabilityResp, Some(parse_boxed::<AccountUsabilityResp>)),
+    (self::deref::DEREF_OID, ControlType::DereferenceResp, Some(parse_boxed::<DereferenceResp>)),
+    (self::vlv::VLV_RESPONSE_OID, ControlType::VirtualListViewResp, Some(parse_boxed::<VirtualListViewResp>)),
+];
+
+fn controls() -> &'static HashMap<&'static str, ControlType> {
+    static CONTROLS: OnceLock<HashMap<&'static str, ControlType>> = OnceLock::new();
+    CONTROLS.get_or_init(|| {
+        BUILTIN
+            .iter()
+            .map(|&(oid, ctype, _)| (oid, ctype))
+            .collect()
+    })
+}
+
+fn parsers() -> &'static RwLock<HashMap<String, DynParser>> {
+    static PARSERS: OnceLock<RwLock<HashMap<String, DynParser>>> = OnceLock::new();
+    PARSERS.get_or_init(|| {
+        let map = BUILTIN
+            .iter()
+            .filter_map(|&(oid, _, parser)| Some((oid.to_owned(), parser?)))
+            .collect();
+        RwLock::new(map)
+    })
+}
+
+/// Register the parser for response controls with `oid`.
+///
+/// Afterwards, every control with that OID received on any connection will have
+/// `Some(ControlType::Registered)` as its type, unless it's implemented by the library,
+/// and the value can be obtained with [`Control::parsed()`](struct.Control.html#method.parsed)
+/// or [`Control::downcast()`](struct.Control.html#method.downcast). This applies equally
+/// to the controls of operation results, search entries and intermediate messages.
+/// Registering a parser for an OID implemented by the library replaces its parser, but
+/// doesn't change the control type. The parsers for the controls implemented by the
+/// library are registered from the start.
+///
+/// ```rust
+/// use ldap3::controls::{register_control_parser, Control, ControlParser, ControlType, RawControl};
+///
+/// #[derive(Debug, PartialEq)]
+/// struct Quota(Vec<u8>);
+///
+/// impl ControlParser for Quota {
+///     fn parse(val: &[u8]) -> Self {
+///         Quota(val.to_vec())
+///     }
+/// }
+///
+/// register_control_parser::<Quota>("1.3.6.1.4.1.99999.1");
+/// let ctrl = Control(Some(ControlType::Registered), RawControl {
+///     ctype: "1.3.6.1.4.1.99999.1".to_owned(),
+///     crit: false,
+///     val: Some(vec![42]),
+/// });
+/// assert_eq!(ctrl.downcast::<Quota>(), Some(Quota(vec![42])));
+/// ```
+pub fn register_control_parser<T: ControlParser + Send + 'static>(oid: &str) {
+    parsers()
+        .write()
+        .expect("parsers lock")
+        .insert(oid.to_owned(), parse_boxed::<T>);
+}
+
+fn registered_parser(oid: &str) -> Option<DynParser> {
+    parsers().read().expect("parsers lock").get(oid).copied()
 }
 
 pub trait IntoRawControlVec {
@@ -131,6 +247,27 @@ pub trait ControlParser {
 #[derive(Clone, Debug)]
 pub struct Control(pub Option<ControlType>, pub RawControl);
 
+impl Control {
+    /// Parse the control with the registered parser for its OID. Returns `None` if
+    /// there's no parser, or the control has no value.
+    pub fn parsed(&self) -> Option<Box<dyn Any + Send>> {
+        let parser = registered_parser(&self.1.ctype)?;
+        Some(parser(self.1.val.as_ref()?))
+    }
+
+    /// Parse the control with the registered parser for its OID, if the parser produces
+    /// values of type `T`.
+    ///
+    /// ```rust
+    /// # use ldap3::controls::{Control, PagedResults};
+    /// let ctrl = Control(None, PagedResults { size: 0, cookie: vec![] }.into());
+    /// assert_eq!(ctrl.downcast::<PagedResults>().unwrap().size, 0);
+    /// ```
+    pub fn downcast<T: 'static>(&self) -> Option<T> {
+        self.parsed()?.downcast::<T>().ok().map(|t| *t)
+    }
+}
+
 /// Generic control.
 ///
 /// This struct can be used both for request and response controls. For requests, an
@@ -190,6 +327,14 @@ pub fn build_tag(rc: RawControl) -> StructureTag {
     .into_structure()
 }
 
+// Type of a received control: built-in, registered by the application, or unknown.
+pub(crate) fn control_type(ctype: &str) -> Option<ControlType> {
+    controls()
+        .get(ctype)
+        .copied()
+        .or_else(|| registered_parser(ctype).map(|_| ControlType::Registered))
+}
+
 pub fn parse_controls(t: StructureTag) -> Vec<Control> {
     let tags = t.expect_constructed().expect("result sequence").into_iter();
     let mut ctrls = Vec::new();
@@ -219,15 +364,95 @@ pub fn parse_controls(t: StructureTag) -> Vec<Control> {
                 _ => panic!("decoding error"),
             },
         };
-        let val = match maybe_val {
-            None => None,
-            Some(v) => Some(v.expect_primitive().expect("octet string")),
+        let val = maybe_val.map(|v| v.expect_primitive().expect("octet string"));
+        ctrls.push(Control(
+            control_type(&ctype),
+            RawControl { ctype, crit, val },
+        ));
+    }
+    ctrls
+}
+
+#[cfg(test)]
+mod test {
+    use super::{build_tag, parse_controls, register_control_parser, BUILTIN};
+    use super::{Control, ControlParser, ControlType, PagedResults, RawControl};
+
+    use lber::structures::{ASNTag, Sequence, Tag};
+
+    // Parse the controls as they would arrive in a message.
+    fn received(ctrls: Vec<RawControl>) -> Vec<Control> {
+        let seq = Tag::Sequence(Sequence {
+            inner: ctrls
+                .into_iter()
+                .map(|c| Tag::StructureTag(build_tag(c)))
+                .collect(),
+            ..Default::default()
+        });
+        parse_controls(seq.into_structure())
+    }
+
+    #[test]
+    fn builtin_controls() {
+        for &(oid, ctype, parser) in BUILTIN.iter() {
+            let ctrl = received(vec![RawControl {
+                ctype: oid.to_owned(),
+                crit: false,
+                val: None,
+            }])
+            .pop()
+            .unwrap();
+            assert_eq!(ctrl.0, Some(ctype), "{}", oid);
+            if parser.is_none() {
+                assert!(ctrl.parsed().is_none(), "{}", oid);
+            }
+        }
+        let paged = PagedResults {
+            size: 42,
+            cookie: b"next".to_vec(),
         };
-        let known_type = match CONTROLS.get(&*ctype) {
-            Some(val) => Some(*val),
-            None => None,
+        let ctrl = received(vec![paged.into()]).pop().unwrap();
+        assert_eq!(ctrl.0, Some(ControlType::PagedResults));
+        let parsed = ctrl.downcast::<PagedResults>().unwrap();
+        assert_eq!((parsed.size, parsed.cookie), (42, b"next".to_vec()));
+        assert!(ctrl.downcast::<String>().is_none());
+        assert!(ctrl.parsed().unwrap().is::<PagedResults>());
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct Counter(usize);
+
+    impl ControlParser for Counter {
+        fn parse(val: &[u8]) -> Self {
+            Counter(val.len())
+        }
+    }
+
+    #[test]
+    fn registered_parser() {
+        const OID: &str = "1.3.6.1.4.1.99999.2";
+        let raw = RawControl {
+            ctype: OID.to_owned(),
+            crit: true,
+            val: Some(vec![1, 2, 3]),
         };
-        ctrls.push(Control(known_type, RawControl { ctype, crit, val }));
+        let ctrl = received(vec![raw.clone()]).pop().unwrap();
+        assert_eq!(ctrl.0, None);
+        assert!(ctrl.parsed().is_none());
+        register_control_parser::<Counter>(OID);
+        let ctrl = received(vec![raw]).pop().unwrap();
+        assert_eq!(ctrl.0, Some(ControlType::Registered));
+        assert!(ctrl.1.crit);
+        assert_eq!(ctrl.downcast::<Counter>(), Some(Counter(3)));
+        // No value, nothing to parse.
+        let ctrl = received(vec![RawControl {
+            ctype: OID.to_owned(),
+            crit: false,
+            val: None,
+        }])
+        .pop()
+        .unwrap();
+        assert_eq!(ctrl.0, Some(ControlType::Registered));
+        assert!(ctrl.parsed().is_none());
     }
-    ctrls
 }
@@ -0,0 +1,145 @@
+use super::{ControlParser, MakeCritical, RawControl};
+
+use lber::common::TagClass;
+use lber::parse::parse_tag;
+use lber::structure::{StructureTag, PL};
+use lber::IResult;
+
+pub const ACCOUNT_USABILITY_OID: &str = "1.3.6.1.4.1.42.2.27.9.5.8";
+
+/// Account Usability request control, as implemented by 389-ds and other
+/// Sun-derived servers.
+///
+/// When attached to a Search, the server returns the
+/// [`AccountUsabilityResp`](struct.AccountUsabilityResp.html) control with every
+/// entry, telling whether the account can be used for binding. The user must be
+/// allowed to use the control by the server's access rules.
+pub struct AccountUsability;
+
+impl MakeCritical for AccountUsability {}
+
+impl From<AccountUsability> for RawControl {
+    fn from(_au: AccountUsability) -> RawControl {
+        RawControl {
+            ctype: ACCOUNT_USABILITY_OID.to_owned(),
+            crit: false,
+            val: None,
+        }
+    }
+}
+
+/// Account Usability response control.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AccountUsabilityResp {
+    /// The account is usable. The value is the number of seconds until the password
+    /// expires, or `None` if it doesn't expire.
+    Available(Option<u32>),
+    /// The account isn't usable, for the reasons given.
+    NotAvailable(AccountUnusable),
+}
+
+/// Reasons for an account not being usable.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AccountUnusable {
+    /// The account is inactivated.
+    pub inactive: bool,
+    /// The password has been reset, and must be changed.
+    pub reset: bool,
+    /// The password has expired.
+    pub expired: bool,
+    /// Grace logins remaining after the expiration.
+    pub remaining_grace: Option<u32>,
+    /// Seconds until a locked account is unlocked.
+    pub seconds_before_unlock: Option<u32>,
+}
+
+impl AccountUnusable {
+    /// Whether the account is temporarily locked. The control can't express a
+    /// permanent lockout.
+    pub fn is_locked(&self) -> bool {
+        self.seconds_before_unlock.is_some()
+    }
+}
+
+// Signed INTEGER; the servers use -1 for "never".
+fn int(tag: StructureTag) -> i64 {
+    let val = tag.expect_primitive().expect("account usability: integer");
+    let init = if val.first().is_some_and(|b| b & 0x80 != 0) {
+        -1
+    } else {
+        0
+    };
+    val.iter().fold(init, |acc, &b| acc << 8 | b as i64)
+}
+
+fn non_negative(tag: StructureTag) -> Option<u32> {
+    let val = int(tag);
+    if val < 0 {
+        None
+    } else {
+        Some(val as u32)
+    }
+}
+
+impl ControlParser for AccountUsabilityResp {
+    fn parse(val: &[u8]) -> AccountUsabilityResp {
+        let tag = match parse_tag(val) {
+            IResult::Done(_, tag) => tag,
+            _ => panic!("account usability: failed to parse tag"),
+        };
+        match (tag.class, tag.id) {
+            (TagClass::Context, 0) => AccountUsabilityResp::Available(non_negative(tag)),
+            (TagClass::Context, 1) => {
+                let mut info = AccountUnusable::default();
+                let comps = tag
+                    .expect_constructed()
+                    .expect("account usability: more info");
+                for comp in comps {
+                    let flag = |comp: &StructureTag| match comp.payload {
+                        PL::P(ref v) => v.first().is_some_and(|&b| b != 0),
+                        PL::C(_) => panic!("account usability: constructed boolean?"),
+                    };
+                    match comp.id {
+                        0 => info.inactive = flag(&comp),
+                        1 => info.reset = flag(&comp),
+                        2 => info.expired = flag(&comp),
+                        3 => info.remaining_grace = non_negative(comp),
+                        4 => info.seconds_before_unlock = non_negative(comp),
+                        _ => panic!("account usability: unrecognized component"),
+                    }
+                }
+                AccountUsabilityResp::NotAvailable(info)
+            }
+            _ => panic!("account usability: unrecognized choice"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{AccountUnusable, AccountUsabilityResp, ControlParser};
+
+    #[test]
+    fn parse_resp() {
+        // [0] 3600
+        assert_eq!(
+            AccountUsabilityResp::parse(b"\x80\x02\x0e\x10"),
+            AccountUsabilityResp::Available(Some(3600))
+        );
+        // [0] -1
+        assert_eq!(
+            AccountUsabilityResp::parse(b"\x80\x01\xff"),
+            AccountUsabilityResp::Available(None)
+        );
+        // [1] { [2] TRUE, [3] 2, [4] 600 }
+        let resp = AccountUsabilityResp::parse(b"\xa1\x0a\x82\x01\xff\x83\x01\x02\x84\x02\x02\x58");
+        let info = AccountUnusable {
+            expired: true,
+            remaining_grace: Some(2),
+            seconds_before_unlock: Some(600),
+            ..Default::default()
+        };
+        assert!(info.is_locked());
+        assert_eq!(resp, AccountUsabilityResp::NotAvailable(info));
+    }
+}
@@ -0,0 +1,75 @@
+use super::{ControlParser, MakeCritical, RawControl};
+
+pub const AUTHZ_ID_REQUEST_OID: &str = "2.16.840.1.113730.3.4.16";
+pub const AUTHZ_ID_RESPONSE_OID: &str = "2.16.840.1.113730.3.4.15";
+
+/// Authorization Identity Request control ([RFC 3829](https://tools.ietf.org/html/rfc3829)).
+///
+/// The control can be attached to a Bind request, asking the server to return the
+/// authorization identity established by the Bind in the
+/// [`AuthzIdResp`](struct.AuthzIdResp.html) response control. The simplest way of
+/// using it is through [`Ldap::with_authzid_request()`](../struct.Ldap.html#method.with_authzid_request).
+pub struct AuthzIdRequest;
+
+impl MakeCritical for AuthzIdRequest {}
+
+impl From<AuthzIdRequest> for RawControl {
+    fn from(_air: AuthzIdRequest) -> RawControl {
+        RawControl {
+            ctype: AUTHZ_ID_REQUEST_OID.to_owned(),
+            crit: false,
+            val: None,
+        }
+    }
+}
+
+/// Authorization Identity Response control ([RFC 3829](https://tools.ietf.org/html/rfc3829)).
+///
+/// The identity has the same form as in the Who Am I? response: `dn:` followed by
+/// a DN, `u:` followed by a user name, or empty for anonymous access.
+#[derive(Clone, Debug)]
+pub struct AuthzIdResp {
+    pub authzid: String,
+}
+
+impl AuthzIdResp {
+    // The control value is absent for the anonymous identity, so it can't always go
+    // through ControlParser.
+    pub(crate) fn from_raw(rc: &RawControl) -> AuthzIdResp {
+        AuthzIdResp::parse(rc.val.as_deref().unwrap_or_default())
+    }
+}
+
+impl ControlParser for AuthzIdResp {
+    fn parse(val: &[u8]) -> AuthzIdResp {
+        AuthzIdResp {
+            authzid: String::from_utf8_lossy(val).into_owned(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{AuthzIdRequest, AuthzIdResp, RawControl, AUTHZ_ID_RESPONSE_OID};
+
+    #[test]
+    fn encode_request() {
+        let ctrl = RawControl::from(AuthzIdRequest);
+        assert_eq!(ctrl.ctype, "2.16.840.1.113730.3.4.16");
+        assert!(!ctrl.crit);
+        assert_eq!(ctrl.val, None);
+    }
+
+    #[test]
+    fn parse_resp() {
+        let mut ctrl = RawControl {
+            ctype: AUTHZ_ID_RESPONSE_OID.to_owned(),
+            crit: false,
+            val: Some(b"u:jdoe".to_vec()),
+        };
+        assert_eq!(AuthzIdResp::from_raw(&ctrl).authzid, "u:jdoe");
+        // Anonymous identity.
+        ctrl.val = None;
+        assert_eq!(AuthzIdResp::from_raw(&ctrl).authzid, "");
+    }
+}
@@ -29,18 +29,13 @@ pub struct SyncRequest {
 ///
 /// See the Content Synchronization specification
 /// ([RFC 4533](https://tools.ietf.org/html/rfc4533)).
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Default)]
 pub enum RefreshMode {
+    #[default]
     RefreshOnly,
     RefreshAndPersist,
 }
 
-impl Default for RefreshMode {
-    fn default() -> Self {
-        RefreshMode::RefreshOnly
-    }
-}
-
 impl From<RefreshMode> for i64 {
     fn from(mode: RefreshMode) -> i64 {
         match mode {
@@ -135,11 +130,9 @@ impl ControlParser for SyncState {
             .expect("syncstate: element 2")
             .expect_primitive()
             .expect("syncstate: entryUUID");
-        let cookie = if let Some(tag) = tags.next() {
-            Some(tag.expect_primitive().expect("syncstate: synCookie"))
-        } else {
-            None
-        };
+        let cookie = tags
+            .next()
+            .map(|tag| tag.expect_primitive().expect("syncstate: synCookie"));
         SyncState {
             state,
             entry_uuid,
@@ -243,7 +236,7 @@ pub fn parse_syncinfo(entry: ResultEntry) -> SyncInfo {
                                 };
                                 SyncInfo::NewCookie(cookie)
                             }
-                            1 | 2 | 3 => {
+                            1..=3 => {
                                 let mut syncinfo_val = match payload {
                                     PL::C(payload) => payload,
                                     PL::P(_) => panic!("syncinfo: [1,2,3] not a sequence"),
@@ -0,0 +1,258 @@
+use std::collections::HashMap;
+
+use super::{ControlParser, MakeCritical, RawControl};
+
+use bytes::BytesMut;
+
+use lber::common::TagClass;
+use lber::parse::parse_tag;
+use lber::structure::StructureTag;
+use lber::structures::{ASNTag, OctetString, Sequence, Tag};
+use lber::{write, IResult};
+
+pub const DEREF_OID: &str = "1.3.6.1.4.1.4203.666.5.16";
+
+/// Dereference request control
+/// ([draft-masarati-ldap-deref](https://tools.ietf.org/html/draft-masarati-ldap-deref-00)).
+///
+/// For every entry returned by a Search, the server follows the DN values of each
+/// named attribute and returns the requested attributes of the referenced entries
+/// in the [`DereferenceResp`](struct.DereferenceResp.html) control attached to the
+/// entry. Listing group members with their names thus takes a single Search instead
+/// of one for every member.
+///
+/// ```rust,no_run
+/// # use ldap3::controls::Dereference;
+/// let ctrl = Dereference::new().deref("member", vec!["uid", "cn"]);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Dereference<S> {
+    /// Pairs of a DN-valued attribute and the attributes to return for its values.
+    pub specs: Vec<(S, Vec<S>)>,
+}
+
+impl<S: AsRef<str>> Dereference<S> {
+    /// Create an empty control instance.
+    pub fn new() -> Self {
+        Dereference { specs: Vec::new() }
+    }
+
+    /// Dereference the values of `attr`, returning `attrs` of each target entry.
+    pub fn deref(mut self, attr: S, attrs: Vec<S>) -> Self {
+        self.specs.push((attr, attrs));
+        self
+    }
+}
+
+impl<S> MakeCritical for Dereference<S> {}
+
+fn octet_string(s: &str) -> Tag {
+    Tag::OctetString(OctetString {
+        inner: Vec::from(s),
+        ..Default::default()
+    })
+}
+
+impl<S: AsRef<str>> From<Dereference<S>> for RawControl {
+    fn from(deref: Dereference<S>) -> RawControl {
+        let mut enc_size_est = 2;
+        let specs = deref
+            .specs
+            .iter()
+            .map(|(attr, attrs)| {
+                enc_size_est += attr.as_ref().len() + 6;
+                Tag::Sequence(Sequence {
+                    inner: vec![
+                        octet_string(attr.as_ref()),
+                        Tag::Sequence(Sequence {
+                            inner: attrs
+                                .iter()
+                                .map(|a| {
+                                    enc_size_est += a.as_ref().len() + 2;
+                                    octet_string(a.as_ref())
+                                })
+                                .collect(),
+                            ..Default::default()
+                        }),
+                    ],
+                    ..Default::default()
+                })
+            })
+            .collect();
+        let cval = Tag::Sequence(Sequence {
+            inner: specs,
+            ..Default::default()
+        })
+        .into_structure();
+        let mut buf = BytesMut::with_capacity(enc_size_est);
+        write::encode_into(&mut buf, cval).expect("encoded");
+        RawControl {
+            ctype: DEREF_OID.to_owned(),
+            crit: false,
+            val: Some(Vec::from(&buf[..])),
+        }
+    }
+}
+
+/// Attributes of a single dereferenced entry.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DerefRes {
+    /// Name of the dereferenced attribute.
+    pub attr: String,
+    /// DN of the target entry, which is the value of `attr`.
+    pub dn: String,
+    /// Requested attributes of the target entry. May be empty if the
+    /// target doesn't exist or can't be read.
+    pub attrs: HashMap<String, Vec<String>>,
+    /// Binary-valued attributes.
+    pub bin_attrs: HashMap<String, Vec<Vec<u8>>>,
+}
+
+/// Dereference response control.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DereferenceResp {
+    /// Dereferenced entries, in the order returned by the server.
+    pub results: Vec<DerefRes>,
+}
+
+impl DereferenceResp {
+    /// Dereferenced entries for the values of `attr`.
+    pub fn for_attr<'a>(&'a self, attr: &'a str) -> impl Iterator<Item = &'a DerefRes> + 'a {
+        self.results
+            .iter()
+            .filter(move |res| res.attr.eq_ignore_ascii_case(attr))
+    }
+}
+
+fn string(tag: StructureTag) -> String {
+    String::from_utf8(tag.expect_primitive().expect("deref: octet string"))
+        .expect("deref: utf-8 string")
+}
+
+impl ControlParser for DereferenceResp {
+    fn parse(val: &[u8]) -> DereferenceResp {
+        let results = match parse_tag(val) {
+            IResult::Done(_, tag) => tag,
+            _ => panic!("deref: failed to parse tag"),
+        }
+        .expect_constructed()
+        .expect("deref: results");
+        let mut resp = DereferenceResp::default();
+        for result in results {
+            let mut comps = result
+                .expect_constructed()
+                .expect("deref: result components")
+                .into_iter();
+            let mut res = DerefRes {
+                attr: string(comps.next().expect("deref: attribute")),
+                dn: string(comps.next().expect("deref: value")),
+                ..Default::default()
+            };
+            if let Some(vals) = comps.next() {
+                let attrs = vals
+                    .match_class(TagClass::Context)
+                    .and_then(|t| t.match_id(0))
+                    .and_then(|t| t.expect_constructed())
+                    .expect("deref: attribute values");
+                for attr in attrs {
+                    let mut attr = attr
+                        .expect_constructed()
+                        .expect("deref: partial attribute")
+                        .into_iter();
+                    let name = string(attr.next().expect("deref: attribute type"));
+                    let vals = attr
+                        .next()
+                        .expect("deref: attribute values")
+                        .expect_constructed()
+                        .expect("deref: value set")
+                        .into_iter()
+                        .map(|v| v.expect_primitive().expect("deref: value"))
+                        .collect::<Vec<_>>();
+                    // Like SearchEntry: any non-UTF-8 value makes the attribute binary.
+                    if vals.iter().all(|v| std::str::from_utf8(v).is_ok()) {
+                        let vals = vals
+                            .into_iter()
+                            .map(|v| String::from_utf8(v).expect("utf-8"))
+                            .collect();
+                        res.attrs.insert(name, vals);
+                    } else {
+                        res.bin_attrs.insert(name, vals);
+                    }
+                }
+            }
+            resp.results.push(res);
+        }
+        resp
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ControlParser, DerefRes, Dereference, DereferenceResp, RawControl};
+
+    use bytes::BytesMut;
+
+    use lber::common::TagClass;
+    use lber::structures::{ASNTag, OctetString, Sequence, Set, Tag};
+    use lber::write;
+
+    #[test]
+    fn encode_request() {
+        let ctrl = RawControl::from(Dereference::new().deref("member", vec!["uid"]));
+        assert_eq!(
+            ctrl.val.unwrap(),
+            b"\x30\x11\x30\x0f\x04\x06member\x30\x05\x04\x03uid".to_vec()
+        );
+    }
+
+    #[test]
+    fn parse_resp() {
+        let ostr = |s: &[u8]| {
+            Tag::OctetString(OctetString {
+                inner: s.to_vec(),
+                ..Default::default()
+            })
+        };
+        let seq = |inner| {
+            Tag::Sequence(Sequence {
+                inner,
+                ..Default::default()
+            })
+        };
+        let resp = seq(vec![
+            seq(vec![
+                ostr(b"member"),
+                ostr(b"uid=a,dc=example,dc=org"),
+                Tag::Sequence(Sequence {
+                    class: TagClass::Context,
+                    id: 0,
+                    inner: vec![seq(vec![
+                        ostr(b"uid"),
+                        Tag::Set(Set {
+                            inner: vec![ostr(b"a")],
+                            ..Default::default()
+                        }),
+                    ])],
+                }),
+            ]),
+            seq(vec![ostr(b"member"), ostr(b"uid=gone,dc=example,dc=org")]),
+        ]);
+        let mut buf = BytesMut::new();
+        write::encode_into(&mut buf, resp.into_structure()).unwrap();
+        let resp = DereferenceResp::parse(&buf);
+        let found = resp.for_attr("Member").collect::<Vec<_>>();
+        assert_eq!(found.len(), 2);
+        assert_eq!(
+            found[0],
+            &DerefRes {
+                attr: "member".to_owned(),
+                dn: "uid=a,dc=example,dc=org".to_owned(),
+                attrs: vec![("uid".to_owned(), vec!["a".to_owned()])]
+                    .into_iter()
+                    .collect(),
+                ..Default::default()
+            }
+        );
+        assert!(found[1].attrs.is_empty());
+    }
+}
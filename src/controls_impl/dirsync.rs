@@ -0,0 +1,112 @@
+use super::{ControlParser, MakeCritical, RawControl};
+
+use bytes::BytesMut;
+
+use lber::common::TagClass;
+use lber::parse::{parse_tag, parse_uint};
+use lber::structures::{ASNTag, Integer, OctetString, Sequence, Tag};
+use lber::universal::Types;
+use lber::write;
+use lber::IResult;
+
+pub const DIRSYNC_OID: &str = "1.2.840.113556.1.4.841";
+
+/// Active Directory DirSync control
+/// ([MS-ADTS 3.1.1.3.4.1.3](https://docs.microsoft.com/en-us/openspecs/windows_protocols/ms-adts/2213a7f2-0a36-483c-b2a4-8574d53aa1e3)).
+///
+/// The control asks for the objects changed since the state represented by the cookie,
+/// which is empty for the first request. It must be marked as critical, and used with
+/// a subtree Search of a naming context root.
+///
+/// This struct can be used both for requests and responses. In a response, a non-zero
+/// `flags` means that there are more changes to retrieve with the returned cookie.
+#[derive(Clone, Debug, Default)]
+pub struct DirSync {
+    /// For requests, option flags; for responses, the "more data" indicator.
+    pub flags: i32,
+    /// For requests, maximum size of the returned data; zero lets the server choose.
+    pub max_bytes: i32,
+    /// Synchronization cookie.
+    pub cookie: Vec<u8>,
+}
+
+impl DirSync {
+    /// Return only the objects and attributes the user has access to.
+    pub const OBJECT_SECURITY: i32 = 0x0000_0001;
+    /// Return parents before their children.
+    pub const ANCESTORS_FIRST_ORDER: i32 = 0x0000_0800;
+    /// Omit secret attributes.
+    pub const PUBLIC_DATA_ONLY: i32 = 0x0000_2000;
+    /// Return only the changed values of multi-valued attributes.
+    pub const INCREMENTAL_VALUES: i32 = 0x8000_0000_u32 as i32;
+}
+
+impl MakeCritical for DirSync {}
+
+impl From<DirSync> for RawControl {
+    fn from(ds: DirSync) -> RawControl {
+        let cookie_len = ds.cookie.len();
+        let cval = Tag::Sequence(Sequence {
+            inner: vec![
+                Tag::Integer(Integer {
+                    inner: ds.flags as i64,
+                    ..Default::default()
+                }),
+                Tag::Integer(Integer {
+                    inner: ds.max_bytes as i64,
+                    ..Default::default()
+                }),
+                Tag::OctetString(OctetString {
+                    inner: ds.cookie,
+                    ..Default::default()
+                }),
+            ],
+            ..Default::default()
+        })
+        .into_structure();
+        let mut buf = BytesMut::with_capacity(cookie_len + 24);
+        write::encode_into(&mut buf, cval).expect("encoded");
+        RawControl {
+            ctype: DIRSYNC_OID.to_owned(),
+            crit: false,
+            val: Some(Vec::from(&buf[..])),
+        }
+    }
+}
+
+impl ControlParser for DirSync {
+    fn parse(val: &[u8]) -> DirSync {
+        let mut comps = match parse_tag(val) {
+            IResult::Done(_, tag) => tag,
+            _ => panic!("failed to parse dirsync value components"),
+        }
+        .expect_constructed()
+        .expect("dirsync components")
+        .into_iter();
+        let mut int = || match parse_uint(
+            comps
+                .next()
+                .expect("element")
+                .match_class(TagClass::Universal)
+                .and_then(|t| t.match_id(Types::Integer as u64))
+                .and_then(|t| t.expect_primitive())
+                .expect("dirsync integer")
+                .as_slice(),
+        ) {
+            IResult::Done(_, val) => val as i32,
+            _ => panic!("failed to parse dirsync integer"),
+        };
+        let flags = int();
+        let max_bytes = int();
+        let cookie = comps
+            .next()
+            .expect("element")
+            .expect_primitive()
+            .expect("octet string");
+        DirSync {
+            flags,
+            max_bytes,
+            cookie,
+        }
+    }
+}
@@ -0,0 +1,36 @@
+use super::RawControl;
+
+/// Don't Use Copy control ([RFC 6171](https://tools.ietf.org/html/rfc6171)).
+///
+/// The control asks that a Search or Compare be answered from the original
+/// entry, not from a shadow copy. The specification requires it to be critical,
+/// so the conversion marks it as such.
+///
+/// This control can only be used for requests; there is no corresponding
+/// response control.
+pub struct DontUseCopy;
+
+pub const DONT_USE_COPY_OID: &str = "1.3.6.1.1.22";
+
+impl From<DontUseCopy> for RawControl {
+    fn from(_duc: DontUseCopy) -> RawControl {
+        RawControl {
+            ctype: DONT_USE_COPY_OID.to_owned(),
+            crit: true,
+            val: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{DontUseCopy, RawControl};
+
+    #[test]
+    fn critical() {
+        let ctrl = RawControl::from(DontUseCopy);
+        assert_eq!(ctrl.ctype, "1.3.6.1.1.22");
+        assert!(ctrl.crit);
+        assert_eq!(ctrl.val, None);
+    }
+}
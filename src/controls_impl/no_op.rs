@@ -0,0 +1,29 @@
+use super::RawControl;
+
+/// No-Op control ([draft specification](https://tools.ietf.org/html/draft-zeilenga-ldap-noop-01)).
+///
+/// An update carrying this control is processed normally, but its changes aren't
+/// applied. If the update would have succeeded, the result code is 16654
+/// (`x-no-operation`). The specification requires the control to be critical,
+/// so the conversion marks it as such. It's usually more convenient to call
+/// [`Ldap::dry_run()`](../struct.Ldap.html#method.dry_run), which attaches the
+/// control and translates the result code.
+///
+/// This control can only be used for requests; there is no corresponding
+/// response control.
+pub struct NoOp;
+
+pub const NO_OP_OID: &str = "1.3.6.1.4.1.4203.1.10.2";
+
+/// Result code returned for a successful No-Op update.
+pub const NO_OP_RC: u32 = 16654;
+
+impl From<NoOp> for RawControl {
+    fn from(_no: NoOp) -> RawControl {
+        RawControl {
+            ctype: NO_OP_OID.to_owned(),
+            crit: true,
+            val: None,
+        }
+    }
+}
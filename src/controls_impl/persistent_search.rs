@@ -0,0 +1,146 @@
+use super::{ControlParser, MakeCritical, RawControl};
+
+use bytes::BytesMut;
+
+use lber::common::TagClass;
+use lber::parse::{parse_tag, parse_uint};
+use lber::structures::{ASNTag, Boolean, Integer, Sequence, Tag};
+use lber::universal::Types;
+use lber::write;
+use lber::IResult;
+
+pub const PERSISTENT_SEARCH_OID: &str = "2.16.840.1.113730.3.4.3";
+pub const ENTRY_CHANGE_NOTIFICATION_OID: &str = "2.16.840.1.113730.3.4.7";
+
+/// Persistent Search control
+/// ([draft-ietf-ldapext-psearch](https://tools.ietf.org/html/draft-ietf-ldapext-psearch-03)).
+///
+/// With this control, a Search doesn't end after returning the matching entries, but
+/// keeps returning the entries as they are changed. The default value asks for all
+/// types of changes, without the initial content, with the
+/// [`EntryChangeNotification`](struct.EntryChangeNotification.html) control attached
+/// to every changed entry. The control should be marked as critical.
+#[derive(Clone, Debug)]
+pub struct PersistentSearch {
+    /// Bit mask of the change types to return: 1 for Add, 2 for Delete, 4 for Modify,
+    /// and 8 for ModifyDN.
+    pub change_types: i32,
+    /// Return only the changed entries, and not the initial content.
+    pub changes_only: bool,
+    /// Attach the Entry Change Notification control to returned entries.
+    pub return_ecs: bool,
+}
+
+impl Default for PersistentSearch {
+    fn default() -> Self {
+        PersistentSearch {
+            change_types: 15,
+            changes_only: true,
+            return_ecs: true,
+        }
+    }
+}
+
+impl MakeCritical for PersistentSearch {}
+
+impl From<PersistentSearch> for RawControl {
+    fn from(ps: PersistentSearch) -> RawControl {
+        let cval = Tag::Sequence(Sequence {
+            inner: vec![
+                Tag::Integer(Integer {
+                    inner: ps.change_types as i64,
+                    ..Default::default()
+                }),
+                Tag::Boolean(Boolean {
+                    inner: ps.changes_only,
+                    ..Default::default()
+                }),
+                Tag::Boolean(Boolean {
+                    inner: ps.return_ecs,
+                    ..Default::default()
+                }),
+            ],
+            ..Default::default()
+        })
+        .into_structure();
+        let mut buf = BytesMut::with_capacity(16);
+        write::encode_into(&mut buf, cval).expect("encoded");
+        RawControl {
+            ctype: PERSISTENT_SEARCH_OID.to_owned(),
+            crit: false,
+            val: Some(Vec::from(&buf[..])),
+        }
+    }
+}
+
+/// Type of change reported by the Entry Change Notification control.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EntryChangeType {
+    Add,
+    Delete,
+    Modify,
+    ModDn,
+}
+
+/// Entry Change Notification response control
+/// ([draft-ietf-ldapext-psearch](https://tools.ietf.org/html/draft-ietf-ldapext-psearch-03)).
+#[derive(Clone, Debug)]
+pub struct EntryChangeNotification {
+    /// Type of the change.
+    pub change_type: EntryChangeType,
+    /// DN of the entry before a ModifyDN.
+    pub previous_dn: Option<String>,
+    /// Change number, if the server keeps a changelog.
+    pub change_number: Option<i64>,
+}
+
+impl ControlParser for EntryChangeNotification {
+    fn parse(val: &[u8]) -> EntryChangeNotification {
+        let mut comps = match parse_tag(val) {
+            IResult::Done(_, tag) => tag,
+            _ => panic!("failed to parse entry change notification components"),
+        }
+        .expect_constructed()
+        .expect("entry change notification components")
+        .into_iter();
+        let change_type = match parse_uint(
+            comps
+                .next()
+                .expect("element")
+                .match_class(TagClass::Universal)
+                .and_then(|t| t.match_id(Types::Enumerated as u64))
+                .and_then(|t| t.expect_primitive())
+                .expect("change type")
+                .as_slice(),
+        ) {
+            IResult::Done(_, 1) => EntryChangeType::Add,
+            IResult::Done(_, 2) => EntryChangeType::Delete,
+            IResult::Done(_, 4) => EntryChangeType::Modify,
+            IResult::Done(_, 8) => EntryChangeType::ModDn,
+            _ => panic!("failed to parse change type"),
+        };
+        let mut previous_dn = None;
+        let mut change_number = None;
+        for comp in comps {
+            match comp.id {
+                id if id == Types::OctetString as u64 => {
+                    let dn = comp.expect_primitive().expect("previous dn");
+                    previous_dn = Some(String::from_utf8(dn).expect("previous dn"));
+                }
+                id if id == Types::Integer as u64 => {
+                    let num = comp.expect_primitive().expect("change number");
+                    change_number = match parse_uint(num.as_slice()) {
+                        IResult::Done(_, num) => Some(num as i64),
+                        _ => panic!("failed to parse change number"),
+                    };
+                }
+                _ => panic!("entry change notification: unrecognized component"),
+            }
+        }
+        EntryChangeNotification {
+            change_type,
+            previous_dn,
+            change_number,
+        }
+    }
+}
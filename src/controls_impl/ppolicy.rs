@@ -0,0 +1,138 @@
+use super::{ControlParser, MakeCritical, RawControl};
+
+use lber::common::TagClass;
+use lber::parse::{parse_tag, parse_uint};
+use lber::structure::PL;
+use lber::IResult;
+
+pub const PASSWORD_POLICY_OID: &str = "1.3.6.1.4.1.42.2.27.8.5.1";
+
+/// Password Policy request control
+/// ([draft-behera-ldap-password-policy](https://tools.ietf.org/html/draft-behera-ldap-password-policy-11)).
+///
+/// The control asks the server to return the
+/// [`PasswordPolicyResp`](struct.PasswordPolicyResp.html) control with the
+/// operation result. It's most useful with a Bind, and is attached automatically by
+/// [`Ldap::simple_bind_ppolicy()`](../struct.Ldap.html#method.simple_bind_ppolicy).
+pub struct PasswordPolicy;
+
+impl MakeCritical for PasswordPolicy {}
+
+impl From<PasswordPolicy> for RawControl {
+    fn from(_pp: PasswordPolicy) -> RawControl {
+        RawControl {
+            ctype: PASSWORD_POLICY_OID.to_owned(),
+            crit: false,
+            val: None,
+        }
+    }
+}
+
+/// Warning in the Password Policy response control.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PasswordPolicyWarning {
+    /// The password expires in the given number of seconds.
+    TimeBeforeExpiration(u32),
+    /// The password has expired, and the given number of grace logins remain.
+    GraceAuthNsRemaining(u32),
+}
+
+/// Error in the Password Policy response control.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PasswordPolicyError {
+    PasswordExpired,
+    AccountLocked,
+    ChangeAfterReset,
+    PasswordModNotAllowed,
+    MustSupplyOldPassword,
+    InsufficientPasswordQuality,
+    PasswordTooShort,
+    PasswordTooYoung,
+    PasswordInHistory,
+    /// Error code not defined by the specification.
+    Other(u32),
+}
+
+impl From<u32> for PasswordPolicyError {
+    fn from(code: u32) -> PasswordPolicyError {
+        match code {
+            0 => PasswordPolicyError::PasswordExpired,
+            1 => PasswordPolicyError::AccountLocked,
+            2 => PasswordPolicyError::ChangeAfterReset,
+            3 => PasswordPolicyError::PasswordModNotAllowed,
+            4 => PasswordPolicyError::MustSupplyOldPassword,
+            5 => PasswordPolicyError::InsufficientPasswordQuality,
+            6 => PasswordPolicyError::PasswordTooShort,
+            7 => PasswordPolicyError::PasswordTooYoung,
+            8 => PasswordPolicyError::PasswordInHistory,
+            code => PasswordPolicyError::Other(code),
+        }
+    }
+}
+
+/// Password Policy response control
+/// ([draft-behera-ldap-password-policy](https://tools.ietf.org/html/draft-behera-ldap-password-policy-11)).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PasswordPolicyResp {
+    pub warning: Option<PasswordPolicyWarning>,
+    pub error: Option<PasswordPolicyError>,
+}
+
+fn uint(payload: PL) -> u32 {
+    match payload {
+        PL::P(val) => match parse_uint(val.as_slice()) {
+            IResult::Done(_, val) => val as u32,
+            _ => panic!("ppolicy: failed to parse integer"),
+        },
+        PL::C(_) => panic!("ppolicy: constructed integer?"),
+    }
+}
+
+impl ControlParser for PasswordPolicyResp {
+    fn parse(val: &[u8]) -> PasswordPolicyResp {
+        let comps = match parse_tag(val) {
+            IResult::Done(_, tag) => tag,
+            _ => panic!("ppolicy: failed to parse tag"),
+        }
+        .expect_constructed()
+        .expect("ppolicy: elements");
+        let mut resp = PasswordPolicyResp::default();
+        for comp in comps {
+            match (comp.class, comp.id) {
+                (TagClass::Context, 0) => {
+                    let choice = comp
+                        .expect_constructed()
+                        .expect("ppolicy: warning")
+                        .into_iter()
+                        .next()
+                        .expect("ppolicy: warning choice");
+                    resp.warning = Some(match choice.id {
+                        0 => PasswordPolicyWarning::TimeBeforeExpiration(uint(choice.payload)),
+                        1 => PasswordPolicyWarning::GraceAuthNsRemaining(uint(choice.payload)),
+                        _ => panic!("ppolicy: unknown warning"),
+                    });
+                }
+                (TagClass::Context, 1) => resp.error = Some(uint(comp.payload).into()),
+                _ => panic!("ppolicy: unrecognized component"),
+            }
+        }
+        resp
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ControlParser, PasswordPolicyResp, PasswordPolicyWarning};
+
+    #[test]
+    fn parse_resp() {
+        // SEQUENCE { [0] { [1] 1 } }
+        assert_eq!(
+            PasswordPolicyResp::parse(b"\x30\x05\xa0\x03\x81\x01\x01"),
+            PasswordPolicyResp {
+                warning: Some(PasswordPolicyWarning::GraceAuthNsRemaining(1)),
+                error: None,
+            }
+        );
+    }
+}
@@ -0,0 +1,52 @@
+use super::{MakeCritical, RawControl};
+
+use bytes::BytesMut;
+
+use lber::structures::{ASNTag, Boolean, Tag};
+use lber::write;
+
+/// Subentries control ([RFC 3672](https://tools.ietf.org/html/rfc3672)).
+///
+/// With `Subentries(true)`, a Search returns only subentries; with
+/// `Subentries(false)`, only regular entries. Without the control, subentries
+/// are visible only in base-scoped searches.
+///
+/// This control can only be used for requests; there is no corresponding
+/// response control.
+pub struct Subentries(pub bool);
+
+pub const SUBENTRIES_OID: &str = "1.3.6.1.4.1.4203.1.10.1";
+
+impl MakeCritical for Subentries {}
+
+impl From<Subentries> for RawControl {
+    fn from(se: Subentries) -> RawControl {
+        let cval = Tag::Boolean(Boolean {
+            inner: se.0,
+            ..Default::default()
+        })
+        .into_structure();
+        let mut buf = BytesMut::with_capacity(3);
+        write::encode_into(&mut buf, cval).expect("encoded");
+        RawControl {
+            ctype: SUBENTRIES_OID.to_owned(),
+            crit: false,
+            val: Some(Vec::from(&buf[..])),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{RawControl, Subentries};
+
+    #[test]
+    fn encode() {
+        let ctrl = RawControl::from(Subentries(true));
+        assert_eq!(ctrl.ctype, "1.3.6.1.4.1.4203.1.10.1");
+        assert!(!ctrl.crit);
+        assert_eq!(ctrl.val, Some(vec![0x01, 0x01, 0xff]));
+        let ctrl = RawControl::from(Subentries(false));
+        assert_eq!(ctrl.val, Some(vec![0x01, 0x01, 0x00]));
+    }
+}
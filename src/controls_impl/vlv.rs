@@ -0,0 +1,107 @@
+use super::{ControlParser, MakeCritical, RawControl};
+
+use bytes::BytesMut;
+
+use lber::codec::{self, BerDecode};
+use lber::common::TagClass;
+use lber::structures::{ASNTag, Integer, OctetString, Sequence, Tag};
+use lber::write;
+
+pub const VLV_REQUEST_OID: &str = "2.16.840.1.113730.3.4.9";
+pub const VLV_RESPONSE_OID: &str = "2.16.840.1.113730.3.4.10";
+
+/// Target entry of a Virtual List View.
+#[derive(Clone, Debug)]
+pub enum VlvTarget {
+    /// Position of the target, starting from 1, and the client's estimate of
+    /// the list size, or zero if unknown.
+    ByOffset { offset: u32, content_count: u32 },
+    /// First entry whose sort key is greater than or equal to the value.
+    GreaterOrEqual(Vec<u8>),
+}
+
+/// Virtual List View request control
+/// ([draft-ietf-ldapext-ldapv3-vlv](https://tools.ietf.org/html/draft-ietf-ldapext-ldapv3-vlv-09)).
+///
+/// The control returns a window of a sorted result set around the target entry,
+/// so it must be accompanied by a Server Side Sort control.
+#[derive(Clone, Debug)]
+pub struct VirtualListView {
+    /// Number of entries before the target.
+    pub before_count: u32,
+    /// Number of entries after the target.
+    pub after_count: u32,
+    /// Target entry.
+    pub target: VlvTarget,
+    /// Context ID from a previous response, if any.
+    pub context_id: Option<Vec<u8>>,
+}
+
+impl MakeCritical for VirtualListView {}
+
+fn integer(val: u32) -> Tag {
+    Tag::Integer(Integer {
+        inner: val as i64,
+        ..Default::default()
+    })
+}
+
+impl From<VirtualListView> for RawControl {
+    fn from(vlv: VirtualListView) -> RawControl {
+        let mut inner = vec![integer(vlv.before_count), integer(vlv.after_count)];
+        inner.push(match vlv.target {
+            VlvTarget::ByOffset {
+                offset,
+                content_count,
+            } => Tag::Sequence(Sequence {
+                class: TagClass::Context,
+                id: 0,
+                inner: vec![integer(offset), integer(content_count)],
+            }),
+            VlvTarget::GreaterOrEqual(val) => Tag::OctetString(OctetString {
+                class: TagClass::Context,
+                id: 1,
+                inner: val,
+            }),
+        });
+        if let Some(context_id) = vlv.context_id {
+            inner.push(Tag::OctetString(OctetString {
+                inner: context_id,
+                ..Default::default()
+            }));
+        }
+        let cval = Tag::Sequence(Sequence {
+            inner,
+            ..Default::default()
+        })
+        .into_structure();
+        let mut buf = BytesMut::with_capacity(32);
+        write::encode_into(&mut buf, cval).expect("encoded");
+        RawControl {
+            ctype: VLV_REQUEST_OID.to_owned(),
+            crit: false,
+            val: Some(Vec::from(&buf[..])),
+        }
+    }
+}
+
+/// Virtual List View response control.
+#[derive(Clone, Debug, PartialEq, Eq, BerDecode)]
+pub struct VirtualListViewResp {
+    /// Position of the target entry in the list.
+    pub target_position: u32,
+    /// Server's estimate of the list size.
+    pub content_count: u32,
+    /// Result code of the VLV processing.
+    #[ber(class = "universal", tag = 10)]
+    pub result: u32,
+    /// Context ID to pass in the next request.
+    #[ber(optional)]
+    pub context_id: Option<Vec<u8>>,
+}
+
+impl ControlParser for VirtualListViewResp {
+    fn parse(val: &[u8]) -> VirtualListViewResp {
+        codec::decode(val).expect("vlv: response value")
+    }
+}
@@ -0,0 +1,429 @@
+//! Entry and subtree comparison.
+//!
+//! [`entry_mods()`](fn.entry_mods.html) computes the modifications which turn one
+//! entry into another, and [`diff_subtrees()`](fn.diff_subtrees.html) compares two
+//! subtrees, on the same server or on different ones, for detecting drift between
+//! replicas or between a directory and its expected state.
+//!
+//! Entries of the two subtrees are paired by their DNs relative to the respective
+//! bases, so that `uid=jdoe,ou=People,dc=example,dc=org` under `dc=example,dc=org`
+//! corresponds to `uid=jdoe,ou=People,o=copy` under `o=copy`. DNs are compared
+//! after removing the whitespace around RDN separators and lowercasing, and attribute
+//! names case-insensitively. Values are compared as octet strings, optionally ignoring
+//! ASCII case; the comparison knows nothing of schema and matching rules, so it should
+//! be restricted to the attributes where octet comparison gives the right answer.
+//!
+//! The reference subtree is held in memory while the target subtree is streamed
+//! against it.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! use ldap3::diff::{diff_subtrees, DiffOptions};
+//! use ldap3::LdapConnAsync;
+//! # use ldap3::result::Result;
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<()> {
+//! let (conn, mut primary) = LdapConnAsync::new("ldap://ldap1.example.org").await?;
+//! ldap3::drive!(conn);
+//! let (conn, mut replica) = LdapConnAsync::new("ldap://ldap2.example.org").await?;
+//! ldap3::drive!(conn);
+//! let opts = DiffOptions::new().ignore(vec!["modifyTimestamp", "modifiersName"]);
+//! let diff = diff_subtrees(
+//!     &mut primary,
+//!     "dc=example,dc=org",
+//!     &mut replica,
+//!     "dc=example,dc=org",
+//!     &opts,
+//! )
+//! .await?;
+//! for dn in &diff.missing {
+//!     println!("missing on the replica: {}", dn);
+//! }
+//! for change in &diff.changed {
+//!     println!("{} differs in {} attribute(s)", change.dn, change.attrs.len());
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::ldap::{Ldap, Mod};
+use crate::request::ModifyRequest;
+use crate::result::Result;
+use crate::search::{Scope, SearchEntry};
+use crate::util::normalize_dn;
+
+// Attribute values keyed by lowercased name, with the name as returned.
+type AttrMap = BTreeMap<String, (String, Vec<Vec<u8>>)>;
+
+/// Parameters of a comparison.
+#[derive(Clone, Debug)]
+pub struct DiffOptions {
+    filter: String,
+    attrs: Vec<String>,
+    ignore: HashSet<String>,
+    ignore_value_case: bool,
+}
+
+impl Default for DiffOptions {
+    fn default() -> Self {
+        DiffOptions {
+            filter: String::from("(objectClass=*)"),
+            attrs: vec![],
+            ignore: HashSet::new(),
+            ignore_value_case: false,
+        }
+    }
+}
+
+impl DiffOptions {
+    /// Create the default options: all entries and all user attributes are compared,
+    /// and values are case-sensitive.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Compare only the entries matching `filter` in both subtrees.
+    pub fn filter(mut self, filter: &str) -> Self {
+        self.filter = filter.to_owned();
+        self
+    }
+
+    /// Retrieve and compare the listed attributes.
+    pub fn attrs<S: AsRef<str>>(mut self, attrs: Vec<S>) -> Self {
+        self.attrs = attrs.iter().map(|a| a.as_ref().to_owned()).collect();
+        self
+    }
+
+    /// Leave the listed attributes out of the comparison.
+    pub fn ignore<S: AsRef<str>>(mut self, attrs: Vec<S>) -> Self {
+        self.ignore = attrs.iter().map(|a| a.as_ref().to_lowercase()).collect();
+        self
+    }
+
+    /// Compare values ignoring ASCII case.
+    pub fn ignore_value_case(mut self, ignore: bool) -> Self {
+        self.ignore_value_case = ignore;
+        self
+    }
+
+    fn attr_map(&self, entry: SearchEntry) -> AttrMap {
+        let text = entry
+            .attrs
+            .into_iter()
+            .map(|(name, vals)| (name, vals.into_iter().map(String::into_bytes).collect()));
+        text.chain(entry.bin_attrs)
+            .filter(|(name, _)| !self.ignore.contains(&name.to_lowercase()))
+            .map(|(name, vals)| (name.to_lowercase(), (name, vals)))
+            .collect()
+    }
+
+    fn same_value(&self, a: &[u8], b: &[u8]) -> bool {
+        if self.ignore_value_case {
+            a.eq_ignore_ascii_case(b)
+        } else {
+            a == b
+        }
+    }
+}
+
+/// Difference in the values of one attribute.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AttrDiff {
+    /// Attribute name, as returned by the reference server if it has the attribute.
+    pub attr: String,
+    /// Values present in the reference entry, but not in the target.
+    pub missing: Vec<Vec<u8>>,
+    /// Values present in the target entry, but not in the reference.
+    pub extra: Vec<Vec<u8>>,
+}
+
+/// Differences between a pair of entries.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EntryDiff {
+    /// DN of the reference entry.
+    pub dn: String,
+    /// DN of the target entry.
+    pub target_dn: String,
+    /// Differing attributes, ordered by name.
+    pub attrs: Vec<AttrDiff>,
+}
+
+impl EntryDiff {
+    /// Modifications which make the target entry equal to the reference.
+    pub fn mods(&self) -> Vec<Mod<Vec<u8>>> {
+        let mut mods = vec![];
+        for diff in &self.attrs {
+            let name = diff.attr.as_bytes().to_vec();
+            if !diff.extra.is_empty() {
+                mods.push(Mod::Delete(
+                    name.clone(),
+                    diff.extra.iter().cloned().collect(),
+                ));
+            }
+            if !diff.missing.is_empty() {
+                mods.push(Mod::Add(name, diff.missing.iter().cloned().collect()));
+            }
+        }
+        mods
+    }
+
+    /// Modify request which makes the target entry equal to the reference.
+    pub fn to_request(&self) -> ModifyRequest {
+        ModifyRequest {
+            dn: self.target_dn.clone(),
+            mods: self.mods(),
+        }
+    }
+}
+
+/// Result of a subtree comparison.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SubtreeDiff {
+    /// DNs of the reference entries without a counterpart in the target subtree.
+    pub missing: Vec<String>,
+    /// DNs of the target entries without a counterpart in the reference subtree.
+    pub extra: Vec<String>,
+    /// Entries present in both subtrees, but with different attributes.
+    pub changed: Vec<EntryDiff>,
+}
+
+impl SubtreeDiff {
+    /// Whether the subtrees are equal.
+    pub fn is_empty(&self) -> bool {
+        self.missing.is_empty() && self.extra.is_empty() && self.changed.is_empty()
+    }
+}
+
+fn values_diff(opts: &DiffOptions, from: &[Vec<u8>], to: &[Vec<u8>]) -> Vec<Vec<u8>> {
+    from.iter()
+        .filter(|v| !to.iter().any(|w| opts.same_value(v, w)))
+        .cloned()
+        .collect()
+}
+
+fn attrs_diff(opts: &DiffOptions, reference: &AttrMap, target: &AttrMap) -> Vec<AttrDiff> {
+    let names: HashSet<&String> = reference.keys().chain(target.keys()).collect();
+    let mut names: Vec<_> = names.into_iter().collect();
+    names.sort();
+    let none = (String::new(), vec![]);
+    names
+        .into_iter()
+        .filter_map(|key| {
+            let (ref_name, ref_vals) = reference.get(key).unwrap_or(&none);
+            let (tgt_name, tgt_vals) = target.get(key).unwrap_or(&none);
+            let diff = AttrDiff {
+                attr: if ref_name.is_empty() {
+                    tgt_name.clone()
+                } else {
+                    ref_name.clone()
+                },
+                missing: values_diff(opts, ref_vals, tgt_vals),
+                extra: values_diff(opts, tgt_vals, ref_vals),
+            };
+            if diff.missing.is_empty() && diff.extra.is_empty() {
+                None
+            } else {
+                Some(diff)
+            }
+        })
+        .collect()
+}
+
+/// Compute the modifications which turn entry `from` into entry `to`, comparing
+/// values exactly. Attribute names are compared case-insensitively, and the DNs of
+/// the entries are not examined.
+pub fn entry_mods(from: &SearchEntry, to: &SearchEntry) -> Vec<Mod<Vec<u8>>> {
+    let opts = DiffOptions::new();
+    EntryDiff {
+        dn: to.dn.clone(),
+        target_dn: from.dn.clone(),
+        attrs: attrs_diff(
+            &opts,
+            &opts.attr_map(to.clone()),
+            &opts.attr_map(from.clone()),
+        ),
+    }
+    .mods()
+}
+
+// Normalized DN relative to the normalized base.
+fn relative_dn(dn: &str, base: &str) -> String {
+    let dn = normalize_dn(dn);
+    if base.is_empty() {
+        return dn;
+    }
+    match dn.strip_suffix(base) {
+        Some("") => String::new(),
+        Some(rest) if rest.ends_with(',') => rest[..rest.len() - 1].to_owned(),
+        _ => dn,
+    }
+}
+
+/// Compare the subtree under `ref_base` on the `reference` connection with the subtree
+/// under `target_base` on the `target` connection.
+///
+/// Both handles can be clones of the same connection. Any search controls, timeout or
+/// options set on the handles apply to their respective searches.
+pub async fn diff_subtrees(
+    reference: &mut Ldap,
+    ref_base: &str,
+    target: &mut Ldap,
+    target_base: &str,
+    opts: &DiffOptions,
+) -> Result<SubtreeDiff> {
+    let ref_norm = normalize_dn(ref_base);
+    let mut entries: HashMap<String, (String, AttrMap)> = HashMap::new();
+    let mut stream = reference
+        .streaming_search(ref_base, Scope::Subtree, &opts.filter, opts.attrs.clone())
+        .await?;
+    while let Some(re) = stream.next().await? {
+        if re.is_ref() || re.is_intermediate() {
+            continue;
+        }
+        let entry = SearchEntry::construct(re);
+        let key = relative_dn(&entry.dn, &ref_norm);
+        entries.insert(key, (entry.dn.clone(), opts.attr_map(entry)));
+    }
+    stream.finish().await.success()?;
+    let target_norm = normalize_dn(target_base);
+    let mut diff = SubtreeDiff::default();
+    let mut stream = target
+        .streaming_search(
+            target_base,
+            Scope::Subtree,
+            &opts.filter,
+            opts.attrs.clone(),
+        )
+        .await?;
+    while let Some(re) = stream.next().await? {
+        if re.is_ref() || re.is_intermediate() {
+            continue;
+        }
+        let entry = SearchEntry::construct(re);
+        let key = relative_dn(&entry.dn, &target_norm);
+        match entries.remove(&key) {
+            Some((dn, attrs)) => {
+                let target_dn = entry.dn.clone();
+                let attrs = attrs_diff(opts, &attrs, &opts.attr_map(entry));
+                if !attrs.is_empty() {
+                    diff.changed.push(EntryDiff {
+                        dn,
+                        target_dn,
+                        attrs,
+                    });
+                }
+            }
+            None => diff.extra.push(entry.dn),
+        }
+    }
+    stream.finish().await.success()?;
+    diff.missing = entries.into_values().map(|(dn, _)| dn).collect();
+    diff.missing.sort();
+    diff.extra.sort();
+    diff.changed.sort_by(|a, b| a.dn.cmp(&b.dn));
+    Ok(diff)
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::{entry_mods, relative_dn};
+    use crate::ldap::Mod;
+    use crate::search::SearchEntry;
+    use maplit::hashset;
+
+    fn entry(attrs: Vec<(&str, Vec<&str>)>) -> SearchEntry {
+        SearchEntry {
+            dn: String::from("cn=x,dc=example,dc=org"),
+            attrs: attrs
+                .into_iter()
+                .map(|(a, v)| (a.to_owned(), v.into_iter().map(String::from).collect()))
+                .collect::<HashMap<_, _>>(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn mods() {
+        let from = entry(vec![("cn", vec!["x"]), ("mail", vec!["a", "b"])]);
+        let to = entry(vec![
+            ("CN", vec!["x"]),
+            ("mail", vec!["b", "c"]),
+            ("sn", vec!["y"]),
+        ]);
+        assert_eq!(
+            entry_mods(&from, &to),
+            vec![
+                Mod::Delete(b"mail".to_vec(), hashset! { b"a".to_vec() }),
+                Mod::Add(b"mail".to_vec(), hashset! { b"c".to_vec() }),
+                Mod::Add(b"sn".to_vec(), hashset! { b"y".to_vec() }),
+            ]
+        );
+        assert!(entry_mods(&from, &from).is_empty());
+    }
+
+    #[test]
+    fn relative() {
+        assert_eq!(
+            relative_dn("uid=a, ou=P,DC=example", "dc=example"),
+            "uid=a,ou=p"
+        );
+        assert_eq!(relative_dn("dc=example", "dc=example"), "");
+        assert_eq!(
+            relative_dn("uid=a,xdc=example", "dc=example"),
+            "uid=a,xdc=example"
+        );
+    }
+}
+
+#[cfg(all(test, feature = "testkit"))]
+mod mock_test {
+    use super::{diff_subtrees, DiffOptions};
+    use crate::testkit::MockServer;
+
+    #[tokio::test]
+    async fn subtrees() {
+        let primary = MockServer::new();
+        primary.add_entry("dc=example,dc=org", vec![("dc", vec!["example"])]);
+        primary.add_entry(
+            "uid=a,dc=example,dc=org",
+            vec![("uid", vec!["a"]), ("mail", vec!["a@example.org"])],
+        );
+        primary.add_entry("uid=b,dc=example,dc=org", vec![("uid", vec!["b"])]);
+        let replica = MockServer::new();
+        replica.add_entry("o=copy", vec![("dc", vec!["example"])]);
+        replica.add_entry(
+            "UID=A, o=copy",
+            vec![("uid", vec!["a"]), ("mail", vec!["old@example.org"])],
+        );
+        replica.add_entry("uid=c,o=copy", vec![("uid", vec!["c"])]);
+        let (conn, mut ldap1) = primary.connect();
+        crate::drive!(conn);
+        let (conn, mut ldap2) = replica.connect();
+        crate::drive!(conn);
+        let diff = diff_subtrees(
+            &mut ldap1,
+            "dc=example,dc=org",
+            &mut ldap2,
+            "o=copy",
+            &DiffOptions::new(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(diff.missing, vec!["uid=b,dc=example,dc=org"]);
+        assert_eq!(diff.extra, vec!["uid=c,o=copy"]);
+        assert_eq!(diff.changed.len(), 1);
+        assert_eq!(diff.changed[0].target_dn, "UID=A, o=copy");
+        ldap2.send(diff.changed[0].to_request()).await.unwrap();
+        let opts = DiffOptions::new().ignore(vec!["MAIL"]);
+        let diff = diff_subtrees(&mut ldap1, "dc=example,dc=org", &mut ldap2, "o=copy", &opts)
+            .await
+            .unwrap();
+        assert!(diff.changed.is_empty());
+    }
+}
@@ -0,0 +1,477 @@
+//! Active Directory domain controller location.
+//!
+//! A domain controller for an AD domain is found the way Windows clients do it. The
+//! candidates are obtained from the DNS SRV records for `_ldap._tcp.dc._msdcs.<domain>`,
+//! or, if the client's site is known, for `_ldap._tcp.<site>._sites.dc._msdcs.<domain>`.
+//! Every candidate is then sent a CLDAP ping (see the [`cldap`](../cldap/index.html)
+//! module); the servers which respond are alive, and their responses indicate whether
+//! they're in the site closest to the client. When the client's site isn't given in
+//! advance, it's learned from the first round of responses, and if none of the responding
+//! servers is in that site, the site-specific records are tried as well.
+//!
+//! [`DcLocator::locate()`](struct.DcLocator.html#method.locate) returns the responsive
+//! servers ordered by preference: servers in the closest site first, then the rest, each
+//! group in the SRV priority and weight order.
+//!
+//! SRV records are looked up through an [`SrvResolver`](trait.SrvResolver.html). The
+//! default, [`SystemSrvResolver`](struct.SystemSrvResolver.html), queries the name servers
+//! listed in `/etc/resolv.conf`. With the __hickory__ feature, the default is
+//! [`HickoryResolver`](../resolve/struct.HickoryResolver.html) instead.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! # use ldap3::discover::DcLocator;
+//! # use ldap3::LdapConnAsync;
+//! # use ldap3::result::Result;
+//! # #[tokio::main]
+//! # async fn main() -> Result<()> {
+//! let dcs = DcLocator::new("example.com").locate().await?;
+//! if let Some(dc) = dcs.first() {
+//!     let (conn, mut ldap) = LdapConnAsync::new(&dc.ldap_url()).await?;
+//!     ldap3::drive!(conn);
+//!     // ...
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::hash_map::RandomState;
+use std::collections::HashSet;
+use std::hash::{BuildHasher, Hasher};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::cldap::{self, NetlogonInfo};
+use crate::dns;
+#[cfg(feature = "hickory")]
+use crate::resolve::HickoryResolver;
+use crate::result::Result;
+
+use async_trait::async_trait;
+use futures_util::future::join_all;
+use tokio::net::lookup_host;
+
+/// DNS SRV record.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SrvRecord {
+    /// Priority; lower values are preferred.
+    pub priority: u16,
+    /// Relative weight among records with the same priority.
+    pub weight: u16,
+    /// Service port.
+    pub port: u16,
+    /// Target host name.
+    pub target: String,
+}
+
+/// Source of DNS SRV records.
+#[async_trait]
+pub trait SrvResolver: Send + Sync {
+    /// Return the SRV records for `name`, in any order. A nonexistent name should
+    /// produce an empty vector, not an error.
+    async fn lookup_srv(&self, name: &str) -> Result<Vec<SrvRecord>>;
+}
+
+/// SRV resolver which directly queries a list of name servers.
+#[derive(Clone, Debug)]
+pub struct SystemSrvResolver {
+    nameservers: Vec<SocketAddr>,
+    timeout: Duration,
+}
+
+impl SystemSrvResolver {
+    /// Create a resolver using the name servers from `/etc/resolv.conf`, or, if
+    /// there are none, the one on the local host.
+    pub fn new() -> Self {
+        Self::with_nameservers(dns::system_nameservers())
+    }
+
+    /// Create a resolver using the given name servers, tried in order.
+    pub fn with_nameservers(nameservers: Vec<SocketAddr>) -> Self {
+        SystemSrvResolver {
+            nameservers,
+            timeout: Duration::from_secs(2),
+        }
+    }
+
+    /// Set the timeout for a query to a single name server. Defaults to two seconds.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+}
+
+impl Default for SystemSrvResolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl SrvResolver for SystemSrvResolver {
+    async fn lookup_srv(&self, name: &str) -> Result<Vec<SrvRecord>> {
+        dns::lookup_srv(&self.nameservers, name, self.timeout).await
+    }
+}
+
+#[cfg(feature = "hickory")]
+fn default_srv_resolver() -> Arc<dyn SrvResolver> {
+    match HickoryResolver::from_system_conf() {
+        Ok(resolver) => Arc::new(resolver),
+        Err(_) => Arc::new(SystemSrvResolver::new()),
+    }
+}
+
+#[cfg(not(feature = "hickory"))]
+fn default_srv_resolver() -> Arc<dyn SrvResolver> {
+    Arc::new(SystemSrvResolver::new())
+}
+
+/// A domain controller which responded to the CLDAP ping.
+#[derive(Clone, Debug)]
+pub struct DomainController {
+    /// Host name from the SRV record.
+    pub host: String,
+    /// LDAP port from the SRV record.
+    pub port: u16,
+    /// Address which responded to the ping.
+    pub addr: SocketAddr,
+    /// Decoded ping response.
+    pub info: NetlogonInfo,
+}
+
+impl DomainController {
+    /// Return the __ldap__ URL for connecting to the server.
+    pub fn ldap_url(&self) -> String {
+        format!("ldap://{}:{}", self.host, self.port)
+    }
+}
+
+/// Domain controller locator.
+///
+/// See the [module-level documentation](index.html) for the overview.
+#[derive(Clone)]
+pub struct DcLocator {
+    domain: String,
+    site: Option<String>,
+    timeout: Duration,
+    resolver: Arc<dyn SrvResolver>,
+    #[cfg(test)]
+    fake_ping: Option<fn(&SrvRecord) -> Option<NetlogonInfo>>,
+}
+
+impl DcLocator {
+    /// Create a locator for the DNS domain `domain`, with the default SRV resolver,
+    /// no predetermined site, and the ping timeout of one second.
+    pub fn new(domain: &str) -> Self {
+        DcLocator {
+            domain: domain.trim_end_matches('.').to_owned(),
+            site: None,
+            timeout: Duration::from_secs(1),
+            resolver: default_srv_resolver(),
+            #[cfg(test)]
+            fake_ping: None,
+        }
+    }
+
+    /// Look for domain controllers in `site` first.
+    pub fn with_site(mut self, site: &str) -> Self {
+        self.site = Some(site.to_owned());
+        self
+    }
+
+    /// Set the time to wait for the responses to CLDAP pings. All candidates are pinged
+    /// at the same time.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Use `resolver` for SRV lookups.
+    pub fn with_resolver(mut self, resolver: Arc<dyn SrvResolver>) -> Self {
+        self.resolver = resolver;
+        self
+    }
+
+    /// Find the responsive domain controllers, most preferred first. The vector is
+    /// empty if no server responded.
+    pub async fn locate(&self) -> Result<Vec<DomainController>> {
+        let mut dcs = vec![];
+        if let Some(ref site) = self.site {
+            dcs = self.ping_all(&self.site_name(site), &[]).await?;
+        }
+        if dcs.is_empty() {
+            dcs = self
+                .ping_all(&format!("_ldap._tcp.dc._msdcs.{}", self.domain), &[])
+                .await?;
+        }
+        if self.site.is_none() && !dcs.iter().any(|dc| dc.info.is_closest()) {
+            let client_site = dcs
+                .iter()
+                .map(|dc| dc.info.client_site_name.as_str())
+                .find(|site| !site.is_empty())
+                .map(String::from);
+            if let Some(site) = client_site {
+                let mut closer = self.ping_all(&self.site_name(&site), &dcs).await?;
+                closer.append(&mut dcs);
+                dcs = closer;
+            }
+        }
+        // Stable, so that the SRV order is kept within each group.
+        dcs.sort_by_key(|dc| !dc.info.is_closest());
+        Ok(dcs)
+    }
+
+    fn site_name(&self, site: &str) -> String {
+        format!("_ldap._tcp.{}._sites.dc._msdcs.{}", site, self.domain)
+    }
+
+    async fn ping_all(
+        &self,
+        name: &str,
+        known: &[DomainController],
+    ) -> Result<Vec<DomainController>> {
+        let known: HashSet<_> = known.iter().map(|dc| (dc.host.as_str(), dc.port)).collect();
+        let records = order_srv(self.resolver.lookup_srv(name).await?)
+            .into_iter()
+            .filter(|r| !known.contains(&(r.target.as_str(), r.port)));
+        let pings = records.map(|record| self.ping(record));
+        Ok(join_all(pings).await.into_iter().flatten().collect())
+    }
+
+    async fn ping(&self, record: SrvRecord) -> Option<DomainController> {
+        #[cfg(test)]
+        if let Some(fake_ping) = self.fake_ping {
+            return fake_ping(&record).map(|info| DomainController {
+                host: record.target,
+                port: record.port,
+                addr: SocketAddr::from(([127, 0, 0, 1], 389)),
+                info,
+            });
+        }
+        // The CLDAP service is on the UDP port 389, regardless of the LDAP port.
+        let addr = lookup_host((record.target.as_str(), 389))
+            .await
+            .ok()?
+            .next()?;
+        let info = cldap::ping(addr, &self.domain, self.timeout).await.ok()?;
+        Some(DomainController {
+            host: record.target,
+            port: record.port,
+            addr,
+            info,
+        })
+    }
+}
+
+/// Order SRV records as described in RFC 2782: by ascending priority, and randomly
+/// within a priority, with the probability of a record being picked next proportional
+/// to its weight.
+fn order_srv(mut records: Vec<SrvRecord>) -> Vec<SrvRecord> {
+    records.sort_by_key(|r| r.priority);
+    let mut ordered = Vec::with_capacity(records.len());
+    while !records.is_empty() {
+        let priority = records[0].priority;
+        let end = records
+            .iter()
+            .position(|r| r.priority != priority)
+            .unwrap_or(records.len());
+        let mut group: Vec<_> = records.drain(..end).collect();
+        // Zero-weight records go first, so that they have a small chance of selection.
+        group.sort_by_key(|r| r.weight != 0);
+        while !group.is_empty() {
+            let total: u32 = group.iter().map(|r| r.weight as u32).sum();
+            let pick = (RandomState::new().build_hasher().finish() % (total as u64 + 1)) as u32;
+            let mut sum = 0;
+            let idx = group
+                .iter()
+                .position(|r| {
+                    sum += r.weight as u32;
+                    sum >= pick
+                })
+                .unwrap_or(0);
+            ordered.push(group.remove(idx));
+        }
+    }
+    ordered
+}
+
+#[cfg(test)]
+mod test {
+    use super::{order_srv, DcLocator, SrvRecord, SrvResolver};
+    use crate::cldap::{NetlogonInfo, DS_CLOSEST_FLAG};
+    use crate::result::Result;
+
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    use async_trait::async_trait;
+
+    fn srv(priority: u16, weight: u16, target: &str) -> SrvRecord {
+        SrvRecord {
+            priority,
+            weight,
+            port: 389,
+            target: target.to_owned(),
+        }
+    }
+
+    #[derive(Default)]
+    struct FakeResolver {
+        records: HashMap<String, Vec<SrvRecord>>,
+        queried: Mutex<Vec<String>>,
+    }
+
+    impl FakeResolver {
+        fn with(mut self, name: &str, records: Vec<SrvRecord>) -> Self {
+            self.records.insert(name.to_owned(), records);
+            self
+        }
+    }
+
+    #[async_trait]
+    impl SrvResolver for FakeResolver {
+        async fn lookup_srv(&self, name: &str) -> Result<Vec<SrvRecord>> {
+            self.queried.lock().unwrap().push(name.to_owned());
+            Ok(self.records.get(name).cloned().unwrap_or_default())
+        }
+    }
+
+    // The first label of the target is the host, the second its site. The client is
+    // in the site "near", and hosts named "down" don't respond.
+    fn fake_ping(record: &SrvRecord) -> Option<NetlogonInfo> {
+        let mut labels = record.target.split('.');
+        let (host, site) = (labels.next()?, labels.next()?);
+        if host == "down" {
+            return None;
+        }
+        Some(NetlogonInfo {
+            flags: if site == "near" { DS_CLOSEST_FLAG } else { 0 },
+            domain_guid: [0; 16],
+            dns_forest_name: String::from("example.com"),
+            dns_domain_name: String::from("example.com"),
+            dns_host_name: record.target.clone(),
+            netbios_domain_name: String::from("EXAMPLE"),
+            netbios_computer_name: host.to_uppercase(),
+            user_name: String::new(),
+            dc_site_name: site.to_owned(),
+            client_site_name: String::from("near"),
+            next_closest_site_name: None,
+            nt_version: 5,
+            user_unknown: false,
+        })
+    }
+
+    const ALL: &str = "_ldap._tcp.dc._msdcs.example.com";
+    const NEAR: &str = "_ldap._tcp.near._sites.dc._msdcs.example.com";
+
+    async fn locate(locator: DcLocator, resolver: &Arc<FakeResolver>) -> Vec<String> {
+        let mut locator = locator.with_resolver(resolver.clone());
+        locator.fake_ping = Some(fake_ping);
+        let dcs = locator.locate().await.unwrap();
+        dcs.into_iter().map(|dc| dc.host).collect()
+    }
+
+    #[test]
+    fn priority_order() {
+        let records = vec![
+            srv(20, 0, "c"),
+            srv(0, 5, "a1"),
+            srv(10, 0, "b"),
+            srv(0, 5, "a2"),
+        ];
+        for _ in 0..20 {
+            let ordered = order_srv(records.clone());
+            let mut first: Vec<_> = ordered[..2].iter().map(|r| r.target.as_str()).collect();
+            first.sort_unstable();
+            assert_eq!(first, vec!["a1", "a2"]);
+            assert_eq!(ordered[2].target, "b");
+            assert_eq!(ordered[3].target, "c");
+        }
+    }
+
+    #[test]
+    fn weight_order() {
+        let records = vec![srv(0, 1, "light"), srv(0, 1000, "heavy")];
+        let heavy_first = (0..200)
+            .filter(|_| order_srv(records.clone())[0].target == "heavy")
+            .count();
+        assert!(heavy_first > 150, "{}", heavy_first);
+        // All zero weights: every record is still returned exactly once.
+        let zero: Vec<_> = (0..5).map(|i| srv(0, 0, &format!("z{}", i))).collect();
+        let mut ordered: Vec<_> = order_srv(zero).into_iter().map(|r| r.target).collect();
+        ordered.sort_unstable();
+        assert_eq!(ordered, vec!["z0", "z1", "z2", "z3", "z4"]);
+        // A zero-weight record can be picked before a weighted one, but rarely.
+        let records = vec![srv(0, 100, "weighted"), srv(0, 0, "zero")];
+        let zero_first = (0..200)
+            .filter(|_| order_srv(records.clone())[0].target == "zero")
+            .count();
+        assert!(zero_first < 50, "{}", zero_first);
+        assert!(order_srv(vec![]).is_empty());
+    }
+
+    #[tokio::test]
+    async fn learn_site() {
+        let resolver = Arc::new(
+            FakeResolver::default()
+                .with(
+                    ALL,
+                    vec![
+                        srv(10, 0, "dc2.far"),
+                        srv(0, 0, "dc1.far"),
+                        srv(0, 0, "down.far"),
+                    ],
+                )
+                .with(NEAR, vec![srv(0, 0, "dc3.near"), srv(0, 0, "dc1.far")]),
+        );
+        let dcs = locate(DcLocator::new("example.com."), &resolver).await;
+        assert_eq!(dcs, vec!["dc3.near", "dc1.far", "dc2.far"]);
+        assert_eq!(*resolver.queried.lock().unwrap(), vec![ALL, NEAR]);
+    }
+
+    #[tokio::test]
+    async fn closest_found() {
+        let resolver = Arc::new(
+            FakeResolver::default()
+                .with(ALL, vec![srv(0, 0, "dc1.far"), srv(10, 0, "dc2.near")])
+                .with(NEAR, vec![srv(0, 0, "dc3.near")]),
+        );
+        let dcs = locate(DcLocator::new("example.com"), &resolver).await;
+        assert_eq!(dcs, vec!["dc2.near", "dc1.far"]);
+        assert_eq!(*resolver.queried.lock().unwrap(), vec![ALL]);
+    }
+
+    #[tokio::test]
+    async fn given_site() {
+        let resolver = Arc::new(
+            FakeResolver::default()
+                .with(ALL, vec![srv(0, 0, "dc1.far")])
+                .with(NEAR, vec![srv(0, 0, "dc3.near")]),
+        );
+        let dcs = locate(DcLocator::new("example.com").with_site("near"), &resolver).await;
+        assert_eq!(dcs, vec!["dc3.near"]);
+        assert_eq!(*resolver.queried.lock().unwrap(), vec![NEAR]);
+        // A site without records, or without responsive servers, falls back to the domain.
+        let resolver = Arc::new(
+            FakeResolver::default()
+                .with(ALL, vec![srv(0, 0, "dc1.far")])
+                .with(NEAR, vec![srv(0, 0, "down.near")]),
+        );
+        let dcs = locate(DcLocator::new("example.com").with_site("near"), &resolver).await;
+        assert_eq!(dcs, vec!["dc1.far"]);
+        let dcs = locate(DcLocator::new("example.com").with_site("other"), &resolver).await;
+        assert_eq!(dcs, vec!["dc1.far"]);
+    }
+
+    #[tokio::test]
+    async fn none_responding() {
+        let resolver = Arc::new(FakeResolver::default().with(ALL, vec![srv(0, 0, "down.far")]));
+        assert!(locate(DcLocator::new("example.com"), &resolver)
+            .await
+            .is_empty());
+        assert_eq!(*resolver.queried.lock().unwrap(), vec![ALL]);
+    }
+}
@@ -0,0 +1,396 @@
+// Minimal DNS client, sufficient for SRV lookups, and the decoder of RFC 1035
+// compressed names, which are also used in Netlogon responses.
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use crate::discover::SrvRecord;
+use crate::result::{LdapError, Result};
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpStream, UdpSocket};
+use tokio::time;
+
+const TYPE_CNAME: u16 = 5;
+const TYPE_SRV: u16 = 33;
+const CLASS_IN: u16 = 1;
+const FLAG_QR: u16 = 0x8000;
+const FLAG_TC: u16 = 0x0200;
+const RCODE_NXDOMAIN: u16 = 3;
+
+// EDNS0 OPT pseudo-record advertising a 4096-byte UDP payload, so that large
+// SRV sets don't need a TCP retry.
+const EDNS_OPT: [u8; 11] = [0, 0, 41, 0x10, 0, 0, 0, 0, 0, 0, 0];
+
+pub(crate) struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(buf: &'a [u8]) -> Self {
+        Reader { buf, pos: 0 }
+    }
+
+    pub(crate) fn at(buf: &'a [u8], pos: usize) -> Self {
+        Reader { buf, pos }
+    }
+
+    pub(crate) fn pos(&self) -> usize {
+        self.pos
+    }
+
+    pub(crate) fn bytes(&mut self, n: usize) -> Option<&'a [u8]> {
+        let end = self.pos.checked_add(n)?;
+        let bytes = self.buf.get(self.pos..end)?;
+        self.pos = end;
+        Some(bytes)
+    }
+
+    pub(crate) fn u16_le(&mut self) -> Option<u16> {
+        let b = self.bytes(2)?;
+        Some(u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    pub(crate) fn u32_le(&mut self) -> Option<u32> {
+        let b = self.bytes(4)?;
+        Some(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    pub(crate) fn u16_be(&mut self) -> Option<u16> {
+        let b = self.bytes(2)?;
+        Some(u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    // Domain name compressed as in RFC 1035, section 4.1.4. Labels are joined
+    // with dots; a pointer continues the name elsewhere in the buffer.
+    pub(crate) fn name(&mut self) -> Option<String> {
+        let mut labels = vec![];
+        let mut pos = self.pos;
+        let mut resume = None;
+        // Every pointer must go backwards, which bounds the number of jumps.
+        let mut limit = pos;
+        loop {
+            let len = *self.buf.get(pos)? as usize;
+            if len == 0 {
+                pos += 1;
+                break;
+            }
+            if len & 0xc0 == 0xc0 {
+                let lo = *self.buf.get(pos + 1)? as usize;
+                let target = (len & 0x3f) << 8 | lo;
+                if target >= limit {
+                    return None;
+                }
+                resume.get_or_insert(pos + 2);
+                limit = target;
+                pos = target;
+                continue;
+            }
+            let label = self.buf.get(pos + 1..pos + 1 + len)?;
+            labels.push(String::from_utf8(label.to_vec()).ok()?);
+            pos += 1 + len;
+        }
+        self.pos = resume.unwrap_or(pos);
+        Some(labels.join("."))
+    }
+}
+
+fn srv_query(id: u16, name: &str) -> Result<Vec<u8>> {
+    let mut query = Vec::with_capacity(name.len() + 30);
+    query.extend(&id.to_be_bytes());
+    // Recursion desired; one question, one additional record
+    query.extend(&[0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 1]);
+    for label in name.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(LdapError::SrvLookup(format!("invalid name: {}", name)));
+        }
+        query.push(label.len() as u8);
+        query.extend(label.as_bytes());
+    }
+    query.push(0);
+    query.extend(&TYPE_SRV.to_be_bytes());
+    query.extend(&CLASS_IN.to_be_bytes());
+    query.extend(&EDNS_OPT);
+    Ok(query)
+}
+
+// Returns None if the response is truncated. The question must be the one asked, and
+// only the SRV records owned by the queried name, or an alias of it given in the same
+// answer, are returned.
+fn parse_srv_response(id: u16, name: &str, buf: &[u8]) -> Result<Option<Vec<SrvRecord>>> {
+    let malformed = || LdapError::SrvLookup(String::from("malformed response"));
+    let mut rd = Reader::new(buf);
+    if rd.u16_be().ok_or_else(malformed)? != id {
+        return Err(LdapError::SrvLookup(String::from("response ID mismatch")));
+    }
+    let flags = rd.u16_be().ok_or_else(malformed)?;
+    if flags & FLAG_QR == 0 {
+        return Err(malformed());
+    }
+    if flags & FLAG_TC != 0 {
+        return Ok(None);
+    }
+    let qdcount = rd.u16_be().ok_or_else(malformed)?;
+    let ancount = rd.u16_be().ok_or_else(malformed)?;
+    rd.bytes(4).ok_or_else(malformed)?;
+    let name = name.trim_end_matches('.');
+    let question = |rd: &mut Reader| -> Option<bool> {
+        let qname = rd.name()?;
+        let (qtype, qclass) = (rd.u16_be()?, rd.u16_be()?);
+        Some(qname.eq_ignore_ascii_case(name) && qtype == TYPE_SRV && qclass == CLASS_IN)
+    };
+    if qdcount != 1 || !question(&mut rd).ok_or_else(malformed)? {
+        return Err(LdapError::SrvLookup(String::from(
+            "response question mismatch",
+        )));
+    }
+    match flags & 0xf {
+        0 => (),
+        RCODE_NXDOMAIN => return Ok(Some(vec![])),
+        rcode => {
+            return Err(LdapError::SrvLookup(format!(
+                "server error, rcode {}",
+                rcode
+            )))
+        }
+    }
+    let mut owners = vec![name.to_owned()];
+    let mut records = vec![];
+    for _ in 0..ancount {
+        let parse_rr = |rd: &mut Reader, owners: &mut Vec<String>| -> Option<Option<SrvRecord>> {
+            let owner = rd.name()?;
+            let rtype = rd.u16_be()?;
+            let class = rd.u16_be()?;
+            rd.bytes(4)?;
+            let rdlen = rd.u16_be()? as usize;
+            let next = rd.pos() + rdlen;
+            let known = owners.iter().any(|o| o.eq_ignore_ascii_case(&owner));
+            let record = match (rtype, class) {
+                (TYPE_SRV, CLASS_IN) if known => Some(SrvRecord {
+                    priority: rd.u16_be()?,
+                    weight: rd.u16_be()?,
+                    port: rd.u16_be()?,
+                    target: rd.name()?,
+                }),
+                (TYPE_CNAME, CLASS_IN) if known => {
+                    owners.push(rd.name()?);
+                    None
+                }
+                _ => None,
+            };
+            *rd = Reader::at(rd.buf, next);
+            Some(record)
+        };
+        if let Some(record) = parse_rr(&mut rd, &mut owners).ok_or_else(malformed)? {
+            records.push(record);
+        }
+    }
+    Ok(Some(records))
+}
+
+fn query_id() -> u16 {
+    use std::collections::hash_map::RandomState;
+    use std::hash::{BuildHasher, Hasher};
+
+    RandomState::new().build_hasher().finish() as u16
+}
+
+async fn query_udp(
+    server: SocketAddr,
+    id: u16,
+    name: &str,
+    query: &[u8],
+) -> Result<Option<Vec<SrvRecord>>> {
+    let local = if server.is_ipv4() {
+        "0.0.0.0:0"
+    } else {
+        "[::]:0"
+    };
+    let socket = UdpSocket::bind(local).await?;
+    socket.connect(server).await?;
+    socket.send(query).await?;
+    let mut buf = vec![0; 65536];
+    loop {
+        let len = socket.recv(&mut buf).await?;
+        // A stray datagram for a different query is ignored.
+        if len >= 2 && buf[..2] == id.to_be_bytes() {
+            return parse_srv_response(id, name, &buf[..len]);
+        }
+    }
+}
+
+async fn query_tcp(
+    server: SocketAddr,
+    id: u16,
+    name: &str,
+    query: &[u8],
+) -> Result<Vec<SrvRecord>> {
+    let mut stream = TcpStream::connect(server).await?;
+    let mut msg = Vec::with_capacity(query.len() + 2);
+    msg.extend(&(query.len() as u16).to_be_bytes());
+    msg.extend(query);
+    stream.write_all(&msg).await?;
+    let len = stream.read_u16().await? as usize;
+    let mut buf = vec![0; len];
+    stream.read_exact(&mut buf).await?;
+    parse_srv_response(id, name, &buf)?
+        .ok_or_else(|| LdapError::SrvLookup(String::from("truncated TCP response")))
+}
+
+/// Look up SRV records for `name`, trying the servers in order. A truncated UDP
+/// response is retried over TCP with the same server.
+pub(crate) async fn lookup_srv(
+    servers: &[SocketAddr],
+    name: &str,
+    timeout: Duration,
+) -> Result<Vec<SrvRecord>> {
+    let id = query_id();
+    let query = srv_query(id, name)?;
+    let mut last_err = LdapError::SrvLookup(String::from("no name servers"));
+    for &server in servers {
+        let res = time::timeout(timeout, async {
+            match query_udp(server, id, name, &query).await? {
+                Some(records) => Ok(records),
+                None => query_tcp(server, id, name, &query).await,
+            }
+        })
+        .await;
+        match res {
+            Ok(Ok(records)) => return Ok(records),
+            Ok(Err(e)) => last_err = e,
+            Err(e) => last_err = e.into(),
+        }
+    }
+    Err(last_err)
+}
+
+/// Name servers from `/etc/resolv.conf`, or the local host if there are none.
+pub(crate) fn system_nameservers() -> Vec<SocketAddr> {
+    let conf = std::fs::read_to_string("/etc/resolv.conf").unwrap_or_default();
+    let mut servers: Vec<SocketAddr> = conf
+        .lines()
+        .filter_map(|line| {
+            let mut words = line.split_whitespace();
+            match (words.next(), words.next()) {
+                (Some("nameserver"), Some(addr)) => addr
+                    // Strip the zone index of a link-local IPv6 address
+                    .split('%')
+                    .next()
+                    .and_then(|addr| addr.parse().ok())
+                    .map(|ip| SocketAddr::new(ip, 53)),
+                _ => None,
+            }
+        })
+        .collect();
+    if servers.is_empty() {
+        servers.push(SocketAddr::from(([127, 0, 0, 1], 53)));
+    }
+    servers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NAME: &str = "_ldap._tcp.example.com";
+
+    // Response to the query for NAME with the given flags, answer count and answers.
+    // The name in the question starts at offset 12, "example.com" at offset 23.
+    fn response(id: u16, flags: u16, ancount: u16, answers: &[u8]) -> Vec<u8> {
+        let query = srv_query(id, NAME).unwrap();
+        let mut resp = query[..query.len() - EDNS_OPT.len()].to_vec();
+        resp[2..4].copy_from_slice(&flags.to_be_bytes());
+        resp[6..8].copy_from_slice(&ancount.to_be_bytes());
+        resp[10..12].copy_from_slice(&[0, 0]);
+        resp.extend(answers);
+        resp
+    }
+
+    // SRV record for dc1.example.com, owned by the name at `owner`.
+    fn srv_rr(owner: u8, priority: u16) -> Vec<u8> {
+        let mut rr = vec![0xc0, owner, 0, 33, 0, 1, 0, 0, 0x0e, 0x10, 0, 12];
+        rr.extend(&priority.to_be_bytes());
+        rr.extend(&[0, 100, 0x01, 0x85]);
+        rr.extend(b"\x03dc1\xc0\x17");
+        rr
+    }
+
+    #[test]
+    fn parse_srv() {
+        // QR, RD, RA
+        let resp = response(0x1234, 0x8180, 1, &srv_rr(12, 10));
+        let records = parse_srv_response(0x1234, NAME, &resp).unwrap().unwrap();
+        assert_eq!(
+            records,
+            vec![SrvRecord {
+                priority: 10,
+                weight: 100,
+                port: 389,
+                target: String::from("dc1.example.com"),
+            }]
+        );
+        let records = parse_srv_response(0x1234, "_LDAP._tcp.Example.com.", &resp).unwrap();
+        assert_eq!(records.unwrap().len(), 1);
+    }
+
+    #[test]
+    fn truncated() {
+        let resp = response(1, 0x8380, 1, &srv_rr(12, 10));
+        assert!(parse_srv_response(1, NAME, &resp).unwrap().is_none());
+        let resp = response(1, 0x8180, 1, &srv_rr(12, 10));
+        for len in [3, 11, 20, resp.len() - 1] {
+            assert!(
+                parse_srv_response(1, NAME, &resp[..len]).is_err(),
+                "{}",
+                len
+            );
+        }
+        let resp = response(1, 0x8180, 2, &srv_rr(12, 10));
+        assert!(parse_srv_response(1, NAME, &resp).is_err());
+    }
+
+    #[test]
+    fn error_codes() {
+        let resp = response(1, 0x8183, 0, &[]);
+        assert_eq!(parse_srv_response(1, NAME, &resp).unwrap(), Some(vec![]));
+        let resp = response(1, 0x8182, 0, &[]);
+        assert!(parse_srv_response(1, NAME, &resp).is_err());
+        // Not a response
+        let resp = response(1, 0x0100, 0, &[]);
+        assert!(parse_srv_response(1, NAME, &resp).is_err());
+    }
+
+    #[test]
+    fn mismatch() {
+        let resp = response(1, 0x8180, 1, &srv_rr(12, 10));
+        assert!(parse_srv_response(2, NAME, &resp).is_err());
+        assert!(parse_srv_response(1, "_ldap._tcp.example.org", &resp).is_err());
+        let mut other_type = resp.clone();
+        let qtype = 12 + NAME.len() + 2;
+        other_type[qtype..qtype + 2].copy_from_slice(&[0, 1]);
+        assert!(parse_srv_response(1, NAME, &other_type).is_err());
+        let mut no_question = resp.clone();
+        no_question[4..6].copy_from_slice(&[0, 0]);
+        assert!(parse_srv_response(1, NAME, &no_question).is_err());
+        // A record owned by "example.com" is skipped.
+        let mut answers = srv_rr(23, 10);
+        answers.extend(srv_rr(12, 20));
+        let resp = response(1, 0x8180, 2, &answers);
+        let records = parse_srv_response(1, NAME, &resp).unwrap().unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].priority, 20);
+    }
+
+    #[test]
+    fn alias() {
+        // NAME is an alias of "srv.example.com", which owns the SRV record.
+        let alias_at = 12 + NAME.len() + 2 + 4 + 12;
+        let mut answers = vec![0xc0, 12, 0, 5, 0, 1, 0, 0, 0x0e, 0x10, 0, 6];
+        answers.extend(b"\x03srv\xc0\x17");
+        answers.extend(srv_rr(alias_at as u8, 10));
+        let resp = response(1, 0x8180, 2, &answers);
+        let records = parse_srv_response(1, NAME, &resp).unwrap().unwrap();
+        assert_eq!(records.len(), 1);
+    }
+}
@@ -0,0 +1,1235 @@
+//! DSMLv2 serialization of requests and responses.
+//!
+//! [DSMLv2](https://www.oasis-open.org/committees/dsml/) is the XML rendering of LDAP
+//! operations used by SOAP-based identity management systems. This module converts
+//! between DSML batches and the library's types in both directions: a client can emit
+//! a `batchRequest` built from the structures of the [`request`](../request/index.html)
+//! module, or parse one and replay its operations on a connection with
+//! [`DsmlRequest::send()`](struct.DsmlRequest.html#method.send); results are likewise
+//! written as, or parsed from, a `batchResponse`.
+//!
+//! Values which aren't valid UTF-8, or contain control characters, are written in
+//! base64 with `xsi:type="xsd:base64Binary"`. The `increment` modification isn't part
+//! of DSMLv2; it's written and recognized as an `operation` with that name. Bind,
+//! Unbind and Abandon aren't expressible in DSMLv2, and the batch processing attributes
+//! (`processing`, `onError`) are neither written nor interpreted.
+//!
+//! This module is available with the __dsml__ feature.
+//!
+//! ## Example
+//!
+//! ```rust
+//! use ldap3::dsml::{self, DsmlOp, DsmlRequest};
+//! use ldap3::request::{DeleteRequest, SearchRequest};
+//! use ldap3::Scope;
+//! # use ldap3::result::Result;
+//!
+//! # fn main() -> Result<()> {
+//! let batch = dsml::write_requests(&[
+//!     DsmlRequest::new(SearchRequest::new("dc=example,dc=org", Scope::Subtree, "(uid=jdoe)", vec!["cn"])),
+//!     DsmlRequest::new(DeleteRequest::new("uid=old,dc=example,dc=org")),
+//! ])?;
+//! let parsed = dsml::parse_requests(&batch)?;
+//! match parsed[0].op {
+//!     DsmlOp::Search(ref req) => assert_eq!(req.filter, "(uid=jdoe)"),
+//!     _ => unreachable!(),
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+
+use crate::controls::{Control, RawControl};
+use crate::exop::Exop;
+use crate::filter::{parse as parse_filter, unparse as unparse_filter};
+use crate::ldap::{Ldap, Mod};
+use crate::request::{
+    AddRequest, CompareRequest, DeleteRequest, ExtendedRequest, ModifyDnRequest, ModifyRequest,
+    SearchRequest,
+};
+use crate::result::{rc_description, LdapError, LdapResult, Result};
+use crate::search::{DerefAliases, Scope, SearchEntry, SearchOptions};
+use crate::util::{base64_decode, base64_encode};
+
+use lber::common::TagClass;
+use lber::structures::{Boolean, ExplicitTag, OctetString, Sequence, Tag};
+
+mod xml;
+use self::xml::{escape, Element};
+
+const DSML_NS: &str = "urn:oasis:names:tc:DSML:2:0:core";
+
+/// Largest nesting depth of XML elements accepted when parsing a batch.
+///
+/// The parser is recursive, so a document nested without bounds could exhaust the stack.
+pub const MAX_DEPTH: usize = 256;
+
+/// Operation of a DSML request.
+#[derive(Clone, Debug)]
+pub enum DsmlOp {
+    /// Search.
+    Search(SearchRequest),
+    /// Add.
+    Add(AddRequest),
+    /// Modify.
+    Modify(ModifyRequest),
+    /// Delete.
+    Delete(DeleteRequest),
+    /// ModifyDN.
+    ModifyDn(ModifyDnRequest),
+    /// Compare.
+    Compare(CompareRequest),
+    /// Extended operation.
+    Extended(ExtendedRequest),
+}
+
+macro_rules! op_from {
+    ($($variant:ident($req:ty)),+) => {
+        $(
+            impl From<$req> for DsmlOp {
+                fn from(req: $req) -> DsmlOp {
+                    DsmlOp::$variant(req)
+                }
+            }
+        )+
+    };
+}
+
+op_from!(
+    Search(SearchRequest),
+    Add(AddRequest),
+    Modify(ModifyRequest),
+    Delete(DeleteRequest),
+    ModifyDn(ModifyDnRequest),
+    Compare(CompareRequest),
+    Extended(ExtendedRequest)
+);
+
+/// Request in a DSML batch.
+#[derive(Clone, Debug)]
+pub struct DsmlRequest {
+    /// Value of the `requestID` attribute.
+    pub request_id: Option<String>,
+    /// Request controls.
+    pub ctrls: Vec<RawControl>,
+    /// The operation.
+    pub op: DsmlOp,
+}
+
+impl DsmlRequest {
+    /// Create a request without an id or controls.
+    pub fn new<O: Into<DsmlOp>>(op: O) -> Self {
+        DsmlRequest {
+            request_id: None,
+            ctrls: vec![],
+            op: op.into(),
+        }
+    }
+
+    /// Set the request id.
+    pub fn request_id(mut self, id: &str) -> Self {
+        self.request_id = Some(id.to_owned());
+        self
+    }
+
+    /// Perform the operation on `ldap`, with the request controls, and return the
+    /// response with the same request id.
+    pub async fn send(self, ldap: &mut Ldap) -> Result<DsmlResponse> {
+        if !self.ctrls.is_empty() {
+            ldap.with_controls(self.ctrls);
+        }
+        let result = match self.op {
+            DsmlOp::Search(req) => {
+                let rs = ldap.send(req).await?;
+                let entries = rs.0.into_iter().map(SearchEntry::construct).collect();
+                DsmlResult::Search(entries, rs.1)
+            }
+            DsmlOp::Add(req) => DsmlResult::Add(ldap.send(req).await?),
+            DsmlOp::Modify(req) => DsmlResult::Modify(ldap.send(req).await?),
+            DsmlOp::Delete(req) => DsmlResult::Delete(ldap.send(req).await?),
+            DsmlOp::ModifyDn(req) => DsmlResult::ModifyDn(ldap.send(req).await?),
+            DsmlOp::Compare(req) => DsmlResult::Compare(ldap.send(req).await?.0),
+            DsmlOp::Extended(req) => {
+                let er = ldap.send(req).await?;
+                DsmlResult::Extended(er.1, er.0)
+            }
+        };
+        Ok(DsmlResponse {
+            request_id: self.request_id,
+            result,
+        })
+    }
+}
+
+/// Result of an operation in a DSML batch.
+#[derive(Clone, Debug)]
+pub enum DsmlResult {
+    /// Search entries and the final result.
+    Search(Vec<SearchEntry>, LdapResult),
+    /// Add.
+    Add(LdapResult),
+    /// Modify.
+    Modify(LdapResult),
+    /// Delete.
+    Delete(LdapResult),
+    /// ModifyDN.
+    ModifyDn(LdapResult),
+    /// Compare.
+    Compare(LdapResult),
+    /// Extended operation, with the response name and value.
+    Extended(LdapResult, Exop),
+}
+
+/// Response in a DSML batch.
+#[derive(Clone, Debug)]
+pub struct DsmlResponse {
+    /// Value of the `requestID` attribute.
+    pub request_id: Option<String>,
+    /// The result.
+    pub result: DsmlResult,
+}
+
+fn dsml_error<T>(msg: String) -> Result<T> {
+    Err(LdapError::Dsml(msg))
+}
+
+// Writing.
+
+struct Out(String);
+
+impl Out {
+    fn open(&mut self, name: &str, attrs: &[(&str, &str)], empty: bool) {
+        self.0.push('<');
+        self.0.push_str(name);
+        for (n, v) in attrs {
+            let _ = write!(self.0, " {}=\"{}\"", n, escape(v));
+        }
+        self.0.push_str(if empty { "/>" } else { ">" });
+    }
+
+    fn close(&mut self, name: &str) {
+        let _ = write!(self.0, "</{}>", name);
+    }
+
+    fn text(&mut self, name: &str, text: &str) {
+        self.open(name, &[], false);
+        self.0.push_str(&escape(text));
+        self.close(name);
+    }
+
+    fn value(&mut self, name: &str, val: &[u8]) {
+        match std::str::from_utf8(val) {
+            Ok(s)
+                if !s
+                    .chars()
+                    .any(|c| c.is_control() && !matches!(c, '\t' | '\n')) =>
+            {
+                self.text(name, s)
+            }
+            _ => {
+                self.open(name, &[("xsi:type", "xsd:base64Binary")], false);
+                self.0.push_str(&base64_encode(val));
+                self.close(name);
+            }
+        }
+    }
+
+    fn attr<'v, I: IntoIterator<Item = &'v [u8]>>(&mut self, elem: &str, name: &str, vals: I) {
+        self.open(elem, &[("name", name)], false);
+        for val in vals {
+            self.value("value", val);
+        }
+        self.close(elem);
+    }
+
+    fn control(&mut self, ctrl: &RawControl) {
+        let crit = if ctrl.crit { "true" } else { "false" };
+        let attrs = [("type", ctrl.ctype.as_str()), ("criticality", crit)];
+        match ctrl.val {
+            Some(ref val) => {
+                self.open("control", &attrs, false);
+                self.open("controlValue", &[("xsi:type", "xsd:base64Binary")], false);
+                self.0.push_str(&base64_encode(val));
+                self.close("controlValue");
+                self.close("control");
+            }
+            None => self.open("control", &attrs, true),
+        }
+    }
+
+    fn batch(name: &str) -> Out {
+        let mut out = Out(String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>"));
+        out.open(
+            name,
+            &[
+                ("xmlns", DSML_NS),
+                ("xmlns:xsd", "http://www.w3.org/2001/XMLSchema"),
+                ("xmlns:xsi", "http://www.w3.org/2001/XMLSchema-instance"),
+            ],
+            false,
+        );
+        out
+    }
+}
+
+fn filter_xml(out: &mut Out, tag: &Tag) -> Result<()> {
+    fn octets(tag: &Tag) -> &[u8] {
+        match *tag {
+            Tag::OctetString(ref os) => &os.inner,
+            _ => b"",
+        }
+    }
+    fn name(tag: &Tag) -> String {
+        String::from_utf8_lossy(octets(tag)).into_owned()
+    }
+    match *tag {
+        Tag::Sequence(ref seq) => match seq.id {
+            0 | 1 => {
+                let op = if seq.id == 0 { "and" } else { "or" };
+                out.open(op, &[], false);
+                for inner in &seq.inner {
+                    filter_xml(out, inner)?;
+                }
+                out.close(op);
+            }
+            3 | 5 | 6 | 8 if seq.inner.len() == 2 => {
+                let op = match seq.id {
+                    3 => "equalityMatch",
+                    5 => "greaterOrEqual",
+                    6 => "lessOrEqual",
+                    _ => "approxMatch",
+                };
+                out.attr(op, &name(&seq.inner[0]), Some(octets(&seq.inner[1])));
+            }
+            4 if seq.inner.len() == 2 => {
+                out.open("substrings", &[("name", &name(&seq.inner[0]))], false);
+                if let Tag::Sequence(ref subs) = seq.inner[1] {
+                    for sub in &subs.inner {
+                        if let Tag::OctetString(ref os) = *sub {
+                            let part = match os.id {
+                                0 => "initial",
+                                1 => "any",
+                                _ => "final",
+                            };
+                            out.value(part, &os.inner);
+                        }
+                    }
+                }
+                out.close("substrings");
+            }
+            9 => {
+                let (mut rule, mut attr, mut value, mut dn) = (None, None, &b""[..], false);
+                for part in &seq.inner {
+                    match *part {
+                        Tag::OctetString(ref os) if os.id == 1 => rule = Some(name(part)),
+                        Tag::OctetString(ref os) if os.id == 2 => attr = Some(name(part)),
+                        Tag::OctetString(ref os) if os.id == 3 => value = &os.inner,
+                        Tag::Boolean(ref b) if b.id == 4 => dn = b.inner,
+                        _ => (),
+                    }
+                }
+                let mut attrs = vec![("dnAttributes", if dn { "true" } else { "false" })];
+                if let Some(ref rule) = rule {
+                    attrs.push(("matchingRule", rule));
+                }
+                if let Some(ref attr) = attr {
+                    attrs.push(("name", attr));
+                }
+                out.open("extensibleMatch", &attrs, false);
+                out.value("value", value);
+                out.close("extensibleMatch");
+            }
+            _ => return dsml_error(String::from("unrecognized filter item")),
+        },
+        Tag::ExplicitTag(ref et) if et.id == 2 => {
+            out.open("not", &[], false);
+            filter_xml(out, &et.inner)?;
+            out.close("not");
+        }
+        Tag::OctetString(ref os) if os.id == 7 => {
+            out.open("present", &[("name", &name(tag))], true);
+        }
+        _ => return dsml_error(String::from("unrecognized filter item")),
+    }
+    Ok(())
+}
+
+fn write_request(out: &mut Out, req: &DsmlRequest) -> Result<()> {
+    let mut attrs: Vec<(&str, String)> = vec![];
+    if let Some(ref id) = req.request_id {
+        attrs.push(("requestID", id.clone()));
+    }
+    let name = match req.op {
+        DsmlOp::Search(ref s) => {
+            attrs.push(("dn", s.base.clone()));
+            let scope = match s.scope {
+                Scope::Base => "baseObject",
+                Scope::OneLevel => "singleLevel",
+                Scope::Subtree => "wholeSubtree",
+            };
+            attrs.push(("scope", scope.to_owned()));
+            let deref = match s.opts.deref {
+                DerefAliases::Never => "neverDerefAliases",
+                DerefAliases::Searching => "derefInSearching",
+                DerefAliases::Finding => "derefFindingBaseObj",
+                DerefAliases::Always => "derefAlways",
+            };
+            attrs.push(("derefAliases", deref.to_owned()));
+            if s.opts.sizelimit != 0 {
+                attrs.push(("sizeLimit", s.opts.sizelimit.to_string()));
+            }
+            if s.opts.timelimit != 0 {
+                attrs.push(("timeLimit", s.opts.timelimit.to_string()));
+            }
+            if s.opts.typesonly {
+                attrs.push(("typesOnly", String::from("true")));
+            }
+            "searchRequest"
+        }
+        DsmlOp::Add(ref a) => {
+            attrs.push(("dn", a.dn.clone()));
+            "addRequest"
+        }
+        DsmlOp::Modify(ref m) => {
+            attrs.push(("dn", m.dn.clone()));
+            "modifyRequest"
+        }
+        DsmlOp::Delete(ref d) => {
+            attrs.push(("dn", d.dn.clone()));
+            "delRequest"
+        }
+        DsmlOp::ModifyDn(ref m) => {
+            attrs.push(("dn", m.dn.clone()));
+            attrs.push(("newrdn", m.rdn.clone()));
+            attrs.push(("deleteoldrdn", m.delete_old.to_string()));
+            if let Some(ref sup) = m.new_sup {
+                attrs.push(("newSuperior", sup.clone()));
+            }
+            "modDNRequest"
+        }
+        DsmlOp::Compare(ref c) => {
+            attrs.push(("dn", c.dn.clone()));
+            "compareRequest"
+        }
+        DsmlOp::Extended(_) => "extendedRequest",
+    };
+    let attrs: Vec<_> = attrs.iter().map(|(n, v)| (*n, v.as_str())).collect();
+    out.open(name, &attrs, false);
+    for ctrl in &req.ctrls {
+        out.control(ctrl);
+    }
+    match req.op {
+        DsmlOp::Search(ref s) => {
+            let filter = parse_filter(&s.filter).map_err(|_| LdapError::FilterParsing)?;
+            out.open("filter", &[], false);
+            filter_xml(out, &filter)?;
+            out.close("filter");
+            if !s.attrs.is_empty() {
+                out.open("attributes", &[], false);
+                for attr in &s.attrs {
+                    out.open("attribute", &[("name", attr)], true);
+                }
+                out.close("attributes");
+            }
+        }
+        DsmlOp::Add(ref a) => {
+            for (name, vals) in &a.attrs {
+                let name = String::from_utf8_lossy(name);
+                out.attr("attr", &name, vals.iter().map(Vec::as_slice));
+            }
+        }
+        DsmlOp::Modify(ref m) => {
+            for m in &m.mods {
+                let (op, name, vals) = match *m {
+                    Mod::Add(ref n, ref v) => ("add", n, v.iter().collect::<Vec<_>>()),
+                    Mod::Delete(ref n, ref v) => ("delete", n, v.iter().collect()),
+                    Mod::Replace(ref n, ref v) => ("replace", n, v.iter().collect()),
+                    Mod::Increment(ref n, ref v) => ("increment", n, vec![v]),
+                };
+                let name = String::from_utf8_lossy(name);
+                out.open("modification", &[("name", &name), ("operation", op)], false);
+                for val in vals {
+                    out.value("value", val);
+                }
+                out.close("modification");
+            }
+        }
+        DsmlOp::Compare(ref c) => out.attr("assertion", &c.attr, Some(&c.val[..])),
+        DsmlOp::Extended(ref e) => {
+            out.text("requestName", e.exop.name.as_deref().unwrap_or(""));
+            if let Some(ref val) = e.exop.val {
+                out.open("requestValue", &[("xsi:type", "xsd:base64Binary")], false);
+                out.0.push_str(&base64_encode(val));
+                out.close("requestValue");
+            }
+        }
+        DsmlOp::Delete(_) | DsmlOp::ModifyDn(_) => (),
+    }
+    out.close(name);
+    Ok(())
+}
+
+/// Write the requests as a DSMLv2 `batchRequest`. Fails if the filter of a Search
+/// can't be parsed.
+pub fn write_requests(reqs: &[DsmlRequest]) -> Result<String> {
+    let mut out = Out::batch("batchRequest");
+    for req in reqs {
+        write_request(&mut out, req)?;
+    }
+    out.close("batchRequest");
+    Ok(out.0)
+}
+
+fn write_result(
+    out: &mut Out,
+    name: &str,
+    id: &Option<String>,
+    res: &LdapResult,
+    exop: Option<&Exop>,
+) {
+    let mut attrs = vec![];
+    if let Some(ref id) = *id {
+        attrs.push(("requestID", id.as_str()));
+    }
+    if !res.matched.is_empty() {
+        attrs.push(("matchedDN", res.matched.as_str()));
+    }
+    out.open(name, &attrs, false);
+    for ctrl in &res.ctrls {
+        out.control(&ctrl.1);
+    }
+    let code = res.rc.to_string();
+    out.open(
+        "resultCode",
+        &[("code", &code), ("descr", rc_description(res.rc))],
+        true,
+    );
+    if !res.text.is_empty() {
+        out.text("errorMessage", &res.text);
+    }
+    for r in &res.refs {
+        out.text("referral", r);
+    }
+    if let Some(exop) = exop {
+        if let Some(ref name) = exop.name {
+            out.text("responseName", name);
+        }
+        if let Some(ref val) = exop.val {
+            out.open("response", &[("xsi:type", "xsd:base64Binary")], false);
+            out.0.push_str(&base64_encode(val));
+            out.close("response");
+        }
+    }
+    out.close(name);
+}
+
+/// Write the responses as a DSMLv2 `batchResponse`.
+pub fn write_responses(resps: &[DsmlResponse]) -> String {
+    let mut out = Out::batch("batchResponse");
+    for resp in resps {
+        let id = &resp.request_id;
+        match resp.result {
+            DsmlResult::Search(ref entries, ref res) => {
+                let attrs: Vec<_> = id.iter().map(|id| ("requestID", id.as_str())).collect();
+                out.open("searchResponse", &attrs, false);
+                for entry in entries {
+                    out.open("searchResultEntry", &[("dn", &entry.dn)], false);
+                    for ctrl in &entry.ctrls {
+                        out.control(&ctrl.1);
+                    }
+                    let mut names: Vec<_> =
+                        entry.attrs.keys().chain(entry.bin_attrs.keys()).collect();
+                    names.sort();
+                    for name in names {
+                        match entry.attrs.get(name) {
+                            Some(vals) => out.attr("attr", name, vals.iter().map(|v| v.as_bytes())),
+                            None => out.attr(
+                                "attr",
+                                name,
+                                entry.bin_attrs[name].iter().map(Vec::as_slice),
+                            ),
+                        }
+                    }
+                    out.close("searchResultEntry");
+                }
+                write_result(&mut out, "searchResultDone", &None, res, None);
+                out.close("searchResponse");
+            }
+            DsmlResult::Add(ref res) => write_result(&mut out, "addResponse", id, res, None),
+            DsmlResult::Modify(ref res) => write_result(&mut out, "modifyResponse", id, res, None),
+            DsmlResult::Delete(ref res) => write_result(&mut out, "delResponse", id, res, None),
+            DsmlResult::ModifyDn(ref res) => write_result(&mut out, "modDNResponse", id, res, None),
+            DsmlResult::Compare(ref res) => {
+                write_result(&mut out, "compareResponse", id, res, None)
+            }
+            DsmlResult::Extended(ref res, ref exop) => {
+                write_result(&mut out, "extendedResponse", id, res, Some(exop))
+            }
+        }
+    }
+    out.close("batchResponse");
+    out.0
+}
+
+// Parsing.
+
+fn required<'e>(elem: &'e Element, attr: &str) -> Result<&'e str> {
+    match elem.attr(attr) {
+        Some(v) => Ok(v),
+        None => dsml_error(format!("{} without {}", elem.name, attr)),
+    }
+}
+
+fn flag(elem: &Element, attr: &str) -> bool {
+    matches!(elem.attr(attr), Some("true") | Some("1"))
+}
+
+fn number(elem: &Element, attr: &str) -> Result<i32> {
+    match elem.attr(attr) {
+        Some(v) => v
+            .parse()
+            .or_else(|_| dsml_error(format!("invalid {} in {}", attr, elem.name))),
+        None => Ok(0),
+    }
+}
+
+fn bytes(elem: &Element) -> Result<Vec<u8>> {
+    if elem
+        .attr("type")
+        .is_some_and(|t| t.ends_with("base64Binary"))
+    {
+        base64_decode(&elem.text).or_else(dsml_error)
+    } else {
+        Ok(elem.text.clone().into_bytes())
+    }
+}
+
+fn values(elem: &Element) -> Result<Vec<Vec<u8>>> {
+    elem.children_named("value").map(bytes).collect()
+}
+
+fn controls(elem: &Element) -> Result<Vec<RawControl>> {
+    elem.children_named("control")
+        .map(|c| {
+            Ok(RawControl {
+                ctype: required(c, "type")?.to_owned(),
+                crit: flag(c, "criticality"),
+                val: c.child("controlValue").map(bytes).transpose()?,
+            })
+        })
+        .collect()
+}
+
+fn filter_tag(elem: &Element) -> Result<Tag> {
+    fn octets(id: u64, class: TagClass, inner: Vec<u8>) -> Tag {
+        Tag::OctetString(OctetString { id, class, inner })
+    }
+    fn universal(inner: &str) -> Tag {
+        octets(4, TagClass::Universal, inner.as_bytes().to_vec())
+    }
+    fn seq(id: u64, inner: Vec<Tag>) -> Tag {
+        Tag::Sequence(Sequence {
+            id,
+            class: TagClass::Context,
+            inner,
+        })
+    }
+    let tag = match elem.name.as_str() {
+        "and" | "or" => seq(
+            if elem.name == "and" { 0 } else { 1 },
+            elem.children
+                .iter()
+                .map(filter_tag)
+                .collect::<Result<_>>()?,
+        ),
+        "not" => match elem.children.first() {
+            Some(inner) => Tag::ExplicitTag(ExplicitTag {
+                id: 2,
+                class: TagClass::Context,
+                inner: Box::new(filter_tag(inner)?),
+            }),
+            None => return dsml_error(String::from("empty not filter")),
+        },
+        "equalityMatch" | "greaterOrEqual" | "lessOrEqual" | "approxMatch" => {
+            let id = match elem.name.as_str() {
+                "equalityMatch" => 3,
+                "greaterOrEqual" => 5,
+                "lessOrEqual" => 6,
+                _ => 8,
+            };
+            let val = match elem.child("value") {
+                Some(val) => bytes(val)?,
+                None => return dsml_error(format!("{} without value", elem.name)),
+            };
+            seq(
+                id,
+                vec![
+                    universal(required(elem, "name")?),
+                    octets(4, TagClass::Universal, val),
+                ],
+            )
+        }
+        "substrings" => {
+            let mut subs = vec![];
+            for part in &elem.children {
+                let id = match part.name.as_str() {
+                    "initial" => 0,
+                    "any" => 1,
+                    "final" => 2,
+                    _ => continue,
+                };
+                subs.push(octets(id, TagClass::Context, bytes(part)?));
+            }
+            seq(
+                4,
+                vec![
+                    universal(required(elem, "name")?),
+                    Tag::Sequence(Sequence {
+                        inner: subs,
+                        ..Default::default()
+                    }),
+                ],
+            )
+        }
+        "present" => octets(
+            7,
+            TagClass::Context,
+            required(elem, "name")?.as_bytes().to_vec(),
+        ),
+        "extensibleMatch" => {
+            let mut parts = vec![];
+            if let Some(rule) = elem.attr("matchingRule") {
+                parts.push(octets(1, TagClass::Context, rule.as_bytes().to_vec()));
+            }
+            if let Some(name) = elem.attr("name") {
+                parts.push(octets(2, TagClass::Context, name.as_bytes().to_vec()));
+            }
+            let val = match elem.child("value") {
+                Some(val) => bytes(val)?,
+                None => vec![],
+            };
+            parts.push(octets(3, TagClass::Context, val));
+            if flag(elem, "dnAttributes") {
+                parts.push(Tag::Boolean(Boolean {
+                    id: 4,
+                    class: TagClass::Context,
+                    inner: true,
+                }));
+            }
+            seq(9, parts)
+        }
+        other => return dsml_error(format!("unknown filter element {}", other)),
+    };
+    Ok(tag)
+}
+
+fn parse_request(elem: &Element) -> Result<DsmlRequest> {
+    let dn = || required(elem, "dn");
+    let op = match elem.name.as_str() {
+        "searchRequest" => {
+            let scope = match required(elem, "scope")? {
+                "baseObject" => Scope::Base,
+                "singleLevel" => Scope::OneLevel,
+                "wholeSubtree" => Scope::Subtree,
+                other => return dsml_error(format!("unknown scope {}", other)),
+            };
+            let deref = match required(elem, "derefAliases")? {
+                "neverDerefAliases" => DerefAliases::Never,
+                "derefInSearching" => DerefAliases::Searching,
+                "derefFindingBaseObj" => DerefAliases::Finding,
+                "derefAlways" => DerefAliases::Always,
+                other => return dsml_error(format!("unknown derefAliases {}", other)),
+            };
+            let filter = match elem.child("filter").and_then(|f| f.children.first()) {
+                Some(filter) => unparse_filter(&filter_tag(filter)?),
+                None => return dsml_error(String::from("searchRequest without filter")),
+            };
+            let attrs = match elem.child("attributes") {
+                Some(attrs) => attrs
+                    .children_named("attribute")
+                    .map(|a| required(a, "name"))
+                    .collect::<Result<Vec<_>>>()?,
+                None => vec![],
+            };
+            let opts = SearchOptions::new()
+                .deref(deref)
+                .sizelimit(number(elem, "sizeLimit")?)
+                .timelimit(number(elem, "timeLimit")?)
+                .typesonly(flag(elem, "typesOnly"));
+            DsmlOp::Search(SearchRequest::new(dn()?, scope, &filter, attrs).opts(opts))
+        }
+        "addRequest" => {
+            let attrs = elem
+                .children_named("attr")
+                .map(|a| {
+                    let vals = values(a)?.into_iter().collect::<HashSet<_>>();
+                    Ok((required(a, "name")?.as_bytes().to_vec(), vals))
+                })
+                .collect::<Result<_>>()?;
+            DsmlOp::Add(AddRequest {
+                dn: dn()?.to_owned(),
+                attrs,
+            })
+        }
+        "modifyRequest" => {
+            let mut mods = vec![];
+            for m in elem.children_named("modification") {
+                let name = required(m, "name")?.as_bytes().to_vec();
+                let vals = values(m)?;
+                mods.push(match required(m, "operation")? {
+                    "add" => Mod::Add(name, vals.into_iter().collect()),
+                    "delete" => Mod::Delete(name, vals.into_iter().collect()),
+                    "replace" => Mod::Replace(name, vals.into_iter().collect()),
+                    "increment" => {
+                        Mod::Increment(name, vals.into_iter().next().unwrap_or_default())
+                    }
+                    other => return dsml_error(format!("unknown operation {}", other)),
+                });
+            }
+            DsmlOp::Modify(ModifyRequest {
+                dn: dn()?.to_owned(),
+                mods,
+            })
+        }
+        "delRequest" => DsmlOp::Delete(DeleteRequest::new(dn()?)),
+        "modDNRequest" => DsmlOp::ModifyDn(ModifyDnRequest::new(
+            dn()?,
+            required(elem, "newrdn")?,
+            elem.attr("deleteoldrdn") != Some("false"),
+            elem.attr("newSuperior"),
+        )),
+        "compareRequest" => {
+            let assertion = match elem.child("assertion") {
+                Some(a) => a,
+                None => return dsml_error(String::from("compareRequest without assertion")),
+            };
+            let val = match assertion.child("value") {
+                Some(val) => bytes(val)?,
+                None => return dsml_error(String::from("assertion without value")),
+            };
+            DsmlOp::Compare(CompareRequest::new(
+                dn()?,
+                required(assertion, "name")?,
+                val,
+            ))
+        }
+        "extendedRequest" => {
+            let name = match elem.child("requestName") {
+                Some(name) => name.text.trim().to_owned(),
+                None => return dsml_error(String::from("extendedRequest without requestName")),
+            };
+            DsmlOp::Extended(ExtendedRequest {
+                exop: Exop {
+                    name: Some(name),
+                    val: elem.child("requestValue").map(bytes).transpose()?,
+                },
+            })
+        }
+        other => return dsml_error(format!("unsupported request {}", other)),
+    };
+    Ok(DsmlRequest {
+        request_id: elem.attr("requestID").map(String::from),
+        ctrls: controls(elem)?,
+        op,
+    })
+}
+
+fn batch(xml: &str, name: &str) -> Result<Element> {
+    let root = xml::parse(xml).or_else(dsml_error)?;
+    if root.name != name {
+        return dsml_error(format!("expected {}, found {}", name, root.name));
+    }
+    Ok(root)
+}
+
+/// Parse a DSMLv2 `batchRequest` into its requests. A document with elements nested
+/// deeper than [`MAX_DEPTH`](constant.MAX_DEPTH.html) is an error.
+pub fn parse_requests(xml: &str) -> Result<Vec<DsmlRequest>> {
+    batch(xml, "batchRequest")?
+        .children
+        .iter()
+        .filter(|e| e.name != "control")
+        .map(parse_request)
+        .collect()
+}
+
+fn parse_result(elem: &Element) -> Result<LdapResult> {
+    let rc = match elem.child("resultCode") {
+        Some(rc) => required(rc, "code")?
+            .parse()
+            .or_else(|_| dsml_error(String::from("invalid result code")))?,
+        None => return dsml_error(format!("{} without resultCode", elem.name)),
+    };
+    Ok(LdapResult {
+        rc,
+        matched: elem.attr("matchedDN").unwrap_or("").to_owned(),
+        text: elem
+            .child("errorMessage")
+            .map(|e| e.text.clone())
+            .unwrap_or_default(),
+        refs: elem
+            .children_named("referral")
+            .map(|r| r.text.trim().to_owned())
+            .collect(),
+        ctrls: controls(elem)?
+            .into_iter()
+            .map(|c| Control(None, c))
+            .collect(),
+    })
+}
+
+fn parse_entry(elem: &Element) -> Result<SearchEntry> {
+    let mut attrs = HashMap::new();
+    let mut bin_attrs = HashMap::new();
+    for attr in elem.children_named("attr") {
+        let name = required(attr, "name")?.to_owned();
+        let vals = values(attr)?;
+        match vals
+            .iter()
+            .map(|v| String::from_utf8(v.clone()))
+            .collect::<std::result::Result<Vec<_>, _>>()
+        {
+            Ok(strs) => {
+                attrs.insert(name, strs);
+            }
+            Err(_) => {
+                bin_attrs.insert(name, vals);
+            }
+        }
+    }
+    Ok(SearchEntry {
+        dn: required(elem, "dn")?.to_owned(),
+        attrs,
+        bin_attrs,
+        ctrls: controls(elem)?
+            .into_iter()
+            .map(|c| Control(None, c))
+            .collect(),
+    })
+}
+
+fn parse_response(elem: &Element) -> Result<DsmlResponse> {
+    let result = match elem.name.as_str() {
+        "searchResponse" => {
+            let entries = elem
+                .children_named("searchResultEntry")
+                .map(parse_entry)
+                .collect::<Result<_>>()?;
+            let done = match elem.child("searchResultDone") {
+                Some(done) => parse_result(done)?,
+                None => return dsml_error(String::from("searchResponse without searchResultDone")),
+            };
+            DsmlResult::Search(entries, done)
+        }
+        "addResponse" => DsmlResult::Add(parse_result(elem)?),
+        "modifyResponse" => DsmlResult::Modify(parse_result(elem)?),
+        "delResponse" => DsmlResult::Delete(parse_result(elem)?),
+        "modDNResponse" => DsmlResult::ModifyDn(parse_result(elem)?),
+        "compareResponse" => DsmlResult::Compare(parse_result(elem)?),
+        "extendedResponse" => DsmlResult::Extended(
+            parse_result(elem)?,
+            Exop {
+                name: elem.child("responseName").map(|n| n.text.trim().to_owned()),
+                val: elem.child("response").map(bytes).transpose()?,
+            },
+        ),
+        "errorResponse" => {
+            let msg = elem.child("message").map(|m| m.text.as_str()).unwrap_or("");
+            return dsml_error(format!(
+                "errorResponse {}: {}",
+                elem.attr("type").unwrap_or("other"),
+                msg
+            ));
+        }
+        other => return dsml_error(format!("unsupported response {}", other)),
+    };
+    Ok(DsmlResponse {
+        request_id: elem.attr("requestID").map(String::from),
+        result,
+    })
+}
+
+/// Parse a DSMLv2 `batchResponse` into its responses. An `errorResponse` in the batch
+/// is returned as an error, as is a document nested deeper than
+/// [`MAX_DEPTH`](constant.MAX_DEPTH.html).
+pub fn parse_responses(xml: &str) -> Result<Vec<DsmlResponse>> {
+    batch(xml, "batchResponse")?
+        .children
+        .iter()
+        .filter(|e| e.name != "control")
+        .map(parse_response)
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::{parse_requests, parse_responses, write_requests, write_responses};
+    use super::{DsmlOp, DsmlRequest, DsmlResponse, DsmlResult, MAX_DEPTH};
+    use crate::request::{ModifyRequest, SearchRequest};
+    use crate::{DerefAliases, LdapResult, Mod, Scope, SearchEntry};
+
+    use maplit::hashset;
+
+    #[test]
+    fn round_trip() {
+        let filter = "(&(objectClass=person)(|(cn=J*n*e)(!(uid=\\2a)))(mail=*)(cn:dn:2.5.13.2:=x))";
+        let batch = write_requests(&[
+            DsmlRequest::new(SearchRequest::new(
+                "dc=x",
+                Scope::OneLevel,
+                filter,
+                vec!["cn"],
+            ))
+            .request_id("1"),
+            DsmlRequest::new(ModifyRequest::new(
+                "cn=a<b,dc=x",
+                vec![Mod::Replace(&b"photo"[..], hashset! { &b"\xff\x00"[..] })],
+            )),
+        ])
+        .unwrap();
+        let reqs = parse_requests(&batch).unwrap();
+        assert_eq!(reqs[0].request_id.as_deref(), Some("1"));
+        match reqs[0].op {
+            DsmlOp::Search(ref s) => {
+                assert_eq!(s.filter, filter);
+                assert_eq!(s.scope, Scope::OneLevel);
+                assert_eq!(s.attrs, vec!["cn"]);
+            }
+            _ => panic!("search expected"),
+        }
+        match reqs[1].op {
+            DsmlOp::Modify(ref m) => {
+                assert_eq!(m.dn, "cn=a<b,dc=x");
+                assert_eq!(
+                    m.mods,
+                    vec![Mod::Replace(b"photo".to_vec(), hashset! { vec![0xff, 0] })]
+                );
+            }
+            _ => panic!("modify expected"),
+        }
+        let entry = SearchEntry {
+            dn: String::from("cn=a,dc=x"),
+            attrs: vec![(String::from("cn"), vec![String::from("a & b")])]
+                .into_iter()
+                .collect(),
+            bin_attrs: HashMap::new(),
+            ctrls: vec![],
+        };
+        let batch = write_responses(&[DsmlResponse {
+            request_id: Some(String::from("1")),
+            result: DsmlResult::Search(
+                vec![entry],
+                LdapResult {
+                    rc: 4,
+                    ..LdapResult::default()
+                },
+            ),
+        }]);
+        assert!(batch.contains("<resultCode code=\"4\" descr=\"sizeLimitExceeded\"/>"));
+        let resps = parse_responses(&batch).unwrap();
+        match resps[0].result {
+            DsmlResult::Search(ref entries, ref res) => {
+                assert_eq!(entries[0].attrs["cn"], vec!["a & b"]);
+                assert_eq!(res.rc, 4);
+            }
+            _ => panic!("search expected"),
+        }
+    }
+
+    fn requests(body: &str) -> Vec<DsmlRequest> {
+        parse_requests(&format!(
+            "<batchRequest xmlns=\"urn:oasis:names:tc:DSML:2:0:core\">{}</batchRequest>",
+            body
+        ))
+        .unwrap()
+    }
+
+    fn responses(body: &str) -> super::Result<Vec<DsmlResponse>> {
+        parse_responses(&format!(
+            "<batchResponse xmlns=\"urn:oasis:names:tc:DSML:2:0:core\">{}</batchResponse>",
+            body
+        ))
+    }
+
+    #[test]
+    fn parse_each_request() {
+        let reqs = requests(
+            "<searchRequest requestID=\"s\" dn=\"dc=x\" scope=\"wholeSubtree\" \
+               derefAliases=\"derefAlways\" sizeLimit=\"10\" timeLimit=\"5\" typesOnly=\"true\">\
+               <control type=\"1.2.3\" criticality=\"true\"><controlValue>v</controlValue></control>\
+               <filter><and><substrings name=\"cn\"><initial>a</initial><any>b</any><final>c</final></substrings>\
+               <greaterOrEqual name=\"n\"><value>1</value></greaterOrEqual>\
+               <approxMatch name=\"sn\"><value>x</value></approxMatch>\
+               <present name=\"mail\"/></and></filter>\
+               <attributes><attribute name=\"cn\"/><attribute name=\"sn\"/></attributes>\
+             </searchRequest>\
+             <addRequest dn=\"cn=a,dc=x\"><attr name=\"cn\"><value>a</value></attr>\
+               <attr name=\"jpegPhoto\"><value xsi:type=\"xsd:base64Binary\">/wA=</value></attr></addRequest>\
+             <modifyRequest dn=\"cn=a,dc=x\">\
+               <modification name=\"sn\" operation=\"add\"><value>b</value></modification>\
+               <modification name=\"cn\" operation=\"delete\"/>\
+               <modification name=\"n\" operation=\"increment\"><value>2</value></modification>\
+             </modifyRequest>\
+             <delRequest dn=\"cn=a,dc=x\"/>\
+             <modDNRequest dn=\"cn=a,dc=x\" newrdn=\"cn=b\" deleteoldrdn=\"false\" newSuperior=\"dc=y\"/>\
+             <compareRequest dn=\"cn=a,dc=x\"><assertion name=\"cn\"><value>a</value></assertion></compareRequest>\
+             <extendedRequest><requestName> 1.3.6.1.4.1.4203.1.11.3 </requestName></extendedRequest>",
+        );
+        assert_eq!(reqs.len(), 7);
+        assert_eq!(reqs[0].request_id.as_deref(), Some("s"));
+        assert_eq!(reqs[0].ctrls[0].ctype, "1.2.3");
+        assert!(reqs[0].ctrls[0].crit);
+        assert_eq!(reqs[0].ctrls[0].val.as_deref(), Some(&b"v"[..]));
+        match reqs[0].op {
+            DsmlOp::Search(ref s) => {
+                assert_eq!(s.base, "dc=x");
+                assert_eq!(s.scope, Scope::Subtree);
+                assert_eq!(s.filter, "(&(cn=a*b*c)(n>=1)(sn~=x)(mail=*))");
+                assert_eq!(s.attrs, vec!["cn", "sn"]);
+                assert_eq!(s.opts.deref, DerefAliases::Always);
+                assert_eq!((s.opts.sizelimit, s.opts.timelimit), (10, 5));
+                assert!(s.opts.typesonly);
+            }
+            _ => panic!("search expected"),
+        }
+        match reqs[1].op {
+            DsmlOp::Add(ref a) => {
+                assert_eq!(a.dn, "cn=a,dc=x");
+                assert_eq!(
+                    a.attrs,
+                    vec![
+                        (b"cn".to_vec(), hashset! { b"a".to_vec() }),
+                        (b"jpegPhoto".to_vec(), hashset! { vec![0xff, 0] }),
+                    ]
+                );
+            }
+            _ => panic!("add expected"),
+        }
+        match reqs[2].op {
+            DsmlOp::Modify(ref m) => assert_eq!(
+                m.mods,
+                vec![
+                    Mod::Add(b"sn".to_vec(), hashset! { b"b".to_vec() }),
+                    Mod::Delete(b"cn".to_vec(), hashset! {}),
+                    Mod::Increment(b"n".to_vec(), b"2".to_vec()),
+                ]
+            ),
+            _ => panic!("modify expected"),
+        }
+        match reqs[3].op {
+            DsmlOp::Delete(ref d) => assert_eq!(d.dn, "cn=a,dc=x"),
+            _ => panic!("delete expected"),
+        }
+        match reqs[4].op {
+            DsmlOp::ModifyDn(ref m) => {
+                assert_eq!((m.dn.as_str(), m.rdn.as_str()), ("cn=a,dc=x", "cn=b"));
+                assert!(!m.delete_old);
+                assert_eq!(m.new_sup.as_deref(), Some("dc=y"));
+            }
+            _ => panic!("modDN expected"),
+        }
+        match reqs[5].op {
+            DsmlOp::Compare(ref c) => {
+                assert_eq!((c.attr.as_str(), c.val.as_slice()), ("cn", &b"a"[..]));
+            }
+            _ => panic!("compare expected"),
+        }
+        match reqs[6].op {
+            DsmlOp::Extended(ref e) => {
+                assert_eq!(e.exop.name.as_deref(), Some("1.3.6.1.4.1.4203.1.11.3"));
+                assert!(e.exop.val.is_none());
+            }
+            _ => panic!("extended expected"),
+        }
+    }
+
+    #[test]
+    fn parse_each_response() {
+        let resps = responses(
+            "<searchResponse requestID=\"s\">\
+               <searchResultEntry dn=\"cn=a,dc=x\"><attr name=\"cn\"><value>a</value></attr>\
+                 <attr name=\"jpegPhoto\"><value xsi:type=\"xsd:base64Binary\">/wA=</value></attr>\
+               </searchResultEntry>\
+               <searchResultDone><resultCode code=\"0\"/></searchResultDone>\
+             </searchResponse>\
+             <addResponse><resultCode code=\"68\"/><errorMessage>exists</errorMessage></addResponse>\
+             <modifyResponse matchedDN=\"dc=x\"><resultCode code=\"32\"/></modifyResponse>\
+             <delResponse><resultCode code=\"10\"/><referral>ldap://other/</referral></delResponse>\
+             <modDNResponse><resultCode code=\"0\"/></modDNResponse>\
+             <compareResponse><resultCode code=\"6\"/></compareResponse>\
+             <extendedResponse><resultCode code=\"0\"/><responseName>1.2.3</responseName>\
+               <response xsi:type=\"xsd:base64Binary\">AQI=</response></extendedResponse>",
+        )
+        .unwrap();
+        assert_eq!(resps.len(), 7);
+        assert_eq!(resps[0].request_id.as_deref(), Some("s"));
+        match resps[0].result {
+            DsmlResult::Search(ref entries, ref done) => {
+                assert_eq!(entries[0].dn, "cn=a,dc=x");
+                assert_eq!(entries[0].attrs["cn"], vec!["a"]);
+                assert_eq!(entries[0].bin_attrs["jpegPhoto"], vec![vec![0xff, 0]]);
+                assert_eq!(done.rc, 0);
+            }
+            _ => panic!("search expected"),
+        }
+        match resps[1].result {
+            DsmlResult::Add(ref res) => assert_eq!((res.rc, res.text.as_str()), (68, "exists")),
+            _ => panic!("add expected"),
+        }
+        match resps[2].result {
+            DsmlResult::Modify(ref res) => assert_eq!((res.rc, res.matched.as_str()), (32, "dc=x")),
+            _ => panic!("modify expected"),
+        }
+        match resps[3].result {
+            DsmlResult::Delete(ref res) => assert_eq!(res.refs, vec!["ldap://other/"]),
+            _ => panic!("delete expected"),
+        }
+        assert!(matches!(resps[4].result, DsmlResult::ModifyDn(ref res) if res.rc == 0));
+        assert!(matches!(resps[5].result, DsmlResult::Compare(ref res) if res.rc == 6));
+        match resps[6].result {
+            DsmlResult::Extended(ref res, ref exop) => {
+                assert_eq!(res.rc, 0);
+                assert_eq!(exop.name.as_deref(), Some("1.2.3"));
+                assert_eq!(exop.val.as_deref(), Some(&[1, 2][..]));
+            }
+            _ => panic!("extended expected"),
+        }
+        let err = responses(
+            "<errorResponse type=\"malformedRequest\"><message>bad</message></errorResponse>",
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("malformedRequest: bad"), "{}", err);
+    }
+
+    #[test]
+    fn parse_errors() {
+        for body in [
+            "<searchRequest dn=\"dc=x\" scope=\"all\" derefAliases=\"derefAlways\"><filter><present name=\"cn\"/></filter></searchRequest>",
+            "<searchRequest dn=\"dc=x\" scope=\"baseObject\" derefAliases=\"derefAlways\"/>",
+            "<searchRequest dn=\"dc=x\" scope=\"baseObject\" derefAliases=\"derefAlways\"><filter><bogus/></filter></searchRequest>",
+            "<delRequest/>",
+            "<modifyRequest dn=\"dc=x\"><modification name=\"cn\" operation=\"move\"/></modifyRequest>",
+            "<compareRequest dn=\"dc=x\"/>",
+            "<extendedRequest/>",
+            "<bindRequest dn=\"dc=x\"/>",
+        ] {
+            let batch = format!("<batchRequest>{}</batchRequest>", body);
+            assert!(parse_requests(&batch).is_err(), "{}", body);
+        }
+        assert!(parse_requests("<batchResponse/>").is_err());
+        assert!(parse_requests("<batchRequest><delRequest dn=\"x\"></batchRequest>").is_err());
+        assert!(responses("<addResponse/>").is_err());
+        assert!(responses("<searchResponse/>").is_err());
+        let deep = format!(
+            "<batchRequest><searchRequest dn=\"dc=x\" scope=\"baseObject\" derefAliases=\"derefAlways\">\
+             <filter>{}<present name=\"cn\"/>{}</filter></searchRequest></batchRequest>",
+            "<not>".repeat(MAX_DEPTH),
+            "</not>".repeat(MAX_DEPTH)
+        );
+        assert!(parse_requests(&deep).is_err());
+    }
+}
@@ -0,0 +1,294 @@
+// Just enough XML for DSML: elements, attributes, character data and CDATA sections.
+// Namespace prefixes are dropped, and namespace declarations aren't kept, since the
+// DSML vocabulary is unambiguous without them.
+
+use std::fmt::Write;
+
+use super::MAX_DEPTH;
+
+#[derive(Debug, Default)]
+pub(crate) struct Element {
+    pub(crate) name: String,
+    pub(crate) attrs: Vec<(String, String)>,
+    pub(crate) children: Vec<Element>,
+    pub(crate) text: String,
+}
+
+impl Element {
+    pub(crate) fn attr(&self, name: &str) -> Option<&str> {
+        self.attrs
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub(crate) fn child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find(|c| c.name == name)
+    }
+
+    pub(crate) fn children_named<'e>(&'e self, name: &'e str) -> impl Iterator<Item = &'e Element> {
+        self.children.iter().filter(move |c| c.name == name)
+    }
+}
+
+fn local(name: &str) -> &str {
+    match name.find(':') {
+        Some(p) => &name[p + 1..],
+        None => name,
+    }
+}
+
+struct Reader<'a> {
+    input: &'a str,
+    pos: usize,
+    depth: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn rest(&self) -> &'a str {
+        &self.input[self.pos..]
+    }
+
+    fn error<T>(&self, what: &str) -> Result<T, String> {
+        Err(format!("{} at offset {}", what, self.pos))
+    }
+
+    fn skip_ws(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn skip_past(&mut self, end: &str) -> Result<&'a str, String> {
+        match self.rest().find(end) {
+            Some(p) => {
+                let skipped = &self.rest()[..p];
+                self.pos += p + end.len();
+                Ok(skipped)
+            }
+            None => self.error(&format!("missing {}", end)),
+        }
+    }
+
+    // Skip the prolog, comments and processing instructions between elements.
+    fn skip_misc(&mut self) -> Result<(), String> {
+        loop {
+            self.skip_ws();
+            let rest = self.rest();
+            if rest.starts_with("<?") {
+                self.skip_past("?>")?;
+            } else if rest.starts_with("<!--") {
+                self.skip_past("-->")?;
+            } else if rest.starts_with("<!") && !rest.starts_with("<![CDATA[") {
+                self.skip_past(">")?;
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    fn name(&mut self) -> Result<&'a str, String> {
+        let rest = self.rest();
+        let len = rest
+            .find(|c: char| c.is_whitespace() || matches!(c, '=' | '>' | '/'))
+            .unwrap_or(rest.len());
+        if len == 0 {
+            return self.error("expected a name");
+        }
+        self.pos += len;
+        Ok(&rest[..len])
+    }
+
+    fn element(&mut self) -> Result<Element, String> {
+        if !self.rest().starts_with('<') {
+            return self.error("expected an element");
+        }
+        if self.depth >= MAX_DEPTH {
+            return self.error("elements nested too deeply");
+        }
+        self.pos += 1;
+        let qname = self.name()?;
+        let mut elem = Element {
+            name: local(qname).to_owned(),
+            ..Element::default()
+        };
+        loop {
+            self.skip_ws();
+            let rest = self.rest();
+            if rest.starts_with("/>") {
+                self.pos += 2;
+                return Ok(elem);
+            }
+            if rest.starts_with('>') {
+                self.pos += 1;
+                break;
+            }
+            let aname = self.name()?;
+            self.skip_ws();
+            if !self.rest().starts_with('=') {
+                return self.error("expected =");
+            }
+            self.pos += 1;
+            self.skip_ws();
+            let quote = match self.rest().chars().next() {
+                Some(q @ '"') | Some(q @ '\'') => q,
+                _ => return self.error("expected a quoted value"),
+            };
+            self.pos += 1;
+            let raw = self.skip_past(if quote == '"' { "\"" } else { "'" })?;
+            if aname != "xmlns" && !aname.starts_with("xmlns:") {
+                elem.attrs.push((local(aname).to_owned(), unescape(raw)?));
+            }
+        }
+        loop {
+            let rest = self.rest();
+            if rest.starts_with("</") {
+                self.pos += 2;
+                let end = self.name()?;
+                if end != qname {
+                    return self.error(&format!("mismatched end tag {}", end));
+                }
+                self.skip_ws();
+                if !self.rest().starts_with('>') {
+                    return self.error("expected >");
+                }
+                self.pos += 1;
+                return Ok(elem);
+            } else if rest.starts_with("<![CDATA[") {
+                self.pos += 9;
+                let data = self.skip_past("]]>")?;
+                elem.text.push_str(data);
+            } else if rest.starts_with("<!--") || rest.starts_with("<?") {
+                self.skip_misc()?;
+            } else if rest.starts_with('<') {
+                self.depth += 1;
+                let child = self.element()?;
+                self.depth -= 1;
+                elem.children.push(child);
+            } else if rest.is_empty() {
+                return self.error(&format!("unterminated element {}", qname));
+            } else {
+                let len = rest.find('<').unwrap_or(rest.len());
+                elem.text.push_str(&unescape(&rest[..len])?);
+                self.pos += len;
+            }
+        }
+    }
+}
+
+pub(crate) fn parse(input: &str) -> Result<Element, String> {
+    let mut reader = Reader {
+        input,
+        pos: 0,
+        depth: 0,
+    };
+    reader.skip_misc()?;
+    let root = reader.element()?;
+    reader.skip_misc()?;
+    if !reader.rest().is_empty() {
+        return reader.error("trailing content");
+    }
+    Ok(root)
+}
+
+fn unescape(raw: &str) -> Result<String, String> {
+    let mut out = String::with_capacity(raw.len());
+    let mut rest = raw;
+    while let Some(p) = rest.find('&') {
+        out.push_str(&rest[..p]);
+        rest = &rest[p + 1..];
+        let end = match rest.find(';') {
+            Some(end) => end,
+            None => return Err(String::from("unterminated entity reference")),
+        };
+        let c = match &rest[..end] {
+            "lt" => '<',
+            "gt" => '>',
+            "amp" => '&',
+            "quot" => '"',
+            "apos" => '\'',
+            num if num.starts_with("#x") => u32::from_str_radix(&num[2..], 16)
+                .ok()
+                .and_then(char::from_u32)
+                .ok_or_else(|| format!("bad character reference &{};", num))?,
+            num if num.starts_with('#') => num[1..]
+                .parse()
+                .ok()
+                .and_then(char::from_u32)
+                .ok_or_else(|| format!("bad character reference &{};", num))?,
+            other => return Err(format!("unknown entity &{};", other)),
+        };
+        out.push(c);
+        rest = &rest[end + 1..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+pub(crate) fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '&' => out.push_str("&amp;"),
+            '"' => out.push_str("&quot;"),
+            c if (c as u32) < 0x20 && !matches!(c, '\t' | '\n' | '\r') => {
+                let _ = write!(out, "&#{};", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse, MAX_DEPTH};
+
+    #[test]
+    fn elements() {
+        let root = parse(
+            "<?xml version=\"1.0\"?>\n<!-- batch -->\n<d:a xmlns:d=\"urn:x\" k='&lt;1&#x3e;'>\
+             t&amp;<b/><![CDATA[<raw>]]><c n=\"v\">&#65;</c></d:a>",
+        )
+        .unwrap();
+        assert_eq!(root.name, "a");
+        assert_eq!(root.attrs, vec![(String::from("k"), String::from("<1>"))]);
+        assert_eq!(root.text, "t&<raw>");
+        assert!(root.child("b").is_some());
+        assert_eq!(root.child("c").unwrap().attr("n"), Some("v"));
+        assert_eq!(root.child("c").unwrap().text, "A");
+    }
+
+    #[test]
+    fn malformed() {
+        for input in [
+            "",
+            "text",
+            "<a>",
+            "<a></b>",
+            "<a><b></a></b>",
+            "<a>&bogus;</a>",
+            "<a>&amp</a>",
+            "<a>&#xd800;</a>",
+            "<a k=\"&nbsp;\"/>",
+            "<a k=v/>",
+            "<a k=\"v/>",
+            "<a><![CDATA[data</a>",
+            "<a><!-- comment</a>",
+            "<a/><b/>",
+            "<a/>trailing",
+        ] {
+            assert!(parse(input).is_err(), "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn depth_limit() {
+        let nested = |depth: usize| format!("{}{}", "<a>".repeat(depth), "</a>".repeat(depth));
+        assert!(parse(&nested(MAX_DEPTH)).is_ok());
+        let err = parse(&nested(MAX_DEPTH + 1)).unwrap_err();
+        assert!(err.contains("nested too deeply"), "{}", err);
+        assert!(parse(&"<a>".repeat(500_000)).is_err());
+    }
+}
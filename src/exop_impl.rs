@@ -1,10 +1,15 @@
 use lber::common::TagClass;
 use lber::structures::{OctetString, Tag};
 
+mod builder;
+pub use self::builder::{ExopBuilder, ExopReader};
+
 mod whoami;
 pub use self::whoami::{WhoAmI, WhoAmIResp};
 
+#[cfg(any(feature = "tls-native", feature = "tls-rustls"))]
 mod starttls;
+#[cfg(any(feature = "tls-native", feature = "tls-rustls"))]
 pub use self::starttls::StartTLS;
 
 mod passmod;
@@ -0,0 +1,381 @@
+use super::Exop;
+use crate::result::{LdapError, Result};
+
+use bytes::BytesMut;
+
+use lber::common::TagClass;
+use lber::parse::parse_tag;
+use lber::structure::{StructureTag, PL};
+use lber::structures::{ASNTag, Boolean, Enumerated, Integer, OctetString, Sequence, Tag};
+use lber::universal::Types;
+use lber::{write, IResult};
+
+/// Builder for extended operations not implemented by the library.
+///
+/// Most extended operations have a request value which is a BER-encoded SEQUENCE of
+/// simple elements, some of them optional and context-tagged. The builder appends
+/// the elements in order, and encodes the sequence as the request value when
+/// [`build()`](#method.build) is called. If no elements were added, the request has
+/// no value. An operation with a differently structured value can supply it already
+/// encoded, with [`raw_value()`](#method.raw_value).
+///
+/// The response value can be decoded with [`ExopReader`](struct.ExopReader.html).
+///
+/// ## Example
+///
+/// ```rust
+/// use ldap3::exop::{ExopBuilder, ExopReader};
+///
+/// // ExampleQuota ::= SEQUENCE { user OCTET STRING, limit [0] INTEGER OPTIONAL }
+/// let exop = ExopBuilder::new("1.3.6.1.4.1.99999.2")
+///     .string("uid=jdoe,ou=People,dc=example,dc=org")
+///     .tagged_integer(0, 1024)
+///     .build();
+/// let mut reader = ExopReader::sequence(exop.val.as_deref().unwrap()).unwrap();
+/// assert_eq!(reader.string().unwrap(), "uid=jdoe,ou=People,dc=example,dc=org");
+/// assert_eq!(reader.tagged_integer(0).unwrap(), Some(1024));
+/// assert!(reader.is_empty());
+/// ```
+#[derive(Clone, Debug)]
+pub struct ExopBuilder {
+    oid: String,
+    elems: Vec<Tag>,
+    raw: Option<Vec<u8>>,
+}
+
+impl ExopBuilder {
+    /// Start building the extended operation with `oid`.
+    pub fn new(oid: &str) -> Self {
+        ExopBuilder {
+            oid: oid.to_owned(),
+            elems: vec![],
+            raw: None,
+        }
+    }
+
+    /// Append an arbitrary element.
+    pub fn tag(mut self, tag: Tag) -> Self {
+        self.elems.push(tag);
+        self
+    }
+
+    /// Append an OCTET STRING.
+    pub fn octets<B: AsRef<[u8]>>(self, val: B) -> Self {
+        self.tag(Tag::OctetString(OctetString {
+            inner: val.as_ref().to_vec(),
+            ..Default::default()
+        }))
+    }
+
+    /// Append a string as an OCTET STRING.
+    pub fn string(self, val: &str) -> Self {
+        self.octets(val)
+    }
+
+    /// Append an INTEGER.
+    pub fn integer(self, val: i64) -> Self {
+        self.tag(Tag::Integer(Integer {
+            inner: val,
+            ..Default::default()
+        }))
+    }
+
+    /// Append an ENUMERATED.
+    pub fn enumerated(self, val: i64) -> Self {
+        self.tag(Tag::Enumerated(Enumerated {
+            inner: val,
+            ..Default::default()
+        }))
+    }
+
+    /// Append a BOOLEAN.
+    pub fn boolean(self, val: bool) -> Self {
+        self.tag(Tag::Boolean(Boolean {
+            inner: val,
+            ..Default::default()
+        }))
+    }
+
+    /// Append an OCTET STRING with the context-specific tag `id`.
+    pub fn tagged_octets<B: AsRef<[u8]>>(self, id: u64, val: B) -> Self {
+        self.tag(Tag::OctetString(OctetString {
+            id,
+            class: TagClass::Context,
+            inner: val.as_ref().to_vec(),
+        }))
+    }
+
+    /// Append a string as an OCTET STRING with the context-specific tag `id`.
+    pub fn tagged_string(self, id: u64, val: &str) -> Self {
+        self.tagged_octets(id, val)
+    }
+
+    /// Append an INTEGER with the context-specific tag `id`.
+    pub fn tagged_integer(self, id: u64, val: i64) -> Self {
+        self.tag(Tag::Integer(Integer {
+            id,
+            class: TagClass::Context,
+            inner: val,
+        }))
+    }
+
+    /// Append a BOOLEAN with the context-specific tag `id`.
+    pub fn tagged_boolean(self, id: u64, val: bool) -> Self {
+        self.tag(Tag::Boolean(Boolean {
+            id,
+            class: TagClass::Context,
+            inner: val,
+        }))
+    }
+
+    /// Set the already encoded request value. Elements appended to the builder,
+    /// if any, are ignored.
+    pub fn raw_value<B: AsRef<[u8]>>(mut self, val: B) -> Self {
+        self.raw = Some(val.as_ref().to_vec());
+        self
+    }
+
+    /// Construct the extended operation.
+    pub fn build(self) -> Exop {
+        let val = match self.raw {
+            Some(raw) => Some(raw),
+            None if self.elems.is_empty() => None,
+            None => {
+                let seq = Tag::Sequence(Sequence {
+                    inner: self.elems,
+                    ..Default::default()
+                })
+                .into_structure();
+                let mut buf = BytesMut::new();
+                write::encode_into(&mut buf, seq).expect("encoded");
+                Some(Vec::from(&buf[..]))
+            }
+        };
+        Exop {
+            name: Some(self.oid),
+            val,
+        }
+    }
+}
+
+impl From<ExopBuilder> for Exop {
+    fn from(builder: ExopBuilder) -> Exop {
+        builder.build()
+    }
+}
+
+/// Sequential decoder of BER-encoded values.
+///
+/// The reader is the counterpart of [`ExopBuilder`](struct.ExopBuilder.html): it's
+/// created from the encoded SEQUENCE, typically the value of an extended response,
+/// and returns its elements in order. The methods for universal types fail if the
+/// next element doesn't have the expected type, while those for context-tagged
+/// elements return `None` without consuming the element if its tag doesn't match,
+/// which makes it easy to skip over absent optional elements. Decoding errors are
+/// reported as [`LdapError::BerDecoding`](../result/enum.LdapError.html#variant.BerDecoding).
+///
+/// Although the reader is placed in the `exop` module, it's equally usable for
+/// the values of third-party controls.
+#[derive(Clone, Debug)]
+pub struct ExopReader {
+    elems: std::vec::IntoIter<StructureTag>,
+    next: Option<StructureTag>,
+}
+
+fn decoding(msg: &str) -> LdapError {
+    LdapError::BerDecoding(msg.to_owned())
+}
+
+// Decode a two's complement big-endian integer.
+fn decode_integer(val: &[u8]) -> Result<i64> {
+    if val.is_empty() || val.len() > 8 {
+        return Err(decoding("invalid integer length"));
+    }
+    let init = if val[0] & 0x80 != 0 { -1 } else { 0 };
+    Ok(val.iter().fold(init, |acc, &b| (acc << 8) | b as i64))
+}
+
+impl ExopReader {
+    /// Create a reader for the elements of the encoded SEQUENCE in `val`.
+    pub fn sequence(val: &[u8]) -> Result<Self> {
+        let elems = match parse_tag(val) {
+            IResult::Done(_, tag) => tag,
+            _ => return Err(decoding("malformed value")),
+        }
+        .match_class(TagClass::Universal)
+        .and_then(|t| t.match_id(Types::Sequence as u64))
+        .and_then(|t| t.expect_constructed())
+        .ok_or_else(|| decoding("value is not a sequence"))?;
+        let mut elems = elems.into_iter();
+        let next = elems.next();
+        Ok(ExopReader { elems, next })
+    }
+
+    /// Check whether all elements have been read.
+    pub fn is_empty(&self) -> bool {
+        self.next.is_none()
+    }
+
+    /// Return the next element, whatever its type.
+    pub fn element(&mut self) -> Result<StructureTag> {
+        let elem = self
+            .next
+            .take()
+            .ok_or_else(|| decoding("no more elements"))?;
+        self.next = self.elems.next();
+        Ok(elem)
+    }
+
+    fn primitive(&mut self, class: TagClass, id: u64) -> Result<Option<Vec<u8>>> {
+        match self.next {
+            Some(ref tag) if tag.class == class && tag.id == id => (),
+            _ => return Ok(None),
+        }
+        match self.element()?.payload {
+            PL::P(val) => Ok(Some(val)),
+            PL::C(_) => Err(decoding("constructed element where primitive expected")),
+        }
+    }
+
+    fn universal(&mut self, ty: Types, what: &str) -> Result<Vec<u8>> {
+        self.primitive(TagClass::Universal, ty as u64)?
+            .ok_or_else(|| decoding(&format!("expected {}", what)))
+    }
+
+    /// Read an OCTET STRING.
+    pub fn octets(&mut self) -> Result<Vec<u8>> {
+        self.universal(Types::OctetString, "octet string")
+    }
+
+    /// Read an OCTET STRING as a UTF-8 string.
+    pub fn string(&mut self) -> Result<String> {
+        String::from_utf8(self.octets()?).map_err(|_| LdapError::DecodingUTF8)
+    }
+
+    /// Read an INTEGER.
+    pub fn integer(&mut self) -> Result<i64> {
+        decode_integer(&self.universal(Types::Integer, "integer")?)
+    }
+
+    /// Read an ENUMERATED.
+    pub fn enumerated(&mut self) -> Result<i64> {
+        decode_integer(&self.universal(Types::Enumerated, "enumerated")?)
+    }
+
+    /// Read a BOOLEAN.
+    pub fn boolean(&mut self) -> Result<bool> {
+        match self.universal(Types::Boolean, "boolean")?[..] {
+            [b] => Ok(b != 0),
+            _ => Err(decoding("invalid boolean length")),
+        }
+    }
+
+    /// Read a primitive element with the context-specific tag `id`, if it's next.
+    pub fn tagged_octets(&mut self, id: u64) -> Result<Option<Vec<u8>>> {
+        self.primitive(TagClass::Context, id)
+    }
+
+    /// Read a string with the context-specific tag `id`, if it's next.
+    pub fn tagged_string(&mut self, id: u64) -> Result<Option<String>> {
+        self.tagged_octets(id)?
+            .map(|val| String::from_utf8(val).map_err(|_| LdapError::DecodingUTF8))
+            .transpose()
+    }
+
+    /// Read an integer with the context-specific tag `id`, if it's next.
+    pub fn tagged_integer(&mut self, id: u64) -> Result<Option<i64>> {
+        self.tagged_octets(id)?
+            .map(|val| decode_integer(&val))
+            .transpose()
+    }
+
+    /// Read a boolean with the context-specific tag `id`, if it's next.
+    pub fn tagged_boolean(&mut self, id: u64) -> Result<Option<bool>> {
+        match self.tagged_octets(id)?.as_deref() {
+            None => Ok(None),
+            Some([b]) => Ok(Some(*b != 0)),
+            Some(_) => Err(decoding("invalid boolean length")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ExopBuilder, ExopReader};
+    use crate::exop::{Exop, ExopParser};
+
+    const QUOTA_OID: &str = "1.3.6.1.4.1.99999.3";
+
+    // QuotaRequest ::= SEQUENCE { user [0] OCTET STRING OPTIONAL, extend [1] INTEGER OPTIONAL }
+    struct Quota<'a> {
+        user: Option<&'a str>,
+        extend: Option<i64>,
+    }
+
+    impl From<Quota<'_>> for Exop {
+        fn from(q: Quota<'_>) -> Exop {
+            let mut builder = ExopBuilder::new(QUOTA_OID);
+            if let Some(user) = q.user {
+                builder = builder.tagged_string(0, user);
+            }
+            if let Some(extend) = q.extend {
+                builder = builder.tagged_integer(1, extend);
+            }
+            builder.build()
+        }
+    }
+
+    // QuotaResponse ::= SEQUENCE { used INTEGER, limit INTEGER, hard BOOLEAN }
+    #[derive(Debug, PartialEq)]
+    struct QuotaResp {
+        used: i64,
+        limit: i64,
+        hard: bool,
+    }
+
+    impl ExopParser for QuotaResp {
+        fn parse(val: &[u8]) -> QuotaResp {
+            let mut reader = ExopReader::sequence(val).expect("quota response");
+            QuotaResp {
+                used: reader.integer().expect("used"),
+                limit: reader.integer().expect("limit"),
+                hard: reader.boolean().expect("hard"),
+            }
+        }
+    }
+
+    #[test]
+    fn vendor_exop() {
+        let exop: Exop = Quota {
+            user: None,
+            extend: Some(-512),
+        }
+        .into();
+        assert_eq!(exop.name.as_deref(), Some(QUOTA_OID));
+        let mut reader = ExopReader::sequence(exop.val.as_deref().unwrap()).unwrap();
+        assert_eq!(reader.tagged_string(0).unwrap(), None);
+        assert_eq!(reader.tagged_integer(1).unwrap(), Some(-512));
+        assert!(reader.is_empty());
+        let empty: Exop = Quota {
+            user: None,
+            extend: None,
+        }
+        .into();
+        assert!(empty.val.is_none());
+        let resp = ExopBuilder::new(QUOTA_OID)
+            .integer(300)
+            .integer(100_000)
+            .boolean(true)
+            .build();
+        assert_eq!(
+            resp.parse::<QuotaResp>(),
+            QuotaResp {
+                used: 300,
+                limit: 100_000,
+                hard: true
+            }
+        );
+        let mut reader = ExopReader::sequence(resp.val.as_deref().unwrap()).unwrap();
+        assert!(reader.string().is_err());
+    }
+}
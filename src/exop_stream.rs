@@ -0,0 +1,219 @@
+use std::time::Duration;
+
+use crate::controls::Control;
+use crate::exop::Exop;
+use crate::ldap::{wait_reply, Cancel, Ldap};
+use crate::protocol::{item_channel, ItemReceiver, LdapOp};
+use crate::request::OpKind;
+use crate::result::{ExopResult, LdapError, LdapResult, Result};
+use crate::search::SearchItem;
+
+use lber::structure::StructureTag;
+
+/// Intermediate response message ([RFC 4511](https://tools.ietf.org/html/rfc4511#section-4.13)).
+///
+/// The first element contains the response name and value, either of which may be
+/// absent. The second element is the vector of response controls, if any.
+#[derive(Clone, Debug)]
+pub struct Intermediate(pub Exop, pub Vec<Control>);
+
+impl Intermediate {
+    pub(crate) fn from_tag(t: StructureTag, ctrls: Vec<Control>) -> Intermediate {
+        let mut exop = Exop {
+            name: None,
+            val: None,
+        };
+        for comp in t.expect_constructed().expect("intermediate sequence") {
+            match comp.id {
+                0 => {
+                    exop.name = Some(
+                        String::from_utf8(comp.expect_primitive().expect("octet string"))
+                            .expect("intermediate name"),
+                    );
+                }
+                1 => exop.val = Some(comp.expect_primitive().expect("octet string")),
+                _ => (),
+            }
+        }
+        Intermediate(exop, ctrls)
+    }
+}
+
+/// Asynchronous handle for an Extended operation producing Intermediate responses. __*__
+///
+/// User code can't construct a stream directly, but only by using
+/// [`streaming_extended()`](struct.Ldap.html#method.streaming_extended) on an `Ldap` handle.
+///
+/// The protocol is similar to that of [`SearchStream`](struct.SearchStream.html): Intermediate
+/// responses are retrieved by repeatedly calling [`next()`](#method.next) until it returns
+/// `Ok(None)` or an error, and the final result is then obtained by calling
+/// [`finish()`](#method.finish). Calling `finish()` earlier stops the processing of responses
+/// in the client; informing the server is the user's responsibility.
+#[derive(Debug)]
+pub struct ExopStream {
+    ldap: Ldap,
+    rx: Option<ItemReceiver>,
+    timeout: Option<Duration>,
+    cancel: Option<Cancel>,
+    res: Option<ExopResult>,
+}
+
+impl ExopStream {
+    pub(crate) async fn start(mut ldap: Ldap, req: lber::structures::Tag) -> Result<Self> {
+        let (tx, rx) = item_channel(ldap.shared.stream_buffer);
+        let timeout = ldap.timeout.or(ldap.shared.default_timeout);
+        let cancel = ldap.cancel.take();
+        ldap.op_call(LdapOp::MultiExop(tx), req).await?;
+        Ok(ExopStream {
+            ldap,
+            rx: Some(rx),
+            timeout,
+            cancel,
+            res: None,
+        })
+    }
+
+    /// Fetch the next Intermediate response.
+    ///
+    /// Returns `Ok(None)` when the final response of the operation has been received.
+    #[allow(clippy::should_implement_trait)]
+    pub async fn next(&mut self) -> Result<Option<Intermediate>> {
+        let rx = match self.rx.as_mut() {
+            Some(rx) => rx,
+            None => return Ok(None),
+        };
+        let item = match wait_reply(rx.recv(), self.timeout, self.cancel.as_mut()).await {
+            Ok(item) => item,
+            Err(LdapError::Cancelled) => {
+                let last_id = self.ldap.last_id;
+                self.cancel = None;
+                self.rx = None;
+                self.ldap.abandon_cancelled(last_id).await?;
+                return Err(LdapError::Cancelled);
+            }
+            Err(e) => {
+                let last_id = self.ldap.last_id;
+                self.ldap.shared.id_scrub_tx.send(last_id)?;
+                return Err(e);
+            }
+        };
+        let (item, controls) = match item {
+            Some((item, controls)) => (item, controls),
+            None => {
+                self.rx = None;
+                return Err(LdapError::EndOfStream);
+            }
+        };
+        match item {
+            SearchItem::Entry(tag) => Ok(Some(Intermediate::from_tag(tag, controls))),
+            SearchItem::ExopDone(mut res, exop) => {
+                res.ctrls = controls;
+                self.res = Some(ExopResult(exop, res));
+                self.rx = None;
+                Ok(None)
+            }
+            SearchItem::Referral(_) | SearchItem::Done(_) => {
+                panic!("unexpected item in extended operation stream")
+            }
+        }
+    }
+
+    /// Return the final result of the operation.
+    ///
+    /// If the stream hasn't been read to the end, a synthetic cancellation result is returned.
+    pub async fn finish(&mut self) -> ExopResult {
+        if self.rx.take().is_some() {
+            let last_id = self.ldap.last_id;
+            if let Err(e) = self.ldap.shared.id_scrub_tx.send(last_id) {
+                warn!(
+                    "error sending scrub message from ExopStream::finish() for ID {}: {}",
+                    last_id, e
+                );
+            }
+        }
+        let mut res = self.res.take().unwrap_or_else(|| {
+            ExopResult(
+                Exop {
+                    name: None,
+                    val: None,
+                },
+                LdapResult {
+                    rc: 88,
+                    matched: String::from(""),
+                    text: String::from("user cancelled"),
+                    refs: vec![],
+                    ctrls: vec![],
+                },
+            )
+        });
+        self.ldap
+            .shared
+            .interceptors
+            .result(OpKind::Extended, &mut res.1);
+        res
+    }
+
+    /// Return the Message ID of the operation.
+    pub fn last_id(&mut self) -> crate::RequestId {
+        self.ldap.last_id()
+    }
+}
+
+#[cfg(all(test, feature = "testkit"))]
+mod test {
+    use crate::exop_impl::WhoAmI;
+    use crate::testkit::{response, MockServer};
+
+    use lber::common::TagClass;
+    use lber::structures::{OctetString, Sequence, Tag};
+
+    // Two progress reports, followed by the final result.
+    fn server() -> MockServer {
+        let server = MockServer::new();
+        server.script(23, |id| {
+            let mut resps = (1..=2)
+                .map(|n| {
+                    let progress = Tag::Sequence(Sequence {
+                        id: 25,
+                        class: TagClass::Application,
+                        inner: vec![Tag::OctetString(OctetString {
+                            class: TagClass::Context,
+                            id: 1,
+                            inner: format!("step {}", n).into_bytes(),
+                        })],
+                    });
+                    (id, progress, None)
+                })
+                .collect::<Vec<_>>();
+            resps.push((id, response(24, 0, "done"), None));
+            resps
+        });
+        server
+    }
+
+    #[tokio::test]
+    async fn intermediate_responses() {
+        let server = server();
+        let (conn, mut ldap) = server.connect();
+        crate::drive!(conn);
+        let mut stream = ldap.streaming_extended(WhoAmI).await.unwrap();
+        let mut steps = vec![];
+        while let Some(im) = stream.next().await.unwrap() {
+            assert_eq!(im.0.name, None);
+            steps.push(im.0.val.unwrap());
+        }
+        assert_eq!(steps, vec![b"step 1".to_vec(), b"step 2".to_vec()]);
+        let res = stream.finish().await.success().unwrap();
+        assert_eq!(res.1.text, "done");
+    }
+
+    #[tokio::test]
+    async fn intermediates_discarded() {
+        let server = server();
+        let (conn, mut ldap) = server.connect();
+        crate::drive!(conn);
+        let res = ldap.extended(WhoAmI).await.unwrap().success().unwrap();
+        assert_eq!(res.1.text, "done");
+        assert!(!ldap.is_closed());
+    }
+}
@@ -0,0 +1,249 @@
+//! Connecting to one of several equivalent servers.
+//!
+//! A directory is often served by several replicas, any of which can be used. A
+//! [`ServerSet`](struct.ServerSet.html) holds their URLs, and connects to the first
+//! one in the list which is reachable. It also keeps count of consecutive failures
+//! for each server: when the count reaches the threshold, the server is ejected from
+//! the set for a cool-down period, and isn't tried during it. After the period, the
+//! server gets a single attempt; if that fails too, it's ejected again. This keeps
+//! clients from repeatedly waiting for a dead server to time out while healthy ones
+//! are available. If every server is ejected, all are tried anyway, in list order.
+//!
+//! A connection is bound before it's returned if the
+//! [rebind function](../struct.LdapConnSettings.html#method.set_rebind_fn) of the
+//! settings supplies the credentials for the server. Connection failures are counted
+//! automatically. Failures noticed later, such as
+//! a connection breaking in the middle of an operation, can be reported with
+//! [`report_failure()`](struct.ServerSet.html#method.report_failure). A `ServerSet`
+//! can be shared between tasks, typically in an `Arc`.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! use ldap3::failover::ServerSet;
+//! use ldap3::LdapConnSettings;
+//! # use ldap3::result::Result;
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<()> {
+//! let servers = ServerSet::new(&["ldap://dc1.example.org", "ldap://dc2.example.org"]);
+//! let (conn, mut ldap, url) = servers.connect(&LdapConnSettings::new()).await?;
+//! ldap3::drive!(conn);
+//! if ldap.extended(ldap3::exop::WhoAmI).await.is_err() {
+//!     servers.report_failure(&url);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::conn::{LdapConnAsync, LdapConnSettings};
+use crate::ldap::Ldap;
+use crate::result::{LdapError, Result};
+
+use futures_util::TryFutureExt;
+use tokio::sync::oneshot;
+
+#[derive(Default)]
+struct Health {
+    failures: u32,
+    ejected_until: Option<Instant>,
+}
+
+struct Server {
+    url: String,
+    health: Mutex<Health>,
+}
+
+/// Set of equivalent servers with per-server failure tracking.
+///
+/// See the [module-level documentation](index.html) for the overview.
+pub struct ServerSet {
+    servers: Vec<Server>,
+    threshold: u32,
+    cooldown: Duration,
+}
+
+impl ServerSet {
+    /// Create a set of servers given by `urls`, which are tried in order. The failure
+    /// threshold is three, and the cool-down period 30 seconds.
+    pub fn new<S: AsRef<str>>(urls: &[S]) -> Self {
+        ServerSet {
+            servers: urls
+                .iter()
+                .map(|url| Server {
+                    url: url.as_ref().to_owned(),
+                    health: Mutex::new(Health::default()),
+                })
+                .collect(),
+            threshold: 3,
+            cooldown: Duration::from_secs(30),
+        }
+    }
+
+    /// Set the number of consecutive failures after which a server is ejected.
+    pub fn with_threshold(mut self, threshold: u32) -> Self {
+        assert!(threshold > 0, "threshold must be positive");
+        self.threshold = threshold;
+        self
+    }
+
+    /// Set the period for which an ejected server isn't tried.
+    pub fn with_cooldown(mut self, cooldown: Duration) -> Self {
+        self.cooldown = cooldown;
+        self
+    }
+
+    fn server(&self, url: &str) -> Option<&Server> {
+        self.servers.iter().find(|s| s.url == url)
+    }
+
+    /// Check whether the server with `url` is currently in the set, that is, not ejected.
+    pub fn is_available(&self, url: &str) -> bool {
+        self.server(url).is_some_and(|s| {
+            let health = s.health.lock().expect("health mutex");
+            health.ejected_until.map_or(true, |t| t <= Instant::now())
+        })
+    }
+
+    /// Record a failure of the server with `url`.
+    pub fn report_failure(&self, url: &str) {
+        if let Some(server) = self.server(url) {
+            let mut health = server.health.lock().expect("health mutex");
+            health.failures += 1;
+            if health.failures >= self.threshold {
+                warn!("ejecting LDAP server {} for {:?}", url, self.cooldown);
+                health.ejected_until = Some(Instant::now() + self.cooldown);
+            }
+        }
+    }
+
+    /// Record a success of the server with `url`, resetting its failure count.
+    pub fn report_success(&self, url: &str) {
+        if let Some(server) = self.server(url) {
+            *server.health.lock().expect("health mutex") = Health::default();
+        }
+    }
+
+    /// Connect to the first available server, using `settings` for the connection.
+    /// Returns the connection, the handle, and the URL of the server, for reporting
+    /// any subsequent failures. If no connection can be established, the error of
+    /// the last attempt is returned.
+    ///
+    /// If the connection has to be bound with the credentials from the rebind function,
+    /// a failed Bind is returned as an error without trying other servers, since the
+    /// credentials are likely to be rejected by all of them.
+    pub async fn connect(
+        &self,
+        settings: &LdapConnSettings,
+    ) -> Result<(LdapConnAsync, Ldap, String)> {
+        let mut candidates: Vec<_> = self
+            .servers
+            .iter()
+            .filter(|s| self.is_available(&s.url))
+            .collect();
+        if candidates.is_empty() {
+            candidates = self.servers.iter().collect();
+        }
+        let mut last_err = None;
+        for server in candidates {
+            match LdapConnAsync::with_settings(settings.clone(), &server.url).await {
+                Ok((mut conn, mut ldap)) => {
+                    self.report_success(&server.url);
+                    if let Some(creds) = settings.rebind_credentials(&server.url)? {
+                        let (tx, rx) = oneshot::channel();
+                        tokio::spawn(async move {
+                            conn.single_op(tx).await;
+                        });
+                        let (conn_res, _) =
+                            tokio::try_join!(rx.map_err(LdapError::from), creds.bind(&mut ldap))?;
+                        conn = conn_res?;
+                    }
+                    return Ok((conn, ldap, server.url.clone()));
+                }
+                Err(e) => {
+                    debug!("connecting to {} failed: {}", server.url, e);
+                    self.report_failure(&server.url);
+                    last_err = Some(e);
+                }
+            }
+        }
+        Err(last_err.unwrap_or_else(|| LdapError::Io {
+            source: std::io::Error::new(std::io::ErrorKind::NotFound, "empty server set"),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ServerSet;
+    use crate::conn::LdapConnSettings;
+
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn eject_dead_server() {
+        let dead = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let dead_url = format!("ldap://{}", dead.local_addr().unwrap());
+        drop(dead);
+        let live = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let live_url = format!("ldap://{}", live.local_addr().unwrap());
+        let servers = ServerSet::new(&[&dead_url, &live_url]).with_threshold(1);
+        let settings = LdapConnSettings::new();
+        let (_conn, _ldap, url) = servers.connect(&settings).await.unwrap();
+        assert_eq!(url, live_url);
+        assert!(!servers.is_available(&dead_url));
+        servers.report_failure(&live_url);
+        assert!(!servers.is_available(&live_url));
+        let (_conn, _ldap, url) = servers.connect(&settings).await.unwrap();
+        assert_eq!(url, live_url);
+    }
+}
+
+#[cfg(all(test, feature = "testkit"))]
+mod mock_test {
+    use super::ServerSet;
+    use crate::conn::{LdapConnSettings, RebindCredentials};
+    use crate::result::LdapError;
+    use crate::testkit::MockServer;
+
+    use std::sync::{Arc, Mutex};
+
+    const ADMIN_DN: &str = "cn=admin,dc=example,dc=org";
+
+    #[tokio::test]
+    async fn rebind_credentials() {
+        let server = MockServer::new();
+        server.add_entry(
+            ADMIN_DN,
+            vec![("cn", vec!["admin"]), ("userPassword", vec!["secret"])],
+        );
+        let (first, second) = (server.listen().await, server.listen().await);
+        let servers = ServerSet::new(&[&first, &second]).with_threshold(1);
+        let called = Arc::new(Mutex::new(vec![]));
+        let (log, pw_for) = (called.clone(), second.clone());
+        let settings = LdapConnSettings::new().set_rebind_fn(move |url| {
+            let url = url.as_str().trim_end_matches('/');
+            log.lock().unwrap().push(url.to_owned());
+            let password = if url == pw_for { "secret" } else { "wrong" };
+            Some(RebindCredentials::Simple {
+                dn: ADMIN_DN.to_owned(),
+                password: password.to_owned(),
+            })
+        });
+        // A rejected Bind isn't retried on the other server.
+        match servers.connect(&settings).await {
+            Err(LdapError::LdapResult { result }) => assert_eq!(result.rc, 49),
+            res => panic!("unexpected result: {:?}", res.map(|_| ())),
+        }
+        servers.report_failure(&first);
+        let (conn, ldap, url) = servers.connect(&settings).await.unwrap();
+        crate::drive!(conn);
+        assert_eq!(url, second);
+        assert_eq!(ldap.bound_identity().as_deref(), Some(ADMIN_DN));
+        let called = called.lock().unwrap();
+        assert_eq!(*called, vec![first, second]);
+    }
+}
@@ -1,15 +1,18 @@
-#![allow(clippy::blocks_in_if_conditions)]
+#![allow(clippy::blocks_in_conditions)]
 
 use std::default::Default;
 use std::str;
 
 use lber::common::TagClass;
+#[cfg(feature = "server")]
+use lber::structure::{StructureTag, PL};
 use lber::structures::{Boolean, ExplicitTag, OctetString, Sequence, Tag};
 
 use nom::IResult;
 use nom::{be_u8, digit, is_alphabetic, is_alphanumeric, is_hex_digit};
 
 #[doc(hidden)]
+#[allow(clippy::result_unit_err)]
 pub fn parse(input: &str) -> Result<Tag, ()> {
     match filtexpr(input.as_bytes()) {
         IResult::Done(r, t) => {
@@ -36,6 +39,155 @@ pub(crate) fn parse_matched_values(input: &str) -> Result<Tag, ()> {
     }
 }
 
+// Render a parsed filter back in its string form, for logging. Assertion values are
+// escaped where needed, and non-printable and non-ASCII bytes are always escaped.
+#[cfg_attr(not(feature = "metrics"), allow(dead_code))]
+pub(crate) fn unparse(tag: &Tag) -> String {
+    let mut out = String::new();
+    unparse_into(&mut out, tag);
+    out
+}
+
+// Convert a filter received on the wire into the form produced by parse(), so that
+// it can be rendered with unparse().
+#[cfg(feature = "server")]
+pub(crate) fn from_structure(t: StructureTag) -> Tag {
+    from_structure_in(t, None)
+}
+
+#[cfg(feature = "server")]
+fn from_structure_in(t: StructureTag, parent: Option<u64>) -> Tag {
+    let (id, class) = (t.id, t.class);
+    match t.payload {
+        PL::C(inner) if class == TagClass::Context && id == NOT_FILT => {
+            let inner = inner
+                .into_iter()
+                .next()
+                .map(|t| from_structure_in(t, None))
+                .unwrap_or_else(|| Tag::Sequence(Sequence::default()));
+            Tag::ExplicitTag(ExplicitTag {
+                id,
+                class,
+                inner: Box::new(inner),
+            })
+        }
+        PL::C(inner) => Tag::Sequence(Sequence {
+            id,
+            class,
+            inner: inner
+                .into_iter()
+                .map(|t| from_structure_in(t, Some(id)))
+                .collect(),
+        }),
+        PL::P(inner) if parent == Some(EXT_MATCH) && class == TagClass::Context && id == 4 => {
+            Tag::Boolean(Boolean {
+                id,
+                class,
+                inner: inner.first().is_some_and(|b| *b != 0),
+            })
+        }
+        PL::P(inner) => Tag::OctetString(OctetString { id, class, inner }),
+    }
+}
+
+fn push_value(out: &mut String, value: &[u8]) {
+    for &c in value {
+        if c.is_ascii_graphic() && !matches!(c, b'(' | b')' | b'*' | b'\\') || c == b' ' {
+            out.push(c as char);
+        } else {
+            out.push_str(&format!("\\{:02x}", c));
+        }
+    }
+}
+
+fn octets(tag: &Tag) -> &[u8] {
+    match *tag {
+        Tag::OctetString(ref os) => &os.inner,
+        _ => b"",
+    }
+}
+
+fn unparse_into(out: &mut String, tag: &Tag) {
+    out.push('(');
+    match *tag {
+        Tag::Sequence(ref seq) => match seq.id {
+            AND_FILT | OR_FILT => {
+                out.push(if seq.id == AND_FILT { '&' } else { '|' });
+                for inner in &seq.inner {
+                    unparse_into(out, inner);
+                }
+            }
+            EQ_MATCH | GTE_MATCH | LTE_MATCH | APPROX_MATCH if seq.inner.len() == 2 => {
+                out.push_str(&String::from_utf8_lossy(octets(&seq.inner[0])));
+                out.push_str(match seq.id {
+                    EQ_MATCH => "=",
+                    GTE_MATCH => ">=",
+                    LTE_MATCH => "<=",
+                    _ => "~=",
+                });
+                push_value(out, octets(&seq.inner[1]));
+            }
+            SUBSTR_MATCH if seq.inner.len() == 2 => {
+                out.push_str(&String::from_utf8_lossy(octets(&seq.inner[0])));
+                out.push('=');
+                let subs = match seq.inner[1] {
+                    Tag::Sequence(ref subs) => &subs.inner[..],
+                    _ => &[],
+                };
+                let mut has_final = false;
+                for (n, sub) in subs.iter().enumerate() {
+                    let id = match *sub {
+                        Tag::OctetString(ref os) => os.id,
+                        _ => continue,
+                    };
+                    if id != SUB_INITIAL || n > 0 {
+                        out.push('*');
+                    }
+                    has_final = id == SUB_FINAL;
+                    push_value(out, octets(sub));
+                }
+                if !has_final {
+                    out.push('*');
+                }
+            }
+            EXT_MATCH => {
+                let (mut rule, mut value, mut dn) = (None, &b""[..], false);
+                for part in &seq.inner {
+                    match *part {
+                        Tag::OctetString(ref os) if os.id == 1 => rule = Some(&os.inner),
+                        Tag::OctetString(ref os) if os.id == 2 => {
+                            out.push_str(&String::from_utf8_lossy(&os.inner))
+                        }
+                        Tag::OctetString(ref os) if os.id == 3 => value = &os.inner,
+                        Tag::Boolean(ref b) if b.id == 4 => dn = b.inner,
+                        _ => (),
+                    }
+                }
+                if dn {
+                    out.push_str(":dn");
+                }
+                if let Some(rule) = rule {
+                    out.push(':');
+                    out.push_str(&String::from_utf8_lossy(rule));
+                }
+                out.push_str(":=");
+                push_value(out, value);
+            }
+            _ => out.push('?'),
+        },
+        Tag::ExplicitTag(ref et) if et.id == NOT_FILT => {
+            out.push('!');
+            unparse_into(out, &et.inner);
+        }
+        Tag::OctetString(ref os) if os.id == PRES_MATCH => {
+            out.push_str(&String::from_utf8_lossy(&os.inner));
+            out.push_str("=*");
+        }
+        _ => out.push('?'),
+    }
+    out.push(')');
+}
+
 const AND_FILT: u64 = 0;
 const OR_FILT: u64 = 1;
 const NOT_FILT: u64 = 2;
@@ -373,7 +525,7 @@ fn extensible_tag(mrule: Option<&[u8]>, attr: Option<&[u8]>, value: Vec<u8>, dn:
 }
 
 named!(
-    attributedescription<&[u8]>,
+    attributedescription<&'a [u8]>,
     recognize!(do_parse!(
         _type: attributetype
             >> _opts: many0!(preceded!(char!(';'), take_while1!(is_alnum_hyphen)))
@@ -381,10 +533,10 @@ named!(
     ))
 );
 
-named!(attributetype<&[u8]>, alt!(numericoid | descr));
+named!(attributetype<&'a [u8]>, alt!(numericoid | descr));
 
 named!(
-    numericoid<&[u8]>,
+    numericoid<&'a [u8]>,
     recognize!(do_parse!(
         _leading: number >> _rest: many0!(preceded!(char!('.'), number)) >> ()
     ))
@@ -392,12 +544,12 @@ named!(
 
 // A number may be zero, but must not have superfluous leading zeroes
 named!(
-    number<&[u8]>,
+    number<&'a [u8]>,
     verify!(digit, |d: &[u8]| d.len() == 1 || d[0] != b'0')
 );
 
 named!(
-    descr<&[u8]>,
+    descr<&'a [u8]>,
     recognize!(do_parse!(
         _leading: verify!(be_u8, |a: u8| is_alphabetic(a))
             >> _rest: take_while!(is_alnum_hyphen)
@@ -408,3 +560,24 @@ named!(
 fn is_alnum_hyphen(c: u8) -> bool {
     is_alphanumeric(c) || c == b'-'
 }
+
+#[cfg(test)]
+mod test {
+    use super::{parse, unparse};
+
+    #[test]
+    fn filter_round_trip() {
+        for filter in &[
+            "(&(objectClass=person)(|(uid=jd*)(cn=*John*Doe))(!(mail=*)))",
+            "(sn>=M)",
+            "(cn~=jon)",
+            "(cn=a\\2ab\\28c\\29)",
+            "(cn:dn:caseExactMatch:=Fred)",
+            "(:1.2.3:=x)",
+            "(cn=\\c3\\a9)",
+        ] {
+            assert_eq!(unparse(&parse(filter).unwrap()), *filter);
+        }
+        assert_eq!(unparse(&parse("uid=*x").unwrap()), "(uid=*x)");
+    }
+}
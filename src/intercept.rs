@@ -0,0 +1,214 @@
+//! Request and result interceptors.
+//!
+//! An [`Interceptor`](trait.Interceptor.html) sees every operation performed through an
+//! `Ldap` handle, synchronous or asynchronous, just before the request is sent, and every
+//! operation result before it's returned to the caller. It can add, change or remove the
+//! request controls, veto the operation altogether, and adjust the result. This makes it
+//! suitable for concerns which cut across all operations, such as audit logging, adding
+//! a control to every request, or refusing operations outside a particular subtree.
+//!
+//! Interceptors are installed with
+//! [`LdapConnSettings::add_interceptor()`](../struct.LdapConnSettings.html#method.add_interceptor),
+//! and apply to all handles of the connection. If there are several, they're called in
+//! the order of installation for requests, and in reverse order for results.
+//!
+//! Abandon and Unbind requests have no results, so only the request hook is called for
+//! them. The result of a Search or a streaming extended operation is the one returned
+//! by `finish()` on the stream.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! use std::sync::Arc;
+//!
+//! use ldap3::intercept::{Interceptor, Request};
+//! use ldap3::request::OpKind;
+//! use ldap3::result::{LdapError, Result};
+//! use ldap3::LdapConnSettings;
+//!
+//! struct TenantScope(&'static str);
+//!
+//! impl Interceptor for TenantScope {
+//!     fn request(&self, req: &mut Request<'_>) -> Result<()> {
+//!         match req.dn {
+//!             Some(dn) if !dn.ends_with(self.0) && req.op != OpKind::Bind => {
+//!                 Err(LdapError::Vetoed(format!("{} outside tenant", dn)))
+//!             }
+//!             _ => Ok(()),
+//!         }
+//!     }
+//! }
+//!
+//! let settings = LdapConnSettings::new()
+//!     .add_interceptor(Arc::new(TenantScope("ou=Tenant1,dc=example,dc=org")));
+//! # let _ = settings;
+//! ```
+
+use std::fmt;
+use std::sync::Arc;
+
+use crate::controls::RawControl;
+use crate::request::OpKind;
+use crate::result::{LdapResult, Result};
+
+use lber::structures::Tag;
+
+/// Outgoing request, as seen by an interceptor.
+#[derive(Debug)]
+pub struct Request<'r> {
+    /// Operation type.
+    pub op: OpKind,
+    /// DN which is the target of the operation: the base of a Search, the entry of an Add,
+    /// Compare, Delete, Modify or ModifyDN, and the name of a Bind. `None` for other
+    /// operations.
+    pub dn: Option<&'r str>,
+    /// Request controls, which can be modified.
+    pub controls: &'r mut Vec<RawControl>,
+}
+
+/// Hooks called for requests and results.
+///
+/// Both methods have empty default implementations. They're called synchronously, in
+/// the task performing the operation, and shouldn't block.
+pub trait Interceptor: Send + Sync {
+    /// Called before `req` is sent. Returning an error vetoes the operation, and the error
+    /// is returned to the caller. Conventionally, it should be
+    /// [`LdapError::Vetoed`](../result/enum.LdapError.html#variant.Vetoed).
+    fn request(&self, req: &mut Request<'_>) -> Result<()> {
+        let _ = req;
+        Ok(())
+    }
+
+    /// Called with the result of an operation of type `op`, which can be modified.
+    fn result(&self, op: OpKind, result: &mut LdapResult) {
+        let _ = (op, result);
+    }
+}
+
+// Extract the target DN from a request, if it has one.
+pub(crate) fn request_dn(tag: &Tag) -> Option<String> {
+    let dn = match *tag {
+        Tag::Sequence(ref seq) => {
+            let pos = if seq.id == 0 { 1 } else { 0 };
+            match seq.inner.get(pos) {
+                Some(Tag::OctetString(ref os)) if seq.id != 23 => &os.inner,
+                _ => return None,
+            }
+        }
+        Tag::OctetString(ref os) if os.id == 10 => &os.inner,
+        _ => return None,
+    };
+    String::from_utf8(dn.clone()).ok()
+}
+
+/// Interceptors installed on a connection.
+#[derive(Clone, Default)]
+pub(crate) struct Interceptors(pub(crate) Vec<Arc<dyn Interceptor>>);
+
+impl fmt::Debug for Interceptors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Interceptors({})", self.0.len())
+    }
+}
+
+impl Interceptors {
+    pub(crate) fn request(
+        &self,
+        op: OpKind,
+        tag: &Tag,
+        controls: &mut Option<Vec<RawControl>>,
+    ) -> Result<()> {
+        if self.0.is_empty() {
+            return Ok(());
+        }
+        let dn = request_dn(tag);
+        let mut ctrls = controls.take().unwrap_or_default();
+        let mut req = Request {
+            op,
+            dn: dn.as_deref(),
+            controls: &mut ctrls,
+        };
+        let res = self.0.iter().try_for_each(|i| i.request(&mut req));
+        if !ctrls.is_empty() {
+            *controls = Some(ctrls);
+        }
+        res
+    }
+
+    pub(crate) fn result(&self, op: OpKind, result: &mut LdapResult) {
+        for i in self.0.iter().rev() {
+            i.result(op, result);
+        }
+    }
+}
+
+#[cfg(all(test, feature = "testkit"))]
+mod test {
+    use super::{Interceptor, Request};
+    use crate::controls::ManageDsaIt;
+    use crate::request::OpKind;
+    use crate::result::{LdapError, LdapResult, Result};
+    use crate::testkit::MockServer;
+    use crate::{LdapConnSettings, Scope};
+
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Default)]
+    struct Audit(Mutex<Vec<String>>);
+
+    impl Interceptor for Audit {
+        fn request(&self, req: &mut Request<'_>) -> Result<()> {
+            if req.op == OpKind::Delete {
+                return Err(LdapError::Vetoed(String::from("no deletes")));
+            }
+            req.controls.push(ManageDsaIt.into());
+            self.0.lock().unwrap().push(format!(
+                "{} {} {}",
+                req.op,
+                req.dn.unwrap_or("-"),
+                req.controls.len()
+            ));
+            Ok(())
+        }
+
+        fn result(&self, op: OpKind, result: &mut LdapResult) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("{} rc={}", op, result.rc));
+        }
+    }
+
+    #[tokio::test]
+    async fn interceptor_hooks() {
+        let server = MockServer::new();
+        server.add_entry("dc=example,dc=org", vec![("dc", vec!["example"])]);
+        let audit = Arc::new(Audit::default());
+        let settings = LdapConnSettings::new().add_interceptor(audit.clone());
+        let (conn, mut ldap) = server.connect_with_settings(settings);
+        crate::drive!(conn);
+        ldap.extended(crate::exop::WhoAmI).await.unwrap();
+        ldap.search(
+            "dc=example,dc=org",
+            Scope::Base,
+            "(objectClass=*)",
+            vec!["dc"],
+        )
+        .await
+        .unwrap();
+        match ldap.delete("dc=example,dc=org").await {
+            Err(LdapError::Vetoed(_)) => (),
+            res => panic!("unexpected delete outcome: {:?}", res),
+        }
+        assert_eq!(server.len(), 1);
+        assert_eq!(
+            *audit.0.lock().unwrap(),
+            vec![
+                "extended - 1",
+                "extended rc=0",
+                "search dc=example,dc=org 1",
+                "search rc=0",
+            ]
+        );
+    }
+}
@@ -0,0 +1,227 @@
+//! Deferred connection establishment.
+//!
+//! An application which may or may not need the directory, such as one where LDAP is
+//! one of several optional authentication backends, would rather not connect at startup,
+//! and have to deal with the server being unavailable at that point. A
+//! [`LazyLdap`](struct.LazyLdap.html) holds the URL and the settings, and connects only
+//! when the handle is first needed. The connection is then bound, if the handle was given
+//! the [credentials](struct.LazyLdap.html#method.with_bind) or the
+//! [rebind function](../struct.LdapConnSettings.html#method.set_rebind_fn) of the
+//! settings supplies them.
+//!
+//! All clones of a `LazyLdap` share the connection, so it can be created once and
+//! handed out to the components that may need it. If the connection closes, the next
+//! request for the handle opens a new one.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! use ldap3::lazy::LazyLdap;
+//! use ldap3::{LdapConnSettings, RebindCredentials, Scope};
+//! # use ldap3::result::Result;
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<()> {
+//! let lazy = LazyLdap::new("ldap://localhost:2389", LdapConnSettings::new()).with_bind(
+//!     RebindCredentials::Simple {
+//!         dn: "cn=app,dc=example,dc=org".to_owned(),
+//!         password: "secret".to_owned(),
+//!     },
+//! );
+//! // No connection so far
+//! let (rs, _res) = lazy
+//!     .ldap()
+//!     .await?
+//!     .search("dc=example,dc=org", Scope::Subtree, "(uid=jdoe)", vec!["cn"])
+//!     .await?
+//!     .success()?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::fmt;
+use std::sync::Arc;
+
+use crate::conn::{LdapConnAsync, LdapConnSettings, RebindCredentials};
+use crate::ldap::Ldap;
+use crate::result::Result;
+
+use tokio::sync::Mutex;
+
+struct Shared {
+    url: String,
+    settings: LdapConnSettings,
+    bind: Option<RebindCredentials>,
+    ldap: Mutex<Option<Ldap>>,
+}
+
+/// Handle which connects on first use.
+///
+/// See the [module-level documentation](index.html) for the overview.
+#[derive(Clone)]
+pub struct LazyLdap {
+    shared: Arc<Shared>,
+}
+
+impl fmt::Debug for LazyLdap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LazyLdap")
+            .field("url", &self.shared.url)
+            .field("bind", &self.shared.bind)
+            .finish()
+    }
+}
+
+impl LazyLdap {
+    /// Create a handle which will connect to `url` with `settings`. Nothing is done
+    /// until the first call to [`ldap()`](#method.ldap).
+    pub fn new(url: &str, settings: LdapConnSettings) -> Self {
+        LazyLdap {
+            shared: Arc::new(Shared {
+                url: url.to_owned(),
+                settings,
+                bind: None,
+                ldap: Mutex::new(None),
+            }),
+        }
+    }
+
+    /// Bind every new connection with `creds`. Without this, the credentials from the
+    /// rebind function of the settings are used, if there are any.
+    ///
+    /// This must be called before the handle is cloned.
+    pub fn with_bind(mut self, creds: RebindCredentials) -> Self {
+        Arc::get_mut(&mut self.shared)
+            .expect("LazyLdap already cloned")
+            .bind = Some(creds);
+        self
+    }
+
+    /// Return the URL of the server.
+    pub fn url(&self) -> &str {
+        &self.shared.url
+    }
+
+    /// Check whether the connection is currently established.
+    pub async fn is_connected(&self) -> bool {
+        match *self.shared.ldap.lock().await {
+            Some(ref mut ldap) => !ldap.is_closed(),
+            None => false,
+        }
+    }
+
+    /// Return a handle of the shared connection, connecting and binding if there's
+    /// no connection yet, or if the previous one has closed. Concurrent callers wait
+    /// for the same connection attempt. If connecting or binding fails, the error is
+    /// returned, and the next call tries again.
+    ///
+    /// The connection is spawned on the current Tokio runtime.
+    pub async fn ldap(&self) -> Result<Ldap> {
+        let mut slot = self.shared.ldap.lock().await;
+        if let Some(ref mut ldap) = *slot {
+            if !ldap.is_closed() {
+                return Ok(ldap.clone());
+            }
+            debug!(
+                "lazy connection to {} closed, reconnecting",
+                self.shared.url
+            );
+        }
+        *slot = None;
+        let (conn, mut ldap) =
+            LdapConnAsync::with_settings(self.shared.settings.clone(), &self.shared.url).await?;
+        crate::drive!(conn);
+        let creds = match self.shared.bind {
+            Some(ref creds) => Some(creds.clone()),
+            None => self.shared.settings.rebind_credentials(&self.shared.url)?,
+        };
+        if let Some(creds) = creds {
+            creds.bind(&mut ldap).await?;
+        }
+        *slot = Some(ldap.clone());
+        Ok(ldap)
+    }
+
+    /// Unbind the shared connection, if it's established. A later call to
+    /// [`ldap()`](#method.ldap) connects again.
+    pub async fn unbind(&self) -> Result<()> {
+        let ldap = self.shared.ldap.lock().await.take();
+        match ldap {
+            Some(mut ldap) if !ldap.shared.tx.is_closed() => ldap.unbind().await,
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::LazyLdap;
+    use crate::conn::LdapConnSettings;
+
+    use tokio::net::TcpListener;
+    use tokio::time;
+
+    #[tokio::test]
+    async fn connect_on_first_use() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ldap://{}", listener.local_addr().unwrap());
+        let lazy = LazyLdap::new(&url, LdapConnSettings::new());
+        let clone = lazy.clone();
+        let idle = time::timeout(Duration::from_millis(50), listener.accept()).await;
+        assert!(idle.is_err());
+        assert!(!lazy.is_connected().await);
+        let (first, second) = tokio::join!(lazy.ldap(), clone.ldap());
+        let (_socket, _) = listener.accept().await.unwrap();
+        assert!(first.is_ok() && second.is_ok());
+        assert!(clone.is_connected().await);
+        let again = time::timeout(Duration::from_millis(50), listener.accept()).await;
+        assert!(again.is_err());
+    }
+}
+
+#[cfg(all(test, feature = "testkit"))]
+mod mock_test {
+    use super::LazyLdap;
+    use crate::conn::{LdapConnSettings, RebindCredentials};
+    use crate::testkit::MockServer;
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    const ADMIN_DN: &str = "cn=admin,dc=example,dc=org";
+
+    #[tokio::test]
+    async fn rebind_on_reconnect() {
+        let server = MockServer::new();
+        server.add_entry(
+            ADMIN_DN,
+            vec![("cn", vec!["admin"]), ("userPassword", vec!["secret"])],
+        );
+        let url = server.listen().await;
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let settings = LdapConnSettings::new().set_rebind_fn(move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            Some(RebindCredentials::Simple {
+                dn: ADMIN_DN.to_owned(),
+                password: "secret".to_owned(),
+            })
+        });
+        let lazy = LazyLdap::new(&url, settings.clone());
+        let ldap = lazy.ldap().await.unwrap();
+        assert_eq!(ldap.bound_identity().as_deref(), Some(ADMIN_DN));
+        lazy.unbind().await.unwrap();
+        let ldap = lazy.ldap().await.unwrap();
+        assert_eq!(ldap.bound_identity().as_deref(), Some(ADMIN_DN));
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        // Explicit credentials take precedence.
+        let lazy = LazyLdap::new(&url, settings).with_bind(RebindCredentials::Simple {
+            dn: ADMIN_DN.to_owned(),
+            password: "wrong".to_owned(),
+        });
+        assert!(lazy.ldap().await.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+}
@@ -1,23 +1,38 @@
 use std::collections::HashSet;
+use std::fmt;
+use std::future::Future;
 use std::hash::Hash;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
 use crate::adapters::{EntriesOnly, IntoAdapterVec};
-use crate::controls_impl::IntoRawControlVec;
+use crate::conn::TlsInfo;
+use crate::controls_impl::{
+    AuthzIdRequest, AuthzIdResp, ControlType, IntoRawControlVec, NoOp, PasswordPolicy, RawControl,
+    NO_OP_RC,
+};
 use crate::exop::Exop;
-use crate::exop_impl::construct_exop;
+use crate::exop_impl::{construct_exop, WhoAmI, WhoAmIResp};
+use crate::exop_stream::ExopStream;
+use crate::intercept::{request_dn, Interceptors};
 use crate::protocol::{LdapOp, MaybeControls, ResultSender};
+use crate::request::{
+    AddRequest, CompareRequest, DeleteRequest, ExtendedRequest, ModifyDnRequest, ModifyRequest,
+    OpKind, Request,
+};
 use crate::result::{
-    CompareResult, ExopResult, LdapError, LdapResult, LdapResultExt, Result, SearchResult,
+    BindOutcome, CompareResult, ExopResult, LdapError, LdapResult, LdapResultExt, OpContext,
+    Result, SearchOutcome, SearchResult,
 };
 use crate::search::{Scope, SearchOptions, SearchStream};
+use crate::util::LdapUrl;
 use crate::RequestId;
 
 use lber::common::TagClass;
-use lber::structures::{Boolean, Enumerated, Integer, Null, OctetString, Sequence, Set, Tag};
+use lber::structures::{Integer, Null, OctetString, Sequence, Tag};
 
-use maplit::hashset;
 use tokio::sync::{mpsc, oneshot};
 use tokio::time;
 
@@ -40,7 +55,8 @@ pub enum Mod<S: AsRef<[u8]> + Eq + Hash> {
 /// the operation. Controls are attached by calling [`with_controls()`](#method.with_controls)
 /// on the handle, and using the result to call another modifier or the operation itself.
 /// A timeout can be imposed on an operation by calling [`with_timeout()`](#method.with_timeout)
-/// on the handle before invoking the operation.
+/// on the handle before invoking the operation. Similarly, an operation can be tied to an
+/// external cancellation signal with [`with_cancel()`](#method.with_cancel).
 ///
 /// The Search operation has many parameters, most of which are infrequently used. Those
 /// parameters can be specified by constructing a [`SearchOptions`](struct.SearchOptions.html)
@@ -64,25 +80,181 @@ pub enum Mod<S: AsRef<[u8]> + Eq + Hash> {
 /// The handle can be freely cloned. Each clone will multiplex the invoked LDAP operations on
 /// the same underlying connection. Dropping the last handle will automatically close the
 /// connection.
+///
+/// Operations are submitted through a channel to the task driving the connection, and
+/// the state of the connection is shared by all clones, so cloning a handle costs a
+/// reference count increment. The handle is `Send` and `Sync`, and can be kept in shared
+/// application state, such as a web framework's extensions or a Tower layer, without
+/// a mutex around it. Since the per-operation modifiers like `with_controls()` need
+/// `&mut self`, each task clones the shared handle and invokes operations on its clone:
+///
+/// ```rust,no_run
+/// # use std::sync::Arc;
+/// # use ldap3::{Ldap, Scope};
+/// # use ldap3::result::Result;
+/// struct AppState {
+///     ldap: Ldap,
+/// }
+///
+/// async fn lookup(state: Arc<AppState>, uid: &str) -> Result<usize> {
+///     let mut ldap = state.ldap.clone();
+///     let filter = format!("(uid={})", ldap3::ldap_escape(uid));
+///     let (rs, _res) = ldap
+///         .search("dc=example,dc=org", Scope::Subtree, &filter, vec!["cn"])
+///         .await?
+///         .success()?;
+///     Ok(rs.len())
+/// }
+/// ```
 #[derive(Debug)]
 pub struct Ldap {
-    pub(crate) msgmap: Arc<Mutex<(RequestId, HashSet<RequestId>)>>,
-    pub(crate) tx: mpsc::UnboundedSender<(RequestId, LdapOp, Tag, MaybeControls, ResultSender)>,
-    pub(crate) id_scrub_tx: mpsc::UnboundedSender<RequestId>,
+    pub(crate) shared: Arc<Shared>,
     pub(crate) last_id: RequestId,
+    pub(crate) authzid_request: bool,
+    pub(crate) dry_run: bool,
     pub timeout: Option<Duration>,
+    pub(crate) cancel: Option<Cancel>,
+    pub(crate) priority: Option<Priority>,
     pub controls: MaybeControls,
     pub search_opts: Option<SearchOptions>,
 }
 
+// Connection state common to all handles. Clones of a handle share it by reference,
+// so that cloning costs a single reference count increment.
+#[derive(Debug)]
+pub(crate) struct Shared {
+    pub(crate) msgmap: Arc<Mutex<(RequestId, HashSet<RequestId>)>>,
+    pub(crate) tx: mpsc::UnboundedSender<(
+        RequestId,
+        LdapOp,
+        Tag,
+        MaybeControls,
+        ResultSender,
+        Priority,
+    )>,
+    pub(crate) id_scrub_tx: mpsc::UnboundedSender<RequestId>,
+    pub(crate) default_timeout: Option<Duration>,
+    pub(crate) stream_buffer: Option<usize>,
+    pub(crate) tls_info: Option<TlsInfo>,
+    pub(crate) server: Option<String>,
+    pub(crate) error_context: bool,
+    pub(crate) interceptors: Interceptors,
+    pub(crate) identity: Mutex<BoundIdentity>,
+    pub(crate) closing: AtomicBool,
+}
+
+/// Priority of an operation, for ordering the writes of requests on a connection.
+///
+/// Requests submitted while the connection is busy wait until they can be written,
+/// and those of higher priority are written first; requests of the same priority keep
+/// their order. A Bind is never reordered with other requests, whatever its priority,
+/// since the requests before it must run under the earlier identity, and those after
+/// it under the new one. See [`Ldap::with_priority()`](struct.Ldap.html#method.with_priority).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    /// Bulk work, such as exports, which can yield to everything else.
+    Low,
+    /// The default.
+    Normal,
+    /// Latency-sensitive operations, such as health checks.
+    High,
+}
+
+/// Outcome of [`Ldap::shutdown()`](struct.Ldap.html#method.shutdown).
+#[derive(Clone, Debug, Default)]
+pub struct ShutdownReport {
+    /// Number of operations which were outstanding when the shutdown started, and
+    /// finished during the grace period.
+    pub completed: usize,
+    /// Message IDs of the operations which were abandoned after the grace period.
+    pub abandoned: Vec<RequestId>,
+}
+
+// Interval for checking outstanding operations during shutdown.
+const SHUTDOWN_POLL: Duration = Duration::from_millis(10);
+
+// Identity established by the last successful Bind, shared by all handles of a
+// connection. The authzid is filled in by whoami_cached(), or from the response
+// control of a Bind made with with_authzid_request().
+#[derive(Debug, Default)]
+pub(crate) struct BoundIdentity {
+    dn: Option<String>,
+    authzid: Option<String>,
+}
+
+// Future whose completion cancels an operation. The mutex is there only to keep
+// the handle Sync; the future is always accessed through a unique reference.
+pub(crate) struct Cancel(Mutex<Pin<Box<dyn Future<Output = ()> + Send>>>);
+
+impl Cancel {
+    async fn wait(&mut self) {
+        self.0.get_mut().expect("cancel mutex").as_mut().await
+    }
+}
+
+impl fmt::Debug for Cancel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Cancel")
+    }
+}
+
+pub(crate) fn next_msgid(msgmap: &Mutex<(RequestId, HashSet<RequestId>)>) -> RequestId {
+    let mut msgmap = msgmap.lock().expect("msgmap mutex (inc id)");
+    let last_ldap_id = msgmap.0;
+    let mut next_ldap_id = last_ldap_id;
+    loop {
+        if next_ldap_id == i32::MAX {
+            next_ldap_id = 1;
+        } else {
+            next_ldap_id += 1;
+        }
+        if !msgmap.1.contains(&next_ldap_id) {
+            break;
+        }
+        assert_ne!(
+            next_ldap_id, last_ldap_id,
+            "LDAP message id wraparound with no free slots"
+        );
+    }
+    msgmap.0 = next_ldap_id;
+    msgmap.1.insert(next_ldap_id);
+    next_ldap_id
+}
+
+// Wait for a reply from the connection loop, subject to the timeout and the
+// cancellation future.
+pub(crate) async fn wait_reply<T, F: Future<Output = T>>(
+    reply: F,
+    timeout: Option<Duration>,
+    cancel: Option<&mut Cancel>,
+) -> Result<T> {
+    let wait = async {
+        match timeout {
+            Some(timeout) => Ok(time::timeout(timeout, reply).await?),
+            None => Ok(reply.await),
+        }
+    };
+    match cancel {
+        // A completed cancellation wins over a reply which is also ready.
+        Some(cancel) => tokio::select! {
+            biased;
+            _ = cancel.wait() => Err(LdapError::Cancelled),
+            res = wait => res,
+        },
+        None => wait.await,
+    }
+}
+
 impl Clone for Ldap {
     fn clone(&self) -> Self {
         Ldap {
-            msgmap: self.msgmap.clone(),
-            tx: self.tx.clone(),
-            id_scrub_tx: self.id_scrub_tx.clone(),
+            shared: self.shared.clone(),
             last_id: 0,
+            authzid_request: false,
+            dry_run: false,
             timeout: None,
+            cancel: None,
+            priority: None,
             controls: None,
             search_opts: None,
         }
@@ -91,51 +263,181 @@ impl Clone for Ldap {
 
 impl Ldap {
     fn next_msgid(&mut self) -> i32 {
-        let mut msgmap = self.msgmap.lock().expect("msgmap mutex (inc id)");
-        let last_ldap_id = msgmap.0;
-        let mut next_ldap_id = last_ldap_id;
-        loop {
-            if next_ldap_id == std::i32::MAX {
-                next_ldap_id = 1;
-            } else {
-                next_ldap_id += 1;
-            }
-            if !msgmap.1.contains(&next_ldap_id) {
-                break;
-            }
-            assert_ne!(
-                next_ldap_id, last_ldap_id,
-                "LDAP message id wraparound with no free slots"
-            );
-        }
-        msgmap.0 = next_ldap_id;
-        msgmap.1.insert(next_ldap_id);
-        next_ldap_id
+        next_msgid(&self.shared.msgmap)
     }
 
     pub(crate) async fn op_call(&mut self, op: LdapOp, req: Tag) -> Result<(LdapResult, Exop)> {
+        if !self.shared.error_context {
+            return self.op_call_inner(op, req).await;
+        }
+        let context = OpContext {
+            op: OpKind::from_tag(&req).map_or("unknown", OpKind::name),
+            dn: request_dn(&req),
+            server: self.shared.server.clone(),
+        };
+        self.op_call_inner(op, req)
+            .await
+            .map_err(|e| e.with_context(context))
+    }
+
+    async fn op_call_inner(&mut self, op: LdapOp, req: Tag) -> Result<(LdapResult, Exop)> {
+        let kind = OpKind::from_tag(&req).expect("protocol op");
+        if self.shared.closing.load(Ordering::Acquire) && kind != OpKind::Unbind {
+            self.controls = None;
+            self.timeout = None;
+            self.cancel = None;
+            self.priority = None;
+            return Err(LdapError::ShuttingDown);
+        }
+        if std::mem::take(&mut self.authzid_request) && kind == OpKind::Bind {
+            self.controls
+                .get_or_insert_with(Vec::new)
+                .push(RawControl::from(AuthzIdRequest));
+        }
+        let dry_run = std::mem::take(&mut self.dry_run) && kind.is_update();
+        if dry_run {
+            self.controls
+                .get_or_insert_with(Vec::new)
+                .push(RawControl::from(NoOp));
+        }
+        if let Err(e) = self
+            .shared
+            .interceptors
+            .request(kind, &req, &mut self.controls)
+        {
+            self.controls = None;
+            self.timeout = None;
+            self.cancel = None;
+            self.priority = None;
+            return Err(e);
+        }
+        let has_result = matches!(op, LdapOp::Single);
+        let bind_dn = match kind {
+            OpKind::Bind => request_dn(&req),
+            OpKind::Unbind => {
+                *self.shared.identity.lock().expect("identity mutex") = BoundIdentity::default();
+                None
+            }
+            _ => None,
+        };
+        let priority = self.priority.take().unwrap_or(Priority::Normal);
         let id = self.next_msgid();
         self.last_id = id;
         let (tx, rx) = oneshot::channel();
-        self.tx.send((id, op, req, self.controls.take(), tx))?;
-        let response = if let Some(timeout) = self.timeout.take() {
-            let res = time::timeout(timeout, rx).await;
-            if res.is_err() {
-                self.id_scrub_tx.send(self.last_id)?;
+        self.shared
+            .tx
+            .send((id, op, req, self.controls.take(), tx, priority))?;
+        let timeout = self.timeout.take().or(self.shared.default_timeout);
+        let mut cancel = self.cancel.take();
+        let response = match wait_reply(rx, timeout, cancel.as_mut()).await {
+            Ok(response) => response?,
+            Err(LdapError::Cancelled) => {
+                self.abandon_cancelled(id).await?;
+                return Err(LdapError::Cancelled);
             }
-            res?
-        } else {
-            rx.await
-        }?;
+            Err(e) => {
+                self.shared.id_scrub_tx.send(id)?;
+                return Err(e);
+            }
+        };
         let (ldap_ext, controls) = (LdapResultExt::from(response.0), response.1);
         let (mut result, exop) = (ldap_ext.0, ldap_ext.1);
         result.ctrls = controls;
+        if dry_run && result.rc == NO_OP_RC {
+            result.rc = 0;
+        }
+        if has_result {
+            self.shared.interceptors.result(kind, &mut result);
+        }
+        if kind == OpKind::Bind && result.rc != 14 {
+            let mut identity = self.shared.identity.lock().expect("identity mutex");
+            identity.authzid = result
+                .ctrls
+                .iter()
+                .find(|c| matches!(c.0, Some(ControlType::AuthzIdResp)))
+                .filter(|_| result.rc == 0)
+                .map(|c| AuthzIdResp::from_raw(&c.1).authzid);
+            identity.dn = match result.rc {
+                0 => bind_dn.filter(|dn| !dn.is_empty()),
+                // A failed Bind leaves the connection anonymous.
+                _ => None,
+            };
+        }
         Ok((result, exop))
     }
 
+    // Abandon an operation after cancellation, leaving last_id alone. This doesn't
+    // go through op_call(), which would make it recursive.
+    pub(crate) async fn abandon_cancelled(&mut self, msgid: RequestId) -> Result<()> {
+        let req = Tag::Integer(Integer {
+            id: 16,
+            class: TagClass::Application,
+            inner: msgid as i64,
+        });
+        let (tx, rx) = oneshot::channel();
+        let id = self.next_msgid();
+        self.shared
+            .tx
+            .send((id, LdapOp::Abandon(msgid), req, None, tx, Priority::High))?;
+        rx.await?;
+        self.shared.id_scrub_tx.send(msgid)?;
+        Ok(())
+    }
+
     /// Check whether the underlying connection has been closed.
     pub fn is_closed(&mut self) -> bool {
-        self.tx.is_closed()
+        self.shared.tx.is_closed()
+    }
+
+    /// Return the parameters of the TLS session, if the connection uses TLS. The
+    /// values are captured when the session is established, and the handle keeps
+    /// them even after the connection is closed.
+    pub fn tls_info(&self) -> Option<&TlsInfo> {
+        self.shared.tls_info.as_ref()
+    }
+
+    /// Return the name used in the last successful Bind on the connection. The value is
+    /// shared by all clones of the handle, and cleared by a failed Bind or an Unbind. If
+    /// the connection is anonymous, or the last Bind didn't carry a name, which is the
+    /// usual case with SASL mechanisms, `None` is returned; in that case,
+    /// [`whoami_cached()`](#method.whoami_cached) can obtain the identity.
+    pub fn bound_identity(&self) -> Option<String> {
+        self.shared
+            .identity
+            .lock()
+            .expect("identity mutex")
+            .dn
+            .clone()
+    }
+
+    /// Return the authorization identity of the connection, as reported by the Who Am I?
+    /// extended operation. The operation is performed only for the first call after each
+    /// Bind, and its successful result is cached for subsequent calls on the connection.
+    /// If the Bind was made with [`with_authzid_request()`](#method.with_authzid_request)
+    /// and the server returned the identity, no operation is needed. An empty string means
+    /// anonymous access.
+    pub async fn whoami_cached(&mut self) -> Result<String> {
+        if let Some(ref authzid) = self.shared.identity.lock().expect("identity mutex").authzid {
+            return Ok(authzid.clone());
+        }
+        let (exop, _res) = self.extended(WhoAmI).await?.success()?;
+        let authzid = exop.parse::<WhoAmIResp>().authzid;
+        self.shared.identity.lock().expect("identity mutex").authzid = Some(authzid.clone());
+        Ok(authzid)
+    }
+
+    /// Check whether the server is responsive by sending it a Who Am I? request, and waiting
+    /// for the response at most `timeout`. Any response counts, even if the server doesn't
+    /// support the operation; an error is returned only if the response doesn't arrive in
+    /// time or the connection is broken.
+    ///
+    /// Unlike [`is_closed()`](#method.is_closed), this needs a roundtrip to the server, but
+    /// also detects connections which are nominally open but unusable.
+    pub async fn check_alive(&mut self, timeout: Duration) -> Result<()> {
+        self.with_timeout(timeout)
+            .extended(WhoAmI)
+            .await
+            .map(|_| ())
     }
 
     /// Use the provided `SearchOptions` with the next Search operation, which can
@@ -167,6 +469,51 @@ impl Ldap {
         self
     }
 
+    /// Ask the server to return the authorization identity established by the next
+    /// operation, if it's a Bind, by attaching the
+    /// [`AuthzIdRequest`](controls/struct.AuthzIdRequest.html) control to its request,
+    /// in addition to any controls set with [`with_controls()`](#method.with_controls).
+    /// The identity from the response control is then returned by
+    /// [`whoami_cached()`](#method.whoami_cached) without a roundtrip to the server. The
+    /// control itself also remains in the Bind result.
+    ///
+    /// The desired operation can be invoked on the result of this method.
+    pub fn with_authzid_request(&mut self) -> &mut Self {
+        self.authzid_request = true;
+        self
+    }
+
+    /// Perform the next operation, if it's an Add, Modify, Delete or ModifyDN, as a
+    /// dry run by attaching the [`NoOp`](controls/struct.NoOp.html) control to its
+    /// request, in addition to any controls set with
+    /// [`with_controls()`](#method.with_controls). The server checks the update
+    /// without applying it, and the result is what it would have been otherwise:
+    /// the No-Op success code is translated to zero, and any error is returned as is.
+    ///
+    /// The control is critical, so a server which doesn't support it will reject
+    /// the operation with result code 12 (`unavailableCriticalExtension`).
+    ///
+    /// The desired operation can be invoked on the result of this method.
+    pub fn dry_run(&mut self) -> &mut Self {
+        self.dry_run = true;
+        self
+    }
+
+    /// Cancel the next operation when `cancel` completes. If it completes before the
+    /// operation, the operation is abandoned, and returns
+    /// [`LdapError::Cancelled`](result/enum.LdapError.html#variant.Cancelled). For a
+    /// streaming Search, `cancel` is watched while waiting for each reply. The future
+    /// is dropped when the operation finishes.
+    ///
+    /// Any future can be used; with a `CancellationToken` from `tokio-util`, pass
+    /// `async move { token.cancelled().await }`.
+    ///
+    /// The desired operation can be invoked on the result of this method.
+    pub fn with_cancel<F: Future<Output = ()> + Send + 'static>(&mut self, cancel: F) -> &mut Self {
+        self.cancel = Some(Cancel(Mutex::new(Box::pin(cancel))));
+        self
+    }
+
     /// Perform the next operation with the timeout specified in `duration`.
     /// The LDAP Search operation consists of an indeterminate number of Entry/Referral
     /// replies; the timer is reset for each reply.
@@ -174,34 +521,67 @@ impl Ldap {
     /// If the timeout occurs, the operation will return an error. The connection remains
     /// usable for subsequent operations.
     ///
+    /// This overrides the default timeout for the connection, if one was set with
+    /// [`LdapConnSettings::set_op_timeout()`](struct.LdapConnSettings.html#method.set_op_timeout).
+    ///
     /// The desired operation can be invoked on the result of this method.
     pub fn with_timeout(&mut self, duration: Duration) -> &mut Self {
         self.timeout = Some(duration);
         self
     }
 
+    /// Perform the next operation with the given `priority`. While the connection is
+    /// busy writing earlier requests, or waiting for responses to free a slot under the
+    /// limit set by [`LdapConnSettings::set_max_in_flight()`](struct.LdapConnSettings.html#method.set_max_in_flight),
+    /// submitted requests wait in the connection, and those of higher priority are written
+    /// first. The default is `Priority::Normal`. Abandon and Unbind are never delayed, and
+    /// a Bind is written after all requests submitted before it and before all requests
+    /// submitted after it, regardless of priority.
+    ///
+    /// The desired operation can be invoked on the result of this method.
+    pub fn with_priority(&mut self, priority: Priority) -> &mut Self {
+        self.priority = Some(priority);
+        self
+    }
+
     /// Do a simple Bind with the provided DN (`bind_dn`) and password (`bind_pw`).
     pub async fn simple_bind(&mut self, bind_dn: &str, bind_pw: &str) -> Result<LdapResult> {
-        let req = Tag::Sequence(Sequence {
-            id: 0,
-            class: TagClass::Application,
-            inner: vec![
-                Tag::Integer(Integer {
-                    inner: 3,
-                    ..Default::default()
-                }),
-                Tag::OctetString(OctetString {
-                    inner: Vec::from(bind_dn),
-                    ..Default::default()
-                }),
-                Tag::OctetString(OctetString {
-                    id: 0,
-                    class: TagClass::Context,
-                    inner: Vec::from(bind_pw),
-                }),
-            ],
-        });
-        Ok(self.op_call(LdapOp::Single, req).await?.0)
+        let req = simple_bind_tag(bind_dn, bind_pw);
+        let res = self.op_call(LdapOp::Single, req).await?.0;
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            msgid = self.last_id,
+            dn = bind_dn,
+            password = if bind_pw.is_empty() {
+                "<empty>"
+            } else {
+                "<redacted>"
+            },
+            rc = res.rc,
+            "simple bind"
+        );
+        Ok(res)
+    }
+
+    /// Do a simple Bind with the [`PasswordPolicy`](controls/struct.PasswordPolicy.html)
+    /// request control, in addition to any controls set with
+    /// [`with_controls()`](#method.with_controls), and return the result together with
+    /// the password policy warning or error sent by the server. See
+    /// [`BindOutcome`](result/struct.BindOutcome.html).
+    ///
+    /// Unlike with other methods, a Bind rejected by the server isn't an error here, since
+    /// the reason for the rejection is the point of calling this method; use
+    /// [`success()`](result/struct.BindOutcome.html#method.success) on the outcome to
+    /// turn a non-zero result code into an error.
+    pub async fn simple_bind_ppolicy(
+        &mut self,
+        bind_dn: &str,
+        bind_pw: &str,
+    ) -> Result<BindOutcome> {
+        let mut ctrls = self.controls.take().unwrap_or_default();
+        ctrls.push(RawControl::from(PasswordPolicy));
+        self.controls = Some(ctrls);
+        Ok(BindOutcome::from(self.simple_bind(bind_dn, bind_pw).await?))
     }
 
     /// Do a SASL EXTERNAL bind on the connection. The identity of the client
@@ -209,35 +589,16 @@ impl Ldap {
     /// is the case for Unix domain sockets or TLS client certificates. The bind
     /// is made with the hardcoded empty authzId value.
     pub async fn sasl_external_bind(&mut self) -> Result<LdapResult> {
-        let req = Tag::Sequence(Sequence {
-            id: 0,
-            class: TagClass::Application,
-            inner: vec![
-                Tag::Integer(Integer {
-                    inner: 3,
-                    ..Default::default()
-                }),
-                Tag::OctetString(OctetString {
-                    inner: Vec::new(),
-                    ..Default::default()
-                }),
-                Tag::Sequence(Sequence {
-                    id: 3,
-                    class: TagClass::Context,
-                    inner: vec![
-                        Tag::OctetString(OctetString {
-                            inner: Vec::from("EXTERNAL"),
-                            ..Default::default()
-                        }),
-                        Tag::OctetString(OctetString {
-                            inner: Vec::new(),
-                            ..Default::default()
-                        }),
-                    ],
-                }),
-            ],
-        });
-        Ok(self.op_call(LdapOp::Single, req).await?.0)
+        let req = sasl_external_tag();
+        let res = self.op_call(LdapOp::Single, req).await?.0;
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            msgid = self.last_id,
+            mech = "EXTERNAL",
+            rc = res.rc,
+            "sasl bind"
+        );
+        Ok(res)
     }
 
     /// Perform a Search with the given base DN (`base`), scope, filter, and
@@ -273,6 +634,51 @@ impl Ldap {
         Ok(SearchResult(re_vec, res))
     }
 
+    /// Perform a Search like [`search()`](#method.search), but report the way in which
+    /// the search ended as a [`SearchOutcome`](result/enum.SearchOutcome.html). The
+    /// expiry of the client-side timeout and cancellation aren't errors here, and
+    /// the entries received until then are returned; server-side time and size limits
+    /// get their own variants as well. Other errors are returned as usual.
+    pub async fn search_outcome<'a, S: AsRef<str> + Send + Sync + 'a>(
+        &mut self,
+        base: &str,
+        scope: Scope,
+        filter: &str,
+        attrs: Vec<S>,
+    ) -> Result<SearchOutcome> {
+        let mut stream = self
+            .streaming_search_with(EntriesOnly::new(), base, scope, filter, attrs)
+            .await?;
+        let mut re_vec = vec![];
+        loop {
+            match stream.next().await {
+                Ok(Some(entry)) => re_vec.push(entry),
+                Ok(None) => break,
+                Err(LdapError::Timeout { .. }) => {
+                    stream.finish().await;
+                    return Ok(SearchOutcome::TimedOut(re_vec));
+                }
+                Err(LdapError::Cancelled) => {
+                    stream.finish().await;
+                    return Ok(SearchOutcome::Cancelled(re_vec));
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        let res = stream.finish().await;
+        Ok(SearchResult(re_vec, res).into())
+    }
+
+    /// Perform a Search with the parameters given by an LDAP URL: the base DN, scope,
+    /// filter and attribute list. The search is done on this connection, regardless of
+    /// the host part of the URL. If the URL contains an unrecognized extension marked
+    /// as critical, the operation is not performed, and an error is returned.
+    pub async fn search_url(&mut self, url: &LdapUrl) -> Result<SearchResult> {
+        url.check_critical()?;
+        self.search(&url.base, url.scope, &url.filter, url.attrs.clone())
+            .await
+    }
+
     /// Perform a Search, but unlike [`search()`](#method.search) (q.v., also for
     /// the parameters), which returns all results at once, return a handle which
     /// will be used for retrieving entries one by one. See [`SearchStream`](struct.SearchStream.html)
@@ -306,6 +712,8 @@ impl Ldap {
         let mut ldap = self.clone();
         ldap.controls = self.controls.take();
         ldap.timeout = self.timeout.take();
+        ldap.priority = self.priority.take();
+        ldap.cancel = self.cancel.take();
         ldap.search_opts = self.search_opts.take();
         let mut stream = SearchStream::new(ldap, adapters.into());
         stream.start(base, scope, filter, attrs).await?;
@@ -320,53 +728,7 @@ impl Ldap {
         dn: &str,
         attrs: Vec<(S, HashSet<S>)>,
     ) -> Result<LdapResult> {
-        let mut any_empty = false;
-        let req = Tag::Sequence(Sequence {
-            id: 8,
-            class: TagClass::Application,
-            inner: vec![
-                Tag::OctetString(OctetString {
-                    inner: Vec::from(dn.as_bytes()),
-                    ..Default::default()
-                }),
-                Tag::Sequence(Sequence {
-                    inner: attrs
-                        .into_iter()
-                        .map(|(name, vals)| {
-                            if vals.is_empty() {
-                                any_empty = true;
-                            }
-                            Tag::Sequence(Sequence {
-                                inner: vec![
-                                    Tag::OctetString(OctetString {
-                                        inner: Vec::from(name.as_ref()),
-                                        ..Default::default()
-                                    }),
-                                    Tag::Set(Set {
-                                        inner: vals
-                                            .into_iter()
-                                            .map(|v| {
-                                                Tag::OctetString(OctetString {
-                                                    inner: Vec::from(v.as_ref()),
-                                                    ..Default::default()
-                                                })
-                                            })
-                                            .collect(),
-                                        ..Default::default()
-                                    }),
-                                ],
-                                ..Default::default()
-                            })
-                        })
-                        .collect(),
-                    ..Default::default()
-                }),
-            ],
-        });
-        if any_empty {
-            return Err(LdapError::AddNoValues);
-        }
-        Ok(self.op_call(LdapOp::Single, req).await?.0)
+        self.send(AddRequest::new(dn, attrs)).await
     }
 
     /// Compare the value(s) of the attribute `attr` within an entry named by `dn` with the
@@ -380,40 +742,27 @@ impl Ldap {
         attr: &str,
         val: B,
     ) -> Result<CompareResult> {
-        let req = Tag::Sequence(Sequence {
-            id: 14,
-            class: TagClass::Application,
-            inner: vec![
-                Tag::OctetString(OctetString {
-                    inner: Vec::from(dn.as_bytes()),
-                    ..Default::default()
-                }),
-                Tag::Sequence(Sequence {
-                    inner: vec![
-                        Tag::OctetString(OctetString {
-                            inner: Vec::from(attr.as_bytes()),
-                            ..Default::default()
-                        }),
-                        Tag::OctetString(OctetString {
-                            inner: Vec::from(val.as_ref()),
-                            ..Default::default()
-                        }),
-                    ],
-                    ..Default::default()
-                }),
-            ],
-        });
-        Ok(CompareResult(self.op_call(LdapOp::Single, req).await?.0))
+        self.send(CompareRequest::new(dn, attr, val)).await
+    }
+
+    /// Compare the value(s) of the attribute `attr` within an entry named by `dn` with the
+    /// value `val`, returning `true` if any of the values matches, and `false` if none do.
+    /// Any result code other than `compareTrue` or `compareFalse` is returned as an error.
+    /// This is a shorthand for calling [`equal()`](result/struct.CompareResult.html#method.equal)
+    /// on the result of [`compare()`](#method.compare), which should be used when the full
+    /// result is needed.
+    pub async fn compare_bool<B: AsRef<[u8]>>(
+        &mut self,
+        dn: &str,
+        attr: &str,
+        val: B,
+    ) -> Result<bool> {
+        self.compare(dn, attr, val).await?.equal()
     }
 
     /// Delete an entry named by `dn`.
     pub async fn delete(&mut self, dn: &str) -> Result<LdapResult> {
-        let req = Tag::OctetString(OctetString {
-            id: 10,
-            class: TagClass::Application,
-            inner: Vec::from(dn.as_bytes()),
-        });
-        Ok(self.op_call(LdapOp::Single, req).await?.0)
+        self.send(DeleteRequest::new(dn)).await
     }
 
     /// Modify an entry named by `dn` by sequentially applying the modifications given by `mods`.
@@ -423,71 +772,7 @@ impl Ldap {
         dn: &str,
         mods: Vec<Mod<S>>,
     ) -> Result<LdapResult> {
-        let mut any_add_empty = false;
-        let req = Tag::Sequence(Sequence {
-            id: 6,
-            class: TagClass::Application,
-            inner: vec![
-                Tag::OctetString(OctetString {
-                    inner: Vec::from(dn.as_bytes()),
-                    ..Default::default()
-                }),
-                Tag::Sequence(Sequence {
-                    inner: mods
-                        .into_iter()
-                        .map(|m| {
-                            let mut is_add = false;
-                            let (num, attr, set) = match m {
-                                Mod::Add(attr, set) => {
-                                    is_add = true;
-                                    (0, attr, set)
-                                }
-                                Mod::Delete(attr, set) => (1, attr, set),
-                                Mod::Replace(attr, set) => (2, attr, set),
-                                Mod::Increment(attr, val) => (3, attr, hashset! { val }),
-                            };
-                            if set.is_empty() && is_add {
-                                any_add_empty = true;
-                            }
-                            let op = Tag::Enumerated(Enumerated {
-                                inner: num,
-                                ..Default::default()
-                            });
-                            let part_attr = Tag::Sequence(Sequence {
-                                inner: vec![
-                                    Tag::OctetString(OctetString {
-                                        inner: Vec::from(attr.as_ref()),
-                                        ..Default::default()
-                                    }),
-                                    Tag::Set(Set {
-                                        inner: set
-                                            .into_iter()
-                                            .map(|val| {
-                                                Tag::OctetString(OctetString {
-                                                    inner: Vec::from(val.as_ref()),
-                                                    ..Default::default()
-                                                })
-                                            })
-                                            .collect(),
-                                        ..Default::default()
-                                    }),
-                                ],
-                                ..Default::default()
-                            });
-                            Tag::Sequence(Sequence {
-                                inner: vec![op, part_attr],
-                                ..Default::default()
-                            })
-                        })
-                        .collect(),
-                    ..Default::default()
-                }),
-            ],
-        });
-        if any_add_empty {
-            return Err(LdapError::AddNoValues);
-        }
-        Ok(self.op_call(LdapOp::Single, req).await?.0)
+        self.send(ModifyRequest::new(dn, mods)).await
     }
 
     /// Rename and/or move an entry named by `dn`. The new name is given by `rdn`. If
@@ -501,39 +786,37 @@ impl Ldap {
         delete_old: bool,
         new_sup: Option<&str>,
     ) -> Result<LdapResult> {
-        let mut params = vec![
-            Tag::OctetString(OctetString {
-                inner: Vec::from(dn.as_bytes()),
-                ..Default::default()
-            }),
-            Tag::OctetString(OctetString {
-                inner: Vec::from(rdn.as_bytes()),
-                ..Default::default()
-            }),
-            Tag::Boolean(Boolean {
-                inner: delete_old,
-                ..Default::default()
-            }),
-        ];
-        if let Some(new_sup) = new_sup {
-            params.push(Tag::OctetString(OctetString {
-                id: 0,
-                class: TagClass::Context,
-                inner: Vec::from(new_sup.as_bytes()),
-            }));
-        }
-        let req = Tag::Sequence(Sequence {
-            id: 12,
-            class: TagClass::Application,
-            inner: params,
-        });
-        Ok(self.op_call(LdapOp::Single, req).await?.0)
+        self.send(ModifyDnRequest::new(dn, rdn, delete_old, new_sup))
+            .await
     }
 
     /// Perform an Extended operation given by `exop`. Extended operations are defined in the
     /// [`exop`](exop/index.html) module. See the module-level documentation for the list of extended
     /// operations supported by this library and procedures for defining custom exops.
     pub async fn extended<E>(&mut self, exop: E) -> Result<ExopResult>
+    where
+        E: Into<Exop>,
+    {
+        self.send(ExtendedRequest::new(exop)).await
+    }
+
+    /// Submit a request built as a value. The return type depends on the request, and
+    /// is the same as that of the corresponding method; sending a
+    /// [`SearchRequest`](request/struct.SearchRequest.html) is equivalent to calling
+    /// [`search()`](#method.search). See the [`request`](request/index.html) module.
+    pub async fn send<R: Request>(&mut self, req: R) -> Result<R::Output> {
+        req.dispatch(self).await
+    }
+
+    /// Perform an Extended operation which may return a number of Intermediate responses
+    /// before the final result, such as a long-running server task reporting its progress.
+    /// Instead of waiting for the final result, return a handle for retrieving the responses
+    /// one by one. See
+    /// [`ExopStream`](struct.ExopStream.html) for the protocol.
+    ///
+    /// If [`extended()`](#method.extended) is used for such an operation, the
+    /// Intermediate responses are discarded.
+    pub async fn streaming_extended<E>(&mut self, exop: E) -> Result<ExopStream>
     where
         E: Into<Exop>,
     {
@@ -542,9 +825,11 @@ impl Ldap {
             class: TagClass::Application,
             inner: construct_exop(exop.into()),
         });
-        self.op_call(LdapOp::Single, req)
-            .await
-            .map(|et| ExopResult(et.1, et.0))
+        let mut ldap = self.clone();
+        ldap.controls = self.controls.take();
+        ldap.timeout = self.timeout.take();
+        ldap.priority = self.priority.take();
+        ExopStream::start(ldap, req).await
     }
 
     /// Terminate the connection to the server.
@@ -554,7 +839,43 @@ impl Ldap {
             class: TagClass::Application,
             inner: (),
         });
-        Ok(self.op_call(LdapOp::Unbind, req).await.map(|_| ())?)
+        self.op_call(LdapOp::Unbind, req).await.map(|_| ())
+    }
+
+    /// Shut down the connection gracefully. From the moment this method is called, new
+    /// operations through any handle of the connection fail with
+    /// [`LdapError::ShuttingDown`](result/enum.LdapError.html#variant.ShuttingDown).
+    /// Operations already in progress, including streaming Searches, get up to `grace`
+    /// to finish; those still outstanding after that are abandoned, and fail in their
+    /// respective tasks. Finally, an Unbind is sent and the connection is closed.
+    ///
+    /// The returned report lists the message IDs of the abandoned operations.
+    pub async fn shutdown(&mut self, grace: Duration) -> Result<ShutdownReport> {
+        self.shared.closing.store(true, Ordering::Release);
+        let outstanding = |ldap: &Ldap| {
+            let msgmap = ldap.shared.msgmap.lock().expect("msgmap mutex (shutdown)");
+            msgmap.1.iter().copied().collect::<HashSet<_>>()
+        };
+        let initial = outstanding(self);
+        let deadline = time::Instant::now() + grace;
+        let mut remaining = initial.clone();
+        while !remaining.is_empty() && time::Instant::now() < deadline && !self.is_closed() {
+            time::sleep(SHUTDOWN_POLL.min(deadline - time::Instant::now())).await;
+            remaining = &remaining & &outstanding(self);
+        }
+        let mut abandoned: Vec<_> = remaining.into_iter().collect();
+        abandoned.sort_unstable();
+        if !self.is_closed() {
+            for &msgid in &abandoned {
+                debug!("abandoning op={} on shutdown", msgid);
+                self.abandon_cancelled(msgid).await?;
+            }
+            self.unbind().await?;
+        }
+        Ok(ShutdownReport {
+            completed: initial.len() - abandoned.len(),
+            abandoned,
+        })
     }
 
     /// Return the message ID of the last active operation. When the handle is initialized, this
@@ -574,9 +895,445 @@ impl Ldap {
             class: TagClass::Application,
             inner: msgid as i64,
         });
-        Ok(self
-            .op_call(LdapOp::Abandon(msgid), req)
+        self.op_call(LdapOp::Abandon(msgid), req).await.map(|_| ())
+    }
+}
+
+// BindRequest for a simple Bind.
+pub(crate) fn simple_bind_tag(bind_dn: &str, bind_pw: &str) -> Tag {
+    Tag::Sequence(Sequence {
+        id: 0,
+        class: TagClass::Application,
+        inner: vec![
+            Tag::Integer(Integer {
+                inner: 3,
+                ..Default::default()
+            }),
+            Tag::OctetString(OctetString {
+                inner: Vec::from(bind_dn),
+                ..Default::default()
+            }),
+            Tag::OctetString(OctetString {
+                id: 0,
+                class: TagClass::Context,
+                inner: Vec::from(bind_pw),
+            }),
+        ],
+    })
+}
+
+// BindRequest for a SASL EXTERNAL Bind, with an empty authzId.
+pub(crate) fn sasl_external_tag() -> Tag {
+    Tag::Sequence(Sequence {
+        id: 0,
+        class: TagClass::Application,
+        inner: vec![
+            Tag::Integer(Integer {
+                inner: 3,
+                ..Default::default()
+            }),
+            Tag::OctetString(OctetString {
+                inner: Vec::new(),
+                ..Default::default()
+            }),
+            Tag::Sequence(Sequence {
+                id: 3,
+                class: TagClass::Context,
+                inner: vec![
+                    Tag::OctetString(OctetString {
+                        inner: Vec::from("EXTERNAL"),
+                        ..Default::default()
+                    }),
+                    Tag::OctetString(OctetString {
+                        inner: Vec::new(),
+                        ..Default::default()
+                    }),
+                ],
+            }),
+        ],
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use crate::conn::LdapConnAsync;
+    use crate::exop::WhoAmI;
+    use crate::result::LdapError;
+
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn handle_is_send_sync() {
+        fn shareable<T: Send + Sync + Clone + 'static>() {}
+        shareable::<super::Ldap>();
+    }
+
+    #[tokio::test]
+    async fn shutdown_abandons_outstanding() {
+        // The server accepts the connection and never responds.
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ldap://{}", listener.local_addr().unwrap());
+        let accept = tokio::spawn(async move { listener.accept().await.unwrap() });
+        let (conn, mut ldap) = LdapConnAsync::new(&url).await.unwrap();
+        crate::drive!(conn);
+        let _server = accept.await.unwrap();
+        let mut clone = ldap.clone();
+        let pending = tokio::spawn(async move { clone.extended(WhoAmI).await });
+        while ldap.shared.msgmap.lock().unwrap().1.is_empty() {
+            tokio::task::yield_now().await;
+        }
+        let report = ldap.shutdown(Duration::from_millis(50)).await.unwrap();
+        assert_eq!(report.completed, 0);
+        assert_eq!(report.abandoned, vec![1]);
+        assert!(pending.await.unwrap().is_err());
+        let mut other = ldap.clone();
+        assert!(matches!(
+            other.extended(WhoAmI).await,
+            Err(LdapError::ShuttingDown)
+        ));
+    }
+}
+
+#[cfg(all(test, feature = "testkit"))]
+mod mock_test {
+    use super::Ldap;
+    use crate::controls::ControlType;
+    use crate::result::{LdapError, SearchOutcome};
+    use crate::testkit::MockServer;
+    use crate::{Mod, Scope, SearchEntry};
+
+    use std::collections::HashSet;
+    use std::sync::Arc;
+
+    fn server() -> MockServer {
+        let server = MockServer::new();
+        server.add_entry("dc=example,dc=org", vec![("dc", vec!["example"])]);
+        server
+    }
+
+    #[tokio::test]
+    async fn cancelled_search() {
+        let server = server();
+        let (conn, mut ldap) = server.connect();
+        crate::drive!(conn);
+        let res = ldap
+            .with_cancel(async {})
+            .search(
+                "dc=example,dc=org",
+                Scope::Base,
+                "(objectClass=*)",
+                vec!["*"],
+            )
+            .await;
+        assert!(matches!(res, Err(LdapError::Cancelled)));
+        let (entries, _) = ldap
+            .search(
+                "dc=example,dc=org",
+                Scope::Base,
+                "(objectClass=*)",
+                vec!["*"],
+            )
+            .await
+            .unwrap()
+            .success()
+            .unwrap();
+        assert_eq!(entries.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn search_outcomes() {
+        let server = server();
+        let (conn, mut ldap) = server.connect();
+        crate::drive!(conn);
+        let outcome = ldap
+            .with_cancel(async {})
+            .search_outcome(
+                "dc=example,dc=org",
+                Scope::Base,
+                "(objectClass=*)",
+                vec!["*"],
+            )
+            .await
+            .unwrap();
+        assert!(matches!(outcome, SearchOutcome::Cancelled(_)));
+        let outcome = ldap
+            .search_outcome(
+                "dc=example,dc=org",
+                Scope::Base,
+                "(objectClass=*)",
+                vec!["*"],
+            )
+            .await
+            .unwrap();
+        assert!(outcome.is_done());
+        assert_eq!(outcome.entries().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn bind_identity() {
+        let server = server();
+        server.add_entry(
+            "cn=admin,dc=example,dc=org",
+            vec![("cn", vec!["admin"]), ("userPassword", vec!["secret"])],
+        );
+        let (conn, mut ldap) = server.connect();
+        crate::drive!(conn);
+        assert_eq!(ldap.bound_identity(), None);
+        assert_eq!(ldap.whoami_cached().await.unwrap(), "");
+        ldap.simple_bind("cn=admin,dc=example,dc=org", "secret")
+            .await
+            .unwrap();
+        let clone = ldap.clone();
+        assert_eq!(
+            clone.bound_identity().as_deref(),
+            Some("cn=admin,dc=example,dc=org")
+        );
+        let authzid = ldap.whoami_cached().await.unwrap();
+        assert_eq!(authzid, "dn:cn=admin,dc=example,dc=org");
+        let last_id = ldap.last_id();
+        assert_eq!(ldap.whoami_cached().await.unwrap(), authzid);
+        assert_eq!(ldap.last_id(), last_id);
+        ldap.simple_bind("cn=admin,dc=example,dc=org", "wrong")
+            .await
+            .unwrap();
+        assert_eq!(clone.bound_identity(), None);
+        let res = ldap
+            .with_authzid_request()
+            .simple_bind("cn=admin,dc=example,dc=org", "secret")
+            .await
+            .unwrap();
+        assert!(matches!(res.ctrls[0].0, Some(ControlType::AuthzIdResp)));
+        let last_id = ldap.last_id();
+        assert_eq!(ldap.whoami_cached().await.unwrap(), authzid);
+        assert_eq!(ldap.last_id(), last_id);
+    }
+
+    #[tokio::test]
+    async fn authzid_request() {
+        let server = server();
+        server.add_entry(
+            "cn=admin,dc=example,dc=org",
+            vec![("cn", vec!["admin"]), ("userPassword", vec!["secret"])],
+        );
+        let (conn, mut ldap) = server.connect();
+        crate::drive!(conn);
+        let whoami = |server: &MockServer| server.received().iter().filter(|r| r.0 == 23).count();
+        let res = ldap
+            .with_authzid_request()
+            .simple_bind("cn=admin,dc=example,dc=org", "secret")
+            .await
+            .unwrap();
+        assert!(matches!(res.ctrls[0].0, Some(ControlType::AuthzIdResp)));
+        assert_eq!(
+            ldap.whoami_cached().await.unwrap(),
+            "dn:cn=admin,dc=example,dc=org"
+        );
+        assert_eq!(whoami(&server), 0);
+        // The request applies only to the next operation, and only to a Bind.
+        ldap.with_authzid_request()
+            .search("dc=example,dc=org", Scope::Base, "(dc=*)", vec!["dc"])
+            .await
+            .unwrap();
+        let res = ldap.simple_bind("", "").await.unwrap();
+        assert!(res.ctrls.is_empty());
+        assert_eq!(ldap.whoami_cached().await.unwrap(), "");
+        assert_eq!(whoami(&server), 1);
+    }
+
+    #[tokio::test]
+    async fn compare_bool() {
+        let server = server();
+        server.add_entry("cn=test,dc=example,dc=org", vec![("cn", vec!["test"])]);
+        let (conn, mut ldap) = server.connect();
+        crate::drive!(conn);
+        assert!(ldap
+            .compare_bool("cn=test,dc=example,dc=org", "cn", "test")
+            .await
+            .unwrap());
+        assert!(!ldap
+            .compare_bool("cn=test,dc=example,dc=org", "cn", "other")
+            .await
+            .unwrap());
+        match ldap
+            .compare_bool("cn=missing,dc=example,dc=org", "cn", "test")
+            .await
+        {
+            Err(LdapError::LdapResult { result }) => assert_eq!(result.rc, 32),
+            res => panic!("unexpected result: {:?}", res),
+        }
+    }
+
+    #[tokio::test]
+    async fn bind_ppolicy() {
+        let server = server();
+        server.add_entry(
+            "uid=test,dc=example,dc=org",
+            vec![("uid", vec!["test"]), ("userPassword", vec!["secret"])],
+        );
+        let (conn, mut ldap) = server.connect();
+        crate::drive!(conn);
+        let outcome = ldap
+            .simple_bind_ppolicy("uid=test,dc=example,dc=org", "secret")
+            .await
+            .unwrap()
+            .success()
+            .unwrap();
+        assert_eq!((outcome.warning, outcome.error), (None, None));
+        let outcome = ldap
+            .simple_bind_ppolicy("uid=test,dc=example,dc=org", "wrong")
+            .await
+            .unwrap();
+        assert_eq!(outcome.result.rc, 49);
+        assert!(outcome.success().is_err());
+    }
+
+    #[tokio::test]
+    async fn dry_run() {
+        let server = MockServer::new();
+        server.add_entry("uid=test,dc=example,dc=org", vec![("uid", vec!["test"])]);
+        let (conn, mut ldap) = server.connect();
+        crate::drive!(conn);
+        let res = ldap
+            .dry_run()
+            .modify(
+                "uid=test,dc=example,dc=org",
+                vec![Mod::Replace("uid", HashSet::from(["other"]))],
+            )
+            .await
+            .unwrap();
+        assert_eq!(res.rc, 0);
+        let res = ldap
+            .dry_run()
+            .delete("uid=missing,dc=example,dc=org")
+            .await
+            .unwrap();
+        assert_eq!(res.rc, 32);
+        ldap.dry_run()
+            .delete("uid=test,dc=example,dc=org")
+            .await
+            .unwrap()
+            .success()
+            .unwrap();
+        let (entries, _) = ldap
+            .search(
+                "uid=test,dc=example,dc=org",
+                Scope::Base,
+                "(uid=test)",
+                vec!["uid"],
+            )
+            .await
+            .unwrap()
+            .success()
+            .unwrap();
+        assert_eq!(entries.len(), 1);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn shared_handle() {
+        struct AppState {
+            ldap: Ldap,
+        }
+
+        let server = server();
+        for n in 0..8 {
+            let dn = format!("uid=u{},dc=example,dc=org", n);
+            server.add_entry(&dn, vec![("uid", vec![&format!("u{}", n)[..]])]);
+        }
+        let (conn, ldap) = server.connect();
+        crate::drive!(conn);
+        let state = Arc::new(AppState { ldap });
+        let tasks: Vec<_> = (0..8)
+            .map(|n| {
+                let state = state.clone();
+                tokio::spawn(async move {
+                    let mut ldap = state.ldap.clone();
+                    let (rs, _res) = ldap
+                        .search(
+                            "dc=example,dc=org",
+                            Scope::OneLevel,
+                            &format!("(uid=u{})", n),
+                            vec!["uid"],
+                        )
+                        .await
+                        .unwrap()
+                        .success()
+                        .unwrap();
+                    SearchEntry::construct(rs[0].clone()).attrs["uid"][0].clone()
+                })
+            })
+            .collect();
+        for (n, task) in tasks.into_iter().enumerate() {
+            assert_eq!(task.await.unwrap(), format!("u{}", n));
+        }
+    }
+
+    // Subscriber which records every event as a line of space-separated fields.
+    #[cfg(feature = "tracing")]
+    struct Events(std::sync::Mutex<Vec<String>>);
+
+    #[cfg(feature = "tracing")]
+    impl tracing::Subscriber for Events {
+        fn enabled(&self, _: &tracing::Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, _: &tracing::span::Attributes<'_>) -> tracing::span::Id {
+            tracing::span::Id::from_u64(1)
+        }
+
+        fn record(&self, _: &tracing::span::Id, _: &tracing::span::Record<'_>) {}
+
+        fn record_follows_from(&self, _: &tracing::span::Id, _: &tracing::span::Id) {}
+
+        fn event(&self, event: &tracing::Event<'_>) {
+            let mut line = vec![];
+            event.record(
+                &mut |field: &tracing::field::Field, val: &dyn std::fmt::Debug| {
+                    line.push(format!("{}={:?}", field.name(), val));
+                },
+            );
+            self.0.lock().unwrap().push(line.join(" "));
+        }
+
+        fn enter(&self, _: &tracing::span::Id) {}
+
+        fn exit(&self, _: &tracing::span::Id) {}
+    }
+
+    #[cfg(feature = "tracing")]
+    #[tokio::test]
+    async fn tracing_events() {
+        let events = Arc::new(Events(std::sync::Mutex::new(vec![])));
+        let _guard = tracing::subscriber::set_default(events.clone());
+        let server = server();
+        server.add_entry(
+            "cn=admin,dc=example,dc=org",
+            vec![("cn", vec!["admin"]), ("userPassword", vec!["secret"])],
+        );
+        let (conn, mut ldap) = server.connect();
+        crate::drive!(conn);
+        ldap.simple_bind("cn=admin,dc=example,dc=org", "secret")
+            .await
+            .unwrap()
+            .success()
+            .unwrap();
+        ldap.search("dc=example,dc=org", Scope::Base, "(dc=*)", vec!["dc"])
             .await
-            .map(|_| ())?)
+            .unwrap()
+            .success()
+            .unwrap();
+        let events = events.0.lock().unwrap();
+        let bind = events
+            .iter()
+            .find(|ev| ev.starts_with("message=simple bind"))
+            .expect("bind event");
+        assert!(bind.contains("dn=\"cn=admin,dc=example,dc=org\""));
+        assert!(bind.contains("password=\"<redacted>\""));
+        assert!(events
+            .iter()
+            .any(|ev| ev.starts_with("message=operation done") && ev.contains("op=\"search\"")));
+        assert!(events.iter().all(|ev| !ev.contains("secret")));
     }
 }
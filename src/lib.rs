@@ -28,14 +28,82 @@
 //!
 //! * __sync__ (enabled by default): Synchronous API support.
 //!
+//! * __ldapi__ (enabled by default): connections to the local server through Unix domain
+//!   sockets on Unix-like platforms, or named pipes on Windows, with `ldapi://` URLs.
+//!
+//! * __blocking__ (disabled by default): synchronous client which performs operations on
+//!   the calling thread, without an async runtime, in the [`blocking`](blocking/index.html)
+//!   module.
+//!
 //! * __tls__ (enabled by default): TLS support, backed by the `native-tls` crate, which uses
-//!  a platform-specific TLS backend. This is an alias for __tls-native__.
+//!   a platform-specific TLS backend. This is an alias for __tls-native__.
 //!
 //! * __tls-rustls__ (disabled by default): TLS support, backed by the Rustls library.
 //!
-//! Without any features, only plain TCP connections (and Unix domain sockets on Unix-like
-//! platforms) are available. For TLS support, __tls__ and __tls-rustls__ are mutually
-//! exclusive: choosing both will produce a compile-time error.
+//! * __tracing__ (disabled by default): emit `tracing` events for connection establishment,
+//!   binds, and the completion of each operation, with its message ID, result code and
+//!   latency. Passwords are never recorded. Implies __metrics__.
+//!
+//! * __tcp-options__ (disabled by default): TCP keepalive and user timeout settings for
+//!   the connection, applied through the `socket2` crate.
+//!
+//! * __tower__ (disabled by default): `tower::Service` adapter for the `Ldap` handle, in the
+//!   [`service`](service/index.html) module.
+//!
+//! * __pool__ (disabled by default): the [`pool`](pool/index.html) module, with a pool
+//!   of connections bound as different identities.
+//!
+//! * __deadpool__, __bb8__ (disabled by default): connection managers for the respective
+//!   connection pool crates, in the [`pool`](pool/index.html) module. Imply __pool__.
+//!
+//! * __testkit__ (disabled by default): in-process mock server for testing code which
+//!   uses the library, in the [`testkit`](testkit/index.html) module.
+//!
+//! * __synthetic__ (disabled by default): generation of large numbers of entries in the
+//!   `testkit` mock server, for benchmarks and load tests. Implies __testkit__.
+//!
+//! * __hickory__ (disabled by default): use `hickory-resolver` for host name and SRV
+//!   lookups. See the [`resolve`](resolve/index.html) module.
+//!
+//! * __audit__ (disabled by default): structured audit records of Add, Modify, Delete and
+//!   ModifyDN operations, in the [`audit`](audit/index.html) module.
+//!
+//! * __serde__ (disabled by default): `Serialize` and `Deserialize` for `SearchEntry` and
+//!   `LdapResult`, and a schema-aware shape for JSON output in the
+//!   [`serialize`](serialize/index.html) module.
+//!   Also enables `Serialize` and `Deserialize` for the BER structures in `lber`.
+//!
+//! * __server__ (disabled by default): minimal framework for LDAP servers, for proxies
+//!   and test doubles, in the [`server`](server/index.html) module.
+//!
+//! * __dsml__ (disabled by default): conversion of requests and results to and from
+//!   DSMLv2, in the [`dsml`](dsml/index.html) module.
+//!
+//! * __passwd__ (disabled by default): generation and verification of hashed `userPassword`
+//!   values, in the [`passwd`](passwd/index.html) module.
+//!
+//! * __metrics__, __observer__ (disabled by default): operation measurements and connection
+//!   lifecycle events, in the [`metrics`](metrics/index.html) and
+//!   [`observer`](observer/index.html) modules.
+//!
+//! * __routing__, __retry__, __failover__, __lazy__ (disabled by default): handles which
+//!   route operations between a writable server and replicas, retry idempotent operations,
+//!   connect to one of several servers, or defer connecting until first use. Each enables
+//!   the module of the same name.
+//!
+//! * __discover__ (disabled by default): Active Directory domain controller location, in
+//!   the [`discover`](discover/index.html) and [`cldap`](cldap/index.html) modules.
+//!
+//! * __olc__, __changelog__, __change-stream__, __diff__ (disabled by default): OpenLDAP
+//!   `cn=config` helpers, the retro changelog reader, the unified stream of directory
+//!   changes, and entry comparison, in the [`olc`](olc/index.html),
+//!   [`changelog`](changelog/index.html), [`change_stream`](change_stream/index.html) and
+//!   [`diff`](diff/index.html) modules.
+//!
+//! Without any features, only the async API over plain TCP connections is available, which
+//! is what `default-features = false` gives for the smallest dependency tree. For TLS
+//! support, __tls__ and __tls-rustls__ are mutually exclusive: choosing both will produce
+//! a compile-time error.
 //!
 //! ## Examples
 //!
@@ -99,6 +167,8 @@ pub use tokio;
 pub type RequestId = i32;
 
 pub mod adapters;
+#[cfg(feature = "audit")]
+pub mod audit;
 pub mod asn1 {
     //! ASN.1 structure construction and parsing.
     //!
@@ -107,7 +177,8 @@ pub mod asn1 {
     //! for, e.g., implementing a new extended operation or a control, consult the source of existing
     //! exops/controls.
     pub use lber::common::TagClass;
-    pub use lber::parse::{parse_tag, parse_uint};
+    pub use lber::parse::{parse_tag, parse_uint, LimitError};
+    pub use lber::raw::{RawPL, RawTag};
     pub use lber::structure::{StructureTag, PL};
     pub use lber::structures::{
         ASNTag, Boolean, Enumerated, ExplicitTag, Integer, Null, OctetString, Sequence, Set, Tag,
@@ -116,6 +187,15 @@ pub mod asn1 {
     pub use lber::write;
     pub use lber::IResult;
 }
+#[cfg(feature = "blocking")]
+pub mod blocking;
+#[cfg(feature = "change-stream")]
+pub mod change_stream;
+#[cfg(feature = "changelog")]
+pub mod changelog;
+#[cfg(feature = "discover")]
+pub mod cldap;
+pub mod client;
 mod conn;
 pub mod controls {
     //! Control construction and parsing.
@@ -146,6 +226,10 @@ pub mod controls {
     //! [`parse()`](struct.RawControl.html#method.parse) on the instance of `RawControl`
     //! representing it. A third-party control must implement the
     //! [`ControlParser`](trait.ControlParser.html) trait to support this interface.
+    //! Its parser can also be registered with
+    //! [`register_control_parser()`](fn.register_control_parser.html), which makes
+    //! [`Control::downcast()`](struct.Control.html#method.downcast) work uniformly for
+    //! the library's and third-party controls.
     //!
     //! ### Example
     //!
@@ -177,18 +261,36 @@ pub mod controls {
     //! # }
     pub use crate::controls_impl::parse_syncinfo;
     pub use crate::controls_impl::{
-        Assertion, ManageDsaIt, MatchedValues, PagedResults, ProxyAuth, RelaxRules,
+        register_control_parser, Control, ControlParser, ControlType, CriticalControl,
+        MakeCritical, RawControl,
     };
+    pub use crate::controls_impl::{AccountUnusable, AccountUsability, AccountUsabilityResp};
     pub use crate::controls_impl::{
-        Control, ControlParser, ControlType, CriticalControl, MakeCritical, RawControl,
+        Assertion, AuthzIdRequest, AuthzIdResp, DirSync, DontUseCopy, ManageDsaIt, MatchedValues,
+        NoOp, PagedResults, ProxyAuth, RelaxRules, Subentries, NO_OP_RC,
     };
+    pub use crate::controls_impl::{DerefRes, Dereference, DereferenceResp};
+    pub use crate::controls_impl::{EntryChangeNotification, EntryChangeType, PersistentSearch};
     pub use crate::controls_impl::{
         EntryState, RefreshMode, SyncDone, SyncInfo, SyncRequest, SyncState,
     };
+    pub use crate::controls_impl::{
+        PasswordPolicy, PasswordPolicyError, PasswordPolicyResp, PasswordPolicyWarning,
+    };
     pub use crate::controls_impl::{PostRead, PostReadResp, PreRead, PreReadResp, ReadEntryResp};
+    pub use crate::controls_impl::{VirtualListView, VirtualListViewResp, VlvTarget};
 }
 mod controls_impl;
+#[cfg(feature = "diff")]
+pub mod diff;
+#[cfg(feature = "discover")]
+pub mod discover;
+#[cfg(feature = "discover")]
+mod dns;
+#[cfg(feature = "dsml")]
+pub mod dsml;
 mod exop_impl;
+mod exop_stream;
 pub mod exop {
     //! Extended operation construction and parsing.
     //!
@@ -201,30 +303,77 @@ pub mod exop {
     //!
     //! A request struct must implement the `From` conversion of itself into `Exop`.
     //! A response struct must implement the [`ExopParser`](trait.ExopParser.html)
-    //! trait.
+    //! trait. For operations not implemented by the library, the request value can
+    //! be constructed with [`ExopBuilder`](struct.ExopBuilder.html), and the response
+    //! value decoded with [`ExopReader`](struct.ExopReader.html).
     pub use crate::exop_impl::{
-        Exop, ExopParser, PasswordModify, PasswordModifyResp, WhoAmI, WhoAmIResp,
+        Exop, ExopBuilder, ExopParser, ExopReader, PasswordModify, PasswordModifyResp, WhoAmI,
+        WhoAmIResp,
     };
 }
+#[cfg(feature = "failover")]
+pub mod failover;
 mod filter;
+pub mod intercept;
+#[cfg(feature = "lazy")]
+pub mod lazy;
 mod ldap;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+#[cfg(feature = "observer")]
+pub mod observer;
+#[cfg(feature = "olc")]
+pub mod olc;
+#[cfg(feature = "passwd")]
+pub mod passwd;
+#[cfg(feature = "pool")]
+pub mod pool;
 mod protocol;
+pub mod request;
+pub mod resolve;
 pub mod result;
+#[cfg(feature = "retry")]
+pub mod retry;
+#[cfg(any(feature = "tls-native", feature = "tls-rustls"))]
+mod revocation;
+#[cfg(feature = "routing")]
+pub mod routing;
 mod search;
+#[cfg(feature = "serde")]
+pub mod serialize;
+#[cfg(feature = "server")]
+pub mod server;
+#[cfg(feature = "tower")]
+pub mod service;
 #[cfg(feature = "sync")]
 mod sync;
+mod tcp;
+#[cfg(feature = "testkit")]
+pub mod testkit;
+pub mod typed;
 mod util;
+pub mod values;
 
-pub use conn::{LdapConnAsync, LdapConnSettings};
+pub use conn::{
+    AsyncStream, ConnCompletion, DisconnectReason, LdapConnAsync, LdapConnSettings,
+    RebindCredentials, TlsInfo,
+};
+#[cfg(any(feature = "tls-native", feature = "tls-rustls"))]
+pub use conn::{RevocationPolicy, StartTlsPolicy, TlsVersion};
+pub use exop_stream::{ExopStream, Intermediate};
 pub use filter::parse as parse_filter;
-pub use ldap::{Ldap, Mod};
-pub use result::{LdapError, LdapResult, SearchResult};
+pub use ldap::{Ldap, Mod, Priority, ShutdownReport};
+pub use protocol::BerDumpFormat;
+pub use result::{BindOutcome, LdapError, LdapResult, SearchOutcome, SearchResult};
 pub use search::parse_refs;
 pub use search::{
-    DerefAliases, ResultEntry, Scope, SearchEntry, SearchOptions, SearchStream, StreamState,
+    Attr, AttrInterner, DerefAliases, InternedEntry, ResultEntry, ResultSize, Scope, SearchEntry,
+    SearchOptions, SearchStream, StreamState, ValueEntry, ALL_OPERATIONAL_ATTRS, ALL_USER_ATTRS,
+    NO_ATTRS,
 };
 #[cfg(feature = "sync")]
 pub use sync::{EntryStream, LdapConn};
 pub use util::{
-    dn_escape, get_url_params, ldap_escape, ldap_str_unescape, LdapUrlExt, LdapUrlParams,
+    dn_escape, get_url_params, ldap_escape, ldap_str_unescape, LdapUrl, LdapUrlExt,
+    LdapUrlExtension, LdapUrlParams,
 };
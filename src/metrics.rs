@@ -0,0 +1,405 @@
+//! Operation metrics.
+//!
+//! Every request and response passes through the connection loop, which makes it the
+//! natural place for measuring operations without the cooperation of the `Ldap` handle
+//! or the caller. To receive the measurements, implement [`MetricsSink`](trait.MetricsSink.html)
+//! and install it with [`LdapConnSettings::set_metrics_sink()`](../struct.LdapConnSettings.html#method.set_metrics_sink).
+//! The sink is invoked once for every finished operation, and can forward the data to
+//! the metrics system of choice. It's also told about changes of the send queue depth,
+//! if the connection limits the number of operations in flight.
+//!
+//! Operations which take longer than a threshold, set with
+//! [`LdapConnSettings::set_slow_op_threshold()`](../struct.LdapConnSettings.html#method.set_slow_op_threshold),
+//! are additionally reported as [`SlowOp`](struct.SlowOp.html)s. They are logged at the
+//! warning level, and also emitted as `tracing` events with the __tracing__ feature,
+//! unless a callback is installed with
+//! [`set_slow_op_callback()`](../struct.LdapConnSettings.html#method.set_slow_op_callback),
+//! in which case only the callback is invoked. For Searches, the report includes the
+//! base and the filter, which help in finding unindexed or pathological queries.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! use std::sync::Arc;
+//! use std::sync::atomic::{AtomicUsize, Ordering};
+//!
+//! use ldap3::metrics::{MetricsSink, OpMetrics};
+//! use ldap3::{LdapConnAsync, LdapConnSettings};
+//! # use ldap3::result::Result;
+//!
+//! #[derive(Default)]
+//! struct ErrorCounter(AtomicUsize);
+//!
+//! impl MetricsSink for ErrorCounter {
+//!     fn op_finished(&self, m: &OpMetrics) {
+//!         if m.rc.map(|rc| rc != 0).unwrap_or(true) {
+//!             self.0.fetch_add(1, Ordering::Relaxed);
+//!         }
+//!     }
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<()> {
+//! let counter = Arc::new(ErrorCounter::default());
+//! let settings = LdapConnSettings::new().set_metrics_sink(counter.clone());
+//! let (conn, ldap) = LdapConnAsync::with_settings(settings, "ldap://localhost:2389").await?;
+//! ldap3::drive!(conn);
+//! // Use ldap, check the counter...
+//! # drop(ldap);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::filter;
+use crate::RequestId;
+
+use lber::structures::Tag;
+
+/// Measurements for a single finished operation.
+#[derive(Clone, Debug)]
+pub struct OpMetrics {
+    /// Message ID of the operation.
+    pub msgid: RequestId,
+    /// Operation type: one of `"bind"`, `"search"`, `"modify"`, `"add"`, `"delete"`,
+    /// `"modifydn"`, `"compare"`, or `"extended"`.
+    pub op: &'static str,
+    /// Time between sending the request and receiving the final response.
+    pub duration: Duration,
+    /// Result code of the final response. If the operation was abandoned or timed out
+    /// before the final response arrived, this is `None`.
+    pub rc: Option<u32>,
+    /// Encoded length of the request.
+    pub bytes_sent: usize,
+    /// Total encoded length of all responses to the request, including search entries,
+    /// referrals and intermediate responses.
+    pub bytes_received: usize,
+}
+
+/// Report of an operation which took longer than the slow operation threshold.
+#[derive(Clone, Debug)]
+pub struct SlowOp {
+    /// Message ID of the operation.
+    pub msgid: RequestId,
+    /// Operation type, as in [`OpMetrics`](struct.OpMetrics.html).
+    pub op: &'static str,
+    /// Time between sending the request and receiving the final response.
+    pub duration: Duration,
+    /// Result code of the final response, or `None` if it didn't arrive.
+    pub rc: Option<u32>,
+    /// Server the connection was opened to, as `scheme://host:port`. Empty for
+    /// connections created over an existing stream.
+    pub server: String,
+    /// Target DN: the base of a Search, or the entry of an update or Compare.
+    pub dn: Option<String>,
+    /// Filter of a Search, in the string form.
+    pub filter: Option<String>,
+}
+
+pub(crate) type SlowOpCallback = Arc<dyn Fn(&SlowOp) + Send + Sync>;
+
+/// Receiver of operation measurements.
+///
+/// The sink is called from the connection loop, which doesn't process any other
+/// traffic while the call is in progress; it must not block, and should be quick.
+pub trait MetricsSink: Send + Sync {
+    /// Called when an operation has finished.
+    fn op_finished(&self, metrics: &OpMetrics);
+
+    /// Called when the number of operations queued for sending changes, with the new
+    /// number. Operations are queued only if the connection has a limit on operations
+    /// in flight, set with [`LdapConnSettings::set_max_in_flight()`](../struct.LdapConnSettings.html#method.set_max_in_flight).
+    /// The default implementation does nothing.
+    fn queue_depth(&self, depth: usize) {
+        let _ = depth;
+    }
+}
+
+struct PendingOp {
+    op: &'static str,
+    started: Instant,
+    bytes_sent: usize,
+    bytes_received: usize,
+    target: Option<Target>,
+}
+
+// What an operation was applied to, kept only if slow operations are reported.
+pub(crate) struct Target {
+    dn: Option<String>,
+    filter: Option<String>,
+}
+
+struct SlowOps {
+    threshold: Duration,
+    callback: Option<SlowOpCallback>,
+    server: String,
+}
+
+/// Bookkeeping for operations in progress, which feeds both the metrics sink and,
+/// if enabled, `tracing` events.
+pub(crate) struct OpTracker {
+    ops: HashMap<RequestId, PendingOp>,
+    sink: Option<Arc<dyn MetricsSink>>,
+    slow: Option<SlowOps>,
+}
+
+impl OpTracker {
+    pub(crate) fn new(
+        sink: Option<Arc<dyn MetricsSink>>,
+        slow_threshold: Option<Duration>,
+        slow_callback: Option<SlowOpCallback>,
+    ) -> Self {
+        OpTracker {
+            ops: HashMap::new(),
+            sink,
+            slow: slow_threshold.map(|threshold| SlowOps {
+                threshold,
+                callback: slow_callback,
+                server: String::new(),
+            }),
+        }
+    }
+
+    fn enabled(&self) -> bool {
+        cfg!(feature = "tracing") || self.sink.is_some() || self.slow.is_some()
+    }
+
+    pub(crate) fn set_server(&mut self, server: String) {
+        if let Some(ref mut slow) = self.slow {
+            slow.server = server;
+        }
+    }
+
+    // Extract the target of the request, if slow operations are reported. This must be
+    // done before the request is sent, since sending consumes it.
+    pub(crate) fn target(&self, tag: &Tag) -> Option<Target> {
+        self.slow.as_ref()?;
+        let (dn, filter) = match *tag {
+            Tag::Sequence(ref seq) if seq.id == 3 => {
+                (seq.inner.first(), seq.inner.get(6).map(filter::unparse))
+            }
+            Tag::Sequence(ref seq) if matches!(seq.id, 6 | 8 | 12 | 14) => {
+                (seq.inner.first(), None)
+            }
+            Tag::OctetString(_) => (Some(tag), None),
+            _ => (None, None),
+        };
+        let dn = match dn {
+            Some(Tag::OctetString(ref os)) => Some(String::from_utf8_lossy(&os.inner).into_owned()),
+            _ => None,
+        };
+        Some(Target { dn, filter })
+    }
+
+    pub(crate) fn start(
+        &mut self,
+        id: RequestId,
+        op: &'static str,
+        bytes_sent: usize,
+        target: Option<Target>,
+    ) {
+        if !self.enabled() {
+            return;
+        }
+        #[cfg(feature = "tracing")]
+        tracing::trace!(msgid = id, op, bytes_sent, "request sent");
+        self.ops.insert(
+            id,
+            PendingOp {
+                op,
+                started: Instant::now(),
+                bytes_sent,
+                bytes_received: 0,
+                target,
+            },
+        );
+    }
+
+    pub(crate) fn queue_depth(&self, depth: usize) {
+        #[cfg(feature = "tracing")]
+        tracing::trace!(depth, "send queue depth");
+        if let Some(ref sink) = self.sink {
+            sink.queue_depth(depth);
+        }
+    }
+
+    pub(crate) fn received(&mut self, id: RequestId, bytes: usize) {
+        if let Some(pending) = self.ops.get_mut(&id) {
+            pending.bytes_received += bytes;
+        }
+    }
+
+    pub(crate) fn finish(&mut self, id: RequestId, rc: Option<u32>) {
+        if let Some(pending) = self.ops.remove(&id) {
+            let metrics = OpMetrics {
+                msgid: id,
+                op: pending.op,
+                duration: pending.started.elapsed(),
+                rc,
+                bytes_sent: pending.bytes_sent,
+                bytes_received: pending.bytes_received,
+            };
+            #[cfg(feature = "tracing")]
+            {
+                let elapsed_us = metrics.duration.as_micros() as u64;
+                match rc {
+                    Some(rc) => tracing::debug!(
+                        msgid = id,
+                        op = metrics.op,
+                        rc,
+                        elapsed_us,
+                        "operation done"
+                    ),
+                    None => tracing::debug!(
+                        msgid = id,
+                        op = metrics.op,
+                        elapsed_us,
+                        "operation dropped"
+                    ),
+                }
+            }
+            if let Some(ref sink) = self.sink {
+                sink.op_finished(&metrics);
+            }
+            if let Some(ref slow) = self.slow {
+                if metrics.duration >= slow.threshold {
+                    let target = pending.target.unwrap_or(Target {
+                        dn: None,
+                        filter: None,
+                    });
+                    let report = SlowOp {
+                        msgid: id,
+                        op: metrics.op,
+                        duration: metrics.duration,
+                        rc,
+                        server: slow.server.clone(),
+                        dn: target.dn,
+                        filter: target.filter,
+                    };
+                    match slow.callback {
+                        Some(ref callback) => callback(&report),
+                        None => report_slow(&report),
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn report_slow(report: &SlowOp) {
+    let (dn, filter) = (
+        report.dn.as_deref().unwrap_or(""),
+        report.filter.as_deref().unwrap_or(""),
+    );
+    warn!(
+        "slow operation: msgid={} op={} server={} dn={:?} filter={:?} rc={:?} duration={:?}",
+        report.msgid, report.op, report.server, dn, filter, report.rc, report.duration
+    );
+    #[cfg(feature = "tracing")]
+    tracing::warn!(
+        msgid = report.msgid,
+        op = report.op,
+        server = %report.server,
+        dn,
+        filter,
+        rc = report.rc,
+        elapsed_us = report.duration.as_micros() as u64,
+        "slow operation"
+    );
+}
+
+#[cfg(all(test, feature = "testkit"))]
+mod test {
+    use super::{MetricsSink, OpMetrics};
+    use crate::result::LdapError;
+    use crate::testkit::MockServer;
+    use crate::{LdapConnSettings, Scope};
+
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<OpMetrics>>);
+
+    impl MetricsSink for Recorder {
+        fn op_finished(&self, metrics: &OpMetrics) {
+            self.0.lock().unwrap().push(metrics.clone());
+        }
+    }
+
+    #[tokio::test]
+    async fn finished_ops() {
+        let server = MockServer::new();
+        server.add_entry("dc=example,dc=org", vec![("dc", vec!["example"])]);
+        server.ignore(10);
+        let recorder = Arc::new(Recorder::default());
+        let settings = LdapConnSettings::new().set_metrics_sink(recorder.clone());
+        let (conn, mut ldap) = server.connect_with_settings(settings);
+        crate::drive!(conn);
+        ldap.search("dc=example,dc=org", Scope::Base, "(dc=*)", vec!["dc"])
+            .await
+            .unwrap()
+            .success()
+            .unwrap();
+        ldap.compare("dc=example,dc=org", "dc", "other")
+            .await
+            .unwrap();
+        let res = ldap
+            .with_timeout(Duration::from_millis(50))
+            .delete("dc=example,dc=org")
+            .await;
+        assert!(matches!(res, Err(LdapError::Timeout { .. })));
+        // The timed out operation is dropped by the time the next one finishes.
+        ldap.compare("dc=example,dc=org", "dc", "example")
+            .await
+            .unwrap();
+        let ops = recorder.0.lock().unwrap();
+        assert_eq!(ops.len(), 4);
+        assert_eq!((ops[0].op, ops[0].rc), ("search", Some(0)));
+        assert!(ops[0].bytes_sent > 0);
+        // The entry and the final result.
+        assert!(ops[0].bytes_received > ops[1].bytes_received);
+        assert_eq!((ops[1].op, ops[1].rc), ("compare", Some(5)));
+        assert!(ops[1].bytes_received > 0);
+        assert_eq!((ops[2].op, ops[2].rc), ("delete", None));
+        assert_eq!(ops[2].bytes_received, 0);
+        assert_eq!((ops[3].op, ops[3].rc), ("compare", Some(6)));
+        assert!(ops.windows(2).all(|w| w[0].msgid < w[1].msgid));
+    }
+
+    #[tokio::test]
+    async fn slow_op_reports() {
+        let server = MockServer::new();
+        server.add_entry("dc=example,dc=org", vec![("dc", vec!["example"])]);
+        let reports = Arc::new(Mutex::new(vec![]));
+        let sink = reports.clone();
+        let settings = LdapConnSettings::new()
+            .set_slow_op_threshold(Duration::from_secs(0))
+            .set_slow_op_callback(move |op| sink.lock().unwrap().push(op.clone()));
+        let (conn, mut ldap) = server.connect_with_settings(settings);
+        crate::drive!(conn);
+        ldap.search(
+            "dc=example,dc=org",
+            Scope::Base,
+            "(&(dc=ex*)(!(cn=a\\2a)))",
+            vec!["dc"],
+        )
+        .await
+        .unwrap();
+        ldap.delete("ou=None,dc=example,dc=org").await.unwrap();
+        let reports = reports.lock().unwrap();
+        assert_eq!(reports.len(), 2);
+        assert_eq!(reports[0].op, "search");
+        assert_eq!(reports[0].dn.as_deref(), Some("dc=example,dc=org"));
+        assert_eq!(
+            reports[0].filter.as_deref(),
+            Some("(&(dc=ex*)(!(cn=a\\2a)))")
+        );
+        assert_eq!(reports[1].op, "delete");
+        assert_eq!(reports[1].rc, Some(32));
+        assert_eq!(reports[1].dn.as_deref(), Some("ou=None,dc=example,dc=org"));
+    }
+}
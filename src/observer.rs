@@ -0,0 +1,121 @@
+//! Connection lifecycle events.
+//!
+//! Whereas a [metrics sink](../metrics/index.html) learns about individual operations,
+//! a [`ConnObserver`](trait.ConnObserver.html) is told about the connection itself: when
+//! it's been established or failed to be, when TLS is set up on it, when a Bind on it
+//! completes, and when it ends. An observer is installed with
+//! [`LdapConnSettings::set_observer()`](../struct.LdapConnSettings.html#method.set_observer),
+//! and the same observer can be given to many connections to track the churn of a
+//! whole connection pool.
+//!
+//! All methods have empty default implementations, so that only the events of interest
+//! need to be handled.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! use std::sync::Arc;
+//!
+//! use ldap3::observer::ConnObserver;
+//! use ldap3::{DisconnectReason, LdapConnAsync, LdapConnSettings};
+//! # use ldap3::result::Result;
+//!
+//! struct LogClose;
+//!
+//! impl ConnObserver for LogClose {
+//!     fn closed(&self, reason: &DisconnectReason) {
+//!         eprintln!("LDAP connection closed: {:?}", reason);
+//!     }
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<()> {
+//! let settings = LdapConnSettings::new().set_observer(Arc::new(LogClose));
+//! let (conn, ldap) = LdapConnAsync::with_settings(settings, "ldap://localhost:2389").await?;
+//! ldap3::drive!(conn);
+//! # drop(ldap);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::conn::{DisconnectReason, TlsInfo};
+use crate::result::LdapError;
+
+use url::Url;
+
+/// Receiver of connection lifecycle events.
+///
+/// Like the metrics sink, the observer is called synchronously, in some cases from
+/// the connection loop; it must not block. The URL passed to the observer is the one
+/// used for connecting, which may include credentials in unusual setups.
+pub trait ConnObserver: Send + Sync {
+    /// Called when the connection to `url` has been established, including the TLS
+    /// handshake and the SASL EXTERNAL Bind, if applicable.
+    fn connected(&self, url: &Url) {
+        let _ = url;
+    }
+
+    /// Called when connecting to `url` has failed.
+    fn connect_failed(&self, url: &Url, error: &LdapError) {
+        let _ = (url, error);
+    }
+
+    /// Called when the TLS handshake on the connection to `url` has completed, either
+    /// directly for __ldaps__, or after StartTLS.
+    fn tls_established(&self, url: &Url, info: &TlsInfo) {
+        let _ = (url, info);
+    }
+
+    /// Called when the final response to a Bind has been received, with its result
+    /// code. Zero means success, and 14 an intermediate step of a SASL exchange.
+    fn bind_finished(&self, rc: u32) {
+        let _ = rc;
+    }
+
+    /// Called when the connection driver exits.
+    fn closed(&self, reason: &DisconnectReason) {
+        let _ = reason;
+    }
+}
+
+#[cfg(all(test, feature = "testkit"))]
+mod test {
+    use super::ConnObserver;
+    use crate::testkit::MockServer;
+    use crate::{DisconnectReason, LdapConnSettings};
+
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Default)]
+    struct Events(Mutex<Vec<String>>);
+
+    impl ConnObserver for Events {
+        fn bind_finished(&self, rc: u32) {
+            self.0.lock().unwrap().push(format!("bind {}", rc));
+        }
+
+        fn closed(&self, reason: &DisconnectReason) {
+            self.0.lock().unwrap().push(format!("closed {:?}", reason));
+        }
+    }
+
+    #[tokio::test]
+    async fn observer_events() {
+        let server = MockServer::new();
+        let events = Arc::new(Events::default());
+        let settings = LdapConnSettings::new().set_observer(events.clone());
+        let (mut conn, mut ldap) = server.connect_with_settings(settings);
+        let mut completion = conn.completion();
+        crate::drive!(conn);
+        ldap.simple_bind("", "").await.unwrap();
+        ldap.simple_bind("uid=nobody,dc=example,dc=org", "secret")
+            .await
+            .unwrap();
+        ldap.unbind().await.unwrap();
+        completion.wait().await;
+        assert_eq!(
+            *events.0.lock().unwrap(),
+            vec!["bind 0", "bind 49", "closed Unbind"]
+        );
+    }
+}
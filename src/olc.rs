@@ -0,0 +1,403 @@
+//! OpenLDAP online configuration (`cn=config`) helpers.
+//!
+//! The configuration of a running OpenLDAP server lives in the `cn=config` tree, where
+//! each database and overlay is an entry, and settings are attributes of those entries.
+//! Many settings, most importantly the access rules in `olcAccess`, are multi-valued
+//! attributes with the `X-ORDERED 'VALUES'` property: every value is prefixed with its
+//! position in braces, like `{0}to * by * read`, and the server renumbers the values when
+//! one is inserted or deleted. The order is significant, so such values can't be managed
+//! with plain set semantics. [`OlcConfig`](struct.OlcConfig.html) wraps a connection,
+//! which must be bound with the rights to the configuration, typically as
+//! `gidNumber=0+uidNumber=0,cn=peercred,cn=external,cn=auth` through `ldapi://`, and
+//! provides typed operations on databases, indexes and access rules.
+//!
+//! Positions taken by the methods are those of the server. For insertion, a position
+//! past the end appends the value.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! use ldap3::olc::OlcConfig;
+//! use ldap3::LdapConnAsync;
+//! # use ldap3::result::Result;
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<()> {
+//! let (conn, mut ldap) = LdapConnAsync::new("ldapi://%2fvar%2frun%2fslapd%2fldapi").await?;
+//! ldap3::drive!(conn);
+//! ldap.sasl_external_bind().await?.success()?;
+//! let mut olc = OlcConfig::new(ldap);
+//! let db = olc.find_database("dc=example,dc=org").await?.expect("database");
+//! olc.add_index(&db.dn, "mail eq,sub").await?;
+//! olc.insert_access(&db.dn, 0, "to attrs=userPassword by self write by * auth").await?;
+//! for (n, rule) in olc.access(&db.dn).await?.iter().enumerate() {
+//!     println!("{{{}}}{}", n, rule);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::HashSet;
+
+use crate::ldap::{Ldap, Mod};
+use crate::result::{LdapError, Result};
+use crate::search::{Scope, SearchEntry};
+use crate::util::split_rdns;
+
+/// Base DN of the configuration tree.
+pub const CONFIG_DN: &str = "cn=config";
+
+/// Split the `{n}` position prefix from an ordered value. A value without a valid
+/// prefix is returned whole, with no position.
+pub fn split_ordered(value: &str) -> (Option<usize>, &str) {
+    if let Some(rest) = value.strip_prefix('{') {
+        if let Some(end) = rest.find('}') {
+            if let Ok(pos) = rest[..end].parse() {
+                return (Some(pos), &rest[end + 1..]);
+            }
+        }
+    }
+    (None, value)
+}
+
+/// Sort ordered values by position and strip the prefixes. Values without a prefix
+/// are placed after the numbered ones, in their original order.
+pub fn ordered_values<S: AsRef<str>>(values: &[S]) -> Vec<String> {
+    let mut vals: Vec<_> = values
+        .iter()
+        .map(|v| split_ordered(v.as_ref()))
+        .enumerate()
+        .collect();
+    vals.sort_by_key(|&(n, (pos, _))| (pos.is_none(), pos, n));
+    vals.into_iter().map(|(_, (_, v))| v.to_owned()).collect()
+}
+
+// Position and name from the RDN of a database or overlay entry, such as
+// `olcDatabase={1}mdb`. The frontend database is numbered -1.
+fn rdn_value(dn: &str) -> (Option<i32>, String) {
+    let rdn = split_rdns(dn).into_iter().next().unwrap_or_default();
+    let val = rdn.split_once('=').map_or("", |(_, v)| v);
+    if let Some(rest) = val.strip_prefix('{') {
+        if let Some((pos, name)) = rest.split_once('}') {
+            if let Ok(pos) = pos.parse() {
+                return (Some(pos), name.to_owned());
+            }
+        }
+    }
+    (None, val.to_owned())
+}
+
+/// Overlay configured on a database.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OlcOverlay {
+    /// DN of the overlay entry.
+    pub dn: String,
+    /// Position of the overlay in the database's overlay stack.
+    pub index: Option<i32>,
+    /// Overlay name, such as `memberof`.
+    pub name: String,
+}
+
+/// Configured database.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OlcDatabase {
+    /// DN of the database entry.
+    pub dn: String,
+    /// Database number; `-1` is the frontend.
+    pub index: Option<i32>,
+    /// Type of the database, such as `mdb` or `config`.
+    pub db_type: String,
+    /// Naming contexts served by the database.
+    pub suffixes: Vec<String>,
+    /// Overlays, ordered by position.
+    pub overlays: Vec<OlcOverlay>,
+}
+
+/// Handle for managing the configuration.
+///
+/// See the [module-level documentation](index.html) for the overview.
+#[derive(Clone, Debug)]
+pub struct OlcConfig {
+    ldap: Ldap,
+}
+
+impl OlcConfig {
+    /// Create a handle operating on `ldap`.
+    pub fn new(ldap: Ldap) -> Self {
+        OlcConfig { ldap }
+    }
+
+    async fn search(
+        &mut self,
+        scope: Scope,
+        filter: &str,
+        attrs: Vec<&str>,
+    ) -> Result<Vec<SearchEntry>> {
+        let (rs, _res) = self
+            .ldap
+            .search(CONFIG_DN, scope, filter, attrs)
+            .await?
+            .success()?;
+        Ok(rs.into_iter().map(SearchEntry::construct).collect())
+    }
+
+    async fn values(&mut self, dn: &str, attr: &str) -> Result<Vec<String>> {
+        let (rs, _res) = self
+            .ldap
+            .search(dn, Scope::Base, "(objectClass=*)", vec![attr])
+            .await?
+            .success()?;
+        let entry = match rs.into_iter().next() {
+            Some(re) => SearchEntry::construct(re),
+            None => return Ok(vec![]),
+        };
+        Ok(entry
+            .attrs
+            .into_iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(attr))
+            .map(|(_, vals)| vals)
+            .unwrap_or_default())
+    }
+
+    async fn modify(&mut self, dn: &str, mods: Vec<Mod<String>>) -> Result<()> {
+        self.ldap.modify(dn, mods).await?.success()?;
+        Ok(())
+    }
+
+    /// List the configured databases, including the frontend and the configuration
+    /// database itself, ordered by number, each with its overlays.
+    pub async fn databases(&mut self) -> Result<Vec<OlcDatabase>> {
+        let mut dbs: Vec<_> = self
+            .search(
+                Scope::OneLevel,
+                "(objectClass=olcDatabaseConfig)",
+                vec!["olcDatabase", "olcSuffix"],
+            )
+            .await?
+            .into_iter()
+            .map(|entry| {
+                let (index, db_type) = rdn_value(&entry.dn);
+                OlcDatabase {
+                    index,
+                    db_type,
+                    suffixes: entry.attrs.get("olcSuffix").cloned().unwrap_or_default(),
+                    overlays: vec![],
+                    dn: entry.dn,
+                }
+            })
+            .collect();
+        let overlays = self
+            .search(
+                Scope::Subtree,
+                "(objectClass=olcOverlayConfig)",
+                vec!["olcOverlay"],
+            )
+            .await?;
+        for entry in overlays {
+            let (index, name) = rdn_value(&entry.dn);
+            let parent = split_rdns(&entry.dn)
+                .into_iter()
+                .skip(1)
+                .collect::<Vec<_>>()
+                .join(",");
+            if let Some(db) = dbs
+                .iter_mut()
+                .find(|db| db.dn.eq_ignore_ascii_case(&parent))
+            {
+                db.overlays.push(OlcOverlay {
+                    dn: entry.dn,
+                    index,
+                    name,
+                });
+            }
+        }
+        for db in &mut dbs {
+            db.overlays.sort_by_key(|o| o.index);
+        }
+        dbs.sort_by_key(|db| db.index);
+        Ok(dbs)
+    }
+
+    /// Find the database serving `suffix`, compared case-insensitively.
+    pub async fn find_database(&mut self, suffix: &str) -> Result<Option<OlcDatabase>> {
+        Ok(self
+            .databases()
+            .await?
+            .into_iter()
+            .find(|db| db.suffixes.iter().any(|s| s.eq_ignore_ascii_case(suffix))))
+    }
+
+    /// Return the `olcDbIndex` values of the database `db_dn`.
+    pub async fn indexes(&mut self, db_dn: &str) -> Result<Vec<String>> {
+        self.values(db_dn, "olcDbIndex").await
+    }
+
+    /// Add an index specification, such as `uid eq,pres`, to the database `db_dn`.
+    pub async fn add_index(&mut self, db_dn: &str, spec: &str) -> Result<()> {
+        self.modify(
+            db_dn,
+            vec![Mod::Add(
+                String::from("olcDbIndex"),
+                HashSet::from([spec.to_owned()]),
+            )],
+        )
+        .await
+    }
+
+    /// Remove an index specification from the database `db_dn`. The specification
+    /// must match the stored value exactly.
+    pub async fn remove_index(&mut self, db_dn: &str, spec: &str) -> Result<()> {
+        self.modify(
+            db_dn,
+            vec![Mod::Delete(
+                String::from("olcDbIndex"),
+                HashSet::from([spec.to_owned()]),
+            )],
+        )
+        .await
+    }
+
+    /// Return the access rules of the database `db_dn` in order, without the position
+    /// prefixes.
+    pub async fn access(&mut self, db_dn: &str) -> Result<Vec<String>> {
+        Ok(ordered_values(&self.values(db_dn, "olcAccess").await?))
+    }
+
+    /// Insert an access rule at position `pos`, moving the rules from that position on
+    /// down by one.
+    pub async fn insert_access(&mut self, db_dn: &str, pos: usize, rule: &str) -> Result<()> {
+        self.modify(
+            db_dn,
+            vec![Mod::Add(
+                String::from("olcAccess"),
+                HashSet::from([format!("{{{}}}{}", pos, rule)]),
+            )],
+        )
+        .await
+    }
+
+    /// Replace the access rule at position `pos`.
+    pub async fn replace_access(&mut self, db_dn: &str, pos: usize, rule: &str) -> Result<()> {
+        self.modify(
+            db_dn,
+            vec![
+                Mod::Delete(
+                    String::from("olcAccess"),
+                    HashSet::from([format!("{{{}}}", pos)]),
+                ),
+                Mod::Add(
+                    String::from("olcAccess"),
+                    HashSet::from([format!("{{{}}}{}", pos, rule)]),
+                ),
+            ],
+        )
+        .await
+    }
+
+    /// Remove the access rule at position `pos`.
+    pub async fn remove_access(&mut self, db_dn: &str, pos: usize) -> Result<()> {
+        self.modify(
+            db_dn,
+            vec![Mod::Delete(
+                String::from("olcAccess"),
+                HashSet::from([format!("{{{}}}", pos)]),
+            )],
+        )
+        .await
+    }
+
+    /// Replace all access rules of the database `db_dn` with `rules`, in the given order.
+    /// An empty `rules` removes the attribute.
+    pub async fn set_access<S: AsRef<str>>(&mut self, db_dn: &str, rules: &[S]) -> Result<()> {
+        let vals: HashSet<String> = rules
+            .iter()
+            .enumerate()
+            .map(|(n, r)| format!("{{{}}}{}", n, split_ordered(r.as_ref()).1))
+            .collect();
+        if vals.len() != rules.len() {
+            return Err(LdapError::InvalidValue(String::from(
+                "duplicate access rules",
+            )));
+        }
+        self.modify(db_dn, vec![Mod::Replace(String::from("olcAccess"), vals)])
+            .await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ordered_values, rdn_value, split_ordered};
+
+    #[test]
+    fn ordered() {
+        assert_eq!(
+            split_ordered("{12}to * by * read"),
+            (Some(12), "to * by * read")
+        );
+        assert_eq!(split_ordered("{x}y"), (None, "{x}y"));
+        assert_eq!(
+            ordered_values(&["{1}b", "plain", "{0}a", "{10}c"]),
+            vec!["a", "b", "c", "plain"]
+        );
+        assert_eq!(
+            rdn_value("olcOverlay={2}memberof,olcDatabase={1}mdb,cn=config"),
+            (Some(2), String::from("memberof"))
+        );
+        assert_eq!(
+            rdn_value("olcDatabase={-1}frontend,cn=config"),
+            (Some(-1), String::from("frontend"))
+        );
+    }
+}
+
+#[cfg(all(test, feature = "testkit"))]
+mod mock_test {
+    use super::OlcConfig;
+    use crate::testkit::MockServer;
+
+    #[tokio::test]
+    async fn olc_databases() {
+        let server = MockServer::new();
+        server.add_entry("cn=config", vec![("objectClass", vec!["olcGlobal"])]);
+        server.add_entry(
+            "olcDatabase={-1}frontend,cn=config",
+            vec![("objectClass", vec!["olcDatabaseConfig"])],
+        );
+        server.add_entry(
+            "olcDatabase={1}mdb,cn=config",
+            vec![
+                ("objectClass", vec!["olcDatabaseConfig", "olcMdbConfig"]),
+                ("olcSuffix", vec!["dc=example,dc=org"]),
+                (
+                    "olcAccess",
+                    vec!["{1}to * by * read", "{0}to attrs=userPassword by * auth"],
+                ),
+            ],
+        );
+        server.add_entry(
+            "olcOverlay={0}memberof,olcDatabase={1}mdb,cn=config",
+            vec![("objectClass", vec!["olcOverlayConfig"])],
+        );
+        let (conn, ldap) = server.connect();
+        crate::drive!(conn);
+        let mut olc = OlcConfig::new(ldap);
+        let dbs = olc.databases().await.unwrap();
+        assert_eq!(dbs.len(), 2);
+        assert_eq!(
+            (dbs[0].index, dbs[0].db_type.as_str()),
+            (Some(-1), "frontend")
+        );
+        assert_eq!(dbs[1].overlays[0].name, "memberof");
+        let db = olc
+            .find_database("DC=example,DC=org")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            olc.access(&db.dn).await.unwrap(),
+            vec!["to attrs=userPassword by * auth", "to * by * read"]
+        );
+        olc.add_index(&db.dn, "uid eq").await.unwrap();
+        assert_eq!(olc.indexes(&db.dn).await.unwrap(), vec!["uid eq"]);
+    }
+}
@@ -0,0 +1,393 @@
+//! Hashing of `userPassword` values.
+//!
+//! Most servers hash a cleartext password written to `userPassword`, but some don't,
+//! or use a scheme which isn't the one desired. Provisioning code can instead hash the
+//! password itself, and write the value in the `{SCHEME}hash` form understood by
+//! OpenLDAP, 389-ds and others. [`hash_password()`](fn.hash_password.html) generates
+//! such a value with a random salt, and [`verify_password()`](fn.verify_password.html)
+//! checks a password against a stored value, which can be useful when migrating
+//! accounts between servers.
+//!
+//! The supported schemes are:
+//!
+//! * `{SSHA}` and `{SSHA512}`: salted SHA-1 and SHA-512, the base64 encoding of the
+//!   digest of the password and the salt, followed by the salt. The unsalted `{SHA}`
+//!   and `{SHA512}` can be verified, but not generated.
+//!
+//! * `{PBKDF2}` and `{PBKDF2-SHA512}`: PBKDF2 with HMAC-SHA1 or HMAC-SHA512, in the
+//!   format of OpenLDAP's `pw-pbkdf2` module: the number of iterations, the salt and
+//!   the derived key, separated by `$`, with the binary values in a base64 variant
+//!   with `.` instead of `+` and no padding.
+//!
+//! * `{CRYPT}`: the value is produced by `crypt(3)`. Only the SHA-512 method,
+//!   with values starting with `$6$`, is supported.
+//!
+//! This module is available with the __passwd__ feature.
+//!
+//! ## Example
+//!
+//! ```rust
+//! use ldap3::passwd::{hash_password, verify_password, Scheme};
+//! # use ldap3::result::Result;
+//!
+//! # fn main() -> Result<()> {
+//! let hashed = hash_password(Scheme::Ssha512, "secret")?;
+//! assert!(hashed.starts_with("{SSHA512}"));
+//! assert!(verify_password(&hashed, "secret")?);
+//! assert!(!verify_password(&hashed, "guess")?);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::io;
+
+use crate::result::{LdapError, Result};
+use crate::util::{base64_decode, base64_encode};
+
+use hmac::Hmac;
+use sha1::Sha1;
+use sha2::{Digest, Sha512};
+use sha_crypt::{sha512_crypt_b64, Sha512Params};
+
+const SALT_LEN: usize = 16;
+const CRYPT_SALT_LEN: usize = 16;
+const CRYPT_B64: &[u8; 64] = b"./0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+/// Default number of PBKDF2 iterations.
+pub const PBKDF2_ROUNDS: u32 = 10000;
+
+/// Default number of SHA-512 crypt rounds.
+pub const CRYPT_ROUNDS: u32 = 5000;
+
+/// Largest number of PBKDF2 iterations accepted when hashing or verifying.
+///
+/// The iteration count of a stored value is chosen by whoever wrote it, so a
+/// value with a huge count could otherwise occupy a CPU for minutes.
+pub const MAX_PBKDF2_ROUNDS: u32 = 1_000_000;
+
+/// Largest number of SHA-512 crypt rounds accepted when hashing or verifying,
+/// for the same reason as [`MAX_PBKDF2_ROUNDS`](constant.MAX_PBKDF2_ROUNDS.html).
+pub const MAX_CRYPT_ROUNDS: u32 = 1_000_000;
+
+/// Password hashing scheme.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Scheme {
+    /// Salted SHA-1.
+    Ssha,
+    /// Salted SHA-512.
+    Ssha512,
+    /// PBKDF2 with HMAC-SHA1 and the given number of iterations.
+    Pbkdf2(u32),
+    /// PBKDF2 with HMAC-SHA512 and the given number of iterations.
+    Pbkdf2Sha512(u32),
+    /// SHA-512 crypt with the given number of rounds.
+    Crypt(u32),
+}
+
+fn random_bytes(len: usize) -> Result<Vec<u8>> {
+    let mut buf = vec![0; len];
+    getrandom::getrandom(&mut buf)
+        .map_err(|e| io::Error::other(format!("random salt generation: {}", e)))?;
+    Ok(buf)
+}
+
+fn invalid<T>(msg: &str) -> Result<T> {
+    Err(LdapError::InvalidValue(msg.to_owned()))
+}
+
+// Base64 variant used by pw-pbkdf2: `.` for `+`, without padding.
+fn ab64_encode(data: &[u8]) -> String {
+    base64_encode(data).trim_end_matches('=').replace('+', ".")
+}
+
+fn ab64_decode(text: &str) -> Result<Vec<u8>> {
+    base64_decode(&text.replace('.', "+")).or_else(|e| invalid(&e))
+}
+
+fn salted<D: Digest>(password: &[u8], salt: &[u8]) -> Vec<u8> {
+    let mut d = D::new();
+    d.update(password);
+    d.update(salt);
+    let mut out = d.finalize().to_vec();
+    out.extend_from_slice(salt);
+    out
+}
+
+// SHA-512 crypt in the `$6$` format. `rounds` of None means the implicit default,
+// which isn't recorded in the output. Like glibc, too few rounds are raised to the
+// minimum of 1000; the maximum is checked by the callers.
+fn sha512_crypt(password: &[u8], salt: &[u8], rounds: Option<u32>) -> Result<String> {
+    let salt = &salt[..salt.len().min(CRYPT_SALT_LEN)];
+    let n = rounds.unwrap_or(CRYPT_ROUNDS).max(1000);
+    let params = match Sha512Params::new(n as usize) {
+        Ok(params) => params,
+        Err(_) => return invalid("invalid crypt rounds"),
+    };
+    let hash = match sha512_crypt_b64(password, salt, &params) {
+        Ok(hash) => hash,
+        Err(_) => return invalid("invalid crypt value"),
+    };
+    let mut out = String::from("$6$");
+    if rounds.is_some() {
+        out.push_str(&format!("rounds={}$", n));
+    }
+    out.push_str(&String::from_utf8_lossy(salt));
+    out.push('$');
+    out.push_str(&hash);
+    Ok(out)
+}
+
+fn verify_crypt(hash: &str, password: &[u8]) -> Result<bool> {
+    let rest = match hash.strip_prefix("$6$") {
+        Some(rest) => rest,
+        None => return invalid("unsupported crypt method"),
+    };
+    let (rounds, rest) = match rest.strip_prefix("rounds=") {
+        Some(rest) => match rest.split_once('$') {
+            Some((n, rest)) => match n.parse::<u32>() {
+                Ok(n) if n <= MAX_CRYPT_ROUNDS => (Some(n), rest),
+                _ => return invalid("invalid crypt rounds"),
+            },
+            None => return invalid("invalid crypt value"),
+        },
+        None => (None, rest),
+    };
+    let salt = match rest.rsplit_once('$') {
+        Some((salt, _)) => salt,
+        None => return invalid("invalid crypt value"),
+    };
+    Ok(constant_eq(
+        sha512_crypt(password, salt.as_bytes(), rounds)?.as_bytes(),
+        hash.as_bytes(),
+    ))
+}
+
+fn constant_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+// PBKDF2 with a particular HMAC, filling the output buffer.
+type Derive = fn(&[u8], &[u8], u32, &mut [u8]);
+
+fn pbkdf2_sha1(password: &[u8], salt: &[u8], rounds: u32, out: &mut [u8]) {
+    pbkdf2::pbkdf2::<Hmac<Sha1>>(password, salt, rounds, out).expect("HMAC key");
+}
+
+fn pbkdf2_sha512(password: &[u8], salt: &[u8], rounds: u32, out: &mut [u8]) {
+    pbkdf2::pbkdf2::<Hmac<Sha512>>(password, salt, rounds, out).expect("HMAC key");
+}
+
+fn pbkdf2_value(derive: Derive, password: &[u8], salt: &[u8], rounds: u32, len: usize) -> String {
+    let mut dk = vec![0; len];
+    derive(password, salt, rounds, &mut dk);
+    format!("{}${}${}", rounds, ab64_encode(salt), ab64_encode(&dk))
+}
+
+// The derived key must be exactly one HMAC output long: an empty key would match
+// any password, and a long one would multiply the work done for each iteration.
+fn verify_pbkdf2(derive: Derive, value: &str, password: &[u8], dk_len: usize) -> Result<bool> {
+    let parts: Vec<_> = value.split('$').collect();
+    let (rounds, salt, dk) = match parts.as_slice() {
+        [rounds, salt, dk] => match rounds.parse::<u32>() {
+            Ok(rounds) if rounds > 0 && rounds <= MAX_PBKDF2_ROUNDS => {
+                (rounds, ab64_decode(salt)?, ab64_decode(dk)?)
+            }
+            _ => return invalid("invalid PBKDF2 iteration count"),
+        },
+        _ => return invalid("invalid PBKDF2 value"),
+    };
+    if dk.len() != dk_len {
+        return invalid("invalid PBKDF2 derived key length");
+    }
+    let mut computed = vec![0; dk.len()];
+    derive(password, &salt, rounds, &mut computed);
+    Ok(constant_eq(&computed, &dk))
+}
+
+fn verify_salted<D: Digest>(
+    value: &str,
+    password: &[u8],
+    digest_len: usize,
+    has_salt: bool,
+) -> Result<bool> {
+    let raw = base64_decode(value).or_else(|e| invalid(&e))?;
+    if raw.len() < digest_len || (has_salt && raw.len() == digest_len) {
+        return invalid("hash value too short");
+    }
+    if !has_salt && raw.len() > digest_len {
+        return invalid("hash value too long");
+    }
+    let (digest, salt) = raw.split_at(digest_len);
+    Ok(constant_eq(
+        &salted::<D>(password, salt)[..digest_len],
+        digest,
+    ))
+}
+
+/// Hash `password` with `scheme` and a random salt, returning the value prefixed
+/// with the scheme name.
+///
+/// An iteration or round count above [`MAX_PBKDF2_ROUNDS`](constant.MAX_PBKDF2_ROUNDS.html)
+/// or [`MAX_CRYPT_ROUNDS`](constant.MAX_CRYPT_ROUNDS.html) is an error, since the value
+/// couldn't be verified afterwards.
+pub fn hash_password<P: AsRef<[u8]>>(scheme: Scheme, password: P) -> Result<String> {
+    let password = password.as_ref();
+    Ok(match scheme {
+        Scheme::Ssha => format!(
+            "{{SSHA}}{}",
+            base64_encode(&salted::<Sha1>(password, &random_bytes(SALT_LEN)?))
+        ),
+        Scheme::Ssha512 => format!(
+            "{{SSHA512}}{}",
+            base64_encode(&salted::<Sha512>(password, &random_bytes(SALT_LEN)?))
+        ),
+        Scheme::Pbkdf2(rounds) | Scheme::Pbkdf2Sha512(rounds) if rounds > MAX_PBKDF2_ROUNDS => {
+            return invalid("too many PBKDF2 iterations");
+        }
+        Scheme::Pbkdf2(rounds) => format!(
+            "{{PBKDF2}}{}",
+            pbkdf2_value(
+                pbkdf2_sha1,
+                password,
+                &random_bytes(SALT_LEN)?,
+                rounds.max(1),
+                20
+            )
+        ),
+        Scheme::Pbkdf2Sha512(rounds) => format!(
+            "{{PBKDF2-SHA512}}{}",
+            pbkdf2_value(
+                pbkdf2_sha512,
+                password,
+                &random_bytes(SALT_LEN)?,
+                rounds.max(1),
+                64
+            )
+        ),
+        Scheme::Crypt(rounds) if rounds > MAX_CRYPT_ROUNDS => {
+            return invalid("too many crypt rounds");
+        }
+        Scheme::Crypt(rounds) => {
+            let salt: Vec<u8> = random_bytes(CRYPT_SALT_LEN)?
+                .into_iter()
+                .map(|b| CRYPT_B64[(b & 0x3f) as usize])
+                .collect();
+            let rounds = if rounds == CRYPT_ROUNDS {
+                None
+            } else {
+                Some(rounds)
+            };
+            format!("{{CRYPT}}{}", sha512_crypt(password, &salt, rounds)?)
+        }
+    })
+}
+
+/// Check `password` against the stored value `hashed`. The scheme name is matched
+/// case-insensitively. A value without a scheme prefix is compared as cleartext.
+///
+/// An unsupported scheme or a malformed value is an error, including a salted value
+/// without a salt and a PBKDF2 value whose derived key isn't exactly one HMAC output
+/// long. So is a PBKDF2 or crypt
+/// value whose iteration or round count exceeds [`MAX_PBKDF2_ROUNDS`](constant.MAX_PBKDF2_ROUNDS.html)
+/// or [`MAX_CRYPT_ROUNDS`](constant.MAX_CRYPT_ROUNDS.html); it's refused before any
+/// hashing is done.
+pub fn verify_password<H: AsRef<[u8]>, P: AsRef<[u8]>>(hashed: H, password: P) -> Result<bool> {
+    let (hashed, password) = (hashed.as_ref(), password.as_ref());
+    let text = match std::str::from_utf8(hashed) {
+        Ok(text) => text,
+        Err(_) => return Ok(constant_eq(hashed, password)),
+    };
+    let (scheme, value) = match text.strip_prefix('{').and_then(|t| t.split_once('}')) {
+        Some((scheme, value)) => (scheme.to_ascii_uppercase(), value),
+        None => return Ok(constant_eq(hashed, password)),
+    };
+    match scheme.as_str() {
+        "SSHA" => verify_salted::<Sha1>(value, password, 20, true),
+        "SHA" => verify_salted::<Sha1>(value, password, 20, false),
+        "SSHA512" => verify_salted::<Sha512>(value, password, 64, true),
+        "SHA512" => verify_salted::<Sha512>(value, password, 64, false),
+        "PBKDF2" | "PBKDF2-SHA1" => verify_pbkdf2(pbkdf2_sha1, value, password, 20),
+        "PBKDF2-SHA512" => verify_pbkdf2(pbkdf2_sha512, value, password, 64),
+        "CRYPT" => verify_crypt(value, password),
+        "CLEARTEXT" => Ok(constant_eq(value.as_bytes(), password)),
+        _ => invalid(&format!("unsupported password scheme {}", scheme)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{hash_password, pbkdf2_sha1, sha512_crypt, verify_password, Scheme};
+    use super::{MAX_CRYPT_ROUNDS, MAX_PBKDF2_ROUNDS};
+
+    #[test]
+    fn known_values() {
+        let mut dk = [0; 20];
+        pbkdf2_sha1(b"password", b"salt", 2, &mut dk);
+        assert_eq!(
+            dk.iter().map(|b| format!("{:02x}", b)).collect::<String>(),
+            "ea6c014dc72d6f8ccd1ed92ace1d41f0d8de8957"
+        );
+        assert_eq!(
+            sha512_crypt(b"Hello world!", b"saltstring", None).unwrap(),
+            "$6$saltstring$svn8UoSVapNtMuq1ukKS4tPQd8iKwSMHWjl/O817G3uBnIFNjnQJu\
+             esI68u4OTLiBFdcbYEdFCoEOfaS35inz1"
+        );
+        assert_eq!(
+            sha512_crypt(b"Hello world!", b"saltstringsaltstring", Some(10000)).unwrap(),
+            "$6$rounds=10000$saltstringsaltst$OW1/O6BYHV6BcXZu8QVeXbDWra3Oeqh0sbHbbMC\
+             VNSnCM/UrjmM0Dp8vOuZeHBy/YTBmSK6H9qs/y3RnOaw5v."
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        for scheme in [
+            Scheme::Ssha,
+            Scheme::Ssha512,
+            Scheme::Pbkdf2(100),
+            Scheme::Pbkdf2Sha512(100),
+            Scheme::Crypt(1000),
+        ] {
+            let hashed = hash_password(scheme, "secret").unwrap();
+            assert!(verify_password(&hashed, "secret").unwrap(), "{}", hashed);
+            assert!(!verify_password(&hashed, "Secret").unwrap(), "{}", hashed);
+        }
+        assert!(verify_password("{sha}5en6G6MezRroT3XKqkdPOmY/BfQ=", "secret").unwrap());
+        assert!(verify_password("{MD5}x", "secret").is_err());
+    }
+
+    #[test]
+    fn oversized_rounds() {
+        let pbkdf2 = format!("{{PBKDF2-SHA512}}{}$c2FsdA$AAAA", MAX_PBKDF2_ROUNDS + 1);
+        assert!(verify_password(pbkdf2, "secret").is_err());
+        assert!(verify_password("{PBKDF2}4294967295$c2FsdA$AAAA", "secret").is_err());
+        let crypt = format!("{{CRYPT}}$6$rounds={}$salt$AAAA", MAX_CRYPT_ROUNDS + 1);
+        assert!(verify_password(crypt, "secret").is_err());
+        assert!(verify_password("{CRYPT}$6$rounds=999999999$salt$AAAA", "secret").is_err());
+        assert!(hash_password(Scheme::Pbkdf2(MAX_PBKDF2_ROUNDS + 1), "secret").is_err());
+        assert!(hash_password(Scheme::Crypt(MAX_CRYPT_ROUNDS + 1), "secret").is_err());
+    }
+
+    #[test]
+    fn derived_key_length() {
+        assert!(verify_password("{PBKDF2}1000$c2FsdA$", "anything").is_err());
+        assert!(verify_password("{PBKDF2}1000$c2FsdA$A", "anything").is_err());
+        let hashed = hash_password(Scheme::Pbkdf2(10), "secret").unwrap();
+        let truncated = &hashed[..hashed.len() - 4];
+        assert!(verify_password(truncated, "secret").is_err());
+        let oversized = format!("{}{}", hashed, "A".repeat(128));
+        assert!(verify_password(oversized, "secret").is_err());
+        let oversized = format!("{{PBKDF2-SHA512}}1000$c2FsdA${}", "A".repeat(100_000));
+        assert!(verify_password(oversized, "secret").is_err());
+    }
+
+    #[test]
+    fn missing_salt() {
+        // SHA-1 of "secret", with no salt following it.
+        assert!(verify_password("{SSHA}5en6G6MezRroT3XKqkdPOmY/BfQ=", "secret").is_err());
+        assert!(verify_password("{SHA}5en6G6MezRroT3XKqkdPOmY/BfQ=", "secret").unwrap());
+        let hashed = hash_password(Scheme::Ssha, "secret").unwrap();
+        let unsalted = hashed.replacen("{SSHA}", "{SHA}", 1);
+        assert!(verify_password(unsalted, "secret").is_err());
+    }
+}
@@ -0,0 +1,522 @@
+//! Connection pooling.
+//!
+//! ## Managers for pool crates
+//!
+//! Both the `deadpool` and the `bb8` pool crates need a manager, which knows how to open a new connection and how
+//! to check whether a pooled connection is still usable. [`LdapManager`](struct.LdapManager.html)
+//! implements `deadpool::managed::Manager` with the __deadpool__ feature, and
+//! `bb8::ManageConnection` with the __bb8__ feature. The pooled object is an
+//! [`Ldap`](../struct.Ldap.html) handle; its connection is spawned on the current Tokio
+//! runtime when the handle is created.
+//!
+//! If the manager is given bind credentials, every new connection is bound with them
+//! before it's handed to the pool. An unsuccessful Bind makes the connection attempt
+//! fail.
+//!
+//! The health check is performed when a connection is taken from the pool. It issues
+//! a cheap operation on the connection, chosen by [`HealthCheck`](enum.HealthCheck.html),
+//! and the connection is discarded if the operation fails.
+//!
+//! ## Example
+//!
+//! ```rust,ignore
+//! use ldap3::pool::LdapManager;
+//! use ldap3::Scope;
+//!
+//! let manager = LdapManager::new("ldap://localhost:2389")
+//!     .with_bind("cn=Manager,dc=example,dc=org", "secret");
+//! let pool = deadpool::managed::Pool::builder(manager).max_size(8).build()?;
+//! let mut ldap = pool.get().await?;
+//! let (rs, _res) = ldap
+//!     .search("dc=example,dc=org", Scope::Subtree, "(uid=*)", vec!["uid"])
+//!     .await?
+//!     .success()?;
+//! ```
+//!
+//! ## Pooling bound connections
+//!
+//! A generic pool hands out connections without regard to their bound identity. When
+//! the same users authenticate repeatedly, as with a web single sign-on service which
+//! checks credentials by binding with them, it pays to keep connections bound as each
+//! user, and reuse them instead of binding again. [`BindPool`](struct.BindPool.html)
+//! does that, and doesn't need any pool crate.
+//!
+//! An idle connection is reused only for a request with exactly the same DN and
+//! password which were used to bind it, so a wrong password never gets a bound
+//! connection. Idle connections are discarded after a time-to-live period, which also
+//! bounds the time during which a changed or revoked password remains usable through
+//! the pool.
+//!
+//! ```rust,no_run
+//! use std::time::Duration;
+//!
+//! use ldap3::pool::BindPool;
+//! # use ldap3::result::Result;
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<()> {
+//! let pool = BindPool::new("ldap://localhost:2389").with_ttl(Duration::from_secs(120));
+//! let mut ldap = pool.get("uid=jdoe,ou=People,dc=example,dc=org", "secret").await?;
+//! let authzid = ldap.whoami_cached().await?;
+//! // When dropped, the connection goes back to the pool.
+//! drop(ldap);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hasher};
+use std::ops::{Deref, DerefMut};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::conn::{LdapConnAsync, LdapConnSettings};
+#[cfg(any(feature = "deadpool", feature = "bb8"))]
+use crate::exop::WhoAmI;
+use crate::ldap::Ldap;
+#[cfg(any(feature = "deadpool", feature = "bb8"))]
+use crate::result::LdapError;
+use crate::result::Result;
+#[cfg(any(feature = "deadpool", feature = "bb8"))]
+use crate::search::Scope;
+
+#[cfg(any(feature = "deadpool", feature = "bb8"))]
+use async_trait::async_trait;
+
+#[cfg(any(feature = "deadpool", feature = "bb8"))]
+/// Operation used for checking the health of a pooled connection.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HealthCheck {
+    /// Base-scoped Search of the root DSE, requesting no attributes. This is the default,
+    /// since every server must support it.
+    RootDse,
+    /// Who Am I extended operation. Doesn't produce a search result, but not all servers
+    /// implement it.
+    WhoAmI,
+}
+
+#[cfg(any(feature = "deadpool", feature = "bb8"))]
+/// Pool manager for LDAP connections.
+///
+/// See the [module-level documentation](index.html) for the overview.
+#[derive(Clone)]
+pub struct LdapManager {
+    url: String,
+    settings: LdapConnSettings,
+    bind: Option<(String, String)>,
+    check: HealthCheck,
+}
+
+#[cfg(any(feature = "deadpool", feature = "bb8"))]
+impl LdapManager {
+    /// Create a manager for connections to the server given by `url`, with default
+    /// connection settings, no bind credentials and the root DSE health check.
+    pub fn new(url: &str) -> Self {
+        LdapManager {
+            url: url.to_owned(),
+            settings: LdapConnSettings::new(),
+            bind: None,
+            check: HealthCheck::RootDse,
+        }
+    }
+
+    /// Use `settings` when opening connections.
+    pub fn with_settings(mut self, settings: LdapConnSettings) -> Self {
+        self.settings = settings;
+        self
+    }
+
+    /// Do a simple Bind with `bind_dn` and `bind_pw` on every new connection.
+    pub fn with_bind(mut self, bind_dn: &str, bind_pw: &str) -> Self {
+        self.bind = Some((bind_dn.to_owned(), bind_pw.to_owned()));
+        self
+    }
+
+    /// Set the health check operation.
+    pub fn with_health_check(mut self, check: HealthCheck) -> Self {
+        self.check = check;
+        self
+    }
+
+    async fn connect(&self) -> Result<Ldap> {
+        let (conn, mut ldap) =
+            LdapConnAsync::with_settings(self.settings.clone(), &self.url).await?;
+        crate::drive!(conn);
+        if let Some((ref bind_dn, ref bind_pw)) = self.bind {
+            ldap.simple_bind(bind_dn, bind_pw).await?.success()?;
+        }
+        Ok(ldap)
+    }
+
+    async fn check(&self, ldap: &mut Ldap) -> Result<()> {
+        match self.check {
+            HealthCheck::RootDse => {
+                ldap.search("", Scope::Base, "(objectClass=*)", vec!["1.1"])
+                    .await?
+                    .success()?;
+            }
+            HealthCheck::WhoAmI => {
+                ldap.extended(WhoAmI).await?.success()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "deadpool")]
+#[async_trait]
+impl deadpool::managed::Manager for LdapManager {
+    type Type = Ldap;
+    type Error = LdapError;
+
+    async fn create(&self) -> Result<Ldap> {
+        self.connect().await
+    }
+
+    async fn recycle(&self, ldap: &mut Ldap) -> deadpool::managed::RecycleResult<LdapError> {
+        Ok(self.check(ldap).await?)
+    }
+}
+
+#[cfg(feature = "bb8")]
+#[async_trait]
+impl bb8::ManageConnection for LdapManager {
+    type Connection = Ldap;
+    type Error = LdapError;
+
+    async fn connect(&self) -> Result<Ldap> {
+        LdapManager::connect(self).await
+    }
+
+    async fn is_valid(&self, ldap: &mut Ldap) -> Result<()> {
+        self.check(ldap).await
+    }
+
+    fn has_broken(&self, ldap: &mut Ldap) -> bool {
+        ldap.is_closed()
+    }
+}
+
+// Pool key: the bind DN, and a keyed hash of the password, so that the passwords
+// themselves aren't kept.
+type BindKey = (String, u64);
+
+struct IdleConn {
+    ldap: Ldap,
+    since: Instant,
+}
+
+/// Pool of connections keyed by their bound identity.
+///
+/// See the [module-level documentation](index.html#pooling-bound-connections) for the
+/// overview. The pool can be shared between tasks, typically in an `Arc`.
+pub struct BindPool {
+    url: String,
+    settings: LdapConnSettings,
+    max_per_identity: usize,
+    ttl: Duration,
+    hasher: RandomState,
+    idle: Mutex<HashMap<BindKey, Vec<IdleConn>>>,
+}
+
+impl BindPool {
+    /// Create a pool of connections to the server given by `url`, with default
+    /// connection settings. At most four idle connections are kept per identity,
+    /// for at most 60 seconds.
+    pub fn new(url: &str) -> Self {
+        BindPool {
+            url: url.to_owned(),
+            settings: LdapConnSettings::new(),
+            max_per_identity: 4,
+            ttl: Duration::from_secs(60),
+            hasher: RandomState::new(),
+            idle: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Use `settings` when opening connections.
+    pub fn with_settings(mut self, settings: LdapConnSettings) -> Self {
+        self.settings = settings;
+        self
+    }
+
+    /// Set the maximum number of idle connections kept for a single identity.
+    /// Connections returned beyond that number are closed.
+    pub fn with_max_per_identity(mut self, max: usize) -> Self {
+        self.max_per_identity = max;
+        self
+    }
+
+    /// Set the time after which an idle connection is discarded.
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    fn key(&self, bind_dn: &str, bind_pw: &str) -> BindKey {
+        let mut hasher = self.hasher.build_hasher();
+        hasher.write(bind_pw.as_bytes());
+        (bind_dn.to_owned(), hasher.finish())
+    }
+
+    /// Return a connection bound with `bind_dn` and `bind_pw`. An idle connection bound
+    /// with the same credentials is reused if available; otherwise, a new connection is
+    /// opened and bound, and an unsuccessful Bind is returned as an error. The
+    /// connection is spawned on the current Tokio runtime.
+    pub async fn get(&self, bind_dn: &str, bind_pw: &str) -> Result<PooledLdap<'_>> {
+        let key = self.key(bind_dn, bind_pw);
+        if let Some(ldap) = self.take_idle(&key) {
+            return Ok(PooledLdap {
+                pool: self,
+                key,
+                ldap: Some(ldap),
+            });
+        }
+        let (conn, mut ldap) =
+            LdapConnAsync::with_settings(self.settings.clone(), &self.url).await?;
+        crate::drive!(conn);
+        ldap.simple_bind(bind_dn, bind_pw).await?.success()?;
+        Ok(PooledLdap {
+            pool: self,
+            key,
+            ldap: Some(ldap),
+        })
+    }
+
+    fn take_idle(&self, key: &BindKey) -> Option<Ldap> {
+        let mut idle = self.idle.lock().expect("bind pool mutex");
+        let conns = idle.get_mut(key)?;
+        let mut found = None;
+        while let Some(mut conn) = conns.pop() {
+            if conn.since.elapsed() < self.ttl && !conn.ldap.is_closed() {
+                found = Some(conn.ldap);
+                break;
+            }
+        }
+        if conns.is_empty() {
+            idle.remove(key);
+        }
+        found
+    }
+
+    fn put(&self, key: BindKey, mut ldap: Ldap) {
+        // The handle could have been used to bind as someone else.
+        if ldap.is_closed() || ldap.bound_identity().as_deref() != Some(key.0.as_str()) {
+            return;
+        }
+        let mut idle = self.idle.lock().expect("bind pool mutex");
+        let conns = idle.entry(key).or_default();
+        conns.retain(|conn| conn.since.elapsed() < self.ttl);
+        if conns.len() < self.max_per_identity {
+            conns.push(IdleConn {
+                ldap,
+                since: Instant::now(),
+            });
+        }
+    }
+
+    /// Return the total number of idle connections in the pool.
+    pub fn idle_count(&self) -> usize {
+        self.idle
+            .lock()
+            .expect("bind pool mutex")
+            .values()
+            .map(Vec::len)
+            .sum()
+    }
+
+    /// Close all idle connections.
+    pub fn clear(&self) {
+        self.idle.lock().expect("bind pool mutex").clear();
+    }
+}
+
+/// Connection taken from a [`BindPool`](struct.BindPool.html).
+///
+/// The struct dereferences to the `Ldap` handle. When dropped, it returns the
+/// connection to the pool, unless the connection has been closed or rebound
+/// with a different DN.
+pub struct PooledLdap<'p> {
+    pool: &'p BindPool,
+    key: BindKey,
+    ldap: Option<Ldap>,
+}
+
+impl PooledLdap<'_> {
+    /// Take the handle out of the pool's management. The connection won't be
+    /// returned to the pool.
+    pub fn into_inner(mut self) -> Ldap {
+        self.ldap.take().expect("pooled handle")
+    }
+}
+
+impl Deref for PooledLdap<'_> {
+    type Target = Ldap;
+
+    fn deref(&self) -> &Ldap {
+        self.ldap.as_ref().expect("pooled handle")
+    }
+}
+
+impl DerefMut for PooledLdap<'_> {
+    fn deref_mut(&mut self) -> &mut Ldap {
+        self.ldap.as_mut().expect("pooled handle")
+    }
+}
+
+impl Drop for PooledLdap<'_> {
+    fn drop(&mut self) {
+        if let Some(ldap) = self.ldap.take() {
+            let key = std::mem::take(&mut self.key);
+            self.pool.put(key, ldap);
+        }
+    }
+}
+
+#[cfg(all(test, feature = "testkit"))]
+mod test {
+    use super::BindPool;
+    #[cfg(any(feature = "deadpool", feature = "bb8"))]
+    use super::{HealthCheck, LdapManager};
+    use crate::testkit::MockServer;
+
+    use std::time::Duration;
+
+    const ADMIN_DN: &str = "cn=admin,dc=example,dc=org";
+    const USER_DN: &str = "uid=test,dc=example,dc=org";
+
+    fn server() -> MockServer {
+        let server = MockServer::new();
+        server.add_entry("dc=example,dc=org", vec![("dc", vec!["example"])]);
+        server.add_entry(
+            ADMIN_DN,
+            vec![("cn", vec!["admin"]), ("userPassword", vec!["secret"])],
+        );
+        server.add_entry(
+            USER_DN,
+            vec![("uid", vec!["test"]), ("userPassword", vec!["password"])],
+        );
+        server
+    }
+
+    #[tokio::test]
+    async fn bind_pool_reuse() {
+        let server = server();
+        let pool = BindPool::new(&server.listen().await);
+        let ldap = pool.get(ADMIN_DN, "secret").await.unwrap();
+        drop(ldap);
+        assert_eq!(pool.idle_count(), 1);
+        let ldap = pool.get(ADMIN_DN, "secret").await.unwrap();
+        assert_eq!(pool.idle_count(), 0);
+        assert_eq!(ldap.bound_identity().as_deref(), Some(ADMIN_DN));
+        drop(ldap);
+        // A different password doesn't match the idle connection, and fails to bind.
+        assert!(pool.get(ADMIN_DN, "wrong").await.is_err());
+        assert_eq!(pool.idle_count(), 1);
+        assert_eq!(count(&server, 0), 2);
+    }
+
+    #[tokio::test]
+    async fn bind_pool_ttl() {
+        let server = server();
+        let pool = BindPool::new(&server.listen().await).with_ttl(Duration::from_millis(20));
+        drop(pool.get(ADMIN_DN, "secret").await.unwrap());
+        assert_eq!(pool.idle_count(), 1);
+        tokio::time::sleep(Duration::from_millis(40)).await;
+        drop(pool.get(ADMIN_DN, "secret").await.unwrap());
+        assert_eq!(pool.idle_count(), 1);
+        assert_eq!(count(&server, 0), 2);
+    }
+
+    #[tokio::test]
+    async fn bind_pool_cap() {
+        let server = server();
+        let pool = BindPool::new(&server.listen().await).with_max_per_identity(2);
+        let conns = vec![
+            pool.get(ADMIN_DN, "secret").await.unwrap(),
+            pool.get(ADMIN_DN, "secret").await.unwrap(),
+            pool.get(ADMIN_DN, "secret").await.unwrap(),
+            pool.get(USER_DN, "password").await.unwrap(),
+        ];
+        drop(conns);
+        assert_eq!(pool.idle_count(), 3);
+        pool.clear();
+        assert_eq!(pool.idle_count(), 0);
+    }
+
+    #[tokio::test]
+    async fn bind_pool_rebind() {
+        let server = server();
+        let pool = BindPool::new(&server.listen().await);
+        let mut ldap = pool.get(ADMIN_DN, "secret").await.unwrap();
+        ldap.simple_bind(USER_DN, "password")
+            .await
+            .unwrap()
+            .success()
+            .unwrap();
+        drop(ldap);
+        assert_eq!(pool.idle_count(), 0);
+        let mut ldap = pool.get(ADMIN_DN, "secret").await.unwrap();
+        ldap.simple_bind(ADMIN_DN, "secret")
+            .await
+            .unwrap()
+            .success()
+            .unwrap();
+        drop(ldap);
+        assert_eq!(pool.idle_count(), 1);
+        let ldap = pool.get(ADMIN_DN, "secret").await.unwrap().into_inner();
+        drop(ldap);
+        assert_eq!(pool.idle_count(), 0);
+    }
+
+    fn count(server: &MockServer, op: u64) -> usize {
+        server.received().iter().filter(|r| r.0 == op).count()
+    }
+
+    #[cfg(feature = "deadpool")]
+    #[tokio::test]
+    async fn deadpool_manager() {
+        let server = server();
+        let url = server.listen().await;
+        let manager = LdapManager::new(&url).with_bind(ADMIN_DN, "secret");
+        let pool: deadpool::managed::Pool<LdapManager> = deadpool::managed::Pool::builder(manager)
+            .max_size(1)
+            .build()
+            .unwrap();
+        let ldap = pool.get().await.unwrap();
+        assert_eq!(ldap.bound_identity().as_deref(), Some(ADMIN_DN));
+        drop(ldap);
+        // The idle connection is checked with a root DSE search, and reused.
+        let ldap = pool.get().await.unwrap();
+        assert_eq!(ldap.bound_identity().as_deref(), Some(ADMIN_DN));
+        assert_eq!((count(&server, 0), count(&server, 3)), (1, 1));
+        drop(ldap);
+        let manager = LdapManager::new(&url).with_bind(ADMIN_DN, "wrong");
+        let pool: deadpool::managed::Pool<LdapManager> =
+            deadpool::managed::Pool::builder(manager).build().unwrap();
+        assert!(pool.get().await.is_err());
+    }
+
+    #[cfg(feature = "bb8")]
+    #[tokio::test]
+    async fn bb8_manager() {
+        let server = server();
+        let url = server.listen().await;
+        let manager = LdapManager::new(&url)
+            .with_bind(ADMIN_DN, "secret")
+            .with_health_check(HealthCheck::WhoAmI);
+        let pool = bb8::Pool::builder()
+            .max_size(1)
+            .build(manager)
+            .await
+            .unwrap();
+        for _ in 0..2 {
+            let ldap = pool.get().await.unwrap();
+            assert_eq!(ldap.bound_identity().as_deref(), Some(ADMIN_DN));
+        }
+        assert_eq!(count(&server, 0), 1);
+        assert!(count(&server, 23) >= 1);
+        assert_eq!(count(&server, 3), 0);
+    }
+}
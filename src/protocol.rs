@@ -1,4 +1,5 @@
-use std::io;
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
 
 use crate::controls::{Control, RawControl};
 use crate::controls_impl::{build_tag, parse_controls};
@@ -7,27 +8,174 @@ use crate::RequestId;
 
 use lber::common::TagClass;
 use lber::parse::parse_uint;
-use lber::parse::Parser;
+use lber::parse::{
+    check_limits, parse_components, parse_header, parse_tag, parse_tag_ref, LimitError, Limits,
+};
 use lber::structure::{StructureTag, PL};
 use lber::structures::{ASNTag, Integer, Sequence, Tag};
 use lber::universal::Types;
 use lber::write;
-use lber::{Consumer, ConsumerState, IResult, Input, Move};
+use lber::{IResult, Needed};
 
 use bytes::{Buf, BytesMut};
+use tokio::sync::mpsc::error::TrySendError;
 use tokio::sync::{mpsc, oneshot};
 use tokio_util::codec::{Decoder, Encoder};
 
-pub struct LdapCodec;
+#[derive(Default)]
+pub struct LdapCodec {
+    /// Encoded length of the last decoded message.
+    pub(crate) last_in: usize,
+    /// Encoded length of the last encoded message.
+    pub(crate) last_out: usize,
+    pub(crate) dump: Option<BerDump>,
+    /// Largest accepted encoded length of an incoming message.
+    pub(crate) max_message_size: Option<usize>,
+    /// Deepest accepted nesting of constructed elements in an incoming message, if
+    /// different from the lber default.
+    pub(crate) max_depth: Option<usize>,
+    /// Largest accepted number of BER elements in an incoming message.
+    pub(crate) max_elements: Option<usize>,
+}
+
+// Upper bound for growing the read buffer in advance when the message size isn't limited.
+const MAX_RESERVE: usize = 16 * 1024 * 1024;
+
+/// Rendering of LDAP messages in the wire dump.
+///
+/// See [`LdapConnSettings::set_ber_dump()`](struct.LdapConnSettings.html#method.set_ber_dump).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BerDumpFormat {
+    /// Raw encoded bytes, in hex, sixteen to a line.
+    Hex,
+    /// Decoded structure of the message, as an indented tree of tags.
+    Pretty,
+}
+
+#[derive(Clone)]
+pub(crate) struct BerDump {
+    pub(crate) sink: Arc<Mutex<dyn Write + Send>>,
+    pub(crate) format: BerDumpFormat,
+}
+
+impl BerDump {
+    fn write(&self, dir: &str, raw: &[u8], tag: Option<&StructureTag>) {
+        let mut sink = match self.sink.lock() {
+            Ok(sink) => sink,
+            Err(_) => return,
+        };
+        if let Err(e) = self.write_msg(&mut *sink, dir, raw, tag) {
+            warn!("BER dump write error: {}", e);
+        }
+    }
+
+    fn write_msg(
+        &self,
+        sink: &mut dyn Write,
+        dir: &str,
+        raw: &[u8],
+        tag: Option<&StructureTag>,
+    ) -> io::Result<()> {
+        writeln!(sink, "{} {} bytes", dir, raw.len())?;
+        match (self.format, tag) {
+            (BerDumpFormat::Pretty, Some(tag)) => write!(sink, "{}", tag.pretty())?,
+            _ => {
+                for (n, line) in raw.chunks(16).enumerate() {
+                    write!(sink, "{:04x}:", n * 16)?;
+                    for b in line {
+                        write!(sink, " {:02x}", b)?;
+                    }
+                    writeln!(sink)?;
+                }
+            }
+        }
+        sink.flush()
+    }
+}
 
 pub(crate) type MaybeControls = Option<Vec<RawControl>>;
-pub(crate) type ItemSender = mpsc::UnboundedSender<(SearchItem, Vec<Control>)>;
 pub(crate) type ResultSender = oneshot::Sender<(Tag, Vec<Control>)>;
 
+pub(crate) type StreamItem = (SearchItem, Vec<Control>);
+
+// Channel for the replies to a streaming operation. A bounded channel makes the
+// connection loop wait for the consumer, instead of buffering everything.
+#[derive(Clone, Debug)]
+pub enum ItemSender {
+    Unbounded(mpsc::UnboundedSender<StreamItem>),
+    Bounded(mpsc::Sender<StreamItem>),
+}
+
+#[derive(Debug)]
+pub(crate) enum ItemReceiver {
+    Unbounded(mpsc::UnboundedReceiver<StreamItem>),
+    Bounded(mpsc::Receiver<StreamItem>),
+}
+
+pub(crate) fn item_channel(capacity: Option<usize>) -> (ItemSender, ItemReceiver) {
+    match capacity {
+        Some(capacity) => {
+            let (tx, rx) = mpsc::channel(capacity);
+            (ItemSender::Bounded(tx), ItemReceiver::Bounded(rx))
+        }
+        None => {
+            let (tx, rx) = mpsc::unbounded_channel();
+            (ItemSender::Unbounded(tx), ItemReceiver::Unbounded(rx))
+        }
+    }
+}
+
+// Extract the result code from a raw response, without parsing the rest of it.
+#[cfg(any(feature = "metrics", feature = "audit", feature = "observer"))]
+pub(crate) fn result_code(tag: &Tag) -> Option<u32> {
+    let t = match *tag {
+        Tag::StructureTag(ref t) => t,
+        _ => return None,
+    };
+    match t.payload {
+        PL::C(ref inner) => match inner.first().map(|rc| &rc.payload) {
+            Some(PL::P(ref rc)) => match parse_uint(rc.as_slice()) {
+                IResult::Done(_, rc) => Some(rc as u32),
+                _ => None,
+            },
+            _ => None,
+        },
+        PL::P(_) => None,
+    }
+}
+
+impl ItemSender {
+    // Hands the item back if the buffer is full, instead of waiting for room.
+    #[allow(clippy::result_large_err)]
+    pub(crate) fn try_send(&self, item: StreamItem) -> Result<(), TrySendError<StreamItem>> {
+        match self {
+            ItemSender::Unbounded(tx) => tx.send(item).map_err(|e| TrySendError::Closed(e.0)),
+            ItemSender::Bounded(tx) => tx.try_send(item),
+        }
+    }
+
+    // Waits until there's room in the buffer, or the receiver is gone.
+    pub(crate) async fn ready(&self) {
+        if let ItemSender::Bounded(tx) = self {
+            let _ = tx.reserve().await;
+        }
+    }
+}
+
+impl ItemReceiver {
+    pub(crate) async fn recv(&mut self) -> Option<StreamItem> {
+        match self {
+            ItemReceiver::Unbounded(rx) => rx.recv().await,
+            ItemReceiver::Bounded(rx) => rx.recv().await,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum LdapOp {
     Single,
     Search(ItemSender),
+    MultiExop(ItemSender),
     Abandon(RequestId),
     Unbind,
 }
@@ -37,27 +185,91 @@ impl Decoder for LdapCodec {
     type Error = io::Error;
 
     fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
-        let decoding_error = io::Error::new(io::ErrorKind::Other, "decoding error");
-        let mut parser = Parser::new();
-        let (amt, tag) = match *parser.handle(Input::Element(buf)) {
-            ConsumerState::Continue(_) => return Ok(None),
-            ConsumerState::Error(_e) => return Err(decoding_error),
-            ConsumerState::Done(amt, ref tag) => (amt, tag),
+        let decoding_error = io::Error::other("decoding error");
+        let too_large =
+            |max: usize| io::Error::new(io::ErrorKind::InvalidData, LimitError::Size(max));
+        if let Some(max) = self.max_message_size {
+            if let IResult::Done(_, hdr) = parse_header(buf) {
+                if hdr.total_len().is_some_and(|total| total > max) {
+                    return Err(too_large(max));
+                }
+            }
+        }
+        // Split the message envelope first, and parse its components separately. When
+        // the message is incomplete, the exact number of missing bytes is known, and the
+        // buffer can be grown at once.
+        let (amt, comps) = match parse_components(buf) {
+            IResult::Done(rest, (hdr, comps)) if hdr.id == Types::Sequence as u64 => {
+                (buf.len() - rest.len(), comps)
+            }
+            IResult::Incomplete(Needed::Size(n)) => {
+                // The size of a message in the indefinite length form isn't known
+                // in advance, so the limit is checked as it arrives.
+                if let Some(max) = self.max_message_size {
+                    if buf.len().saturating_add(n) > max {
+                        return Err(too_large(max));
+                    }
+                }
+                buf.reserve(n.min(self.max_message_size.unwrap_or(MAX_RESERVE)));
+                return Ok(None);
+            }
+            IResult::Incomplete(_) => return Ok(None),
+            _ => return Err(decoding_error),
         };
-        let amt = match amt {
-            Move::Await(_) => return Ok(None),
-            Move::Seek(_) => return Err(decoding_error),
-            Move::Consume(amt) => amt,
+        // The whole message is checked before parsing its components, whose recursion
+        // could otherwise exhaust the stack.
+        let defaults = Limits::default();
+        let limits = Limits {
+            max_depth: self.max_depth.unwrap_or(defaults.max_depth),
+            max_elements: self.max_elements.unwrap_or(defaults.max_elements),
+            max_size: self.max_message_size.unwrap_or(defaults.max_size),
         };
-        buf.advance(amt);
-        let tag = tag.clone();
-        let mut tags = match tag
-            .match_id(Types::Sequence as u64)
-            .and_then(|t| t.expect_constructed())
+        if let Err(e) = check_limits(&buf[..amt], &limits) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, e));
+        }
+        if comps.len() < 2 {
+            return Err(decoding_error);
+        }
+        // The message ID is read in place, the rest is turned into owned structures.
+        let msgid_tag = match parse_tag_ref(comps[0]) {
+            IResult::Done(_, tag) => tag,
+            _ => return Err(decoding_error),
+        };
+        let msgid = match msgid_tag
+            .clone()
+            .match_class(TagClass::Universal)
+            .and_then(|t| t.match_id(Types::Integer as u64))
+            .and_then(|t| t.expect_primitive())
+            .map(parse_uint)
         {
-            Some(tags) => tags,
-            None => return Err(decoding_error),
+            Some(IResult::Done(_, id)) => id as i32,
+            _ => return Err(decoding_error),
         };
+        let mut tags = Vec::with_capacity(comps.len() - 1);
+        for comp in &comps[1..] {
+            match parse_tag(comp) {
+                IResult::Done(_, tag) => tags.push(tag),
+                _ => return Err(decoding_error),
+            }
+        }
+        let trace = log_enabled!(log::Level::Trace);
+        if self.dump.is_some() || trace {
+            let mut payload = vec![msgid_tag.to_owned_tag()];
+            payload.extend(tags.iter().cloned());
+            let tag = StructureTag {
+                class: TagClass::Universal,
+                id: Types::Sequence as u64,
+                payload: PL::C(payload),
+            };
+            if let Some(ref dump) = self.dump {
+                dump.write("<<", &buf[..amt], Some(&tag));
+            }
+            if trace {
+                trace!("received message:\n{}", tag.pretty());
+            }
+        }
+        buf.advance(amt);
+        self.last_in = amt;
         let mut maybe_controls = tags.pop().expect("element");
         let has_controls = match maybe_controls {
             StructureTag {
@@ -75,8 +287,13 @@ impl Decoder for LdapCodec {
                 // Unsolicited Notification should be part of the ExtendedResponse sequence
                 // but AD puts it outside, where the optional controls belong. This confuses
                 // our parser, which doesn't expect the extra sequence element at the end
-                // and crashes. This match arm thus ignores the element.
+                // and crashes. This match arm thus moves the element where it should be,
+                // so that the notification can be recognized.
+                let oid = maybe_controls;
                 maybe_controls = tags.pop().expect("element");
+                if let PL::C(ref mut inner) = maybe_controls.payload {
+                    inner.push(oid);
+                }
                 false
             }
             _ => false,
@@ -90,18 +307,6 @@ impl Decoder for LdapCodec {
             Some(controls) => parse_controls(controls),
             None => vec![],
         };
-        let msgid = match parse_uint(
-            tags.pop()
-                .expect("element")
-                .match_class(TagClass::Universal)
-                .and_then(|t| t.match_id(Types::Integer as u64))
-                .and_then(|t| t.expect_primitive())
-                .expect("message id")
-                .as_slice(),
-        ) {
-            IResult::Done(_, id) => id as i32,
-            _ => return Err(decoding_error),
-        };
         Ok(Some((msgid, (Tag::StructureTag(protoop), controls))))
     }
 }
@@ -136,7 +341,236 @@ impl Encoder<(RequestId, Tag, MaybeControls)> for LdapCodec {
             })
             .into_structure()
         };
+        let before = into.len();
+        let trace = log_enabled!(log::Level::Trace);
+        let dump_tag = match self.dump {
+            Some(ref dump) if dump.format == BerDumpFormat::Pretty => Some(outstruct.clone()),
+            _ if trace => Some(outstruct.clone()),
+            _ => None,
+        };
         write::encode_into(into, outstruct)?;
+        self.last_out = into.len() - before;
+        if let Some(ref dump) = self.dump {
+            dump.write(">>", &into[before..], dump_tag.as_ref());
+        }
+        if let (true, Some(tag)) = (trace, dump_tag) {
+            trace!("sent message:\n{}", tag.pretty());
+        }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::LdapCodec;
+
+    use bytes::BytesMut;
+    use lber::common::TagClass;
+    use lber::parse::{LimitError, DEFAULT_MAX_DEPTH};
+    use lber::structures::{ASNTag, Enumerated, Integer, Null, OctetString, Sequence, Tag};
+    use lber::write;
+    use tokio_util::codec::Decoder;
+
+    #[test]
+    fn ad_notice_of_disconnection() {
+        let msg = Tag::Sequence(Sequence {
+            inner: vec![
+                Tag::Integer(Integer {
+                    inner: 0,
+                    ..Default::default()
+                }),
+                Tag::Sequence(Sequence {
+                    id: 24,
+                    class: TagClass::Application,
+                    inner: vec![
+                        Tag::Enumerated(Enumerated {
+                            inner: 52,
+                            ..Default::default()
+                        }),
+                        Tag::OctetString(OctetString {
+                            inner: vec![],
+                            ..Default::default()
+                        }),
+                        Tag::OctetString(OctetString {
+                            inner: Vec::from("going down"),
+                            ..Default::default()
+                        }),
+                    ],
+                }),
+                Tag::OctetString(OctetString {
+                    id: 10,
+                    class: TagClass::Context,
+                    inner: Vec::from("1.3.6.1.4.1.1466.20036"),
+                }),
+            ],
+            ..Default::default()
+        })
+        .into_structure();
+        let mut buf = BytesMut::new();
+        write::encode_into(&mut buf, msg).expect("encoded");
+        let (id, (tag, controls)) = LdapCodec::default()
+            .decode(&mut buf)
+            .expect("decoded")
+            .expect("item");
+        assert_eq!(id, 0);
+        assert!(controls.is_empty());
+        let protoop = match tag {
+            Tag::StructureTag(protoop) => protoop,
+            _ => panic!("not a structure tag"),
+        };
+        let elems = protoop.expect_constructed().expect("elements");
+        assert_eq!(elems.len(), 4);
+        assert_eq!(elems[3].id, 10);
+    }
+
+    #[test]
+    fn incremental_decode() {
+        let msg = Tag::Sequence(Sequence {
+            inner: vec![
+                Tag::Integer(Integer {
+                    inner: 7,
+                    ..Default::default()
+                }),
+                Tag::OctetString(OctetString {
+                    id: 10,
+                    class: TagClass::Application,
+                    inner: Vec::from("cn=gone"),
+                }),
+            ],
+            ..Default::default()
+        })
+        .into_structure();
+        let mut encoded = BytesMut::new();
+        write::encode_into(&mut encoded, msg).expect("encoded");
+        let mut codec = LdapCodec::default();
+        let mut buf = BytesMut::new();
+        for &b in encoded.iter() {
+            assert!(codec.decode(&mut buf).expect("decoded").is_none());
+            buf.extend_from_slice(&[b]);
+        }
+        let (id, (tag, controls)) = codec.decode(&mut buf).expect("decoded").expect("item");
+        assert_eq!((id, controls.len(), buf.len()), (7, 0, 0));
+        match tag {
+            Tag::StructureTag(protoop) => assert_eq!(protoop.id, 10),
+            _ => panic!("not a structure tag"),
+        }
+    }
+
+    #[test]
+    fn message_limits() {
+        let msg = Tag::Sequence(Sequence {
+            inner: vec![
+                Tag::Integer(Integer {
+                    inner: 1,
+                    ..Default::default()
+                }),
+                Tag::OctetString(OctetString {
+                    inner: vec![0; 200],
+                    ..Default::default()
+                }),
+            ],
+            ..Default::default()
+        })
+        .into_structure();
+        let mut buf = BytesMut::new();
+        write::encode_into(&mut buf, msg).expect("encoded");
+        let mut codec = LdapCodec {
+            max_message_size: Some(100),
+            ..Default::default()
+        };
+        // Only the header is needed to reject the message.
+        let mut head = BytesMut::from(&buf[..4]);
+        let err = codec.decode(&mut head).unwrap_err();
+        assert_eq!(
+            err.get_ref().and_then(|e| e.downcast_ref::<LimitError>()),
+            Some(&LimitError::Size(100))
+        );
+        // SEQUENCE { INTEGER 1, SEQUENCE { SEQUENCE { NULL } } }
+        let nested = [
+            0x30, 0x09, 0x02, 0x01, 0x01, 0x30, 0x04, 0x30, 0x02, 0x05, 0x00,
+        ];
+        let decode = |depth, elements| {
+            let mut codec = LdapCodec {
+                max_depth: Some(depth),
+                max_elements: Some(elements),
+                ..Default::default()
+            };
+            codec
+                .decode(&mut BytesMut::from(&nested[..]))
+                .map(|_| ())
+                .map_err(|e| *e.into_inner().unwrap().downcast::<LimitError>().unwrap())
+        };
+        assert_eq!(decode(3, 5), Ok(()));
+        assert_eq!(decode(2, 5), Err(LimitError::Depth(2)));
+        assert_eq!(decode(3, 4), Err(LimitError::Elements(4)));
+        // Without explicit limits, the default depth applies.
+        let mut deep = Tag::Null(Null::default());
+        for _ in 0..DEFAULT_MAX_DEPTH {
+            deep = Tag::Sequence(Sequence {
+                inner: vec![deep],
+                ..Default::default()
+            });
+        }
+        let msg = Tag::Sequence(Sequence {
+            inner: vec![
+                Tag::Integer(Integer {
+                    inner: 1,
+                    ..Default::default()
+                }),
+                deep,
+            ],
+            ..Default::default()
+        })
+        .into_structure();
+        let mut buf = BytesMut::new();
+        write::encode_into(&mut buf, msg).expect("encoded");
+        let err = LdapCodec::default().decode(&mut buf).unwrap_err();
+        assert_eq!(
+            err.get_ref().and_then(|e| e.downcast_ref::<LimitError>()),
+            Some(&LimitError::Depth(DEFAULT_MAX_DEPTH))
+        );
+    }
+
+    #[test]
+    fn indefinite_length() {
+        let msg = Tag::Sequence(Sequence {
+            inner: vec![
+                Tag::Integer(Integer {
+                    inner: 3,
+                    ..Default::default()
+                }),
+                Tag::Sequence(Sequence {
+                    id: 7,
+                    class: TagClass::Application,
+                    inner: vec![Tag::OctetString(OctetString {
+                        inner: vec![0; 200],
+                        ..Default::default()
+                    })],
+                }),
+            ],
+            ..Default::default()
+        })
+        .into_structure();
+        let mut encoded = BytesMut::new();
+        write::encode_indefinite_into(&mut encoded, msg).expect("encoded");
+        let mut codec = LdapCodec::default();
+        let mut buf = BytesMut::new();
+        for &b in encoded.iter() {
+            assert!(codec.decode(&mut buf).expect("decoded").is_none());
+            buf.extend_from_slice(&[b]);
+        }
+        let (id, (tag, _)) = codec.decode(&mut buf).expect("decoded").expect("item");
+        assert_eq!((id, buf.len()), (3, 0));
+        match tag {
+            Tag::StructureTag(protoop) => assert_eq!(protoop.id, 7),
+            _ => panic!("not a structure tag"),
+        }
+        // Without the length, the size limit is enforced while receiving.
+        let mut codec = LdapCodec {
+            max_message_size: Some(100),
+            ..Default::default()
+        };
+        let mut head = BytesMut::from(&encoded[..150]);
+        assert!(codec.decode(&mut head).is_err());
+    }
+}
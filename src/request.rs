@@ -0,0 +1,615 @@
+//! Whole-operation request structures.
+//!
+//! The methods of [`Ldap`](../struct.Ldap.html) take the parameters of an operation
+//! as arguments and send the request immediately. Sometimes it's more useful to have
+//! the request as a value: it can be built in one place and submitted in another,
+//! logged, compared in a test, or relayed between connections in a proxy. The structures
+//! in this module hold all parameters of an operation in public fields. A request
+//! can be encoded as BER with [`to_ber()`](trait.Request.html#method.to_ber), and
+//! submitted with [`Ldap::send()`](../struct.Ldap.html#method.send), which returns the
+//! same type as the corresponding method. Request controls, timeouts and cancellation
+//! are attached to the handle as usual.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! use ldap3::request::{ModifyRequest, Request};
+//! use ldap3::{LdapConnAsync, Mod};
+//! # use ldap3::result::Result;
+//! use maplit::hashset;
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<()> {
+//! let (conn, mut ldap) = LdapConnAsync::new("ldap://localhost:2389").await?;
+//! ldap3::drive!(conn);
+//! let req = ModifyRequest::new(
+//!     "uid=jdoe,ou=People,dc=example,dc=org",
+//!     vec![Mod::Replace("mail", hashset! {"jdoe@example.org"})],
+//! );
+//! println!("{:?}", req);
+//! ldap.send(req).await?.success()?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::HashSet;
+use std::fmt;
+use std::hash::Hash;
+
+use crate::exop::Exop;
+use crate::exop_impl::construct_exop;
+use crate::filter::parse as parse_filter;
+use crate::ldap::{Ldap, Mod};
+use crate::protocol::LdapOp;
+use crate::result::{CompareResult, ExopResult, LdapError, LdapResult, Result, SearchResult};
+use crate::search::{
+    with_binary_option, Scope, SearchOptions, ALL_OPERATIONAL_ATTRS, ALL_USER_ATTRS,
+};
+use crate::RequestId;
+
+use async_trait::async_trait;
+use bytes::BytesMut;
+use lber::common::TagClass;
+use lber::structures::{ASNTag, Boolean, Enumerated, Integer, OctetString, Sequence, Set, Tag};
+use lber::write;
+
+/// Common interface of operation requests.
+#[async_trait]
+pub trait Request: Send {
+    /// Type returned by [`Ldap::send()`](../struct.Ldap.html#method.send) for this request.
+    type Output;
+
+    /// Construct the protocol operation element of the request.
+    fn to_tag(&self) -> Result<Tag>;
+
+    /// Encode the request as a complete LDAPMessage with the message id `msgid`,
+    /// without controls.
+    fn to_ber(&self, msgid: RequestId) -> Result<Vec<u8>> {
+        let msg = Tag::Sequence(Sequence {
+            inner: vec![
+                Tag::Integer(Integer {
+                    inner: msgid as i64,
+                    ..Default::default()
+                }),
+                self.to_tag()?,
+            ],
+            ..Default::default()
+        });
+        let mut buf = BytesMut::new();
+        write::encode_into(&mut buf, msg.into_structure())?;
+        Ok(buf.to_vec())
+    }
+
+    #[doc(hidden)]
+    async fn dispatch(self, ldap: &mut Ldap) -> Result<Self::Output>;
+}
+
+fn octet_string(inner: &[u8]) -> Tag {
+    Tag::OctetString(OctetString {
+        inner: Vec::from(inner),
+        ..Default::default()
+    })
+}
+
+fn value_set(vals: &HashSet<Vec<u8>>) -> Tag {
+    Tag::Set(Set {
+        inner: vals.iter().map(|v| octet_string(v)).collect(),
+        ..Default::default()
+    })
+}
+
+/// Type of an LDAP operation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum OpKind {
+    Bind,
+    Unbind,
+    Search,
+    Modify,
+    Add,
+    Delete,
+    ModifyDn,
+    Compare,
+    Abandon,
+    Extended,
+}
+
+impl OpKind {
+    // Determine the operation from the protocol op element of a request.
+    pub(crate) fn from_tag(tag: &Tag) -> Option<OpKind> {
+        let id = match *tag {
+            Tag::Sequence(ref t) => t.id,
+            Tag::OctetString(ref t) => t.id,
+            Tag::Integer(ref t) => t.id,
+            Tag::Null(ref t) => t.id,
+            Tag::StructureTag(ref t) => t.id,
+            _ => return None,
+        };
+        Some(match id {
+            0 => OpKind::Bind,
+            2 => OpKind::Unbind,
+            3 => OpKind::Search,
+            6 => OpKind::Modify,
+            8 => OpKind::Add,
+            10 => OpKind::Delete,
+            12 => OpKind::ModifyDn,
+            14 => OpKind::Compare,
+            16 => OpKind::Abandon,
+            23 => OpKind::Extended,
+            _ => return None,
+        })
+    }
+
+    /// Name of the operation, as used in [metrics](../metrics/index.html), audit records
+    /// and error context: `"bind"`, `"unbind"`, `"search"`, `"modify"`, `"add"`, `"delete"`,
+    /// `"modifydn"`, `"compare"`, `"abandon"`, or `"extended"`.
+    pub fn name(self) -> &'static str {
+        match self {
+            OpKind::Bind => "bind",
+            OpKind::Unbind => "unbind",
+            OpKind::Search => "search",
+            OpKind::Modify => "modify",
+            OpKind::Add => "add",
+            OpKind::Delete => "delete",
+            OpKind::ModifyDn => "modifydn",
+            OpKind::Compare => "compare",
+            OpKind::Abandon => "abandon",
+            OpKind::Extended => "extended",
+        }
+    }
+
+    /// Whether the operation changes the directory: Add, Delete, Modify, or ModifyDN.
+    pub fn is_update(self) -> bool {
+        matches!(
+            self,
+            OpKind::Add | OpKind::Delete | OpKind::Modify | OpKind::ModifyDn
+        )
+    }
+}
+
+impl fmt::Display for OpKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Search request.
+#[derive(Clone, Debug)]
+pub struct SearchRequest {
+    /// Base DN.
+    pub base: String,
+    /// Search scope.
+    pub scope: Scope,
+    /// Filter, in the string form.
+    pub filter: String,
+    /// Attributes to return.
+    pub attrs: Vec<String>,
+    /// Less frequently used parameters.
+    pub opts: SearchOptions,
+}
+
+impl SearchRequest {
+    /// Create a request with the parameters of [`Ldap::search()`](../struct.Ldap.html#method.search)
+    /// and the default [`SearchOptions`](../struct.SearchOptions.html).
+    pub fn new<S: AsRef<str>>(base: &str, scope: Scope, filter: &str, attrs: Vec<S>) -> Self {
+        SearchRequest {
+            base: base.to_owned(),
+            scope,
+            filter: filter.to_owned(),
+            attrs: attrs.iter().map(|a| a.as_ref().to_owned()).collect(),
+            opts: SearchOptions::new(),
+        }
+    }
+
+    /// Set the search options.
+    pub fn opts(mut self, opts: SearchOptions) -> Self {
+        self.opts = opts;
+        self
+    }
+}
+
+#[async_trait]
+impl Request for SearchRequest {
+    type Output = SearchResult;
+
+    fn to_tag(&self) -> Result<Tag> {
+        let opts = &self.opts;
+        let mut attrs = self.attrs.clone();
+        if opts.operational && !attrs.iter().any(|a| a == ALL_OPERATIONAL_ATTRS) {
+            if attrs.is_empty() {
+                attrs.push(String::from(ALL_USER_ATTRS));
+            }
+            attrs.push(String::from(ALL_OPERATIONAL_ATTRS));
+        }
+        if opts.binary_transfer {
+            attrs = attrs.into_iter().map(with_binary_option).collect();
+        }
+        Ok(Tag::Sequence(Sequence {
+            id: 3,
+            class: TagClass::Application,
+            inner: vec![
+                octet_string(self.base.as_bytes()),
+                Tag::Enumerated(Enumerated {
+                    inner: self.scope as i64,
+                    ..Default::default()
+                }),
+                Tag::Enumerated(Enumerated {
+                    inner: opts.deref as i64,
+                    ..Default::default()
+                }),
+                Tag::Integer(Integer {
+                    inner: opts.sizelimit as i64,
+                    ..Default::default()
+                }),
+                Tag::Integer(Integer {
+                    inner: opts.timelimit as i64,
+                    ..Default::default()
+                }),
+                Tag::Boolean(Boolean {
+                    inner: opts.typesonly,
+                    ..Default::default()
+                }),
+                parse_filter(&self.filter).map_err(|_| LdapError::FilterParsing)?,
+                Tag::Sequence(Sequence {
+                    inner: attrs.iter().map(|a| octet_string(a.as_bytes())).collect(),
+                    ..Default::default()
+                }),
+            ],
+        }))
+    }
+
+    async fn dispatch(self, ldap: &mut Ldap) -> Result<SearchResult> {
+        ldap.with_search_options(self.opts)
+            .search(&self.base, self.scope, &self.filter, self.attrs)
+            .await
+    }
+}
+
+/// Add request.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AddRequest {
+    /// DN of the new entry.
+    pub dn: String,
+    /// Attributes and their values.
+    pub attrs: Vec<(Vec<u8>, HashSet<Vec<u8>>)>,
+}
+
+impl AddRequest {
+    /// Create a request with the parameters of [`Ldap::add()`](../struct.Ldap.html#method.add).
+    pub fn new<S: AsRef<[u8]> + Eq + Hash>(dn: &str, attrs: Vec<(S, HashSet<S>)>) -> Self {
+        AddRequest {
+            dn: dn.to_owned(),
+            attrs: attrs
+                .into_iter()
+                .map(|(name, vals)| {
+                    (
+                        Vec::from(name.as_ref()),
+                        vals.iter().map(|v| Vec::from(v.as_ref())).collect(),
+                    )
+                })
+                .collect(),
+        }
+    }
+}
+
+#[async_trait]
+impl Request for AddRequest {
+    type Output = LdapResult;
+
+    fn to_tag(&self) -> Result<Tag> {
+        if self.attrs.iter().any(|(_, vals)| vals.is_empty()) {
+            return Err(LdapError::AddNoValues);
+        }
+        Ok(Tag::Sequence(Sequence {
+            id: 8,
+            class: TagClass::Application,
+            inner: vec![
+                octet_string(self.dn.as_bytes()),
+                Tag::Sequence(Sequence {
+                    inner: self
+                        .attrs
+                        .iter()
+                        .map(|(name, vals)| {
+                            Tag::Sequence(Sequence {
+                                inner: vec![octet_string(name), value_set(vals)],
+                                ..Default::default()
+                            })
+                        })
+                        .collect(),
+                    ..Default::default()
+                }),
+            ],
+        }))
+    }
+
+    async fn dispatch(self, ldap: &mut Ldap) -> Result<LdapResult> {
+        let req = self.to_tag()?;
+        Ok(ldap.op_call(LdapOp::Single, req).await?.0)
+    }
+}
+
+/// Modify request.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ModifyRequest {
+    /// DN of the entry.
+    pub dn: String,
+    /// Modifications, applied in order.
+    pub mods: Vec<Mod<Vec<u8>>>,
+}
+
+impl ModifyRequest {
+    /// Create a request with the parameters of [`Ldap::modify()`](../struct.Ldap.html#method.modify).
+    pub fn new<S: AsRef<[u8]> + Eq + Hash>(dn: &str, mods: Vec<Mod<S>>) -> Self {
+        let owned = |set: HashSet<S>| set.iter().map(|v| Vec::from(v.as_ref())).collect();
+        ModifyRequest {
+            dn: dn.to_owned(),
+            mods: mods
+                .into_iter()
+                .map(|m| match m {
+                    Mod::Add(attr, set) => Mod::Add(Vec::from(attr.as_ref()), owned(set)),
+                    Mod::Delete(attr, set) => Mod::Delete(Vec::from(attr.as_ref()), owned(set)),
+                    Mod::Replace(attr, set) => Mod::Replace(Vec::from(attr.as_ref()), owned(set)),
+                    Mod::Increment(attr, val) => {
+                        Mod::Increment(Vec::from(attr.as_ref()), Vec::from(val.as_ref()))
+                    }
+                })
+                .collect(),
+        }
+    }
+}
+
+#[async_trait]
+impl Request for ModifyRequest {
+    type Output = LdapResult;
+
+    fn to_tag(&self) -> Result<Tag> {
+        let mut changes = Vec::with_capacity(self.mods.len());
+        for m in &self.mods {
+            let incr;
+            let (num, attr, set) = match *m {
+                Mod::Add(ref attr, ref set) => {
+                    if set.is_empty() {
+                        return Err(LdapError::AddNoValues);
+                    }
+                    (0, attr, set)
+                }
+                Mod::Delete(ref attr, ref set) => (1, attr, set),
+                Mod::Replace(ref attr, ref set) => (2, attr, set),
+                Mod::Increment(ref attr, ref val) => {
+                    incr = HashSet::from([val.clone()]);
+                    (3, attr, &incr)
+                }
+            };
+            changes.push(Tag::Sequence(Sequence {
+                inner: vec![
+                    Tag::Enumerated(Enumerated {
+                        inner: num,
+                        ..Default::default()
+                    }),
+                    Tag::Sequence(Sequence {
+                        inner: vec![octet_string(attr), value_set(set)],
+                        ..Default::default()
+                    }),
+                ],
+                ..Default::default()
+            }));
+        }
+        Ok(Tag::Sequence(Sequence {
+            id: 6,
+            class: TagClass::Application,
+            inner: vec![
+                octet_string(self.dn.as_bytes()),
+                Tag::Sequence(Sequence {
+                    inner: changes,
+                    ..Default::default()
+                }),
+            ],
+        }))
+    }
+
+    async fn dispatch(self, ldap: &mut Ldap) -> Result<LdapResult> {
+        let req = self.to_tag()?;
+        Ok(ldap.op_call(LdapOp::Single, req).await?.0)
+    }
+}
+
+/// Delete request.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DeleteRequest {
+    /// DN of the entry.
+    pub dn: String,
+}
+
+impl DeleteRequest {
+    /// Create a request for deleting the entry named by `dn`.
+    pub fn new(dn: &str) -> Self {
+        DeleteRequest { dn: dn.to_owned() }
+    }
+}
+
+#[async_trait]
+impl Request for DeleteRequest {
+    type Output = LdapResult;
+
+    fn to_tag(&self) -> Result<Tag> {
+        Ok(Tag::OctetString(OctetString {
+            id: 10,
+            class: TagClass::Application,
+            inner: Vec::from(self.dn.as_bytes()),
+        }))
+    }
+
+    async fn dispatch(self, ldap: &mut Ldap) -> Result<LdapResult> {
+        let req = self.to_tag()?;
+        Ok(ldap.op_call(LdapOp::Single, req).await?.0)
+    }
+}
+
+/// ModifyDN request.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ModifyDnRequest {
+    /// DN of the entry.
+    pub dn: String,
+    /// New RDN.
+    pub rdn: String,
+    /// Whether to delete the old RDN value from the entry.
+    pub delete_old: bool,
+    /// New superior entry, if the entry is moved.
+    pub new_sup: Option<String>,
+}
+
+impl ModifyDnRequest {
+    /// Create a request with the parameters of
+    /// [`Ldap::modifydn()`](../struct.Ldap.html#method.modifydn).
+    pub fn new(dn: &str, rdn: &str, delete_old: bool, new_sup: Option<&str>) -> Self {
+        ModifyDnRequest {
+            dn: dn.to_owned(),
+            rdn: rdn.to_owned(),
+            delete_old,
+            new_sup: new_sup.map(String::from),
+        }
+    }
+}
+
+#[async_trait]
+impl Request for ModifyDnRequest {
+    type Output = LdapResult;
+
+    fn to_tag(&self) -> Result<Tag> {
+        let mut params = vec![
+            octet_string(self.dn.as_bytes()),
+            octet_string(self.rdn.as_bytes()),
+            Tag::Boolean(Boolean {
+                inner: self.delete_old,
+                ..Default::default()
+            }),
+        ];
+        if let Some(ref new_sup) = self.new_sup {
+            params.push(Tag::OctetString(OctetString {
+                id: 0,
+                class: TagClass::Context,
+                inner: Vec::from(new_sup.as_bytes()),
+            }));
+        }
+        Ok(Tag::Sequence(Sequence {
+            id: 12,
+            class: TagClass::Application,
+            inner: params,
+        }))
+    }
+
+    async fn dispatch(self, ldap: &mut Ldap) -> Result<LdapResult> {
+        let req = self.to_tag()?;
+        Ok(ldap.op_call(LdapOp::Single, req).await?.0)
+    }
+}
+
+/// Compare request.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CompareRequest {
+    /// DN of the entry.
+    pub dn: String,
+    /// Attribute to compare.
+    pub attr: String,
+    /// Asserted value.
+    pub val: Vec<u8>,
+}
+
+impl CompareRequest {
+    /// Create a request with the parameters of
+    /// [`Ldap::compare()`](../struct.Ldap.html#method.compare).
+    pub fn new<B: AsRef<[u8]>>(dn: &str, attr: &str, val: B) -> Self {
+        CompareRequest {
+            dn: dn.to_owned(),
+            attr: attr.to_owned(),
+            val: Vec::from(val.as_ref()),
+        }
+    }
+}
+
+#[async_trait]
+impl Request for CompareRequest {
+    type Output = CompareResult;
+
+    fn to_tag(&self) -> Result<Tag> {
+        Ok(Tag::Sequence(Sequence {
+            id: 14,
+            class: TagClass::Application,
+            inner: vec![
+                octet_string(self.dn.as_bytes()),
+                Tag::Sequence(Sequence {
+                    inner: vec![octet_string(self.attr.as_bytes()), octet_string(&self.val)],
+                    ..Default::default()
+                }),
+            ],
+        }))
+    }
+
+    async fn dispatch(self, ldap: &mut Ldap) -> Result<CompareResult> {
+        let req = self.to_tag()?;
+        Ok(CompareResult(ldap.op_call(LdapOp::Single, req).await?.0))
+    }
+}
+
+/// Extended request.
+#[derive(Clone, Debug)]
+pub struct ExtendedRequest {
+    /// The extended operation.
+    pub exop: Exop,
+}
+
+impl ExtendedRequest {
+    /// Create a request with the parameters of
+    /// [`Ldap::extended()`](../struct.Ldap.html#method.extended).
+    pub fn new<E: Into<Exop>>(exop: E) -> Self {
+        ExtendedRequest { exop: exop.into() }
+    }
+}
+
+#[async_trait]
+impl Request for ExtendedRequest {
+    type Output = ExopResult;
+
+    fn to_tag(&self) -> Result<Tag> {
+        Ok(Tag::Sequence(Sequence {
+            id: 23,
+            class: TagClass::Application,
+            inner: construct_exop(self.exop.clone()),
+        }))
+    }
+
+    async fn dispatch(self, ldap: &mut Ldap) -> Result<ExopResult> {
+        let req = self.to_tag()?;
+        ldap.op_call(LdapOp::Single, req)
+            .await
+            .map(|et| ExopResult(et.1, et.0))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{AddRequest, DeleteRequest, ModifyRequest, Request};
+    use crate::ldap::Mod;
+    use crate::result::LdapError;
+
+    use maplit::hashset;
+
+    #[test]
+    fn encode_requests() {
+        assert_eq!(
+            DeleteRequest::new("o=x").to_ber(2).unwrap(),
+            b"\x30\x08\x02\x01\x02\x4a\x03o=x"
+        );
+        let add = AddRequest::new("o=x", vec![("o", hashset! {})]);
+        assert!(matches!(add.to_ber(1), Err(LdapError::AddNoValues)));
+        let modify = ModifyRequest::new("o=x", vec![Mod::Increment("n", "1")]);
+        assert_eq!(
+            modify.mods,
+            vec![Mod::Increment(b"n".to_vec(), b"1".to_vec())]
+        );
+        assert_eq!(
+            modify.to_ber(3).unwrap(),
+            &b"\x30\x1b\x02\x01\x03\x66\x16\x04\x03o=x\x30\x0f\x30\x0d\x0a\x01\x03\
+               \x30\x08\x04\x01n\x31\x03\x04\x011"[..]
+        );
+    }
+}
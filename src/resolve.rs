@@ -0,0 +1,179 @@
+//! Host name resolution for TCP connections.
+//!
+//! Before connecting, the host part of an __ldap__ or __ldaps__ URL is resolved to a list
+//! of addresses by a [`Resolver`](trait.Resolver.html). A custom resolver can be installed
+//! with [`LdapConnSettings::set_resolver()`](../struct.LdapConnSettings.html#method.set_resolver).
+//! Without it, a connection uses [`SystemResolver`](struct.SystemResolver.html), which relies
+//! on the platform's resolver library, or, with the __hickory__ feature, a
+//! [`HickoryResolver`](struct.HickoryResolver.html) configured from the system settings.
+//!
+//! `HickoryResolver` also implements [`SrvResolver`](../discover/trait.SrvResolver.html),
+//! and is used by default for the SRV lookups in the [`discover`](../discover/index.html)
+//! module when both features are enabled.
+
+use std::net::SocketAddr;
+
+#[cfg(all(feature = "hickory", feature = "discover"))]
+use crate::discover::{SrvRecord, SrvResolver};
+#[cfg(feature = "hickory")]
+use crate::result::LdapError;
+use crate::result::Result;
+
+use async_trait::async_trait;
+#[cfg(all(feature = "hickory", feature = "discover"))]
+use hickory_resolver::error::ResolveErrorKind;
+#[cfg(feature = "hickory")]
+use hickory_resolver::TokioAsyncResolver;
+
+/// Resolver of host names to socket addresses.
+#[async_trait]
+pub trait Resolver: Send + Sync {
+    /// Return the addresses of `host`, with `port` as the port. The host is never an IP
+    /// address literal, since those are used directly.
+    async fn lookup_host(&self, host: &str, port: u16) -> Result<Vec<SocketAddr>>;
+}
+
+/// Resolver using the platform's resolver library, through `tokio::net::lookup_host()`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemResolver;
+
+#[async_trait]
+impl Resolver for SystemResolver {
+    async fn lookup_host(&self, host: &str, port: u16) -> Result<Vec<SocketAddr>> {
+        Ok(tokio::net::lookup_host((host, port)).await?.collect())
+    }
+}
+
+/// Resolver backed by `hickory-resolver`. __*__
+///
+/// Available with the __hickory__ feature.
+#[cfg(feature = "hickory")]
+#[derive(Clone)]
+pub struct HickoryResolver {
+    inner: TokioAsyncResolver,
+}
+
+#[cfg(feature = "hickory")]
+impl HickoryResolver {
+    /// Create a resolver with the system configuration, which is `/etc/resolv.conf`
+    /// on Unix-like systems, and the registry on Windows.
+    pub fn from_system_conf() -> Result<Self> {
+        let inner = TokioAsyncResolver::tokio_from_system_conf()
+            .map_err(|e| LdapError::Resolver(e.to_string()))?;
+        Ok(HickoryResolver { inner })
+    }
+
+    /// Wrap an already constructed resolver.
+    pub fn new(inner: TokioAsyncResolver) -> Self {
+        HickoryResolver { inner }
+    }
+}
+
+#[cfg(feature = "hickory")]
+#[async_trait]
+impl Resolver for HickoryResolver {
+    async fn lookup_host(&self, host: &str, port: u16) -> Result<Vec<SocketAddr>> {
+        let ips = self
+            .inner
+            .lookup_ip(host)
+            .await
+            .map_err(|e| LdapError::Resolver(e.to_string()))?;
+        Ok(ips.iter().map(|ip| SocketAddr::new(ip, port)).collect())
+    }
+}
+
+#[cfg(all(feature = "hickory", feature = "discover"))]
+#[async_trait]
+impl SrvResolver for HickoryResolver {
+    async fn lookup_srv(&self, name: &str) -> Result<Vec<SrvRecord>> {
+        let srvs = match self.inner.srv_lookup(name).await {
+            Ok(srvs) => srvs,
+            Err(e) => match e.kind() {
+                ResolveErrorKind::NoRecordsFound { .. } => return Ok(vec![]),
+                _ => return Err(LdapError::SrvLookup(e.to_string())),
+            },
+        };
+        Ok(srvs
+            .iter()
+            .map(|srv| SrvRecord {
+                priority: srv.priority(),
+                weight: srv.weight(),
+                port: srv.port(),
+                target: srv.target().to_utf8().trim_end_matches('.').to_owned(),
+            })
+            .collect())
+    }
+}
+
+#[cfg(feature = "hickory")]
+pub(crate) fn default_resolver() -> Result<Box<dyn Resolver>> {
+    Ok(Box::new(HickoryResolver::from_system_conf()?))
+}
+
+#[cfg(not(feature = "hickory"))]
+pub(crate) fn default_resolver() -> Result<Box<dyn Resolver>> {
+    Ok(Box::new(SystemResolver))
+}
+
+#[cfg(all(test, feature = "testkit"))]
+mod test {
+    use super::Resolver;
+    use crate::result::{LdapError, Result};
+    use crate::testkit::MockServer;
+    use crate::{LdapConnAsync, LdapConnSettings, Scope};
+
+    use std::net::{Ipv4Addr, SocketAddr};
+    use std::sync::{Arc, Mutex};
+
+    use async_trait::async_trait;
+
+    // Resolves a single name to the loopback address, and records the lookups.
+    #[derive(Default)]
+    struct StaticResolver(Mutex<Vec<(String, u16)>>);
+
+    #[async_trait]
+    impl Resolver for StaticResolver {
+        async fn lookup_host(&self, host: &str, port: u16) -> Result<Vec<SocketAddr>> {
+            self.0.lock().unwrap().push((host.to_owned(), port));
+            if host != "ldap.example.test" {
+                return Err(LdapError::Resolver(format!("unknown host: {}", host)));
+            }
+            Ok(vec![SocketAddr::from((Ipv4Addr::LOCALHOST, port))])
+        }
+    }
+
+    #[tokio::test]
+    async fn custom_resolver() {
+        let server = MockServer::new();
+        server.add_entry("dc=example,dc=org", vec![("dc", vec!["example"])]);
+        let url = server.listen().await;
+        let port = url.rsplit(':').next().unwrap().parse::<u16>().unwrap();
+        let resolver = Arc::new(StaticResolver::default());
+        let settings = LdapConnSettings::new().set_resolver(resolver.clone());
+        let url = format!("ldap://ldap.example.test:{}", port);
+        let (conn, mut ldap) = LdapConnAsync::with_settings(settings.clone(), &url)
+            .await
+            .unwrap();
+        crate::drive!(conn);
+        let (entries, _) = ldap
+            .search("dc=example,dc=org", Scope::Base, "(dc=*)", vec!["dc"])
+            .await
+            .unwrap()
+            .success()
+            .unwrap();
+        assert_eq!(entries.len(), 1);
+        let url = format!("ldap://ldap.invalid.test:{}", port);
+        let res = LdapConnAsync::with_settings(settings.clone(), &url).await;
+        assert!(matches!(res, Err(LdapError::Resolver(_))));
+        // Address literals are used directly.
+        let url = format!("ldap://127.0.0.1:{}", port);
+        LdapConnAsync::with_settings(settings, &url).await.unwrap();
+        assert_eq!(
+            *resolver.0.lock().unwrap(),
+            vec![
+                ("ldap.example.test".to_owned(), port),
+                ("ldap.invalid.test".to_owned(), port),
+            ]
+        );
+    }
+}
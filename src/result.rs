@@ -9,12 +9,19 @@ use std::error::Error;
 use std::fmt;
 use std::io;
 use std::result::Result as StdResult;
+use std::time::Duration;
 
-use crate::controls::Control;
+use crate::controls::{
+    Control, ControlParser, ControlType, PasswordPolicyError, PasswordPolicyResp,
+    PasswordPolicyWarning,
+};
 use crate::exop::Exop;
+use crate::ldap::Priority;
 use crate::protocol::{LdapOp, MaybeControls, ResultSender};
 use crate::search::parse_refs;
 use crate::search::ResultEntry;
+use crate::util::LdapUrl;
+use crate::values::Dn;
 use crate::RequestId;
 
 use lber::common::TagClass;
@@ -31,7 +38,13 @@ use tokio::time;
 pub type Result<T> = std::result::Result<T, LdapError>;
 
 /// Error variants recognized by the library.
+///
+/// Instead of matching on the variants and result codes, the broad kind of an error
+/// can be determined with [`category()`](#method.category), and the common questions
+/// asked of an error answered by [`is_transient()`](#method.is_transient),
+/// [`is_auth_failure()`](#method.is_auth_failure) and [`is_referral()`](#method.is_referral).
 #[derive(Debug, Error)]
+#[non_exhaustive]
 pub enum LdapError {
     /// No path given for a `ldapi://` URL.
     #[error("empty Unix domain socket path")]
@@ -52,7 +65,14 @@ pub enum LdapError {
     #[error("op send error: {source}")]
     OpSend {
         #[from]
-        source: mpsc::error::SendError<(RequestId, LdapOp, Tag, MaybeControls, ResultSender)>,
+        source: mpsc::error::SendError<(
+            RequestId,
+            LdapOp,
+            Tag,
+            MaybeControls,
+            ResultSender,
+            Priority,
+        )>,
     },
 
     /// Error while receiving operation results from the connection handler.
@@ -76,6 +96,10 @@ pub enum LdapError {
         elapsed: time::error::Elapsed,
     },
 
+    /// Operation cancelled through the future given to `with_cancel()`.
+    #[error("operation cancelled")]
+    Cancelled,
+
     /// Error parsing the string representation of a search filter.
     #[error("filter parse error")]
     FilterParsing,
@@ -119,6 +143,17 @@ pub enum LdapError {
         source: tokio_rustls::webpki::InvalidDNSNameError,
     },
 
+    #[cfg(any(feature = "tls-native", feature = "tls-rustls"))]
+    /// Invalid certificate given as a trust root.
+    #[error("invalid root certificate: {0}")]
+    InvalidCertificate(String),
+
+    #[cfg(any(feature = "tls-native", feature = "tls-rustls"))]
+    /// Server certificate revoked, or its revocation status unknown with
+    /// the hard-fail policy.
+    #[error("certificate revocation check failed: {0}")]
+    Revocation(String),
+
     /// LDAP operation result with an error return code.
     #[error("LDAP operation result: {result}")]
     LdapResult {
@@ -145,13 +180,319 @@ pub enum LdapError {
     /// Unreconized LDAP URL extension marked as critical.
     #[error("unrecognized critical LDAP URL extension: {0}")]
     UnrecognizedCriticalExtension(String),
+
+    /// Missing or malformed Netlogon attribute in a CLDAP ping response.
+    #[error("Netlogon response decoding error")]
+    NetlogonDecoding,
+
+    /// DNS SRV lookup failure.
+    #[error("SRV lookup error: {0}")]
+    SrvLookup(String),
+
+    /// Host name resolver failure.
+    #[error("resolver error: {0}")]
+    Resolver(String),
+
+    /// Operation refused by an interceptor.
+    #[error("operation vetoed: {0}")]
+    Vetoed(String),
+
+    /// Attribute value which can't be converted to the requested type.
+    #[error("invalid attribute value: {0}")]
+    InvalidValue(String),
+
+    /// Malformed BER value.
+    #[error("BER decoding error: {0}")]
+    BerDecoding(String),
+
+    /// Operation attempted after the start of a graceful shutdown.
+    #[error("connection is shutting down")]
+    ShuttingDown,
+
+    /// Malformed changelog entry.
+    #[error("changelog error: {0}")]
+    Changelog(String),
+
+    /// Malformed or unsupported DSML.
+    #[cfg(feature = "dsml")]
+    #[error("DSML error: {0}")]
+    Dsml(String),
+
+    /// Error of an operation, with the description of the operation. Returned instead
+    /// of the original error when enabled by
+    /// [`LdapConnSettings::set_error_context()`](../struct.LdapConnSettings.html#method.set_error_context).
+    #[error("{context}: {source}")]
+    Context {
+        context: OpContext,
+        source: Box<LdapError>,
+    },
+}
+
+/// Description of the operation during which an error occurred.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct OpContext {
+    /// Operation name, as in the [metrics](../metrics/index.html).
+    pub op: &'static str,
+    /// DN named in the request, if any.
+    pub dn: Option<String>,
+    /// URL of the server, without the path.
+    pub server: Option<String>,
+}
+
+impl fmt::Display for OpContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> StdResult<(), fmt::Error> {
+        write!(f, "{}", self.op)?;
+        if let Some(ref dn) = self.dn {
+            write!(f, " \"{}\"", dn)?;
+        }
+        if let Some(ref server) = self.server {
+            write!(f, " on {}", server)?;
+        }
+        Ok(())
+    }
+}
+
+/// Broad category of an [`LdapError`](enum.LdapError.html).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ErrorCategory {
+    /// Operation result with an error code.
+    Result(ResultCategory),
+    /// The connection failed or was closed, or couldn't be established.
+    Connection,
+    /// TLS setup or certificate verification failed.
+    Tls,
+    /// The operation timed out.
+    Timeout,
+    /// The operation was cancelled by the caller.
+    Cancelled,
+    /// The operation was refused locally, by an interceptor or because of a shutdown.
+    Refused,
+    /// Invalid arguments, such as a malformed filter or URL.
+    Usage,
+    /// Malformed data received from the server or read from a file.
+    Decoding,
+}
+
+/// Broad category of a result code.
+///
+/// The categories follow the grouping of error codes in X.511 and the LDAPv2
+/// specification, with the non-error codes of LDAPv3 split out.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ResultCategory {
+    /// Successful completion, including `compareFalse`, `compareTrue` and
+    /// `saslBindInProgress`.
+    Success,
+    /// The operation must be performed elsewhere: `referral`.
+    Referral,
+    /// A time, size or administrative limit was exceeded.
+    Limit,
+    /// The client couldn't be authenticated, or must authenticate differently:
+    /// `invalidCredentials`, `inappropriateAuthentication`, `authMethodNotSupported`,
+    /// `strongerAuthRequired` and `confidentialityRequired`.
+    Authentication,
+    /// The client isn't allowed to perform the operation: `insufficientAccessRights`.
+    Authorization,
+    /// Problem with an attribute or its values.
+    Attribute,
+    /// Problem with the name of an entry, such as `noSuchObject`.
+    Name,
+    /// The update violates the directory schema or structure, such as
+    /// `entryAlreadyExists` or `notAllowedOnNonLeaf`.
+    Update,
+    /// The server can't perform the operation at this time, or at all: `busy`,
+    /// `unavailable`, `unwillingToPerform`, `loopDetect`.
+    Service,
+    /// Malformed or unsupported request: `operationsError`, `protocolError` and
+    /// `unavailableCriticalExtension`.
+    Protocol,
+    /// The operation was abandoned or cancelled.
+    Cancelled,
+    /// Any other code, including `other`.
+    Other,
+}
+
+impl ResultCategory {
+    /// Category of the result code `rc`.
+    pub fn of(rc: u32) -> ResultCategory {
+        match rc {
+            0 | 5 | 6 | 14 => ResultCategory::Success,
+            10 => ResultCategory::Referral,
+            3 | 4 | 11 => ResultCategory::Limit,
+            7 | 8 | 13 | 48 | 49 => ResultCategory::Authentication,
+            50 => ResultCategory::Authorization,
+            16..=21 => ResultCategory::Attribute,
+            32..=36 => ResultCategory::Name,
+            64..=71 => ResultCategory::Update,
+            51..=54 => ResultCategory::Service,
+            1 | 2 | 12 => ResultCategory::Protocol,
+            88 | 118..=121 => ResultCategory::Cancelled,
+            _ => ResultCategory::Other,
+        }
+    }
+}
+
+/// Windows error code from the diagnostic text of an Active Directory result.
+///
+/// The codes most often seen with `invalidCredentials` (49) have their own variants;
+/// the rest are returned as `Other`. See
+/// [`LdapResult::ad_error()`](struct.LdapResult.html#method.ad_error).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum AdError {
+    /// The user doesn't exist (`525`).
+    NoSuchUser,
+    /// The password is wrong (`52e`).
+    InvalidCredentials,
+    /// Logon not permitted at this time (`530`).
+    InvalidLogonHours,
+    /// Logon not permitted from this workstation (`531`).
+    InvalidWorkstation,
+    /// The password has expired (`532`).
+    PasswordExpired,
+    /// The account is disabled (`533`).
+    AccountDisabled,
+    /// The account has expired (`701`).
+    AccountExpired,
+    /// The password must be changed before logging on (`773`).
+    MustChangePassword,
+    /// The account is locked out (`775`).
+    AccountLocked,
+    /// Any other code.
+    Other(u32),
+}
+
+impl AdError {
+    /// Variant for the numeric error `code`.
+    pub fn from_code(code: u32) -> AdError {
+        match code {
+            0x525 => AdError::NoSuchUser,
+            0x52e => AdError::InvalidCredentials,
+            0x530 => AdError::InvalidLogonHours,
+            0x531 => AdError::InvalidWorkstation,
+            0x532 => AdError::PasswordExpired,
+            0x533 => AdError::AccountDisabled,
+            0x701 => AdError::AccountExpired,
+            0x773 => AdError::MustChangePassword,
+            0x775 => AdError::AccountLocked,
+            code => AdError::Other(code),
+        }
+    }
+
+    /// Numeric error code.
+    pub fn code(&self) -> u32 {
+        match *self {
+            AdError::NoSuchUser => 0x525,
+            AdError::InvalidCredentials => 0x52e,
+            AdError::InvalidLogonHours => 0x530,
+            AdError::InvalidWorkstation => 0x531,
+            AdError::PasswordExpired => 0x532,
+            AdError::AccountDisabled => 0x533,
+            AdError::AccountExpired => 0x701,
+            AdError::MustChangePassword => 0x773,
+            AdError::AccountLocked => 0x775,
+            AdError::Other(code) => code,
+        }
+    }
+}
+
+impl LdapError {
+    /// Wrap the error with the description of the operation. An error which already
+    /// has a context is returned unchanged.
+    pub fn with_context(self, context: OpContext) -> LdapError {
+        match self {
+            LdapError::Context { .. } => self,
+            e => LdapError::Context {
+                context,
+                source: Box::new(e),
+            },
+        }
+    }
+
+    /// Return the description of the operation, if the error has one.
+    pub fn context(&self) -> Option<&OpContext> {
+        match *self {
+            LdapError::Context { ref context, .. } => Some(context),
+            _ => None,
+        }
+    }
+
+    /// Return the error without the description of the operation.
+    pub fn inner(&self) -> &LdapError {
+        match *self {
+            LdapError::Context { ref source, .. } => source.inner(),
+            ref e => e,
+        }
+    }
+
+    /// Return the operation result, if the error is an error result code.
+    pub fn result(&self) -> Option<&LdapResult> {
+        match *self.inner() {
+            LdapError::LdapResult { ref result } => Some(result),
+            _ => None,
+        }
+    }
+
+    /// Return the broad category of the error.
+    pub fn category(&self) -> ErrorCategory {
+        match *self.inner() {
+            LdapError::LdapResult { ref result } => ErrorCategory::Result(result.category()),
+            LdapError::Io { .. }
+            | LdapError::OpSend { .. }
+            | LdapError::ResultRecv { .. }
+            | LdapError::IdScrubSend { .. }
+            | LdapError::EndOfStream
+            | LdapError::SrvLookup(_)
+            | LdapError::Resolver(_) => ErrorCategory::Connection,
+            #[cfg(feature = "tls-native")]
+            LdapError::NativeTLS { .. } => ErrorCategory::Tls,
+            #[cfg(feature = "tls-rustls")]
+            LdapError::Rustls { .. } | LdapError::DNSName { .. } => ErrorCategory::Tls,
+            #[cfg(any(feature = "tls-native", feature = "tls-rustls"))]
+            LdapError::InvalidCertificate(_) | LdapError::Revocation(_) => ErrorCategory::Tls,
+            LdapError::Timeout { .. } => ErrorCategory::Timeout,
+            LdapError::Cancelled => ErrorCategory::Cancelled,
+            LdapError::Vetoed(_) | LdapError::ShuttingDown => ErrorCategory::Refused,
+            LdapError::NetlogonDecoding
+            | LdapError::BerDecoding(_)
+            | LdapError::DecodingUTF8
+            | LdapError::Changelog(_) => ErrorCategory::Decoding,
+            #[cfg(feature = "dsml")]
+            LdapError::Dsml(_) => ErrorCategory::Decoding,
+            _ => ErrorCategory::Usage,
+        }
+    }
+
+    /// Returns true if the same operation might succeed if tried again later: the
+    /// connection failed, the operation timed out, or the server reported that it's
+    /// `busy` or `unavailable`. A failed connection must be reopened first.
+    pub fn is_transient(&self) -> bool {
+        match self.category() {
+            ErrorCategory::Connection | ErrorCategory::Timeout => true,
+            ErrorCategory::Result(_) => self.result().is_some_and(LdapResult::is_transient),
+            _ => false,
+        }
+    }
+
+    /// Returns true if the error is a result code signifying that the client couldn't
+    /// be authenticated. See [`ResultCategory::Authentication`](enum.ResultCategory.html#variant.Authentication).
+    pub fn is_auth_failure(&self) -> bool {
+        self.result().is_some_and(LdapResult::is_auth_failure)
+    }
+
+    /// Returns true if the error is a referral result.
+    pub fn is_referral(&self) -> bool {
+        self.result().is_some_and(LdapResult::is_referral)
+    }
 }
 
 impl From<LdapError> for io::Error {
     fn from(le: LdapError) -> io::Error {
         match le {
             LdapError::Io { source, .. } => source,
-            _ => io::Error::new(io::ErrorKind::Other, format!("{}", le)),
+            _ => io::Error::other(format!("{}", le)),
         }
     }
 }
@@ -166,7 +507,7 @@ impl From<LdapError> for io::Error {
 /// implements helper methods, [`success()`](#method.success) and
 /// [`non_error()`](#method.non_error), which may be used for ergonomic error
 /// handling when simple condition checking suffices.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Default)]
 pub struct LdapResult {
     /// Result code.
     ///
@@ -197,60 +538,61 @@ impl From<Tag> for LdapResult {
 
 impl Error for LdapResult {}
 
+// Descriptive name of a result code, from RFC 4511.
+pub(crate) fn rc_description(rc: u32) -> &'static str {
+    match rc {
+        0 => "success",
+        1 => "operationsError",
+        2 => "protocolError",
+        3 => "timeLimitExceeded",
+        4 => "sizeLimitExceeded",
+        5 => "compareFalse",
+        6 => "compareTrue",
+        7 => "authMethodNotSupported",
+        8 => "strongerAuthRequired",
+        10 => "referral",
+        11 => "adminLimitExceeded",
+        12 => "unavailableCriticalExtension",
+        13 => "confidentialityRequired",
+        14 => "saslBindInProgress",
+        16 => "noSuchAttribute",
+        17 => "undefinedAttributeType",
+        18 => "inappropriateMatching",
+        19 => "constraintViolation",
+        20 => "attributeOrValueExists",
+        21 => "invalidAttributeSyntax",
+        32 => "noSuchObject",
+        33 => "aliasProblem",
+        34 => "invalidDNSyntax",
+        36 => "aliasDereferencingProblem",
+        48 => "inappropriateAuthentication",
+        49 => "invalidCredentials",
+        50 => "insufficientAccessRights",
+        51 => "busy",
+        52 => "unavailable",
+        53 => "unwillingToPerform",
+        54 => "loopDetect",
+        64 => "namingViolation",
+        65 => "objectClassViolation",
+        66 => "notAllowedOnNonLeaf",
+        67 => "notAllowedOnRDN",
+        68 => "entryAlreadyExists",
+        69 => "objectClassModsProhibited",
+        71 => "affectsMultipleDSAs",
+        80 => "other",
+        88 => "abandoned",
+        122 => "assertionFailed",
+        _ => "unknown",
+    }
+}
+
 impl fmt::Display for LdapResult {
     fn fmt(&self, f: &mut fmt::Formatter) -> StdResult<(), fmt::Error> {
-        fn description(this: &LdapResult) -> &'static str {
-            match this.rc {
-                0 => "success",
-                1 => "operationsError",
-                2 => "protocolError",
-                3 => "timeLimitExceeded",
-                4 => "sizeLimitExceeded",
-                5 => "compareFalse",
-                6 => "compareTrue",
-                7 => "authMethodNotSupported",
-                8 => "strongerAuthRequired",
-                10 => "referral",
-                11 => "adminLimitExceeded",
-                12 => "unavailableCriticalExtension",
-                13 => "confidentialityRequired",
-                14 => "saslBindInProgress",
-                16 => "noSuchAttribute",
-                17 => "undefinedAttributeType",
-                18 => "inappropriateMatching",
-                19 => "constraintViolation",
-                20 => "attributeOrValueExists",
-                21 => "invalidAttributeSyntax",
-                32 => "noSuchObject",
-                33 => "aliasProblem",
-                34 => "invalidDNSyntax",
-                36 => "aliasDereferencingProblem",
-                48 => "inappropriateAuthentication",
-                49 => "invalidCredentials",
-                50 => "insufficientAccessRights",
-                51 => "busy",
-                52 => "unavailable",
-                53 => "unwillingToPerform",
-                54 => "loopDetect",
-                64 => "namingViolation",
-                65 => "objectClassViolation",
-                66 => "notAllowedOnNonLeaf",
-                67 => "notAllowedOnRDN",
-                68 => "entryAlreadyExists",
-                69 => "objectClassModsProhibited",
-                71 => "affectsMultipleDSAs",
-                80 => "other",
-                88 => "abandoned",
-                122 => "assertionFailed",
-                _ => "unknown",
-            }
-        }
-
         write!(
             f,
             "rc={} ({}), dn: \"{}\", text: \"{}\"",
             self.rc,
-            description(self),
+            rc_description(self.rc),
             self.matched,
             self.text
         )
@@ -258,6 +600,57 @@ impl fmt::Display for LdapResult {
 }
 
 impl LdapResult {
+    /// Return the broad category of the result code.
+    pub fn category(&self) -> ResultCategory {
+        ResultCategory::of(self.rc)
+    }
+
+    /// Returns true if the server reported a condition which might go away: `busy` (51)
+    /// or `unavailable` (52).
+    pub fn is_transient(&self) -> bool {
+        self.rc == 51 || self.rc == 52
+    }
+
+    /// Returns true if the client couldn't be authenticated. See
+    /// [`ResultCategory::Authentication`](enum.ResultCategory.html#variant.Authentication).
+    pub fn is_auth_failure(&self) -> bool {
+        self.category() == ResultCategory::Authentication
+    }
+
+    /// Returns true if the result is a referral (10). The referral URLs are in `refs`.
+    pub fn is_referral(&self) -> bool {
+        self.rc == 10
+    }
+
+    /// Return the matched DN, if the server has provided one. For a name-related error,
+    /// such as `noSuchObject`, it's the DN of the deepest existing entry on the path to
+    /// the requested one.
+    pub fn matched_dn(&self) -> Option<Dn> {
+        if self.matched.is_empty() {
+            None
+        } else {
+            Some(Dn::new(self.matched.as_str()))
+        }
+    }
+
+    /// Parse the referral URLs. An error is returned if any of them isn't a valid
+    /// LDAP URL.
+    pub fn referral_urls(&self) -> Result<Vec<LdapUrl>> {
+        self.refs.iter().map(|r| LdapUrl::parse(r)).collect()
+    }
+
+    /// Decode the diagnostic text of an Active Directory error result, which contains
+    /// the Windows error code in a `data` item, as in
+    /// `80090308: LdapErr: DSID-0C09044E, comment: AcceptSecurityContext error, data 52e, v4563`.
+    /// Returns `None` if there's no such item.
+    pub fn ad_error(&self) -> Option<AdError> {
+        let start = self.text.find(", data ")? + ", data ".len();
+        let hex = self.text[start..]
+            .split(|c: char| !c.is_ascii_hexdigit())
+            .next()?;
+        u32::from_str_radix(hex, 16).ok().map(AdError::from_code)
+    }
+
     /// If the result code is zero, return the instance itself wrapped
     /// in `Ok()`, otherwise wrap the instance in an `LdapError`.
     pub fn success(self) -> Result<Self> {
@@ -401,6 +794,76 @@ impl SearchResult {
     }
 }
 
+/// Outcome of a Search, telling apart the ways in which a search can end early.
+///
+/// Returned by [`Ldap::search_outcome()`](../struct.Ldap.html#method.search_outcome).
+/// Every variant holds the entries received before the search ended, so that partial
+/// results are available regardless of the reason. A client-side timeout or cancellation
+/// leaves the server's search running; use Abandon if that matters.
+#[derive(Clone, Debug)]
+pub enum SearchOutcome {
+    /// The final result was received, with a result code other than 3 or 4. The
+    /// result code may still indicate an error.
+    Done(SearchResult),
+    /// The server's time limit for the search was exceeded (result code 3).
+    TimeLimitExceeded(SearchResult),
+    /// The server's size limit for the search was exceeded (result code 4).
+    SizeLimitExceeded(SearchResult),
+    /// The client-side timeout expired before the final result was received.
+    TimedOut(Vec<ResultEntry>),
+    /// The search was cancelled with [`with_cancel()`](../struct.Ldap.html#method.with_cancel)
+    /// before the final result was received.
+    Cancelled(Vec<ResultEntry>),
+}
+
+impl SearchOutcome {
+    /// Return true if the search ended with the final result from the server, and no
+    /// limit was exceeded.
+    pub fn is_done(&self) -> bool {
+        matches!(self, SearchOutcome::Done(_))
+    }
+
+    /// Return the entries received, which may be a partial set.
+    pub fn entries(&self) -> &[ResultEntry] {
+        match self {
+            SearchOutcome::Done(res)
+            | SearchOutcome::TimeLimitExceeded(res)
+            | SearchOutcome::SizeLimitExceeded(res) => &res.0,
+            SearchOutcome::TimedOut(entries) | SearchOutcome::Cancelled(entries) => entries,
+        }
+    }
+
+    /// Consume the outcome and return the entries received.
+    pub fn into_entries(self) -> Vec<ResultEntry> {
+        match self {
+            SearchOutcome::Done(res)
+            | SearchOutcome::TimeLimitExceeded(res)
+            | SearchOutcome::SizeLimitExceeded(res) => res.0,
+            SearchOutcome::TimedOut(entries) | SearchOutcome::Cancelled(entries) => entries,
+        }
+    }
+
+    /// Return the final result of the search, if it was received.
+    pub fn result(&self) -> Option<&LdapResult> {
+        match self {
+            SearchOutcome::Done(res)
+            | SearchOutcome::TimeLimitExceeded(res)
+            | SearchOutcome::SizeLimitExceeded(res) => Some(&res.1),
+            SearchOutcome::TimedOut(_) | SearchOutcome::Cancelled(_) => None,
+        }
+    }
+}
+
+impl From<SearchResult> for SearchOutcome {
+    fn from(res: SearchResult) -> SearchOutcome {
+        match res.1.rc {
+            3 => SearchOutcome::TimeLimitExceeded(res),
+            4 => SearchOutcome::SizeLimitExceeded(res),
+            _ => SearchOutcome::Done(res),
+        }
+    }
+}
+
 /// Wrapper for the result of a Compare operation.
 ///
 /// Compare uniquely has two non-zero return codes to indicate the outcome of a successful
@@ -462,3 +925,191 @@ impl ExopResult {
         }
     }
 }
+
+/// Result of a simple Bind made with the Password Policy control.
+///
+/// Returned by [`Ldap::simple_bind_ppolicy()`](../struct.Ldap.html#method.simple_bind_ppolicy).
+/// Besides the result, it holds the warning and the error from the
+/// [`PasswordPolicyResp`](../controls/struct.PasswordPolicyResp.html) control, if the
+/// server returned one. A successful Bind can come with a warning that the password is
+/// about to expire, or that it has expired and grace logins are being used, or with the
+/// `ChangeAfterReset` error, when changing the password is the only permitted operation.
+/// A failed Bind can come with the reason, such as `AccountLocked` or `PasswordExpired`.
+#[derive(Clone, Debug)]
+pub struct BindOutcome {
+    /// Result of the Bind.
+    pub result: LdapResult,
+    /// Password policy warning.
+    pub warning: Option<PasswordPolicyWarning>,
+    /// Password policy error.
+    pub error: Option<PasswordPolicyError>,
+}
+
+impl From<LdapResult> for BindOutcome {
+    fn from(result: LdapResult) -> BindOutcome {
+        let resp = result
+            .ctrls
+            .iter()
+            .find(|ctrl| ctrl.0 == Some(ControlType::PasswordPolicyResp))
+            .and_then(|ctrl| ctrl.1.val.as_deref())
+            .map(PasswordPolicyResp::parse)
+            .unwrap_or_default();
+        BindOutcome {
+            result,
+            warning: resp.warning,
+            error: resp.error,
+        }
+    }
+}
+
+impl BindOutcome {
+    /// Time until the password expires, if the server sent the warning.
+    pub fn expires_in(&self) -> Option<Duration> {
+        match self.warning {
+            Some(PasswordPolicyWarning::TimeBeforeExpiration(secs)) => {
+                Some(Duration::from_secs(secs as u64))
+            }
+            _ => None,
+        }
+    }
+
+    /// Number of grace logins remaining after this one, if the password has expired.
+    pub fn grace_logins(&self) -> Option<u32> {
+        match self.warning {
+            Some(PasswordPolicyWarning::GraceAuthNsRemaining(n)) => Some(n),
+            _ => None,
+        }
+    }
+
+    /// Whether the password must be changed before any other operation.
+    pub fn must_change(&self) -> bool {
+        self.error == Some(PasswordPolicyError::ChangeAfterReset)
+    }
+
+    /// If the result code is zero, return the outcome wrapped in `Ok()`, otherwise
+    /// wrap the `LdapResult` part in an `LdapError`.
+    pub fn success(self) -> Result<Self> {
+        if self.result.rc == 0 {
+            Ok(self)
+        } else {
+            Err(LdapError::from(self.result))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{AdError, BindOutcome, LdapResult};
+    use crate::controls::{Control, ControlType, RawControl};
+    use crate::values::Dn;
+
+    use std::time::Duration;
+
+    #[test]
+    fn result_helpers() {
+        let res = LdapResult {
+            rc: 49,
+            text: String::from(
+                "80090308: LdapErr: DSID-0C09044E, comment: AcceptSecurityContext error, data 773, v4563\0",
+            ),
+            ..Default::default()
+        };
+        assert_eq!(res.ad_error(), Some(AdError::MustChangePassword));
+        assert_eq!(res.matched_dn(), None);
+        let res = LdapResult {
+            rc: 10,
+            matched: String::from("dc=example, dc=org"),
+            refs: vec![String::from(
+                "ldap://ldap2.example.org/ou=People,dc=example,dc=org??one",
+            )],
+            ..Default::default()
+        };
+        assert_eq!(res.ad_error(), None);
+        assert_eq!(res.matched_dn(), Some(Dn::new("dc=example,dc=org")));
+        let urls = res.referral_urls().unwrap();
+        assert_eq!(urls[0].url.host_str(), Some("ldap2.example.org"));
+        assert_eq!(urls[0].base, "ou=People,dc=example,dc=org");
+        assert_eq!(AdError::from_code(0x52f), AdError::Other(0x52f));
+        assert_eq!(AdError::from_code(0x52f).code(), 0x52f);
+    }
+
+    #[test]
+    fn bind_outcome() {
+        // SEQUENCE { [0] { [0] 300 } }, SEQUENCE { [1] 2 }
+        let ctrl = |val: &[u8]| {
+            Control(
+                Some(ControlType::PasswordPolicyResp),
+                RawControl {
+                    ctype: "1.3.6.1.4.1.42.2.27.8.5.1".to_owned(),
+                    crit: false,
+                    val: Some(val.to_vec()),
+                },
+            )
+        };
+        let outcome = BindOutcome::from(LdapResult {
+            ctrls: vec![ctrl(b"\x30\x06\xa0\x04\x80\x02\x01\x2c")],
+            ..Default::default()
+        });
+        assert_eq!(outcome.expires_in(), Some(Duration::from_secs(300)));
+        let outcome = BindOutcome::from(LdapResult {
+            ctrls: vec![ctrl(b"\x30\x03\x81\x01\x02")],
+            ..Default::default()
+        });
+        assert!(outcome.must_change());
+    }
+}
+
+#[cfg(all(test, feature = "testkit"))]
+mod mock_test {
+    use super::{ErrorCategory, LdapError, ResultCategory};
+    use crate::testkit::MockServer;
+    use crate::{LdapConnSettings, Scope};
+
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn error_categories() {
+        let server = MockServer::new();
+        server.add_entry(
+            "uid=test,dc=example,dc=org",
+            vec![("uid", vec!["test"]), ("userPassword", vec!["secret"])],
+        );
+        let settings = LdapConnSettings::new().set_error_context(true);
+        let (conn, mut ldap) = server.connect_with_settings(settings);
+        crate::drive!(conn);
+        let err = ldap
+            .simple_bind("uid=test,dc=example,dc=org", "wrong")
+            .await
+            .unwrap()
+            .success()
+            .unwrap_err();
+        assert_eq!(
+            err.category(),
+            ErrorCategory::Result(ResultCategory::Authentication)
+        );
+        assert!(err.is_auth_failure());
+        assert!(!err.is_transient());
+        assert!(err.context().is_none());
+        let res = ldap
+            .search(
+                "ou=none,dc=example,dc=org",
+                Scope::Base,
+                "(objectClass=*)",
+                vec!["*"],
+            )
+            .await
+            .unwrap();
+        assert_eq!(res.1.category(), ResultCategory::Name);
+        ldap.shutdown(Duration::from_millis(10)).await.unwrap();
+        let err = ldap.delete("uid=test,dc=example,dc=org").await.unwrap_err();
+        assert_eq!(err.category(), ErrorCategory::Refused);
+        assert!(matches!(err.inner(), LdapError::ShuttingDown));
+        let context = err.context().unwrap();
+        assert_eq!(context.op, "delete");
+        assert_eq!(context.dn.as_deref(), Some("uid=test,dc=example,dc=org"));
+        assert_eq!(
+            err.to_string(),
+            "delete \"uid=test,dc=example,dc=org\": connection is shutting down"
+        );
+    }
+}
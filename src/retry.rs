@@ -0,0 +1,474 @@
+//! Retrying idempotent operations on transient failures.
+//!
+//! A directory server which is overloaded or shutting down answers with the result code
+//! `busy` (51) or `unavailable` (52), and a broken connection makes operations fail with
+//! a transport error. For operations which can be safely repeated, waiting a little and
+//! trying again is often the best response. [`RetryingLdap`](struct.RetryingLdap.html)
+//! wraps an `Ldap` handle and does that for Searches, Compares and simple Binds, with
+//! the number of attempts and the waiting time between them determined by a
+//! [`RetryPolicy`](struct.RetryPolicy.html).
+//!
+//! If `RetryingLdap` is created with [`connect()`](struct.RetryingLdap.html#method.connect),
+//! it also knows how to open a new connection, and does so after a transport error. The
+//! new connection is bound with the credentials from the
+//! [rebind function](../struct.LdapConnSettings.html#method.set_rebind_fn) of the
+//! settings, if it supplies them, or else with those of the last successful simple
+//! Bind. A
+//! failover setup where the server name moves to a healthy server can thus be survived
+//! without the application noticing anything but a delay.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! use ldap3::retry::{RetryPolicy, RetryingLdap};
+//! use ldap3::{LdapConnSettings, Scope};
+//! # use ldap3::result::Result;
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<()> {
+//! let policy = RetryPolicy::new().with_max_attempts(5);
+//! let mut ldap =
+//!     RetryingLdap::connect("ldap://localhost:2389", LdapConnSettings::new(), policy).await?;
+//! ldap.simple_bind("cn=Manager,dc=example,dc=org", "secret").await?.success()?;
+//! let (rs, _res) = ldap
+//!     .search("dc=example,dc=org", Scope::Subtree, "(uid=*)", vec!["uid"])
+//!     .await?
+//!     .success()?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+use crate::conn::{LdapConnAsync, LdapConnSettings, RebindCredentials};
+use crate::ldap::Ldap;
+use crate::result::{CompareResult, ErrorCategory, LdapError, LdapResult, Result, SearchResult};
+use crate::search::Scope;
+
+use tokio::time;
+
+/// Number of attempts and backoff parameters for retrying operations.
+///
+/// The wait before the second attempt is the initial backoff, and every following
+/// wait is twice as long as the previous one, up to the maximum. With jitter, which
+/// is enabled by default, each wait is randomly shortened by up to a half, so that
+/// many clients failing at the same time don't retry in lockstep.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    jitter: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+            jitter: true,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Create a policy with three attempts, an initial backoff of 100 ms, a maximum
+    /// backoff of five seconds, and jitter.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the total number of attempts, including the first one. A value of one
+    /// disables retrying.
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        assert!(max_attempts > 0, "at least one attempt must be made");
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    /// Set the initial and the maximum backoff.
+    pub fn with_backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max;
+        self
+    }
+
+    /// Enable or disable jitter.
+    pub fn with_jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    // Wait after the failed attempt number `attempt`, counting from one.
+    fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 1u32.checked_shl(attempt - 1).unwrap_or(u32::MAX);
+        let backoff = self
+            .initial_backoff
+            .checked_mul(factor)
+            .map_or(self.max_backoff, |b| b.min(self.max_backoff));
+        if self.jitter {
+            let mut hasher = RandomState::new().build_hasher();
+            hasher.write_u32(attempt);
+            let half = backoff / 2;
+            half + half.mul_f64((hasher.finish() % 1024) as f64 / 1024.0)
+        } else {
+            backoff
+        }
+    }
+}
+
+fn is_transport(e: &LdapError) -> bool {
+    e.category() == ErrorCategory::Connection
+}
+
+/// `Ldap` handle wrapper which retries idempotent operations.
+///
+/// See the [module-level documentation](index.html) for the overview. Operations
+/// without a retrying counterpart can be performed on the inner handle, obtained with
+/// [`ldap()`](#method.ldap).
+pub struct RetryingLdap {
+    ldap: Ldap,
+    policy: RetryPolicy,
+    reconnect: Option<(String, LdapConnSettings)>,
+    bind: Option<(String, String)>,
+}
+
+impl RetryingLdap {
+    /// Wrap an existing handle. Transport errors are retried, but with the same handle,
+    /// so they're not likely to go away.
+    pub fn new(ldap: Ldap, policy: RetryPolicy) -> Self {
+        RetryingLdap {
+            ldap,
+            policy,
+            reconnect: None,
+            bind: None,
+        }
+    }
+
+    /// Connect to `url` with `settings`, and spawn the connection on the current Tokio
+    /// runtime. The URL and the settings are kept for opening a new connection when the
+    /// current one fails. Connecting is itself retried according to the policy.
+    pub async fn connect(
+        url: &str,
+        settings: LdapConnSettings,
+        policy: RetryPolicy,
+    ) -> Result<Self> {
+        let mut attempt = 1;
+        let ldap = loop {
+            match Self::open(url, &settings).await {
+                Ok(ldap) => break ldap,
+                Err(e) if attempt < policy.max_attempts && is_transport(&e) => {
+                    time::sleep(policy.backoff(attempt)).await;
+                    attempt += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        };
+        Ok(RetryingLdap {
+            ldap,
+            policy,
+            reconnect: Some((url.to_owned(), settings)),
+            bind: None,
+        })
+    }
+
+    async fn open(url: &str, settings: &LdapConnSettings) -> Result<Ldap> {
+        let (conn, ldap) = LdapConnAsync::with_settings(settings.clone(), url).await?;
+        crate::drive!(conn);
+        Ok(ldap)
+    }
+
+    /// Return a reference to the inner handle.
+    pub fn ldap(&mut self) -> &mut Ldap {
+        &mut self.ldap
+    }
+
+    /// Unwrap the inner handle.
+    pub fn into_inner(self) -> Ldap {
+        self.ldap
+    }
+
+    // Decide whether to make another attempt after `attempt` has ended with `outcome`,
+    // which is either the result or the error. If so, wait, and reconnect if
+    // the connection has failed.
+    async fn again(
+        &mut self,
+        attempt: u32,
+        outcome: std::result::Result<&LdapResult, &LdapError>,
+    ) -> bool {
+        let transport = match outcome {
+            Ok(res) if res.is_transient() => false,
+            Err(e) if is_transport(e) => true,
+            _ => return false,
+        };
+        if attempt >= self.policy.max_attempts {
+            return false;
+        }
+        debug!("retrying operation, attempt {} failed", attempt);
+        time::sleep(self.policy.backoff(attempt)).await;
+        if transport {
+            if let Some((ref url, ref settings)) = self.reconnect {
+                match Self::open(url, settings).await {
+                    Ok(mut ldap) => {
+                        let creds =
+                            settings.rebind_credentials(url).map(|creds| {
+                                creds.or_else(|| {
+                                    self.bind.clone().map(|(dn, password)| {
+                                        RebindCredentials::Simple { dn, password }
+                                    })
+                                })
+                            });
+                        let res = match creds {
+                            Ok(Some(creds)) => creds.bind(&mut ldap).await,
+                            Ok(None) => Ok(()),
+                            Err(e) => Err(e),
+                        };
+                        if let Err(e) = res {
+                            warn!("rebind after reconnect failed: {}", e);
+                            return true;
+                        }
+                        self.ldap = ldap;
+                    }
+                    // Keep the broken handle; the next attempt will fail quickly,
+                    // and count against the limit.
+                    Err(e) => warn!("reconnect failed: {}", e),
+                }
+            }
+        }
+        true
+    }
+
+    /// Do a simple Bind, retrying it if necessary. If the Bind succeeds, the credentials
+    /// are remembered, and used on new connections.
+    pub async fn simple_bind(&mut self, bind_dn: &str, bind_pw: &str) -> Result<LdapResult> {
+        let mut attempt = 1;
+        loop {
+            let res = self.ldap.simple_bind(bind_dn, bind_pw).await;
+            if res.as_ref().is_ok_and(|r| r.rc == 0) {
+                self.bind = Some((bind_dn.to_owned(), bind_pw.to_owned()));
+            }
+            if !self.again(attempt, res.as_ref()).await {
+                return res;
+            }
+            attempt += 1;
+        }
+    }
+
+    /// Perform a Search, retrying it if necessary. See
+    /// [`Ldap::search()`](../struct.Ldap.html#method.search).
+    pub async fn search<'a, S: AsRef<str> + Clone + Send + Sync + 'a>(
+        &mut self,
+        base: &str,
+        scope: Scope,
+        filter: &str,
+        attrs: Vec<S>,
+    ) -> Result<SearchResult> {
+        let mut attempt = 1;
+        loop {
+            let res = self.ldap.search(base, scope, filter, attrs.clone()).await;
+            if !self.again(attempt, res.as_ref().map(|r| &r.1)).await {
+                return res;
+            }
+            attempt += 1;
+        }
+    }
+
+    /// Compare an attribute value, retrying the operation if necessary. See
+    /// [`Ldap::compare()`](../struct.Ldap.html#method.compare).
+    pub async fn compare<B: AsRef<[u8]>>(
+        &mut self,
+        dn: &str,
+        attr: &str,
+        val: B,
+    ) -> Result<CompareResult> {
+        let mut attempt = 1;
+        loop {
+            let res = self.ldap.compare(dn, attr, val.as_ref()).await;
+            if !self.again(attempt, res.as_ref().map(|r| &r.0)).await {
+                return res;
+            }
+            attempt += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::RetryPolicy;
+
+    use std::time::Duration;
+
+    #[test]
+    fn backoff_growth() {
+        let policy = RetryPolicy::new()
+            .with_backoff(Duration::from_millis(100), Duration::from_millis(500))
+            .with_jitter(false);
+        let waits: Vec<_> = (1..=5).map(|n| policy.backoff(n).as_millis()).collect();
+        assert_eq!(waits, vec![100, 200, 400, 500, 500]);
+        assert_eq!(policy.backoff(100), Duration::from_millis(500));
+        let policy = policy.with_jitter(true);
+        for n in 1..=5 {
+            let wait = policy.backoff(n);
+            assert!(wait >= Duration::from_millis(50) && wait <= Duration::from_millis(500));
+        }
+    }
+}
+
+#[cfg(all(test, feature = "testkit"))]
+mod mock_test {
+    use super::{RetryPolicy, RetryingLdap};
+    use crate::conn::LdapConnSettings;
+    use crate::testkit::{response, MockServer};
+    use crate::Scope;
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    const ADMIN_DN: &str = "cn=admin,dc=example,dc=org";
+
+    fn server() -> MockServer {
+        let server = MockServer::new();
+        server.add_entry("dc=example,dc=org", vec![("dc", vec!["example"])]);
+        server.add_entry(
+            ADMIN_DN,
+            vec![("cn", vec!["admin"]), ("userPassword", vec!["secret"])],
+        );
+        server
+    }
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy::new()
+            .with_max_attempts(3)
+            .with_backoff(Duration::from_millis(1), Duration::from_millis(1))
+    }
+
+    fn retrying(server: &MockServer) -> RetryingLdap {
+        let (conn, ldap) = server.connect();
+        crate::drive!(conn);
+        RetryingLdap::new(ldap, policy())
+    }
+
+    // Answer the Searches with the result codes in `rcs`, and the rest with success.
+    fn script_searches(server: &MockServer, rcs: &'static [u32]) {
+        let calls = AtomicUsize::new(0);
+        server.script(3, move |id| {
+            let rc = rcs.get(calls.fetch_add(1, Ordering::SeqCst)).copied();
+            vec![(id, response(5, rc.unwrap_or(0), ""), None)]
+        });
+    }
+
+    fn count(server: &MockServer, op: u64) -> usize {
+        server.received().iter().filter(|r| r.0 == op).count()
+    }
+
+    #[tokio::test]
+    async fn transient_results() {
+        let server = server();
+        script_searches(&server, &[51, 52]);
+        let mut ldap = retrying(&server);
+        let res = ldap
+            .search(
+                "dc=example,dc=org",
+                Scope::Base,
+                "(objectClass=*)",
+                vec!["1.1"],
+            )
+            .await
+            .unwrap();
+        assert_eq!(res.1.rc, 0);
+        assert_eq!(count(&server, 3), 3);
+    }
+
+    #[tokio::test]
+    async fn max_attempts() {
+        let server = server();
+        script_searches(&server, &[51, 51, 51, 51]);
+        server.script(14, |id| vec![(id, response(15, 52, "unavailable"), None)]);
+        let mut ldap = retrying(&server);
+        let res = ldap
+            .search(
+                "dc=example,dc=org",
+                Scope::Base,
+                "(objectClass=*)",
+                vec!["1.1"],
+            )
+            .await
+            .unwrap();
+        assert_eq!(res.1.rc, 51);
+        assert_eq!(count(&server, 3), 3);
+        let res = ldap.compare(ADMIN_DN, "cn", "admin").await.unwrap();
+        assert_eq!(res.0.rc, 52);
+        assert_eq!(count(&server, 14), 3);
+    }
+
+    #[tokio::test]
+    async fn permanent_results() {
+        let server = server();
+        script_searches(&server, &[32, 51]);
+        let mut ldap = retrying(&server);
+        let res = ldap
+            .search(
+                "dc=example,dc=org",
+                Scope::Base,
+                "(objectClass=*)",
+                vec!["1.1"],
+            )
+            .await
+            .unwrap();
+        assert_eq!(res.1.rc, 32);
+        assert_eq!(count(&server, 3), 1);
+        let res = ldap.simple_bind(ADMIN_DN, "wrong").await.unwrap();
+        assert_eq!(res.rc, 49);
+        assert_eq!(count(&server, 0), 1);
+        let res = ldap.compare(ADMIN_DN, "cn", "other").await.unwrap();
+        assert_eq!(res.0.rc, 5);
+        assert_eq!(count(&server, 14), 1);
+    }
+
+    #[tokio::test]
+    async fn reconnect_and_rebind() {
+        let server = server();
+        let url = server.listen().await;
+        let mut ldap = RetryingLdap::connect(&url, LdapConnSettings::new(), policy())
+            .await
+            .unwrap();
+        ldap.simple_bind(ADMIN_DN, "secret")
+            .await
+            .unwrap()
+            .success()
+            .unwrap();
+        server.drop_connection(3, 1);
+        let res = ldap
+            .search(
+                "dc=example,dc=org",
+                Scope::Base,
+                "(objectClass=*)",
+                vec!["1.1"],
+            )
+            .await
+            .unwrap();
+        assert_eq!(res.1.rc, 0);
+        assert_eq!(res.0.len(), 1);
+        let ops: Vec<_> = server.received().iter().map(|r| r.0).collect();
+        assert_eq!(ops, vec![0, 3, 0, 3]);
+        assert_eq!(ldap.ldap().bound_identity().as_deref(), Some(ADMIN_DN));
+        // Without a reconnection URL, the broken handle stays, and the attempts run out.
+        let server = self::server();
+        let mut ldap = retrying(&server);
+        server.drop_connection(3, 1);
+        let err = ldap
+            .search(
+                "dc=example,dc=org",
+                Scope::Base,
+                "(objectClass=*)",
+                vec!["1.1"],
+            )
+            .await
+            .unwrap_err();
+        assert!(err.is_transient(), "{:?}", err);
+        assert_eq!(count(&server, 3), 1);
+    }
+}
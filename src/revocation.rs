@@ -0,0 +1,311 @@
+// Certificate revocation checking against CRLs supplied in the connection settings.
+//
+// The check runs after the TLS handshake, on the certificates presented by the server,
+// and before anything else is sent over the secure channel. A certificate is looked up
+// by its serial number in the CRLs whose issuer matches the certificate's issuer. A CRL
+// which isn't yet or no longer valid, judging by its thisUpdate and nextUpdate times,
+// doesn't vouch for a certificate. CRL signatures aren't checked: the CRLs come from
+// the local configuration, and are trusted as such (see RevocationPolicy).
+
+use crate::conn::RevocationPolicy;
+use crate::result::{LdapError, Result};
+
+use lber::common::TagClass;
+use lber::parse::parse_tag;
+use lber::structure::StructureTag;
+use lber::structures::{GeneralizedTime, UtcTime};
+use lber::IResult;
+use log::warn;
+
+// Universal tag numbers of interest.
+const INTEGER: u64 = 2;
+const SEQUENCE: u64 = 16;
+const UTC_TIME: u64 = 23;
+const GENERALIZED_TIME: u64 = 24;
+
+pub(crate) struct Crl {
+    issuer: StructureTag,
+    // Validity period, in seconds since the Unix epoch. An absent nextUpdate means
+    // that the issuer doesn't promise another CRL.
+    this_update: i64,
+    next_update: Option<i64>,
+    revoked: Vec<Vec<u8>>,
+}
+
+// Unix time of an X.509 time value.
+fn unix_time(tag: &StructureTag) -> Option<i64> {
+    if tag.class != TagClass::Universal {
+        return None;
+    }
+    let bytes = tag.clone().expect_primitive()?;
+    let time = match tag.id {
+        UTC_TIME => UtcTime::from_bytes(&bytes).ok()?.inner,
+        GENERALIZED_TIME => GeneralizedTime::from_bytes(&bytes).ok()?.inner,
+        _ => return None,
+    };
+    time.unix_timestamp()
+}
+
+impl Crl {
+    fn parse(der: &[u8]) -> Option<Crl> {
+        let mut crl = parse_der(der)?.expect_constructed()?.into_iter();
+        let mut tbs = crl.next()?.expect_constructed()?.into_iter().peekable();
+        if tbs.peek().is_some_and(|t| is_universal(t, INTEGER)) {
+            tbs.next();
+        }
+        tbs.next()?;
+        let issuer = tbs.next()?;
+        let this_update = unix_time(&tbs.next()?)?;
+        let next_update = match tbs.peek().map(unix_time) {
+            Some(Some(time)) => {
+                tbs.next();
+                Some(time)
+            }
+            _ => None,
+        };
+        // What remains are the optional revokedCertificates (the only universal
+        // SEQUENCE), and crlExtensions (context-tagged).
+        let mut revoked = vec![];
+        for elem in tbs.filter(|t| is_universal(t, SEQUENCE)) {
+            for entry in elem.expect_constructed()? {
+                let serial = entry
+                    .expect_constructed()?
+                    .into_iter()
+                    .next()?
+                    .expect_primitive()?;
+                revoked.push(serial);
+            }
+        }
+        Some(Crl {
+            issuer,
+            this_update,
+            next_update,
+            revoked,
+        })
+    }
+}
+
+fn parse_der(der: &[u8]) -> Option<StructureTag> {
+    match parse_tag(der) {
+        IResult::Done(_, tag) => Some(tag),
+        _ => None,
+    }
+}
+
+fn is_universal(tag: &StructureTag, id: u64) -> bool {
+    tag.class == TagClass::Universal && tag.id == id
+}
+
+// Serial number and issuer name of a certificate.
+fn cert_id(der: &[u8]) -> Option<(Vec<u8>, StructureTag)> {
+    let mut tbs = parse_der(der)?
+        .expect_constructed()?
+        .into_iter()
+        .next()?
+        .expect_constructed()?
+        .into_iter()
+        .peekable();
+    if tbs
+        .peek()
+        .is_some_and(|t| t.class == TagClass::Context && t.id == 0)
+    {
+        tbs.next();
+    }
+    let serial = tbs.next()?.expect_primitive()?;
+    tbs.next()?;
+    let issuer = tbs.next()?;
+    Some((serial, issuer))
+}
+
+fn unknown(policy: RevocationPolicy, msg: &str) -> Result<()> {
+    match policy {
+        RevocationPolicy::HardFail => Err(LdapError::Revocation(String::from(msg))),
+        RevocationPolicy::SoftFail => {
+            warn!("revocation check: {}", msg);
+            Ok(())
+        }
+    }
+}
+
+pub(crate) fn load(crls: &[Vec<u8>], policy: RevocationPolicy) -> Result<Vec<Crl>> {
+    let mut parsed = Vec::with_capacity(crls.len());
+    for der in crls {
+        match Crl::parse(der) {
+            Some(crl) => parsed.push(crl),
+            None => unknown(policy, "malformed CRL")?,
+        }
+    }
+    Ok(parsed)
+}
+
+// Check the server's certificate chain, leaf first, at the Unix time `now`. Intermediate
+// certificates are checked if there is a CRL for their issuer; the leaf must be covered
+// by a current CRL, or the status is unknown, which the policy decides about. A revoked
+// certificate is rejected even if the CRL listing it is out of date.
+pub(crate) fn check(
+    chain: &[Vec<u8>],
+    crls: &[Crl],
+    policy: RevocationPolicy,
+    now: i64,
+) -> Result<()> {
+    if chain.is_empty() {
+        return unknown(policy, "no server certificate");
+    }
+    for (n, cert) in chain.iter().enumerate() {
+        let (serial, issuer) = match cert_id(cert) {
+            Some(id) => id,
+            None if n == 0 => return unknown(policy, "malformed server certificate"),
+            None => continue,
+        };
+        let mut covered = false;
+        let mut stale = false;
+        for crl in crls.iter().filter(|crl| crl.issuer == issuer) {
+            if crl.revoked.contains(&serial) {
+                return Err(LdapError::Revocation(if n == 0 {
+                    String::from("server certificate revoked")
+                } else {
+                    format!("certificate {} in the server chain revoked", n)
+                }));
+            }
+            if crl.this_update > now || crl.next_update.is_some_and(|next| next < now) {
+                stale = true;
+            } else {
+                covered = true;
+            }
+        }
+        if n == 0 && !covered {
+            unknown(
+                policy,
+                if stale {
+                    "CRL for the server certificate issuer out of date"
+                } else {
+                    "no CRL for the server certificate issuer"
+                },
+            )?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{check, load};
+    use crate::conn::RevocationPolicy;
+    use crate::result::LdapError;
+
+    use bytes::BytesMut;
+    use lber::common::TagClass;
+    use lber::structures::{ASNTag, Integer, OctetString, Sequence, Tag};
+    use lber::write;
+
+    fn seq(inner: Vec<Tag>) -> Tag {
+        Tag::Sequence(Sequence {
+            inner,
+            ..Default::default()
+        })
+    }
+
+    fn int(inner: i64) -> Tag {
+        Tag::Integer(Integer {
+            inner,
+            ..Default::default()
+        })
+    }
+
+    fn name(cn: &str) -> Tag {
+        seq(vec![Tag::OctetString(OctetString {
+            inner: Vec::from(cn),
+            ..Default::default()
+        })])
+    }
+
+    fn time(utc: &str) -> Tag {
+        Tag::OctetString(OctetString {
+            id: 23,
+            inner: Vec::from(utc),
+            ..Default::default()
+        })
+    }
+
+    fn der(tag: Tag) -> Vec<u8> {
+        let mut buf = BytesMut::new();
+        write::encode_into(&mut buf, tag.into_structure()).unwrap();
+        buf.to_vec()
+    }
+
+    fn cert(serial: i64, issuer: &str) -> Vec<u8> {
+        der(seq(vec![
+            seq(vec![
+                Tag::Sequence(Sequence {
+                    class: TagClass::Context,
+                    id: 0,
+                    inner: vec![int(2)],
+                }),
+                int(serial),
+                seq(vec![]),
+                name(issuer),
+                seq(vec![time("210101000000Z"), time("310101000000Z")]),
+                name("server"),
+            ]),
+            seq(vec![]),
+        ]))
+    }
+
+    fn crl(issuer: &str, revoked: &[i64]) -> Vec<u8> {
+        der(seq(vec![
+            seq(vec![
+                int(1),
+                seq(vec![]),
+                name(issuer),
+                time("210101000000Z"),
+                time("210103000000Z"),
+                seq(revoked
+                    .iter()
+                    .map(|&s| seq(vec![int(s), time("210101000000Z")]))
+                    .collect()),
+            ]),
+            seq(vec![]),
+        ]))
+    }
+
+    // 2021-01-02 00:00:00 UTC, within the validity of the synthetic CRLs.
+    const NOW: i64 = 1_609_545_600;
+
+    #[test]
+    fn crl_lookup() {
+        let crls = load(&[crl("CA", &[5, 7])], RevocationPolicy::HardFail).unwrap();
+        let hard = RevocationPolicy::HardFail;
+        let soft = RevocationPolicy::SoftFail;
+        assert!(check(&[cert(6, "CA")], &crls, hard, NOW).is_ok());
+        assert!(check(&[cert(7, "CA")], &crls, soft, NOW).is_err());
+        assert!(check(&[cert(7, "Other")], &crls, hard, NOW).is_err());
+        assert!(check(&[cert(7, "Other")], &crls, soft, NOW).is_ok());
+        assert!(load(&[vec![0x30, 0x00]], hard).is_err());
+        // Out of date in either direction.
+        assert!(check(&[cert(6, "CA")], &crls, hard, NOW - 2 * 86_400).is_err());
+        assert!(check(&[cert(6, "CA")], &crls, hard, NOW + 2 * 86_400).is_err());
+        assert!(check(&[cert(6, "CA")], &crls, soft, NOW + 2 * 86_400).is_ok());
+    }
+
+    // Certificates and a CRL produced by openssl, see data/README.md.
+    #[test]
+    fn openssl_crl() {
+        let crl = include_bytes!("../data/crl/ca.crl.der");
+        let revoked = [include_bytes!("../data/crl/revoked.der").to_vec()];
+        let good = [include_bytes!("../data/crl/good.der").to_vec()];
+        let hard = RevocationPolicy::HardFail;
+        let soft = RevocationPolicy::SoftFail;
+        let crls = load(&[crl.to_vec()], hard).unwrap();
+        // 2030-01-01 00:00:00 UTC, before nextUpdate in 2126.
+        let now = 1_893_456_000;
+        assert!(matches!(
+            check(&revoked, &crls, hard, now),
+            Err(LdapError::Revocation(_))
+        ));
+        assert!(check(&good, &crls, hard, now).is_ok());
+        // Revoked regardless of the policy and the age of the CRL.
+        assert!(check(&revoked, &crls, soft, i64::MAX).is_err());
+        assert!(check(&good, &crls, hard, i64::MAX).is_err());
+        assert!(check(&good, &crls, soft, i64::MAX).is_ok());
+    }
+}
@@ -0,0 +1,356 @@
+//! Routing operations between a writable server and read-only replicas.
+//!
+//! A common directory deployment has a single writable server, the master or provider,
+//! and a number of read-only replicas, which take most of the search load. With
+//! [`RoutedLdap`](struct.RoutedLdap.html), an application can use such a setup through
+//! a single handle: Searches and Compares are distributed among the replicas in
+//! round-robin fashion, while updates and extended operations go to the master.
+//!
+//! A read-only server asked to perform an update usually answers with the result code
+//! `referral` (10), naming the writable server. Such a result is followed automatically:
+//! a connection is opened to the server named by the first referral URL, bound with the
+//! credentials from the [rebind function](../struct.LdapConnSettings.html#method.set_rebind_fn),
+//! if it supplies them, or else with those of the last simple Bind through the handle,
+//! and the operation is repeated there. Only one referral is followed for each operation,
+//! and connections opened for referrals are kept for reuse.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! use ldap3::routing::RoutedLdap;
+//! use ldap3::{LdapConnSettings, Mod, Scope};
+//! # use ldap3::result::Result;
+//! # use maplit::hashset;
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<()> {
+//! let mut ldap = RoutedLdap::connect(
+//!     "ldap://master.example.org",
+//!     &["ldap://replica1.example.org", "ldap://replica2.example.org"],
+//!     LdapConnSettings::new(),
+//! )
+//! .await?;
+//! ldap.simple_bind("cn=Manager,dc=example,dc=org", "secret").await?.success()?;
+//! let (rs, _res) = ldap
+//!     .search("dc=example,dc=org", Scope::Subtree, "(uid=jdoe)", vec!["dn"])
+//!     .await?
+//!     .success()?;
+//! ldap.modify(
+//!     "uid=jdoe,ou=People,dc=example,dc=org",
+//!     vec![Mod::Replace("mail", hashset! {"jdoe@example.org"})],
+//! )
+//! .await?
+//! .success()?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+
+use crate::conn::{LdapConnAsync, LdapConnSettings, RebindCredentials};
+use crate::exop::Exop;
+use crate::ldap::{Ldap, Mod};
+use crate::result::{CompareResult, ExopResult, LdapResult, Result, SearchResult};
+use crate::search::Scope;
+
+use url::Url;
+
+// Server part of a referral URL, if it's a usable LDAP URL.
+fn referral_server(refs: &[String]) -> Option<String> {
+    let url = Url::parse(refs.first()?).ok()?;
+    if !matches!(url.scheme(), "ldap" | "ldaps") {
+        return None;
+    }
+    let mut server = format!("{}://{}", url.scheme(), url.host_str()?);
+    if let Some(port) = url.port() {
+        server.push_str(&format!(":{}", port));
+    }
+    Some(server)
+}
+
+/// Handle routing reads to replicas and updates to the master.
+///
+/// See the [module-level documentation](index.html) for the overview.
+pub struct RoutedLdap {
+    master: Ldap,
+    replicas: Vec<Ldap>,
+    next: usize,
+    settings: LdapConnSettings,
+    bind: Option<(String, String)>,
+    referred: HashMap<String, Ldap>,
+}
+
+impl RoutedLdap {
+    /// Create a routing handle from already connected handles. Connections opened for
+    /// following referrals use `settings`.
+    pub fn new(master: Ldap, replicas: Vec<Ldap>, settings: LdapConnSettings) -> Self {
+        RoutedLdap {
+            master,
+            replicas,
+            next: 0,
+            settings,
+            bind: None,
+            referred: HashMap::new(),
+        }
+    }
+
+    /// Connect to the master and all replicas with `settings`, and spawn the connections
+    /// on the current Tokio runtime. Failure to connect to any server is an error.
+    pub async fn connect<S: AsRef<str>>(
+        master_url: &str,
+        replica_urls: &[S],
+        settings: LdapConnSettings,
+    ) -> Result<Self> {
+        let master = Self::open(master_url, &settings).await?;
+        let mut replicas = Vec::with_capacity(replica_urls.len());
+        for url in replica_urls {
+            replicas.push(Self::open(url.as_ref(), &settings).await?);
+        }
+        Ok(Self::new(master, replicas, settings))
+    }
+
+    async fn open(url: &str, settings: &LdapConnSettings) -> Result<Ldap> {
+        let (conn, ldap) = LdapConnAsync::with_settings(settings.clone(), url).await?;
+        crate::drive!(conn);
+        Ok(ldap)
+    }
+
+    /// Return the handle of the master.
+    pub fn master(&mut self) -> &mut Ldap {
+        &mut self.master
+    }
+
+    /// Return the handle of the next replica in turn, or the master if there are
+    /// no replicas.
+    pub fn replica(&mut self) -> &mut Ldap {
+        if self.replicas.is_empty() {
+            return &mut self.master;
+        }
+        let n = self.next % self.replicas.len();
+        self.next = self.next.wrapping_add(1);
+        &mut self.replicas[n]
+    }
+
+    // Return the handle for the server named by the referral in `res`, connecting and
+    // binding if necessary. `None` means that the result isn't a referral, or that
+    // it can't be followed.
+    async fn follow(&mut self, res: &LdapResult) -> Result<Option<&mut Ldap>> {
+        if res.rc != 10 {
+            return Ok(None);
+        }
+        let server = match referral_server(&res.refs) {
+            Some(server) => server,
+            None => return Ok(None),
+        };
+        if self
+            .referred
+            .get_mut(&server)
+            .is_some_and(|ldap| ldap.is_closed())
+        {
+            self.referred.remove(&server);
+        }
+        if !self.referred.contains_key(&server) {
+            debug!("following referral to {}", server);
+            let mut ldap = Self::open(&server, &self.settings).await?;
+            let creds = self.settings.rebind_credentials(&server)?.or_else(|| {
+                self.bind
+                    .clone()
+                    .map(|(dn, password)| RebindCredentials::Simple { dn, password })
+            });
+            if let Some(creds) = creds {
+                creds.bind(&mut ldap).await?;
+            }
+            self.referred.insert(server.clone(), ldap);
+        }
+        Ok(self.referred.get_mut(&server))
+    }
+
+    /// Do a simple Bind on the master and all replicas. The first unsuccessful result
+    /// is returned; if all are successful, the result from the master. The credentials
+    /// are remembered, and used for binding connections opened for referrals.
+    ///
+    /// Connections already opened for referrals are closed, since they're bound with
+    /// the previous credentials.
+    pub async fn simple_bind(&mut self, bind_dn: &str, bind_pw: &str) -> Result<LdapResult> {
+        self.referred.clear();
+        let res = self.master.simple_bind(bind_dn, bind_pw).await?;
+        if res.rc != 0 {
+            return Ok(res);
+        }
+        for replica in &mut self.replicas {
+            let replica_res = replica.simple_bind(bind_dn, bind_pw).await?;
+            if replica_res.rc != 0 {
+                return Ok(replica_res);
+            }
+        }
+        self.bind = Some((bind_dn.to_owned(), bind_pw.to_owned()));
+        Ok(res)
+    }
+
+    /// Perform a Search on a replica. See [`Ldap::search()`](../struct.Ldap.html#method.search).
+    pub async fn search<'a, S: AsRef<str> + Clone + Send + Sync + 'a>(
+        &mut self,
+        base: &str,
+        scope: Scope,
+        filter: &str,
+        attrs: Vec<S>,
+    ) -> Result<SearchResult> {
+        let res = self
+            .replica()
+            .search(base, scope, filter, attrs.clone())
+            .await?;
+        match self.follow(&res.1).await? {
+            Some(ldap) => ldap.search(base, scope, filter, attrs).await,
+            None => Ok(res),
+        }
+    }
+
+    /// Perform a Compare on a replica. See [`Ldap::compare()`](../struct.Ldap.html#method.compare).
+    pub async fn compare<B: AsRef<[u8]>>(
+        &mut self,
+        dn: &str,
+        attr: &str,
+        val: B,
+    ) -> Result<CompareResult> {
+        let res = self.replica().compare(dn, attr, val.as_ref()).await?;
+        match self.follow(&res.0).await? {
+            Some(ldap) => ldap.compare(dn, attr, val).await,
+            None => Ok(res),
+        }
+    }
+
+    /// Add an entry on the master. See [`Ldap::add()`](../struct.Ldap.html#method.add).
+    pub async fn add<S: AsRef<[u8]> + Eq + Hash + Clone>(
+        &mut self,
+        dn: &str,
+        attrs: Vec<(S, HashSet<S>)>,
+    ) -> Result<LdapResult> {
+        let res = self.master.add(dn, attrs.clone()).await?;
+        match self.follow(&res).await? {
+            Some(ldap) => ldap.add(dn, attrs).await,
+            None => Ok(res),
+        }
+    }
+
+    /// Modify an entry on the master. See [`Ldap::modify()`](../struct.Ldap.html#method.modify).
+    pub async fn modify<S: AsRef<[u8]> + Eq + Hash + Clone>(
+        &mut self,
+        dn: &str,
+        mods: Vec<Mod<S>>,
+    ) -> Result<LdapResult> {
+        let res = self.master.modify(dn, mods.clone()).await?;
+        match self.follow(&res).await? {
+            Some(ldap) => ldap.modify(dn, mods).await,
+            None => Ok(res),
+        }
+    }
+
+    /// Delete an entry on the master. See [`Ldap::delete()`](../struct.Ldap.html#method.delete).
+    pub async fn delete(&mut self, dn: &str) -> Result<LdapResult> {
+        let res = self.master.delete(dn).await?;
+        match self.follow(&res).await? {
+            Some(ldap) => ldap.delete(dn).await,
+            None => Ok(res),
+        }
+    }
+
+    /// Rename or move an entry on the master. See
+    /// [`Ldap::modifydn()`](../struct.Ldap.html#method.modifydn).
+    pub async fn modifydn(
+        &mut self,
+        dn: &str,
+        rdn: &str,
+        delete_old: bool,
+        new_sup: Option<&str>,
+    ) -> Result<LdapResult> {
+        let res = self.master.modifydn(dn, rdn, delete_old, new_sup).await?;
+        match self.follow(&res).await? {
+            Some(ldap) => ldap.modifydn(dn, rdn, delete_old, new_sup).await,
+            None => Ok(res),
+        }
+    }
+
+    /// Perform an Extended operation on the master. See
+    /// [`Ldap::extended()`](../struct.Ldap.html#method.extended).
+    pub async fn extended<E: Into<Exop>>(&mut self, exop: E) -> Result<ExopResult> {
+        let exop = exop.into();
+        let res = self.master.extended(exop.clone()).await?;
+        match self.follow(&res.1).await? {
+            Some(ldap) => ldap.extended(exop).await,
+            None => Ok(res),
+        }
+    }
+
+    /// Unbind all connections.
+    pub async fn unbind(&mut self) -> Result<()> {
+        for (_, mut ldap) in self.referred.drain() {
+            ldap.unbind().await?;
+        }
+        for replica in &mut self.replicas {
+            replica.unbind().await?;
+        }
+        self.master.unbind().await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::referral_server;
+
+    #[test]
+    fn referral_urls() {
+        let refs = vec![String::from(
+            "ldap://master.example.org:1389/dc=example,dc=org",
+        )];
+        assert_eq!(
+            referral_server(&refs).as_deref(),
+            Some("ldap://master.example.org:1389")
+        );
+        assert_eq!(referral_server(&[String::from("http://x")]), None);
+        assert_eq!(referral_server(&[]), None);
+    }
+}
+
+#[cfg(all(test, feature = "testkit"))]
+mod mock_test {
+    use super::RoutedLdap;
+    use crate::testkit::MockServer;
+    use crate::{LdapConnSettings, Scope};
+
+    use maplit::hashset;
+
+    #[tokio::test]
+    async fn routed_operations() {
+        let (master, replica) = (MockServer::new(), MockServer::new());
+        for server in &[&master, &replica] {
+            server.add_entry("dc=example,dc=org", vec![("dc", vec!["example"])]);
+        }
+        replica.add_entry(
+            "ou=Replica,dc=example,dc=org",
+            vec![("ou", vec!["Replica"])],
+        );
+        let (conn, master_ldap) = master.connect();
+        crate::drive!(conn);
+        let (conn, replica_ldap) = replica.connect();
+        crate::drive!(conn);
+        let mut ldap = RoutedLdap::new(master_ldap, vec![replica_ldap], LdapConnSettings::new());
+        let (rs, _res) = ldap
+            .search("dc=example,dc=org", Scope::OneLevel, "(ou=*)", vec!["ou"])
+            .await
+            .unwrap()
+            .success()
+            .unwrap();
+        assert_eq!(rs.len(), 1);
+        ldap.add(
+            "ou=Master,dc=example,dc=org",
+            vec![("ou", hashset! {"Master"})],
+        )
+        .await
+        .unwrap()
+        .success()
+        .unwrap();
+        assert!(master.entry("ou=Master,dc=example,dc=org").is_some());
+        assert!(replica.entry("ou=Master,dc=example,dc=org").is_none());
+    }
+}
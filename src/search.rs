@@ -1,21 +1,22 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fmt::Debug;
+use std::io;
 use std::sync::Arc;
 use std::time::Duration;
 
 use crate::adapters::Adapter;
-use crate::controls::Control;
-use crate::ldap::Ldap;
-use crate::parse_filter;
-use crate::protocol::LdapOp;
+use crate::controls::{Control, ControlType, PagedResults, VirtualListViewResp};
+use crate::exop::Exop;
+use crate::ldap::{wait_reply, Cancel, Ldap};
+use crate::protocol::{item_channel, ItemReceiver, LdapOp};
+use crate::request::{OpKind, Request, SearchRequest};
 use crate::result::{LdapError, LdapResult, Result};
+use crate::values::{AttrValue, FromLdapValue};
 
-use tokio::sync::{mpsc, Mutex};
-use tokio::time;
+use bytes::Bytes;
+use tokio::sync::Mutex;
 
-use lber::common::TagClass;
-use lber::structure::StructureTag;
-use lber::structures::{Boolean, Enumerated, Integer, OctetString, Sequence, Tag};
+use lber::structure::{StructureTag, PL};
 
 /// Possible values for search scope.
 #[derive(Clone, Copy, Debug, PartialEq)]
@@ -29,9 +30,10 @@ pub enum Scope {
 }
 
 /// Possible values for alias dereferencing during search.
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
 pub enum DerefAliases {
     /// Never dereference.
+    #[default]
     Never = 0,
     /// Dereference while retrieving objects according to search scope.
     Searching = 1,
@@ -41,17 +43,12 @@ pub enum DerefAliases {
     Always = 3,
 }
 
-impl Default for DerefAliases {
-    fn default() -> Self {
-        DerefAliases::Never
-    }
-}
-
 #[derive(Debug)]
 pub enum SearchItem {
     Entry(StructureTag),
     Referral(StructureTag),
     Done(LdapResult),
+    ExopDone(LdapResult, Exop),
 }
 
 /// Wrapper for the internal structure of a result entry.
@@ -74,6 +71,123 @@ impl ResultEntry {
     pub fn is_intermediate(&self) -> bool {
         self.0.id == 25
     }
+
+    // Value sets of the attributes named `attr`, which may appear more than once.
+    fn value_sets<'e>(
+        &'e mut self,
+        attr: &'e str,
+    ) -> impl Iterator<Item = &'e mut Vec<StructureTag>> {
+        let attrs = match self.0.payload {
+            PL::C(ref mut elems) if self.0.id == 4 => elems.get_mut(1),
+            _ => None,
+        };
+        attrs
+            .and_then(|attrs| match attrs.payload {
+                PL::C(ref mut attrs) => Some(attrs.iter_mut()),
+                PL::P(_) => None,
+            })
+            .into_iter()
+            .flatten()
+            .filter_map(move |part_attr| match part_attr.payload {
+                PL::C(ref mut elems) if elems.len() == 2 => {
+                    let (name, vals) = elems.split_at_mut(1);
+                    match (&name[0].payload, &mut vals[0].payload) {
+                        (PL::P(name), PL::C(vals))
+                            if name.eq_ignore_ascii_case(attr.as_bytes()) =>
+                        {
+                            Some(vals)
+                        }
+                        _ => None,
+                    }
+                }
+                _ => None,
+            })
+    }
+
+    /// Remove the values of `attr` from a search entry and return them as `Bytes`.
+    ///
+    /// The values are moved out of the decoded message, not copied, so a multi-megabyte
+    /// value, such as a `jpegPhoto`, is held in memory only once. The attribute remains
+    /// in the entry without values, which makes the entry unsuitable for
+    /// [`SearchEntry::construct()`](struct.SearchEntry.html#method.construct) if it's
+    /// expected to have values there; extract the other attributes first if they're needed.
+    ///
+    /// Returns an empty vector if the attribute isn't present or this isn't a search entry.
+    pub fn take_values(&mut self, attr: &str) -> Vec<Bytes> {
+        self.value_sets(attr)
+            .flat_map(std::mem::take)
+            .filter_map(|val| match val.payload {
+                PL::P(val) => Some(Bytes::from(val)),
+                PL::C(_) => None,
+            })
+            .collect()
+    }
+
+    /// Write the values of `attr` to `w`, one after another, without constructing the
+    /// [`SearchEntry`](struct.SearchEntry.html) or copying the values. Returns the number
+    /// of values written.
+    ///
+    /// This is meant for single-valued binary attributes whose values are large, and
+    /// should go directly to a file or socket.
+    pub fn write_values<W: io::Write>(&mut self, attr: &str, w: &mut W) -> io::Result<usize> {
+        let mut count = 0;
+        for vals in self.value_sets(attr) {
+            for val in vals.iter() {
+                if let PL::P(ref val) = val.payload {
+                    w.write_all(val)?;
+                    count += 1;
+                }
+            }
+        }
+        Ok(count)
+    }
+}
+
+/// Attribute list element selecting all user attributes.
+pub const ALL_USER_ATTRS: &str = "*";
+
+/// Attribute list element selecting all operational attributes.
+pub const ALL_OPERATIONAL_ATTRS: &str = "+";
+
+/// Attribute list element requesting no attributes. It must be the only element of the list.
+pub const NO_ATTRS: &str = "1.1";
+
+/// Element of a Search attribute list.
+///
+/// The special selectors can be combined with attribute names in the same list:
+///
+/// ```rust
+/// use ldap3::Attr;
+///
+/// let attrs = vec![Attr::AllUser, Attr::from("modifyTimestamp")];
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Attr<'a> {
+    /// All user attributes, `"*"`.
+    AllUser,
+    /// All operational attributes, `"+"`.
+    AllOperational,
+    /// No attributes, `"1.1"`.
+    NoAttrs,
+    /// Attribute given by name, OID, or any other description the server understands.
+    Name(&'a str),
+}
+
+impl<'a> From<&'a str> for Attr<'a> {
+    fn from(name: &'a str) -> Self {
+        Attr::Name(name)
+    }
+}
+
+impl AsRef<str> for Attr<'_> {
+    fn as_ref(&self) -> &str {
+        match *self {
+            Attr::AllUser => ALL_USER_ATTRS,
+            Attr::AllOperational => ALL_OPERATIONAL_ATTRS,
+            Attr::NoAttrs => NO_ATTRS,
+            Attr::Name(name) => name,
+        }
+    }
 }
 
 /// Additional parameters for the Search operation.
@@ -84,6 +198,8 @@ pub struct SearchOptions {
     pub typesonly: bool,
     pub timelimit: i32,
     pub sizelimit: i32,
+    pub operational: bool,
+    pub binary_transfer: bool,
 }
 
 impl SearchOptions {
@@ -120,6 +236,25 @@ impl SearchOptions {
         self.sizelimit = sizelimit;
         self
     }
+
+    /// Request all operational attributes in addition to those in the attribute list.
+    /// If the list is empty, which normally selects all user attributes, they are still
+    /// returned.
+    pub fn include_operational(mut self, operational: bool) -> Self {
+        self.operational = operational;
+        self
+    }
+
+    /// Request the attributes with certificate syntaxes, such as `userCertificate` and
+    /// `certificateRevocationList`, with the `;binary` transfer option, which some servers
+    /// require for returning them. The option is added to every such attribute in the list
+    /// which doesn't already have it. Since the returned attribute descriptions will also
+    /// have the option, [`ValueEntry`](struct.ValueEntry.html), which removes it, is the
+    /// most convenient way of processing the entries.
+    pub fn binary_transfer(mut self, binary_transfer: bool) -> Self {
+        self.binary_transfer = binary_transfer;
+        self
+    }
 }
 
 /// Parsed search result entry.
@@ -135,7 +270,7 @@ impl SearchOptions {
 /// possible that a particular set of values for a binary attribute _could_ be
 /// converted into UTF-8 `String`s, the presence of of such attribute in the result
 /// entry should be checked for both in `attrs` and `bin_atrrs`.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct SearchEntry {
     /// Entry DN.
     pub dn: String,
@@ -143,34 +278,41 @@ pub struct SearchEntry {
     pub attrs: HashMap<String, Vec<String>>,
     /// Binary-valued attributes.
     pub bin_attrs: HashMap<String, Vec<Vec<u8>>>,
+    /// Controls attached to the entry, such as Entry Change Notification or
+    /// Sync State. Controls recognized by the library have their type in the first
+    /// component of each element.
+    pub ctrls: Vec<Control>,
 }
 
-impl SearchEntry {
-    /// Parse raw BER data and convert it into attribute map(s).
-    ///
-    /// __Note__: this function will panic on parsing error.
-    pub fn construct(re: ResultEntry) -> SearchEntry {
-        let mut tags =
-            re.0.match_id(4)
-                .and_then(|t| t.expect_constructed())
-                .expect("entry")
-                .into_iter();
-        let dn = String::from_utf8(
-            tags.next()
-                .expect("element")
-                .expect_primitive()
-                .expect("octet string"),
-        )
-        .expect("dn");
-        let mut attr_vals = HashMap::new();
-        let mut bin_attr_vals = HashMap::new();
-        let attrs = tags
-            .next()
+// DN, attributes with raw values, and controls of a result entry. The attributes are
+// extracted as they're iterated over, without collecting them first. Panics on parsing error.
+fn entry_parts(
+    re: ResultEntry,
+) -> (
+    String,
+    impl ExactSizeIterator<Item = (String, Vec<Vec<u8>>)>,
+    Vec<Control>,
+) {
+    let ResultEntry(tag, ctrls) = re;
+    let mut tags = tag
+        .match_id(4)
+        .and_then(|t| t.expect_constructed())
+        .expect("entry")
+        .into_iter();
+    let dn = String::from_utf8(
+        tags.next()
             .expect("element")
-            .expect_constructed()
-            .expect("attrs")
-            .into_iter();
-        for a_v in attrs {
+            .expect_primitive()
+            .expect("octet string"),
+    )
+    .expect("dn");
+    let attrs = tags
+        .next()
+        .expect("element")
+        .expect_constructed()
+        .expect("attrs")
+        .into_iter()
+        .map(|a_v| {
             let mut part_attr = a_v
                 .expect_constructed()
                 .expect("partial attribute")
@@ -183,7 +325,6 @@ impl SearchEntry {
                     .expect("octet string"),
             )
             .expect("attribute type");
-            let mut any_binary = false;
             let values = part_attr
                 .next()
                 .expect("element")
@@ -191,37 +332,308 @@ impl SearchEntry {
                 .expect("values")
                 .into_iter()
                 .map(|t| t.expect_primitive().expect("octet string"))
-                .filter_map(|s| {
-                    if let Ok(s) = std::str::from_utf8(s.as_ref()) {
-                        return Some(s.to_owned());
-                    }
-                    bin_attr_vals
-                        .entry(a_type.clone())
-                        .or_insert_with(Vec::new)
-                        .push(s);
-                    any_binary = true;
-                    None
-                })
-                .collect::<Vec<String>>();
-            if any_binary {
-                bin_attr_vals.get_mut(&a_type).expect("bin vector").extend(
-                    values
-                        .into_iter()
-                        .map(String::into_bytes)
-                        .collect::<Vec<Vec<u8>>>(),
-                );
-            } else {
-                attr_vals.insert(a_type, values);
+                .collect();
+            (a_type, values)
+        });
+    (dn, attrs, ctrls)
+}
+
+// Convert the values to strings if they're all valid UTF-8, reusing their buffers. Otherwise,
+// return them unchanged, converting back the ones which have already been turned into strings.
+fn utf8_values(values: Vec<Vec<u8>>) -> std::result::Result<Vec<String>, Vec<Vec<u8>>> {
+    let mut strings = Vec::with_capacity(values.len());
+    let mut values = values.into_iter();
+    while let Some(v) = values.next() {
+        match String::from_utf8(v) {
+            Ok(s) => strings.push(s),
+            Err(e) => {
+                let mut bytes: Vec<Vec<u8>> = strings.into_iter().map(String::into_bytes).collect();
+                bytes.push(e.into_bytes());
+                bytes.extend(values);
+                return Err(bytes);
+            }
+        }
+    }
+    Ok(strings)
+}
+
+// Attributes with certificate syntaxes, which must be transferred with the ;binary
+// option (RFC 4523, section 2.1).
+const BINARY_TRANSFER_ATTRS: &[&str] = &[
+    "userCertificate",
+    "cACertificate",
+    "crossCertificatePair",
+    "certificateRevocationList",
+    "authorityRevocationList",
+    "deltaRevocationList",
+    "userSMIMECertificate",
+];
+
+// Add the ;binary option to the known certificate attributes which don't already have it.
+pub(crate) fn with_binary_option(attr: String) -> String {
+    let name = attr.split(';').next().unwrap_or_default();
+    let has_binary = attr
+        .split(';')
+        .skip(1)
+        .any(|o| o.eq_ignore_ascii_case("binary"));
+    if !has_binary
+        && BINARY_TRANSFER_ATTRS
+            .iter()
+            .any(|a| a.eq_ignore_ascii_case(name))
+    {
+        attr + ";binary"
+    } else {
+        attr
+    }
+}
+
+// Remove the ;binary transfer option from an attribute description.
+fn without_binary_option(attr: String) -> String {
+    if !attr.contains(';') {
+        return attr;
+    }
+    attr.split(';')
+        .enumerate()
+        .filter(|&(i, o)| i == 0 || !o.eq_ignore_ascii_case("binary"))
+        .map(|(_, o)| o)
+        .collect::<Vec<_>>()
+        .join(";")
+}
+
+impl SearchEntry {
+    /// Parse raw BER data and convert it into attribute map(s).
+    ///
+    /// __Note__: this function will panic on parsing error.
+    pub fn construct(re: ResultEntry) -> SearchEntry {
+        let (dn, attrs, ctrls) = entry_parts(re);
+        let mut attr_vals = HashMap::with_capacity(attrs.len());
+        let mut bin_attr_vals = HashMap::new();
+        for (a_type, values) in attrs {
+            match utf8_values(values) {
+                Ok(values) => {
+                    attr_vals.insert(a_type, values);
+                }
+                Err(values) => {
+                    bin_attr_vals.insert(a_type, values);
+                }
             }
         }
         SearchEntry {
             dn,
             attrs: attr_vals,
             bin_attrs: bin_attr_vals,
+            ctrls,
+        }
+    }
+
+    // Values of an attribute, looked up first exactly and then case-insensitively.
+    fn raw_values(&self, attr: &str) -> Vec<&[u8]> {
+        fn lookup<'e, V>(map: &'e HashMap<String, Vec<V>>, attr: &str) -> Option<&'e Vec<V>> {
+            map.get(attr).or_else(|| {
+                map.iter()
+                    .find(|(k, _)| k.eq_ignore_ascii_case(attr))
+                    .map(|(_, v)| v)
+            })
+        }
+        if let Some(vals) = lookup(&self.attrs, attr) {
+            return vals.iter().map(|v| v.as_bytes()).collect();
+        }
+        lookup(&self.bin_attrs, attr)
+            .map(|vals| vals.iter().map(Vec::as_slice).collect())
+            .unwrap_or_default()
+    }
+
+    /// Convert the first value of `attr` to the type `T`. Returns `None` if the entry
+    /// doesn't have the attribute, and an error if the value can't be converted. The
+    /// attribute name is matched case-insensitively, and values are looked up in both
+    /// `attrs` and `bin_attrs`. See the [`values`](values/index.html) module for the
+    /// available conversions.
+    pub fn attr_as<T: FromLdapValue>(&self, attr: &str) -> Result<Option<T>> {
+        self.raw_values(attr)
+            .first()
+            .map(|v| T::from_ldap_value(v))
+            .transpose()
+    }
+
+    /// Convert all values of `attr` to the type `T`. Returns an empty vector if the
+    /// entry doesn't have the attribute, and an error if any value can't be converted.
+    pub fn attrs_as<T: FromLdapValue>(&self, attr: &str) -> Result<Vec<T>> {
+        self.raw_values(attr)
+            .into_iter()
+            .map(T::from_ldap_value)
+            .collect()
+    }
+}
+
+/// Parsed search result entry with a single attribute map.
+///
+/// Unlike [`SearchEntry`](struct.SearchEntry.html), which splits the attributes
+/// in two maps depending on whether all their values are valid UTF-8, this structure
+/// keeps every value as an [`AttrValue`](values/struct.AttrValue.html), which holds
+/// the bytes as received and can be viewed as a string if it's valid UTF-8. That's
+/// more convenient for generic processing of entries.
+///
+/// The `;binary` transfer option is removed from attribute descriptions, so that
+/// `userCertificate;binary` in the result appears as `userCertificate`. See
+/// [`SearchOptions::binary_transfer()`](struct.SearchOptions.html#method.binary_transfer)
+/// for requesting it.
+#[derive(Debug, Clone)]
+pub struct ValueEntry {
+    /// Entry DN.
+    pub dn: String,
+    /// Attributes.
+    pub attrs: HashMap<String, Vec<AttrValue>>,
+    /// Controls attached to the entry.
+    pub ctrls: Vec<Control>,
+}
+
+impl ValueEntry {
+    /// Parse raw BER data and convert it into the attribute map.
+    ///
+    /// __Note__: this function will panic on parsing error.
+    pub fn construct(re: ResultEntry) -> ValueEntry {
+        let (dn, attrs, ctrls) = entry_parts(re);
+        let mut attr_vals: HashMap<String, Vec<AttrValue>> = HashMap::with_capacity(attrs.len());
+        for (a_type, values) in attrs {
+            attr_vals
+                .entry(without_binary_option(a_type))
+                .or_default()
+                .extend(values.into_iter().map(AttrValue::from));
+        }
+        ValueEntry {
+            dn,
+            attrs: attr_vals,
+            ctrls,
+        }
+    }
+
+    /// Return the values of `attr`, matching the name case-insensitively if there's
+    /// no exact match.
+    pub fn get(&self, attr: &str) -> Option<&[AttrValue]> {
+        self.attrs
+            .get(attr)
+            .or_else(|| {
+                self.attrs
+                    .iter()
+                    .find(|(k, _)| k.eq_ignore_ascii_case(attr))
+                    .map(|(_, v)| v)
+            })
+            .map(Vec::as_slice)
+    }
+}
+
+impl From<SearchEntry> for ValueEntry {
+    fn from(entry: SearchEntry) -> Self {
+        let mut attrs: HashMap<String, Vec<AttrValue>> = HashMap::new();
+        for (attr, vals) in entry.attrs {
+            attrs
+                .entry(without_binary_option(attr))
+                .or_default()
+                .extend(vals.into_iter().map(AttrValue::from));
+        }
+        for (attr, vals) in entry.bin_attrs {
+            attrs
+                .entry(without_binary_option(attr))
+                .or_default()
+                .extend(vals.into_iter().map(AttrValue::from));
+        }
+        ValueEntry {
+            dn: entry.dn,
+            attrs,
+            ctrls: entry.ctrls,
         }
     }
 }
 
+/// Set of attribute names shared by the entries of a result set.
+///
+/// In a large result set, the same attribute names repeat in every entry. Entries
+/// constructed as [`InternedEntry`](struct.InternedEntry.html) with the same interner
+/// share a single allocation for each distinct name, instead of owning a copy.
+#[derive(Clone, Debug, Default)]
+pub struct AttrInterner {
+    names: HashSet<Arc<str>>,
+}
+
+impl AttrInterner {
+    /// Create an empty interner.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Return the shared instance of `name`, adding it if it's not yet present.
+    pub fn intern(&mut self, name: &str) -> Arc<str> {
+        if let Some(name) = self.names.get(name) {
+            return name.clone();
+        }
+        let name: Arc<str> = Arc::from(name);
+        self.names.insert(name.clone());
+        name
+    }
+
+    /// Return the number of distinct names.
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    /// Return `true` if no names have been interned.
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+}
+
+/// Parsed search result entry with shared attribute names.
+///
+/// The same as [`ValueEntry`](struct.ValueEntry.html), except that the attribute names
+/// are obtained from an [`AttrInterner`](struct.AttrInterner.html), which should be
+/// kept for all entries of a result set. This saves memory when many entries are kept
+/// at once, such as when exporting a large part of the directory.
+#[derive(Debug, Clone)]
+pub struct InternedEntry {
+    /// Entry DN.
+    pub dn: String,
+    /// Attributes.
+    pub attrs: HashMap<Arc<str>, Vec<AttrValue>>,
+    /// Controls attached to the entry.
+    pub ctrls: Vec<Control>,
+}
+
+impl InternedEntry {
+    /// Parse raw BER data and convert it into the attribute map, interning the
+    /// attribute names with `names`.
+    ///
+    /// __Note__: this function will panic on parsing error.
+    pub fn construct(re: ResultEntry, names: &mut AttrInterner) -> InternedEntry {
+        let (dn, attrs, ctrls) = entry_parts(re);
+        let mut attr_vals: HashMap<Arc<str>, Vec<AttrValue>> = HashMap::with_capacity(attrs.len());
+        for (a_type, values) in attrs {
+            attr_vals
+                .entry(names.intern(&without_binary_option(a_type)))
+                .or_default()
+                .extend(values.into_iter().map(AttrValue::from));
+        }
+        InternedEntry {
+            dn,
+            attrs: attr_vals,
+            ctrls,
+        }
+    }
+
+    /// Return the values of `attr`, matching the name case-insensitively if there's
+    /// no exact match.
+    pub fn get(&self, attr: &str) -> Option<&[AttrValue]> {
+        self.attrs
+            .get(attr)
+            .or_else(|| {
+                self.attrs
+                    .iter()
+                    .find(|(k, _)| k.eq_ignore_ascii_case(attr))
+                    .map(|(_, v)| v)
+            })
+            .map(Vec::as_slice)
+    }
+}
+
 /// Possible states of a `SearchStream`.
 ///
 /// ## `SearchStream` call/state conceptual diagram
@@ -574,14 +986,50 @@ pub enum StreamState {
 #[derive(Debug)]
 pub struct SearchStream<'a, S> {
     pub(crate) ldap: Ldap,
-    pub(crate) rx: Option<mpsc::UnboundedReceiver<(SearchItem, Vec<Control>)>>,
-    state: StreamState,
+    pub(crate) rx: Option<ItemReceiver>,
+    pub(crate) state: StreamState,
     adapters: Vec<Arc<Mutex<Box<dyn Adapter<'a, S> + 'a>>>>,
     ax: usize,
     timeout: Option<Duration>,
+    cancel: Option<Cancel>,
+    size: Option<ResultSize>,
     pub res: Option<LdapResult>,
 }
 
+/// Size of the complete result set, as reported by the server.
+///
+/// Obtained from a [`SearchStream`](struct.SearchStream.html) with
+/// [`result_size()`](struct.SearchStream.html#method.result_size).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResultSize {
+    /// Estimate from the `size` field of the Paged Results response control.
+    Estimate(u32),
+    /// Content count from the Virtual List View response control.
+    ContentCount(u32),
+}
+
+impl ResultSize {
+    /// The reported number of entries.
+    pub fn count(&self) -> u32 {
+        match *self {
+            ResultSize::Estimate(n) | ResultSize::ContentCount(n) => n,
+        }
+    }
+
+    fn from_controls(ctrls: &[Control]) -> Option<ResultSize> {
+        ctrls.iter().find_map(|ctrl| match ctrl.0 {
+            Some(ControlType::PagedResults) => match ctrl.1.parse::<PagedResults>().size {
+                size if size > 0 => Some(ResultSize::Estimate(size as u32)),
+                _ => None,
+            },
+            Some(ControlType::VirtualListViewResp) => Some(ResultSize::ContentCount(
+                ctrl.1.parse::<VirtualListViewResp>().content_count,
+            )),
+            _ => None,
+        })
+    }
+}
+
 impl<'a, S> SearchStream<'a, S>
 where
     S: AsRef<str> + Send + Sync + 'a,
@@ -594,6 +1042,8 @@ where
             adapters: adapters.into_iter().map(Mutex::new).map(Arc::new).collect(),
             ax: 0,
             timeout: None,
+            cancel: None,
+            size: None,
             res: None,
         }
     }
@@ -609,54 +1059,12 @@ where
             Some(opts) => opts,
             None => SearchOptions::new(),
         };
-        self.timeout = self.ldap.timeout;
-        let req = Tag::Sequence(Sequence {
-            id: 3,
-            class: TagClass::Application,
-            inner: vec![
-                Tag::OctetString(OctetString {
-                    inner: Vec::from(base.as_bytes()),
-                    ..Default::default()
-                }),
-                Tag::Enumerated(Enumerated {
-                    inner: scope as i64,
-                    ..Default::default()
-                }),
-                Tag::Enumerated(Enumerated {
-                    inner: opts.deref as i64,
-                    ..Default::default()
-                }),
-                Tag::Integer(Integer {
-                    inner: opts.sizelimit as i64,
-                    ..Default::default()
-                }),
-                Tag::Integer(Integer {
-                    inner: opts.timelimit as i64,
-                    ..Default::default()
-                }),
-                Tag::Boolean(Boolean {
-                    inner: opts.typesonly,
-                    ..Default::default()
-                }),
-                match parse_filter(filter) {
-                    Ok(filter) => filter,
-                    _ => return Err(LdapError::FilterParsing),
-                },
-                Tag::Sequence(Sequence {
-                    inner: attrs
-                        .into_iter()
-                        .map(|s| {
-                            Tag::OctetString(OctetString {
-                                inner: Vec::from(s.as_ref()),
-                                ..Default::default()
-                            })
-                        })
-                        .collect(),
-                    ..Default::default()
-                }),
-            ],
-        });
-        let (tx, rx) = mpsc::unbounded_channel();
+        self.timeout = self.ldap.timeout.or(self.ldap.shared.default_timeout);
+        self.cancel = self.ldap.cancel.take();
+        let req = SearchRequest::new(base, scope, filter, attrs)
+            .opts(opts)
+            .to_tag()?;
+        let (tx, rx) = item_channel(self.ldap.shared.stream_buffer);
         self.rx = Some(rx);
         if let Some(timeout) = self.timeout {
             self.ldap.with_timeout(timeout);
@@ -667,15 +1075,21 @@ where
     }
 
     pub(crate) async fn next_inner(&mut self) -> Result<Option<ResultEntry>> {
-        let item = if let Some(timeout) = self.timeout {
-            let res = time::timeout(timeout, self.rx.as_mut().unwrap().recv()).await;
-            if res.is_err() {
+        let rx = self.rx.as_mut().unwrap();
+        let item = match wait_reply(rx.recv(), self.timeout, self.cancel.as_mut()).await {
+            Ok(item) => item,
+            Err(LdapError::Cancelled) => {
+                let last_id = self.ldap.last_id;
+                self.cancel = None;
+                self.rx = None;
+                self.ldap.abandon_cancelled(last_id).await?;
+                return Err(LdapError::Cancelled);
+            }
+            Err(e) => {
                 let last_id = self.ldap.last_id;
-                self.ldap.id_scrub_tx.send(last_id)?;
+                self.ldap.shared.id_scrub_tx.send(last_id)?;
+                return Err(e);
             }
-            res?
-        } else {
-            self.rx.as_mut().unwrap().recv().await
         };
         let (item, controls) = match item {
             Some((item, controls)) => (item, controls),
@@ -688,8 +1102,11 @@ where
             SearchItem::Entry(tag) | SearchItem::Referral(tag) => {
                 return Ok(Some(ResultEntry(tag, controls)))
             }
-            SearchItem::Done(mut res) => {
+            SearchItem::Done(mut res) | SearchItem::ExopDone(mut res, _) => {
                 res.ctrls = controls;
+                if let Some(size) = ResultSize::from_controls(&res.ctrls) {
+                    self.size = Some(size);
+                }
                 self.res = Some(res);
                 self.rx = None;
             }
@@ -700,7 +1117,7 @@ where
     pub(crate) async fn finish_inner(&mut self) -> LdapResult {
         if self.state != StreamState::Done {
             let last_id = self.ldap.last_id;
-            if let Err(e) = self.ldap.id_scrub_tx.send(last_id) {
+            if let Err(e) = self.ldap.shared.id_scrub_tx.send(last_id) {
                 warn!(
                     "error sending scrub message from SearchStream::finish() for ID {}: {}",
                     last_id, e
@@ -709,13 +1126,18 @@ where
         }
         self.state = StreamState::Closed;
         self.rx = None;
-        self.res.take().unwrap_or_else(|| LdapResult {
+        let mut res = self.res.take().unwrap_or_else(|| LdapResult {
             rc: 88,
             matched: String::from(""),
             text: String::from("user cancelled"),
             refs: vec![],
             ctrls: vec![],
-        })
+        });
+        self.ldap
+            .shared
+            .interceptors
+            .result(OpKind::Search, &mut res);
+        res
     }
 
     /// Initialize a streaming Search.
@@ -744,7 +1166,7 @@ where
         let adapter = self.adapters[self.ax].clone();
         let mut adapter = adapter.lock().await;
         self.ax += 1;
-        let res = (&mut adapter).start(self, base, scope, filter, attrs).await;
+        let res = adapter.start(self, base, scope, filter, attrs).await;
         self.ax -= 1;
         if res.is_err() {
             self.state = StreamState::Error;
@@ -771,7 +1193,7 @@ where
         let adapter = self.adapters[self.ax].clone();
         let mut adapter = adapter.lock().await;
         self.ax += 1;
-        let res = (&mut adapter).next(self).await;
+        let res = adapter.next(self).await;
         self.ax -= 1;
         match res {
             Ok(None) if self.ax == 0 => self.state = StreamState::Done,
@@ -806,7 +1228,7 @@ where
         let adapter = self.adapters[self.ax].clone();
         let mut adapter = adapter.lock().await;
         self.ax += 1;
-        let res = (&mut adapter).finish(self).await;
+        let res = adapter.finish(self).await;
         self.ax -= 1;
         res
     }
@@ -830,6 +1252,15 @@ where
         self.state
     }
 
+    /// Return the size of the complete result set, if the server has reported it in the
+    /// Paged Results or Virtual List View response control. Since the control arrives
+    /// with the result of a Search, the size is known only after the first page has been
+    /// read. With the [`PagedResults`](adapters/struct.PagedResults.html) adapter, that
+    /// can be well before the end of the stream; the value is updated with every page.
+    pub fn result_size(&self) -> Option<ResultSize> {
+        self.size
+    }
+
     /// Return the `Ldap` handle of the stream.
     ///
     /// Mutating the public elements of `Ldap` through the obtained handle can't affect
@@ -850,3 +1281,327 @@ pub fn parse_refs(t: StructureTag) -> Vec<String> {
         .map(|s| s.expect("uri"))
         .collect()
 }
+
+#[cfg(all(test, feature = "testkit"))]
+mod test {
+    use super::{
+        Attr, AttrInterner, InternedEntry, ResultEntry, ResultSize, SearchEntry, SearchOptions,
+        ValueEntry,
+    };
+    use crate::adapters::PagedResults;
+    use crate::controls::{
+        Control, ControlType, EntryChangeNotification, EntryChangeType, RawControl,
+    };
+    use crate::testkit::{response, MockServer};
+    use crate::Scope;
+
+    use std::sync::Arc;
+
+    use bytes::BytesMut;
+    use lber::common::TagClass;
+    use lber::structures::{ASNTag, Enumerated, OctetString, Sequence, Set, Tag};
+    use lber::write;
+
+    fn ostr(val: &[u8]) -> Tag {
+        Tag::OctetString(OctetString {
+            inner: val.to_vec(),
+            ..Default::default()
+        })
+    }
+
+    fn server() -> MockServer {
+        let server = MockServer::new();
+        server.add_entry("dc=example,dc=org", vec![("dc", vec!["example"])]);
+        server
+    }
+
+    #[tokio::test]
+    async fn attr_selectors() {
+        let server = MockServer::new();
+        server.add_entry(
+            "dc=example,dc=org",
+            vec![("dc", vec!["example"]), ("description", vec!["top"])],
+        );
+        let (conn, mut ldap) = server.connect();
+        crate::drive!(conn);
+        let attr_names = |rs: Vec<ResultEntry>| {
+            let mut names: Vec<_> = SearchEntry::construct(rs.into_iter().next().unwrap())
+                .attrs
+                .into_keys()
+                .collect();
+            names.sort();
+            names
+        };
+        let (rs, _res) = ldap
+            .search(
+                "dc=example,dc=org",
+                Scope::Base,
+                "(objectClass=*)",
+                vec![Attr::NoAttrs],
+            )
+            .await
+            .unwrap()
+            .success()
+            .unwrap();
+        assert!(attr_names(rs).is_empty());
+        let (rs, _res) = ldap
+            .with_search_options(SearchOptions::new().include_operational(true))
+            .search(
+                "dc=example,dc=org",
+                Scope::Base,
+                "(objectClass=*)",
+                vec![Attr::from("dc")],
+            )
+            .await
+            .unwrap()
+            .success()
+            .unwrap();
+        assert_eq!(attr_names(rs), vec!["dc"]);
+    }
+
+    #[tokio::test]
+    async fn binary_values() {
+        let server = MockServer::new();
+        server.add_entry(
+            "cn=ca,dc=example,dc=org",
+            vec![
+                ("cn", vec![&b"ca"[..]]),
+                ("cACertificate;binary", vec![&b"\x30\x82\xff"[..]]),
+            ],
+        );
+        let (conn, mut ldap) = server.connect();
+        crate::drive!(conn);
+        let (rs, _res) = ldap
+            .with_search_options(SearchOptions::new().binary_transfer(true))
+            .search(
+                "cn=ca,dc=example,dc=org",
+                Scope::Base,
+                "(objectClass=*)",
+                vec!["cn", "cACertificate"],
+            )
+            .await
+            .unwrap()
+            .success()
+            .unwrap();
+        let entry = ValueEntry::construct(rs.into_iter().next().unwrap());
+        assert_eq!(entry.get("CN").unwrap()[0].as_str(), Some("ca"));
+        let cert = &entry.attrs["cACertificate"][0];
+        assert_eq!(cert.as_str(), None);
+        assert_eq!(cert.as_bytes(), b"\x30\x82\xff");
+    }
+
+    #[tokio::test]
+    async fn result_size() {
+        let server = server();
+        for n in 0..3 {
+            let dn = format!("uid=u{},dc=example,dc=org", n);
+            server.add_entry(&dn, vec![("uid", vec![&format!("u{}", n)[..]])]);
+        }
+        let (conn, mut ldap) = server.connect();
+        crate::drive!(conn);
+        let mut stream = ldap
+            .streaming_search_with(
+                PagedResults::new(10),
+                "dc=example,dc=org",
+                Scope::Subtree,
+                "(uid=*)",
+                vec!["uid"],
+            )
+            .await
+            .unwrap();
+        assert_eq!(stream.result_size(), None);
+        while stream.next().await.unwrap().is_some() {}
+        assert_eq!(stream.result_size(), Some(ResultSize::Estimate(3)));
+        assert_eq!(stream.finish().await.rc, 0);
+
+        let mut stream = ldap
+            .streaming_search("dc=example,dc=org", Scope::Subtree, "(uid=*)", vec!["uid"])
+            .await
+            .unwrap();
+        while stream.next().await.unwrap().is_some() {}
+        assert_eq!(stream.result_size(), None);
+    }
+
+    #[tokio::test]
+    async fn large_values() {
+        let photo = vec![0xffu8; 3 * 1024 * 1024];
+        let server = MockServer::new();
+        server.add_entry(
+            "uid=test,dc=example,dc=org",
+            vec![
+                ("uid", vec![&b"test"[..]]),
+                ("cn", vec![&b"Test"[..]]),
+                ("jpegPhoto", vec![&photo[..]]),
+            ],
+        );
+        let (conn, mut ldap) = server.connect();
+        crate::drive!(conn);
+        let (mut entries, _) = ldap
+            .search(
+                "uid=test,dc=example,dc=org",
+                Scope::Base,
+                "(objectClass=*)",
+                vec!["cn", "jpegPhoto"],
+            )
+            .await
+            .unwrap()
+            .success()
+            .unwrap();
+        let mut entry = entries.pop().unwrap();
+        let mut out = Vec::new();
+        assert_eq!(entry.write_values("JPEGPHOTO", &mut out).unwrap(), 1);
+        assert_eq!(out, photo);
+        let vals = entry.take_values("jpegPhoto");
+        assert_eq!(vals.len(), 1);
+        assert_eq!(&vals[0][..], &photo[..]);
+        assert!(entry.take_values("jpegPhoto").is_empty());
+        assert!(entry.take_values("missing").is_empty());
+        assert_eq!(SearchEntry::construct(entry).attrs["cn"], vec!["Test"]);
+    }
+
+    #[tokio::test]
+    async fn binary_split() {
+        let server = MockServer::new();
+        server.add_entry(
+            "uid=test,dc=example,dc=org",
+            vec![
+                ("cn", vec![&b"Test"[..], &b"Other"[..]]),
+                ("mixed", vec![&b"text"[..], &b"\xff\x00"[..], &b"more"[..]]),
+            ],
+        );
+        let (conn, mut ldap) = server.connect();
+        crate::drive!(conn);
+        let (mut entries, _) = ldap
+            .search(
+                "uid=test,dc=example,dc=org",
+                Scope::Base,
+                "(objectClass=*)",
+                vec!["cn", "mixed"],
+            )
+            .await
+            .unwrap()
+            .success()
+            .unwrap();
+        let entry = SearchEntry::construct(entries.pop().unwrap());
+        assert_eq!(entry.attrs["cn"], vec!["Test", "Other"]);
+        assert!(!entry.attrs.contains_key("mixed"));
+        assert_eq!(
+            entry.bin_attrs["mixed"],
+            vec![b"text".to_vec(), vec![0xff, 0x00], b"more".to_vec()]
+        );
+    }
+
+    #[tokio::test]
+    async fn interned_names() {
+        let server = server();
+        for uid in &["a", "b"] {
+            server.add_entry(
+                &format!("uid={},dc=example,dc=org", uid),
+                vec![
+                    ("objectClass", vec!["account"]),
+                    ("uid", vec![*uid]),
+                    ("userCertificate;binary", vec!["cert"]),
+                ],
+            );
+        }
+        let (conn, mut ldap) = server.connect();
+        crate::drive!(conn);
+        let (entries, _) = ldap
+            .search("dc=example,dc=org", Scope::OneLevel, "(uid=*)", vec!["*"])
+            .await
+            .unwrap()
+            .success()
+            .unwrap();
+        let mut names = AttrInterner::new();
+        let entries: Vec<_> = entries
+            .into_iter()
+            .map(|e| InternedEntry::construct(e, &mut names))
+            .collect();
+        assert_eq!(names.len(), 3);
+        let key = |e: &InternedEntry, attr: &str| e.attrs.get_key_value(attr).unwrap().0.clone();
+        assert!(Arc::ptr_eq(
+            &key(&entries[0], "objectClass"),
+            &key(&entries[1], "objectClass")
+        ));
+        assert_eq!(
+            entries[1].get("USERCERTIFICATE").unwrap()[0].as_bytes(),
+            b"cert"
+        );
+    }
+
+    #[tokio::test]
+    async fn entry_controls() {
+        let server = MockServer::new();
+        server.script(3, |id| {
+            let entry = Tag::Sequence(Sequence {
+                id: 4,
+                class: TagClass::Application,
+                inner: vec![
+                    ostr(b"dc=example,dc=org"),
+                    Tag::Sequence(Sequence {
+                        inner: vec![Tag::Sequence(Sequence {
+                            inner: vec![
+                                ostr(b"dc"),
+                                Tag::Set(Set {
+                                    inner: vec![ostr(b"example")],
+                                    ..Default::default()
+                                }),
+                            ],
+                            ..Default::default()
+                        })],
+                        ..Default::default()
+                    }),
+                ],
+            });
+            // Entry Change Notification for a Modify.
+            let ecn = Tag::Sequence(Sequence {
+                inner: vec![Tag::Enumerated(Enumerated {
+                    inner: 4,
+                    ..Default::default()
+                })],
+                ..Default::default()
+            });
+            let mut val = BytesMut::new();
+            write::encode_into(&mut val, ecn.into_structure()).unwrap();
+            let ctrl = RawControl {
+                ctype: "2.16.840.1.113730.3.4.7".to_owned(),
+                crit: false,
+                val: Some(val.to_vec()),
+            };
+            vec![
+                (id, entry, Some(vec![ctrl])),
+                (id, response(5, 0, ""), None),
+            ]
+        });
+        let (conn, mut ldap) = server.connect();
+        crate::drive!(conn);
+        let (rs, _res) = ldap
+            .search(
+                "dc=example,dc=org",
+                Scope::Base,
+                "(objectClass=*)",
+                vec!["*"],
+            )
+            .await
+            .unwrap()
+            .success()
+            .unwrap();
+        let re = rs.into_iter().next().unwrap();
+        let check = |ctrls: &[Control]| {
+            assert_eq!(ctrls.len(), 1);
+            assert!(matches!(
+                ctrls[0].0,
+                Some(ControlType::EntryChangeNotification)
+            ));
+            let ecn = ctrls[0].1.parse::<EntryChangeNotification>();
+            assert!(matches!(ecn.change_type, EntryChangeType::Modify));
+        };
+        let entry = ValueEntry::construct(re.clone());
+        assert_eq!(entry.dn, "dc=example,dc=org");
+        check(&entry.ctrls);
+        let entry = SearchEntry::construct(re);
+        assert_eq!(entry.attrs["dc"], vec!["example"]);
+        check(&entry.ctrls);
+        check(&ValueEntry::from(entry).ctrls);
+    }
+}
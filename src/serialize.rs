@@ -0,0 +1,525 @@
+//! Serde serialization of search entries and results.
+//!
+//! [`SearchEntry`](../struct.SearchEntry.html) implements `Serialize` and `Deserialize`
+//! with its field structure, leaving out the controls, where every attribute is a list
+//! of values. [`LdapResult`](../result/struct.LdapResult.html) does the same, with the
+//! controls in their raw form; the type of a known control is restored on deserialization.
+//! That's faithful to the protocol, but awkward for consumers of, say, a JSON API,
+//! who expect `"uid": "jdoe"` rather than `"uid": ["jdoe"]`. The alternative shape, produced by
+//! [`SearchEntry::with_hints()`](../struct.SearchEntry.html#method.with_hints), emits
+//! single-valued attributes as scalars and multi-valued ones as arrays:
+//!
+//! ```text
+//! {"dn": "uid=jdoe,ou=People,dc=example,dc=org",
+//!  "attrs": {"cn": "John Doe", "mail": ["jdoe@example.org"], "uid": "jdoe"}}
+//! ```
+//!
+//! Whether an attribute is single-valued is decided by the [`ValueHints`](struct.ValueHints.html),
+//! which can be built from the `attributeTypes` of the server schema or listed by the
+//! application, so that the shape doesn't depend on the number of values in a particular
+//! entry. Values from `bin_attrs` are serialized as bytes, and attributes are sorted by name.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! use ldap3::serialize::ValueHints;
+//! use ldap3::{LdapConnAsync, Scope, SearchEntry};
+//! # use ldap3::result::Result;
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<()> {
+//! let (conn, mut ldap) = LdapConnAsync::new("ldap://localhost:2389").await?;
+//! ldap3::drive!(conn);
+//! let (rs, _res) = ldap
+//!     .search("cn=Subschema", Scope::Base, "(objectClass=*)", vec!["attributeTypes"])
+//!     .await?
+//!     .success()?;
+//! let schema = SearchEntry::construct(rs.into_iter().next().expect("subschema"));
+//! let hints = ValueHints::from_schema(&schema.attrs["attributeTypes"]);
+//! let (rs, _res) = ldap
+//!     .search("dc=example,dc=org", Scope::Subtree, "(uid=jdoe)", vec!["*"])
+//!     .await?
+//!     .success()?;
+//! for entry in rs.into_iter().map(SearchEntry::construct) {
+//!     let shaped = entry.with_hints(&hints);
+//!     // serde_json::to_string(&shaped)
+//! #   let _ = shaped;
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::controls::{Control, RawControl};
+use crate::controls_impl::control_type;
+use crate::result::LdapResult;
+use crate::search::SearchEntry;
+
+use serde::de::{self, Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::ser::{Serialize, SerializeMap, SerializeStruct, Serializer};
+
+const ENTRY_FIELDS: &[&str] = &["dn", "attrs", "bin_attrs"];
+const RESULT_FIELDS: &[&str] = &["rc", "matched", "text", "refs", "ctrls"];
+const CONTROL_FIELDS: &[&str] = &["ctype", "crit", "val"];
+
+/// Knowledge of which attributes are single-valued.
+///
+/// Attribute names are matched case-insensitively. Attributes not known to be single-valued
+/// are serialized as arrays, unless [`scalar_when_single()`](#method.scalar_when_single)
+/// is set. A single-valued attribute which nevertheless has several values in an entry
+/// is serialized as an array, so that no values are lost.
+#[derive(Clone, Debug, Default)]
+pub struct ValueHints {
+    single: HashSet<String>,
+    by_count: bool,
+}
+
+// Tokens of an RFC 4512 definition: parentheses, quoted strings without the quotes,
+// and bare words.
+fn def_tokens(def: &str) -> Vec<(bool, &str)> {
+    let mut tokens = vec![];
+    let mut rest = def.trim_start();
+    while let Some(c) = rest.chars().next() {
+        let (token, len) = match c {
+            '(' | ')' => ((false, &rest[..1]), 1),
+            '\'' => {
+                let end = rest[1..].find('\'').map_or(rest.len(), |p| p + 1);
+                ((true, &rest[1..end]), (end + 1).min(rest.len()))
+            }
+            _ => {
+                let end = rest
+                    .find(|c: char| c.is_whitespace() || c == '(' || c == ')')
+                    .unwrap_or(rest.len());
+                ((false, &rest[..end]), end)
+            }
+        };
+        tokens.push(token);
+        rest = rest[len..].trim_start();
+    }
+    tokens
+}
+
+impl ValueHints {
+    /// Create an empty set of hints.
+    pub fn new() -> Self {
+        ValueHints::default()
+    }
+
+    /// Build the hints from attribute type definitions, typically the values of
+    /// `attributeTypes` from the subschema entry. Every name of a type with the
+    /// `SINGLE-VALUE` flag is considered single-valued. Unparseable definitions
+    /// are skipped.
+    pub fn from_schema<S: AsRef<str>>(defs: &[S]) -> Self {
+        let mut hints = ValueHints::new();
+        for def in defs {
+            let tokens = def_tokens(def.as_ref());
+            if !tokens.contains(&(false, "SINGLE-VALUE")) {
+                continue;
+            }
+            let mut names = tokens.iter().skip_while(|&&t| t != (false, "NAME")).skip(1);
+            match names.next() {
+                Some(&(true, name)) => {
+                    hints.single.insert(name.to_ascii_lowercase());
+                }
+                Some(&(false, "(")) => {
+                    for &(_, name) in names.take_while(|&&(quoted, _)| quoted) {
+                        hints.single.insert(name.to_ascii_lowercase());
+                    }
+                }
+                _ => (),
+            }
+        }
+        hints
+    }
+
+    /// Mark the attributes in `attrs` as single-valued.
+    pub fn single_valued<S: AsRef<str>>(mut self, attrs: &[S]) -> Self {
+        self.single
+            .extend(attrs.iter().map(|a| a.as_ref().to_ascii_lowercase()));
+        self
+    }
+
+    /// Serialize attributes which aren't known to be single-valued as scalars if they
+    /// have exactly one value in the entry being serialized. The shape then varies between
+    /// entries, but may still be adequate when the schema isn't available. Off by default.
+    pub fn scalar_when_single(mut self, by_count: bool) -> Self {
+        self.by_count = by_count;
+        self
+    }
+
+    /// Check whether `attr` is known to be single-valued.
+    pub fn is_single_valued(&self, attr: &str) -> bool {
+        self.single.contains(&attr.to_ascii_lowercase())
+    }
+
+    fn as_scalar(&self, attr: &str, count: usize) -> bool {
+        count == 1 && (self.by_count || self.is_single_valued(attr))
+    }
+}
+
+impl Serialize for SearchEntry {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut s = serializer.serialize_struct("SearchEntry", 3)?;
+        s.serialize_field("dn", &self.dn)?;
+        s.serialize_field("attrs", &self.attrs)?;
+        s.serialize_field("bin_attrs", &BinAttrs(&self.bin_attrs))?;
+        s.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for SearchEntry {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct EntryVisitor;
+
+        impl<'de> Visitor<'de> for EntryVisitor {
+            type Value = SearchEntry;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a search entry")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<SearchEntry, A::Error> {
+                let (mut dn, mut attrs, mut bin_attrs) = (None, None, None);
+                while let Some(key) = map.next_key::<String>()? {
+                    match key.as_str() {
+                        "dn" => dn = Some(map.next_value()?),
+                        "attrs" => attrs = Some(map.next_value()?),
+                        "bin_attrs" => {
+                            let vals: HashMap<String, Vec<ByteBuf>> = map.next_value()?;
+                            bin_attrs = Some(
+                                vals.into_iter()
+                                    .map(|(attr, vals)| {
+                                        (attr, vals.into_iter().map(|v| v.0).collect())
+                                    })
+                                    .collect(),
+                            );
+                        }
+                        other => return Err(de::Error::unknown_field(other, ENTRY_FIELDS)),
+                    }
+                }
+                Ok(SearchEntry {
+                    dn: dn.ok_or_else(|| de::Error::missing_field("dn"))?,
+                    attrs: attrs.ok_or_else(|| de::Error::missing_field("attrs"))?,
+                    bin_attrs: bin_attrs.unwrap_or_default(),
+                    ctrls: vec![],
+                })
+            }
+        }
+
+        deserializer.deserialize_struct("SearchEntry", ENTRY_FIELDS, EntryVisitor)
+    }
+}
+
+impl Serialize for LdapResult {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut s = serializer.serialize_struct("LdapResult", 5)?;
+        s.serialize_field("rc", &self.rc)?;
+        s.serialize_field("matched", &self.matched)?;
+        s.serialize_field("text", &self.text)?;
+        s.serialize_field("refs", &self.refs)?;
+        s.serialize_field(
+            "ctrls",
+            &self.ctrls.iter().map(|c| &c.1).collect::<Vec<_>>(),
+        )?;
+        s.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for LdapResult {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ResultVisitor;
+
+        impl<'de> Visitor<'de> for ResultVisitor {
+            type Value = LdapResult;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("an LDAP result")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<LdapResult, A::Error> {
+                let (mut rc, mut matched, mut text, mut refs, mut ctrls) =
+                    (None, None, None, None, None);
+                while let Some(key) = map.next_key::<String>()? {
+                    match key.as_str() {
+                        "rc" => rc = Some(map.next_value()?),
+                        "matched" => matched = Some(map.next_value()?),
+                        "text" => text = Some(map.next_value()?),
+                        "refs" => refs = Some(map.next_value()?),
+                        "ctrls" => ctrls = Some(map.next_value::<Vec<RawControl>>()?),
+                        other => return Err(de::Error::unknown_field(other, RESULT_FIELDS)),
+                    }
+                }
+                Ok(LdapResult {
+                    rc: rc.ok_or_else(|| de::Error::missing_field("rc"))?,
+                    matched: matched.unwrap_or_default(),
+                    text: text.unwrap_or_default(),
+                    refs: refs.unwrap_or_default(),
+                    ctrls: ctrls
+                        .unwrap_or_default()
+                        .into_iter()
+                        .map(|raw| Control(control_type(&raw.ctype), raw))
+                        .collect(),
+                })
+            }
+        }
+
+        deserializer.deserialize_struct("LdapResult", RESULT_FIELDS, ResultVisitor)
+    }
+}
+
+impl Serialize for RawControl {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut s = serializer.serialize_struct("RawControl", 3)?;
+        s.serialize_field("ctype", &self.ctype)?;
+        s.serialize_field("crit", &self.crit)?;
+        s.serialize_field("val", &self.val.as_deref().map(Bytes))?;
+        s.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for RawControl {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ControlVisitor;
+
+        impl<'de> Visitor<'de> for ControlVisitor {
+            type Value = RawControl;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a control")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<RawControl, A::Error> {
+                let (mut ctype, mut crit, mut val) = (None, None, None);
+                while let Some(key) = map.next_key::<String>()? {
+                    match key.as_str() {
+                        "ctype" => ctype = Some(map.next_value()?),
+                        "crit" => crit = Some(map.next_value()?),
+                        "val" => val = map.next_value::<Option<ByteBuf>>()?,
+                        other => return Err(de::Error::unknown_field(other, CONTROL_FIELDS)),
+                    }
+                }
+                Ok(RawControl {
+                    ctype: ctype.ok_or_else(|| de::Error::missing_field("ctype"))?,
+                    crit: crit.unwrap_or(false),
+                    val: val.map(|v| v.0),
+                })
+            }
+        }
+
+        deserializer.deserialize_struct("RawControl", CONTROL_FIELDS, ControlVisitor)
+    }
+}
+
+struct Bytes<'v>(&'v [u8]);
+
+impl Serialize for Bytes<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(self.0)
+    }
+}
+
+// Formats without a native byte string, like JSON, serialize bytes as a sequence of numbers.
+struct ByteBuf(Vec<u8>);
+
+impl<'de> Deserialize<'de> for ByteBuf {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct BytesVisitor;
+
+        impl<'de> Visitor<'de> for BytesVisitor {
+            type Value = ByteBuf;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a byte string")
+            }
+
+            fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<ByteBuf, E> {
+                Ok(ByteBuf(v.to_vec()))
+            }
+
+            fn visit_byte_buf<E: de::Error>(self, v: Vec<u8>) -> Result<ByteBuf, E> {
+                Ok(ByteBuf(v))
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<ByteBuf, A::Error> {
+                let mut buf = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+                while let Some(b) = seq.next_element()? {
+                    buf.push(b);
+                }
+                Ok(ByteBuf(buf))
+            }
+        }
+
+        deserializer.deserialize_bytes(BytesVisitor)
+    }
+}
+
+struct BinAttrs<'e>(&'e HashMap<String, Vec<Vec<u8>>>);
+
+impl Serialize for BinAttrs<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(
+            self.0
+                .iter()
+                .map(|(attr, vals)| (attr, vals.iter().map(|v| Bytes(v)).collect::<Vec<_>>())),
+        )
+    }
+}
+
+/// Search entry serialized with single-valued attributes as scalars.
+///
+/// Returned by [`SearchEntry::with_hints()`](../struct.SearchEntry.html#method.with_hints).
+#[derive(Debug)]
+pub struct HintedEntry<'e> {
+    entry: &'e SearchEntry,
+    hints: &'e ValueHints,
+}
+
+enum Values<'e> {
+    Text(&'e [String]),
+    Binary(&'e [Vec<u8>]),
+}
+
+struct HintedAttrs<'e>(&'e HintedEntry<'e>);
+
+impl Serialize for HintedAttrs<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let entry = self.0.entry;
+        let mut attrs: Vec<_> = entry
+            .attrs
+            .iter()
+            .map(|(attr, vals)| (attr, Values::Text(vals)))
+            .chain(
+                entry
+                    .bin_attrs
+                    .iter()
+                    .map(|(attr, vals)| (attr, Values::Binary(vals))),
+            )
+            .collect();
+        attrs.sort_by(|a, b| a.0.cmp(b.0));
+        let mut map = serializer.serialize_map(Some(attrs.len()))?;
+        for (attr, vals) in attrs {
+            match vals {
+                Values::Text(vals) if self.0.hints.as_scalar(attr, vals.len()) => {
+                    map.serialize_entry(attr, &vals[0])?
+                }
+                Values::Text(vals) => map.serialize_entry(attr, vals)?,
+                Values::Binary(vals) if self.0.hints.as_scalar(attr, vals.len()) => {
+                    map.serialize_entry(attr, &Bytes(&vals[0]))?
+                }
+                Values::Binary(vals) => {
+                    map.serialize_entry(attr, &vals.iter().map(|v| Bytes(v)).collect::<Vec<_>>())?
+                }
+            }
+        }
+        map.end()
+    }
+}
+
+impl Serialize for HintedEntry<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut s = serializer.serialize_struct("SearchEntry", 2)?;
+        s.serialize_field("dn", &self.entry.dn)?;
+        s.serialize_field("attrs", &HintedAttrs(self))?;
+        s.end()
+    }
+}
+
+impl SearchEntry {
+    /// Return a view of the entry which serializes single-valued attributes as scalars,
+    /// according to `hints`. See the [`serialize`](serialize/index.html) module.
+    pub fn with_hints<'e>(&'e self, hints: &'e ValueHints) -> HintedEntry<'e> {
+        HintedEntry { entry: self, hints }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ValueHints;
+    use crate::controls::{Control, ControlType, RawControl};
+    use crate::result::LdapResult;
+    use crate::search::SearchEntry;
+
+    use maplit::hashmap;
+
+    #[test]
+    fn schema_hints() {
+        let hints = ValueHints::from_schema(&[
+            "( 2.5.4.3 NAME ( 'cn' 'commonName' ) DESC 'RFC4519: common name(s)' SUP name )",
+            "( 1.3.6.1.1.1.1.0 NAME 'uidNumber' DESC 'SINGLE-VALUE in text' EQUALITY \
+             integerMatch SYNTAX 1.3.6.1.4.1.1466.115.121.1.27 SINGLE-VALUE )",
+            "( 2.16.840.1.113730.3.1.241 NAME ('displayName' 'dispName') SINGLE-VALUE )",
+            "( 2.5.4.41 NAME 'name' DESC 'SINGLE-VALUE' )",
+        ]);
+        assert!(hints.is_single_valued("uidnumber"));
+        assert!(hints.is_single_valued("displayName"));
+        assert!(hints.is_single_valued("DISPNAME"));
+        assert!(!hints.is_single_valued("cn"));
+        assert!(!hints.is_single_valued("name"));
+        let hints = hints.single_valued(&["cn"]);
+        assert!(hints.is_single_valued("CN"));
+        assert!(!hints.is_single_valued("commonName"));
+    }
+
+    #[test]
+    fn entry_round_trip() {
+        let entry = SearchEntry {
+            dn: "uid=jdoe,ou=People,dc=example,dc=org".into(),
+            attrs: hashmap! {
+                "cn".into() => vec!["John Doe".into()],
+                "mail".into() => vec!["jdoe@example.org".into(), "john@example.org".into()],
+            },
+            bin_attrs: hashmap! {
+                "jpegPhoto".into() => vec![vec![0xff, 0xd8, 0x00, 0xff]],
+            },
+            ctrls: vec![],
+        };
+        let json = serde_json::to_string(&entry).expect("serialized");
+        let back: SearchEntry = serde_json::from_str(&json).expect("deserialized");
+        assert_eq!(back.dn, entry.dn);
+        assert_eq!(back.attrs, entry.attrs);
+        assert_eq!(back.bin_attrs, entry.bin_attrs);
+    }
+
+    #[test]
+    fn result_round_trip() {
+        let res = LdapResult {
+            rc: 10,
+            matched: "dc=example,dc=org".into(),
+            text: "referral".into(),
+            refs: vec!["ldap://ldap2.example.org/dc=example,dc=org".into()],
+            ctrls: vec![
+                Control(
+                    Some(ControlType::PagedResults),
+                    RawControl {
+                        ctype: "1.2.840.113556.1.4.319".into(),
+                        crit: false,
+                        val: Some(vec![0x30, 0x05, 0x02, 0x01, 0x00, 0x04, 0x00]),
+                    },
+                ),
+                Control(
+                    None,
+                    RawControl {
+                        ctype: "1.3.6.1.4.1.4203.666.5.99".into(),
+                        crit: true,
+                        val: None,
+                    },
+                ),
+            ],
+        };
+        let json = serde_json::to_string(&res).expect("serialized");
+        let back: LdapResult = serde_json::from_str(&json).expect("deserialized");
+        assert_eq!(back.rc, res.rc);
+        assert_eq!(back.matched, res.matched);
+        assert_eq!(back.text, res.text);
+        assert_eq!(back.refs, res.refs);
+        assert_eq!(back.ctrls.len(), 2);
+        for (back, orig) in back.ctrls.iter().zip(res.ctrls.iter()) {
+            assert_eq!(back.0, orig.0);
+            assert_eq!(back.1.ctype, orig.1.ctype);
+            assert_eq!(back.1.crit, orig.1.crit);
+            assert_eq!(back.1.val, orig.1.val);
+        }
+    }
+}
@@ -0,0 +1,721 @@
+//! Minimal LDAP server framework.
+//!
+//! The protocol layer of the library can decode requests as well as responses, and this
+//! module uses it to implement the server side of a connection. The application supplies
+//! a [`Handler`](trait.Handler.html), whose methods receive decoded requests and return
+//! results; [`LdapServer`](struct.LdapServer.html) accepts connections, reads messages,
+//! calls the handler, and encodes the responses. Requests are represented by the
+//! structures of the [`request`](../request/index.html) module, so a proxy can pass
+//! them on to another server with [`Ldap::send()`](../struct.Ldap.html#method.send)
+//! unchanged. That, together with test doubles and protocol gateways, is the intended
+//! use; there is no TLS, SASL negotiation or access control.
+//!
+//! Requests on a connection are processed one at a time, in the order of arrival. A
+//! handler method which isn't implemented returns `unwillingToPerform`. Abandon requests
+//! are ignored, and Unbind closes the connection. A request with a critical control
+//! whose OID isn't listed by [`Handler::supported_controls()`](trait.Handler.html#method.supported_controls)
+//! fails with `unavailableCriticalExtension` without reaching the handler.
+//!
+//! A [`Proxy`](struct.Proxy.html) is a ready-made handler which forwards operations
+//! to an upstream server, rewriting DNs and filters and adding controls on the way.
+//!
+//! This module is available with the __server__ feature.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! use async_trait::async_trait;
+//! use ldap3::request::SearchRequest;
+//! use ldap3::server::{BindAuth, BindRequest, Handler, LdapServer, Session};
+//! use ldap3::{LdapResult, SearchEntry};
+//! use tokio::net::TcpListener;
+//!
+//! struct Static;
+//!
+//! #[async_trait]
+//! impl Handler for Static {
+//!     async fn bind(&self, _session: &mut Session, req: BindRequest) -> LdapResult {
+//!         match req.auth {
+//!             BindAuth::Simple(ref pw) if req.dn.is_empty() && pw.is_empty() => LdapResult::default(),
+//!             _ => LdapResult { rc: 49, ..LdapResult::default() },
+//!         }
+//!     }
+//!
+//!     async fn search(&self, _session: &Session, _req: SearchRequest) -> (Vec<SearchEntry>, LdapResult) {
+//!         (vec![], LdapResult::default())
+//!     }
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> std::io::Result<()> {
+//! let listener = TcpListener::bind("127.0.0.1:2389").await?;
+//! LdapServer::new(Static).serve(listener).await
+//! # }
+//! ```
+
+use std::collections::HashSet;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use crate::controls::{Control, RawControl};
+use crate::exop::Exop;
+use crate::filter::{from_structure as filter_from_structure, unparse as unparse_filter};
+use crate::ldap::Mod;
+use crate::protocol::{LdapCodec, MaybeControls};
+use crate::request::{
+    AddRequest, CompareRequest, DeleteRequest, ModifyDnRequest, ModifyRequest, SearchRequest,
+};
+use crate::result::LdapResult;
+use crate::search::{DerefAliases, Scope, SearchEntry, SearchOptions};
+
+use async_trait::async_trait;
+use futures_util::sink::SinkExt;
+use lber::common::TagClass;
+use lber::parse::parse_uint;
+use lber::structure::{StructureTag, PL};
+use lber::structures::{Enumerated, OctetString, Sequence, Set, Tag};
+use lber::IResult;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpListener;
+use tokio_stream::StreamExt;
+use tokio_util::codec::Decoder;
+
+mod proxy;
+pub use self::proxy::{Proxy, Rewrite};
+
+/// Authentication choice of a Bind request.
+#[derive(Clone, Debug, PartialEq)]
+pub enum BindAuth {
+    /// Simple authentication with a password, which is empty for an anonymous Bind.
+    Simple(Vec<u8>),
+    /// SASL authentication.
+    Sasl {
+        /// Mechanism name.
+        mechanism: String,
+        /// Credentials, if any.
+        credentials: Option<Vec<u8>>,
+    },
+}
+
+/// Decoded Bind request.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BindRequest {
+    /// Protocol version, normally 3.
+    pub version: u8,
+    /// Name of the bound entity.
+    pub dn: String,
+    /// Authentication choice.
+    pub auth: BindAuth,
+}
+
+/// State of a client connection.
+#[derive(Debug)]
+pub struct Session {
+    peer: Option<SocketAddr>,
+    bound_dn: String,
+    controls: Vec<RawControl>,
+}
+
+impl Session {
+    /// Return the address of the client, if the connection is a TCP one.
+    pub fn peer(&self) -> Option<SocketAddr> {
+        self.peer
+    }
+
+    /// Return the DN of the last successful Bind. It's empty if the connection is anonymous.
+    pub fn bound_dn(&self) -> &str {
+        &self.bound_dn
+    }
+
+    /// Return the controls of the request being processed.
+    pub fn controls(&self) -> &[RawControl] {
+        &self.controls
+    }
+}
+
+fn unwilling() -> LdapResult {
+    LdapResult {
+        rc: 53,
+        text: String::from("operation not supported"),
+        ..LdapResult::default()
+    }
+}
+
+/// Request handler.
+///
+/// Every method has a default implementation which refuses the request, so only the
+/// supported operations need to be implemented. The result of a Compare is returned
+/// in the result code, `compareTrue` (6) or `compareFalse` (5). Response controls are
+/// taken from the `ctrls` vector of the result, and for Search also from the entries.
+#[async_trait]
+pub trait Handler: Send + Sync + 'static {
+    /// Process a Bind request. If the result code is zero, the session is considered
+    /// bound to the DN of the request.
+    async fn bind(&self, _session: &mut Session, _req: BindRequest) -> LdapResult {
+        unwilling()
+    }
+
+    /// Process a Search request, returning the entries and the final result.
+    async fn search(
+        &self,
+        _session: &Session,
+        _req: SearchRequest,
+    ) -> (Vec<SearchEntry>, LdapResult) {
+        (vec![], unwilling())
+    }
+
+    /// Process an Add request.
+    async fn add(&self, _session: &Session, _req: AddRequest) -> LdapResult {
+        unwilling()
+    }
+
+    /// Process a Modify request.
+    async fn modify(&self, _session: &Session, _req: ModifyRequest) -> LdapResult {
+        unwilling()
+    }
+
+    /// Process a Delete request.
+    async fn delete(&self, _session: &Session, _req: DeleteRequest) -> LdapResult {
+        unwilling()
+    }
+
+    /// Process a ModifyDN request.
+    async fn modifydn(&self, _session: &Session, _req: ModifyDnRequest) -> LdapResult {
+        unwilling()
+    }
+
+    /// Process a Compare request.
+    async fn compare(&self, _session: &Session, _req: CompareRequest) -> LdapResult {
+        unwilling()
+    }
+
+    /// Process an Extended request, returning the result and the response name and value.
+    async fn extended(&self, _session: &mut Session, _req: Exop) -> (LdapResult, Exop) {
+        (
+            unwilling(),
+            Exop {
+                name: None,
+                val: None,
+            },
+        )
+    }
+
+    /// Return the OIDs of the controls which the handler understands. Requests with
+    /// other critical controls are refused. The default is an empty list.
+    fn supported_controls(&self) -> &[&str] {
+        &[]
+    }
+
+    /// Check whether a critical control with the OID `oid` can be passed to the handler.
+    /// By default, the control must be listed by [`supported_controls()`](#method.supported_controls).
+    fn handles_control(&self, oid: &str) -> bool {
+        self.supported_controls().contains(&oid)
+    }
+}
+
+/// LDAP server.
+///
+/// See the [module-level documentation](index.html) for the overview.
+#[derive(Debug)]
+pub struct LdapServer<H> {
+    handler: Arc<H>,
+}
+
+impl<H> Clone for LdapServer<H> {
+    fn clone(&self) -> Self {
+        LdapServer {
+            handler: self.handler.clone(),
+        }
+    }
+}
+
+impl<H: Handler> LdapServer<H> {
+    /// Create a server which dispatches requests to `handler`.
+    pub fn new(handler: H) -> Self {
+        LdapServer {
+            handler: Arc::new(handler),
+        }
+    }
+
+    /// Accept connections from `listener`, serving each one in a separate task
+    /// spawned on the current Tokio runtime. Return only if accepting fails.
+    pub async fn serve(&self, listener: TcpListener) -> io::Result<()> {
+        loop {
+            let (stream, peer) = listener.accept().await?;
+            let server = self.clone();
+            tokio::spawn(async move { server.serve_stream(stream, Some(peer)).await });
+        }
+    }
+
+    /// Serve a single connection over `stream` until the client unbinds or closes
+    /// the connection. The address of the client, if known, is made available to the
+    /// handler through the [`Session`](struct.Session.html).
+    pub async fn serve_stream<S>(&self, stream: S, peer: Option<SocketAddr>)
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send,
+    {
+        let mut session = Session {
+            peer,
+            bound_dn: String::new(),
+            controls: vec![],
+        };
+        let mut framed = LdapCodec::default().framed(stream);
+        while let Some(item) = framed.next().await {
+            let (id, (tag, controls)) = match item {
+                Ok(item) => item,
+                Err(e) => {
+                    debug!("server connection error: {}", e);
+                    break;
+                }
+            };
+            let op = match tag {
+                Tag::StructureTag(op) => op,
+                _ => break,
+            };
+            let responses = match op.id {
+                2 => break,
+                16 => continue,
+                op_id if self.unsupported_critical(&controls) => vec![(
+                    result_tag(
+                        response_id(op_id),
+                        &LdapResult {
+                            rc: 12,
+                            text: String::from("critical control not supported"),
+                            ..LdapResult::default()
+                        },
+                    ),
+                    None,
+                )],
+                _ => {
+                    session.controls = controls.into_iter().map(|c| c.1).collect();
+                    self.dispatch(&mut session, op).await
+                }
+            };
+            for (resp, ctrls) in responses {
+                if let Err(e) = framed.send((id, resp, ctrls)).await {
+                    debug!("server send error: {}", e);
+                    return;
+                }
+            }
+        }
+    }
+
+    fn unsupported_critical(&self, controls: &[Control]) -> bool {
+        controls
+            .iter()
+            .any(|c| c.1.crit && !self.handler.handles_control(&c.1.ctype))
+    }
+
+    async fn dispatch(&self, session: &mut Session, op: StructureTag) -> Vec<(Tag, MaybeControls)> {
+        let handler = &self.handler;
+        let op_id = op.id;
+        if op_id == 10 {
+            let dn = string(Some(op));
+            let res = handler.delete(session, DeleteRequest::new(&dn)).await;
+            return vec![result_response(11, res)];
+        }
+        let elems = children(op);
+        match op_id {
+            0 => {
+                let req = match bind_request(elems) {
+                    Some(req) => req,
+                    None => return vec![protocol_error(1, "malformed bind request")],
+                };
+                let dn = req.dn.clone();
+                let res = handler.bind(session, req).await;
+                session.bound_dn = if res.rc == 0 { dn } else { String::new() };
+                vec![result_response(1, res)]
+            }
+            3 => {
+                let req = match search_request(elems) {
+                    Some(req) => req,
+                    None => return vec![protocol_error(5, "malformed search request")],
+                };
+                let (entries, res) = handler.search(session, req).await;
+                let mut resps: Vec<_> = entries.into_iter().map(entry_response).collect();
+                resps.push(result_response(5, res));
+                resps
+            }
+            6 => {
+                let req = match modify_request(elems) {
+                    Some(req) => req,
+                    None => return vec![protocol_error(7, "malformed modify request")],
+                };
+                vec![result_response(7, handler.modify(session, req).await)]
+            }
+            8 => {
+                let mut elems = elems.into_iter();
+                let dn = string(elems.next());
+                let attrs = children_of(elems.next())
+                    .into_iter()
+                    .map(|attr| {
+                        let mut parts = children(attr).into_iter();
+                        let name = octets(parts.next());
+                        let vals = values(parts.next());
+                        (name, vals)
+                    })
+                    .collect();
+                let req = AddRequest { dn, attrs };
+                vec![result_response(9, handler.add(session, req).await)]
+            }
+            12 => {
+                let mut elems = elems.into_iter();
+                let dn = string(elems.next());
+                let rdn = string(elems.next());
+                let delete_old = octets(elems.next()).first().is_some_and(|b| *b != 0);
+                let new_sup = elems.next().map(|t| string(Some(t)));
+                let req = ModifyDnRequest {
+                    dn,
+                    rdn,
+                    delete_old,
+                    new_sup,
+                };
+                vec![result_response(13, handler.modifydn(session, req).await)]
+            }
+            14 => {
+                let mut elems = elems.into_iter();
+                let dn = string(elems.next());
+                let mut ava = children_of(elems.next()).into_iter();
+                let attr = string(ava.next());
+                let val = octets(ava.next());
+                let req = CompareRequest { dn, attr, val };
+                vec![result_response(15, handler.compare(session, req).await)]
+            }
+            23 => {
+                let mut exop = Exop {
+                    name: None,
+                    val: None,
+                };
+                for elem in elems {
+                    match elem.id {
+                        0 => exop.name = Some(string(Some(elem))),
+                        1 => exop.val = Some(octets(Some(elem))),
+                        _ => (),
+                    }
+                }
+                let (res, resp) = handler.extended(session, exop).await;
+                let mut inner = result_elems(&res);
+                if let Some(name) = resp.name {
+                    inner.push(Tag::OctetString(OctetString {
+                        id: 10,
+                        class: TagClass::Context,
+                        inner: name.into_bytes(),
+                    }));
+                }
+                if let Some(val) = resp.val {
+                    inner.push(Tag::OctetString(OctetString {
+                        id: 11,
+                        class: TagClass::Context,
+                        inner: val,
+                    }));
+                }
+                let tag = Tag::Sequence(Sequence {
+                    id: 24,
+                    class: TagClass::Application,
+                    inner,
+                });
+                vec![(tag, response_ctrls(res.ctrls))]
+            }
+            _ => vec![protocol_error(response_id(op_id), "unsupported operation")],
+        }
+    }
+}
+
+fn bind_request(elems: Vec<StructureTag>) -> Option<BindRequest> {
+    let mut elems = elems.into_iter();
+    let version = uint(elems.next()) as u8;
+    let dn = string(elems.next());
+    let auth = elems.next()?;
+    let auth = match (auth.class, auth.id) {
+        (TagClass::Context, 0) => BindAuth::Simple(octets(Some(auth))),
+        (TagClass::Context, 3) => {
+            let mut parts = children(auth).into_iter();
+            BindAuth::Sasl {
+                mechanism: string(parts.next()),
+                credentials: parts.next().map(|t| octets(Some(t))),
+            }
+        }
+        _ => return None,
+    };
+    Some(BindRequest { version, dn, auth })
+}
+
+fn search_request(elems: Vec<StructureTag>) -> Option<SearchRequest> {
+    let mut elems = elems.into_iter();
+    let base = string(elems.next());
+    let scope = match uint(elems.next()) {
+        0 => Scope::Base,
+        1 => Scope::OneLevel,
+        2 => Scope::Subtree,
+        _ => return None,
+    };
+    let deref = match uint(elems.next()) {
+        0 => DerefAliases::Never,
+        1 => DerefAliases::Searching,
+        2 => DerefAliases::Finding,
+        _ => DerefAliases::Always,
+    };
+    let sizelimit = uint(elems.next()) as i32;
+    let timelimit = uint(elems.next()) as i32;
+    let typesonly = octets(elems.next()).first().is_some_and(|b| *b != 0);
+    let filter = unparse_filter(&filter_from_structure(elems.next()?));
+    let attrs = children_of(elems.next())
+        .into_iter()
+        .map(|t| string(Some(t)))
+        .collect::<Vec<_>>();
+    let opts = SearchOptions::new()
+        .deref(deref)
+        .sizelimit(sizelimit)
+        .timelimit(timelimit)
+        .typesonly(typesonly);
+    Some(SearchRequest::new(&base, scope, &filter, attrs).opts(opts))
+}
+
+fn modify_request(elems: Vec<StructureTag>) -> Option<ModifyRequest> {
+    let mut elems = elems.into_iter();
+    let dn = string(elems.next());
+    let mut mods = vec![];
+    for change in children_of(elems.next()) {
+        let mut parts = children(change).into_iter();
+        let op = uint(parts.next());
+        let mut attr = children_of(parts.next()).into_iter();
+        let name = octets(attr.next());
+        let vals = values(attr.next());
+        mods.push(match op {
+            0 => Mod::Add(name, vals),
+            1 => Mod::Delete(name, vals),
+            2 => Mod::Replace(name, vals),
+            3 => Mod::Increment(name, vals.into_iter().next().unwrap_or_default()),
+            _ => return None,
+        });
+    }
+    Some(ModifyRequest { dn, mods })
+}
+
+fn values(t: Option<StructureTag>) -> HashSet<Vec<u8>> {
+    children_of(t)
+        .into_iter()
+        .map(|v| octets(Some(v)))
+        .collect()
+}
+
+fn response_id(req_id: u64) -> u64 {
+    match req_id {
+        3 => 5,
+        23 => 24,
+        _ => req_id + 1,
+    }
+}
+
+fn response_ctrls(ctrls: Vec<Control>) -> MaybeControls {
+    if ctrls.is_empty() {
+        None
+    } else {
+        Some(ctrls.into_iter().map(|c| c.1).collect())
+    }
+}
+
+fn result_elems(res: &LdapResult) -> Vec<Tag> {
+    let mut elems = vec![
+        Tag::Enumerated(Enumerated {
+            inner: res.rc as i64,
+            ..Default::default()
+        }),
+        octet_string(res.matched.as_bytes()),
+        octet_string(res.text.as_bytes()),
+    ];
+    if !res.refs.is_empty() {
+        elems.push(Tag::Sequence(Sequence {
+            id: 3,
+            class: TagClass::Context,
+            inner: res
+                .refs
+                .iter()
+                .map(|r| octet_string(r.as_bytes()))
+                .collect(),
+        }));
+    }
+    elems
+}
+
+fn result_tag(id: u64, res: &LdapResult) -> Tag {
+    Tag::Sequence(Sequence {
+        id,
+        class: TagClass::Application,
+        inner: result_elems(res),
+    })
+}
+
+fn result_response(id: u64, res: LdapResult) -> (Tag, MaybeControls) {
+    (result_tag(id, &res), response_ctrls(res.ctrls))
+}
+
+fn protocol_error(id: u64, text: &str) -> (Tag, MaybeControls) {
+    let res = LdapResult {
+        rc: 2,
+        text: text.to_owned(),
+        ..LdapResult::default()
+    };
+    (result_tag(id, &res), None)
+}
+
+fn entry_response(entry: SearchEntry) -> (Tag, MaybeControls) {
+    let text = entry
+        .attrs
+        .into_iter()
+        .map(|(name, vals)| (name, vals.into_iter().map(String::into_bytes).collect()));
+    let attrs = text
+        .chain(entry.bin_attrs)
+        .map(|(name, vals): (String, Vec<Vec<u8>>)| {
+            Tag::Sequence(Sequence {
+                inner: vec![
+                    octet_string(name.as_bytes()),
+                    Tag::Set(Set {
+                        inner: vals.iter().map(|v| octet_string(v)).collect(),
+                        ..Default::default()
+                    }),
+                ],
+                ..Default::default()
+            })
+        })
+        .collect();
+    let tag = Tag::Sequence(Sequence {
+        id: 4,
+        class: TagClass::Application,
+        inner: vec![
+            octet_string(entry.dn.as_bytes()),
+            Tag::Sequence(Sequence {
+                inner: attrs,
+                ..Default::default()
+            }),
+        ],
+    });
+    (tag, response_ctrls(entry.ctrls))
+}
+
+fn octet_string(val: &[u8]) -> Tag {
+    Tag::OctetString(OctetString {
+        inner: val.to_vec(),
+        ..Default::default()
+    })
+}
+
+fn children(t: StructureTag) -> Vec<StructureTag> {
+    t.expect_constructed().unwrap_or_default()
+}
+
+fn children_of(t: Option<StructureTag>) -> Vec<StructureTag> {
+    t.map(children).unwrap_or_default()
+}
+
+fn octets(t: Option<StructureTag>) -> Vec<u8> {
+    match t.map(|t| t.payload) {
+        Some(PL::P(val)) => val,
+        _ => vec![],
+    }
+}
+
+fn string(t: Option<StructureTag>) -> String {
+    String::from_utf8_lossy(&octets(t)).into_owned()
+}
+
+fn uint(t: Option<StructureTag>) -> u64 {
+    match parse_uint(&octets(t)) {
+        IResult::Done(_, val) => val,
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::{BindAuth, BindRequest, Handler, LdapServer, Session};
+    use crate::request::{ModifyRequest, SearchRequest};
+    use crate::{LdapConnAsync, LdapConnSettings, LdapResult, Mod, Scope, SearchEntry};
+
+    use async_trait::async_trait;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct Recording {
+        seen: Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl Handler for Recording {
+        async fn bind(&self, _session: &mut Session, req: BindRequest) -> LdapResult {
+            let rc = match req.auth {
+                BindAuth::Simple(ref pw) if pw == b"secret" => 0,
+                _ => 49,
+            };
+            LdapResult {
+                rc,
+                ..LdapResult::default()
+            }
+        }
+
+        async fn search(
+            &self,
+            session: &Session,
+            req: SearchRequest,
+        ) -> (Vec<SearchEntry>, LdapResult) {
+            self.seen.lock().unwrap().push(req.filter.clone());
+            let entry = SearchEntry {
+                dn: req.base,
+                attrs: vec![("boundAs".to_owned(), vec![session.bound_dn().to_owned()])]
+                    .into_iter()
+                    .collect(),
+                bin_attrs: HashMap::new(),
+                ctrls: vec![],
+            };
+            (vec![entry], LdapResult::default())
+        }
+
+        async fn modify(&self, _session: &Session, req: ModifyRequest) -> LdapResult {
+            self.seen.lock().unwrap().push(format!("{:?}", req.mods));
+            LdapResult::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn serve_requests() {
+        let (client, server) = tokio::io::duplex(16 * 1024);
+        let srv = LdapServer::new(Recording::default());
+        let handler = srv.handler.clone();
+        tokio::spawn(async move { srv.serve_stream(server, None).await });
+        let (conn, mut ldap) = LdapConnAsync::from_stream(client, LdapConnSettings::new());
+        crate::drive!(conn);
+        let res = ldap.simple_bind("cn=x", "wrong").await.unwrap();
+        assert_eq!(res.rc, 49);
+        ldap.simple_bind("cn=x", "secret")
+            .await
+            .unwrap()
+            .success()
+            .unwrap();
+        let (rs, _res) = ldap
+            .search(
+                "o=x",
+                Scope::Base,
+                "(&(cn=a*b)(!(sn=\\2a)))",
+                vec!["boundAs"],
+            )
+            .await
+            .unwrap()
+            .success()
+            .unwrap();
+        let entry = SearchEntry::construct(rs[0].clone());
+        assert_eq!(entry.attrs["boundAs"], vec!["cn=x"]);
+        ldap.modify("o=x", vec![Mod::Increment("n", "1")])
+            .await
+            .unwrap()
+            .success()
+            .unwrap();
+        let res = ldap.delete("o=x").await.unwrap();
+        assert_eq!(res.rc, 53);
+        let seen = handler.seen.lock().unwrap();
+        assert_eq!(seen[0], "(&(cn=a*b)(!(sn=\\2a)))");
+        assert_eq!(seen[1], "[Increment([110], [49])]");
+    }
+}
@@ -0,0 +1,369 @@
+use std::sync::Arc;
+
+use super::{BindAuth, BindRequest, Handler, Session};
+use crate::conn::{LdapConnAsync, LdapConnSettings};
+use crate::controls::RawControl;
+use crate::exop::Exop;
+use crate::ldap::Ldap;
+use crate::request::{
+    AddRequest, CompareRequest, DeleteRequest, ExtendedRequest, ModifyDnRequest, ModifyRequest,
+    Request, SearchRequest,
+};
+use crate::result::{LdapResult, Result};
+use crate::search::SearchEntry;
+
+use async_trait::async_trait;
+
+/// Rewriting hooks of a [`Proxy`](struct.Proxy.html).
+///
+/// DNs are rewritten in both directions: names in requests are mapped to the upstream
+/// namespace with [`request_dn()`](#method.request_dn), and names in responses, that is,
+/// the DNs of search entries and the matched DNs of results, are mapped back with
+/// [`response_dn()`](#method.response_dn). Attribute values are passed unchanged. The
+/// defaults leave everything as it is.
+pub trait Rewrite: Send + Sync + 'static {
+    /// Map a DN from a client request to the upstream namespace.
+    fn request_dn(&self, dn: &str) -> String {
+        dn.to_owned()
+    }
+
+    /// Map a DN from an upstream response to the client namespace.
+    fn response_dn(&self, dn: &str) -> String {
+        dn.to_owned()
+    }
+
+    /// Rewrite the filter of a Search request.
+    fn filter(&self, filter: &str) -> String {
+        filter.to_owned()
+    }
+
+    /// Adjust the controls of a request before it's sent upstream. The controls
+    /// received from the client are already in `ctrls`.
+    fn controls(&self, _session: &Session, _ctrls: &mut Vec<RawControl>) {}
+}
+
+struct Verbatim;
+
+impl Rewrite for Verbatim {}
+
+/// Handler which forwards operations to an upstream server.
+///
+/// All clients share the upstream connection, whose identity is established before
+/// the proxy is created. For that reason, a client Bind is never forwarded. Anonymous
+/// Binds succeed, while others are refused unless [`verify_binds()`](#method.verify_binds)
+/// is used, in which case the credentials are checked on a separate connection, and the
+/// session remembers the bound DN. Together with a [`Rewrite`](trait.Rewrite.html) which
+/// adds a [`ProxyAuth`](../controls/struct.ProxyAuth.html) control for that DN, client
+/// operations are then performed upstream with the client's authorization.
+///
+/// ## Example
+///
+/// ```rust,no_run
+/// use ldap3::controls::{ProxyAuth, RawControl};
+/// use ldap3::server::{LdapServer, Proxy, Rewrite, Session};
+/// use ldap3::{LdapConnAsync, LdapConnSettings};
+/// use tokio::net::TcpListener;
+/// # use ldap3::result::Result;
+///
+/// struct Virtual;
+///
+/// impl Rewrite for Virtual {
+///     fn request_dn(&self, dn: &str) -> String {
+///         dn.replace("o=virtual", "dc=example,dc=org")
+///     }
+///
+///     fn response_dn(&self, dn: &str) -> String {
+///         dn.replace("dc=example,dc=org", "o=virtual")
+///     }
+///
+///     fn controls(&self, session: &Session, ctrls: &mut Vec<RawControl>) {
+///         let authzid = format!("dn:{}", self.request_dn(session.bound_dn()));
+///         ctrls.push(ProxyAuth { authzid }.into());
+///     }
+/// }
+///
+/// # #[tokio::main]
+/// # async fn main() -> Result<()> {
+/// let (conn, mut ldap) = LdapConnAsync::new("ldap://upstream:389").await?;
+/// ldap3::drive!(conn);
+/// ldap.simple_bind("cn=proxy,dc=example,dc=org", "secret").await?.success()?;
+/// let proxy = Proxy::new(ldap)
+///     .rewrite(Virtual)
+///     .verify_binds("ldap://upstream:389", LdapConnSettings::new());
+/// let listener = TcpListener::bind("127.0.0.1:2389").await?;
+/// LdapServer::new(proxy).serve(listener).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct Proxy {
+    upstream: Ldap,
+    rewrite: Arc<dyn Rewrite>,
+    verify: Option<(String, LdapConnSettings)>,
+}
+
+impl std::fmt::Debug for Proxy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Proxy")
+            .field("upstream", &self.upstream)
+            .field("verify", &self.verify.as_ref().map(|v| &v.0))
+            .finish()
+    }
+}
+
+fn failed(e: crate::result::LdapError) -> LdapResult {
+    LdapResult {
+        rc: 80,
+        text: format!("upstream error: {}", e),
+        ..LdapResult::default()
+    }
+}
+
+impl Proxy {
+    /// Create a proxy forwarding to `upstream`.
+    pub fn new(upstream: Ldap) -> Self {
+        Proxy {
+            upstream,
+            rewrite: Arc::new(Verbatim),
+            verify: None,
+        }
+    }
+
+    /// Set the rewriting hooks.
+    pub fn rewrite<R: Rewrite>(mut self, rewrite: R) -> Self {
+        self.rewrite = Arc::new(rewrite);
+        self
+    }
+
+    /// Check the credentials of client Binds by binding to `url`, with the DN mapped
+    /// by the rewriting hooks, on a new connection opened with `settings` for each Bind.
+    pub fn verify_binds(mut self, url: &str, settings: LdapConnSettings) -> Self {
+        self.verify = Some((url.to_owned(), settings));
+        self
+    }
+
+    async fn forward<R: Request>(&self, session: &Session, req: R) -> Result<R::Output> {
+        let mut ctrls = session.controls().to_vec();
+        self.rewrite.controls(session, &mut ctrls);
+        let mut ldap = self.upstream.clone();
+        if !ctrls.is_empty() {
+            ldap.with_controls(ctrls);
+        }
+        ldap.send(req).await
+    }
+
+    fn result(&self, res: Result<LdapResult>) -> LdapResult {
+        match res {
+            Ok(mut res) => {
+                if !res.matched.is_empty() {
+                    res.matched = self.rewrite.response_dn(&res.matched);
+                }
+                res
+            }
+            Err(e) => failed(e),
+        }
+    }
+
+    async fn check_bind(&self, dn: &str, pw: &str) -> Result<LdapResult> {
+        let (url, settings) = self.verify.as_ref().expect("verify settings");
+        let (conn, mut ldap) = LdapConnAsync::with_settings(settings.clone(), url).await?;
+        crate::drive!(conn);
+        let res = ldap.simple_bind(&self.rewrite.request_dn(dn), pw).await?;
+        ldap.unbind().await?;
+        Ok(res)
+    }
+}
+
+#[async_trait]
+impl Handler for Proxy {
+    async fn bind(&self, _session: &mut Session, req: BindRequest) -> LdapResult {
+        let pw = match req.auth {
+            BindAuth::Simple(ref pw) if req.dn.is_empty() && pw.is_empty() => {
+                return LdapResult::default()
+            }
+            BindAuth::Simple(pw) if self.verify.is_some() => pw,
+            _ => {
+                return LdapResult {
+                    rc: 53,
+                    text: String::from("bind not supported by the proxy"),
+                    ..LdapResult::default()
+                }
+            }
+        };
+        match String::from_utf8(pw) {
+            Ok(pw) => self.result(self.check_bind(&req.dn, &pw).await),
+            Err(_) => LdapResult {
+                rc: 49,
+                ..LdapResult::default()
+            },
+        }
+    }
+
+    async fn search(
+        &self,
+        session: &Session,
+        mut req: SearchRequest,
+    ) -> (Vec<SearchEntry>, LdapResult) {
+        req.base = self.rewrite.request_dn(&req.base);
+        req.filter = self.rewrite.filter(&req.filter);
+        match self.forward(session, req).await {
+            Ok(rs) => {
+                let entries =
+                    rs.0.into_iter()
+                        .map(|re| {
+                            let mut entry = SearchEntry::construct(re);
+                            entry.dn = self.rewrite.response_dn(&entry.dn);
+                            entry
+                        })
+                        .collect();
+                (entries, self.result(Ok(rs.1)))
+            }
+            Err(e) => (vec![], failed(e)),
+        }
+    }
+
+    async fn add(&self, session: &Session, mut req: AddRequest) -> LdapResult {
+        req.dn = self.rewrite.request_dn(&req.dn);
+        self.result(self.forward(session, req).await)
+    }
+
+    async fn modify(&self, session: &Session, mut req: ModifyRequest) -> LdapResult {
+        req.dn = self.rewrite.request_dn(&req.dn);
+        self.result(self.forward(session, req).await)
+    }
+
+    async fn delete(&self, session: &Session, mut req: DeleteRequest) -> LdapResult {
+        req.dn = self.rewrite.request_dn(&req.dn);
+        self.result(self.forward(session, req).await)
+    }
+
+    async fn modifydn(&self, session: &Session, mut req: ModifyDnRequest) -> LdapResult {
+        req.dn = self.rewrite.request_dn(&req.dn);
+        req.new_sup = req.new_sup.map(|sup| self.rewrite.request_dn(&sup));
+        self.result(self.forward(session, req).await)
+    }
+
+    async fn compare(&self, session: &Session, mut req: CompareRequest) -> LdapResult {
+        req.dn = self.rewrite.request_dn(&req.dn);
+        self.result(self.forward(session, req).await.map(|cr| cr.0))
+    }
+
+    async fn extended(&self, session: &mut Session, exop: Exop) -> (LdapResult, Exop) {
+        let req = ExtendedRequest { exop };
+        match self.forward(session, req).await {
+            Ok(er) => (self.result(Ok(er.1)), er.0),
+            Err(e) => (
+                failed(e),
+                Exop {
+                    name: None,
+                    val: None,
+                },
+            ),
+        }
+    }
+
+    fn handles_control(&self, _oid: &str) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    use super::{Proxy, Rewrite};
+    use crate::controls::{ProxyAuth, RawControl};
+    use crate::request::SearchRequest;
+    use crate::server::{Handler, LdapServer, Session};
+    use crate::{LdapConnAsync, LdapConnSettings, LdapResult, Scope, SearchEntry};
+
+    use async_trait::async_trait;
+
+    #[derive(Default)]
+    struct Upstream {
+        seen: Mutex<Vec<(String, String, Vec<String>)>>,
+    }
+
+    #[async_trait]
+    impl Handler for Upstream {
+        async fn search(
+            &self,
+            session: &Session,
+            req: SearchRequest,
+        ) -> (Vec<SearchEntry>, LdapResult) {
+            let ctrls = session.controls().iter().map(|c| c.ctype.clone()).collect();
+            self.seen
+                .lock()
+                .unwrap()
+                .push((req.base.clone(), req.filter, ctrls));
+            let entry = SearchEntry {
+                dn: format!("cn=x,{}", req.base),
+                attrs: HashMap::new(),
+                bin_attrs: HashMap::new(),
+                ctrls: vec![],
+            };
+            (vec![entry], LdapResult::default())
+        }
+
+        fn supported_controls(&self) -> &[&str] {
+            &["2.16.840.1.113730.3.4.18"]
+        }
+    }
+
+    struct Virtual;
+
+    impl Rewrite for Virtual {
+        fn request_dn(&self, dn: &str) -> String {
+            dn.replace("o=virtual", "o=real")
+        }
+
+        fn response_dn(&self, dn: &str) -> String {
+            dn.replace("o=real", "o=virtual")
+        }
+
+        fn filter(&self, filter: &str) -> String {
+            format!("(&{}(objectClass=person))", filter)
+        }
+
+        fn controls(&self, _session: &Session, ctrls: &mut Vec<RawControl>) {
+            ctrls.push(
+                ProxyAuth {
+                    authzid: String::from("dn:cn=client"),
+                }
+                .into(),
+            );
+        }
+    }
+
+    fn connect<H: Handler>(server: LdapServer<H>) -> crate::Ldap {
+        let (client, stream) = tokio::io::duplex(16 * 1024);
+        tokio::spawn(async move { server.serve_stream(stream, None).await });
+        let (conn, ldap) = LdapConnAsync::from_stream(client, LdapConnSettings::new());
+        crate::drive!(conn);
+        ldap
+    }
+
+    #[tokio::test]
+    async fn rewrite_and_forward() {
+        let upstream = LdapServer::new(Upstream::default());
+        let handler = upstream.handler.clone();
+        let proxy = Proxy::new(connect(upstream)).rewrite(Virtual);
+        let mut ldap = connect(LdapServer::new(proxy));
+        let (rs, _res) = ldap
+            .search("ou=a,o=virtual", Scope::Subtree, "(cn=x)", vec!["cn"])
+            .await
+            .unwrap()
+            .success()
+            .unwrap();
+        assert_eq!(
+            SearchEntry::construct(rs[0].clone()).dn,
+            "cn=x,ou=a,o=virtual"
+        );
+        let res = ldap.simple_bind("cn=client,o=virtual", "pw").await.unwrap();
+        assert_eq!(res.rc, 53);
+        let seen = handler.seen.lock().unwrap();
+        assert_eq!(seen[0].0, "ou=a,o=real");
+        assert_eq!(seen[0].1, "(&(cn=x)(objectClass=person))");
+        assert_eq!(seen[0].2, vec!["2.16.840.1.113730.3.4.18"]);
+    }
+}
@@ -0,0 +1,221 @@
+//! `tower::Service` adapter for the `Ldap` handle.
+//!
+//! Wrapping a handle in [`LdapService`](struct.LdapService.html) makes LDAP operations
+//! composable with the middleware of the `tower` ecosystem: timeouts, retries, rate
+//! limiting, load shedding, and the like. An operation is described by an
+//! [`LdapRequest`](enum.LdapRequest.html), which owns all of its parameters, and yields
+//! an [`LdapResponse`](enum.LdapResponse.html) with the variant corresponding to the
+//! operation type.
+//!
+//! Every call is performed on a clone of the wrapped handle, so the service is always
+//! ready, and any number of requests can be in flight at the same time. Controls and
+//! timeouts set on the wrapped handle are not propagated to the clones.
+//!
+//! This module is available with the __tower__ feature.
+
+use std::collections::HashSet;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use crate::exop::Exop;
+use crate::ldap::{Ldap, Mod};
+use crate::result::{CompareResult, ExopResult, LdapError, LdapResult, SearchResult};
+use crate::search::Scope;
+
+use tower_service::Service;
+
+/// Description of an LDAP operation.
+///
+/// The variants correspond to the methods of [`Ldap`](../struct.Ldap.html) with the same
+/// name, and have the same parameters.
+#[derive(Clone, Debug)]
+pub enum LdapRequest {
+    /// Simple Bind.
+    SimpleBind { dn: String, pw: String },
+    /// SASL EXTERNAL Bind.
+    SaslExternalBind,
+    /// Search, returning all results at once.
+    Search {
+        base: String,
+        scope: Scope,
+        filter: String,
+        attrs: Vec<String>,
+    },
+    /// Add.
+    Add {
+        dn: String,
+        attrs: Vec<(Vec<u8>, HashSet<Vec<u8>>)>,
+    },
+    /// Compare.
+    Compare {
+        dn: String,
+        attr: String,
+        val: Vec<u8>,
+    },
+    /// Delete.
+    Delete { dn: String },
+    /// Modify.
+    Modify { dn: String, mods: Vec<Mod<Vec<u8>>> },
+    /// ModifyDN.
+    ModifyDn {
+        dn: String,
+        rdn: String,
+        delete_old: bool,
+        new_sup: Option<String>,
+    },
+    /// Extended operation.
+    Extended(Exop),
+}
+
+/// Result of an LDAP operation performed through the service.
+#[derive(Clone, Debug)]
+pub enum LdapResponse {
+    /// Result of Bind, Add, Delete, Modify and ModifyDN.
+    Result(LdapResult),
+    /// Result of Search.
+    Search(SearchResult),
+    /// Result of Compare.
+    Compare(CompareResult),
+    /// Result of an Extended operation.
+    Extended(ExopResult),
+}
+
+/// `tower::Service` implementation over an `Ldap` handle. __*__
+///
+/// See the [module-level documentation](index.html) for the overview.
+#[derive(Clone, Debug)]
+pub struct LdapService {
+    ldap: Ldap,
+}
+
+impl LdapService {
+    /// Wrap an `Ldap` handle.
+    pub fn new(ldap: Ldap) -> Self {
+        LdapService { ldap }
+    }
+
+    /// Return the wrapped handle.
+    pub fn into_inner(self) -> Ldap {
+        self.ldap
+    }
+}
+
+impl From<Ldap> for LdapService {
+    fn from(ldap: Ldap) -> Self {
+        LdapService::new(ldap)
+    }
+}
+
+impl Service<LdapRequest> for LdapService {
+    type Response = LdapResponse;
+    type Error = LdapError;
+    type Future = Pin<Box<dyn Future<Output = Result<LdapResponse, LdapError>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), LdapError>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: LdapRequest) -> Self::Future {
+        let mut ldap = self.ldap.clone();
+        Box::pin(async move {
+            Ok(match req {
+                LdapRequest::SimpleBind { dn, pw } => {
+                    LdapResponse::Result(ldap.simple_bind(&dn, &pw).await?)
+                }
+                LdapRequest::SaslExternalBind => {
+                    LdapResponse::Result(ldap.sasl_external_bind().await?)
+                }
+                LdapRequest::Search {
+                    base,
+                    scope,
+                    filter,
+                    attrs,
+                } => LdapResponse::Search(ldap.search(&base, scope, &filter, attrs).await?),
+                LdapRequest::Add { dn, attrs } => LdapResponse::Result(ldap.add(&dn, attrs).await?),
+                LdapRequest::Compare { dn, attr, val } => {
+                    LdapResponse::Compare(ldap.compare(&dn, &attr, val).await?)
+                }
+                LdapRequest::Delete { dn } => LdapResponse::Result(ldap.delete(&dn).await?),
+                LdapRequest::Modify { dn, mods } => {
+                    LdapResponse::Result(ldap.modify(&dn, mods).await?)
+                }
+                LdapRequest::ModifyDn {
+                    dn,
+                    rdn,
+                    delete_old,
+                    new_sup,
+                } => LdapResponse::Result(
+                    ldap.modifydn(&dn, &rdn, delete_old, new_sup.as_deref())
+                        .await?,
+                ),
+                LdapRequest::Extended(exop) => LdapResponse::Extended(ldap.extended(exop).await?),
+            })
+        })
+    }
+}
+
+#[cfg(all(test, feature = "testkit"))]
+mod test {
+    use super::{LdapRequest, LdapResponse, LdapService};
+    use crate::exop_impl::WhoAmI;
+    use crate::testkit::MockServer;
+    use crate::Scope;
+
+    use futures::future::poll_fn;
+    use maplit::hashset;
+    use tower_service::Service;
+
+    async fn call(svc: &mut LdapService, req: LdapRequest) -> LdapResponse {
+        poll_fn(|cx| svc.poll_ready(cx)).await.unwrap();
+        svc.call(req).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn operations() {
+        let server = MockServer::new();
+        server.add_entry("dc=example,dc=org", vec![("dc", vec!["example"])]);
+        let (conn, ldap) = server.connect();
+        crate::drive!(conn);
+        let mut svc = LdapService::from(ldap);
+        let req = LdapRequest::Add {
+            dn: "uid=test,dc=example,dc=org".into(),
+            attrs: vec![(b"uid".to_vec(), hashset! { b"test".to_vec() })],
+        };
+        match call(&mut svc, req).await {
+            LdapResponse::Result(res) => assert_eq!(res.rc, 0),
+            resp => panic!("unexpected response: {:?}", resp),
+        }
+        let req = LdapRequest::Search {
+            base: "dc=example,dc=org".into(),
+            scope: Scope::OneLevel,
+            filter: "(uid=test)".into(),
+            attrs: vec!["uid".into()],
+        };
+        match call(&mut svc, req).await {
+            LdapResponse::Search(res) => assert_eq!(res.success().unwrap().0.len(), 1),
+            resp => panic!("unexpected response: {:?}", resp),
+        }
+        let req = LdapRequest::Compare {
+            dn: "uid=test,dc=example,dc=org".into(),
+            attr: "uid".into(),
+            val: b"test".to_vec(),
+        };
+        match call(&mut svc, req).await {
+            LdapResponse::Compare(res) => assert!(res.equal().unwrap()),
+            resp => panic!("unexpected response: {:?}", resp),
+        }
+        match call(&mut svc, LdapRequest::Extended(WhoAmI.into())).await {
+            LdapResponse::Extended(res) => assert_eq!(res.1.rc, 0),
+            resp => panic!("unexpected response: {:?}", resp),
+        }
+        let req = LdapRequest::Delete {
+            dn: "uid=missing,dc=example,dc=org".into(),
+        };
+        match call(&mut svc, req).await {
+            LdapResponse::Result(res) => assert_eq!(res.rc, 32),
+            resp => panic!("unexpected response: {:?}", resp),
+        }
+        assert!(!svc.into_inner().is_closed());
+    }
+}
@@ -3,12 +3,16 @@ use std::hash::Hash;
 use std::time::Duration;
 
 use crate::adapters::IntoAdapterVec;
-use crate::conn::{LdapConnAsync, LdapConnSettings};
+use crate::conn::{LdapConnAsync, LdapConnSettings, TlsInfo};
 use crate::controls_impl::IntoRawControlVec;
 use crate::exop::Exop;
-use crate::ldap::{Ldap, Mod};
-use crate::result::{CompareResult, ExopResult, LdapResult, Result, SearchResult};
-use crate::search::{ResultEntry, Scope, SearchOptions, SearchStream};
+use crate::ldap::{Ldap, Mod, ShutdownReport};
+use crate::request::Request;
+use crate::result::{
+    BindOutcome, CompareResult, ExopResult, LdapResult, Result, SearchOutcome, SearchResult,
+};
+use crate::search::{ResultEntry, ResultSize, Scope, SearchOptions, SearchStream};
+use crate::util::LdapUrl;
 use crate::RequestId;
 
 use tokio::runtime::{self, Runtime};
@@ -80,6 +84,18 @@ impl LdapConn {
         self
     }
 
+    /// See [`Ldap::with_authzid_request()`](struct.Ldap.html#method.with_authzid_request).
+    pub fn with_authzid_request(&mut self) -> &mut Self {
+        self.ldap.authzid_request = true;
+        self
+    }
+
+    /// See [`Ldap::dry_run()`](struct.Ldap.html#method.dry_run).
+    pub fn dry_run(&mut self) -> &mut Self {
+        self.ldap.dry_run = true;
+        self
+    }
+
     /// See [`Ldap::with_timeout()`](struct.Ldap.html#method.with_timeout).
     pub fn with_timeout(&mut self, duration: Duration) -> &mut Self {
         self.ldap.timeout = Some(duration);
@@ -88,7 +104,7 @@ impl LdapConn {
 
     /// See [`Ldap::is_closed()`](struct.Ldap.html#method.is_closed).
     pub fn is_closed(&mut self) -> bool {
-        self.ldap.tx.is_closed()
+        self.ldap.shared.tx.is_closed()
     }
 
     /// See [`Ldap::simple_bind()`](struct.Ldap.html#method.simple_bind).
@@ -98,6 +114,13 @@ impl LdapConn {
         rt.block_on(async move { ldap.simple_bind(bind_dn, bind_pw).await })
     }
 
+    /// See [`Ldap::simple_bind_ppolicy()`](struct.Ldap.html#method.simple_bind_ppolicy).
+    pub fn simple_bind_ppolicy(&mut self, bind_dn: &str, bind_pw: &str) -> Result<BindOutcome> {
+        let rt = &mut self.rt;
+        let ldap = &mut self.ldap;
+        rt.block_on(async move { ldap.simple_bind_ppolicy(bind_dn, bind_pw).await })
+    }
+
     /// See [`Ldap::sasl_external_bind()`](struct.Ldap.html#method.sasl_external_bind).
     pub fn sasl_external_bind(&mut self) -> Result<LdapResult> {
         let rt = &mut self.rt;
@@ -118,6 +141,35 @@ impl LdapConn {
         rt.block_on(async move { ldap.search(base, scope, filter, attrs).await })
     }
 
+    /// See [`Ldap::search_outcome()`](struct.Ldap.html#method.search_outcome).
+    pub fn search_outcome<'a, S: AsRef<str> + Send + Sync + 'a>(
+        &mut self,
+        base: &str,
+        scope: Scope,
+        filter: &str,
+        attrs: Vec<S>,
+    ) -> Result<SearchOutcome> {
+        let rt = &mut self.rt;
+        let ldap = &mut self.ldap;
+        rt.block_on(async move { ldap.search_outcome(base, scope, filter, attrs).await })
+    }
+
+    /// Check whether the server is responsive. See
+    /// [`Ldap::check_alive()`](struct.Ldap.html#method.check_alive).
+    pub fn check_alive(&mut self, timeout: Duration) -> Result<()> {
+        let rt = &mut self.rt;
+        let ldap = &mut self.ldap;
+        rt.block_on(async move { ldap.check_alive(timeout).await })
+    }
+
+    /// Perform a Search with the parameters given by an LDAP URL. See
+    /// [`Ldap::search_url()`](struct.Ldap.html#method.search_url).
+    pub fn search_url(&mut self, url: &LdapUrl) -> Result<SearchResult> {
+        let rt = &mut self.rt;
+        let ldap = &mut self.ldap;
+        rt.block_on(async move { ldap.search_url(url).await })
+    }
+
     /// Perform a Search, but unlike `search()`, which returns all results at once, return a handle which
     /// will be used for retrieving entries one by one. See [`EntryStream`](struct.EntryStream.html)
     /// for the explanation of the protocol which must be adhered to in this case.
@@ -182,6 +234,13 @@ impl LdapConn {
         rt.block_on(async move { ldap.compare(dn, attr, val).await })
     }
 
+    /// See [`Ldap::compare_bool()`](struct.Ldap.html#method.compare_bool).
+    pub fn compare_bool<B: AsRef<[u8]>>(&mut self, dn: &str, attr: &str, val: B) -> Result<bool> {
+        let rt = &mut self.rt;
+        let ldap = &mut self.ldap;
+        rt.block_on(async move { ldap.compare_bool(dn, attr, val).await })
+    }
+
     /// See [`Ldap::delete()`](struct.Ldap.html#method.delete).
     pub fn delete(&mut self, dn: &str) -> Result<LdapResult> {
         let rt = &mut self.rt;
@@ -213,6 +272,13 @@ impl LdapConn {
         rt.block_on(async move { ldap.modifydn(dn, rdn, delete_old, new_sup).await })
     }
 
+    /// See [`Ldap::shutdown()`](struct.Ldap.html#method.shutdown).
+    pub fn shutdown(&mut self, grace: Duration) -> Result<ShutdownReport> {
+        let rt = &mut self.rt;
+        let ldap = &mut self.ldap;
+        rt.block_on(async move { ldap.shutdown(grace).await })
+    }
+
     /// See [`Ldap::unbind()`](struct.Ldap.html#method.unbind).
     pub fn unbind(&mut self) -> Result<()> {
         let rt = &mut self.rt;
@@ -230,11 +296,35 @@ impl LdapConn {
         rt.block_on(async move { ldap.extended(exop).await })
     }
 
+    /// See [`Ldap::send()`](struct.Ldap.html#method.send).
+    pub fn send<R: Request>(&mut self, req: R) -> Result<R::Output> {
+        let rt = &mut self.rt;
+        let ldap = &mut self.ldap;
+        rt.block_on(async move { ldap.send(req).await })
+    }
+
     /// See [`Ldap::last_id()`](struct.Ldap.html#method.last_id).
     pub fn last_id(&mut self) -> RequestId {
         self.ldap.last_id()
     }
 
+    /// See [`Ldap::bound_identity()`](struct.Ldap.html#method.bound_identity).
+    pub fn bound_identity(&self) -> Option<String> {
+        self.ldap.bound_identity()
+    }
+
+    /// See [`Ldap::whoami_cached()`](struct.Ldap.html#method.whoami_cached).
+    pub fn whoami_cached(&mut self) -> Result<String> {
+        let rt = &mut self.rt;
+        let ldap = &mut self.ldap;
+        rt.block_on(async move { ldap.whoami_cached().await })
+    }
+
+    /// See [`Ldap::tls_info()`](struct.Ldap.html#method.tls_info).
+    pub fn tls_info(&self) -> Option<&TlsInfo> {
+        self.ldap.tls_info()
+    }
+
     /// See [`Ldap::abandon()`](struct.Ldap.html#method.abandon).
     pub fn abandon(&mut self, msgid: RequestId) -> Result<()> {
         let rt = &mut self.rt;
@@ -282,6 +372,11 @@ where
         rt.block_on(async move { stream.finish().await })
     }
 
+    /// See [`SearchStream::result_size()`](struct.SearchStream.html#method.result_size).
+    pub fn result_size(&self) -> Option<ResultSize> {
+        self.stream.result_size()
+    }
+
     /// Returns the Message ID of the initial Search.
     ///
     /// This method calls [`Ldap::last_id()`](struct.Ldap.html#method.last_id)
@@ -0,0 +1,205 @@
+// TCP connection establishment with Happy Eyeballs (RFC 8305).
+//
+// All addresses of the host are tried, IPv6 and IPv4 interleaved, with a new attempt
+// started whenever the previous one fails or hasn't completed within the attempt delay.
+// The first connection to succeed is used, and the others are dropped.
+//
+// Socket options from the connection settings are applied to the stream afterwards,
+// and the PROXY protocol header sent, if configured.
+
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+use crate::resolve::{default_resolver, Resolver};
+use crate::result::Result;
+
+use futures_util::stream::{FuturesUnordered, StreamExt};
+#[cfg(feature = "tcp-options")]
+use socket2::{SockRef, TcpKeepalive};
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+use tokio::time;
+
+// Recommended value from RFC 8305, section 5.
+const ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+pub(crate) async fn connect(
+    host: &str,
+    port: u16,
+    resolver: Option<&dyn Resolver>,
+) -> Result<TcpStream> {
+    let literal = host
+        .strip_prefix('[')
+        .and_then(|h| h.strip_suffix(']'))
+        .unwrap_or(host);
+    let addrs = match literal.parse::<IpAddr>() {
+        Ok(ip) => vec![SocketAddr::new(ip, port)],
+        Err(_) => match resolver {
+            Some(resolver) => resolver.lookup_host(host, port).await?,
+            None => default_resolver()?.lookup_host(host, port).await?,
+        },
+    };
+    Ok(connect_addrs(interleave(addrs)).await?)
+}
+
+#[cfg(feature = "tcp-options")]
+pub(crate) fn set_options(
+    stream: &TcpStream,
+    keepalive: Option<Duration>,
+    user_timeout: Option<Duration>,
+) -> io::Result<()> {
+    let sock = SockRef::from(stream);
+    if let Some(time) = keepalive {
+        sock.set_tcp_keepalive(&TcpKeepalive::new().with_time(time))?;
+    }
+    #[cfg(any(target_os = "android", target_os = "fuchsia", target_os = "linux"))]
+    if let Some(timeout) = user_timeout {
+        sock.set_tcp_user_timeout(Some(timeout))?;
+    }
+    #[cfg(not(any(target_os = "android", target_os = "fuchsia", target_os = "linux")))]
+    let _ = user_timeout;
+    Ok(())
+}
+
+const PROXY_V2_SIGNATURE: &[u8; 12] = b"\r\n\r\n\0\r\nQUIT\n";
+
+// PROXY protocol v2 header for a TCP connection from `src` to `dst`. If the address
+// families differ, IPv4 addresses are mapped to IPv6.
+fn proxy_v2_header(src: SocketAddr, dst: SocketAddr) -> Vec<u8> {
+    let mut header = PROXY_V2_SIGNATURE.to_vec();
+    // Version 2, PROXY command.
+    header.push(0x21);
+    match (src.ip(), dst.ip()) {
+        (IpAddr::V4(s), IpAddr::V4(d)) => {
+            header.push(0x11);
+            header.extend_from_slice(&12u16.to_be_bytes());
+            header.extend_from_slice(&s.octets());
+            header.extend_from_slice(&d.octets());
+        }
+        (s, d) => {
+            let v6 = |ip: IpAddr| match ip {
+                IpAddr::V4(ip) => ip.to_ipv6_mapped(),
+                IpAddr::V6(ip) => ip,
+            };
+            header.push(0x21);
+            header.extend_from_slice(&36u16.to_be_bytes());
+            header.extend_from_slice(&v6(s).octets());
+            header.extend_from_slice(&v6(d).octets());
+        }
+    }
+    header.extend_from_slice(&src.port().to_be_bytes());
+    header.extend_from_slice(&dst.port().to_be_bytes());
+    header
+}
+
+pub(crate) async fn send_proxy_header(stream: &mut TcpStream, src: SocketAddr) -> io::Result<()> {
+    let dst = stream.peer_addr()?;
+    stream.write_all(&proxy_v2_header(src, dst)).await
+}
+
+fn interleave(addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let (v6, v4): (Vec<_>, Vec<_>) = addrs.into_iter().partition(SocketAddr::is_ipv6);
+    let mut v6 = v6.into_iter();
+    let mut v4 = v4.into_iter();
+    let mut addrs = vec![];
+    loop {
+        match (v6.next(), v4.next()) {
+            (None, None) => break,
+            (a, b) => addrs.extend(a.into_iter().chain(b)),
+        }
+    }
+    addrs
+}
+
+async fn connect_addrs(addrs: Vec<SocketAddr>) -> io::Result<TcpStream> {
+    let mut addrs = addrs.into_iter();
+    let mut attempts = FuturesUnordered::new();
+    match addrs.next() {
+        Some(addr) => attempts.push(TcpStream::connect(addr)),
+        None => {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrNotAvailable,
+                "no addresses to connect to",
+            ))
+        }
+    }
+    loop {
+        tokio::select! {
+            res = attempts.next() => match res {
+                Some(Ok(stream)) => return Ok(stream),
+                Some(Err(e)) => match addrs.next() {
+                    Some(addr) => attempts.push(TcpStream::connect(addr)),
+                    None if attempts.is_empty() => return Err(e),
+                    None => (),
+                },
+                None => unreachable!("no connection attempts in progress"),
+            },
+            _ = time::sleep(ATTEMPT_DELAY) => {
+                if let Some(addr) = addrs.next() {
+                    attempts.push(TcpStream::connect(addr));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn skip_failed_address() {
+        let refused = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let refused_addr = refused.local_addr().unwrap();
+        drop(refused);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let stream = connect_addrs(vec![refused_addr, addr]).await.unwrap();
+        assert_eq!(stream.peer_addr().unwrap(), addr);
+    }
+
+    #[cfg(feature = "tcp-options")]
+    #[tokio::test]
+    async fn keepalive_option() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let stream = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        set_options(&stream, Some(Duration::from_secs(60)), None).unwrap();
+        assert!(SockRef::from(&stream).keepalive().unwrap());
+    }
+
+    #[test]
+    fn proxy_header() {
+        let header = proxy_v2_header(
+            "192.0.2.1:50000".parse().unwrap(),
+            "192.0.2.2:389".parse().unwrap(),
+        );
+        assert_eq!(&header[..12], PROXY_V2_SIGNATURE);
+        assert_eq!(
+            &header[12..],
+            &[0x21, 0x11, 0, 12, 192, 0, 2, 1, 192, 0, 2, 2, 0xc3, 0x50, 0x01, 0x85]
+        );
+        let header = proxy_v2_header(
+            "192.0.2.1:50000".parse().unwrap(),
+            "[2001:db8::1]:389".parse().unwrap(),
+        );
+        assert_eq!(header.len(), 16 + 36);
+        assert_eq!(&header[13..16], &[0x21, 0, 36]);
+        assert_eq!(&header[26..32], &[0xff, 0xff, 192, 0, 2, 1]);
+    }
+
+    #[test]
+    fn interleave_families() {
+        let addrs: Vec<SocketAddr> = vec![
+            "192.0.2.1:389".parse().unwrap(),
+            "192.0.2.2:389".parse().unwrap(),
+            "[2001:db8::1]:389".parse().unwrap(),
+        ];
+        let ordered = interleave(addrs.clone());
+        assert_eq!(ordered, vec![addrs[2], addrs[0], addrs[1]]);
+    }
+}
@@ -0,0 +1,1159 @@
+//! In-process mock LDAP server for testing.
+//!
+//! Code which uses LDAP is hard to test without a directory server, and running one
+//! alongside a test suite is a chore. [`MockServer`](struct.MockServer.html) keeps an
+//! entry tree in memory and serves it to connections obtained by calling
+//! [`connect()`](struct.MockServer.html#method.connect), which returns the same
+//! connection/handle pair as [`LdapConnAsync::new()`](../struct.LdapConnAsync.html#method.new).
+//! No sockets are involved: the client and the server are joined by an in-memory stream.
+//!
+//! The server understands the following operations:
+//!
+//! * Simple Bind, checked against the plaintext values of the `userPassword` attribute.
+//!   An anonymous Bind always succeeds, while an unauthenticated Bind (with a DN, but an
+//!   empty password) is refused. The Authorization Identity Request control is honored.
+//!
+//! * Search, with all standard filter items except extensible match. Approximate match
+//!   is treated as equality. The operational attributes of entries are neither kept nor
+//!   returned, but the root DSE is synthesized for a base-scoped Search of the empty DN.
+//!
+//! * Add, Modify, Delete, ModifyDN and Compare.
+//!
+//! * The Who Am I extended operation.
+//!
+//! DN and attribute value comparisons are case-insensitive, and spaces around RDN
+//! separators are ignored; escaped characters and multi-valued RDNs are compared verbatim.
+//! The parent of an added entry doesn't have to exist. The No-Op control is honored for
+//! updates, and a Search with the Paged Results control is done in a single page, whose
+//! size estimate is the number of entries. Requests with other critical controls fail with `unavailableCriticalExtension`,
+//! while other non-critical controls are ignored.
+//!
+//! For tests which must run against the behavior of a particular server, a session with
+//! the real server can be recorded and later replayed using a [`Cassette`](struct.Cassette.html).
+//!
+//! This module is available with the __testkit__ feature.
+//!
+//! ## Example
+//!
+//! ```rust
+//! use ldap3::testkit::MockServer;
+//! use ldap3::{Scope, SearchEntry};
+//! # use ldap3::result::Result;
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<()> {
+//! let server = MockServer::new();
+//! server.add_entry("dc=example,dc=org", vec![("objectClass", vec!["domain"]), ("dc", vec!["example"])]);
+//! server.add_entry(
+//!     "uid=test,dc=example,dc=org",
+//!     vec![("objectClass", vec!["account"]), ("uid", vec!["test"]), ("userPassword", vec!["secret"])],
+//! );
+//! let (conn, mut ldap) = server.connect();
+//! ldap3::drive!(conn);
+//! ldap.simple_bind("uid=test,dc=example,dc=org", "secret").await?.success()?;
+//! let (rs, _res) = ldap
+//!     .search("dc=example,dc=org", Scope::Subtree, "(uid=t*)", vec!["uid"])
+//!     .await?
+//!     .success()?;
+//! assert_eq!(rs.len(), 1);
+//! assert_eq!(SearchEntry::construct(rs[0].clone()).attrs["uid"], vec!["test"]);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use crate::conn::{LdapConnAsync, LdapConnSettings};
+use crate::controls::{Control, PagedResults, RawControl, NO_OP_RC};
+use crate::ldap::Ldap;
+use crate::protocol::{LdapCodec, MaybeControls};
+use crate::search::SearchEntry;
+use crate::util::{normalize_dn, rdn_ava, split_rdns};
+use crate::RequestId;
+
+use lber::common::TagClass;
+use lber::parse::parse_uint;
+use lber::structure::{StructureTag, PL};
+use lber::structures::{Enumerated, OctetString, Sequence, Set, Tag};
+use lber::IResult;
+
+use futures_util::sink::SinkExt;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_stream::StreamExt;
+use tokio_util::codec::Decoder;
+
+mod cassette;
+pub use self::cassette::{Cassette, Recorder};
+
+const WHOAMI_OID: &str = "1.3.6.1.4.1.4203.1.11.3";
+const AUTHZ_ID_REQUEST_OID: &str = "2.16.840.1.113730.3.4.16";
+const AUTHZ_ID_RESPONSE_OID: &str = "2.16.840.1.113730.3.4.15";
+const NO_OP_OID: &str = "1.3.6.1.4.1.4203.1.10.2";
+const PAGED_RESULTS_OID: &str = "1.2.840.113556.1.4.319";
+const DUPLEX_BUFFER: usize = 64 * 1024;
+
+#[derive(Clone, Debug)]
+struct MockEntry {
+    dn: String,
+    attrs: Vec<(String, Vec<Vec<u8>>)>,
+}
+
+impl MockEntry {
+    fn values(&self, attr: &str) -> &[Vec<u8>] {
+        self.attrs
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(attr))
+            .map(|(_, vals)| vals.as_slice())
+            .unwrap_or(&[])
+    }
+
+    fn values_mut(&mut self, attr: &str) -> &mut Vec<Vec<u8>> {
+        let pos = match self
+            .attrs
+            .iter()
+            .position(|(name, _)| name.eq_ignore_ascii_case(attr))
+        {
+            Some(pos) => pos,
+            None => {
+                self.attrs.push((attr.to_owned(), vec![]));
+                self.attrs.len() - 1
+            }
+        };
+        &mut self.attrs[pos].1
+    }
+
+    fn prune(&mut self) {
+        self.attrs.retain(|(_, vals)| !vals.is_empty());
+    }
+
+    fn to_search_entry(&self) -> SearchEntry {
+        let mut attrs = HashMap::new();
+        let mut bin_attrs = HashMap::new();
+        for (name, vals) in &self.attrs {
+            let strs = vals
+                .iter()
+                .map(|v| String::from_utf8(v.clone()))
+                .collect::<Result<Vec<_>, _>>();
+            match strs {
+                Ok(strs) => {
+                    attrs.insert(name.clone(), strs);
+                }
+                Err(_) => {
+                    bin_attrs.insert(name.clone(), vals.clone());
+                }
+            }
+        }
+        SearchEntry {
+            dn: self.dn.clone(),
+            attrs,
+            bin_attrs,
+            ctrls: vec![],
+        }
+    }
+}
+
+type Dit = BTreeMap<String, MockEntry>;
+
+// Canned responses to a request, given its message id, as (message id, op, controls).
+type Script = Arc<dyn Fn(RequestId) -> Vec<(RequestId, Tag, MaybeControls)> + Send + Sync>;
+
+#[derive(Clone, Default)]
+struct Scripts(HashMap<u64, Script>);
+
+impl fmt::Debug for Scripts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.0.keys()).finish()
+    }
+}
+
+/// In-memory LDAP server.
+///
+/// Clones of the server share the same entry tree. See the
+/// [module-level documentation](index.html) for the details.
+#[derive(Clone, Debug, Default)]
+pub struct MockServer {
+    dit: Arc<Mutex<Dit>>,
+    // Protocol op and message id of every request received, in order.
+    received: Arc<Mutex<Vec<(u64, RequestId)>>>,
+    // Protocol ops which are received, but never answered.
+    ignored: Arc<Mutex<HashSet<u64>>>,
+    // Protocol ops which are answered with canned responses.
+    scripts: Arc<Mutex<Scripts>>,
+    // Protocol ops whose receipt closes the connection, with the number of times left.
+    dropped: Arc<Mutex<HashMap<u64, usize>>>,
+}
+
+impl MockServer {
+    /// Create a server with an empty entry tree.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add an entry to the tree, replacing any existing entry with the same DN.
+    pub fn add_entry<S: AsRef<[u8]>>(&self, dn: &str, attrs: Vec<(&str, Vec<S>)>) -> &Self {
+        let entry = MockEntry {
+            dn: dn.to_owned(),
+            attrs: attrs
+                .into_iter()
+                .map(|(name, vals)| {
+                    (
+                        name.to_owned(),
+                        vals.iter().map(|v| v.as_ref().to_vec()).collect(),
+                    )
+                })
+                .collect(),
+        };
+        self.lock().insert(normalize_dn(dn), entry);
+        self
+    }
+
+    /// Add `count` generated entries under `base`, for benchmarks and load tests which
+    /// need large result sets. The entries are named `uid=entryN,<base>`, with N counting
+    /// from zero, and have the `objectClass` and `uid` attributes, and `attrs` more
+    /// attributes `attr0`, `attr1`, etc., each with a single printable value of
+    /// `value_len` bytes.
+    ///
+    /// Available with the __synthetic__ feature.
+    #[cfg(feature = "synthetic")]
+    pub fn add_synthetic_entries(
+        &self,
+        base: &str,
+        count: usize,
+        attrs: usize,
+        value_len: usize,
+    ) -> &Self {
+        let mut dit = self.lock();
+        for n in 0..count {
+            let dn = format!("uid=entry{},{}", n, base);
+            let mut entry_attrs = Vec::with_capacity(attrs + 2);
+            entry_attrs.push(("objectClass".to_owned(), vec![b"account".to_vec()]));
+            entry_attrs.push(("uid".to_owned(), vec![format!("entry{}", n).into_bytes()]));
+            for a in 0..attrs {
+                let value = (0..value_len)
+                    .map(|i| b'a' + ((n + a + i) % 26) as u8)
+                    .collect();
+                entry_attrs.push((format!("attr{}", a), vec![value]));
+            }
+            dit.insert(
+                normalize_dn(&dn),
+                MockEntry {
+                    dn,
+                    attrs: entry_attrs,
+                },
+            );
+        }
+        drop(dit);
+        self
+    }
+
+    /// Return the current contents of the entry named by `dn`, if it exists.
+    pub fn entry(&self, dn: &str) -> Option<SearchEntry> {
+        self.lock()
+            .get(&normalize_dn(dn))
+            .map(|e| e.to_search_entry())
+    }
+
+    /// Return the number of entries in the tree.
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Return `true` if there are no entries in the tree.
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    /// Open a connection to the server.
+    ///
+    /// The server side of the connection is spawned on the current Tokio runtime,
+    /// and the returned connection must be driven like any other.
+    pub fn connect(&self) -> (LdapConnAsync, Ldap) {
+        self.connect_with_settings(LdapConnSettings::new())
+    }
+
+    /// Open a connection to the server, using `settings` for the client side.
+    /// Only the settings which don't concern establishing the connection are
+    /// meaningful.
+    pub fn connect_with_settings(&self, settings: LdapConnSettings) -> (LdapConnAsync, Ldap) {
+        let (client, server) = tokio::io::duplex(DUPLEX_BUFFER);
+        tokio::spawn(self.session().serve(server));
+        LdapConnAsync::from_stream(client, settings)
+    }
+
+    fn session(&self) -> Session {
+        Session {
+            dit: self.dit.clone(),
+            received: self.received.clone(),
+            ignored: self.ignored.clone(),
+            scripts: self.scripts.clone(),
+            dropped: self.dropped.clone(),
+            bound: String::new(),
+        }
+    }
+
+    // Accept connections on a local TCP socket, returning the URL to connect to.
+    #[cfg(test)]
+    pub(crate) async fn listen(&self) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("listener");
+        let url = format!("ldap://{}", listener.local_addr().expect("local address"));
+        let server = self.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(server.session().serve(stream));
+            }
+        });
+        url
+    }
+
+    // Accept TLS connections on a local TCP socket, returning the URL to connect to.
+    #[cfg(all(test, feature = "tls-native"))]
+    pub(crate) async fn listen_tls(&self, acceptor: native_tls::TlsAcceptor) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("listener");
+        let url = format!("ldaps://{}", listener.local_addr().expect("local address"));
+        let acceptor = tokio_native_tls::TlsAcceptor::from(acceptor);
+        let server = self.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let (acceptor, session) = (acceptor.clone(), server.session());
+                tokio::spawn(async move {
+                    if let Ok(stream) = acceptor.accept(stream).await {
+                        session.serve(stream).await;
+                    }
+                });
+            }
+        });
+        url
+    }
+
+    // Accept connections on a Unix domain socket at `path`, returning the URL to connect to.
+    #[cfg(all(test, unix, feature = "ldapi"))]
+    pub(crate) fn listen_unix(&self, path: &std::path::Path) -> String {
+        use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+
+        let _ = std::fs::remove_file(path);
+        let listener = tokio::net::UnixListener::bind(path).expect("listener");
+        let server = self.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(server.session().serve(stream));
+            }
+        });
+        let path = path.to_str().expect("socket path");
+        format!("ldapi://{}", utf8_percent_encode(path, NON_ALPHANUMERIC))
+    }
+
+    // Accept connections on the local named pipe `name`, returning the URL to connect to.
+    #[cfg(all(test, windows, feature = "ldapi"))]
+    pub(crate) fn listen_pipe(&self, name: &str) -> String {
+        use tokio::net::windows::named_pipe::ServerOptions;
+
+        let path = format!(r"\\.\pipe\{}", name);
+        let mut pipe = ServerOptions::new()
+            .first_pipe_instance(true)
+            .create(&path)
+            .expect("pipe");
+        let server = self.clone();
+        tokio::spawn(async move {
+            while pipe.connect().await.is_ok() {
+                // A new instance must exist before the next client tries to connect.
+                let next = match ServerOptions::new().create(&path) {
+                    Ok(next) => next,
+                    Err(_) => break,
+                };
+                tokio::spawn(server.session().serve(std::mem::replace(&mut pipe, next)));
+            }
+        });
+        format!("ldapi://{}", name)
+    }
+
+    // Protocol op and message ids of the requests received so far, in order.
+    #[cfg(test)]
+    pub(crate) fn received(&self) -> Vec<(u64, RequestId)> {
+        self.received.lock().expect("received mutex").clone()
+    }
+
+    // Leave the requests with the protocol op id `op` unanswered.
+    #[cfg(test)]
+    pub(crate) fn ignore(&self, op: u64) {
+        self.ignored.lock().expect("ignored mutex").insert(op);
+    }
+
+    // Answer the requests with the protocol op id `op` with the responses returned by
+    // `script`, which is called with the message id of the request.
+    #[cfg(test)]
+    pub(crate) fn script<F>(&self, op: u64, script: F)
+    where
+        F: Fn(RequestId) -> Vec<(RequestId, Tag, MaybeControls)> + Send + Sync + 'static,
+    {
+        self.scripts
+            .lock()
+            .expect("scripts mutex")
+            .0
+            .insert(op, Arc::new(script));
+    }
+
+    // Close the connection instead of answering the next `count` requests with the
+    // protocol op id `op`.
+    #[cfg(all(test, feature = "retry"))]
+    pub(crate) fn drop_connection(&self, op: u64, count: usize) {
+        self.dropped
+            .lock()
+            .expect("dropped mutex")
+            .insert(op, count);
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Dit> {
+        self.dit.lock().expect("dit mutex")
+    }
+}
+
+struct Session {
+    dit: Arc<Mutex<Dit>>,
+    received: Arc<Mutex<Vec<(u64, RequestId)>>>,
+    ignored: Arc<Mutex<HashSet<u64>>>,
+    scripts: Arc<Mutex<Scripts>>,
+    dropped: Arc<Mutex<HashMap<u64, usize>>>,
+    bound: String,
+}
+
+impl Session {
+    async fn serve<S: AsyncRead + AsyncWrite + Unpin>(mut self, stream: S) {
+        let mut framed = LdapCodec::default().framed(stream);
+        while let Some(Ok((id, (tag, controls)))) = framed.next().await {
+            let op = match tag {
+                Tag::StructureTag(op) => op,
+                _ => break,
+            };
+            self.received
+                .lock()
+                .expect("received mutex")
+                .push((op.id, id));
+            if let Some(count) = self.dropped.lock().expect("dropped mutex").get_mut(&op.id) {
+                if *count > 0 {
+                    *count -= 1;
+                    return;
+                }
+            }
+            if self.ignored.lock().expect("ignored mutex").contains(&op.id) {
+                continue;
+            }
+            let script = self
+                .scripts
+                .lock()
+                .expect("scripts mutex")
+                .0
+                .get(&op.id)
+                .cloned();
+            if let Some(script) = script {
+                for (id, resp, ctrls) in script(id) {
+                    if framed.send((id, resp, ctrls)).await.is_err() {
+                        return;
+                    }
+                }
+                continue;
+            }
+            let authz_id = op.id == 0 && has_control(&controls, AUTHZ_ID_REQUEST_OID);
+            let no_op = matches!(op.id, 6 | 8 | 10 | 12) && has_control(&controls, NO_OP_OID);
+            let paged = op.id == 3 && has_control(&controls, PAGED_RESULTS_OID);
+            let responses = match op.id {
+                2 => break,
+                16 => continue,
+                id if has_critical(&controls) => {
+                    vec![response(response_id(id), 12, "critical control")]
+                }
+                id if no_op => self.dispatch_no_op(id, op),
+                _ => self.dispatch(op),
+            };
+            // Response controls go with the last response. A Bind has a single response,
+            // and succeeds if the bound name is set. A paged Search is done in one page.
+            let mut resp_controls = if authz_id && !self.bound.is_empty() {
+                Some(vec![RawControl {
+                    ctype: AUTHZ_ID_RESPONSE_OID.to_owned(),
+                    crit: false,
+                    val: Some(format!("dn:{}", self.bound).into_bytes()),
+                }])
+            } else if paged {
+                Some(vec![RawControl::from(PagedResults {
+                    size: responses.len() as i32 - 1,
+                    cookie: vec![],
+                })])
+            } else {
+                None
+            };
+            let last = responses.len().saturating_sub(1);
+            for (n, resp) in responses.into_iter().enumerate() {
+                let ctrls = if n == last {
+                    resp_controls.take()
+                } else {
+                    None
+                };
+                if framed.send((id, resp, ctrls)).await.is_err() {
+                    return;
+                }
+            }
+        }
+    }
+
+    fn dispatch(&mut self, op: StructureTag) -> Vec<Tag> {
+        let id = op.id;
+        let dit = self.dit.clone();
+        let mut dit = dit.lock().expect("dit mutex");
+        if id == 10 {
+            return vec![delete(&mut dit, &string(Some(op)))];
+        }
+        let elems = children(op);
+        match id {
+            0 => vec![self.bind(&dit, elems)],
+            3 => search(&dit, elems),
+            6 => vec![modify(&mut dit, elems)],
+            8 => vec![add(&mut dit, elems)],
+            12 => vec![modifydn(&mut dit, elems)],
+            14 => vec![compare(&dit, elems)],
+            23 => vec![self.extended(elems)],
+            _ => vec![response(response_id(id), 2, "unsupported operation")],
+        }
+    }
+
+    // Perform an update on a copy of the tree, reporting success with x-no-operation.
+    fn dispatch_no_op(&mut self, id: u64, op: StructureTag) -> Vec<Tag> {
+        let dit = self.dit.clone();
+        self.dit = Arc::new(Mutex::new(dit.lock().expect("dit mutex").clone()));
+        let responses = self.dispatch(op);
+        self.dit = dit;
+        responses
+            .into_iter()
+            .map(|resp| match resp {
+                Tag::Sequence(ref seq)
+                    if matches!(seq.inner.first(), Some(Tag::Enumerated(rc)) if rc.inner == 0) =>
+                {
+                    response(response_id(id), NO_OP_RC, "")
+                }
+                resp => resp,
+            })
+            .collect()
+    }
+
+    fn bind(&mut self, dit: &Dit, elems: Vec<StructureTag>) -> Tag {
+        let mut elems = elems.into_iter().skip(1);
+        let dn = string(elems.next());
+        let auth = match elems.next() {
+            Some(auth) if auth.class == TagClass::Context && auth.id == 0 => auth,
+            _ => return response(1, 7, "only simple bind is supported"),
+        };
+        let pw = octets(Some(auth));
+        let rc = if dn.is_empty() && pw.is_empty() {
+            0
+        } else if pw.is_empty() {
+            return response(1, 53, "unauthenticated bind not allowed");
+        } else {
+            match dit.get(&normalize_dn(&dn)) {
+                Some(entry) if entry.values("userPassword").contains(&pw) => 0,
+                _ => 49,
+            }
+        };
+        self.bound = if rc == 0 { dn } else { String::new() };
+        response(1, rc, "")
+    }
+
+    fn extended(&self, elems: Vec<StructureTag>) -> Tag {
+        let name = string(elems.into_iter().find(|t| t.id == 0));
+        if name != WHOAMI_OID {
+            return response(24, 2, "unsupported extended operation");
+        }
+        let authzid = if self.bound.is_empty() {
+            String::new()
+        } else {
+            format!("dn:{}", self.bound)
+        };
+        let mut resp = result_elems(0, "");
+        resp.push(Tag::OctetString(OctetString {
+            id: 11,
+            class: TagClass::Context,
+            inner: authzid.into_bytes(),
+        }));
+        Tag::Sequence(Sequence {
+            id: 24,
+            class: TagClass::Application,
+            inner: resp,
+        })
+    }
+}
+
+fn search(dit: &Dit, elems: Vec<StructureTag>) -> Vec<Tag> {
+    let mut elems = elems.into_iter();
+    let base = normalize_dn(&string(elems.next()));
+    let scope = uint(elems.next());
+    let _deref = elems.next();
+    let sizelimit = uint(elems.next()) as usize;
+    let _timelimit = elems.next();
+    let types_only = octets(elems.next()).first().map(|b| *b != 0) == Some(true);
+    let filter = match elems.next() {
+        Some(filter) => filter,
+        None => return vec![response(5, 2, "missing filter")],
+    };
+    let attrs = children_of(elems.next())
+        .into_iter()
+        .map(|t| string(Some(t)))
+        .collect::<Vec<_>>();
+    if base.is_empty() && scope == 0 {
+        let root_dse = root_dse(dit);
+        let mut resps = vec![];
+        if matches(&filter, &root_dse) {
+            resps.push(search_entry(&root_dse, &attrs, types_only));
+        }
+        resps.push(response(5, 0, ""));
+        return resps;
+    }
+    if !base.is_empty() && !dit.contains_key(&base) {
+        return vec![response(5, 32, "")];
+    }
+    let mut resps = vec![];
+    for (norm, entry) in dit {
+        let in_scope = match scope {
+            0 => *norm == base,
+            1 => parent_dn(norm) == base,
+            _ => is_in_subtree(norm, &base),
+        };
+        if !in_scope || !matches(&filter, entry) {
+            continue;
+        }
+        if sizelimit != 0 && resps.len() == sizelimit {
+            resps.push(response(5, 4, ""));
+            return resps;
+        }
+        resps.push(search_entry(entry, &attrs, types_only));
+    }
+    resps.push(response(5, 0, ""));
+    resps
+}
+
+fn root_dse(dit: &Dit) -> MockEntry {
+    let contexts = dit
+        .iter()
+        .filter(|(norm, _)| !dit.contains_key(parent_dn(norm)))
+        .map(|(_, entry)| entry.dn.clone().into_bytes())
+        .collect();
+    MockEntry {
+        dn: String::new(),
+        attrs: vec![
+            ("objectClass".to_owned(), vec![b"top".to_vec()]),
+            ("supportedLDAPVersion".to_owned(), vec![b"3".to_vec()]),
+            (
+                "supportedExtension".to_owned(),
+                vec![WHOAMI_OID.as_bytes().to_vec()],
+            ),
+            ("namingContexts".to_owned(), contexts),
+        ],
+    }
+}
+
+fn search_entry(entry: &MockEntry, attrs: &[String], types_only: bool) -> Tag {
+    let all = attrs.is_empty() || attrs.iter().any(|a| a == "*");
+    let selected = entry
+        .attrs
+        .iter()
+        .filter(|(name, _)| all || attrs.iter().any(|a| a.eq_ignore_ascii_case(name)))
+        .map(|(name, vals)| {
+            Tag::Sequence(Sequence {
+                inner: vec![
+                    octet_string(name.as_bytes()),
+                    Tag::Set(Set {
+                        inner: if types_only {
+                            vec![]
+                        } else {
+                            vals.iter().map(|v| octet_string(v)).collect()
+                        },
+                        ..Default::default()
+                    }),
+                ],
+                ..Default::default()
+            })
+        })
+        .collect();
+    Tag::Sequence(Sequence {
+        id: 4,
+        class: TagClass::Application,
+        inner: vec![
+            octet_string(entry.dn.as_bytes()),
+            Tag::Sequence(Sequence {
+                inner: selected,
+                ..Default::default()
+            }),
+        ],
+    })
+}
+
+fn matches(filter: &StructureTag, entry: &MockEntry) -> bool {
+    let items = match filter.payload {
+        PL::C(ref items) => items.as_slice(),
+        PL::P(ref attr) => {
+            // Only the presence filter is primitive
+            let attr = String::from_utf8_lossy(attr);
+            return filter.id == 7
+                && (attr.eq_ignore_ascii_case("objectClass") || !entry.values(&attr).is_empty());
+        }
+    };
+    match filter.id {
+        0 => items.iter().all(|f| matches(f, entry)),
+        1 => items.iter().any(|f| matches(f, entry)),
+        2 => !items.iter().any(|f| matches(f, entry)),
+        3 | 5 | 6 | 8 => {
+            let (attr, val) = match items {
+                [attr, val] => (primitive(attr), primitive(val)),
+                _ => return false,
+            };
+            let val = val.to_ascii_lowercase();
+            entry
+                .values(&String::from_utf8_lossy(attr))
+                .iter()
+                .map(|v| v.to_ascii_lowercase())
+                .any(|v| match filter.id {
+                    5 => v >= val,
+                    6 => v <= val,
+                    _ => v == val,
+                })
+        }
+        4 => {
+            let (attr, subs) = match items {
+                [attr, subs] => (primitive(attr), subs),
+                _ => return false,
+            };
+            let subs = match subs.payload {
+                PL::C(ref subs) => subs,
+                PL::P(_) => return false,
+            };
+            entry
+                .values(&String::from_utf8_lossy(attr))
+                .iter()
+                .any(|v| substrings_match(&v.to_ascii_lowercase(), subs))
+        }
+        _ => false,
+    }
+}
+
+fn substrings_match(val: &[u8], subs: &[StructureTag]) -> bool {
+    let mut rest = val;
+    for sub in subs {
+        let pat = primitive(sub).to_ascii_lowercase();
+        if pat.is_empty() {
+            continue;
+        }
+        match sub.id {
+            0 => {
+                if !rest.starts_with(&pat) {
+                    return false;
+                }
+                rest = &rest[pat.len()..];
+            }
+            1 => match rest.windows(pat.len()).position(|w| w == pat.as_slice()) {
+                Some(pos) => rest = &rest[pos + pat.len()..],
+                None => return false,
+            },
+            _ => return rest.ends_with(&pat),
+        }
+    }
+    true
+}
+
+fn add(dit: &mut Dit, elems: Vec<StructureTag>) -> Tag {
+    let mut elems = elems.into_iter();
+    let dn = string(elems.next());
+    let norm = normalize_dn(&dn);
+    if dit.contains_key(&norm) {
+        return response(9, 68, "");
+    }
+    let attrs = children_of(elems.next())
+        .into_iter()
+        .map(|attr| {
+            let mut attr = children(attr).into_iter();
+            let name = string(attr.next());
+            let vals = children_of(attr.next())
+                .into_iter()
+                .map(|v| octets(Some(v)))
+                .collect();
+            (name, vals)
+        })
+        .collect();
+    dit.insert(norm, MockEntry { dn, attrs });
+    response(9, 0, "")
+}
+
+fn modify(dit: &mut Dit, elems: Vec<StructureTag>) -> Tag {
+    let mut elems = elems.into_iter();
+    let norm = normalize_dn(&string(elems.next()));
+    let mut entry = match dit.get(&norm) {
+        Some(entry) => entry.clone(),
+        None => return response(7, 32, ""),
+    };
+    for change in children_of(elems.next()) {
+        let mut change = children(change).into_iter();
+        let op = uint(change.next());
+        let mut attr = children_of(change.next()).into_iter();
+        let name = string(attr.next());
+        let vals = children_of(attr.next())
+            .into_iter()
+            .map(|v| octets(Some(v)))
+            .collect::<Vec<_>>();
+        let existing = entry.values_mut(&name);
+        match op {
+            0 => {
+                for val in vals {
+                    if existing.iter().any(|v| v.eq_ignore_ascii_case(&val)) {
+                        return response(7, 20, "");
+                    }
+                    existing.push(val);
+                }
+            }
+            1 if vals.is_empty() => {
+                if existing.is_empty() {
+                    return response(7, 16, "");
+                }
+                existing.clear();
+            }
+            1 => {
+                for val in vals {
+                    match existing.iter().position(|v| v.eq_ignore_ascii_case(&val)) {
+                        Some(pos) => {
+                            existing.remove(pos);
+                        }
+                        None => return response(7, 16, ""),
+                    }
+                }
+            }
+            2 => *existing = vals,
+            3 => {
+                let incr = vals.first().and_then(|v| parse_int(v));
+                let (incr, current) = match (incr, existing.first().and_then(|v| parse_int(v))) {
+                    (Some(incr), Some(current)) => (incr, current),
+                    _ => return response(7, 21, ""),
+                };
+                *existing = vec![(current + incr).to_string().into_bytes()];
+            }
+            _ => return response(7, 2, "unknown modify operation"),
+        }
+    }
+    entry.prune();
+    dit.insert(norm, entry);
+    response(7, 0, "")
+}
+
+fn delete(dit: &mut Dit, dn: &str) -> Tag {
+    let norm = normalize_dn(dn);
+    if !dit.contains_key(&norm) {
+        return response(11, 32, "");
+    }
+    if dit.keys().any(|k| parent_dn(k) == norm) {
+        return response(11, 66, "");
+    }
+    dit.remove(&norm);
+    response(11, 0, "")
+}
+
+fn modifydn(dit: &mut Dit, elems: Vec<StructureTag>) -> Tag {
+    let mut elems = elems.into_iter();
+    let norm = normalize_dn(&string(elems.next()));
+    let new_rdn = string(elems.next());
+    let delete_old = octets(elems.next()).first().map(|b| *b != 0) == Some(true);
+    let entry = match dit.get(&norm) {
+        Some(entry) => entry.clone(),
+        None => return response(13, 32, ""),
+    };
+    let new_sup = match elems.next() {
+        Some(sup) => string(Some(sup)),
+        None => split_rdns(&entry.dn)[1..].join(","),
+    };
+    if !new_sup.is_empty() && !dit.contains_key(&normalize_dn(&new_sup)) {
+        return response(13, 32, "");
+    }
+    let new_dn = if new_sup.is_empty() {
+        new_rdn.clone()
+    } else {
+        format!("{},{}", new_rdn, new_sup)
+    };
+    let new_norm = normalize_dn(&new_dn);
+    if dit.contains_key(&new_norm) {
+        return response(13, 68, "");
+    }
+    let moved = dit
+        .keys()
+        .filter(|k| is_in_subtree(k, &norm))
+        .cloned()
+        .collect::<Vec<_>>();
+    let depth = split_rdns(&entry.dn).len();
+    for key in moved {
+        let mut child = dit.remove(&key).expect("subtree entry");
+        let rdns = split_rdns(&child.dn);
+        let prefix = &rdns[..rdns.len() - depth];
+        child.dn = prefix
+            .iter()
+            .map(|r| r.to_string())
+            .chain(std::iter::once(new_dn.clone()))
+            .collect::<Vec<_>>()
+            .join(",");
+        if key == norm {
+            if delete_old {
+                if let Some((attr, val)) = rdn_ava(split_rdns(&entry.dn)[0]) {
+                    child
+                        .values_mut(attr)
+                        .retain(|v| !v.eq_ignore_ascii_case(val.as_bytes()));
+                }
+            }
+            if let Some((attr, val)) = rdn_ava(&new_rdn) {
+                let vals = child.values_mut(attr);
+                if !vals.iter().any(|v| v.eq_ignore_ascii_case(val.as_bytes())) {
+                    vals.push(val.as_bytes().to_vec());
+                }
+            }
+            child.prune();
+        }
+        dit.insert(normalize_dn(&child.dn), child);
+    }
+    response(13, 0, "")
+}
+
+fn compare(dit: &Dit, elems: Vec<StructureTag>) -> Tag {
+    let mut elems = elems.into_iter();
+    let norm = normalize_dn(&string(elems.next()));
+    let mut ava = children_of(elems.next()).into_iter();
+    let attr = string(ava.next());
+    let val = octets(ava.next());
+    let entry = match dit.get(&norm) {
+        Some(entry) => entry,
+        None => return response(15, 32, ""),
+    };
+    let vals = entry.values(&attr);
+    if vals.is_empty() {
+        response(15, 16, "")
+    } else if vals.iter().any(|v| v.eq_ignore_ascii_case(&val)) {
+        response(15, 6, "")
+    } else {
+        response(15, 5, "")
+    }
+}
+
+fn has_critical(controls: &[Control]) -> bool {
+    controls
+        .iter()
+        .any(|c| c.1.crit && c.1.ctype != AUTHZ_ID_REQUEST_OID && c.1.ctype != NO_OP_OID)
+}
+
+fn has_control(controls: &[Control], oid: &str) -> bool {
+    controls.iter().any(|c| c.1.ctype == oid)
+}
+
+fn response_id(req_id: u64) -> u64 {
+    match req_id {
+        3 => 5,
+        _ => req_id + 1,
+    }
+}
+
+fn result_elems(rc: u32, text: &str) -> Vec<Tag> {
+    vec![
+        Tag::Enumerated(Enumerated {
+            inner: rc as i64,
+            ..Default::default()
+        }),
+        octet_string(b""),
+        octet_string(text.as_bytes()),
+    ]
+}
+
+pub(crate) fn response(id: u64, rc: u32, text: &str) -> Tag {
+    Tag::Sequence(Sequence {
+        id,
+        class: TagClass::Application,
+        inner: result_elems(rc, text),
+    })
+}
+
+fn octet_string(val: &[u8]) -> Tag {
+    Tag::OctetString(OctetString {
+        inner: val.to_vec(),
+        ..Default::default()
+    })
+}
+
+fn primitive(t: &StructureTag) -> &[u8] {
+    match t.payload {
+        PL::P(ref val) => val,
+        PL::C(_) => &[],
+    }
+}
+
+fn children(t: StructureTag) -> Vec<StructureTag> {
+    t.expect_constructed().unwrap_or_default()
+}
+
+fn children_of(t: Option<StructureTag>) -> Vec<StructureTag> {
+    t.map(children).unwrap_or_default()
+}
+
+fn octets(t: Option<StructureTag>) -> Vec<u8> {
+    t.and_then(|t| t.expect_primitive()).unwrap_or_default()
+}
+
+fn string(t: Option<StructureTag>) -> String {
+    String::from_utf8_lossy(&octets(t)).into_owned()
+}
+
+fn uint(t: Option<StructureTag>) -> u64 {
+    match parse_uint(&octets(t)) {
+        IResult::Done(_, val) => val,
+        _ => 0,
+    }
+}
+
+fn parse_int(val: &[u8]) -> Option<i64> {
+    std::str::from_utf8(val).ok()?.trim().parse().ok()
+}
+
+fn parent_dn(norm: &str) -> &str {
+    match split_rdns(norm).first() {
+        Some(rdn) if rdn.len() < norm.len() => &norm[rdn.len() + 1..],
+        _ => "",
+    }
+}
+
+fn is_in_subtree(norm: &str, base: &str) -> bool {
+    base.is_empty()
+        || norm == base
+        || (norm.len() > base.len()
+            && norm.ends_with(base)
+            && norm.as_bytes()[norm.len() - base.len() - 1] == b',')
+}
+
+#[cfg(test)]
+mod test {
+    use super::{normalize_dn, parent_dn, MockServer};
+    use crate::{Mod, Scope, SearchEntry};
+
+    use maplit::hashset;
+
+    #[test]
+    fn dn_normalization() {
+        assert_eq!(
+            normalize_dn("CN=Test User , OU=People,dc=Example"),
+            "cn=test user,ou=people,dc=example"
+        );
+        assert_eq!(normalize_dn("cn=a\\,b,dc=org"), "cn=a\\,b,dc=org");
+        assert_eq!(parent_dn("cn=a\\,b,dc=org"), "dc=org");
+        assert_eq!(parent_dn("dc=org"), "");
+    }
+
+    #[tokio::test]
+    async fn modify_rename_delete() {
+        let server = MockServer::new();
+        server.add_entry("dc=example,dc=org", vec![("dc", vec!["example"])]);
+        server.add_entry("ou=People,dc=example,dc=org", vec![("ou", vec!["People"])]);
+        let (conn, mut ldap) = server.connect();
+        crate::drive!(conn);
+        ldap.add(
+            "uid=jdoe,ou=People,dc=example,dc=org",
+            vec![("uid", hashset! {"jdoe"}), ("cn", hashset! {"John Doe"})],
+        )
+        .await
+        .unwrap()
+        .success()
+        .unwrap();
+        let res = ldap.delete("ou=People,dc=example,dc=org").await.unwrap();
+        assert_eq!(res.rc, 66);
+        ldap.modify(
+            "uid=jdoe,ou=people,dc=example,dc=org",
+            vec![Mod::Add("mail", hashset! {"jdoe@example.org"})],
+        )
+        .await
+        .unwrap()
+        .success()
+        .unwrap();
+        assert!(ldap
+            .compare(
+                "uid=jdoe,ou=People,dc=example,dc=org",
+                "mail",
+                "JDOE@example.org"
+            )
+            .await
+            .unwrap()
+            .equal()
+            .unwrap());
+        assert!(!ldap
+            .compare_bool("uid=jdoe,ou=People,dc=example,dc=org", "cn", "Jane Doe")
+            .await
+            .unwrap());
+        ldap.modifydn("ou=People,dc=example,dc=org", "ou=Staff", true, None)
+            .await
+            .unwrap()
+            .success()
+            .unwrap();
+        let entry = server.entry("uid=jdoe,ou=Staff,dc=example,dc=org").unwrap();
+        assert_eq!(entry.dn, "uid=jdoe,ou=Staff,dc=example,dc=org");
+        assert_eq!(
+            server.entry("ou=Staff,dc=example,dc=org").unwrap().attrs["ou"],
+            vec!["Staff"]
+        );
+        let (rs, _res) = ldap
+            .search(
+                "dc=example,dc=org",
+                Scope::OneLevel,
+                "(|(ou=st*f)(uid=*))",
+                vec!["ou"],
+            )
+            .await
+            .unwrap()
+            .success()
+            .unwrap();
+        assert_eq!(rs.len(), 1);
+        assert_eq!(
+            SearchEntry::construct(rs[0].clone()).dn,
+            "ou=Staff,dc=example,dc=org"
+        );
+        ldap.delete("uid=jdoe,ou=Staff,dc=example,dc=org")
+            .await
+            .unwrap()
+            .success()
+            .unwrap();
+        assert_eq!(server.len(), 2);
+        ldap.unbind().await.unwrap();
+    }
+
+    #[cfg(feature = "synthetic")]
+    #[tokio::test]
+    async fn synthetic_entries() {
+        let server = MockServer::new();
+        server.add_entry("ou=Bench,dc=example,dc=org", vec![("ou", vec!["Bench"])]);
+        server.add_synthetic_entries("ou=Bench,dc=example,dc=org", 50, 3, 8);
+        assert_eq!(server.len(), 51);
+        let (conn, mut ldap) = server.connect();
+        crate::drive!(conn);
+        let (rs, _res) = ldap
+            .search(
+                "ou=Bench,dc=example,dc=org",
+                Scope::OneLevel,
+                "(objectClass=account)",
+                vec!["*"],
+            )
+            .await
+            .unwrap()
+            .success()
+            .unwrap();
+        assert_eq!(rs.len(), 50);
+        let entry = server
+            .entry("uid=entry7,ou=Bench,dc=example,dc=org")
+            .unwrap();
+        assert_eq!(entry.attrs["uid"], vec!["entry7"]);
+        assert_eq!(entry.attrs.len(), 5);
+        // Values are deterministic, and differ between entries and attributes.
+        assert_eq!(entry.attrs["attr0"], vec!["hijklmno"]);
+        assert_eq!(entry.attrs["attr2"], vec!["jklmnopq"]);
+        let other = server
+            .entry("uid=entry8,ou=Bench,dc=example,dc=org")
+            .unwrap();
+        assert_ne!(other.attrs["attr0"], entry.attrs["attr0"]);
+    }
+}
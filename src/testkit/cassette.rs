@@ -0,0 +1,400 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use super::{response, response_id};
+use crate::conn::{AsyncStream, LdapConnAsync, LdapConnSettings};
+use crate::ldap::Ldap;
+use crate::RequestId;
+
+use bytes::{Buf, BytesMut};
+use lber::parse::{parse_tag, parse_uint};
+use lber::structure::{StructureTag, PL};
+use lber::structures::{ASNTag, Integer, Sequence, Tag};
+use lber::write;
+use lber::IResult;
+
+use futures_util::sink::SinkExt;
+use tokio_stream::StreamExt;
+use tokio_util::codec::{Decoder, Encoder, Framed};
+
+/// Codec for whole LDAPMessages, without any interpretation.
+struct RawCodec;
+
+impl Decoder for RawCodec {
+    type Item = StructureTag;
+    type Error = io::Error;
+
+    fn decode(&mut self, buf: &mut BytesMut) -> io::Result<Option<StructureTag>> {
+        let (amt, tag) = match parse_tag(&buf[..]) {
+            IResult::Done(rest, tag) => (buf.len() - rest.len(), tag),
+            IResult::Incomplete(_) => return Ok(None),
+            IResult::Error(_) => return Err(io::Error::other("decoding error")),
+        };
+        buf.advance(amt);
+        Ok(Some(tag))
+    }
+}
+
+impl Encoder<StructureTag> for RawCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, tag: StructureTag, into: &mut BytesMut) -> io::Result<()> {
+        write::encode_into(into, tag)
+    }
+}
+
+fn msgid(msg: &StructureTag) -> Option<RequestId> {
+    match msg.payload {
+        PL::C(ref elems) => match elems.first().map(|e| &e.payload) {
+            Some(PL::P(ref id)) => match parse_uint(id) {
+                IResult::Done(_, id) => Some(id as RequestId),
+                _ => None,
+            },
+            _ => None,
+        },
+        PL::P(_) => None,
+    }
+}
+
+fn with_msgid(mut msg: StructureTag, id: RequestId) -> StructureTag {
+    if let PL::C(ref mut elems) = msg.payload {
+        if let Some(first) = elems.first_mut() {
+            *first = Tag::Integer(Integer {
+                inner: id as i64,
+                ..Default::default()
+            })
+            .into_structure();
+        }
+    }
+    msg
+}
+
+fn protoop_id(msg: &StructureTag) -> Option<u64> {
+    match msg.payload {
+        PL::C(ref elems) => elems.get(1).map(|op| op.id),
+        PL::P(_) => None,
+    }
+}
+
+fn encode(msg: &StructureTag) -> Vec<u8> {
+    let mut buf = BytesMut::new();
+    write::encode_into(&mut buf, msg.clone()).expect("encoded message");
+    buf.to_vec()
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Interaction {
+    request: StructureTag,
+    responses: Vec<StructureTag>,
+}
+
+/// Recorded LDAP conversation, for replaying in tests.
+///
+/// A cassette is a list of interactions, each consisting of a request and the responses
+/// it produced. It's created by recording a session against a real server with
+/// [`record()`](#method.record), and can be saved to and loaded from a file.
+///
+/// When a session is replayed with [`replay()`](#method.replay), each request sent by
+/// the client is matched to the first unused recorded interaction with the same request,
+/// and the recorded responses are sent back. Requests are normalized by ignoring the message
+/// ID, which is substituted in the responses; apart from that, they must be identical to
+/// the recorded ones, including the controls. Consequently, a replayed session can have
+/// the requests in a different order, as long as there are no duplicate requests with
+/// different responses. An unmatched request receives a response with the result code 80
+/// (`other`).
+///
+/// The file format is line-oriented. Every line contains a hex-encoded LDAPMessage with
+/// the message ID set to zero, preceded by `>` for requests and `<` for responses.
+///
+/// ## Example
+///
+/// ```rust,no_run
+/// use ldap3::testkit::Cassette;
+/// use ldap3::LdapConnSettings;
+/// # use ldap3::result::Result;
+/// use tokio::net::TcpStream;
+///
+/// # #[tokio::main]
+/// # async fn main() -> Result<()> {
+/// let stream = TcpStream::connect("localhost:2389").await?;
+/// let (conn, mut ldap, recorder) = Cassette::record(stream, LdapConnSettings::new());
+/// ldap3::drive!(conn);
+/// ldap.simple_bind("cn=Manager,dc=example,dc=org", "secret").await?;
+/// ldap.unbind().await?;
+/// recorder.cassette().save("tests/bind.cassette")?;
+///
+/// // Later, without the server:
+/// let (conn, mut ldap) = Cassette::load("tests/bind.cassette")?.replay();
+/// ldap3::drive!(conn);
+/// ldap.simple_bind("cn=Manager,dc=example,dc=org", "secret").await?.success()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Cassette {
+    interactions: Vec<Interaction>,
+}
+
+impl Cassette {
+    /// Open a recorded connection over `stream`, which must be connected to a real
+    /// LDAP server. The settings are used for the client side, as in
+    /// [`LdapConnAsync::from_stream()`](../struct.LdapConnAsync.html#method.from_stream).
+    ///
+    /// The returned [`Recorder`](struct.Recorder.html) provides the cassette with
+    /// the interactions recorded so far.
+    pub fn record<S: AsyncStream + 'static>(
+        stream: S,
+        settings: LdapConnSettings,
+    ) -> (LdapConnAsync, Ldap, Recorder) {
+        let (client, proxy) = tokio::io::duplex(super::DUPLEX_BUFFER);
+        let recorder = Recorder {
+            interactions: Arc::new(Mutex::new(vec![])),
+        };
+        tokio::spawn(record_session(
+            RawCodec.framed(proxy),
+            RawCodec.framed(stream),
+            recorder.interactions.clone(),
+        ));
+        let (conn, ldap) = LdapConnAsync::from_stream(client, settings);
+        (conn, ldap, recorder)
+    }
+
+    /// Open a connection which replays the recorded interactions.
+    pub fn replay(&self) -> (LdapConnAsync, Ldap) {
+        self.replay_with_settings(LdapConnSettings::new())
+    }
+
+    /// Open a connection which replays the recorded interactions, using `settings`
+    /// for the client side.
+    pub fn replay_with_settings(&self, settings: LdapConnSettings) -> (LdapConnAsync, Ldap) {
+        let (client, server) = tokio::io::duplex(super::DUPLEX_BUFFER);
+        tokio::spawn(replay_session(
+            RawCodec.framed(server),
+            self.interactions.clone(),
+        ));
+        LdapConnAsync::from_stream(client, settings)
+    }
+
+    /// Write the cassette to `sink`.
+    pub fn write_to<W: Write>(&self, mut sink: W) -> io::Result<()> {
+        for interaction in &self.interactions {
+            writeln!(sink, "> {}", hex(&encode(&interaction.request)))?;
+            for resp in &interaction.responses {
+                writeln!(sink, "< {}", hex(&encode(resp)))?;
+            }
+        }
+        sink.flush()
+    }
+
+    /// Read a cassette from `source`.
+    pub fn read_from<R: BufRead>(source: R) -> io::Result<Cassette> {
+        let mut interactions: Vec<Interaction> = vec![];
+        for line in source.lines() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let invalid = || io::Error::new(io::ErrorKind::InvalidData, "invalid cassette line");
+            if !line.is_char_boundary(1) {
+                return Err(invalid());
+            }
+            let (dir, data) = line.split_at(1);
+            let bytes = unhex(data.trim()).ok_or_else(invalid)?;
+            let msg = match parse_tag(&bytes) {
+                IResult::Done(&[], msg) => msg,
+                _ => return Err(invalid()),
+            };
+            match dir {
+                ">" => interactions.push(Interaction {
+                    request: msg,
+                    responses: vec![],
+                }),
+                "<" => interactions
+                    .last_mut()
+                    .ok_or_else(invalid)?
+                    .responses
+                    .push(msg),
+                _ => return Err(invalid()),
+            }
+        }
+        Ok(Cassette { interactions })
+    }
+
+    /// Save the cassette to the file at `path`.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        self.write_to(io::BufWriter::new(File::create(path)?))
+    }
+
+    /// Load a cassette from the file at `path`.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Cassette> {
+        Cassette::read_from(BufReader::new(File::open(path)?))
+    }
+}
+
+/// Access to the cassette of a recorded connection.
+#[derive(Clone, Debug)]
+pub struct Recorder {
+    interactions: Arc<Mutex<Vec<Interaction>>>,
+}
+
+impl Recorder {
+    /// Return the cassette with the interactions recorded so far.
+    pub fn cassette(&self) -> Cassette {
+        Cassette {
+            interactions: self
+                .interactions
+                .lock()
+                .expect("interactions mutex")
+                .clone(),
+        }
+    }
+}
+
+async fn record_session<S: AsyncStream>(
+    mut client: Framed<tokio::io::DuplexStream, RawCodec>,
+    mut server: Framed<S, RawCodec>,
+    interactions: Arc<Mutex<Vec<Interaction>>>,
+) {
+    let mut pending = HashMap::new();
+    loop {
+        tokio::select! {
+            req = client.next() => {
+                let req = match req {
+                    Some(Ok(req)) => req,
+                    _ => break,
+                };
+                if let Some(id) = msgid(&req) {
+                    let mut interactions = interactions.lock().expect("interactions mutex");
+                    pending.insert(id, interactions.len());
+                    interactions.push(Interaction {
+                        request: with_msgid(req.clone(), 0),
+                        responses: vec![],
+                    });
+                }
+                if server.send(req).await.is_err() {
+                    break;
+                }
+            },
+            resp = server.next() => {
+                let resp = match resp {
+                    Some(Ok(resp)) => resp,
+                    _ => break,
+                };
+                if let Some(&idx) = msgid(&resp).and_then(|id| pending.get(&id)) {
+                    interactions.lock().expect("interactions mutex")[idx]
+                        .responses
+                        .push(with_msgid(resp.clone(), 0));
+                }
+                if client.send(resp).await.is_err() {
+                    break;
+                }
+            },
+        }
+    }
+}
+
+async fn replay_session(
+    mut client: Framed<tokio::io::DuplexStream, RawCodec>,
+    interactions: Vec<Interaction>,
+) {
+    let mut used = vec![false; interactions.len()];
+    while let Some(Ok(req)) = client.next().await {
+        let id = match msgid(&req) {
+            Some(id) => id,
+            None => break,
+        };
+        let normalized = with_msgid(req.clone(), 0);
+        let found = interactions
+            .iter()
+            .enumerate()
+            .position(|(i, int)| !used[i] && int.request == normalized);
+        let responses = match found {
+            Some(i) => {
+                used[i] = true;
+                interactions[i].responses.clone()
+            }
+            None => match protoop_id(&req) {
+                Some(2) | Some(16) | None => vec![],
+                Some(op) => vec![Tag::Sequence(Sequence {
+                    inner: vec![
+                        Tag::Integer(Integer::default()),
+                        response(response_id(op), 80, "no recorded interaction"),
+                    ],
+                    ..Default::default()
+                })
+                .into_structure()],
+            },
+        };
+        for resp in responses {
+            if client.send(with_msgid(resp, id)).await.is_err() {
+                return;
+            }
+        }
+        if protoop_id(&req) == Some(2) {
+            break;
+        }
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn unhex(s: &str) -> Option<Vec<u8>> {
+    if s.len() % 2 != 0 {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::Cassette;
+    use crate::testkit::MockServer;
+    use crate::LdapConnSettings;
+
+    #[tokio::test]
+    async fn record_and_replay() {
+        let server = MockServer::new();
+        server.add_entry(
+            "uid=test,dc=example,dc=org",
+            vec![("uid", vec!["test"]), ("userPassword", vec!["secret"])],
+        );
+        let (stream, server_side) = tokio::io::duplex(1024);
+        tokio::spawn(server.session().serve(server_side));
+        let (conn, mut ldap, recorder) = Cassette::record(stream, LdapConnSettings::new());
+        crate::drive!(conn);
+        ldap.simple_bind("uid=test,dc=example,dc=org", "secret")
+            .await
+            .unwrap()
+            .success()
+            .unwrap();
+        let res = ldap.delete("uid=test,dc=example,dc=org").await.unwrap();
+        assert_eq!(res.rc, 0);
+        ldap.unbind().await.unwrap();
+        let mut saved = vec![];
+        recorder.cassette().write_to(&mut saved).unwrap();
+        let cassette = Cassette::read_from(&saved[..]).unwrap();
+        assert_eq!(cassette, recorder.cassette());
+
+        let (conn, mut ldap) = cassette.replay();
+        crate::drive!(conn);
+        // Replay in a different order, with different message IDs
+        let res = ldap.delete("uid=test,dc=example,dc=org").await.unwrap();
+        assert_eq!(res.rc, 0);
+        let res = ldap.delete("uid=test,dc=example,dc=org").await.unwrap();
+        assert_eq!(res.rc, 80);
+        let res = ldap
+            .simple_bind("uid=test,dc=example,dc=org", "secret")
+            .await
+            .unwrap();
+        assert_eq!(res.rc, 0);
+    }
+}
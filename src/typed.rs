@@ -0,0 +1,337 @@
+//! Bind-state tracking for the `Ldap` handle.
+//!
+//! A common error in LDAP client code is issuing operations before the connection has
+//! been authenticated. Most servers will happily execute them as anonymous, and the
+//! results will silently differ from expectations. The wrapper in this module,
+//! [`TypedLdap`](struct.TypedLdap.html), carries the bind state in its type parameter,
+//! so that directory operations are available only on a handle which has performed
+//! a successful Bind.
+//!
+//! A freshly wrapped handle is in the [`Unbound`](struct.Unbound.html) state, and can do
+//! little besides binding. A successful Bind consumes the handle and returns a new one in
+//! the [`Bound`](struct.Bound.html) state, which provides the directory operations of the
+//! underlying [`Ldap`](../struct.Ldap.html). The calls which change the bind state, Bind
+//! and Unbind, always consume the handle, so that a handle can't outlive the state it
+//! claims: a failed Bind leaves the connection in an unknown state and doesn't return
+//! the handle, and nothing can be done on a handle after Unbind.
+//!
+//! The typed wrapper is optional, and the regular, untyped handle is available in either state
+//! by calling [`into_inner()`](struct.TypedLdap.html#method.into_inner).
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! use ldap3::typed::TypedLdap;
+//! use ldap3::result::Result;
+//! use ldap3::{LdapConnAsync, Scope};
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<()> {
+//!     let (conn, ldap) = LdapConnAsync::new("ldap://localhost:2389").await?;
+//!     ldap3::drive!(conn);
+//!     let ldap = TypedLdap::new(ldap);
+//!     // Searching here wouldn't compile
+//!     let (mut ldap, _res) = ldap
+//!         .simple_bind("cn=Manager,dc=example,dc=org", "secret")
+//!         .await?;
+//!     let (_rs, _res) = ldap
+//!         .search("dc=example,dc=org", Scope::Base, "(objectClass=*)", vec!["*"])
+//!         .await?
+//!         .success()?;
+//!     ldap.unbind().await
+//! }
+//! ```
+
+use std::collections::HashSet;
+use std::hash::Hash;
+use std::marker::PhantomData;
+use std::time::Duration;
+
+use crate::adapters::IntoAdapterVec;
+use crate::controls_impl::IntoRawControlVec;
+use crate::exop::Exop;
+use crate::ldap::{Ldap, Mod};
+use crate::result::{CompareResult, ExopResult, LdapResult, Result, SearchResult};
+use crate::search::{Scope, SearchOptions, SearchStream};
+use crate::RequestId;
+
+/// Marker for a handle which hasn't been successfully bound.
+#[derive(Clone, Copy, Debug)]
+pub struct Unbound;
+
+/// Marker for a handle which has been successfully bound.
+#[derive(Clone, Copy, Debug)]
+pub struct Bound;
+
+/// `Ldap` handle with the bind state tracked at compile time. __*__
+///
+/// See the [module-level documentation](index.html) for the overview. A handle can't
+/// be used after Unbind:
+///
+/// ```rust,compile_fail
+/// # use ldap3::typed::{Bound, TypedLdap};
+/// # async fn f(mut ldap: TypedLdap<Bound>) {
+/// ldap.unbind().await.unwrap();
+/// ldap.delete("cn=gone,dc=example,dc=org").await.unwrap();
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct TypedLdap<S> {
+    ldap: Ldap,
+    _state: PhantomData<S>,
+}
+
+impl<S> TypedLdap<S> {
+    /// Return the untyped handle.
+    pub fn into_inner(self) -> Ldap {
+        self.ldap
+    }
+
+    /// See [`Ldap::with_controls()`](../struct.Ldap.html#method.with_controls).
+    pub fn with_controls<V: IntoRawControlVec>(&mut self, ctrls: V) -> &mut Self {
+        self.ldap.with_controls(ctrls);
+        self
+    }
+
+    /// See [`Ldap::with_timeout()`](../struct.Ldap.html#method.with_timeout).
+    pub fn with_timeout(&mut self, duration: Duration) -> &mut Self {
+        self.ldap.with_timeout(duration);
+        self
+    }
+
+    /// See [`Ldap::is_closed()`](../struct.Ldap.html#method.is_closed).
+    pub fn is_closed(&mut self) -> bool {
+        self.ldap.is_closed()
+    }
+
+    /// Do a simple Bind. If the Bind succeeds, return the handle in the `Bound` state
+    /// together with the result of the operation, which may carry response controls.
+    /// Any other outcome, including a non-zero result code, is returned as an error,
+    /// and the handle is dropped. On a bound handle, this rebinds as another identity.
+    ///
+    /// See [`Ldap::simple_bind()`](../struct.Ldap.html#method.simple_bind).
+    pub async fn simple_bind(
+        mut self,
+        bind_dn: &str,
+        bind_pw: &str,
+    ) -> Result<(TypedLdap<Bound>, LdapResult)> {
+        let res = self.ldap.simple_bind(bind_dn, bind_pw).await?.success()?;
+        Ok((self.into_state(), res))
+    }
+
+    /// Do a SASL EXTERNAL Bind, with the same outcome handling as
+    /// [`simple_bind()`](#method.simple_bind).
+    ///
+    /// See [`Ldap::sasl_external_bind()`](../struct.Ldap.html#method.sasl_external_bind).
+    pub async fn sasl_external_bind(mut self) -> Result<(TypedLdap<Bound>, LdapResult)> {
+        let res = self.ldap.sasl_external_bind().await?.success()?;
+        Ok((self.into_state(), res))
+    }
+
+    /// Perform an Extended operation. Some operations, such as Who Am I, are meaningful
+    /// before the connection is authenticated.
+    ///
+    /// See [`Ldap::extended()`](../struct.Ldap.html#method.extended).
+    pub async fn extended<E>(&mut self, exop: E) -> Result<ExopResult>
+    where
+        E: Into<Exop>,
+    {
+        self.ldap.extended(exop).await
+    }
+
+    /// Terminate the connection, consuming the handle.
+    ///
+    /// See [`Ldap::unbind()`](../struct.Ldap.html#method.unbind).
+    pub async fn unbind(mut self) -> Result<()> {
+        self.ldap.unbind().await
+    }
+
+    fn into_state<T>(self) -> TypedLdap<T> {
+        TypedLdap {
+            ldap: self.ldap,
+            _state: PhantomData,
+        }
+    }
+}
+
+impl TypedLdap<Unbound> {
+    /// Wrap an `Ldap` handle.
+    ///
+    /// The handle is assumed to be unbound, regardless of any previous operations
+    /// performed on the connection through it or its clones.
+    pub fn new(ldap: Ldap) -> Self {
+        TypedLdap {
+            ldap,
+            _state: PhantomData,
+        }
+    }
+}
+
+impl From<Ldap> for TypedLdap<Unbound> {
+    fn from(ldap: Ldap) -> Self {
+        TypedLdap::new(ldap)
+    }
+}
+
+impl TypedLdap<Bound> {
+    /// See [`Ldap::with_search_options()`](../struct.Ldap.html#method.with_search_options).
+    pub fn with_search_options(&mut self, opts: SearchOptions) -> &mut Self {
+        self.ldap.with_search_options(opts);
+        self
+    }
+
+    /// See [`Ldap::search()`](../struct.Ldap.html#method.search).
+    pub async fn search<'a, S: AsRef<str> + Send + Sync + 'a>(
+        &mut self,
+        base: &str,
+        scope: Scope,
+        filter: &str,
+        attrs: Vec<S>,
+    ) -> Result<SearchResult> {
+        self.ldap.search(base, scope, filter, attrs).await
+    }
+
+    /// See [`Ldap::streaming_search()`](../struct.Ldap.html#method.streaming_search).
+    pub async fn streaming_search<'a, S: AsRef<str> + Send + Sync + 'a>(
+        &mut self,
+        base: &str,
+        scope: Scope,
+        filter: &str,
+        attrs: Vec<S>,
+    ) -> Result<SearchStream<'a, S>> {
+        self.ldap.streaming_search(base, scope, filter, attrs).await
+    }
+
+    /// See [`Ldap::streaming_search_with()`](../struct.Ldap.html#method.streaming_search_with).
+    pub async fn streaming_search_with<
+        'a,
+        V: IntoAdapterVec<'a, S>,
+        S: AsRef<str> + Send + Sync + 'a,
+    >(
+        &mut self,
+        adapters: V,
+        base: &str,
+        scope: Scope,
+        filter: &str,
+        attrs: Vec<S>,
+    ) -> Result<SearchStream<'a, S>> {
+        self.ldap
+            .streaming_search_with(adapters, base, scope, filter, attrs)
+            .await
+    }
+
+    /// See [`Ldap::add()`](../struct.Ldap.html#method.add).
+    pub async fn add<S: AsRef<[u8]> + Eq + Hash>(
+        &mut self,
+        dn: &str,
+        attrs: Vec<(S, HashSet<S>)>,
+    ) -> Result<LdapResult> {
+        self.ldap.add(dn, attrs).await
+    }
+
+    /// See [`Ldap::compare()`](../struct.Ldap.html#method.compare).
+    pub async fn compare<B: AsRef<[u8]>>(
+        &mut self,
+        dn: &str,
+        attr: &str,
+        val: B,
+    ) -> Result<CompareResult> {
+        self.ldap.compare(dn, attr, val).await
+    }
+
+    /// See [`Ldap::delete()`](../struct.Ldap.html#method.delete).
+    pub async fn delete(&mut self, dn: &str) -> Result<LdapResult> {
+        self.ldap.delete(dn).await
+    }
+
+    /// See [`Ldap::modify()`](../struct.Ldap.html#method.modify).
+    pub async fn modify<S: AsRef<[u8]> + Eq + Hash>(
+        &mut self,
+        dn: &str,
+        mods: Vec<Mod<S>>,
+    ) -> Result<LdapResult> {
+        self.ldap.modify(dn, mods).await
+    }
+
+    /// See [`Ldap::modifydn()`](../struct.Ldap.html#method.modifydn).
+    pub async fn modifydn(
+        &mut self,
+        dn: &str,
+        rdn: &str,
+        delete_old: bool,
+        new_sup: Option<&str>,
+    ) -> Result<LdapResult> {
+        self.ldap.modifydn(dn, rdn, delete_old, new_sup).await
+    }
+
+    /// See [`Ldap::last_id()`](../struct.Ldap.html#method.last_id).
+    pub fn last_id(&mut self) -> RequestId {
+        self.ldap.last_id()
+    }
+
+    /// See [`Ldap::abandon()`](../struct.Ldap.html#method.abandon).
+    pub async fn abandon(&mut self, msgid: RequestId) -> Result<()> {
+        self.ldap.abandon(msgid).await
+    }
+}
+
+#[cfg(all(test, feature = "testkit"))]
+mod test {
+    use super::TypedLdap;
+    use crate::testkit::MockServer;
+    use crate::Scope;
+
+    use maplit::hashset;
+
+    fn server() -> MockServer {
+        let server = MockServer::new();
+        server.add_entry("dc=example,dc=org", vec![("dc", vec!["example"])]);
+        server.add_entry(
+            "cn=admin,dc=example,dc=org",
+            vec![("cn", vec!["admin"]), ("userPassword", vec!["secret"])],
+        );
+        server
+    }
+
+    #[tokio::test]
+    async fn bind_transitions() {
+        let server = server();
+        let (conn, ldap) = server.connect();
+        crate::drive!(conn);
+        let ldap = TypedLdap::new(ldap);
+        let (mut ldap, res) = ldap
+            .simple_bind("cn=admin,dc=example,dc=org", "secret")
+            .await
+            .unwrap();
+        assert_eq!(res.rc, 0);
+        ldap.add("cn=new,dc=example,dc=org", vec![("cn", hashset! {"new"})])
+            .await
+            .unwrap()
+            .success()
+            .unwrap();
+        let (rs, _) = ldap
+            .search("dc=example,dc=org", Scope::OneLevel, "(cn=new)", vec!["cn"])
+            .await
+            .unwrap()
+            .success()
+            .unwrap();
+        assert_eq!(rs.len(), 1);
+        let (mut ldap, _) = ldap.simple_bind("", "").await.unwrap();
+        assert!(!ldap.is_closed());
+        ldap.unbind().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn failed_bind_consumes() {
+        let server = server();
+        let (conn, ldap) = server.connect();
+        crate::drive!(conn);
+        let err = TypedLdap::new(ldap.clone())
+            .simple_bind("cn=admin,dc=example,dc=org", "wrong")
+            .await
+            .unwrap_err();
+        assert_eq!(err.result().map(|res| res.rc), Some(49));
+        let ldap = TypedLdap::from(ldap);
+        ldap.unbind().await.unwrap();
+    }
+}
@@ -1,6 +1,8 @@
 use std::borrow::Cow;
 use std::collections::HashSet;
+use std::fmt;
 use std::hash::{Hash, Hasher};
+use std::str::FromStr;
 
 use crate::filter::Unescaper;
 use crate::result::{LdapError, Result};
@@ -150,15 +152,7 @@ pub enum LdapUrlExt<'a> {
 
 impl<'a> PartialEq for LdapUrlExt<'a> {
     fn eq(&self, other: &Self) -> bool {
-        match (self, other) {
-            (LdapUrlExt::Bindname(_), LdapUrlExt::Bindname(_)) => true,
-            (LdapUrlExt::XBindpw(_), LdapUrlExt::XBindpw(_)) => true,
-            (LdapUrlExt::Credentials(_), LdapUrlExt::Credentials(_)) => true,
-            (LdapUrlExt::SaslMech(_), LdapUrlExt::SaslMech(_)) => true,
-            (LdapUrlExt::StartTLS, LdapUrlExt::StartTLS) => true,
-            (LdapUrlExt::Unknown(_), LdapUrlExt::Unknown(_)) => true,
-            _ => false,
-        }
+        std::mem::discriminant(self) == std::mem::discriminant(other)
     }
 }
 
@@ -270,7 +264,7 @@ pub fn get_url_params(url: &Url) -> Result<LdapUrlParams<'_>> {
                     let mut crit = false;
                     let mut idv = ext.splitn(2, '=');
                     let mut id = idv.next().unwrap_or("");
-                    if id != "" && &id[..1] == "!" {
+                    if !id.is_empty() && &id[..1] == "!" {
                         id = &id[1..];
                         crit = true;
                     }
@@ -318,6 +312,193 @@ pub fn get_url_params(url: &Url) -> Result<LdapUrlParams<'_>> {
     })
 }
 
+/// An extension of an LDAP URL, as found in the URL.
+///
+/// Unlike [`LdapUrlExt`](enum.LdapUrlExt.html), this structure keeps the name of every
+/// extension, recognized or not, and its criticality.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LdapUrlExtension {
+    /// Whether the extension was marked as critical with a leading `!`.
+    pub critical: bool,
+
+    /// Extension name, an OID or a descriptor.
+    pub name: String,
+
+    /// Extension value, percent-decoded, or `None` if the extension has no value.
+    pub value: Option<String>,
+}
+
+impl LdapUrlExtension {
+    /// Return the variant of `LdapUrlExt` corresponding to this extension. An unrecognized
+    /// extension is returned as `LdapUrlExt::Unknown` with the extension name.
+    pub fn kind(&self) -> LdapUrlExt<'_> {
+        let val = || Cow::Borrowed(self.value.as_deref().unwrap_or(""));
+        match self.name.as_str() {
+            "1.3.6.1.4.1.10094.1.5.1" => LdapUrlExt::Credentials(val()),
+            "1.3.6.1.4.1.10094.1.5.2" => LdapUrlExt::SaslMech(val()),
+            "1.3.6.1.4.1.1466.20037" => LdapUrlExt::StartTLS,
+            name if ascii_lc_equal("bindname", name) => LdapUrlExt::Bindname(val()),
+            name if ascii_lc_equal("x-bindpw", name) => LdapUrlExt::XBindpw(val()),
+            name => LdapUrlExt::Unknown(Cow::Borrowed(name)),
+        }
+    }
+}
+
+/// Parsed LDAP URL, with all components described in RFC 4516.
+///
+/// The URL has the form
+///
+/// ```text
+/// scheme://host:port/base?attrs?scope?filter?extensions
+/// ```
+///
+/// where every part after the scheme is optional. The missing parts get the defaults
+/// specified by the RFC, which differ from those used by [`get_url_params()`](fn.get_url_params.html):
+/// the attribute list is empty, meaning all user attributes, the scope is `base`, and
+/// the filter is `(objectClass=*)`.
+///
+/// Values of the `refs` field of [`LdapResult`](result/struct.LdapResult.html), and the
+/// URLs in search result references, can be parsed into this structure, which can be
+/// used to connect to the referred server with
+/// [`LdapConnAsync::from_url()`](struct.LdapConnAsync.html#method.from_url) and to
+/// perform the search with [`Ldap::search_url()`](struct.Ldap.html#method.search_url).
+///
+/// ```rust
+/// # use ldap3::{LdapUrl, Scope};
+/// # use ldap3::result::Result;
+/// # fn main() -> Result<()> {
+/// let url: LdapUrl = "ldap://example.org/dc=example,dc=org?cn,mail?sub?(uid=foo)?x-extension".parse()?;
+/// assert_eq!(url.base, "dc=example,dc=org");
+/// assert_eq!(url.attrs, vec!["cn", "mail"]);
+/// assert_eq!(url.scope, Scope::Subtree);
+/// assert_eq!(url.filter, "(uid=foo)");
+/// assert_eq!(url.extensions[0].name, "x-extension");
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct LdapUrl {
+    /// The URL itself, with the scheme, host and port.
+    pub url: Url,
+
+    /// Base DN, percent-decoded.
+    pub base: String,
+
+    /// Attribute list, percent-decoded.
+    pub attrs: Vec<String>,
+
+    /// Search scope.
+    pub scope: Scope,
+
+    /// Filter string, percent-decoded.
+    pub filter: String,
+
+    /// Extensions, in the order of appearance.
+    pub extensions: Vec<LdapUrlExtension>,
+}
+
+fn url_decode(s: &str) -> Result<String> {
+    Ok(percent_decode_str(s)
+        .decode_utf8()
+        .map_err(|_| LdapError::DecodingUTF8)?
+        .into_owned())
+}
+
+impl LdapUrl {
+    /// Parse a string into an LDAP URL.
+    pub fn parse(url: &str) -> Result<Self> {
+        Self::from_url(Url::parse(url)?)
+    }
+
+    /// Extract the components of an already parsed URL. The scheme must be one of
+    /// `ldap`, `ldaps`, or `ldapi`.
+    pub fn from_url(url: Url) -> Result<Self> {
+        match url.scheme() {
+            "ldap" | "ldaps" | "ldapi" => (),
+            scheme => return Err(LdapError::UnknownScheme(scheme.to_owned())),
+        }
+        let base = url_decode(url.path().trim_start_matches('/'))?;
+        let mut query = url.query().unwrap_or("").splitn(4, '?');
+        let attrs = match query.next() {
+            Some("") | None => vec![],
+            Some(alist) => alist.split(',').map(url_decode).collect::<Result<_>>()?,
+        };
+        let scope = match query.next() {
+            Some("") | None => Scope::Base,
+            Some(scope_str) => match scope_str {
+                "base" => Scope::Base,
+                "one" => Scope::OneLevel,
+                "sub" => Scope::Subtree,
+                any => return Err(LdapError::InvalidScopeString(any.into())),
+            },
+        };
+        let filter = match query.next() {
+            Some("") | None => String::from("(objectClass=*)"),
+            Some(filter) => url_decode(filter)?,
+        };
+        let mut extensions = vec![];
+        if let Some(exts) = query.next() {
+            for ext in exts.split(',').filter(|ext| !ext.is_empty()) {
+                let (critical, ext) = match ext.strip_prefix('!') {
+                    Some(ext) => (true, ext),
+                    None => (false, ext),
+                };
+                let mut idv = ext.splitn(2, '=');
+                let name = url_decode(idv.next().unwrap_or(""))?;
+                let value = idv.next().map(url_decode).transpose()?;
+                extensions.push(LdapUrlExtension {
+                    critical,
+                    name,
+                    value,
+                });
+            }
+        }
+        Ok(LdapUrl {
+            url,
+            base,
+            attrs,
+            scope,
+            filter,
+            extensions,
+        })
+    }
+
+    /// Find the first extension whose `LdapUrlExt` variant matches that of `ext`. As in
+    /// the `extensions` set of [`LdapUrlParams`](struct.LdapUrlParams.html), the value of
+    /// `ext` is ignored.
+    pub fn extension(&self, ext: &LdapUrlExt<'_>) -> Option<&LdapUrlExtension> {
+        self.extensions.iter().find(|e| e.kind() == *ext)
+    }
+
+    /// Return an error if the URL contains an unrecognized extension marked as critical.
+    /// RFC 4516 prohibits processing such an URL.
+    pub fn check_critical(&self) -> Result<()> {
+        for ext in &self.extensions {
+            if let (true, kind @ LdapUrlExt::Unknown(_)) = (ext.critical, ext.kind()) {
+                return Err(LdapError::UnrecognizedCriticalExtension(format!(
+                    "{:?}",
+                    kind
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for LdapUrl {
+    type Err = LdapError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        LdapUrl::parse(s)
+    }
+}
+
+impl fmt::Display for LdapUrl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.url.fmt(f)
+    }
+}
+
 /// Unescape a string using LDAP filter escapes.
 ///
 /// If a string contains `\nn` hexadecimal escapes, return a string where those
@@ -330,24 +511,23 @@ pub fn ldap_str_unescape<'a, S: Into<Cow<'a, str>>>(val: S) -> Result<Cow<'a, st
     for (i, &c) in val.as_bytes().iter().enumerate() {
         esc = esc.feed(c);
         match esc {
-            Unescaper::WantFirst => {
-                if output.is_none() {
-                    output = Some(Vec::with_capacity(val.len() + 12)); // guess: up to 4 escaped chars
-                    output.as_mut().unwrap().extend(val[..i].as_bytes());
-                }
+            Unescaper::WantFirst if output.is_none() => {
+                let mut buf = Vec::with_capacity(val.len() + 12); // guess: up to 4 escaped chars
+                buf.extend(val[..i].as_bytes());
+                output = Some(buf);
             }
             Unescaper::Value(c) => {
-                if output.is_some() {
-                    output.as_mut().unwrap().push(c);
+                if let Some(ref mut output) = output {
+                    output.push(c);
                 }
             }
             _ => (),
         }
     }
-    if output.is_some() {
+    if let Some(output) = output {
         if let Unescaper::Value(_) = esc {
             Ok(Cow::Owned(
-                String::from_utf8(output.unwrap()).map_err(|_| LdapError::DecodingUTF8)?,
+                String::from_utf8(output).map_err(|_| LdapError::DecodingUTF8)?,
             ))
         } else {
             Err(LdapError::DecodingUTF8)
@@ -357,9 +537,101 @@ pub fn ldap_str_unescape<'a, S: Into<Cow<'a, str>>>(val: S) -> Result<Cow<'a, st
     }
 }
 
+// Split a DN into RDNs at unescaped commas.
+pub(crate) fn split_rdns(dn: &str) -> Vec<&str> {
+    let mut rdns = vec![];
+    let mut start = 0;
+    let mut escaped = false;
+    for (i, c) in dn.char_indices() {
+        match c {
+            '\\' if !escaped => escaped = true,
+            ',' if !escaped => {
+                rdns.push(&dn[start..i]);
+                start = i + 1;
+            }
+            _ => escaped = false,
+        }
+    }
+    if !dn.is_empty() {
+        rdns.push(&dn[start..]);
+    }
+    rdns
+}
+
+// Attribute type and value of a single-valued RDN.
+pub(crate) fn rdn_ava(rdn: &str) -> Option<(&str, &str)> {
+    let pos = rdn.find('=')?;
+    Some((rdn[..pos].trim(), rdn[pos + 1..].trim()))
+}
+
+// Normalized DN for equality comparisons: whitespace around the RDN components
+// removed, and everything lowercased.
+pub(crate) fn normalize_dn(dn: &str) -> String {
+    split_rdns(dn)
+        .into_iter()
+        .map(|rdn| match rdn_ava(rdn) {
+            Some((attr, val)) => format!("{}={}", attr, val),
+            None => rdn.trim().to_owned(),
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+        .to_lowercase()
+}
+
+// Standard base64 alphabet, for LDIF and DSML values.
+const B64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+#[cfg_attr(not(any(feature = "dsml", feature = "passwd")), allow(dead_code))]
+pub(crate) fn base64_encode(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let b = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(B64[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+#[cfg_attr(
+    not(any(feature = "changelog", feature = "dsml", feature = "passwd")),
+    allow(dead_code)
+)]
+pub(crate) fn base64_decode(text: &str) -> std::result::Result<Vec<u8>, String> {
+    let mut out = Vec::with_capacity(text.len() / 4 * 3);
+    let (mut acc, mut bits) = (0u32, 0);
+    for c in text.bytes().filter(|c| !c.is_ascii_whitespace()) {
+        if c == b'=' {
+            break;
+        }
+        let v = match B64.iter().position(|&b| b == c) {
+            Some(v) => v as u32,
+            None => return Err(format!("invalid base64 character {:?}", c as char)),
+        };
+        acc = acc << 6 | v;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits) as u8);
+            acc &= (1 << bits) - 1;
+        }
+    }
+    Ok(out)
+}
+
 #[cfg(test)]
 mod test {
-    use super::dn_escape;
+    use super::{dn_escape, LdapUrl, LdapUrlExt};
+    use crate::search::Scope;
 
     #[test]
     fn dn_esc_leading_space() {
@@ -395,4 +667,28 @@ mod test {
     fn dn_esc_leading_hash() {
         assert_eq!(dn_escape("#rust"), "\\23rust");
     }
+
+    #[test]
+    fn url_defaults() {
+        let url = LdapUrl::parse("ldap://example.org").unwrap();
+        assert_eq!(url.base, "");
+        assert!(url.attrs.is_empty());
+        assert_eq!(url.scope, Scope::Base);
+        assert_eq!(url.filter, "(objectClass=*)");
+        assert!(url.extensions.is_empty());
+    }
+
+    #[test]
+    fn url_extensions() {
+        let url = LdapUrl::parse("ldap:///o=a%2cb???(cn=x%3fy)?!bindname=cn=admin,!x-foo,e-bar=1")
+            .unwrap();
+        assert_eq!(url.base, "o=a,b");
+        assert_eq!(url.filter, "(cn=x?y)");
+        assert_eq!(url.extensions.len(), 3);
+        let bindname = url.extension(&LdapUrlExt::Bindname("".into())).unwrap();
+        assert!(bindname.critical);
+        assert_eq!(bindname.value.as_deref(), Some("cn=admin"));
+        assert_eq!(url.extensions[2].value.as_deref(), Some("1"));
+        assert!(url.check_critical().is_err());
+    }
 }
@@ -0,0 +1,360 @@
+//! Conversion of attribute values to Rust types.
+//!
+//! Attribute values arrive as octet strings, and their syntax is known only from the
+//! schema. Applications typically know what to expect of the attributes they use, and
+//! [`SearchEntry::attr_as()`](../struct.SearchEntry.html#method.attr_as) and
+//! [`attrs_as()`](../struct.SearchEntry.html#method.attrs_as) convert the values to
+//! the appropriate types, reporting any malformed value as
+//! [`LdapError::InvalidValue`](../result/enum.LdapError.html#variant.InvalidValue).
+//!
+//! The conversions are defined by the [`FromLdapValue`](trait.FromLdapValue.html) trait,
+//! which is implemented for:
+//!
+//! * integer types, for the Integer syntax;
+//!
+//! * `bool`, for the Boolean syntax (`TRUE` or `FALSE`);
+//!
+//! * `SystemTime`, for the Generalized Time syntax;
+//!
+//! * [`Dn`](struct.Dn.html), for the DN syntax;
+//!
+//! * `String` and `Vec<u8>`, which accept any UTF-8 or any value, respectively.
+//!
+//! The trait can be implemented for application types. It's also used by
+//! [`AttrValue::parse()`](struct.AttrValue.html#method.parse).
+//!
+//! ## Example
+//!
+//! ```rust
+//! use std::collections::HashMap;
+//! use std::time::SystemTime;
+//!
+//! use ldap3::SearchEntry;
+//! use ldap3::values::Dn;
+//! # use ldap3::result::Result;
+//!
+//! # fn main() -> Result<()> {
+//! let entry = SearchEntry {
+//!     dn: "uid=jdoe,ou=People,dc=example,dc=org".to_owned(),
+//!     attrs: vec![
+//!         ("uidNumber", "1000"),
+//!         ("modifyTimestamp", "20210101120000Z"),
+//!         ("manager", "uid=boss,ou=People,dc=example,dc=org"),
+//!     ]
+//!     .into_iter()
+//!     .map(|(a, v)| (a.to_owned(), vec![v.to_owned()]))
+//!     .collect(),
+//!     bin_attrs: HashMap::new(),
+//!     ctrls: vec![],
+//! };
+//! let uid_number: Option<i64> = entry.attr_as("uidNumber")?;
+//! assert_eq!(uid_number, Some(1000));
+//! let modified: Option<SystemTime> = entry.attr_as("modifyTimestamp")?;
+//! let manager: Option<Dn> = entry.attr_as("manager")?;
+//! assert_eq!(manager.unwrap().parent().unwrap().as_str(), "ou=People,dc=example,dc=org");
+//! # Ok(())
+//! # }
+//! ```
+
+use std::fmt;
+use std::str;
+use std::time::{Duration, SystemTime};
+
+use crate::result::{LdapError, Result};
+use crate::util::{normalize_dn, split_rdns};
+
+/// Conversion from a raw attribute value.
+pub trait FromLdapValue: Sized {
+    /// Convert `value`, returning an error if it doesn't have the expected syntax.
+    /// Conventionally, the error is
+    /// [`LdapError::InvalidValue`](../result/enum.LdapError.html#variant.InvalidValue).
+    fn from_ldap_value(value: &[u8]) -> Result<Self>;
+}
+
+fn invalid(what: &str, value: &[u8]) -> LdapError {
+    LdapError::InvalidValue(format!("{}: {:?}", what, String::from_utf8_lossy(value)))
+}
+
+fn utf8<'v>(what: &str, value: &'v [u8]) -> Result<&'v str> {
+    str::from_utf8(value).map_err(|_| invalid(what, value))
+}
+
+macro_rules! from_int {
+    ($($t:ty),*) => {
+        $(
+            impl FromLdapValue for $t {
+                fn from_ldap_value(value: &[u8]) -> Result<Self> {
+                    utf8("integer", value)?
+                        .parse()
+                        .map_err(|_| invalid("integer", value))
+                }
+            }
+        )*
+    };
+}
+
+from_int!(i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize);
+
+impl FromLdapValue for bool {
+    fn from_ldap_value(value: &[u8]) -> Result<Self> {
+        match value {
+            b"TRUE" => Ok(true),
+            b"FALSE" => Ok(false),
+            _ => Err(invalid("boolean", value)),
+        }
+    }
+}
+
+impl FromLdapValue for String {
+    fn from_ldap_value(value: &[u8]) -> Result<Self> {
+        utf8("string", value).map(str::to_owned)
+    }
+}
+
+impl FromLdapValue for Vec<u8> {
+    fn from_ldap_value(value: &[u8]) -> Result<Self> {
+        Ok(value.to_vec())
+    }
+}
+
+// Days from 1970-01-01 to the given proleptic Gregorian date.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = if y >= 0 { y } else { y - 399 } / 400;
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+fn digits(s: &[u8]) -> Option<i64> {
+    if s.is_empty() || !s.iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+    Some(s.iter().fold(0, |acc, d| acc * 10 + (d - b'0') as i64))
+}
+
+// Seconds and nanoseconds since the Unix epoch, as in RFC 4517, section 3.3.13:
+// YYYYMMDDHH[MM[SS]][(.|,)fraction](Z|(+|-)HH[MM]). The fraction applies to the last
+// unit present.
+fn parse_generalized_time(value: &[u8]) -> Option<(i64, u32)> {
+    let tz_pos = value
+        .iter()
+        .position(|&c| matches!(c, b'Z' | b'+' | b'-'))?;
+    let (local, tz) = value.split_at(tz_pos);
+    let (whole, fraction) = match local.iter().position(|&c| c == b'.' || c == b',') {
+        Some(pos) => (&local[..pos], Some(&local[pos + 1..])),
+        None => (local, None),
+    };
+    if !matches!(whole.len(), 10 | 12 | 14) {
+        return None;
+    }
+    let (year, month, day, hour) = (
+        digits(&whole[0..4])?,
+        digits(&whole[4..6])?,
+        digits(&whole[6..8])?,
+        digits(&whole[8..10])?,
+    );
+    let minute = whole.get(10..12).map_or(Some(0), digits)?;
+    let second = whole.get(12..14).map_or(Some(0), digits)?;
+    if !(1..=12).contains(&month)
+        || !(1..=31).contains(&day)
+        || hour > 23
+        || minute > 59
+        || second > 60
+    {
+        return None;
+    }
+    let unit_secs: i64 = match whole.len() {
+        10 => 3600,
+        12 => 60,
+        _ => 1,
+    };
+    let frac_nanos = match fraction {
+        Some(f) => {
+            digits(f)?;
+            let f = &f[..f.len().min(9)];
+            let scaled = digits(f)? * 10i64.pow(9 - f.len() as u32);
+            scaled * unit_secs
+        }
+        None => 0,
+    };
+    let offset = match tz {
+        b"Z" => 0,
+        [sign, rest @ ..] if rest.len() == 2 || rest.len() == 4 => {
+            let hours = digits(&rest[0..2])?;
+            let minutes = rest.get(2..4).map_or(Some(0), digits)?;
+            let offset = hours * 3600 + minutes * 60;
+            if *sign == b'-' {
+                -offset
+            } else {
+                offset
+            }
+        }
+        _ => return None,
+    };
+    let secs = days_from_civil(year, month, day) * 86_400 + hour * 3600 + minute * 60 + second
+        - offset
+        + frac_nanos / 1_000_000_000;
+    Some((secs, (frac_nanos % 1_000_000_000) as u32))
+}
+
+impl FromLdapValue for SystemTime {
+    fn from_ldap_value(value: &[u8]) -> Result<Self> {
+        let (secs, nanos) =
+            parse_generalized_time(value).ok_or_else(|| invalid("generalized time", value))?;
+        let time = if secs >= 0 {
+            SystemTime::UNIX_EPOCH.checked_add(Duration::new(secs as u64, nanos))
+        } else {
+            SystemTime::UNIX_EPOCH
+                .checked_sub(Duration::from_secs(secs.unsigned_abs()))
+                .and_then(|t| t.checked_add(Duration::from_nanos(nanos as u64)))
+        };
+        time.ok_or_else(|| invalid("generalized time", value))
+    }
+}
+
+/// Attribute value, as received from the server.
+///
+/// The value is kept as bytes, and can be viewed as a string if it's valid UTF-8.
+/// This is what [`ValueEntry`](../struct.ValueEntry.html) uses for all attributes,
+/// regardless of their syntax.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct AttrValue(Vec<u8>);
+
+impl AttrValue {
+    /// Return the value as bytes.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// Return the value as a string, or `None` if it isn't valid UTF-8.
+    pub fn as_str(&self) -> Option<&str> {
+        str::from_utf8(&self.0).ok()
+    }
+
+    /// Convert the value to the type `T`.
+    pub fn parse<T: FromLdapValue>(&self) -> Result<T> {
+        T::from_ldap_value(&self.0)
+    }
+
+    /// Return the bytes, consuming the value.
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.0
+    }
+}
+
+impl AsRef<[u8]> for AttrValue {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl From<Vec<u8>> for AttrValue {
+    fn from(value: Vec<u8>) -> Self {
+        AttrValue(value)
+    }
+}
+
+impl From<String> for AttrValue {
+    fn from(value: String) -> Self {
+        AttrValue(value.into_bytes())
+    }
+}
+
+impl From<&str> for AttrValue {
+    fn from(value: &str) -> Self {
+        AttrValue(value.as_bytes().to_vec())
+    }
+}
+
+/// Distinguished name.
+///
+/// The DN is kept as given, but compared for equality in a normalized form, with
+/// the whitespace around RDN components removed and all letters lowercased. This
+/// is adequate for the common case of case-insensitive naming attributes.
+#[derive(Clone, Debug)]
+pub struct Dn(String);
+
+impl Dn {
+    /// Create a DN from its string representation.
+    pub fn new<S: Into<String>>(dn: S) -> Self {
+        Dn(dn.into())
+    }
+
+    /// Return the string representation.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Return the RDNs, starting with the leftmost.
+    pub fn rdns(&self) -> Vec<&str> {
+        split_rdns(&self.0)
+    }
+
+    /// Return the DN of the parent entry, or `None` if the DN is empty.
+    pub fn parent(&self) -> Option<Dn> {
+        let first = *self.rdns().first()?;
+        let rest = self.0[first.len()..].trim_start_matches(',');
+        Some(Dn(rest.to_owned()))
+    }
+}
+
+impl PartialEq for Dn {
+    fn eq(&self, other: &Dn) -> bool {
+        normalize_dn(&self.0) == normalize_dn(&other.0)
+    }
+}
+
+impl Eq for Dn {}
+
+impl fmt::Display for Dn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl FromLdapValue for Dn {
+    fn from_ldap_value(value: &[u8]) -> Result<Self> {
+        utf8("DN", value).map(Dn::new)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Dn, FromLdapValue};
+
+    use std::time::{Duration, SystemTime};
+
+    fn time(value: &str) -> Option<SystemTime> {
+        SystemTime::from_ldap_value(value.as_bytes()).ok()
+    }
+
+    #[test]
+    fn value_conversions() {
+        let epoch = SystemTime::UNIX_EPOCH;
+        assert_eq!(time("19700101000000Z"), Some(epoch));
+        assert_eq!(
+            time("20210302123456.5Z"),
+            Some(epoch + Duration::new(1_614_688_496, 500_000_000))
+        );
+        assert_eq!(
+            time("2021030213+0100"),
+            Some(epoch + Duration::from_secs(1_614_686_400))
+        );
+        assert_eq!(
+            time("19691231235959Z"),
+            Some(epoch - Duration::from_secs(1))
+        );
+        assert_eq!(time("20211301000000Z"), None);
+        assert_eq!(time("20210101000000"), None);
+        assert!(bool::from_ldap_value(b"TRUE").unwrap());
+        assert!(bool::from_ldap_value(b"true").is_err());
+        assert!(u32::from_ldap_value(b"-1").is_err());
+        let dn = Dn::new("uid=a, ou=People,dc=Example");
+        assert_eq!(dn, Dn::new("UID=a,ou=people,dc=example"));
+        assert_eq!(dn.parent().unwrap().as_str(), " ou=People,dc=Example");
+        assert_eq!(Dn::new("").parent(), None);
+    }
+}